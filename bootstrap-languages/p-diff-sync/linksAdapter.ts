@@ -139,10 +139,11 @@ export class LinkAdapter implements LinkSyncAdapter {
         if(!hash) continue
         if (this.myCurrentRevision && (encodeBase64(hash) == encodeBase64(this.myCurrentRevision))) continue;
         
+        // Pulls can merge as scribe, so they get more time but are never retried
         let pullResult = await this.hcDna.call(DNA_NICK, ZOME_NAME, "pull", { 
           hash,
           is_scribe 
-        });
+        }, { timeoutMs: 30000 });
 
         if (pullResult) {
           if (pullResult.current_revision && Buffer.isBuffer(pullResult.current_revision)) {
@@ -190,7 +191,7 @@ export class LinkAdapter implements LinkSyncAdapter {
 
   async render(): Promise<Perspective> {
     //@ts-ignore
-    let res = await this.hcDna.call(DNA_NICK, ZOME_NAME, "render", null);
+    let res = await this.hcDna.call(DNA_NICK, ZOME_NAME, "render", null, { timeoutMs: 30000, idempotent: true, retries: 2 });
     return new Perspective(res.links);
   }
 
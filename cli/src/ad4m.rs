@@ -132,7 +132,9 @@ async fn get_ad4m_client(args: &ClapApp) -> Result<Ad4mClient> {
                 command:
                     AgentFunctions::Lock
                     | AgentFunctions::Unlock { .. }
-                    | AgentFunctions::Generate { .. },
+                    | AgentFunctions::Generate { .. }
+                    | AgentFunctions::RestoreMnemonic { .. }
+                    | AgentFunctions::RestoreBackup { .. },
             } => "".to_string(),
             _ => startup::get_cap_token(executor_url.clone()).await?,
         }
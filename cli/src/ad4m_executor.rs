@@ -28,7 +28,9 @@ use ad4m_client::*;
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use dev::DevFunctions;
-use rust_executor::{config::TlsConfig, Ad4mConfig};
+use rust_executor::{
+    agent::capabilities::rate_limits::RateLimitConfig, config::TlsConfig, Ad4mConfig,
+};
 
 /// AD4M command line interface.
 /// https://ad4m.dev
@@ -127,6 +129,38 @@ enum Domain {
         tls_key_file: Option<String>,
         #[arg(long, action)]
         log_holochain_metrics: Option<bool>,
+        /// Argon2 memory cost in KiB for encrypting the agent's keys
+        #[arg(long, action)]
+        wallet_kdf_memory_cost: Option<u32>,
+        /// Argon2 iterations for encrypting the agent's keys
+        #[arg(long, action)]
+        wallet_kdf_iterations: Option<u32>,
+        /// Seconds to wait for the JS core before a call fails
+        #[arg(long, action)]
+        js_call_timeout_secs: Option<u64>,
+        /// Directory or zip archive of language bundles to make installable without network
+        #[arg(long, action)]
+        language_bundle_seed: Option<String>,
+        /// Serve metrics in OpenMetrics format on /metrics
+        #[arg(long, action)]
+        metrics_endpoint: Option<bool>,
+        /// Serve /metrics on this port instead of the GraphQL port
+        #[arg(long, action)]
+        metrics_port: Option<u16>,
+        /// Only serve the metrics port on 127.0.0.1, defaults to true
+        #[arg(long, action)]
+        metrics_localhost: Option<bool>,
+        /// Throttle app tokens per operation, as <operation>=<limit>/<window_seconds>,
+        /// e.g. aiPrompt=20/3600. Can be given multiple times
+        #[arg(long = "rate-limit")]
+        rate_limits: Vec<RateLimitConfig>,
+        /// JSON file with timeouts and retries of Holochain zome calls per app, zome or function
+        #[arg(long, action)]
+        zome_call_policies: Option<String>,
+        /// Restore the agent at startup if there is none yet. Reads the recovery phrase and
+        /// the passphrase, one per line, from this file or from stdin if given "-"
+        #[arg(long, action)]
+        restore_agent_from_mnemonic: Option<String>,
     },
     RunLocalHcServices {},
 }
@@ -175,8 +209,32 @@ async fn main() -> Result<()> {
         tls_cert_file,
         tls_key_file,
         log_holochain_metrics,
+        wallet_kdf_memory_cost,
+        wallet_kdf_iterations,
+        js_call_timeout_secs,
+        language_bundle_seed,
+        metrics_endpoint,
+        metrics_port,
+        metrics_localhost,
+        rate_limits,
+        zome_call_policies,
+        restore_agent_from_mnemonic,
     } = args.domain
     {
+        let zome_call_policies = match zome_call_policies {
+            Some(path) => Some(
+                rust_executor::config::read_zome_call_policies(&path)
+                    .map_err(|e| anyhow::anyhow!("{}", e))?,
+            ),
+            None => None,
+        };
+        let restore_agent_from_mnemonic = match restore_agent_from_mnemonic {
+            Some(path) => Some(
+                rust_executor::config::read_agent_restore_config(&path)
+                    .map_err(|e| anyhow::anyhow!("{}", e))?,
+            ),
+            None => None,
+        };
         let tls = if tls_cert_file.is_some() && tls_cert_file.is_some() {
             Some(TlsConfig {
                 cert_file_path: tls_cert_file.unwrap(),
@@ -209,6 +267,16 @@ async fn main() -> Result<()> {
                 auto_permit_cap_requests: Some(true),
                 tls,
                 log_holochain_metrics,
+                rate_limits: (!rate_limits.is_empty()).then_some(rate_limits),
+                wallet_kdf_memory_cost,
+                wallet_kdf_iterations,
+                js_call_timeout_secs,
+                language_bundle_seed,
+                zome_call_policies,
+                metrics_endpoint,
+                metrics_port,
+                metrics_localhost,
+                restore_agent_from_mnemonic,
             })
             .await;
         })
@@ -31,6 +31,28 @@ pub enum AgentFunctions {
         #[arg(short, long)]
         passphrase: Option<String>,
     },
+    /// Print the recovery phrase of the agent's keys
+    Mnemonic,
+    /// Initialize the agent from a recovery phrase instead of generating new keys
+    RestoreMnemonic {
+        /// Agent passphrase
+        #[arg(short, long)]
+        passphrase: Option<String>,
+    },
+    /// Write an encrypted backup of the agent's keys, apps, trusted agents and friends
+    Backup {
+        /// File to write the backup to
+        file: String,
+    },
+    /// Initialize the agent from a backup file
+    RestoreBackup {
+        /// Backup file created with `agent backup`
+        file: String,
+
+        /// Passphrase the backup was created with
+        #[arg(short, long)]
+        passphrase: Option<String>,
+    },
     AddEntanglementProof {
         device_key: String,
         device_key_signed_by_did: String,
@@ -51,6 +73,29 @@ pub enum AgentFunctions {
         device_key: String,
         device_key_type: String,
     },
+    /// Authorise another executor to act as a device of this agent
+    AuthoriseDevice {
+        /// DID of the other executor's own keys, as shown by `agent status` there
+        device_did: String,
+    },
+    /// Link this executor to the agent that authorised it with `agent authorise-device`
+    LinkDevice {
+        /// DID of the agent
+        #[arg(long)]
+        did: String,
+        /// Key the agent signed the authorisation with
+        #[arg(long)]
+        did_signing_key_id: String,
+        /// The agent's signature of this executor's DID
+        #[arg(long)]
+        device_key_signed_by_did: String,
+        /// Device sync neighbourhoods to join
+        #[arg(short, long)]
+        neighbourhood: Vec<String>,
+        /// Key of the device sync neighbourhoods, sealed to this executor
+        #[arg(long)]
+        sync_key: String,
+    },
     GenerateJwt {
         request_id: String,
         rand: String,
@@ -134,6 +179,68 @@ pub async fn run(ad4m_client: Ad4mClient, command: AgentFunctions) -> Result<()>
                 bail!(error);
             } else {
                 println!("Agent generated");
+                if let Some(mnemonic) = result.mnemonic {
+                    println!("Recovery phrase (keep it secret and safe):\n{}", mnemonic);
+                }
+            }
+        }
+        AgentFunctions::Mnemonic => {
+            let mnemonic = ad4m_client
+                .agent
+                .mnemonic(readline_masked("Passphrase: ")?)
+                .await?;
+            println!("Recovery phrase (keep it secret and safe):\n{}", mnemonic);
+        }
+        AgentFunctions::RestoreMnemonic { passphrase } => {
+            let mnemonic = readline_masked("Recovery phrase: ")?;
+            let pp = if let Some(pp) = passphrase {
+                pp
+            } else {
+                let passphrase1 = readline_masked("New passphrase: ")?;
+                let passphrase2 = readline_masked("Repeat passphrase: ")?;
+                if passphrase1 != passphrase2 {
+                    bail!("Passphrases do not match");
+                }
+                passphrase1
+            };
+
+            let result = ad4m_client
+                .agent
+                .restore_from_mnemonic(mnemonic, pp)
+                .await?;
+            if let Some(error) = result.error {
+                bail!(error);
+            } else {
+                println!(
+                    "Agent restored: {}",
+                    result.did.unwrap_or_else(|| "<undefined>".to_string())
+                );
+            }
+        }
+        AgentFunctions::Backup { file } => {
+            let backup = ad4m_client
+                .agent
+                .export_backup(readline_masked("Passphrase: ")?)
+                .await?;
+            std::fs::write(&file, backup)?;
+            println!("Backup written to {}", file);
+        }
+        AgentFunctions::RestoreBackup { file, passphrase } => {
+            let backup = std::fs::read_to_string(&file)?;
+            let pp = if let Some(pp) = passphrase {
+                pp
+            } else {
+                readline_masked("Passphrase: ")?
+            };
+
+            let result = ad4m_client.agent.import_backup(backup, pp).await?;
+            if let Some(error) = result.error {
+                bail!(error);
+            } else {
+                println!(
+                    "Agent restored: {}",
+                    result.did.unwrap_or_else(|| "<undefined>".to_string())
+                );
             }
         }
         AgentFunctions::AddEntanglementProof {
@@ -190,6 +297,60 @@ pub async fn run(ad4m_client: Ad4mClient, command: AgentFunctions) -> Result<()>
                 .await?;
             println!("Add preflight!: {:#?}", result);
         }
+        AgentFunctions::AuthoriseDevice { device_did } => {
+            let link = ad4m_client.agent.authorise_device(device_did).await?;
+            let neighbourhoods: String = link
+                .neighbourhoods
+                .iter()
+                .map(|url| format!(" -n {}", url))
+                .collect();
+            println!("Run this on the device to link it:");
+            println!(
+                "ad4m agent link-device --did {} --did-signing-key-id {} --device-key-signed-by-did {} --sync-key {}{}",
+                link.proof.did,
+                link.proof.did_signing_key_id,
+                link.proof.device_key_signed_by_did,
+                link.sync_key,
+                neighbourhoods
+            );
+        }
+        AgentFunctions::LinkDevice {
+            did,
+            did_signing_key_id,
+            device_key_signed_by_did,
+            neighbourhood,
+            sync_key,
+        } => {
+            let device_key = ad4m_client
+                .agent
+                .status()
+                .await?
+                .did
+                .ok_or_else(|| anyhow::anyhow!("Agent has no DID yet"))?;
+            let link = ad4m_client::agent::link_device::DeviceLinkInput {
+                did,
+                did_signing_key_id,
+                device_key,
+                device_key_signed_by_did,
+                neighbourhoods: neighbourhood,
+                sync_key,
+            };
+            let proof = ad4m_client
+                .agent
+                .link_device(link, readline_masked("Passphrase: ")?)
+                .await?;
+            println!("Linked to agent {}", proof.did);
+            println!("Run this on the primary device to complete the link:");
+            println!(
+                "ad4m agent add-entanglement-proof {} {} {} {} {} {}",
+                proof.device_key,
+                proof.device_key_signed_by_did,
+                proof.device_key_type,
+                proof.did,
+                proof.did_signed_by_device_key.unwrap_or_default(),
+                proof.did_signing_key_id
+            );
+        }
         AgentFunctions::GenerateJwt { request_id, rand } => {
             let result = ad4m_client
                 .agent
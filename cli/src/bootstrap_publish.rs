@@ -1,4 +1,4 @@
-use ad4m_client::Ad4mClient;
+use ad4m_client::{types::LanguagePermissions, Ad4mClient};
 use colour::green_ln;
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -30,6 +30,12 @@ pub struct ProtoMeta {
     pub source_code_link: String,
     #[serde(rename = "possibleTemplateParams")]
     pub possible_template_params: Vec<String>,
+    #[serde(default)]
+    pub permissions: Option<LanguagePermissions>,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub supersedes: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -105,6 +111,9 @@ pub async fn start_publishing(
                 Some(language.meta.description),
                 Some(language.meta.possible_template_params),
                 Some(language.meta.source_code_link),
+                language.meta.permissions,
+                language.meta.version,
+                language.meta.supersedes,
             )
             .await
             .expect("Could not publish language");
@@ -141,6 +150,9 @@ pub async fn start_publishing(
                 Some(language.meta.description),
                 Some(language.meta.possible_template_params),
                 Some(language.meta.source_code_link),
+                language.meta.permissions,
+                language.meta.version,
+                language.meta.supersedes,
             )
             .await
             .expect("Could not publish language");
@@ -164,6 +176,9 @@ pub async fn start_publishing(
                 Some(language.meta.description),
                 Some(language.meta.possible_template_params),
                 Some(language.meta.source_code_link),
+                language.meta.permissions,
+                language.meta.version,
+                language.meta.supersedes,
             )
             .await
             .expect("Could not publish language");
@@ -1,6 +1,7 @@
 use anyhow::Result;
 use clap::Subcommand;
 use colour::{self, green_ln};
+use rust_executor::agent::capabilities::rate_limits::RateLimitConfig;
 use std::{fs, str::FromStr};
 
 use crate::bootstrap_publish::*;
@@ -12,19 +13,60 @@ pub enum DevFunctions {
         agent_path: String,
         passphrase: String,
         seed_proto: String,
+        /// Throttle app tokens of the publishing executor, as <operation>=<limit>/<window_seconds>
+        #[arg(long = "rate-limit")]
+        rate_limits: Vec<RateLimitConfig>,
+        /// JSON file with timeouts and retries of Holochain zome calls per app, zome or function
+        #[arg(long, action)]
+        zome_call_policies: Option<String>,
     },
     PublishAndTestExpressionLanguage {
         language_path: String,
         data: String,
+        /// Throttle app tokens of the test executor, as <operation>=<limit>/<window_seconds>
+        #[arg(long = "rate-limit")]
+        rate_limits: Vec<RateLimitConfig>,
+        /// JSON file with timeouts and retries of Holochain zome calls per app, zome or function
+        #[arg(long, action)]
+        zome_call_policies: Option<String>,
+        /// Publish as the agent restored from a recovery phrase instead of a new test agent.
+        /// Reads the recovery phrase and the passphrase, one per line, from this file
+        /// or from stdin if given "-"
+        #[arg(long, action)]
+        restore_agent_from_mnemonic: Option<String>,
     },
 }
 
+fn read_zome_call_policies(
+    path: Option<String>,
+) -> Result<Option<Vec<rust_executor::config::ZomeCallPolicyConfig>>> {
+    match path {
+        Some(path) => Ok(Some(
+            rust_executor::config::read_zome_call_policies(&path)
+                .map_err(|e| anyhow::anyhow!("{}", e))?,
+        )),
+        None => Ok(None),
+    }
+}
+
 pub async fn run(command: DevFunctions) -> Result<()> {
     match command {
         DevFunctions::PublishAndTestExpressionLanguage {
             language_path,
             data,
+            rate_limits,
+            zome_call_policies,
+            restore_agent_from_mnemonic,
         } => {
+            let zome_call_policies = read_zome_call_policies(zome_call_policies)?;
+            let restore_agent_from_mnemonic = match restore_agent_from_mnemonic {
+                Some(path) => Some(
+                    rust_executor::config::read_agent_restore_config(&path)
+                        .map_err(|e| anyhow::anyhow!("{}", e))?,
+                ),
+                None => None,
+            };
+            let generate_agent = restore_agent_from_mnemonic.is_none();
             let ad4m_test_dir = dirs::home_dir()
                 .expect("Could not get home directory")
                 .join(".ad4m-test");
@@ -55,6 +97,16 @@ pub async fn run(command: DevFunctions) -> Result<()> {
                     auto_permit_cap_requests: Some(true),
                     tls: None,
                     log_holochain_metrics: None,
+                    rate_limits: (!rate_limits.is_empty()).then_some(rate_limits),
+                    wallet_kdf_memory_cost: None,
+                    wallet_kdf_iterations: None,
+                    js_call_timeout_secs: None,
+                    language_bundle_seed: None,
+                    zome_call_policies,
+                    metrics_endpoint: None,
+                    metrics_port: None,
+                    metrics_localhost: None,
+                    restore_agent_from_mnemonic,
                 })
                 .await
                 .join()
@@ -69,8 +121,10 @@ pub async fn run(command: DevFunctions) -> Result<()> {
                 );
                 let me = client.agent.me().await;
                 println!("Me: {:?}", me);
-                let agent_generate = client.agent.generate(String::from("test")).await;
-                println!("Agent generate: {:?}", agent_generate);
+                if generate_agent {
+                    let agent_generate = client.agent.generate(String::from("test")).await;
+                    println!("Agent generate: {:?}", agent_generate);
+                }
                 let publish_language = client
                     .languages
                     .publish(
@@ -79,6 +133,9 @@ pub async fn run(command: DevFunctions) -> Result<()> {
                         Some(String::from("some-desc")),
                         None,
                         None,
+                        None,
+                        None,
+                        None,
                     )
                     .await;
                 println!("Publish language: {:?}", publish_language);
@@ -113,7 +170,10 @@ pub async fn run(command: DevFunctions) -> Result<()> {
             agent_path,
             passphrase,
             seed_proto,
+            rate_limits,
+            zome_call_policies,
         } => {
+            let zome_call_policies = read_zome_call_policies(zome_call_policies)?;
             green_ln!(
                 "Attempting to generate a new bootstrap seed using agent path: {:?}\n",
                 agent_path
@@ -206,6 +266,16 @@ pub async fn run(command: DevFunctions) -> Result<()> {
                     auto_permit_cap_requests: Some(true),
                     tls: None,
                     log_holochain_metrics: None,
+                    rate_limits: (!rate_limits.is_empty()).then_some(rate_limits),
+                    wallet_kdf_memory_cost: None,
+                    wallet_kdf_iterations: None,
+                    js_call_timeout_secs: None,
+                    language_bundle_seed: None,
+                    zome_call_policies,
+                    metrics_endpoint: None,
+                    metrics_port: None,
+                    metrics_localhost: None,
+                    restore_agent_from_mnemonic: None,
                 })
                 .await
                 .join()
@@ -1,7 +1,10 @@
 use anyhow::{bail, Result};
 use serde_json::Value;
 
-use crate::types::{Agent, LinkExpression, PerspectiveExpression, SentPerspectiveMessage};
+use crate::types::{
+    Agent, Conversation, ConversationMessage, LanguagePermissions, LanguageUpgrade, LinkExpression,
+    PerspectiveExpression, SentPerspectiveMessage, TrustReport, TrustStatement,
+};
 
 pub fn print_prolog_results(results: Value) -> Result<()> {
     match results {
@@ -150,3 +153,108 @@ pub fn print_sent_message_perspective(sent: SentPerspectiveMessage) {
         print_link(link);
     }
 }
+
+pub fn print_trust_report(report: TrustReport) {
+    if report.trusted {
+        println!("\x1b[97m{} \x1b[32mtrusted ✅", report.did);
+    } else {
+        println!("\x1b[97m{} \x1b[31mnot trusted ❌", report.did);
+    }
+    if let Some(depth) = report.depth {
+        println!("\x1b[36mDepth: \x1b[97m{}", depth);
+    }
+    if !report.path.is_empty() {
+        println!("\x1b[36mPath: \x1b[97m{}", report.path.join(" -> "));
+    }
+    if !report.endorsers.is_empty() {
+        println!(
+            "\x1b[36mVouched for by: \x1b[97m{}",
+            report.endorsers.join(", ")
+        );
+    }
+    println!("\x1b[90m{}", report.reason);
+}
+
+pub fn print_trust_statement(statement: TrustStatement) {
+    println!(
+        "\x1b[36m{} \x1b[90mtrusts \x1b[97m{} \x1b[90m({})",
+        statement.issuer, statement.subject, statement.timestamp
+    );
+}
+
+pub fn print_language_permissions(permissions: LanguagePermissions) {
+    if permissions.is_empty() {
+        println!("\x1b[90mNothing beyond its own storage");
+        return;
+    }
+    for (name, entries) in [
+        ("Network", &permissions.net),
+        ("Read", &permissions.read),
+        ("Write", &permissions.write),
+        ("Environment", &permissions.env),
+        ("Run", &permissions.run),
+    ] {
+        if !entries.is_empty() {
+            println!("\x1b[36m{}: \x1b[97m{}", name, entries.join(", "));
+        }
+    }
+}
+
+pub fn print_language_upgrade(upgrade: LanguageUpgrade) {
+    let version = |version: Option<String>| version.unwrap_or_else(|| "<no version>".to_string());
+    println!(
+        "\x1b[36m{} \x1b[97m{} \x1b[90m-> \x1b[97m{}",
+        upgrade.name,
+        version(upgrade.version),
+        version(upgrade.upgrade_version)
+    );
+    println!("\x1b[36mAddress: \x1b[97m{}", upgrade.address);
+    println!("\x1b[36mUpgrade: \x1b[97m{}", upgrade.upgrade_address);
+    if let Some(perspective_uuid) = upgrade.perspective_uuid {
+        println!(
+            "\x1b[36mLink language of perspective: \x1b[97m{}",
+            perspective_uuid
+        );
+    }
+    if let Some(neighbourhood_url) = upgrade.neighbourhood_url {
+        println!("\x1b[36mAlready moved to: \x1b[97m{}", neighbourhood_url);
+    }
+}
+
+pub fn print_conversation(conversation: Conversation) {
+    println!(
+        "\x1b[36m{} \x1b[97m{}{}",
+        conversation.id,
+        conversation.name,
+        if conversation.encrypted {
+            " \x1b[90m(encrypted)"
+        } else {
+            ""
+        }
+    );
+    println!("\x1b[36mCreated by: \x1b[97m{}", conversation.created_by);
+    println!(
+        "\x1b[36mMembers: \x1b[97m{}",
+        conversation.members.join(", ")
+    );
+    println!("\x1b[36mLast activity: \x1b[97m{}", conversation.updated_at);
+    println!("\x1b[36mUnread: \x1b[97m{}", conversation.unread_count);
+}
+
+pub fn print_conversation_message(message: ConversationMessage) {
+    println!("\x1b[36mMessage: \x1b[97m{}", message.id);
+    println!("\x1b[36mFrom: {}", message.author);
+    println!("\x1b[36mTimestamp: {}", message.timestamp);
+    if let Some(reply_to) = message.reply_to {
+        println!("\x1b[36mReply to: {}", reply_to);
+    }
+    for link in message.message.links {
+        print_link(link);
+    }
+    if message.read_at.is_none() {
+        println!("\x1b[33mUnread");
+    }
+    for receipt in message.read_by {
+        println!("\x1b[90mRead by {} at {}", receipt.reader, receipt.read_at);
+    }
+}
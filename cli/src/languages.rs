@@ -1,8 +1,40 @@
-use ad4m_client::Ad4mClient;
+use crate::formatting::{print_language_permissions, print_language_upgrade};
+use ad4m_client::{types::LanguagePermissions, Ad4mClient};
 use anyhow::{Context, Result};
-use clap::Subcommand;
+use clap::{Args, Subcommand};
 use rustyline::Editor;
 
+#[derive(Args, Debug)]
+pub struct PermissionArgs {
+    /// Hosts the language can connect to, `host` or `host:port`, `*.domain` for subdomains
+    #[arg(long, value_delimiter = ',')]
+    net: Vec<String>,
+    /// Paths the language can read from
+    #[arg(long, value_delimiter = ',')]
+    read: Vec<String>,
+    /// Paths the language can write to
+    #[arg(long, value_delimiter = ',')]
+    write: Vec<String>,
+    /// Environment variables the language can read
+    #[arg(long, value_delimiter = ',')]
+    env: Vec<String>,
+    /// Programs the language can run
+    #[arg(long, value_delimiter = ',')]
+    run: Vec<String>,
+}
+
+impl From<PermissionArgs> for LanguagePermissions {
+    fn from(args: PermissionArgs) -> Self {
+        LanguagePermissions {
+            net: args.net,
+            read: args.read,
+            write: args.write,
+            env: args.env,
+            run: args.run,
+        }
+    }
+}
+
 #[derive(Debug, Subcommand)]
 pub enum LanguageFunctions {
     /// List all languages
@@ -36,6 +68,15 @@ pub enum LanguageFunctions {
         /// URL of public repository of the language source code
         #[arg(short, long)]
         source_code_link: Option<String>,
+        /// What the language needs to be allowed to do, users approve this when installing it
+        #[command(flatten)]
+        permissions: PermissionArgs,
+        /// Version of the language
+        #[arg(long)]
+        language_version: Option<String>,
+        /// Address of the previous version of the language, which has to be published by the same agent
+        #[arg(long)]
+        supersedes: Option<String>,
     },
     /// Show meta information about a language
     Meta { address: String },
@@ -43,6 +84,18 @@ pub enum LanguageFunctions {
     Source { address: String },
     /// Uninstall given language
     Remove { address: String },
+    /// Show what a language was approved to do
+    Permissions { address: String },
+    /// Approve what a language may do, replacing what was approved before
+    ApprovePermissions {
+        address: String,
+        #[command(flatten)]
+        permissions: PermissionArgs,
+    },
+    /// Take back all permissions of a language
+    RevokePermissions { address: String },
+    /// List newer versions of installed languages and of the link languages of neighbourhoods
+    Upgrades,
 }
 
 pub async fn run(ad4m_client: Ad4mClient, command: Option<LanguageFunctions>) -> Result<()> {
@@ -112,6 +165,9 @@ pub async fn run(ad4m_client: Ad4mClient, command: Option<LanguageFunctions>) ->
             description,
             possible_template_params,
             source_code_link,
+            permissions,
+            language_version,
+            supersedes,
         } => {
             let _ = std::fs::read_to_string(path.clone())
                 .with_context(|| format!("Could not read language file `{}`!", path))?;
@@ -164,6 +220,13 @@ pub async fn run(ad4m_client: Ad4mClient, command: Option<LanguageFunctions>) ->
                 Some(source_code_link)
             };
 
+            let permissions: LanguagePermissions = permissions.into();
+            let permissions = if permissions.is_empty() {
+                None
+            } else {
+                Some(permissions)
+            };
+
             let publish_result = ad4m_client
                 .languages
                 .publish(
@@ -172,6 +235,9 @@ pub async fn run(ad4m_client: Ad4mClient, command: Option<LanguageFunctions>) ->
                     description,
                     possible_template_params,
                     source_code_link,
+                    permissions,
+                    language_version,
+                    supersedes,
                 )
                 .await?;
             println!(
@@ -187,6 +253,40 @@ pub async fn run(ad4m_client: Ad4mClient, command: Option<LanguageFunctions>) ->
             ad4m_client.languages.remove(address).await?;
             println!("Language removed");
         }
+        LanguageFunctions::Permissions { address } => {
+            match ad4m_client.languages.permissions(address).await? {
+                Some(permissions) => print_language_permissions(permissions),
+                None => println!("No permissions approved"),
+            }
+        }
+        LanguageFunctions::ApprovePermissions {
+            address,
+            permissions,
+        } => {
+            let approved = ad4m_client
+                .languages
+                .approve_permissions(address, permissions.into())
+                .await?;
+            println!("Permissions approved:");
+            print_language_permissions(approved);
+        }
+        LanguageFunctions::RevokePermissions { address } => {
+            if ad4m_client.languages.revoke_permissions(address).await? {
+                println!("Permissions revoked");
+            } else {
+                println!("No permissions approved");
+            }
+        }
+        LanguageFunctions::Upgrades => {
+            let upgrades = ad4m_client.languages.upgrades().await?;
+            if upgrades.is_empty() {
+                println!("All languages are up to date");
+            }
+            for upgrade in upgrades {
+                print_language_upgrade(upgrade);
+                println!();
+            }
+        }
     };
     Ok(())
 }
@@ -182,7 +182,9 @@ async fn get_ad4m_client(args: &ClapApp) -> Result<Ad4mClient> {
             Domain::Agent { command } => match command {
                 AgentFunctions::Lock
                 | AgentFunctions::Unlock { .. }
-                | AgentFunctions::Generate { .. } => "".to_string(),
+                | AgentFunctions::Generate { .. }
+                | AgentFunctions::RestoreMnemonic { .. }
+                | AgentFunctions::RestoreBackup { .. } => "".to_string(),
                 _ => startup::get_cap_token(executor_url.clone()).await?,
             },
             _ => startup::get_cap_token(executor_url.clone()).await?,
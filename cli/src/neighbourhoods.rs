@@ -11,6 +11,13 @@ pub enum NeighbourhoodFunctions {
     Join {
         url: String,
     },
+    /// Move a neighbourhood to a newer version of its link language, or to where other members already moved it
+    Upgrade {
+        perspective_id: String,
+        /// Newer version of the link language's source, defaults to the newest one known
+        #[arg(short, long)]
+        language: Option<String>,
+    },
 }
 
 pub async fn run(ad4m_client: Ad4mClient, command: NeighbourhoodFunctions) -> Result<()> {
@@ -29,6 +36,16 @@ pub async fn run(ad4m_client: Ad4mClient, command: NeighbourhoodFunctions) -> Re
             let neighbourhood = ad4m_client.neighbourhoods.join(url).await?;
             println!("Neighbourhood joined!\n{:#?}", neighbourhood);
         }
+        NeighbourhoodFunctions::Upgrade {
+            perspective_id,
+            language,
+        } => {
+            let perspective = ad4m_client
+                .neighbourhoods
+                .upgrade_link_language(perspective_id, language)
+                .await?;
+            println!("Neighbourhood upgraded!\n{:#?}", perspective);
+        }
     };
     Ok(())
 }
@@ -53,6 +53,9 @@ pub enum PerspectiveFunctions {
     /// Retrieve snapshot of perspective with given uuid
     Snapshot { id: String },
 
+    /// Keep perspective with given uuid in sync between the agent's devices
+    SyncWithDevices { id: String, link_language: String },
+
     /// Run Prolog / SDNA query on perspective with given uuid
     Infer { id: String, query: String },
 
@@ -197,6 +200,13 @@ pub async fn run(ad4m_client: Ad4mClient, command: Option<PerspectiveFunctions>)
             let result = ad4m_client.perspectives.snapshot(id).await?;
             println!("{:#?}", result);
         }
+        PerspectiveFunctions::SyncWithDevices { id, link_language } => {
+            let url = ad4m_client
+                .perspectives
+                .sync_with_devices(id, link_language)
+                .await?;
+            println!("Synced between devices through {}", url);
+        }
         PerspectiveFunctions::Repl { id } => {
             //let _ = perspectives::run_watch(cap_token, id);
             repl_loop(ad4m_client.perspectives.get(id).await?).await?;
@@ -1,4 +1,7 @@
-use crate::formatting::{print_message_perspective, print_sent_message_perspective};
+use crate::formatting::{
+    print_conversation, print_conversation_message, print_message_perspective,
+    print_sent_message_perspective, print_trust_report, print_trust_statement,
+};
 use crate::util::string_2_perspective_snapshot;
 use ad4m_client::Ad4mClient;
 use anyhow::Result;
@@ -19,6 +22,34 @@ pub enum RuntimeFunctions {
         agents: Vec<String>,
     },
     TrustedAgents,
+    /// Show whether an agent is trusted, directly or through trust statements, and why
+    AgentTrust {
+        did: String,
+    },
+    /// Publish a signed statement that you trust an agent
+    TrustAgent {
+        did: String,
+    },
+    /// Withdraw your trust statement about an agent
+    RevokeTrust {
+        did: String,
+    },
+    /// List cached trust statements
+    TrustStatements {
+        #[arg(short, long)]
+        issuer: Option<String>,
+    },
+    /// Show or change how far trust spreads through trust statements
+    TrustConfig {
+        /// How many statements away from a trusted agent trust reaches, 0 disables it
+        #[arg(short, long)]
+        max_depth: Option<i64>,
+        /// How many trusted agents have to vouch for an agent
+        #[arg(short, long)]
+        threshold: Option<i64>,
+    },
+    /// Fetch the trust statements published by trusted agents
+    RefreshTrust,
     AddLinkLanguageTemplates {
         addresses: Vec<String>,
     },
@@ -59,6 +90,41 @@ pub enum RuntimeFunctions {
     MessageOutbox {
         filter: Option<String>,
     },
+    Conversations,
+    CreateConversation {
+        name: String,
+        members: Vec<String>,
+        /// End-to-end encrypt all messages of the conversation
+        #[arg(short, long)]
+        encrypted: bool,
+    },
+    ConversationMembers {
+        conversation_id: String,
+        #[arg(short, long)]
+        add: Vec<String>,
+        #[arg(short, long)]
+        remove: Vec<String>,
+    },
+    ConversationMessages {
+        conversation_id: String,
+        /// Only messages sent before the message with this id
+        #[arg(short, long)]
+        before: Option<String>,
+        #[arg(short, long)]
+        limit: Option<i64>,
+    },
+    MessageThread {
+        message_id: String,
+    },
+    ConversationSendMessage {
+        conversation_id: String,
+        message: String,
+        #[arg(short, long)]
+        reply_to: Option<String>,
+    },
+    MarkConversationRead {
+        conversation_id: String,
+    },
 }
 
 pub async fn run(ad4m_client: Ad4mClient, command: RuntimeFunctions) -> Result<()> {
@@ -85,6 +151,51 @@ pub async fn run(ad4m_client: Ad4mClient, command: RuntimeFunctions) -> Result<(
                 println!("{}", agent);
             }
         }
+        RuntimeFunctions::AgentTrust { did } => {
+            let report = ad4m_client.runtime.agent_trust(did).await?;
+            print_trust_report(report);
+        }
+        RuntimeFunctions::TrustAgent { did } => {
+            let statements = ad4m_client.runtime.trust_agent(did).await?;
+            println!("Trust statement published!");
+            for statement in statements {
+                print_trust_statement(statement);
+            }
+        }
+        RuntimeFunctions::RevokeTrust { did } => {
+            ad4m_client.runtime.revoke_trust(did).await?;
+            println!("Trust statement withdrawn!");
+        }
+        RuntimeFunctions::TrustStatements { issuer } => {
+            let statements = ad4m_client.runtime.trust_statements(issuer).await?;
+            for statement in statements {
+                print_trust_statement(statement);
+            }
+        }
+        RuntimeFunctions::TrustConfig {
+            max_depth,
+            threshold,
+        } => {
+            let current = ad4m_client.runtime.trust_config().await?;
+            let (max_depth, threshold) = if max_depth.is_some() || threshold.is_some() {
+                let config = ad4m_client
+                    .runtime
+                    .set_trust_config(
+                        max_depth.unwrap_or(current.max_depth),
+                        threshold.unwrap_or(current.threshold),
+                    )
+                    .await?;
+                (config.max_depth, config.threshold)
+            } else {
+                (current.max_depth, current.threshold)
+            };
+            println!("\x1b[36mMax depth: \x1b[97m{}", max_depth);
+            println!("\x1b[36mThreshold: \x1b[97m{}", threshold);
+        }
+        RuntimeFunctions::RefreshTrust => {
+            let fetched = ad4m_client.runtime.refresh_trust().await?;
+            println!("Fetched trust statements of {} agents", fetched);
+        }
         RuntimeFunctions::LinkLanguageTemplates => {
             let templates = ad4m_client.runtime.link_language_templates().await?;
             for template in templates {
@@ -205,6 +316,77 @@ pub async fn run(ad4m_client: Ad4mClient, command: RuntimeFunctions) -> Result<(
                 println!();
             }
         }
+        RuntimeFunctions::Conversations => {
+            let conversations = ad4m_client.runtime.conversations().await?;
+            for conversation in conversations {
+                print_conversation(conversation);
+                println!();
+            }
+        }
+        RuntimeFunctions::CreateConversation {
+            name,
+            members,
+            encrypted,
+        } => {
+            let id = ad4m_client
+                .runtime
+                .create_conversation(name, members, Some(encrypted))
+                .await?;
+            println!("Conversation created: {}", id);
+        }
+        RuntimeFunctions::ConversationMembers {
+            conversation_id,
+            add,
+            remove,
+        } => {
+            let members = ad4m_client
+                .runtime
+                .update_conversation_members(conversation_id, Some(add), Some(remove))
+                .await?;
+            for member in members {
+                println!("{}", member);
+            }
+        }
+        RuntimeFunctions::ConversationMessages {
+            conversation_id,
+            before,
+            limit,
+        } => {
+            let messages = ad4m_client
+                .runtime
+                .conversation_messages(conversation_id, before, limit)
+                .await?;
+            for message in messages {
+                print_conversation_message(message);
+                println!();
+            }
+        }
+        RuntimeFunctions::MessageThread { message_id } => {
+            let messages = ad4m_client.runtime.message_thread(message_id).await?;
+            for message in messages {
+                print_conversation_message(message);
+                println!();
+            }
+        }
+        RuntimeFunctions::ConversationSendMessage {
+            conversation_id,
+            message,
+            reply_to,
+        } => {
+            let message = string_2_perspective_snapshot(&ad4m_client, message).await?;
+            let id = ad4m_client
+                .runtime
+                .send_conversation_message(conversation_id, message.into(), reply_to)
+                .await?;
+            println!("Message sent: {}", id);
+        }
+        RuntimeFunctions::MarkConversationRead { conversation_id } => {
+            let count = ad4m_client
+                .runtime
+                .mark_conversation_read(conversation_id)
+                .await?;
+            println!("Marked {} messages as read", count);
+        }
     };
     Ok(())
 }
@@ -25,7 +25,7 @@ import PerspectiveResolver from "./perspectives/PerspectiveResolver";
 import RuntimeResolver from "./runtime/RuntimeResolver";
 import ExpressionResolver from "./expression/ExpressionResolver";
 import AIResolver from './ai/AIResolver'
-import { AuthInfoInput, EntanglementProofInput, CapabilityInput, ResourceInput } from "./agent/Agent";
+import { AuthInfoInput, EntanglementProofInput, CapabilityInput, ResourceInput, DeviceLinkInput } from "./agent/Agent";
 import { LanguageMetaInput } from "./language/LanguageMeta";
 import { InteractionCall } from "./language/Language";
 import { PerspectiveState } from "./perspectives/PerspectiveHandle";
@@ -152,6 +152,7 @@ describe('Ad4mClient', () => {
             const agentStatus = await ad4mClient.agent.generate("passphrase")
             expect(agentStatus.did).toBeDefined()
             expect(agentStatus.isInitialized).toBeTruthy()
+            expect(agentStatus.mnemonic!.split(" ").length).toBe(24)
         })
 
         it('lock() smoke test', async () => {
@@ -250,6 +251,27 @@ describe('Ad4mClient', () => {
             expect(preflight.didSignedByDeviceKey).toBe(null)
         })
 
+        it('device linking smoke tests', async () => {
+            const link = await ad4mClient.agent.authoriseDevice("did:key:device")
+            expect(link.proof.did).toBe("did:ad4m:test")
+            expect(link.proof.deviceKeyType).toBe("ad4m")
+            expect(link.proof.deviceKey).toBe("did:key:device")
+            expect(link.neighbourhoods).toStrictEqual(["neighbourhood://Qm12345"])
+            expect(link.syncKey).toBe("sealed-sync-key")
+
+            const proof = await ad4mClient.agent.linkDevice(new DeviceLinkInput(
+                link.proof.did,
+                link.proof.didSigningKeyId,
+                link.proof.deviceKey,
+                link.proof.deviceKeySignedByDid,
+                link.neighbourhoods,
+                link.syncKey
+            ), "passphrase")
+            expect(proof.did).toBe("did:ad4m:test")
+            expect(proof.deviceKey).toBe("did:key:device")
+            expect(proof.didSignedByDeviceKey).toBe("sig2")
+        })
+
         it('requestCapability() smoke tests', async () => {
             const requestId = await ad4mClient.agent.requestCapability({
                 appName: "demo-app",
@@ -289,6 +311,7 @@ describe('Ad4mClient', () => {
             const newApps = await ad4mClient.agent.revokeToken('test-request-id')
             expect(newApps.length).toBe(1)
             expect(newApps[0].revoked).toBe(true)
+            expect(newApps[0].tokenHash).toBe("test-token-hash")
         })
 
         it('agentRemoveToken() smoke tests', async () => {
@@ -306,6 +329,23 @@ describe('Ad4mClient', () => {
             expect(sig.signature).toBe("test-message-signature")
             expect(sig.publicKey).toBe("test-public-key")
         })
+
+        it('agentSignMessage() with a named key smoke tests', async () => {
+            const sig = await ad4mClient.agent.signMessage("test-message", "test-key")
+            expect(sig.publicKey).toBe("did:key:test-key")
+        })
+
+        it('agentKeys() smoke tests', async () => {
+            const keys = await ad4mClient.agent.keys()
+            expect(keys.length).toBe(1)
+            expect(keys[0].name).toBe("test-key")
+        })
+
+        it('agentGenerateKey() smoke tests', async () => {
+            const key = await ad4mClient.agent.generateKey("app-key", "secret")
+            expect(key.name).toBe("app-key")
+            expect(key.did).toBe("did:key:test-key")
+        })
     })
 
     describe('.expression', () => {
@@ -405,6 +445,8 @@ describe('Ad4mClient', () => {
             input.description = "Language for smoke testing"
             input.possibleTemplateParams = ['uuid', 'name', 'membrane']
             input.sourceCodeLink = "https://github.com/perspect3vism/test-language"
+            input.version = "2.0.0"
+            input.supersedes = "Qm1234"
 
             const languageMeta = await ad4mClient.languages.publish(
                 '/some/language/path/',
@@ -418,6 +460,8 @@ describe('Ad4mClient', () => {
             expect(languageMeta.author).toBe("did:test:me")
             expect(languageMeta.templateSourceLanguageAddress).toBe("Qm12345")
             expect(languageMeta.templateAppliedParams).toBe(JSON.stringify({uuid: 'asdfsdaf', name: 'test template'}))
+            expect(languageMeta.version).toBe("2.0.0")
+            expect(languageMeta.supersedes).toBe("Qm1234")
         })
 
         it('meta() smoke test', async () => {
@@ -438,6 +482,36 @@ describe('Ad4mClient', () => {
             expect(languageMeta.templateAppliedParams).toBe(JSON.stringify({uuid: 'asdfsdaf', name: 'test template'}))
             expect(languageMeta.possibleTemplateParams).toStrictEqual(['uuid', 'name'])
             expect(languageMeta.sourceCodeLink).toBe("https://github.com/perspect3vism/ad4m")
+            expect(languageMeta.permissions!.net).toStrictEqual(['api.example.com:443'])
+            expect(languageMeta.version).toBe("2.0.0")
+            expect(languageMeta.supersedes).toBe("Qm1234")
+        })
+
+        it('upgrades() smoke test', async () => {
+            const upgrades = await ad4mClient.languages.upgrades()
+            expect(upgrades.length).toBe(1)
+            expect(upgrades[0].address).toBe("test-link-language")
+            expect(upgrades[0].upgradeAddress).toBe("Qm12345")
+            expect(upgrades[0].upgradeVersion).toBe("2.0.0")
+            expect(upgrades[0].perspectiveUuid).toBe("00001")
+            expect(upgrades[0].neighbourhoodUrl).toBe("neighbourhood://Qm123456")
+        })
+
+        it('permissions() smoke test', async () => {
+            const permissions = await ad4mClient.languages.permissions("Qm12345")
+            expect(permissions!.net).toStrictEqual(['api.example.com:443'])
+            expect(permissions!.env).toStrictEqual(['HOME'])
+        })
+
+        it('approvePermissions() smoke test', async () => {
+            const permissions = await ad4mClient.languages.approvePermissions("Qm12345", { net: ['example.com'] })
+            expect(permissions.net).toStrictEqual(['example.com'])
+            expect(permissions.read).toStrictEqual([])
+        })
+
+        it('revokePermissions() smoke test', async () => {
+            const result = await ad4mClient.languages.revokePermissions("Qm12345")
+            expect(result).toBe(true)
         })
 
         it('source() smoke test', async () => {
@@ -478,6 +552,12 @@ describe('Ad4mClient', () => {
             expect(perspective.name).toBeTruthy()
         })
 
+        it('upgradeLinkLanguage() smoke test', async () => {
+            const perspective = await ad4mClient.neighbourhood.upgradeLinkLanguage('01234', 'Qm12345')
+            expect(perspective.uuid).toBe('01234')
+            expect(perspective.sharedUrl).toBe('neighbourhood://upgradedNeighbourhoodAddress')
+        })
+
         it('hasTelepresenceAdapter() smoke test', async () => {
             const result = await ad4mClient.neighbourhood.hasTelepresenceAdapter('01234')
             expect(result).toBe(true)
@@ -582,6 +662,11 @@ describe('Ad4mClient', () => {
 
         })
 
+        it('syncWithDevices() smoke test', async () => {
+            const url = await ad4mClient.perspective.syncWithDevices('00004', 'lang://Qm123')
+            expect(url).toBe('neighbourhood://Qm12345')
+        })
+
         it('queryLinks() smoke test', async () => {
             const links = await ad4mClient.perspective.queryLinks('000001', {source: 'root'})
             expect(links.length).toBe(1)
@@ -968,9 +1053,16 @@ describe('Ad4mClient', () => {
             await ad4mClient.runtime.grantNotification("test-notification");
         })
 
+        it('rotateNotificationWebhookSecret smoke test', async () => {
+            const secret = await ad4mClient.runtime.rotateNotificationWebhookSecret("test-notification");
+            expect(secret).toBe("test-signing-secret");
+        })
+
         it('notifications smoke test', async () => {
             const notifications = await ad4mClient.runtime.notifications();
             expect(notifications.length).toBe(1);
+            expect(notifications[0].hasWebhookAuth).toBe(true);
+            expect((notifications[0] as any).webhookAuth).toBeUndefined();
         })
 
         it('updateNotification smoke test', async () => {
@@ -4,4 +4,6 @@ export enum ExceptionType {
     AgentIsUntrusted = "AGENT_IS_UNTRUSTED",
     CapabilityRequested = "CAPABILITY_REQUESTED",
     InstallNotificationRequest = 'INSTALL_NOTIFICATION_REQUEST',
+    LanguagePermissionsRequested = 'LANGUAGE_PERMISSIONS_REQUESTED',
+    LanguagePermissionDenied = 'LANGUAGE_PERMISSION_DENIED',
 }
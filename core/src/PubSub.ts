@@ -1,6 +1,7 @@
 export const AGENT_UPDATED = 'agent-updated-topic'
 export const AGENT_STATUS_CHANGED = 'agent-status-changed-topic'
 export const RUNTIME_MESSAGED_RECEIVED_TOPIC = 'runtime-messaged-received-topic'
+export const RUNTIME_FRIEND_REQUEST_RECEIVED_TOPIC = 'runtime-friend-request-received-topic'
 export const PERSPECTIVE_ADDED_TOPIC = 'perspective-added-topic'
 export const PERSPECTIVE_UPDATED_TOPIC = 'perspective-updated-topic'
 export const PERSPECTIVE_REMOVED_TOPIC = 'perspective-removed-topic'
@@ -2,6 +2,18 @@ import { Field, ObjectType, InputType } from "type-graphql";
 import { Perspective } from "../perspectives/Perspective";
 import { ExpressionGeneric } from "../expression/Expression";
 
+/** X25519 public key of an Agent, signed by the Agent's DID */
+@ObjectType()
+export class AgentEncryptionKey {
+  /** Hex encoded public key */
+  @Field()
+  publicKey: string;
+
+  /** Signature of the Agent's DID over the public key */
+  @Field()
+  signature: string;
+}
+
 /**  AD4M's representation of an Agent
  *
  * AD4M Agents are build around DIDs, which are used to identify and authenticate the Agent.
@@ -39,6 +51,18 @@ export class Agent {
   @Field({ nullable: true })
   directMessageLanguage?: string;
 
+  /** Key other agents encrypt direct messages to this Agent with */
+  @Field((type) => AgentEncryptionKey, { nullable: true })
+  encryptionKey?: AgentEncryptionKey;
+
+  /** Proofs of other executors linked as devices of this Agent, whose signatures count as the Agent's */
+  @Field((type) => [EntanglementProof], { nullable: true })
+  deviceProofs?: EntanglementProof[];
+
+  /** Rotations of the Agent's key, so signatures of its previous keys keep verifying */
+  @Field((type) => [KeySuccession], { nullable: true })
+  keySuccessions?: KeySuccession[];
+
   constructor(did: string, perspective?: Perspective) {
     this.did = did;
     if (perspective) {
@@ -89,6 +113,49 @@ export class EntanglementProof {
   }
 }
 
+/** Statement signed by previousDid that the Agent's key got rotated to newDid */
+@ObjectType()
+export class KeySuccession {
+  @Field()
+  previousDid: string;
+
+  @Field()
+  newDid: string;
+
+  @Field()
+  timestamp: string;
+
+  @Field()
+  signature: string;
+
+  constructor(
+    previousDid: string,
+    newDid: string,
+    timestamp: string,
+    signature: string
+  ) {
+    this.previousDid = previousDid;
+    this.newDid = newDid;
+    this.timestamp = timestamp;
+    this.signature = signature;
+  }
+}
+
+/** Additional key of the Agent, usable in signMessage next to the main key */
+@ObjectType()
+export class AgentKey {
+  @Field()
+  name: string;
+
+  @Field()
+  did: string;
+
+  constructor(name: string, did: string) {
+    this.name = name;
+    this.did = did;
+  }
+}
+
 @InputType()
 export class EntanglementProofInput {
   @Field()
@@ -126,6 +193,66 @@ export class EntanglementProofInput {
   }
 }
 
+@ObjectType()
+export class DeviceLink {
+  @Field((type) => EntanglementProof)
+  proof: EntanglementProof;
+
+  @Field((type) => [String])
+  neighbourhoods: string[];
+
+  /** Key of the device sync neighbourhoods, sealed to the device */
+  @Field()
+  syncKey: string;
+
+  constructor(
+    proof: EntanglementProof,
+    neighbourhoods: string[],
+    syncKey: string
+  ) {
+    this.proof = proof;
+    this.neighbourhoods = neighbourhoods;
+    this.syncKey = syncKey;
+  }
+}
+
+@InputType()
+export class DeviceLinkInput {
+  @Field()
+  did: string;
+
+  @Field()
+  didSigningKeyId: string;
+
+  @Field()
+  deviceKey: string;
+
+  @Field()
+  deviceKeySignedByDid: string;
+
+  @Field((type) => [String])
+  neighbourhoods: string[];
+
+  @Field()
+  syncKey: string;
+
+  constructor(
+    did: string,
+    didSigningKeyId: string,
+    deviceKey: string,
+    deviceKeySignedByDid: string,
+    neighbourhoods: string[],
+    syncKey: string
+  ) {
+    this.did = did;
+    this.didSigningKeyId = didSigningKeyId;
+    this.deviceKey = deviceKey;
+    this.deviceKeySignedByDid = deviceKeySignedByDid;
+    this.neighbourhoods = neighbourhoods;
+    this.syncKey = syncKey;
+  }
+}
+
 @ObjectType()
 export class AgentSignature {
   @Field()
@@ -205,9 +332,12 @@ export class Apps {
   @Field()
   requestId: string;
 
-  @Field()
+  @Field({ deprecationReason: "Tokens are not stored, always empty. Use tokenHash" })
   token: string;
 
+  @Field()
+  tokenHash: string;
+
   @Field({ nullable: true })
   revoked?: boolean;
 
@@ -217,12 +347,13 @@ export class Apps {
   constructor(
     requestId: string,
     auth: AuthInfo,
-    token: string,
+    tokenHash: string,
     revoked?: boolean
   ) {
     this.requestId = requestId;
     this.auth = auth;
-    this.token = token;
+    this.token = "";
+    this.tokenHash = tokenHash;
     this.revoked = revoked;
   }
 }
@@ -3,9 +3,12 @@ import { PerspectiveInput } from "../perspectives/Perspective";
 import unwrapApolloResult from "../unwrapApolloResult";
 import {
   Agent,
+  AgentKey,
   Apps,
   AuthInfo,
   AuthInfoInput,
+  DeviceLink,
+  DeviceLinkInput,
   EntanglementProof,
   EntanglementProofInput,
 } from "./Agent";
@@ -16,6 +19,9 @@ import { PerspectiveClient } from "../perspectives/PerspectiveClient";
 const AGENT_SUBITEMS = `
     did
     directMessageLanguage
+    encryptionKey { publicKey, signature }
+    deviceProofs { did, didSigningKeyId, deviceKeyType, deviceKey, deviceKeySignedByDid, didSignedByDeviceKey }
+    keySuccessions { previousDid, newDid, timestamp, signature }
     perspective { 
         links {
             author, timestamp, 
@@ -32,6 +38,7 @@ const AGENT_SUBITEMS = `
 const Apps_FIELDS = `
     requestId
     revoked
+    tokenHash
     auth {
         appName
         appDesc
@@ -117,6 +124,9 @@ export class AgentClient {
     );
     let agentObject = new Agent(agent.did, agent.perspective);
     agentObject.directMessageLanguage = agent.directMessageLanguage;
+    agentObject.encryptionKey = agent.encryptionKey;
+    agentObject.deviceProofs = agent.deviceProofs;
+    agentObject.keySuccessions = agent.keySuccessions;
     return agentObject;
   }
 
@@ -141,6 +151,7 @@ export class AgentClient {
             ) {
                 agentGenerate(passphrase: $passphrase) {
                     ${AGENT_STATUS_FIELDS}
+                    mnemonic
                 }
             }`,
         variables: { passphrase },
@@ -169,6 +180,87 @@ export class AgentClient {
     return new AgentStatus(agentImport);
   }
 
+  async restoreFromMnemonic(mnemonic: string, passphrase: string): Promise<AgentStatus> {
+    const { agentRestoreFromMnemonic } = unwrapApolloResult(
+      await this.#apolloClient.mutate({
+        mutation: gql`mutation agentRestoreFromMnemonic(
+                $mnemonic: String!,
+                $passphrase: String!
+            ) {
+                agentRestoreFromMnemonic(mnemonic: $mnemonic, passphrase: $passphrase) {
+                    ${AGENT_STATUS_FIELDS}
+                }
+            }`,
+        variables: { mnemonic, passphrase },
+      })
+    );
+    return new AgentStatus(agentRestoreFromMnemonic);
+  }
+
+  async importBackup(backup: string, passphrase: string): Promise<AgentStatus> {
+    const { agentImportBackup } = unwrapApolloResult(
+      await this.#apolloClient.mutate({
+        mutation: gql`mutation agentImportBackup(
+                $backup: String!,
+                $passphrase: String!
+            ) {
+                agentImportBackup(backup: $backup, passphrase: $passphrase) {
+                    ${AGENT_STATUS_FIELDS}
+                }
+            }`,
+        variables: { backup, passphrase },
+      })
+    );
+    return new AgentStatus(agentImportBackup);
+  }
+
+  async mnemonic(passphrase: string): Promise<string> {
+    const { agentMnemonic } = unwrapApolloResult(
+      await this.#apolloClient.query({
+        query: gql`query agentMnemonic($passphrase: String!) {
+                agentMnemonic(passphrase: $passphrase)
+            }`,
+        variables: { passphrase },
+      })
+    );
+    return agentMnemonic;
+  }
+
+  async keys(): Promise<AgentKey[]> {
+    const { agentKeys } = unwrapApolloResult(
+      await this.#apolloClient.query({
+        query: gql`query agentKeys {
+                agentKeys { name, did }
+            }`,
+      })
+    );
+    return agentKeys;
+  }
+
+  async generateKey(name: string, passphrase: string): Promise<AgentKey> {
+    const { agentGenerateKey } = unwrapApolloResult(
+      await this.#apolloClient.mutate({
+        mutation: gql`mutation agentGenerateKey($name: String!, $passphrase: String!) {
+                agentGenerateKey(name: $name, passphrase: $passphrase) { name, did }
+            }`,
+        variables: { name, passphrase },
+      })
+    );
+    return agentGenerateKey;
+  }
+
+  async exportBackup(passphrase: string): Promise<string> {
+    const { agentExportBackup } = unwrapApolloResult(
+      await this.#apolloClient.query({
+        query: gql`query agentExportBackup($passphrase: String!) {
+                agentExportBackup(passphrase: $passphrase)
+            }`,
+        variables: { passphrase },
+      })
+    );
+    return agentExportBackup;
+  }
+
   async lock(passphrase: string): Promise<AgentStatus> {
     const { agentLock } = unwrapApolloResult(
       await this.#apolloClient.mutate({
@@ -349,6 +441,49 @@ export class AgentClient {
     return agentEntanglementProofPreFlight;
   }
 
+  /**
+   * Authorises the executor whose main key has the DID `deviceDid` to act as another
+   * device of this agent. Hand the result to `linkDevice()` on that executor.
+   */
+  async authoriseDevice(deviceDid: string): Promise<DeviceLink> {
+    const { agentAuthoriseDevice } = unwrapApolloResult(
+      await this.#apolloClient.mutate({
+        mutation: gql`mutation agentAuthoriseDevice($deviceDid: String!) {
+                agentAuthoriseDevice(deviceDid: $deviceDid) {
+                    proof {
+                        ${ENTANGLEMENT_PROOF_FIELDS}
+                    }
+                    neighbourhoods
+                    syncKey
+                }
+            }`,
+        variables: { deviceDid },
+      })
+    );
+    return agentAuthoriseDevice;
+  }
+
+  /**
+   * Links this executor to the agent that authorised it. The returned proof
+   * needs to be added with `addEntanglementProofs()` on the primary device.
+   */
+  async linkDevice(
+    link: DeviceLinkInput,
+    passphrase: string
+  ): Promise<EntanglementProof> {
+    const { agentLinkDevice } = unwrapApolloResult(
+      await this.#apolloClient.mutate({
+        mutation: gql`mutation agentLinkDevice($link: DeviceLinkInput!, $passphrase: String!) {
+                agentLinkDevice(link: $link, passphrase: $passphrase) {
+                    ${ENTANGLEMENT_PROOF_FIELDS}
+                }
+            }`,
+        variables: { link, passphrase },
+      })
+    );
+    return agentLinkDevice;
+  }
+
   addUpdatedListener(listener) {
     this.#updatedCallbacks.push(listener);
   }
@@ -515,15 +650,16 @@ export class AgentClient {
     return agentIsLocked;
   }
 
-  async signMessage(message: string): Promise<string> {
+  /** Signs with the main key, or with the named key created by generateKey() */
+  async signMessage(message: string, keyName?: string): Promise<string> {
     const { agentSignMessage } = unwrapApolloResult(
       await this.#apolloClient.mutate({
-        mutation: gql`mutation agentSignMessage($message: String!) {
-          agentSignMessage(message: $message) {
+        mutation: gql`mutation agentSignMessage($message: String!, $keyName: String) {
+          agentSignMessage(message: $message, keyName: $keyName) {
               ${AGENT_SIGNATURE_FIELDS}
           }
         }`,
-        variables: { message },
+        variables: { message, keyName },
       })
     );
     return agentSignMessage;
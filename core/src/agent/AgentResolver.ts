@@ -9,9 +9,12 @@ import {
 import { Perspective, PerspectiveInput } from "../perspectives/Perspective";
 import {
   Agent,
+  AgentKey,
   AgentSignature,
   Apps,
   AuthInfoInput,
+  DeviceLink,
+  DeviceLinkInput,
   EntanglementProof,
   EntanglementProofInput,
 } from "./Agent";
@@ -19,6 +22,7 @@ import { AgentStatus } from "./AgentStatus";
 import { AGENT_STATUS_CHANGED, AGENT_UPDATED, APPS_CHANGED } from "../PubSub";
 
 export const TEST_AGENT_DID = "did:ad4m:test";
+const TEST_MNEMONIC = "abandon ".repeat(23) + "art";
 
 @Resolver()
 export default class AgentResolver {
@@ -44,7 +48,7 @@ export default class AgentResolver {
       isUnlocked: true,
     });
     pubSub.publish(AGENT_STATUS_CHANGED, { status });
-    return status;
+    return new AgentStatus({ ...status, mnemonic: TEST_MNEMONIC });
   }
 
   @Mutation((returns) => AgentStatus)
@@ -62,6 +66,61 @@ export default class AgentResolver {
     });
   }
 
+  @Mutation((returns) => AgentStatus)
+  agentRestoreFromMnemonic(
+    @Arg("mnemonic") mnemonic: string,
+    @Arg("passphrase") passphrase: string,
+    @PubSub() pubSub: any
+  ): AgentStatus {
+    const status = new AgentStatus({
+      did: TEST_AGENT_DID,
+      didDocument: "did document",
+      isInitialized: true,
+      isUnlocked: true,
+    });
+    pubSub.publish(AGENT_STATUS_CHANGED, { status });
+    return status;
+  }
+
+  @Mutation((returns) => AgentStatus)
+  agentImportBackup(
+    @Arg("backup") backup: string,
+    @Arg("passphrase") passphrase: string,
+    @PubSub() pubSub: any
+  ): AgentStatus {
+    const status = new AgentStatus({
+      did: TEST_AGENT_DID,
+      didDocument: "did document",
+      isInitialized: true,
+      isUnlocked: true,
+    });
+    pubSub.publish(AGENT_STATUS_CHANGED, { status });
+    return status;
+  }
+
+  @Query((returns) => String)
+  agentMnemonic(@Arg("passphrase") passphrase: string): string {
+    return TEST_MNEMONIC;
+  }
+
+  @Query((returns) => [AgentKey])
+  agentKeys(): AgentKey[] {
+    return [new AgentKey("test-key", "did:key:test-key")];
+  }
+
+  @Mutation((returns) => AgentKey)
+  agentGenerateKey(
+    @Arg("name") name: string,
+    @Arg("passphrase") passphrase: string
+  ): AgentKey {
+    return new AgentKey(name, "did:key:test-key");
+  }
+
+  @Query((returns) => String)
+  agentExportBackup(@Arg("passphrase") passphrase: string): string {
+    return "encrypted backup";
+  }
+
   @Mutation((returns) => AgentStatus)
   agentLock(
     @Arg("passphrase") passphrase: string,
@@ -189,6 +248,36 @@ export default class AgentResolver {
     );
   }
 
+  @Mutation((returns) => DeviceLink)
+  agentAuthoriseDevice(@Arg("deviceDid") deviceDid: string): DeviceLink {
+    return new DeviceLink(
+      new EntanglementProof(
+        TEST_AGENT_DID,
+        "did-key-id",
+        "ad4m",
+        deviceDid,
+        "sig"
+      ),
+      ["neighbourhood://Qm12345"],
+      "sealed-sync-key"
+    );
+  }
+
+  @Mutation((returns) => EntanglementProof)
+  agentLinkDevice(
+    @Arg("link") link: DeviceLinkInput,
+    @Arg("passphrase") passphrase: string
+  ): EntanglementProof {
+    return new EntanglementProof(
+      link.did,
+      link.didSigningKeyId,
+      "ad4m",
+      link.deviceKey,
+      link.deviceKeySignedByDid,
+      "sig2"
+    );
+  }
+
   @Mutation((returns) => String)
   agentRequestCapability(@Arg("authInfo") authInfo: AuthInfoInput): String {
     return "test-request-id";
@@ -251,13 +340,20 @@ export default class AgentResolver {
             },
           ],
         },
-        token: "test-token",
+        token: "",
+        tokenHash: "test-token-hash",
       },
     ];
   }
 
   @Mutation((returns) => AgentSignature)
-  agentSignMessage(@Arg("message") message: string): AgentSignature {
-    return new AgentSignature("test-message-signature", "test-public-key");
+  agentSignMessage(
+    @Arg("message") message: string,
+    @Arg("keyName", { nullable: true }) keyName?: string
+  ): AgentSignature {
+    return new AgentSignature(
+      "test-message-signature",
+      keyName ? "did:key:test-key" : "test-public-key"
+    );
   }
 }
@@ -17,6 +17,9 @@ export class AgentStatus {
     @Field({nullable: true})
     error?: string
 
+    @Field({nullable: true})
+    mnemonic?: string
+
     constructor(obj?: object) {
         if(obj) {
             //@ts-ignore
@@ -35,6 +38,8 @@ export class AgentStatus {
             this.didDocument = obj.didDocument
             //@ts-ignore
             this.error = obj.error
+            //@ts-ignore
+            this.mnemonic = obj.mnemonic
         } else {
             this.isInitialized = false
             this.isUnlocked = false
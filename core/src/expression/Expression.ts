@@ -17,6 +17,11 @@ export class ExpressionProof {
     @Field({nullable: true})
     invalid?: boolean;
 
+    /** Signed with a key the author has rotated away from since. Such signatures only count
+     * because of their timestamp, which the signer chose. */
+    @Field({nullable: true})
+    retiredKey?: boolean;
+
     constructor(sig: string, k: string) {
         this.key = k
         this.signature = sig
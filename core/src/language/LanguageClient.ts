@@ -1,7 +1,7 @@
 import { ApolloClient, gql } from "@apollo/client/core"
 import unwrapApolloResult from "../unwrapApolloResult"
 import { LanguageHandle } from "./LanguageHandle"
-import { LanguageMeta, LanguageMetaInput } from "./LanguageMeta"
+import { LanguageMeta, LanguageMetaInput, LanguagePermissions, LanguagePermissionsInput, LanguageUpgrade } from "./LanguageMeta"
 import { LanguageRef } from "./LanguageRef"
 
 const LANGUAGE_COMPLETE = `
@@ -13,6 +13,14 @@ const LANGUAGE_COMPLETE = `
     settingsIcon { code }
 `
 
+const LANGUAGE_PERMISSIONS = `
+    net
+    read
+    write
+    env
+    run
+`
+
 const LANGUAGE_META = `
     name
     address
@@ -23,6 +31,9 @@ const LANGUAGE_META = `
     templateAppliedParams
     possibleTemplateParams
     sourceCodeLink
+    permissions { ${LANGUAGE_PERMISSIONS} }
+    version
+    supersedes
 `
 
 export class LanguageClient {
@@ -143,6 +154,67 @@ export class LanguageClient {
         return languageSource
     }
 
+    async permissions(address: string): Promise<LanguagePermissions | null> {
+        const { languagePermissions } = unwrapApolloResult(await this.#apolloClient.query({
+            query: gql`query languagePermissions($address: String!) {
+                languagePermissions(address: $address) {
+                    ${LANGUAGE_PERMISSIONS}
+                }
+            }`,
+            variables: { address }
+        }))
+
+        return languagePermissions
+    }
+
+    async approvePermissions(
+        address: string,
+        permissions: LanguagePermissionsInput
+    ): Promise<LanguagePermissions> {
+        const { languageApprovePermissions } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation languageApprovePermissions(
+                $address: String!,
+                $permissions: LanguagePermissionsInput!
+            ) {
+                languageApprovePermissions(address: $address, permissions: $permissions) {
+                    ${LANGUAGE_PERMISSIONS}
+                }
+            }`,
+            variables: { address, permissions }
+        }))
+
+        return languageApprovePermissions
+    }
+
+    async revokePermissions(address: string): Promise<Boolean> {
+        const { languageRevokePermissions } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation languageRevokePermissions($address: String!) {
+                languageRevokePermissions(address: $address)
+            }`,
+            variables: { address }
+        }))
+
+        return languageRevokePermissions
+    }
+
+    async upgrades(): Promise<LanguageUpgrade[]> {
+        const { languageUpgrades } = unwrapApolloResult(await this.#apolloClient.query({
+            query: gql`query languageUpgrades {
+                languageUpgrades {
+                    address
+                    name
+                    version
+                    upgradeAddress
+                    upgradeVersion
+                    perspectiveUuid
+                    neighbourhoodUrl
+                }
+            }`
+        }))
+
+        return languageUpgrades
+    }
+
     async remove(
         address: string
     ): Promise<Boolean> {
@@ -25,11 +25,22 @@ export class Dna {
     zomeCalls: [string, string][]
 }
 
+/** Overrides the executor's timeout and retry policy for a single zome call */
+export interface ZomeCallOptions {
+    timeoutMs?: number;
+    /** How often a timed out call is tried again, only used if the call is idempotent */
+    retries?: number;
+    /** Delay before the first retry, doubled for every further one */
+    backoffMs?: number;
+    /** Whether the call can safely be repeated, e.g. because it only reads */
+    idempotent?: boolean;
+}
+
 export interface HolochainLanguageDelegate {
     /** Installs/registers a given DNA in the ad4m-executor */
     registerDNAs(dnas: Dna[], holochainSignalCallback?: AppSignalCb): Promise<void>;
     /** Makes a single call to a given holochain DNA. Underlying implementation puts these calls into a sync fifo queue */
-    call(dnaNick: string, zomeName: string, fnName: string, params: object|string): Promise<any>;
+    call(dnaNick: string, zomeName: string, fnName: string, params: object|string, options?: ZomeCallOptions): Promise<any>;
     /** Makes all supplied calls in parallel to the provided holochain dna... Should only be called on read operations to avoid source chain async mutation errors */
     callAsync(calls: {dnaNick: string, zomeName: string, fnName: string, params: object|string}[], timeoutMs?: number): Promise<any[]>;
 }
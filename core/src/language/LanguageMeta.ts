@@ -1,6 +1,42 @@
 import { Field, InputType, ObjectType } from "type-graphql";
 import { ExpressionGeneric } from "../expression/Expression";
 
+@ObjectType()
+export class LanguagePermissions {
+    @Field(type => [String])
+    net: string[];
+
+    @Field(type => [String])
+    read: string[];
+
+    @Field(type => [String])
+    write: string[];
+
+    @Field(type => [String])
+    env: string[];
+
+    @Field(type => [String])
+    run: string[];
+}
+
+@InputType()
+export class LanguagePermissionsInput {
+    @Field(type => [String], {nullable: true})
+    net?: string[];
+
+    @Field(type => [String], {nullable: true})
+    read?: string[];
+
+    @Field(type => [String], {nullable: true})
+    write?: string[];
+
+    @Field(type => [String], {nullable: true})
+    env?: string[];
+
+    @Field(type => [String], {nullable: true})
+    run?: string[];
+}
+
 @ObjectType()
 export class LanguageMeta {
     @Field()
@@ -29,6 +65,15 @@ export class LanguageMeta {
 
     @Field({nullable: true})
     sourceCodeLink?: string;
+
+    @Field(type => LanguagePermissions, {nullable: true})
+    permissions?: LanguagePermissions;
+
+    @Field({nullable: true})
+    version?: string;
+
+    @Field({nullable: true})
+    supersedes?: string;
 }
 
 @InputType()
@@ -45,6 +90,15 @@ export class LanguageMetaInput {
     @Field({nullable: true})
     sourceCodeLink?: string;
 
+    @Field(type => LanguagePermissionsInput, {nullable: true})
+    permissions?: LanguagePermissionsInput;
+
+    @Field({nullable: true})
+    version?: string;
+
+    @Field({nullable: true})
+    supersedes?: string;
+
     constructor(name?: string, description?: string) {
         this.name = name
         this.description = description
@@ -60,6 +114,33 @@ export class LanguageMetaInternal {
     templateAppliedParams?: string;
     possibleTemplateParams?: string[];
     sourceCodeLink?: string;
+    permissions?: LanguagePermissions;
+    version?: string;
+    supersedes?: string;
+}
+
+@ObjectType()
+export class LanguageUpgrade {
+    @Field()
+    address: string;
+
+    @Field()
+    name: string;
+
+    @Field({nullable: true})
+    version?: string;
+
+    @Field()
+    upgradeAddress: string;
+
+    @Field({nullable: true})
+    upgradeVersion?: string;
+
+    @Field({nullable: true})
+    perspectiveUuid?: string;
+
+    @Field({nullable: true})
+    neighbourhoodUrl?: string;
 }
 
 export class LanguageExpression extends ExpressionGeneric(LanguageMetaInternal) {};
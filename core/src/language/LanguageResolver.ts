@@ -2,7 +2,7 @@
 import { Arg, Mutation, Query, Resolver } from "type-graphql";
 import { Icon } from "./Icon";
 import { LanguageHandle } from "./LanguageHandle";
-import { LanguageMeta, LanguageMetaInput } from "./LanguageMeta"
+import { LanguageMeta, LanguageMetaInput, LanguagePermissions, LanguagePermissionsInput, LanguageUpgrade } from "./LanguageMeta"
 import { LanguageRef } from "./LanguageRef";
 
 /**
@@ -69,6 +69,15 @@ export default class LanguageResolver {
         meta.templateAppliedParams = JSON.stringify({uuid: 'asdfsdaf', name: 'test template'})
         meta.possibleTemplateParams = languageMeta.possibleTemplateParams
         meta.sourceCodeLink = languageMeta.sourceCodeLink
+        meta.permissions = {
+            net: languageMeta.permissions?.net ?? [],
+            read: languageMeta.permissions?.read ?? [],
+            write: languageMeta.permissions?.write ?? [],
+            env: languageMeta.permissions?.env ?? [],
+            run: languageMeta.permissions?.run ?? [],
+        }
+        meta.version = languageMeta.version
+        meta.supersedes = languageMeta.supersedes
         return meta
     }
 
@@ -84,9 +93,47 @@ export default class LanguageResolver {
         meta.templateAppliedParams = JSON.stringify({uuid: 'asdfsdaf', name: 'test template'})
         meta.possibleTemplateParams = ['uuid', 'name']
         meta.sourceCodeLink = "https://github.com/perspect3vism/ad4m"
+        meta.permissions = { net: ['api.example.com:443'], read: [], write: [], env: [], run: [] }
+        meta.version = "2.0.0"
+        meta.supersedes = "Qm1234"
         return meta
     }
 
+    @Query(returns => [LanguageUpgrade])
+    languageUpgrades(): LanguageUpgrade[] {
+        return [{
+            address: "test-link-language",
+            name: "test-links-language",
+            version: "1.0.0",
+            upgradeAddress: "Qm12345",
+            upgradeVersion: "2.0.0",
+            perspectiveUuid: "00001",
+            neighbourhoodUrl: "neighbourhood://Qm123456",
+        }]
+    }
+
+    @Query(returns => LanguagePermissions, {nullable: true})
+    languagePermissions(@Arg('address') address: string): LanguagePermissions {
+        return { net: ['api.example.com:443'], read: [], write: [], env: ['HOME'], run: [] }
+    }
+
+    @Mutation(returns => LanguagePermissions)
+    languageApprovePermissions(
+        @Arg('address') address: string,
+        @Arg('permissions') permissions: LanguagePermissionsInput,
+    ): LanguagePermissions {
+        return {
+            net: permissions.net ?? [],
+            read: permissions.read ?? [],
+            write: permissions.write ?? [],
+            env: permissions.env ?? [],
+            run: permissions.run ?? [],
+        }
+    }
+
+    @Mutation(returns => Boolean)
+    languageRevokePermissions(@Arg('address') address: string): Boolean { return true }
+
     @Query()
     languageSource(@Arg('address') address: string): string {
         return "var test = 'language source code'"
@@ -67,6 +67,21 @@ export class NeighbourhoodClient {
         return neighbourhoodJoinFromUrl
     }
 
+    async upgradeLinkLanguage(perspectiveUUID: string, languageAddress?: string): Promise<PerspectiveHandle> {
+        const { neighbourhoodUpgradeLinkLanguage } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation neighbourhoodUpgradeLinkLanguage($perspectiveUUID: String!, $languageAddress: String) {
+                neighbourhoodUpgradeLinkLanguage(perspectiveUUID: $perspectiveUUID, languageAddress: $languageAddress) {
+                    uuid
+                    name
+                    sharedUrl
+                    state
+                }
+            }`,
+            variables: { perspectiveUUID, languageAddress }
+        }))
+        return neighbourhoodUpgradeLinkLanguage
+    }
+
     async otherAgents(perspectiveUUID: string): Promise<DID[]> {
         const { neighbourhoodOtherAgents } = unwrapApolloResult(await this.#apolloClient.query({
             query: gql`query neighbourhoodOtherAgents($perspectiveUUID: String!) {
@@ -45,6 +45,21 @@ export default class NeighbourhoodResolver {
         return perspective
     }
 
+    @Mutation(returns => PerspectiveHandle)
+    neighbourhoodUpgradeLinkLanguage(
+        @Arg('perspectiveUUID') perspectiveUUID: string,
+        @Arg('languageAddress', { nullable: true }) languageAddress: string,
+        @PubSub() pubSub: any
+    ): PerspectiveHandle {
+        const perspective = new PerspectiveHandle
+        perspective.name = "test-perspective"
+        perspective.sharedUrl = "neighbourhood://upgradedNeighbourhoodAddress"
+        perspective.uuid = perspectiveUUID
+        perspective.state = PerspectiveState.LinkLanguageInstalledButNotSynced
+        pubSub.publish(PERSPECTIVE_UPDATED_TOPIC, { perspective});
+        return perspective
+    }
+
     @Query(returns => [String])
     neighbourhoodOtherAgents(@Arg('perspectiveUUID') perspectiveUUID: string): DID[] {
         return ['did:test:other']
@@ -121,6 +121,17 @@ export class PerspectiveClient {
         return perspectivePublishSnapshot
     }
 
+    /** Keeps the perspective in sync between the agent's devices, returns the URL of the neighbourhood doing it */
+    async syncWithDevices(uuid: string, linkLanguage: string): Promise<string> {
+        const { perspectiveSyncWithDevices } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation perspectiveSyncWithDevices($uuid: String!, $linkLanguage: String!) {
+                perspectiveSyncWithDevices(uuid: $uuid, linkLanguage: $linkLanguage)
+            }`,
+            variables: { uuid, linkLanguage }
+        }))
+        return perspectiveSyncWithDevices
+    }
+
     async queryLinks(uuid: string, query: LinkQuery): Promise<LinkExpression[]> {
         const { perspectiveQueryLinks } = unwrapApolloResult(await this.#apolloClient.query({
             query: gql`query perspectiveQueryLinks($uuid: String!, $query: LinkQuery!) {
@@ -66,6 +66,11 @@ export default class PerspectiveResolver {
         return 'perspective://Qm12345'
     }
 
+    @Mutation(returns => String)
+    perspectiveSyncWithDevices(@Arg('uuid') uuid: string, @Arg('linkLanguage') linkLanguage: string): string {
+        return 'neighbourhood://Qm12345'
+    }
+
     @Query(returns => [LinkExpression], {nullable: true})
     perspectiveQueryLinks(@Arg('uuid') uuid: string, @Arg('query') query: LinkQuery): LinkExpression[] {
         return [testLink]
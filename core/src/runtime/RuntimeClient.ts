@@ -1,7 +1,7 @@
 import { ApolloClient, gql } from "@apollo/client/core"
 import { Perspective, PerspectiveExpression } from "../perspectives/Perspective"
 import unwrapApolloResult from "../unwrapApolloResult"
-import { RuntimeInfo, ExceptionInfo, SentMessage, NotificationInput, Notification, TriggeredNotification } from "./RuntimeResolver"
+import { RuntimeInfo, ExceptionInfo, SentMessage, NotificationInput, Notification, TriggeredNotification, WebhookDelivery, ScheduledJobInput, ScheduledJob, ScheduledJobRun, Conversation, ConversationMessage, FriendRequest, TrustStatement, TrustConfig, TrustReport } from "./RuntimeResolver"
 
 const PERSPECTIVE_EXPRESSION_FIELDS = `
 author
@@ -17,6 +17,52 @@ data {
 proof { valid, invalid, signature, key }
 `
 
+const TRUST_STATEMENT_FIELDS = `
+issuer
+subject
+timestamp
+key
+signature
+`
+
+const FRIEND_REQUEST_FIELDS = `
+did
+direction
+state
+message
+createdAt
+updatedAt
+`
+
+const CONVERSATION_FIELDS = `
+id
+name
+createdBy
+members
+encrypted
+createdAt
+updatedAt
+unreadCount
+`
+
+const CONVERSATION_MESSAGE_FIELDS = `
+id
+conversationId
+author
+message {
+    links {
+        author
+        timestamp
+        data { source, predicate, target }
+        proof { valid, invalid, signature, key }
+    }
+}
+replyTo
+timestamp
+readAt
+readBy { reader, readAt }
+`
+
 const NOTIFICATION_DEFINITION_FIELDS = `
 description
 appName
@@ -25,7 +71,10 @@ appIconPath
 trigger
 perspectiveIds
 webhookUrl
-webhookAuth
+hasWebhookAuth
+triggerType
+channels { channelType, url, recipient, sender, username, hasSecret, titleTemplate, bodyTemplate }
+throttle { maxDeliveries, windowSeconds, digestIntervalMinutes, quietHoursStart, quietHoursEnd, utcOffsetMinutes }
 `
 
 const NOTIFICATION_FIELDS = `
@@ -34,6 +83,22 @@ granted
 ${NOTIFICATION_DEFINITION_FIELDS}
 `
 
+const SCHEDULED_JOB_FIELDS = `
+id
+perspectiveUuid
+name
+schedule
+jobType
+query
+commands
+taskId
+prompt
+paused
+createdAt
+lastRunAt
+nextRunAt
+`
+
 const TRIGGERED_NOTIFICATION_FIELDS = `
 notification { ${NOTIFICATION_FIELDS} }
 perspectiveId
@@ -44,6 +109,7 @@ export type MessageCallback = (message: PerspectiveExpression) => null
 export type ExceptionCallback = (info: ExceptionInfo) => null
 export type NotificationTriggeredCallback = (notification: TriggeredNotification) => null
 export type NotificationRequestedCallback = (notification: Notification) => null
+export type FriendRequestCallback = (request: FriendRequest) => null
 
 export class RuntimeClient {
     #apolloClient: ApolloClient<any>
@@ -51,15 +117,18 @@ export class RuntimeClient {
     #exceptionOccurredCallbacks: ExceptionCallback[]
     #notificationTriggeredCallbacks: NotificationTriggeredCallback[]
     #notificationRequestedCallbacks: NotificationRequestedCallback[]
+    #friendRequestReceivedCallbacks: FriendRequestCallback[]
 
     constructor(client: ApolloClient<any>, subscribe: boolean = true) {
         this.#apolloClient = client
         this.#messageReceivedCallbacks = []
         this.#exceptionOccurredCallbacks = []
         this.#notificationTriggeredCallbacks = []
+        this.#friendRequestReceivedCallbacks = []
 
         if(subscribe) {
             this.subscribeMessageReceived()
+            this.subscribeFriendRequestReceived()
             this.subscribeExceptionOccurred()
             this.subscribeNotificationTriggered()
         }
@@ -125,6 +194,81 @@ export class RuntimeClient {
         return getTrustedAgents
     }
 
+    async agentTrust(did: string): Promise<TrustReport> {
+        const { runtimeAgentTrust } = unwrapApolloResult(await this.#apolloClient.query({
+            query: gql`query runtimeAgentTrust($did: String!) {
+                runtimeAgentTrust(did: $did) {
+                    did
+                    trusted
+                    depth
+                    endorsers
+                    path
+                    reason
+                }
+            }`,
+            variables: { did }
+        }))
+        return runtimeAgentTrust
+    }
+
+    async trustStatements(issuer?: string): Promise<TrustStatement[]> {
+        const { runtimeTrustStatements } = unwrapApolloResult(await this.#apolloClient.query({
+            query: gql`query runtimeTrustStatements($issuer: String) {
+                runtimeTrustStatements(issuer: $issuer) { ${TRUST_STATEMENT_FIELDS} }
+            }`,
+            variables: { issuer }
+        }))
+        return runtimeTrustStatements
+    }
+
+    async trustConfig(): Promise<TrustConfig> {
+        const { runtimeTrustConfig } = unwrapApolloResult(await this.#apolloClient.query({
+            query: gql`query runtimeTrustConfig {
+                runtimeTrustConfig { maxDepth, threshold }
+            }`,
+        }))
+        return runtimeTrustConfig
+    }
+
+    async trustAgent(did: string): Promise<TrustStatement[]> {
+        const { runtimeTrustAgent } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimeTrustAgent($did: String!) {
+                runtimeTrustAgent(did: $did) { ${TRUST_STATEMENT_FIELDS} }
+            }`,
+            variables: { did }
+        }))
+        return runtimeTrustAgent
+    }
+
+    async revokeTrust(did: string): Promise<TrustStatement[]> {
+        const { runtimeRevokeTrust } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimeRevokeTrust($did: String!) {
+                runtimeRevokeTrust(did: $did) { ${TRUST_STATEMENT_FIELDS} }
+            }`,
+            variables: { did }
+        }))
+        return runtimeRevokeTrust
+    }
+
+    async setTrustConfig(maxDepth: number, threshold: number): Promise<TrustConfig> {
+        const { runtimeSetTrustConfig } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimeSetTrustConfig($maxDepth: Int!, $threshold: Int!) {
+                runtimeSetTrustConfig(maxDepth: $maxDepth, threshold: $threshold) { maxDepth, threshold }
+            }`,
+            variables: { maxDepth, threshold }
+        }))
+        return runtimeSetTrustConfig
+    }
+
+    async refreshTrust(): Promise<number> {
+        const { runtimeRefreshTrust } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimeRefreshTrust {
+                runtimeRefreshTrust
+            }`,
+        }))
+        return runtimeRefreshTrust
+    }
+
     async addKnownLinkLanguageTemplates(addresses: string[]): Promise<string[]> {
         const { runtimeAddKnownLinkLanguageTemplates } = unwrapApolloResult(await this.#apolloClient.mutate({
             mutation: gql`mutation runtimeAddKnownLinkLanguageTemplates($addresses: [String!]!) {
@@ -183,6 +327,46 @@ export class RuntimeClient {
         return runtimeFriends
     }
 
+    async friendRequests(state?: string): Promise<FriendRequest[]> {
+        const { runtimeFriendRequests } = unwrapApolloResult(await this.#apolloClient.query({
+            query: gql`query runtimeFriendRequests($state: String) {
+                runtimeFriendRequests(state: $state) { ${FRIEND_REQUEST_FIELDS} }
+            }`,
+            variables: { state }
+        }))
+        return runtimeFriendRequests
+    }
+
+    async sendFriendRequest(did: string, message?: string): Promise<FriendRequest> {
+        const { runtimeSendFriendRequest } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimeSendFriendRequest($did: String!, $message: String) {
+                runtimeSendFriendRequest(did: $did, message: $message) { ${FRIEND_REQUEST_FIELDS} }
+            }`,
+            variables: { did, message }
+        }))
+        return runtimeSendFriendRequest
+    }
+
+    async acceptFriendRequest(did: string): Promise<FriendRequest> {
+        const { runtimeAcceptFriendRequest } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimeAcceptFriendRequest($did: String!) {
+                runtimeAcceptFriendRequest(did: $did) { ${FRIEND_REQUEST_FIELDS} }
+            }`,
+            variables: { did }
+        }))
+        return runtimeAcceptFriendRequest
+    }
+
+    async rejectFriendRequest(did: string): Promise<FriendRequest> {
+        const { runtimeRejectFriendRequest } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimeRejectFriendRequest($did: String!) {
+                runtimeRejectFriendRequest(did: $did) { ${FRIEND_REQUEST_FIELDS} }
+            }`,
+            variables: { did }
+        }))
+        return runtimeRejectFriendRequest
+    }
+
     async hcAgentInfos(): Promise<String> {
         const { runtimeHcAgentInfos } = unwrapApolloResult(await this.#apolloClient.query({
             query: gql`query runtimeHcAgentInfos {
@@ -232,12 +416,12 @@ export class RuntimeClient {
         return runtimeFriendStatus
     }
 
-    async friendSendMessage(did: string, message: Perspective): Promise<boolean> {
+    async friendSendMessage(did: string, message: Perspective, encrypted?: boolean): Promise<boolean> {
         const { runtimeFriendSendMessage } = unwrapApolloResult(await this.#apolloClient.mutate({
-            mutation: gql`mutation runtimeFriendSendMessage($did: String!, $message: PerspectiveInput!) {
-                runtimeFriendSendMessage(did: $did, message: $message)
+            mutation: gql`mutation runtimeFriendSendMessage($did: String!, $message: PerspectiveInput!, $encrypted: Boolean) {
+                runtimeFriendSendMessage(did: $did, message: $message, encrypted: $encrypted)
             }`,
-            variables: { did,  message }
+            variables: { did,  message, encrypted }
         }))
         return runtimeFriendSendMessage
     }
@@ -256,10 +440,16 @@ export class RuntimeClient {
         const { runtimeMessageOutbox } = unwrapApolloResult(await this.#apolloClient.query({
             query: gql`query runtimeMessageOutbox($filter: String) {
                 runtimeMessageOutbox(filter: $filter) { 
+                    id,
                     recipient,
                     message {
                         ${PERSPECTIVE_EXPRESSION_FIELDS} 
                     }
+                    status,
+                    attempts,
+                    lastError,
+                    nextAttemptAt,
+                    encrypted
                 }
             }`,
             variables: { filter }
@@ -287,6 +477,16 @@ export class RuntimeClient {
         return runtimeGrantNotification
     }
 
+    async rotateNotificationWebhookSecret(id: string): Promise<string> {
+        const { runtimeRotateNotificationWebhookSecret } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimeRotateNotificationWebhookSecret($id: String!) {
+                runtimeRotateNotificationWebhookSecret(id: $id)
+            }`,
+            variables: { id }
+        }))
+        return runtimeRotateNotificationWebhookSecret
+    }
+
     async notifications(): Promise<Notification[]> {
         const { runtimeNotifications } = unwrapApolloResult(await this.#apolloClient.query({
             query: gql`query runtimeNotifications {
@@ -296,6 +496,24 @@ export class RuntimeClient {
         return runtimeNotifications
     }
 
+    async notificationDeliveries(notificationId: string): Promise<WebhookDelivery[]> {
+        const { runtimeNotificationDeliveries } = unwrapApolloResult(await this.#apolloClient.query({
+            query: gql`query runtimeNotificationDeliveries($notificationId: String!) {
+                runtimeNotificationDeliveries(notificationId: $notificationId) {
+                    id
+                    notificationId
+                    url
+                    status
+                    createdAt
+                    nextAttemptAt
+                    attempts { attempt timestamp statusCode error success }
+                }
+            }`,
+            variables: { notificationId }
+        }))
+        return runtimeNotificationDeliveries
+    }
+
     async updateNotification(id: string, notification: NotificationInput): Promise<boolean> {
         const { runtimeUpdateNotification } = unwrapApolloResult(await this.#apolloClient.mutate({
             mutation: gql`mutation runtimeUpdateNotification($id: String!, $notification: NotificationInput!) {
@@ -316,6 +534,143 @@ export class RuntimeClient {
         return runtimeRemoveNotification
     }
 
+    async scheduledJobs(perspectiveUuid?: string): Promise<ScheduledJob[]> {
+        const { runtimeScheduledJobs } = unwrapApolloResult(await this.#apolloClient.query({
+            query: gql`query runtimeScheduledJobs($perspectiveUuid: String) {
+                runtimeScheduledJobs(perspectiveUuid: $perspectiveUuid) { ${SCHEDULED_JOB_FIELDS} }
+            }`,
+            variables: { perspectiveUuid }
+        }))
+        return runtimeScheduledJobs
+    }
+
+    async scheduledJobRuns(jobId: string, limit?: number): Promise<ScheduledJobRun[]> {
+        const { runtimeScheduledJobRuns } = unwrapApolloResult(await this.#apolloClient.query({
+            query: gql`query runtimeScheduledJobRuns($jobId: String!, $limit: Int) {
+                runtimeScheduledJobRuns(jobId: $jobId, limit: $limit) {
+                    jobId
+                    startedAt
+                    finishedAt
+                    success
+                    output
+                    error
+                }
+            }`,
+            variables: { jobId, limit }
+        }))
+        return runtimeScheduledJobRuns
+    }
+
+    async addScheduledJob(job: ScheduledJobInput): Promise<ScheduledJob> {
+        const { runtimeAddScheduledJob } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimeAddScheduledJob($job: ScheduledJobInput!) {
+                runtimeAddScheduledJob(job: $job) { ${SCHEDULED_JOB_FIELDS} }
+            }`,
+            variables: { job }
+        }))
+        return runtimeAddScheduledJob
+    }
+
+    async pauseScheduledJob(id: string): Promise<ScheduledJob> {
+        const { runtimePauseScheduledJob } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimePauseScheduledJob($id: String!) {
+                runtimePauseScheduledJob(id: $id) { ${SCHEDULED_JOB_FIELDS} }
+            }`,
+            variables: { id }
+        }))
+        return runtimePauseScheduledJob
+    }
+
+    async resumeScheduledJob(id: string): Promise<ScheduledJob> {
+        const { runtimeResumeScheduledJob } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimeResumeScheduledJob($id: String!) {
+                runtimeResumeScheduledJob(id: $id) { ${SCHEDULED_JOB_FIELDS} }
+            }`,
+            variables: { id }
+        }))
+        return runtimeResumeScheduledJob
+    }
+
+    async removeScheduledJob(id: string): Promise<boolean> {
+        const { runtimeRemoveScheduledJob } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimeRemoveScheduledJob($id: String!) {
+                runtimeRemoveScheduledJob(id: $id)
+            }`,
+            variables: { id }
+        }))
+        return runtimeRemoveScheduledJob
+    }
+
+    async conversations(): Promise<Conversation[]> {
+        const { runtimeConversations } = unwrapApolloResult(await this.#apolloClient.query({
+            query: gql`query runtimeConversations {
+                runtimeConversations { ${CONVERSATION_FIELDS} }
+            }`
+        }))
+        return runtimeConversations
+    }
+
+    /** Pages backwards through a conversation, pass the id of the oldest message seen so far as `before` */
+    async conversationMessages(conversationId: string, before?: string, limit?: number): Promise<ConversationMessage[]> {
+        const { runtimeConversationMessages } = unwrapApolloResult(await this.#apolloClient.query({
+            query: gql`query runtimeConversationMessages($conversationId: String!, $before: String, $limit: Int) {
+                runtimeConversationMessages(conversationId: $conversationId, before: $before, limit: $limit) { ${CONVERSATION_MESSAGE_FIELDS} }
+            }`,
+            variables: { conversationId, before, limit }
+        }))
+        return runtimeConversationMessages
+    }
+
+    async messageThread(messageId: string): Promise<ConversationMessage[]> {
+        const { runtimeMessageThread } = unwrapApolloResult(await this.#apolloClient.query({
+            query: gql`query runtimeMessageThread($messageId: String!) {
+                runtimeMessageThread(messageId: $messageId) { ${CONVERSATION_MESSAGE_FIELDS} }
+            }`,
+            variables: { messageId }
+        }))
+        return runtimeMessageThread
+    }
+
+    async createConversation(name: string, members: string[], encrypted?: boolean): Promise<Conversation> {
+        const { runtimeCreateConversation } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimeCreateConversation($name: String!, $members: [String!]!, $encrypted: Boolean) {
+                runtimeCreateConversation(name: $name, members: $members, encrypted: $encrypted) { ${CONVERSATION_FIELDS} }
+            }`,
+            variables: { name, members, encrypted }
+        }))
+        return runtimeCreateConversation
+    }
+
+    async updateConversationMembers(conversationId: string, add?: string[], remove?: string[]): Promise<Conversation> {
+        const { runtimeUpdateConversationMembers } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimeUpdateConversationMembers($conversationId: String!, $add: [String!], $remove: [String!]) {
+                runtimeUpdateConversationMembers(conversationId: $conversationId, add: $add, remove: $remove) { ${CONVERSATION_FIELDS} }
+            }`,
+            variables: { conversationId, add, remove }
+        }))
+        return runtimeUpdateConversationMembers
+    }
+
+    async sendConversationMessage(conversationId: string, message: Perspective, replyTo?: string): Promise<ConversationMessage> {
+        const { runtimeSendConversationMessage } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimeSendConversationMessage($conversationId: String!, $message: PerspectiveInput!, $replyTo: String) {
+                runtimeSendConversationMessage(conversationId: $conversationId, message: $message, replyTo: $replyTo) { ${CONVERSATION_MESSAGE_FIELDS} }
+            }`,
+            variables: { conversationId, message, replyTo }
+        }))
+        return runtimeSendConversationMessage
+    }
+
+    async markConversationRead(conversationId: string): Promise<number> {
+        const { runtimeMarkConversationRead } = unwrapApolloResult(await this.#apolloClient.mutate({
+            mutation: gql`mutation runtimeMarkConversationRead($conversationId: String!) {
+                runtimeMarkConversationRead(conversationId: $conversationId)
+            }`,
+            variables: { conversationId }
+        }))
+        return runtimeMarkConversationRead
+    }
+
 
     addNotificationTriggeredCallback(cb: NotificationTriggeredCallback) {
         this.#notificationTriggeredCallbacks.push(cb)
@@ -355,6 +710,25 @@ export class RuntimeClient {
         })
     }
 
+    addFriendRequestCallback(cb: FriendRequestCallback) {
+        this.#friendRequestReceivedCallbacks.push(cb)
+    }
+
+    subscribeFriendRequestReceived() {
+        this.#apolloClient.subscribe({
+            query: gql` subscription {
+                runtimeFriendRequestReceived { ${FRIEND_REQUEST_FIELDS} }
+            }
+        `}).subscribe({
+            next: result => {
+                this.#friendRequestReceivedCallbacks.forEach(cb => {
+                    cb(result.data.runtimeFriendRequestReceived)
+                })
+            },
+            error: (e) => console.error(e)
+        })
+    }
+
     addExceptionCallback(cb: ExceptionCallback) {
         this.#exceptionOccurredCallbacks.push(cb)
     }
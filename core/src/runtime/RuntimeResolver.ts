@@ -3,7 +3,7 @@ import { Perspective, PerspectiveExpression, PerspectiveInput } from "../perspec
 import { ExpressionProof } from "../expression/Expression";
 import { LinkExpression } from "../links/Links";
 import { ExceptionType } from "../Exception";
-import { RUNTIME_MESSAGED_RECEIVED_TOPIC, EXCEPTION_OCCURRED_TOPIC, RUNTIME_NOTIFICATION_REQUESTED_TOPIC, RUNTIME_NOTIFICATION_TRIGGERED_TOPIC } from '../PubSub';
+import { RUNTIME_MESSAGED_RECEIVED_TOPIC, EXCEPTION_OCCURRED_TOPIC, RUNTIME_NOTIFICATION_REQUESTED_TOPIC, RUNTIME_NOTIFICATION_TRIGGERED_TOPIC, RUNTIME_FRIEND_REQUEST_RECEIVED_TOPIC } from '../PubSub';
 
 const testLink = new LinkExpression()
 testLink.author = "did:ad4m:test"
@@ -26,10 +26,26 @@ testPerspectiveExpression.data = new Perspective([testLink])
 
 @ObjectType()
 export class SentMessage {
+    @Field()
+    id: string;
     @Field()
     recipient: string;
+    // Until the message is delivered this is the unsigned message as it was queued
     @Field()
     message: PerspectiveExpression;
+    // "queued", "sentP2P", "storedInInbox" or "failed".
+    // Queued messages are retried until the recipient can be reached.
+    @Field()
+    status: string;
+    @Field(type => Int)
+    attempts: number;
+    @Field({ nullable: true })
+    lastError?: string;
+    @Field({ nullable: true })
+    nextAttemptAt?: string;
+    // Sent end-to-end encrypted to the recipient's published encryption key
+    @Field()
+    encrypted: boolean;
 }
 
 @ObjectType()
@@ -54,6 +70,92 @@ export class ExceptionInfo {
     addon?: string;
 }
 
+// A delivery channel for triggered notifications, used fields depend on channelType:
+// - "email": url is the SMTP server (smtp:// or smtps://), recipient the address
+//   to send to, sender the from address, username/secret optional credentials
+// - "ntfy": url is the topic URL, secret an optional access token
+// - "matrix": url is the homeserver, recipient the room ID, secret the access token
+// Templates can use {{description}}, {{appName}}, {{perspectiveId}}, {{match}}
+// and the variables bound by the trigger match, e.g. {{Message}}.
+// The body is rendered once per match.
+@InputType()
+export class NotificationChannelInput {
+    @Field()
+    channelType: string;
+    @Field()
+    url: string;
+    @Field({ nullable: true })
+    recipient?: string;
+    @Field({ nullable: true })
+    sender?: string;
+    @Field({ nullable: true })
+    username?: string;
+    @Field({ nullable: true })
+    secret?: string;
+    @Field({ nullable: true })
+    titleTemplate?: string;
+    @Field({ nullable: true })
+    bodyTemplate?: string;
+}
+
+@ObjectType()
+export class NotificationChannel {
+    @Field()
+    channelType: string;
+    @Field()
+    url: string;
+    @Field({ nullable: true })
+    recipient?: string;
+    @Field({ nullable: true })
+    sender?: string;
+    @Field({ nullable: true })
+    username?: string;
+    // The secret itself is write-only
+    @Field()
+    hasSecret: boolean;
+    @Field({ nullable: true })
+    titleTemplate?: string;
+    @Field({ nullable: true })
+    bodyTemplate?: string;
+}
+
+// Limits how often a notification gets delivered on each perspective. Matches that
+// can't be delivered right away are queued and sent later as one digest per perspective.
+// - maxDeliveries per windowSeconds
+// - digestIntervalMinutes: always batch matches, at most one digest per interval
+// - quietHoursStart/quietHoursEnd as "HH:MM", in UTC shifted by utcOffsetMinutes
+@InputType()
+export class NotificationThrottleInput {
+    @Field(type => Int, { nullable: true })
+    maxDeliveries?: number;
+    @Field(type => Int, { nullable: true })
+    windowSeconds?: number;
+    @Field(type => Int, { nullable: true })
+    digestIntervalMinutes?: number;
+    @Field({ nullable: true })
+    quietHoursStart?: string;
+    @Field({ nullable: true })
+    quietHoursEnd?: string;
+    @Field(type => Int, { nullable: true })
+    utcOffsetMinutes?: number;
+}
+
+@ObjectType()
+export class NotificationThrottle {
+    @Field(type => Int, { nullable: true })
+    maxDeliveries?: number;
+    @Field(type => Int, { nullable: true })
+    windowSeconds?: number;
+    @Field(type => Int, { nullable: true })
+    digestIntervalMinutes?: number;
+    @Field({ nullable: true })
+    quietHoursStart?: string;
+    @Field({ nullable: true })
+    quietHoursEnd?: string;
+    @Field(type => Int, { nullable: true })
+    utcOffsetMinutes?: number;
+}
+
 // This class defines a Notification and is what an app provides
 // when registering and installing a notification.
 @InputType()
@@ -83,8 +185,28 @@ export class NotificationInput {
     webhookUrl: string;
 
     // Authentication bearer token to be sent via POST to the webhookUrl.
+    // Left empty on updates, the current one is kept.
     @Field()
     webhookAuth: string;
+
+    // What the trigger is checked against, defaults to "prolog":
+    // - "prolog": trigger is a Prolog query run on perspective changes
+    // - "telepresenceSignal": trigger is a link predicate received signals
+    //   need to contain, or empty/"*" for any signal
+    // - "directMessage": trigger is the author DID of direct messages,
+    //   or empty/"*" for any message
+    // - "neighbourhoodJoined": trigger is a neighbourhood URL,
+    //   or "*" for any joined neighbourhood
+    // perspectiveIds may contain "*" to be active on all perspectives.
+    @Field({ nullable: true })
+    triggerType?: string;
+
+    // Channels triggered notifications get delivered to, once granted
+    @Field(type => [NotificationChannelInput], { nullable: true })
+    channels?: NotificationChannelInput[];
+
+    @Field(type => NotificationThrottleInput, { nullable: true })
+    throttle?: NotificationThrottleInput;
 }
 
 // This is a notification as it is stored in the runtime.
@@ -119,9 +241,55 @@ export class Notification {
     @Field()
     webhookUrl: string;
 
-    // Authentication bearer token to be sent via POST to the webhookUrl.
+    // Whether a bearer token is sent along to the webhookUrl.
+    // The token itself is write-only and can't be read back.
     @Field()
-    webhookAuth: string;
+    hasWebhookAuth: boolean;
+
+    // "prolog", "telepresenceSignal", "directMessage" or "neighbourhoodJoined"
+    @Field()
+    triggerType: string;
+
+    @Field(type => [NotificationChannel])
+    channels: NotificationChannel[];
+
+    @Field(type => NotificationThrottle, { nullable: true })
+    throttle?: NotificationThrottle;
+}
+
+@ObjectType()
+export class WebhookDeliveryAttempt {
+    @Field(type => Int)
+    attempt: number;
+    @Field()
+    timestamp: string;
+    @Field(type => Int, { nullable: true })
+    statusCode?: number;
+    @Field({ nullable: true })
+    error?: string;
+    @Field()
+    success: boolean;
+}
+
+// Delivery of a triggered notification to its webhook,
+// retried with exponential backoff until it succeeds or is given up.
+@ObjectType()
+export class WebhookDelivery {
+    @Field()
+    id: string;
+    @Field()
+    notificationId: string;
+    @Field()
+    url: string;
+    // "pending", "delivered" or "failed"
+    @Field()
+    status: string;
+    @Field()
+    createdAt: string;
+    @Field({ nullable: true })
+    nextAttemptAt?: string;
+    @Field(type => [WebhookDeliveryAttempt])
+    attempts: WebhookDeliveryAttempt[];
 }
 
 // This is what is sent to the launcher and the webhook.
@@ -129,15 +297,239 @@ export class Notification {
 export class TriggeredNotification {
     @Field()
     notification: Notification;
+    // Empty for triggers not tied to a perspective, like direct messages.
     @Field()
     perspectiveId: string;
 
-    // This is the Prolog query match that triggered the notification.
-    // It is a list of all variable bindings that match the notification's trigger.
+    // For Prolog triggers this is the list of all variable bindings
+    // that match the notification's trigger.
+    // Other trigger types send a JSON object with what triggered the notification:
+    // { signal } for telepresence signals, { message } for direct messages,
+    // { neighbourhoodUrl, perspective } for joined neighbourhoods.
     @Field()
     triggerMatch: string;
 }
 
+// A job the executor runs on a cron schedule in the context of a perspective.
+@InputType()
+export class ScheduledJobInput {
+    @Field()
+    perspectiveUuid: string;
+    @Field()
+    name: string;
+    // Cron expression with 5 (minute precision) or 6 (seconds) fields, in UTC
+    @Field()
+    schedule: string;
+    // "prolog": runs `query` and executes `commands` once per match,
+    // with the match's bindings as parameters.
+    // "aiTask": sends `prompt` to the AI task `taskId`.
+    @Field()
+    jobType: string;
+    @Field({ nullable: true })
+    query?: string;
+    // JSON encoded list of commands, like in perspectiveExecuteCommands
+    @Field({ nullable: true })
+    commands?: string;
+    @Field({ nullable: true })
+    taskId?: string;
+    @Field({ nullable: true })
+    prompt?: string;
+}
+
+@ObjectType()
+export class ScheduledJob {
+    @Field()
+    id: string;
+    @Field()
+    perspectiveUuid: string;
+    @Field()
+    name: string;
+    @Field()
+    schedule: string;
+    @Field()
+    jobType: string;
+    @Field({ nullable: true })
+    query?: string;
+    @Field({ nullable: true })
+    commands?: string;
+    @Field({ nullable: true })
+    taskId?: string;
+    @Field({ nullable: true })
+    prompt?: string;
+    @Field()
+    paused: boolean;
+    @Field()
+    createdAt: string;
+    @Field({ nullable: true })
+    lastRunAt?: string;
+    @Field({ nullable: true })
+    nextRunAt?: string;
+}
+
+@ObjectType()
+export class ScheduledJobRun {
+    @Field()
+    jobId: string;
+    @Field()
+    startedAt: string;
+    @Field()
+    finishedAt: string;
+    @Field()
+    success: boolean;
+    @Field({ nullable: true })
+    output?: string;
+    @Field({ nullable: true })
+    error?: string;
+}
+
+@ObjectType()
+export class Conversation {
+    @Field()
+    id: string;
+    @Field()
+    name: string;
+    // Only the creator can change the members
+    @Field()
+    createdBy: string;
+    @Field(type => [String])
+    members: string[];
+    // All messages of the conversation are end-to-end encrypted
+    @Field()
+    encrypted: boolean;
+    @Field()
+    createdAt: string;
+    @Field()
+    updatedAt: string;
+    @Field(type => Int)
+    unreadCount: number;
+}
+
+@ObjectType()
+export class TrustStatement {
+    @Field()
+    issuer: string;
+    @Field()
+    subject: string;
+    @Field()
+    timestamp: string;
+    @Field()
+    key: string;
+    @Field()
+    signature: string;
+}
+
+@ObjectType()
+export class TrustConfig {
+    // 0 only trusts the trusted agents list
+    @Field(type => Int)
+    maxDepth: number;
+    // How many trusted agents have to vouch for an agent
+    @Field(type => Int)
+    threshold: number;
+}
+
+@ObjectType()
+export class TrustReport {
+    @Field()
+    did: string;
+    @Field()
+    trusted: boolean;
+    @Field(type => Int, { nullable: true })
+    depth?: number;
+    @Field(type => [String])
+    endorsers: string[];
+    @Field(type => [String])
+    path: string[];
+    @Field()
+    reason: string;
+}
+
+@ObjectType()
+export class FriendRequest {
+    @Field()
+    did: string;
+    // "incoming" or "outgoing"
+    @Field()
+    direction: string;
+    // "pending", "accepted" or "rejected"
+    @Field()
+    state: string;
+    @Field({ nullable: true })
+    message?: string;
+    @Field()
+    createdAt: string;
+    @Field()
+    updatedAt: string;
+}
+
+@ObjectType()
+export class MessageReadReceipt {
+    @Field()
+    reader: string;
+    @Field()
+    readAt: string;
+}
+
+@ObjectType()
+export class ConversationMessage {
+    @Field()
+    id: string;
+    @Field()
+    conversationId: string;
+    @Field()
+    author: string;
+    @Field()
+    message: Perspective;
+    // The message this one replies to, replies form threads
+    @Field({ nullable: true })
+    replyTo?: string;
+    @Field()
+    timestamp: string;
+    // When we read it, our own messages count as read when sent
+    @Field({ nullable: true })
+    readAt?: string;
+    @Field(type => [MessageReadReceipt])
+    readBy: MessageReadReceipt[];
+}
+
+const testConversation: Conversation = {
+    id: "test-conversation-id",
+    name: "Book club",
+    createdBy: "did:ad4m:test",
+    members: ["did:ad4m:test", "did:ad4m:friend"],
+    encrypted: false,
+    createdAt: "2024-01-01T00:00:00+00:00",
+    updatedAt: "2024-01-01T00:00:00+00:00",
+    unreadCount: 0,
+}
+
+const testConversationMessage: ConversationMessage = {
+    id: "test-message-id",
+    conversationId: "test-conversation-id",
+    author: "did:ad4m:test",
+    message: new Perspective([testLink]),
+    timestamp: "2024-01-01T00:00:00+00:00",
+    readAt: "2024-01-01T00:00:00+00:00",
+    readBy: [{ reader: "did:ad4m:friend", readAt: "2024-01-01T00:01:00+00:00" }],
+}
+
+const testTrustStatement: TrustStatement = {
+    issuer: "did:ad4m:test",
+    subject: "did:ad4m:friend",
+    timestamp: "2024-01-01T00:00:00.000Z",
+    key: "did:ad4m:test#key",
+    signature: "test-signature",
+}
+
+const testFriendRequest: FriendRequest = {
+    did: "did:ad4m:friend",
+    direction: "incoming",
+    state: "pending",
+    message: "Hi, it's me",
+    createdAt: "2024-01-01T00:00:00+00:00",
+    updatedAt: "2024-01-01T00:00:00+00:00",
+}
+
 /**
  * Resolver classes are used here to define the GraphQL schema 
  * (through the type-graphql annotations)
@@ -180,6 +572,51 @@ export default class RuntimeResolver {
         return ["agentPubKey"]
     }
 
+    @Query(returns => TrustReport)
+    runtimeAgentTrust(@Arg("did", type => String) did: string): TrustReport {
+        return {
+            did,
+            trusted: true,
+            depth: 1,
+            endorsers: ["did:ad4m:test"],
+            path: ["did:ad4m:test", did],
+            reason: `${did} is vouched for by did:ad4m:test (1 of 1 required), 1 statement(s) away from the trusted agents list`,
+        }
+    }
+
+    @Query(returns => [TrustStatement])
+    runtimeTrustStatements(@Arg("issuer", type => String, { nullable: true }) issuer?: string): TrustStatement[] {
+        return [testTrustStatement]
+    }
+
+    @Query(returns => TrustConfig)
+    runtimeTrustConfig(): TrustConfig {
+        return { maxDepth: 0, threshold: 1 }
+    }
+
+    @Mutation(returns => [TrustStatement])
+    runtimeTrustAgent(@Arg("did", type => String) did: string): TrustStatement[] {
+        return [{ ...testTrustStatement, subject: did }]
+    }
+
+    @Mutation(returns => [TrustStatement])
+    runtimeRevokeTrust(@Arg("did", type => String) did: string): TrustStatement[] {
+        return []
+    }
+
+    @Mutation(returns => TrustConfig)
+    runtimeSetTrustConfig(
+        @Arg("maxDepth", type => Int) maxDepth: number,
+        @Arg("threshold", type => Int) threshold: number
+    ): TrustConfig {
+        return { maxDepth, threshold }
+    }
+
+    @Mutation(returns => Int)
+    runtimeRefreshTrust(): number {
+        return 1
+    }
+
     @Query(returns => [String])
     runtimeKnownLinkLanguageTemplates(): string[] {
         return ["Qm12345abcdef"]
@@ -210,6 +647,34 @@ export default class RuntimeResolver {
         return []
     }
 
+    @Query(returns => [FriendRequest])
+    runtimeFriendRequests(@Arg("state", type => String, { nullable: true }) state?: string): FriendRequest[] {
+        return [testFriendRequest]
+    }
+
+    @Mutation(returns => FriendRequest)
+    runtimeSendFriendRequest(
+        @Arg("did", type => String) did: string,
+        @Arg("message", type => String, { nullable: true }) message?: string
+    ): FriendRequest {
+        return { ...testFriendRequest, did, direction: "outgoing", message }
+    }
+
+    @Mutation(returns => FriendRequest)
+    runtimeAcceptFriendRequest(@Arg("did", type => String) did: string): FriendRequest {
+        return { ...testFriendRequest, did, state: "accepted" }
+    }
+
+    @Mutation(returns => FriendRequest)
+    runtimeRejectFriendRequest(@Arg("did", type => String) did: string): FriendRequest {
+        return { ...testFriendRequest, did, state: "rejected" }
+    }
+
+    @Subscription({topics: RUNTIME_FRIEND_REQUEST_RECEIVED_TOPIC, nullable: true})
+    runtimeFriendRequestReceived(): FriendRequest {
+        return testFriendRequest
+    }
+
     @Query()
     runtimeHcAgentInfos(): String {
         return JSON.stringify([{"agent":{"type":"Buffer","data":[9,191,231,58,255,107,202,55,206,57,9,103,17,34,206,195,207,114,5,73,77,198,56,136,17,227,242,231,194,135,128,48,170,189,119,186]},"signature":{"type":"Buffer","data":[195,143,170,36,234,123,255,85,188,138,124,2,91,18,53,231,44,41,240,8,80,131,100,150,165,125,146,90,17,200,190,129,114,211,11,146,150,128,198,199,79,118,157,101,194,68,58,245,98,182,79,139,140,41,143,129,8,136,87,77,180,231,218,11]},"agent_info":{"type":"Buffer","data":[134,165,115,112,97,99,101,196,36,203,165,212,173,24,215,165,196,25,145,248,113,246,139,205,182,241,138,57,0,26,8,217,179,23,126,59,136,128,213,110,225,173,72,197,95,165,97,103,101,110,116,196,36,9,191,231,58,255,107,202,55,206,57,9,103,17,34,206,195,207,114,5,73,77,198,56,136,17,227,242,231,194,135,128,48,170,189,119,186,164,117,114,108,115,145,217,99,107,105,116,115,117,110,101,45,112,114,111,120,121,58,47,47,101,83,52,86,112,54,109,118,80,48,122,116,85,101,104,114,117,113,89,52,102,66,53,101,69,98,121,48,69,66,100,89,84,50,95,67,48,69,112,77,111,76,111,47,107,105,116,115,117,110,101,45,113,117,105,99,47,104,47,49,57,50,46,49,54,56,46,49,55,56,46,54,48,47,112,47,55,55,52,52,47,45,45,172,115,105,103,110,101,100,95,97,116,95,109,115,207,0,0,1,123,233,104,189,50,176,101,120,112,105,114,101,115,95,97,102,116,101,114,95,109,115,206,0,18,79,128,169,109,101,116,97,95,105,110,102,111,196,34,129,187,100,104,116,95,115,116,111,114,97,103,101,95,97,114,99,95,104,97,108,102,95,108,101,110,103,116,104,206,128,0,0,1]}},{"agent":{"type":"Buffer","data":[98,187,145,48,115,209,94,143,31,153,102,69,138,29,133,213,34,52,39,164,157,139,178,111,23,33,118,250,28,155,78,246,128,49,179,38]},"signature":{"type":"Buffer","data":[233,192,38,3,59,248,124,231,57,255,40,154,50,60,119,252,68,198,154,109,175,155,106,217,211,155,109,223,249,91,221,210,17,132,72,230,11,230,247,119,72,244,145,91,75,7,67,5,130,151,44,231,52,220,28,154,212,82,58,90,203,211,236,4]},"agent_info":{"type":"Buffer","data":[134,165,115,112,97,99,101,196,36,203,165,212,173,24,215,165,196,25,145,248,113,246,139,205,182,241,138,57,0,26,8,217,179,23,126,59,136,128,213,110,225,173,72,197,95,165,97,103,101,110,116,196,36,98,187,145,48,115,209,94,143,31,153,102,69,138,29,133,213,34,52,39,164,157,139,178,111,23,33,118,250,28,155,78,246,128,49,179,38,164,117,114,108,115,145,217,99,107,105,116,115,117,110,101,45,112,114,111,120,121,58,47,47,55,101,77,106,48,73,83,72,56,56,81,56,45,89,51,74,89,70,53,76,72,57,83,54,68,119,71,73,49,88,45,115,87,53,68,104,66,87,121,71,119,78,115,47,107,105,116,115,117,110,101,45,113,117,105,99,47,104,47,49,57,50,46,49,54,56,46,49,55,56,46,54,48,47,112,47,55,55,52,52,47,45,45,172,115,105,103,110,101,100,95,97,116,95,109,115,207,0,0,1,123,233,103,45,197,176,101,120,112,105,114,101,115,95,97,102,116,101,114,95,109,115,206,0,18,79,128,169,109,101,116,97,95,105,110,102,111,196,34,129,187,100,104,116,95,115,116,111,114,97,103,101,95,97,114,99,95,104,97,108,102,95,108,101,110,103,116,104,206,128,0,0,1]}},{"agent":{"type":"Buffer","data":[181,101,115,45,214,127,198,153,159,184,30,87,67,224,208,184,203,176,130,158,236,127,153,125,243,183,188,167,154,25,118,254,101,145,210,109]},"signature":{"type":"Buffer","data":[88,189,229,122,44,171,194,156,90,79,148,49,207,224,34,199,219,88,24,243,103,127,123,41,87,171,127,92,6,216,198,171,26,226,237,217,122,78,98,146,55,255,68,240,202,83,58,140,147,185,3,66,15,216,210,22,99,197,73,234,120,17,74,1]},"agent_info":{"type":"Buffer","data":[134,165,115,112,97,99,101,196,36,203,165,212,173,24,215,165,196,25,145,248,113,246,139,205,182,241,138,57,0,26,8,217,179,23,126,59,136,128,213,110,225,173,72,197,95,165,97,103,101,110,116,196,36,181,101,115,45,214,127,198,153,159,184,30,87,67,224,208,184,203,176,130,158,236,127,153,125,243,183,188,167,154,25,118,254,101,145,210,109,164,117,114,108,115,145,217,99,107,105,116,115,117,110,101,45,112,114,111,120,121,58,47,47,114,70,71,83,100,113,104,101,68,107,70,70,56,69,102,109,45,69,116,55,119,81,101,120,83,88,55,65,112,69,51,89,86,99,45,120,102,52,104,106,77,115,77,47,107,105,116,115,117,110,101,45,113,117,105,99,47,104,47,49,57,50,46,49,54,56,46,49,55,56,46,54,48,47,112,47,55,55,52,52,47,45,45,172,115,105,103,110,101,100,95,97,116,95,109,115,207,0,0,1,123,233,113,23,220,176,101,120,112,105,114,101,115,95,97,102,116,101,114,95,109,115,206,0,18,79,128,169,109,101,116,97,95,105,110,102,111,196,34,129,187,100,104,116,95,115,116,111,114,97,103,101,95,97,114,99,95,104,97,108,102,95,108,101,110,103,116,104,206,128,0,0,1]}},{"agent":{"type":"Buffer","data":[3,171,237,107,186,245,165,47,237,235,211,49,245,62,113,53,255,252,223,226,75,118,148,187,23,53,70,174,160,184,64,63,94,210,227,56]},"signature":{"type":"Buffer","data":[129,12,55,104,239,121,138,2,86,106,136,51,219,79,170,8,195,69,81,188,225,192,247,196,54,39,164,110,75,39,240,56,245,189,154,77,72,11,97,250,202,149,242,97,128,28,47,164,236,104,136,82,212,246,44,143,132,119,255,135,112,11,20,13]},"agent_info":{"type":"Buffer","data":[134,165,115,112,97,99,101,196,36,203,165,212,173,24,215,165,196,25,145,248,113,246,139,205,182,241,138,57,0,26,8,217,179,23,126,59,136,128,213,110,225,173,72,197,95,165,97,103,101,110,116,196,36,3,171,237,107,186,245,165,47,237,235,211,49,245,62,113,53,255,252,223,226,75,118,148,187,23,53,70,174,160,184,64,63,94,210,227,56,164,117,114,108,115,145,217,97,107,105,116,115,117,110,101,45,112,114,111,120,121,58,47,47,104,121,112,115,86,121,103,117,80,84,115,53,118,73,80,65,102,97,112,90,88,113,117,84,115,79,80,100,67,81,79,79,117,103,57,51,82,103,106,95,114,85,73,47,107,105,116,115,117,110,101,45,113,117,105,99,47,104,47,49,54,53,46,50,50,46,51,50,46,49,49,47,112,47,53,55,55,57,47,45,45,172,115,105,103,110,101,100,95,97,116,95,109,115,207,0,0,1,123,233,113,188,123,176,101,120,112,105,114,101,115,95,97,102,116,101,114,95,109,115,206,0,18,79,128,169,109,101,116,97,95,105,110,102,111,196,34,129,187,100,104,116,95,115,116,111,114,97,103,101,95,97,114,99,95,104,97,108,102,95,108,101,110,103,116,104,206,128,0,0,1]}}])
@@ -242,7 +707,8 @@ export default class RuntimeResolver {
     @Mutation()
     runtimeFriendSendMessage(
         @Arg("did", type => String) did: string, 
-        @Arg("message", type => PerspectiveInput) message: PerspectiveInput
+        @Arg("message", type => PerspectiveInput) message: PerspectiveInput,
+        @Arg("encrypted", type => Boolean, { nullable: true }) encrypted?: boolean
     ): boolean {
         return true
     }
@@ -255,8 +721,11 @@ export default class RuntimeResolver {
     @Query(returns => [SentMessage])
     runtimeMessageOutbox(@Arg("filter", type => String, {nullable: true }) filter?: string): SentMessage[] {
         return [{
+            id: "1",
             recipient: "did:test:recipient", 
-            message: testPerspectiveExpression
+            message: testPerspectiveExpression,
+            status: "sentP2P",
+            attempts: 1,
         } as SentMessage]
     }
 
@@ -282,6 +751,23 @@ export default class RuntimeResolver {
         return "new-notification-id"        
     }
 
+    @Query(returns => [WebhookDelivery])
+    runtimeNotificationDeliveries(@Arg("notificationId") notificationId: string): WebhookDelivery[] {
+        return [{
+            id: "test-delivery-id",
+            notificationId,
+            url: "https://example.com/webhook",
+            status: "delivered",
+            createdAt: "2024-01-01T00:00:00+00:00",
+            attempts: [{
+                attempt: 1,
+                timestamp: "2024-01-01T00:00:00+00:00",
+                statusCode: 200,
+                success: true,
+            }],
+        }]
+    }
+
     @Query(returns => [Notification])
     runtimeNotifications(): Notification[] {
         return [{
@@ -294,8 +780,9 @@ export default class RuntimeResolver {
             trigger: "triple(X, ad4m://has_type, flux://message)",
             perspectiveIds: ["u983ud-jdhh38d"],
             webhookUrl: "https://example.com/webhook",
-            webhookAuth: "test-auth",
-        
+            hasWebhookAuth: true,
+            triggerType: "prolog",
+            channels: [],
         }]
     }
 
@@ -317,6 +804,13 @@ export default class RuntimeResolver {
         return true
     }
 
+    // The signing secret is only handed out here, to the app that installed the notification.
+    // Key of the HMAC-SHA256 in the X-Ad4m-Signature header of webhook deliveries.
+    @Mutation(returns => String)
+    runtimeRotateNotificationWebhookSecret(@Arg("id", type => String) id: string): string {
+        return "test-signing-secret"
+    }
+
     @Subscription({topics: RUNTIME_NOTIFICATION_TRIGGERED_TOPIC, nullable: true})
     runtimeNotificationTriggered(): TriggeredNotification {
         return {
@@ -332,9 +826,136 @@ export default class RuntimeResolver {
                 trigger: "triple(X, ad4m://has_type, flux://message)",
                 perspectiveIds: ["u983ud-jdhh38d"],
                 webhookUrl: "https://example.com/webhook",
-                webhookAuth: "test-auth",
+                hasWebhookAuth: true,
+                triggerType: "prolog",
+                channels: [],
             }
         }
     }
-}
 
+    @Query(returns => [ScheduledJob])
+    runtimeScheduledJobs(@Arg("perspectiveUuid", type => String, { nullable: true }) perspectiveUuid?: string): ScheduledJob[] {
+        return [{
+            id: "test-job-id",
+            perspectiveUuid: "test-perspective-id",
+            name: "Expire posts",
+            schedule: "0 3 * * *",
+            jobType: "prolog",
+            query: "triple(Base, \"todo://state\", \"todo://done\")",
+            paused: false,
+            createdAt: "2024-01-01T00:00:00+00:00",
+            nextRunAt: "2024-01-02T03:00:00+00:00",
+        }]
+    }
+
+    @Query(returns => [ScheduledJobRun])
+    runtimeScheduledJobRuns(
+        @Arg("jobId", type => String) jobId: string,
+        @Arg("limit", type => Int, { nullable: true }) limit?: number
+    ): ScheduledJobRun[] {
+        return [{
+            jobId,
+            startedAt: "2024-01-02T03:00:00+00:00",
+            finishedAt: "2024-01-02T03:00:01+00:00",
+            success: true,
+            output: "[]",
+        }]
+    }
+
+    @Mutation(returns => ScheduledJob)
+    runtimeAddScheduledJob(@Arg("job", type => ScheduledJobInput) job: ScheduledJobInput): ScheduledJob {
+        return {
+            id: "test-job-id",
+            ...job,
+            paused: false,
+            createdAt: "2024-01-01T00:00:00+00:00",
+            nextRunAt: "2024-01-02T03:00:00+00:00",
+        }
+    }
+
+    @Mutation(returns => ScheduledJob)
+    runtimePauseScheduledJob(@Arg("id", type => String) id: string): ScheduledJob {
+        return {
+            id: "test-job-id",
+            perspectiveUuid: "test-perspective-id",
+            name: "Expire posts",
+            schedule: "0 3 * * *",
+            jobType: "prolog",
+            query: "triple(Base, \"todo://state\", \"todo://done\")",
+            paused: true,
+            createdAt: "2024-01-01T00:00:00+00:00",
+            nextRunAt: "2024-01-02T03:00:00+00:00",
+        }
+    }
+
+    @Mutation(returns => ScheduledJob)
+    runtimeResumeScheduledJob(@Arg("id", type => String) id: string): ScheduledJob {
+        return {
+            id: "test-job-id",
+            perspectiveUuid: "test-perspective-id",
+            name: "Expire posts",
+            schedule: "0 3 * * *",
+            jobType: "prolog",
+            query: "triple(Base, \"todo://state\", \"todo://done\")",
+            paused: false,
+            createdAt: "2024-01-01T00:00:00+00:00",
+            nextRunAt: "2024-01-02T03:00:00+00:00",
+        }
+    }
+
+    @Mutation()
+    runtimeRemoveScheduledJob(@Arg("id", type => String) id: string): boolean {
+        return true
+    }
+
+    @Query(returns => [Conversation])
+    runtimeConversations(): Conversation[] {
+        return [testConversation]
+    }
+
+    @Query(returns => [ConversationMessage])
+    runtimeConversationMessages(
+        @Arg("conversationId", type => String) conversationId: string,
+        @Arg("before", type => String, { nullable: true }) before?: string,
+        @Arg("limit", type => Int, { nullable: true }) limit?: number
+    ): ConversationMessage[] {
+        return [testConversationMessage]
+    }
+
+    @Query(returns => [ConversationMessage])
+    runtimeMessageThread(@Arg("messageId", type => String) messageId: string): ConversationMessage[] {
+        return [testConversationMessage]
+    }
+
+    @Mutation(returns => Conversation)
+    runtimeCreateConversation(
+        @Arg("name", type => String) name: string,
+        @Arg("members", type => [String]) members: string[],
+        @Arg("encrypted", type => Boolean, { nullable: true }) encrypted?: boolean
+    ): Conversation {
+        return { ...testConversation, name, members, encrypted: !!encrypted }
+    }
+
+    @Mutation(returns => Conversation)
+    runtimeUpdateConversationMembers(
+        @Arg("conversationId", type => String) conversationId: string,
+        @Arg("add", type => [String], { nullable: true }) add?: string[],
+        @Arg("remove", type => [String], { nullable: true }) remove?: string[]
+    ): Conversation {
+        return testConversation
+    }
+
+    @Mutation(returns => ConversationMessage)
+    runtimeSendConversationMessage(
+        @Arg("conversationId", type => String) conversationId: string,
+        @Arg("message", type => PerspectiveInput) message: PerspectiveInput,
+        @Arg("replyTo", type => String, { nullable: true }) replyTo?: string
+    ): ConversationMessage {
+        return { ...testConversationMessage, replyTo }
+    }
+
+    @Mutation(returns => Int)
+    runtimeMarkConversationRead(@Arg("conversationId", type => String) conversationId: string): number {
+        return 1
+    }
+}
@@ -56,6 +56,7 @@ declare global {
         agent: () => Agent;
         isInitialized: () => boolean;
         isUnlocked: () => boolean;
+        isLinkedDevice: () => boolean;
         unlock: (password: string) => boolean;
         lock: () => void;
         save_agent_profile: (agent: Agent) => void;
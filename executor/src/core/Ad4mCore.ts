@@ -1,4 +1,4 @@
-import type { Address, PublicSharing, PerspectiveHandle, Perspective, LanguageLanguageInput, LanguageExpression, LanguageMetaInput, AgentExpression, Language, NeighbourhoodExpression  } from '@coasys/ad4m'
+import type { Address, PublicSharing, PerspectiveHandle, Perspective, LanguageLanguageInput, LanguageExpression, LanguageMetaInput, AgentExpression, Language, NeighbourhoodExpression, PerspectiveExpression  } from '@coasys/ad4m'
 import { parseExprUrl, LanguageRef, Neighbourhood, PerspectiveState } from '@coasys/ad4m'
 
 import * as Config from './Config'
@@ -15,6 +15,7 @@ import { AgentInfoResponse } from '@holochain/client'
 import { v4 as uuidv4 } from 'uuid';
 import { MainConfig } from './Config'
 import { getPubSub, sleep } from "./utils";
+import { dispatchRpc, RpcRequest } from './Rpc'
 
 export interface InitServicesParams {
     agentService: AgentService,
@@ -106,6 +107,11 @@ export default class Ad4mCore {
       }
     }
 
+    // Single entry point for calls from the Rust executor, see Rpc.ts
+    async rpc(request: RpcRequest): Promise<any> {
+        return await dispatchRpc(this, request)
+    }
+
     get holochainService(): HolochainService | undefined {
         return this.#holochain
     }
@@ -161,6 +167,25 @@ export default class Ad4mCore {
         });
     }
 
+    // After a restart of the JS core. Holochain and the agent's keys live in Rust and
+    // kept running, so only the JS side gets set up again.
+    async resume() {
+        if(!this.#agentService.isUnlocked()) return
+
+        this.#holochain = new HolochainService({
+            conductorPath: this.#config.holochainConductorPath,
+            dataPath: this.#config.holochainDataPath,
+            resourcePath: this.#config.resourcePath,
+            // Only needed to start the conductor
+            hcProxyUrl: "",
+            hcBootstrapUrl: "",
+            logHolochainMetrics: this.#config.logHolochainMetrics
+        })
+        this.#holochain.attach()
+        this.initControllers()
+        await this.initLanguages()
+    }
+
     async waitForLanguages(): Promise<void> {
         return this.#languagesReady
     }
@@ -278,6 +303,36 @@ export default class Ad4mCore {
             return null
     }
 
+    // Sends a direct message peer-to-peer if the recipient is online, to their inbox otherwise.
+    // Throws if neither worked, so the outbox can retry later.
+    async sendDirectMessage(did: string, message: Perspective): Promise<{ status: string, expression: PerspectiveExpression }> {
+        const dmLang = await this.friendsDirectMessageLanguage(did)
+        if(!dmLang) throw new Error(`No direct message language found for ${did}`)
+        const adapter = dmLang.directMessageAdapter!
+
+        let online = false
+        try {
+            online = !!(await adapter.status())
+        } catch(e) {
+            console.debug("Couldn't get status of", did, e)
+        }
+
+        if(online) {
+            try {
+                const expression = await adapter.sendP2P(message)
+                if(expression) return { status: "sentP2P", expression }
+            } catch(e) {
+                // They might have gone offline in the meantime, their inbox still works
+                console.debug("Couldn't send message p2p to", did, e)
+            }
+        }
+
+        const expression = await adapter.sendInbox(message)
+        if(expression) return { status: "storedInInbox", expression }
+
+        throw new Error(`Couldn't send message to ${did}, neither p2p nor to their inbox`)
+    }
+
     async myDirectMessageLanguage(): Promise<Language> {
         const agent = AGENT.agent();
         const dmLang = agent!.directMessageLanguage!
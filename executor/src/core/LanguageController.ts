@@ -16,6 +16,8 @@ import { v4 as uuidv4 } from 'uuid';
 import { Ad4mDb } from './db';
 import stringify from 'json-stable-stringify'
 import { getPubSub, tagExpressionSignatureStatus } from './utils';
+import LanguageSandbox, { languagePermissions } from './LanguageSandbox';
+import type { CachedLanguage } from '../languages_extension';
 
 function cloneWithoutCircularReferences(obj: any, seen: WeakSet<any> = new WeakSet()): any {
     if (typeof obj === 'object' && obj !== null) {
@@ -37,6 +39,9 @@ function cloneWithoutCircularReferences(obj: any, seen: WeakSet<any> = new WeakS
     return obj;
 }
 
+// Sandboxed languages get loaded from a copy of their bundle with the sandbox prelude
+const SANDBOXED_BUNDLE = "bundle.sandboxed.js"
+
 type LinkObservers = (diff: PerspectiveDiff, lang: LanguageRef)=>void;
 type TelepresenceSignalObserver = (signal: PerspectiveExpression, lang: LanguageRef)=>void;
 type SyncStateChangeObserver = (state: PerspectiveState, lang: LanguageRef)=>void;
@@ -120,7 +125,7 @@ export default class LanguageController {
             console.log("LanguageController.loadSystemLanguages: Found settings for languageLanguage, writting settings");
             this.writeSettings(calculatedHash, this.#config.languageLanguageSettings);
         }
-        const { hash, language: languageLanguage } = await this.loadLanguage(sourcePath);
+        const { hash, language: languageLanguage } = await this.loadLanguage(sourcePath, true);
         this.#config.languageAliases[Config.languageLanguageAlias] = hash;
         this.#languageLanguage = languageLanguage!;
 
@@ -160,7 +165,7 @@ export default class LanguageController {
                 if (this.#config.bootstrapFixtures!.languages) {
                     await Promise.all(this.#config.bootstrapFixtures!.languages!.map(async file => {
                         const { sourcePath } = await this.saveLanguageBundle(this.#config.languageLanguageBundle);
-                        await this.loadLanguage(sourcePath);
+                        await this.loadLanguage(sourcePath, true);
                     }))
                 }
             }
@@ -215,7 +220,55 @@ export default class LanguageController {
         })
     }
 
-    async loadLanguage(sourceFilePath: string): Promise<{
+    #isSystemLanguage(hash: string): boolean {
+        return this.#config.systemLanguages.includes(hash)
+            || this.#config.preloadLanguages.includes(hash)
+            || Object.values(this.#config.languageAliases).includes(hash)
+    }
+
+    // The bundle cache checks bundles against their address, so failing to cache one is no error
+    #cachedLanguage(address: string): CachedLanguage | null {
+        try {
+            return LANGUAGE_CONTROLLER.cachedLanguage(address)
+        } catch (e) {
+            console.error(`LanguageController: could not read cached language ${address}:`, e)
+            return null
+        }
+    }
+
+    #cacheBundle(address: string, bundle: string) {
+        try {
+            LANGUAGE_CONTROLLER.cacheBundle(address, bundle)
+        } catch (e) {
+            console.error(`LanguageController: not caching bundle of language ${address}:`, e)
+        }
+    }
+
+    #cacheMeta(address: string, meta: object) {
+        try {
+            LANGUAGE_CONTROLLER.cacheMeta(address, meta)
+        } catch (e) {
+            console.error(`LanguageController: not caching meta of language ${address}:`, e)
+        }
+    }
+
+    // Languages installed before permissions were checked have none approved, they get what they declare
+    #migratePermissions(hash: string, languagePath: string) {
+        try {
+            const metaFile = path.join(languagePath, "meta.json")
+            const meta = fs.existsSync(metaFile)
+                ? JSON.parse(fs.readFileSync(metaFile).toString())
+                : this.#cachedLanguage(hash)?.meta
+            const declared = languagePermissions(meta?.data?.permissions)
+            if (LANGUAGE_CONTROLLER.migratePermissions(hash, declared)) {
+                console.log(`LanguageController: approved declared permissions of previously installed language ${hash}`)
+            }
+        } catch (e) {
+            console.error(`LanguageController: could not migrate permissions of language ${hash}:`, e)
+        }
+    }
+
+    async loadLanguage(sourceFilePath: string, system: boolean = false): Promise<{
         language: Language,
         hash: string,
     }> {
@@ -230,9 +283,20 @@ export default class LanguageController {
         // @ts-ignore
         const hash = await this.ipfsHash(bundleBytes)
         console.debug("LanguageController.loadLanguage: loading language at path", sourceFilePath, "with hash", hash);
+        const storageDirectory = this.getLanguageStoragePath(hash)
         let languageSource;
         try {
-            languageSource = await loadModule(sourceFilePath);
+            let modulePath = sourceFilePath
+            if (!system && !this.#isSystemLanguage(hash)) {
+                this.#migratePermissions(hash, path.dirname(sourceFilePath))
+                const sandbox = new LanguageSandbox(hash, storageDirectory)
+                const bundle = bundleBytes.toString()
+                sandbox.checkImports(bundle)
+                sandbox.register()
+                modulePath = path.join(path.dirname(sourceFilePath), SANDBOXED_BUNDLE)
+                fs.writeFileSync(modulePath, sandbox.wrap(bundle))
+            }
+            languageSource = await loadModule(modulePath);
         } catch (e) {
             const errMsg = `Could not load language ${e}`;
             console.error(errMsg);
@@ -259,7 +323,6 @@ export default class LanguageController {
         }
 
         const customSettings = this.getSettings(hash)
-        const storageDirectory = this.getLanguageStoragePath(hash)
 
         const Holochain = this.#holochainService?.getDelegateForLanguage(hash)
         //@ts-ignore
@@ -290,7 +353,11 @@ export default class LanguageController {
         //@ts-ignore
         if(language.directMessageAdapter && language.directMessageAdapter.recipient() == this.#context.agent.did) {
             language.directMessageAdapter.addMessageCallback(async (message: PerspectiveExpression) => {
-                await this.#pubSub.publish(PubSubDefinitions.RUNTIME_MESSAGED_RECEIVED_TOPIC, message)
+                // Encrypted messages come back decrypted, messages that fail to decrypt are dropped
+                const received = await RUNTIME_SERVICE.messageReceived(message)
+                if(received) {
+                    await this.#pubSub.publish(PubSubDefinitions.RUNTIME_MESSAGED_RECEIVED_TOPIC, received)
+                }
             })
         }
 
@@ -339,7 +406,11 @@ export default class LanguageController {
         //@ts-ignore
         if(language.directMessageAdapter && language.directMessageAdapter.recipient() == this.#context.agent.did) {
             language.directMessageAdapter.addMessageCallback(async (message: PerspectiveExpression) => {
-                await this.#pubSub.publish(PubSubDefinitions.RUNTIME_MESSAGED_RECEIVED_TOPIC, message)
+                // Encrypted messages come back decrypted, messages that fail to decrypt are dropped
+                const received = await RUNTIME_SERVICE.messageReceived(message)
+                if(received) {
+                    await this.#pubSub.publish(PubSubDefinitions.RUNTIME_MESSAGED_RECEIVED_TOPIC, received)
+                }
             })
         }
 
@@ -387,13 +458,18 @@ export default class LanguageController {
             //Check that the metafile already exists with language with this address to avoid refetch
             const metaFile = path.join(path.join(this.#config.languagesPath, address), "meta.json");
 
+            const cached = this.#cachedLanguage(address)
             if(fs.existsSync(metaFile)) {
                 languageMeta = JSON.parse(fs.readFileSync(metaFile).toString());
+            } else if(cached?.meta) {
+                languageMeta = cached.meta
             } else {
                 // We need to get the meta from the language language
-                // Retry 10 times with increasing delay to account for Holochain sync
+                // Retry 10 times with increasing delay to account for Holochain sync,
+                // unless we have the bundle and can do without when offline
+                const maxRetries = cached ? 1 : 10;
                 let retries = 0;
-                while (!languageMeta && retries < 10) {
+                while (!languageMeta && retries < maxRetries) {
                     try {
                         languageMeta = await this.getLanguageExpression(address)
                     } catch (e) {
@@ -445,6 +521,13 @@ export default class LanguageController {
             hash = await this.ipfsHash(source);
         }
 
+        //Languages other than the system ones only get what the user approved, so installing needs approval of what they declare
+        if (!this.#isSystemLanguage(address)) {
+            //@ts-ignore
+            const { name, permissions } = languageMeta.data
+            await LANGUAGE_CONTROLLER.checkPermissions(address, name ?? address, languagePermissions(permissions))
+        }
+
         //TODO: potential for unnecassary write to happen here if meta / source is already present at languages paths
         const {languagePath, sourcePath} = await this.saveLanguageBundle(source, languageMeta, hash);
         console.log(new Date(), "LanguageController.installLanguage: installed language");
@@ -514,10 +597,11 @@ export default class LanguageController {
             }
             const languageMetaData = languageMeta.data as LanguageExpression;
             const languageAuthor = languageMeta.author;
-            const trustedAgents: string[] = await RUNTIME_SERVICE.getTrustedAgents();
+            const authorTrust = await RUNTIME_SERVICE.agentTrust(languageAuthor);
             const agentService = (this.#context as LanguageContext).agent as AgentService;
-            //Check if the author of the language is in the trusted agent list the current agent holds, if so then go ahead and install
-            if (trustedAgents.find((agent) => agent === languageAuthor) || AGENT.did() === languageAuthor) {
+            //Check if the author of the language is trusted, directly or through trust statements of trusted agents, if so then go ahead and install
+            if (authorTrust.trusted || AGENT.did() === languageAuthor) {
+                console.log(`Installing language ${address}: ${authorTrust.reason}`);
                 //Get the language source so we can generate a hash and check against the hash given in the language meta information
                 const languageSource = await this.getLanguageSource(address);
                 if (!languageSource) {
@@ -544,7 +628,7 @@ export default class LanguageController {
                     Object.keys(languageMetaData.templateAppliedParams).length == 0 ||
                     !languageMetaData.templateSourceLanguageAddress
                 ) {
-                    let errMsg = `Language not created by trusted agent: ${languageAuthor} and is not templated... aborting language install. Language metadata: ${stringify(languageMetaData)}`
+                    let errMsg = `Language not created by trusted agent: ${languageAuthor} and is not templated... aborting language install. ${authorTrust.reason}. Language metadata: ${stringify(languageMetaData)}`
                     console.error(errMsg)
                     await this.#pubSub.publish(
                         PubSubDefinitions.EXCEPTION_OCCURRED_TOPIC,
@@ -565,8 +649,10 @@ export default class LanguageController {
                     throw new Error("Could not get the meta for the source language");
                 }
 
-                //Check that the agent who authored the original template language is in the current agents trust list
-                if (trustedAgents.find((agent) => agent === sourceLanguageMeta.author)) {
+                //Check that the agent who authored the original template language is trusted
+                const sourceAuthorTrust = await RUNTIME_SERVICE.agentTrust(sourceLanguageMeta.author);
+                if (sourceAuthorTrust.trusted) {
+                    console.log(`Installing language ${address} templated from ${sourceLanguageHash}: ${sourceAuthorTrust.reason}`);
                     //Apply the template information supplied in the language to be installed to the source language and make sure that the resulting
                     //language hash is equal to the one trying to be installed. This ensures that the only thing changed between language versions is the template data
                     const sourceLanguageTemplated = await this.languageApplyTemplateOnSource(sourceLanguageHash, JSON.parse(languageMetaData.templateAppliedParams));
@@ -585,7 +671,7 @@ export default class LanguageController {
                         throw new Error(`Templating of original source language did not result in the same language hash of un-trusted language trying to be installed... aborting language install. Expected hash: ${languageHash}. But got: ${sourceLanguageTemplated.meta.address}`)
                     }
                 } else {
-                    let errMsg = "Agent which created source language for language trying to be installed is not a trustedAgent... aborting language install";
+                    let errMsg = `Agent which created source language for language trying to be installed is not a trustedAgent... aborting language install. ${sourceAuthorTrust.reason}`;
                     await this.#pubSub.publish(
                         PubSubDefinitions.EXCEPTION_OCCURRED_TOPIC,
                         {
@@ -768,6 +854,7 @@ export default class LanguageController {
         const internal: LanguageMetaInternal = metaInput as LanguageMetaInternal
 
         internal.address = languageHash
+        internal.permissions = languagePermissions(metaInput.permissions)
 
         let input = new LanguageLanguageInput()
         input.bundle = languageData
@@ -816,6 +903,8 @@ export default class LanguageController {
 
         meta.templateAppliedParams = JSON.stringify(templateData)
         meta.templateSourceLanguageAddress = sourceLanguageHash
+        // An instance of the template is no new version of anything
+        delete meta.supersedes
 
         return await this.constructLanguageLanguageInput(sourceLanguageLines, meta)
     }
@@ -844,11 +933,17 @@ export default class LanguageController {
             }
         }
 
+        const cached = this.#cachedLanguage(address)
+        if(cached?.meta) return cached.meta
+
+        let expression
         try {
-            return await this.#languageLanguage!.expressionAdapter!.get(address)
+            expression = await this.#languageLanguage!.expressionAdapter!.get(address)
         } catch (e) {
             throw Error(`Error inside language language expression adapter: ${e}`)
         }
+        if(expression) this.#cacheMeta(address, expression)
+        return expression
     }
 
     async getLanguageSource(address: string): Promise<string | null> {
@@ -861,11 +956,17 @@ export default class LanguageController {
                 }
             }
         }
+        const cached = this.#cachedLanguage(address)
+        if(cached) return cached.bundle
+
+        let source
         try {
-            return await this.#languageLanguage!.languageAdapter!.getLanguageSource(address)
+            source = await this.#languageLanguage!.languageAdapter!.getLanguageSource(address)
         } catch (e) {
             throw Error(`Error inside language language getLanguageSource adapter: ${e}`)
         }
+        if(source) this.#cacheBundle(address, source)
+        return source
     }
 
     async getPerspective(address: string): Promise<Expression | null> {
@@ -1041,6 +1142,13 @@ export default class LanguageController {
             const fixtureLang = this.#config.bootstrapFixtures.languages!.find(f=>f.address===ref.expression)
             if(fixtureLang && fixtureLang.meta) return fixtureLang.meta
         }
+        if(ref.language.address === "lang") {
+            const cached = this.#cachedLanguage(ref.expression)
+            if(cached?.meta) {
+                await this.tagExpressionSignatureStatus(cached.meta)
+                return cached.meta
+            }
+        }
         let expr;
 
         try {
@@ -1082,6 +1190,7 @@ export default class LanguageController {
         }
 
         if(expr) {
+            if(ref.language.address === "lang") this.#cacheMeta(ref.expression, expr)
             await this.tagExpressionSignatureStatus(expr);
         }
 
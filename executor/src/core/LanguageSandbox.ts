@@ -0,0 +1,358 @@
+import path from 'node:path'
+import type { LanguagePermissions } from '../languages_extension'
+
+// Keeps language bundles to the permissions the user approved for them.
+//
+// All languages share the one JS core worker, which has all Deno permissions,
+// so instead of Deno's permission checks this shadows the globals a bundle could
+// reach outside with guarded versions: fetch, WebSocket, Deno, process and the
+// global object itself. The shadowing Deno and process only have the guarded members and
+// a few harmless ones, and no link to the real objects. Each access is checked against what is approved at that
+// moment, a language can always use its own storage directory. The executor's own
+// LANGUAGE_CONTROLLER, which approves permissions, is hidden from bundles altogether.
+//
+// This is a guard against languages doing more than they declared, not a hard
+// boundary: code that gets hold of the real globals through eval or Function is not covered.
+// Dynamic imports are only allowed with a literal module name, so they can be checked.
+
+export type Permission = keyof LanguagePermissions
+
+export function languagePermissions(declared?: Partial<LanguagePermissions> | null): LanguagePermissions {
+    return {
+        net: declared?.net ?? [],
+        read: declared?.read ?? [],
+        write: declared?.write ?? [],
+        env: declared?.env ?? [],
+        run: declared?.run ?? [],
+    }
+}
+
+// Builtin modules giving unguarded access, importing them needs the permission
+const GUARDED_MODULES: Record<string, Permission[]> = {
+    "fs": ["read", "write"],
+    "fs/promises": ["read", "write"],
+    "child_process": ["run"],
+    "net": ["net"],
+    "tls": ["net"],
+    "dgram": ["net"],
+    "dns": ["net"],
+    "http": ["net"],
+    "https": ["net"],
+    "http2": ["net"],
+}
+
+const READ_FUNCTIONS = [
+    "readFile", "readFileSync", "readTextFile", "readTextFileSync", "readDir", "readDirSync",
+    "stat", "statSync", "lstat", "lstatSync", "realPath", "realPathSync", "readLink", "readLinkSync",
+]
+
+const WRITE_FUNCTIONS = [
+    "writeFile", "writeFileSync", "writeTextFile", "writeTextFileSync", "mkdir", "mkdirSync",
+    "remove", "removeSync", "rename", "renameSync", "create", "createSync", "truncate", "truncateSync",
+    "chmod", "chmodSync", "chown", "chownSync", "symlink", "symlinkSync", "link", "linkSync",
+    "utime", "utimeSync",
+]
+
+const NET_FUNCTIONS = ["connect", "connectTls", "listen", "listenTls", "listenDatagram", "serve"]
+
+// Members of Deno and process that give no access outside, passed through as they are.
+// Everything not listed here or wrapped below is missing from the sandboxed objects.
+const HARMLESS_DENO_MEMBERS = [
+    "errors", "build", "version", "noColor", "inspect", "pid", "ppid", "memoryUsage",
+    "isatty", "consoleSize", "stdin", "stdout", "stderr", "SeekMode",
+]
+
+const HARMLESS_PROCESS_MEMBERS = [
+    "nextTick", "hrtime", "platform", "arch", "version", "versions", "release", "browser",
+    "emitWarning", "memoryUsage", "uptime", "pid", "stdout", "stderr",
+]
+
+const SHADOWED_GLOBALS = [
+    "fetch", "WebSocket", "Deno", "process", "LANGUAGE_CONTROLLER", "globalThis", "self", "window",
+]
+
+declare global {
+    var __ad4mLanguageSandboxes: Record<string, Record<string, any>>
+}
+
+function define(target: object, name: string, value: any) {
+    Object.defineProperty(target, name, { value, enumerable: true })
+}
+
+// A fresh object without prototype, so nothing of the real one can be reached through it
+function withHarmless(real: any, names: string[]): any {
+    const sandboxed = Object.create(null)
+    for (const name of names) {
+        if (!(name in real)) continue
+        const value = real[name]
+        define(sandboxed, name, typeof value === "function" ? value.bind(real) : value)
+    }
+    return sandboxed
+}
+
+function pathOf(target: string | URL): string {
+    if (target instanceof URL || (typeof target === "string" && target.startsWith("file://"))) {
+        return decodeURIComponent(new URL(target).pathname)
+    }
+    return path.resolve(String(target))
+}
+
+function isWithin(base: string, target: string): boolean {
+    const relative = path.relative(path.resolve(base), target)
+    return !relative.startsWith("..") && !path.isAbsolute(relative)
+}
+
+function defaultPort(protocol: string): string {
+    switch (protocol) {
+        case "http:": case "ws:": return "80"
+        case "https:": case "wss:": return "443"
+        default: return ""
+    }
+}
+
+export default class LanguageSandbox {
+    #address: string
+    #storageDirectory: string
+    #reported: Set<string> = new Set()
+
+    constructor(address: string, storageDirectory: string) {
+        this.#address = address
+        this.#storageDirectory = storageDirectory
+    }
+
+    approved(): LanguagePermissions {
+        return languagePermissions(LANGUAGE_CONTROLLER.approvedPermissions(this.#address))
+    }
+
+    #deny(permission: Permission, target: string): never {
+        const key = `${permission}:${target}`
+        if (!this.#reported.has(key)) {
+            this.#reported.add(key)
+            LANGUAGE_CONTROLLER.permissionDenied(this.#address, permission, target)
+                .catch((e: any) => console.error("LanguageSandbox: could not report permission violation", e))
+        }
+        //@ts-ignore
+        throw new Deno.errors.PermissionDenied(`Language ${this.#address} has no ${permission} permission for "${target}"`)
+    }
+
+    allowsHost(hostname: string, port: string): boolean {
+        return this.approved().net.some(entry =>
+            entry === "*"
+            || entry === hostname
+            || entry === `${hostname}:${port}`
+            || (entry.startsWith("*.") && hostname.endsWith(entry.slice(1)))
+        )
+    }
+
+    checkHost(hostname: string, port: string | number | undefined) {
+        const portString = port === undefined ? "" : String(port)
+        if (!this.allowsHost(hostname, portString)) this.#deny("net", portString ? `${hostname}:${portString}` : hostname)
+    }
+
+    checkUrl(url: URL) {
+        switch (url.protocol) {
+            case "data:":
+            case "blob:":
+                return
+            case "file:":
+                return this.checkPath("read", url)
+            default:
+                return this.checkHost(url.hostname, url.port || defaultPort(url.protocol))
+        }
+    }
+
+    checkPath(permission: "read" | "write", target: string | URL) {
+        const resolved = pathOf(target)
+        if (isWithin(this.#storageDirectory, resolved)) return
+        if (this.approved()[permission].some(entry => isWithin(entry, resolved))) return
+        this.#deny(permission, resolved)
+    }
+
+    allowsEnv(name: string): boolean {
+        const env = this.approved().env
+        return env.includes("*") || env.includes(name)
+    }
+
+    checkEnv(name: string) {
+        if (!this.allowsEnv(name)) this.#deny("env", name)
+    }
+
+    checkRun(command: string | URL) {
+        const program = String(command)
+        const run = this.approved().run
+        if (!run.includes("*") && !run.includes(program) && !run.includes(path.basename(program))) {
+            this.#deny("run", program)
+        }
+    }
+
+    // Refuses bundles importing builtin modules they have no permission for,
+    // and dynamic imports of computed module names, which can't be checked
+    checkImports(source: string) {
+        const imports = /(?:\bfrom\s*|\bimport\s*\(\s*|\bimport\s+|\brequire\s*\(\s*)["'](?:node:)?([a-z_/0-9]+)["']/g
+        const approved = this.approved()
+        for (const match of source.matchAll(imports)) {
+            const needed = GUARDED_MODULES[match[1]]
+            if (needed && !needed.some(permission => approved[permission].length > 0)) {
+                this.#deny(needed[0], `node:${match[1]}`)
+            }
+        }
+
+        // import("node:" + "fs") starts with a literal too, so the literal has to be the whole argument
+        const dynamicImports = /\bimport\s*\(\s*(?:(["'])[^"'\n]*\1\s*[,)])?/g
+        for (const match of source.matchAll(dynamicImports)) {
+            if (match[1] === undefined) {
+                //@ts-ignore
+                throw new Deno.errors.PermissionDenied(
+                    `Language ${this.#address} imports a module whose name is computed at runtime: "${match[0]}..."`
+                )
+            }
+        }
+    }
+
+    #deno(): any {
+        //@ts-ignore
+        const real = Deno
+        const sandboxed = withHarmless(real, HARMLESS_DENO_MEMBERS)
+        for (const name of READ_FUNCTIONS) {
+            define(sandboxed, name, (target: string | URL, ...args: any[]) => {
+                this.checkPath("read", target)
+                return real[name](target, ...args)
+            })
+        }
+        for (const name of WRITE_FUNCTIONS) {
+            define(sandboxed, name, (target: string | URL, ...args: any[]) => {
+                this.checkPath("write", target)
+                // rename, symlink and link also create the second path
+                if (["rename", "renameSync", "symlink", "symlinkSync", "link", "linkSync"].includes(name)) {
+                    this.checkPath("write", args[0])
+                }
+                return real[name](target, ...args)
+            })
+        }
+        for (const name of ["copyFile", "copyFileSync"]) {
+            define(sandboxed, name, (from: string | URL, to: string | URL) => {
+                this.checkPath("read", from)
+                this.checkPath("write", to)
+                return real[name](from, to)
+            })
+        }
+        for (const name of ["open", "openSync"]) {
+            define(sandboxed, name, (target: string | URL, options?: any) => {
+                if (!options || options.read !== false) this.checkPath("read", target)
+                if (options && (options.write || options.append || options.create || options.createNew || options.truncate)) {
+                    this.checkPath("write", target)
+                }
+                return real[name](target, options)
+            })
+        }
+        define(sandboxed, "watchFs", (paths: string | string[], options?: any) => {
+            for (const target of Array.isArray(paths) ? paths : [paths]) this.checkPath("read", target)
+            return real.watchFs(paths, options)
+        })
+        for (const name of NET_FUNCTIONS) {
+            define(sandboxed, name, (options: any = {}, ...args: any[]) => {
+                // serve also takes just a handler
+                const target = typeof options === "function" ? {} : options
+                this.checkHost(target.hostname ?? (name.startsWith("connect") ? "127.0.0.1" : "0.0.0.0"), target.port)
+                return real[name](options, ...args)
+            })
+        }
+        define(sandboxed, "resolveDns", (query: string, ...args: any[]) => {
+            this.checkHost(query, undefined)
+            return real.resolveDns(query, ...args)
+        })
+        define(sandboxed, "env", {
+            get: (name: string) => { this.checkEnv(name); return real.env.get(name) },
+            has: (name: string) => { this.checkEnv(name); return real.env.has(name) },
+            set: (name: string, value: string) => { this.checkEnv(name); return real.env.set(name, value) },
+            delete: (name: string) => { this.checkEnv(name); return real.env.delete(name) },
+            toObject: () => Object.fromEntries(
+                Object.entries(real.env.toObject()).filter(([name]) => this.allowsEnv(name))
+            ),
+        })
+        const sandbox = this
+        define(sandboxed, "Command", class extends real.Command {
+            constructor(command: string | URL, options?: any) {
+                sandbox.checkRun(command)
+                super(command, options)
+            }
+        })
+        define(sandboxed, "run", (options: any) => {
+            this.checkRun(options.cmd[0])
+            return real.run(options)
+        })
+        return sandboxed
+    }
+
+    #process(): any {
+        const real = (globalThis as any).process
+        if (!real) return undefined
+        const sandboxed = withHarmless(real, HARMLESS_PROCESS_MEMBERS)
+        // Libraries probe variables like NODE_ENV all the time, so unapproved ones read as unset
+        define(sandboxed, "env", new Proxy({}, {
+            get: (_, name) => {
+                if (typeof name !== "string" || !this.allowsEnv(name)) return undefined
+                return real.env[name]
+            },
+            set: (_, name, value) => {
+                this.checkEnv(String(name))
+                real.env[name] = value
+                return true
+            },
+            has: (_, name) => typeof name === "string" && this.allowsEnv(name) && name in real.env,
+            ownKeys: () => Object.keys(real.env).filter(name => this.allowsEnv(name)),
+            getOwnPropertyDescriptor: (_, name) => {
+                if (typeof name !== "string" || !this.allowsEnv(name) || !(name in real.env)) return undefined
+                return { value: real.env[name], writable: true, enumerable: true, configurable: true }
+            },
+        }))
+        return sandboxed
+    }
+
+    // The names a sandboxed bundle sees instead of the real globals
+    globals(): Record<string, any> {
+        const fetchGuarded = (input: any, init?: any) => {
+            const url = input instanceof Request ? input.url : String(input)
+            this.checkUrl(new URL(url))
+            return fetch(input, init)
+        }
+        const sandbox = this
+        class WebSocketGuarded extends WebSocket {
+            constructor(url: string | URL, protocols?: string | string[]) {
+                sandbox.checkUrl(new URL(url))
+                super(url, protocols)
+            }
+        }
+        const overrides: Record<string, any> = {
+            fetch: fetchGuarded,
+            WebSocket: WebSocketGuarded,
+            Deno: this.#deno(),
+            process: this.#process(),
+            LANGUAGE_CONTROLLER: undefined,
+        }
+        // Everything else still reads and writes the real global object
+        const global = new Proxy({}, {
+            get: (_, name) => name in overrides ? overrides[name as string] : Reflect.get(globalThis, name),
+            set: (_, name, value) => Reflect.set(globalThis, name, value),
+            has: (_, name) => name in overrides ? overrides[name as string] !== undefined : Reflect.has(globalThis, name),
+            deleteProperty: (_, name) => Reflect.deleteProperty(globalThis, name),
+        })
+        return { ...overrides, globalThis: global, self: global, window: global }
+    }
+
+    // The bundle with the shadowing prelude in front, on the same line to keep line numbers.
+    // All names are always shadowed: declarations in nested scopes only shadow them there,
+    // and a bundle declaring one of them at the top level fails to load with a SyntaxError.
+    wrap(source: string): string {
+        // Not through globalThis, which is one of the shadowed names
+        const registry = `__ad4mLanguageSandboxes[${JSON.stringify(this.#address)}]`
+        const prelude = SHADOWED_GLOBALS.map(name => `const ${name} = ${registry}.${name};`).join(" ")
+        return prelude + source
+    }
+
+    // Makes the globals available to the prelude of the wrapped bundle
+    register() {
+        if (!globalThis.__ad4mLanguageSandboxes) globalThis.__ad4mLanguageSandboxes = {}
+        globalThis.__ad4mLanguageSandboxes[this.#address] = this.globals()
+    }
+}
@@ -0,0 +1,121 @@
+import type { Language, LanguageRef, Neighbourhood, Perspective, PerspectiveDiff, PerspectiveExpression } from '@coasys/ad4m'
+import type Ad4mCore from './Ad4mCore'
+
+// Requests the Rust executor sends into the JS core, mirroring `JsRpc` in
+// rust-executor/src/js_core/rpc.rs. They arrive as data through JSON.parse,
+// so nothing in here ever gets evaluated as code.
+
+export type LinksAdapterCall =
+    { method: "sync" }
+    | { method: "commit", diff: PerspectiveDiff }
+    | { method: "currentRevision" }
+    | { method: "render" }
+    | { method: "others" }
+
+export type TelepresenceAdapterCall =
+    { method: "exists" }
+    | { method: "setOnlineStatus", status: PerspectiveExpression }
+    | { method: "getOnlineAgents" }
+    | { method: "sendSignal", remoteAgentDid: string, payload: PerspectiveExpression }
+    | { method: "sendBroadcast", payload: PerspectiveExpression }
+
+export type RpcRequest =
+    { op: "callResolver", resolverType: "Query" | "Mutation", name: string, args: any }
+    | { op: "waitForLanguages" }
+    | { op: "resume" }
+    | { op: "ensureAgentExpression" }
+    | { op: "publishAgentExpression" }
+    | { op: "installLanguage", address: string }
+    | { op: "languageInstalled", address: string }
+    | { op: "getNeighbourhood", address: string }
+    | { op: "createNeighbourhood", neighbourhood: Neighbourhood }
+    | { op: "agentByDid", did: string }
+    | { op: "sendDirectMessage", did: string, message: Perspective }
+    | { op: "directMessageInbox", author: string | null }
+    | { op: "friendStatus", did: string }
+    | { op: "linksAdapter", address: string, call: LinksAdapterCall }
+    | { op: "telepresenceAdapter", address: string, call: TelepresenceAdapterCall }
+    | { op: "holochainSignal", cellId: [number[], number[]], zomeName: string, signal: number[] }
+
+async function languageByAddress(core: Ad4mCore, address: string): Promise<Language | undefined> {
+    return await core.languageController.languageByRef({ address } as LanguageRef)
+}
+
+async function linksAdapter(core: Ad4mCore, address: string, call: LinksAdapterCall): Promise<any> {
+    const language = await languageByAddress(core, address)
+    if (!language) return null
+    const adapter = language.linksAdapter!
+    switch (call.method) {
+        case "sync": return await adapter.sync()
+        case "commit": return await adapter.commit(call.diff)
+        case "currentRevision": return await adapter.currentRevision()
+        case "render": return await adapter.render()
+        case "others": return await adapter.others()
+    }
+}
+
+async function telepresenceAdapter(core: Ad4mCore, address: string, call: TelepresenceAdapterCall): Promise<any> {
+    const language = await languageByAddress(core, address)
+    if (call.method === "exists") return !!(language && language.telepresenceAdapter)
+    if (!language) return null
+    const adapter = language.telepresenceAdapter!
+    switch (call.method) {
+        case "setOnlineStatus": return await adapter.setOnlineStatus(call.status)
+        case "getOnlineAgents": return await adapter.getOnlineAgents()
+        case "sendSignal": return await adapter.sendSignal(call.remoteAgentDid, call.payload)
+        case "sendBroadcast": return await adapter.sendBroadcast(call.payload)
+    }
+}
+
+export async function dispatchRpc(core: Ad4mCore, request: RpcRequest): Promise<any> {
+    switch (request.op) {
+        case "callResolver":
+            return await core.callResolver(request.resolverType, request.name, request.args, undefined)
+        case "waitForLanguages":
+            return await core.waitForLanguages()
+        case "resume":
+            return await core.resume()
+        case "ensureAgentExpression":
+            return await core.agentService.ensureAgentExpression()
+        case "publishAgentExpression":
+            return await core.agentService.updateAgent(core.agentService.agent)
+        case "installLanguage":
+            return await core.languageController.installLanguage(request.address, null)
+        case "languageInstalled":
+            return !!(await languageByAddress(core, request.address))
+        case "getNeighbourhood":
+            return await core.languageController.getPerspective(request.address)
+        case "createNeighbourhood":
+            return await core.languageController
+                .getNeighbourhoodLanguage()
+                .expressionAdapter!
+                .putAdapter
+                //@ts-ignore
+                .createPublic(request.neighbourhood)
+        case "agentByDid":
+            return (await core.languageController.getAgentLanguage().expressionAdapter!.get(request.did))?.data ?? null
+        case "sendDirectMessage":
+            return await core.sendDirectMessage(request.did, request.message)
+        case "directMessageInbox":
+            return await (await core.myDirectMessageLanguage()).directMessageAdapter!.inbox(request.author ?? undefined)
+        case "friendStatus": {
+            const dmLang = await core.friendsDirectMessageLanguage(request.did)
+            return dmLang ? await dmLang.directMessageAdapter!.status() : null
+        }
+        case "linksAdapter":
+            return await linksAdapter(core, request.address, request.call)
+        case "telepresenceAdapter":
+            return await telepresenceAdapter(core, request.address, request.call)
+        case "holochainSignal":
+            return await core.holochainService!.handleCallback({
+                //@ts-ignore
+                cell_id: request.cellId,
+                zome_name: request.zomeName,
+                //@ts-ignore
+                signal: request.signal,
+            })
+        default:
+            //@ts-ignore
+            throw new Error(`Unknown rpc operation: ${request.op}`)
+    }
+}
@@ -90,6 +90,13 @@ export default class AgentService {
 
     const agentLanguage = this.getAgentLanguage();
 
+    // The primary device publishes the agent expression with the proofs of all devices,
+    // a linked device would replace them with its own view
+    if (AGENT.isLinkedDevice()) {
+      console.log("Not publishing agent profile from a linked device");
+      return;
+    }
+
     if (agent?.did) {
       let adapter = agentLanguage.expressionAdapter!.putAdapter;
 
@@ -5,6 +5,7 @@ import * as PubSubDefinitions from './SubscriptionDefinitions'
 import { ad4mExecutorVersion } from '../Config';
 import { OuterConfig } from '../../main';
 import { getPubSub, tagExpressionSignatureStatus } from '../utils';
+import { languagePermissions } from '../LanguageSandbox';
 
 
 export function createResolvers(core: Ad4mCore, config: OuterConfig) {
@@ -194,6 +195,9 @@ export function createResolvers(core: Ad4mCore, config: OuterConfig) {
                 meta.templateAppliedParams = internal.templateAppliedParams
                 meta.possibleTemplateParams = internal.possibleTemplateParams
                 meta.sourceCodeLink = internal.sourceCodeLink
+                meta.permissions = languagePermissions(internal.permissions)
+                meta.version = internal.version
+                meta.supersedes = internal.supersedes
 
                 return meta
             },
@@ -343,6 +347,9 @@ export function createResolvers(core: Ad4mCore, config: OuterConfig) {
                 meta.templateAppliedParams = internal.templateAppliedParams
                 meta.possibleTemplateParams = internal.possibleTemplateParams
                 meta.sourceCodeLink = internal.sourceCodeLink
+                meta.permissions = languagePermissions(internal.permissions)
+                meta.version = internal.version
+                meta.supersedes = internal.supersedes
                 return meta
             },
             //@ts-ignore
@@ -362,36 +369,6 @@ export function createResolvers(core: Ad4mCore, config: OuterConfig) {
                 await core.languageController.putSettings(languageAddress, JSON.parse(settings))
                 return true
             },
-
-            //@ts-ignore
-            runtimeFriendSendMessage: async (args, context) => {
-                const { did, message } = args
-
-                const dmLang = await core.friendsDirectMessageLanguage(did)
-                if(!dmLang) return false
-
-                let wasSent = false
-                let messageExpression
-                try {
-                    const status = await dmLang.directMessageAdapter!.status()
-                    if(status) {
-                        messageExpression = await dmLang.directMessageAdapter!.sendP2P(message)
-                        wasSent = true
-                    } else {
-                        throw "Friends seems offline"
-                    }
-                } catch(e) {
-                    messageExpression = await dmLang.directMessageAdapter!.sendInbox(message)
-                    wasSent = true
-                }
-
-                if(wasSent && messageExpression) {
-                    await RUNTIME_SERVICE.addMessageOutbox(did, messageExpression, wasSent)
-                }
-
-                return wasSent;
-            }
-
         },
 
         Agent: {
@@ -1,4 +1,4 @@
-import type { Dna } from '@coasys/ad4m'
+import type { Dna, ZomeCallOptions } from '@coasys/ad4m'
 import type HolochainService from './HolochainService'
 import type { AppSignalCb } from '@holochain/client'
 import { AsyncQueue } from './Queue'
@@ -19,9 +19,9 @@ export default class HolochainLanguageDelegate {
         return;
     }
 
-    async call(dnaNick: string, zomeName: string, fnName: string, params: object|string): Promise<any> {
+    async call(dnaNick: string, zomeName: string, fnName: string, params: object|string, options?: ZomeCallOptions): Promise<any> {
         return await this.#queue.add(async () => {
-            return await this.#holochainService.callZomeFunction(this.#languageHash, dnaNick, zomeName, fnName, params)
+            return await this.#holochainService.callZomeFunction(this.#languageHash, dnaNick, zomeName, fnName, params, options)
         })
     }
 
@@ -2,7 +2,7 @@ import { AppSignalCb, AppSignal, CellId, CellType, AgentInfoResponse, InstallApp
 import path from 'node:path'
 import fs from 'node:fs'
 import HolochainLanguageDelegate from "./HolochainLanguageDelegate"
-import type { Dna } from '@coasys/ad4m'
+import type { Dna, ZomeCallOptions } from '@coasys/ad4m'
 import { AsyncQueue } from './Queue'
 import { decode, encode } from "@msgpack/msgpack"
 
@@ -124,6 +124,11 @@ export default class HolochainService {
         resolveReady!()
     }
 
+    // For a conductor that is already running, started before the JS core restarted
+    attach() {
+        this.#ready = Promise.resolve()
+    }
+
     async stop() {
         await this.#ready
         console.log("HolochainService.stop(): Stopping holochain process");
@@ -255,7 +260,7 @@ export default class HolochainService {
         return `${languageHash}-${dnaNick}`
     }
 
-    async callZomeFunction(lang: string, dnaNick: string, zomeName: string, fnName: string, payload: any): Promise<any> {
+    async callZomeFunction(lang: string, dnaNick: string, zomeName: string, fnName: string, payload: any, options?: ZomeCallOptions): Promise<any> {
         await this.#ready
         const installed_app_id = lang
 
@@ -265,7 +270,7 @@ export default class HolochainService {
                 console.debug("\x1b[34m", new Date().toISOString(), "HolochainService calling zome function:", dnaNick, zomeName, fnName, JSON.stringify(payload).substring(0, 50), "\nFor language with address", lang, "\x1b[0m");
             }
 
-            let result = await HOLOCHAIN_SERVICE.callZomeFunction(installed_app_id, dnaNick, zomeName, fnName, encode(payload));
+            let result = await HOLOCHAIN_SERVICE.callZomeFunction(installed_app_id, dnaNick, zomeName, fnName, encode(payload), options);
             if (result["Ok"]) {
                 result = decode(result["Ok"])
             } else {
@@ -22,18 +22,19 @@ export function getPubSub(): PubSub {
 export async function tagExpressionSignatureStatus(expression: Expression) {
   if(!expression) throw "tagExpressionSignatureStatus - expression is undefined"
 
-  let verified
+  let check
 
   try {
     if(typeof expression == "string") expression = JSON.parse(expression)
-    verified = SIGNATURE.verify(expression)
+    check = SIGNATURE.check(expression)
   } catch(e) {
-    verified = false
+    check = { isValid: false, retiredKey: false }
   }
 
-  if(verified) {
+  if(check.isValid) {
     expression.proof.valid = true
     expression.proof.invalid = false
+    expression.proof.retiredKey = check.retiredKey
   } else {
     expression.proof.invalid = true
     expression.proof.valid = false
@@ -48,5 +49,6 @@ export function removeSignatureTags(e: Expression) {
   if(e.proof) {
       delete e.proof.valid
       delete e.proof.invalid
+      delete e.proof.retiredKey
   }
 }
\ No newline at end of file
@@ -14,12 +14,19 @@ declare global {
         appPort: Number
     }
 
+    interface ZomeCallOptions {
+        timeoutMs?: Number,
+        retries?: Number,
+        backoffMs?: Number,
+        idempotent?: bool
+    }
+
     interface HolochainService {
         async startHolochainConductor: (config: ConductorConfig) => void;
         async logDhtStatus: () => void;
         async installApp: (install_app_payload: InstallAppRequest) => AppInfo;
         async getAppInfo: (app_id: String) => AppInfo | null;
-        async callZomeFunction: (app_id: String, cell_name: String, zome_name: String, fn_name: String, payload: any) => CallZomeResponse;
+        async callZomeFunction: (app_id: String, cell_name: String, zome_name: String, fn_name: String, payload: any, options?: ZomeCallOptions) => CallZomeResponse;
         async agentInfos: () => AgentInfoSigned[];
         async addAgentInfos: (agent_infos: AgentInfoSigned[]) => void;
         async removeApp: (app_id: String) => void;
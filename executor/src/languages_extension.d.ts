@@ -35,11 +35,31 @@ export interface Perspective {
 // PerspectiveState is an enum in Rust, which can be represented as a union type in TypeScript
 export type PerspectiveState = 'PRIVATE' | 'NEIGHBOURHOOD_JOIN_INITIATED' | 'LINK_LANGUAGE_FAILED_TO_INSTALL' | 'LINK_LANGUAGE_INSTALLED_BUT_NOT_SYNCED' | 'SYNCED';
 
+export interface LanguagePermissions {
+    net: string[];
+    read: string[];
+    write: string[];
+    env: string[];
+    run: string[];
+}
+
+export interface CachedLanguage {
+    bundle: string;
+    meta: any | null;
+}
+
 declare global {
     interface RustLanguages {
         perspectiveDiffReceived: (diff: PerspectiveDiff, languageAddress: string) => void;
         syncStateChanged: (state: PerspectiveState, languageAddress: string) => void;
         telepresenceSignalReceived: (signal: PerspectiveExpression, languageAddress: string) => void;
+        checkPermissions: (languageAddress: string, name: string, requested: LanguagePermissions) => Promise<void>;
+        approvedPermissions: (languageAddress: string) => LanguagePermissions | null;
+        migratePermissions: (languageAddress: string, declared: LanguagePermissions) => boolean;
+        permissionDenied: (languageAddress: string, permission: string, target: string) => Promise<void>;
+        cachedLanguage: (languageAddress: string) => CachedLanguage | null;
+        cacheBundle: (languageAddress: string, bundle: string) => void;
+        cacheMeta: (languageAddress: string, meta: object) => void;
     }
 
     const LANGUAGES: RustLanguages;
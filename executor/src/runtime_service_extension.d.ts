@@ -1,8 +1,18 @@
 declare global {
+    interface TrustReport {
+        did: string;
+        trusted: boolean;
+        depth: number | null;
+        endorsers: string[];
+        path: string[];
+        reason: string;
+    }
+
     interface RuntimeService {
         friends(): Promise<string[]>;
-        addMessageOutbox(did: string, message: object, wasSent: boolean): Promise<void>;
         getTrustedAgents(): Promise<string[]>;
+        agentTrust(did: string): Promise<TrustReport>;
+        messageReceived(message: object): Promise<object | null>;
     }
         
     const RUNTIME_SERVICE: RuntimeService;
@@ -10,10 +10,17 @@ export interface ExpressionProof {
     key: string;
 }
 
+export interface SignatureCheck {
+    isValid: boolean;
+    /** Signed with a key the author has rotated away from since */
+    retiredKey: boolean;
+}
+
 declare global {
     interface RustSignature {
         verifyStringSignedByDid: (did: string, data: string, signedData: string) => boolean;
         verify: (expr: Expression) => boolean;
+        check: (expr: Expression) => SignatureCheck;
     }
 
     const SIGNATURE: RustSignature;
@@ -282,6 +282,103 @@ pub async fn generate(
     Ok(response_data.agent_generate)
 }
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/agent.gql",
+    response_derives = "Debug"
+)]
+pub struct RestoreFromMnemonic;
+
+pub async fn restore_from_mnemonic(
+    executor_url: String,
+    cap_token: String,
+    mnemonic: String,
+    passphrase: String,
+) -> Result<restore_from_mnemonic::RestoreFromMnemonicAgentRestoreFromMnemonic> {
+    let response_data: restore_from_mnemonic::ResponseData = query(
+        executor_url,
+        cap_token,
+        RestoreFromMnemonic::build_query(restore_from_mnemonic::Variables {
+            mnemonic,
+            passphrase,
+        }),
+    )
+    .await
+    .with_context(|| "Failed to run agent->restore_from_mnemonic")?;
+    Ok(response_data.agent_restore_from_mnemonic)
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/agent.gql",
+    response_derives = "Debug"
+)]
+pub struct ImportBackup;
+
+pub async fn import_backup(
+    executor_url: String,
+    cap_token: String,
+    backup: String,
+    passphrase: String,
+) -> Result<import_backup::ImportBackupAgentImportBackup> {
+    let response_data: import_backup::ResponseData = query(
+        executor_url,
+        cap_token,
+        ImportBackup::build_query(import_backup::Variables { backup, passphrase }),
+    )
+    .await
+    .with_context(|| "Failed to run agent->import_backup")?;
+    Ok(response_data.agent_import_backup)
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/agent.gql",
+    response_derives = "Debug"
+)]
+pub struct Mnemonic;
+
+pub async fn mnemonic(
+    executor_url: String,
+    cap_token: String,
+    passphrase: String,
+) -> Result<String> {
+    let response_data: mnemonic::ResponseData = query(
+        executor_url,
+        cap_token,
+        Mnemonic::build_query(mnemonic::Variables { passphrase }),
+    )
+    .await
+    .with_context(|| "Failed to run agent->mnemonic")?;
+    Ok(response_data.agent_mnemonic)
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/agent.gql",
+    response_derives = "Debug"
+)]
+pub struct ExportBackup;
+
+pub async fn export_backup(
+    executor_url: String,
+    cap_token: String,
+    passphrase: String,
+) -> Result<String> {
+    let response_data: export_backup::ResponseData = query(
+        executor_url,
+        cap_token,
+        ExportBackup::build_query(export_backup::Variables { passphrase }),
+    )
+    .await
+    .with_context(|| "Failed to run agent->export_backup")?;
+    Ok(response_data.agent_export_backup)
+}
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "schema.gql",
@@ -376,6 +473,53 @@ pub async fn entanglement_proof_pre_flight(
     .with_context(|| "Failed to run runtime->add-trusted-agents query")
 }
 
+#[derive(GraphQLQuery, Debug, Clone)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/agent.gql",
+    response_derives = "Debug"
+)]
+pub struct AuthoriseDevice;
+
+pub async fn authorise_device(
+    executor_url: String,
+    cap_token: String,
+    device_did: String,
+) -> Result<authorise_device::AuthoriseDeviceAgentAuthoriseDevice> {
+    let response: authorise_device::ResponseData = query(
+        executor_url,
+        cap_token,
+        AuthoriseDevice::build_query(authorise_device::Variables { device_did }),
+    )
+    .await
+    .with_context(|| "Failed to run agent->authorise-device query")?;
+    Ok(response.agent_authorise_device)
+}
+
+#[derive(GraphQLQuery, Debug, Clone)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/agent.gql",
+    response_derives = "Debug"
+)]
+pub struct LinkDevice;
+
+pub async fn link_device(
+    executor_url: String,
+    cap_token: String,
+    link: link_device::DeviceLinkInput,
+    passphrase: String,
+) -> Result<link_device::LinkDeviceAgentLinkDevice> {
+    let response: link_device::ResponseData = query(
+        executor_url,
+        cap_token,
+        LinkDevice::build_query(link_device::Variables { link, passphrase }),
+    )
+    .await
+    .with_context(|| "Failed to run agent->link-device query")?;
+    Ok(response.agent_link_device)
+}
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "schema.gql",
@@ -507,6 +651,52 @@ impl AgentClient {
         .await
     }
 
+    pub async fn restore_from_mnemonic(
+        &self,
+        mnemonic: String,
+        passphrase: String,
+    ) -> Result<restore_from_mnemonic::RestoreFromMnemonicAgentRestoreFromMnemonic> {
+        restore_from_mnemonic(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            mnemonic,
+            passphrase,
+        )
+        .await
+    }
+
+    pub async fn import_backup(
+        &self,
+        backup: String,
+        passphrase: String,
+    ) -> Result<import_backup::ImportBackupAgentImportBackup> {
+        import_backup(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            backup,
+            passphrase,
+        )
+        .await
+    }
+
+    pub async fn mnemonic(&self, passphrase: String) -> Result<String> {
+        mnemonic(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            passphrase,
+        )
+        .await
+    }
+
+    pub async fn export_backup(&self, passphrase: String) -> Result<String> {
+        export_backup(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            passphrase,
+        )
+        .await
+    }
+
     pub async fn sign_message(
         &self,
         message: String,
@@ -557,6 +747,32 @@ impl AgentClient {
         .await
     }
 
+    pub async fn authorise_device(
+        &self,
+        device_did: String,
+    ) -> Result<authorise_device::AuthoriseDeviceAgentAuthoriseDevice> {
+        authorise_device(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            device_did,
+        )
+        .await
+    }
+
+    pub async fn link_device(
+        &self,
+        link: link_device::DeviceLinkInput,
+        passphrase: String,
+    ) -> Result<link_device::LinkDeviceAgentLinkDevice> {
+        link_device(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            link,
+            passphrase,
+        )
+        .await
+    }
+
     pub async fn watch(&self) -> Result<()> {
         watch(self.info.executor_url.clone(), self.info.cap_token.clone()).await
     }
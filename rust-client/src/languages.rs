@@ -1,6 +1,10 @@
 use std::sync::Arc;
 
-use crate::{util::query, ClientInfo};
+use crate::{
+    types::{LanguagePermissions, LanguageUpgrade},
+    util::query,
+    ClientInfo,
+};
 use anyhow::{Context, Result};
 use graphql_client::GraphQLQuery;
 use serde::{Deserialize, Serialize};
@@ -135,6 +139,7 @@ pub async fn meta(
 )]
 pub struct Publish;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn publish(
     executor_url: String,
     cap_token: String,
@@ -143,6 +148,9 @@ pub async fn publish(
     description: Option<String>,
     possible_template_params: Option<Vec<String>>,
     source_code_link: Option<String>,
+    permissions: Option<LanguagePermissions>,
+    version: Option<String>,
+    supersedes: Option<String>,
 ) -> Result<publish::PublishLanguagePublish> {
     let response_data: publish::ResponseData = query(
         executor_url,
@@ -154,6 +162,15 @@ pub async fn publish(
                 description,
                 possible_template_params,
                 source_code_link,
+                permissions: permissions.map(|p| publish::LanguagePermissionsInput {
+                    net: Some(p.net),
+                    read: Some(p.read),
+                    write: Some(p.write),
+                    env: Some(p.env),
+                    run: Some(p.run),
+                }),
+                version,
+                supersedes,
             },
         }),
     )
@@ -200,6 +217,108 @@ pub async fn remove(executor_url: String, cap_token: String, address: String) ->
     Ok(())
 }
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/languages.gql",
+    response_derives = "Debug"
+)]
+pub struct Permissions;
+
+pub async fn permissions(
+    executor_url: String,
+    cap_token: String,
+    address: String,
+) -> Result<Option<LanguagePermissions>> {
+    let response_data: permissions::ResponseData = query(
+        executor_url,
+        cap_token,
+        Permissions::build_query(permissions::Variables { address }),
+    )
+    .await
+    .with_context(|| "Failed to run languages -> permissions")?;
+    Ok(response_data.language_permissions.map(|p| p.into()))
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/languages.gql",
+    response_derives = "Debug"
+)]
+pub struct ApprovePermissions;
+
+pub async fn approve_permissions(
+    executor_url: String,
+    cap_token: String,
+    address: String,
+    permissions: LanguagePermissions,
+) -> Result<LanguagePermissions> {
+    let response_data: approve_permissions::ResponseData = query(
+        executor_url,
+        cap_token,
+        ApprovePermissions::build_query(approve_permissions::Variables {
+            address,
+            permissions: approve_permissions::LanguagePermissionsInput {
+                net: Some(permissions.net),
+                read: Some(permissions.read),
+                write: Some(permissions.write),
+                env: Some(permissions.env),
+                run: Some(permissions.run),
+            },
+        }),
+    )
+    .await
+    .with_context(|| "Failed to run languages -> approve-permissions")?;
+    Ok(response_data.language_approve_permissions.into())
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/languages.gql",
+    response_derives = "Debug"
+)]
+pub struct RevokePermissions;
+
+pub async fn revoke_permissions(
+    executor_url: String,
+    cap_token: String,
+    address: String,
+) -> Result<bool> {
+    let response_data: revoke_permissions::ResponseData = query(
+        executor_url,
+        cap_token,
+        RevokePermissions::build_query(revoke_permissions::Variables { address }),
+    )
+    .await
+    .with_context(|| "Failed to run languages -> revoke-permissions")?;
+    Ok(response_data.language_revoke_permissions)
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/languages.gql",
+    response_derives = "Debug"
+)]
+pub struct Upgrades;
+
+pub async fn upgrades(executor_url: String, cap_token: String) -> Result<Vec<LanguageUpgrade>> {
+    let response_data: upgrades::ResponseData = query(
+        executor_url,
+        cap_token,
+        Upgrades::build_query(upgrades::Variables {}),
+    )
+    .await
+    .with_context(|| "Failed to run languages -> upgrades")?;
+    Ok(response_data
+        .language_upgrades
+        .into_iter()
+        .map(|upgrade| upgrade.into())
+        .collect())
+}
+
 pub struct LanguagesClient {
     info: Arc<ClientInfo>,
 }
@@ -271,6 +390,7 @@ impl LanguagesClient {
         .await
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn publish(
         &self,
         language_path: String,
@@ -278,6 +398,9 @@ impl LanguagesClient {
         description: Option<String>,
         possible_template_params: Option<Vec<String>>,
         source_code_link: Option<String>,
+        permissions: Option<LanguagePermissions>,
+        version: Option<String>,
+        supersedes: Option<String>,
     ) -> Result<publish::PublishLanguagePublish> {
         publish(
             self.info.executor_url.clone(),
@@ -287,6 +410,9 @@ impl LanguagesClient {
             description,
             possible_template_params,
             source_code_link,
+            permissions,
+            version,
+            supersedes,
         )
         .await
     }
@@ -300,6 +426,42 @@ impl LanguagesClient {
         .await
     }
 
+    pub async fn permissions(&self, address: String) -> Result<Option<LanguagePermissions>> {
+        permissions(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            address,
+        )
+        .await
+    }
+
+    pub async fn approve_permissions(
+        &self,
+        address: String,
+        permissions: LanguagePermissions,
+    ) -> Result<LanguagePermissions> {
+        approve_permissions(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            address,
+            permissions,
+        )
+        .await
+    }
+
+    pub async fn revoke_permissions(&self, address: String) -> Result<bool> {
+        revoke_permissions(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            address,
+        )
+        .await
+    }
+
+    pub async fn upgrades(&self) -> Result<Vec<LanguageUpgrade>> {
+        upgrades(self.info.executor_url.clone(), self.info.cap_token.clone()).await
+    }
+
     pub async fn remove(&self, address: String) -> Result<()> {
         remove(
             self.info.executor_url.clone(),
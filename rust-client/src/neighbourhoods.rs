@@ -57,6 +57,33 @@ pub async fn join(
     Ok(response_data.neighbourhood_join_from_url)
 }
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/neighbourhoods.gql",
+    response_derives = "Debug"
+)]
+pub struct UpgradeLinkLanguage;
+
+pub async fn upgrade_link_language(
+    executor_url: String,
+    cap_token: String,
+    perspective_uuid: String,
+    language_address: Option<String>,
+) -> Result<upgrade_link_language::UpgradeLinkLanguageNeighbourhoodUpgradeLinkLanguage> {
+    let response_data: upgrade_link_language::ResponseData = query(
+        executor_url,
+        cap_token,
+        UpgradeLinkLanguage::build_query(upgrade_link_language::Variables {
+            perspective_uuid,
+            language_address,
+        }),
+    )
+    .await
+    .with_context(|| "Failed to run neighbourhoods->upgrade-link-language query")?;
+    Ok(response_data.neighbourhood_upgrade_link_language)
+}
+
 pub struct NeighbourhoodsClient {
     info: Arc<ClientInfo>,
 }
@@ -93,4 +120,18 @@ impl NeighbourhoodsClient {
         )
         .await
     }
+
+    pub async fn upgrade_link_language(
+        &self,
+        perspective_uuid: String,
+        language_address: Option<String>,
+    ) -> Result<upgrade_link_language::UpgradeLinkLanguageNeighbourhoodUpgradeLinkLanguage> {
+        upgrade_link_language(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            perspective_uuid,
+            language_address,
+        )
+        .await
+    }
 }
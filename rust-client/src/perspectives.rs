@@ -74,6 +74,33 @@ pub async fn remove(executor_url: String, cap_token: String, uuid: String) -> Re
     }
 }
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/perspectives.gql",
+    response_derives = "Debug"
+)]
+pub struct SyncWithDevices;
+
+pub async fn sync_with_devices(
+    executor_url: String,
+    cap_token: String,
+    uuid: String,
+    link_language: String,
+) -> Result<String> {
+    let response: sync_with_devices::ResponseData = query(
+        executor_url,
+        cap_token,
+        SyncWithDevices::build_query(sync_with_devices::Variables {
+            uuid,
+            link_language,
+        }),
+    )
+    .await
+    .with_context(|| "Failed to run perspectives->sync-with-devices query")?;
+    Ok(response.perspective_sync_with_devices)
+}
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "schema.gql",
@@ -358,6 +385,16 @@ impl PerspectivesClient {
         .await
     }
 
+    pub async fn sync_with_devices(&self, uuid: String, link_language: String) -> Result<String> {
+        sync_with_devices(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            uuid,
+            link_language,
+        )
+        .await
+    }
+
     pub async fn add_link(
         &self,
         uid: String,
@@ -1,7 +1,10 @@
 use std::sync::Arc;
 
 use crate::{
-    types::{PerspectiveExpression, SentPerspectiveMessage},
+    types::{
+        Conversation, ConversationMessage, PerspectiveExpression, SentPerspectiveMessage,
+        TrustReport, TrustStatement,
+    },
     util::query,
     ClientInfo,
 };
@@ -108,6 +111,178 @@ pub async fn trusted_agents(executor_url: String, cap_token: String) -> Result<V
     Ok(response_data.get_trusted_agents)
 }
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/runtime.gql",
+    response_derives = "Debug"
+)]
+pub struct AgentTrust;
+
+pub async fn agent_trust(
+    executor_url: String,
+    cap_token: String,
+    did: String,
+) -> Result<TrustReport> {
+    let response_data: agent_trust::ResponseData = query(
+        executor_url,
+        cap_token,
+        AgentTrust::build_query(agent_trust::Variables { did }),
+    )
+    .await
+    .with_context(|| "Failed to run runtime->agent-trust query")?;
+    Ok(response_data.runtime_agent_trust.into())
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/runtime.gql",
+    response_derives = "Debug"
+)]
+pub struct TrustStatements;
+
+pub async fn trust_statements(
+    executor_url: String,
+    cap_token: String,
+    issuer: Option<String>,
+) -> Result<Vec<TrustStatement>> {
+    let response_data: trust_statements::ResponseData = query(
+        executor_url,
+        cap_token,
+        TrustStatements::build_query(trust_statements::Variables { issuer }),
+    )
+    .await
+    .with_context(|| "Failed to run runtime->trust-statements query")?;
+    Ok(response_data
+        .runtime_trust_statements
+        .into_iter()
+        .map(|s| s.into())
+        .collect())
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/runtime.gql",
+    response_derives = "Debug"
+)]
+pub struct TrustConfig;
+
+pub async fn trust_config(
+    executor_url: String,
+    cap_token: String,
+) -> Result<trust_config::TrustConfigRuntimeTrustConfig> {
+    let response_data: trust_config::ResponseData = query(
+        executor_url,
+        cap_token,
+        TrustConfig::build_query(trust_config::Variables {}),
+    )
+    .await
+    .with_context(|| "Failed to run runtime->trust-config query")?;
+    Ok(response_data.runtime_trust_config)
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/runtime.gql",
+    response_derives = "Debug"
+)]
+pub struct TrustAgent;
+
+pub async fn trust_agent(
+    executor_url: String,
+    cap_token: String,
+    did: String,
+) -> Result<Vec<TrustStatement>> {
+    let response_data: trust_agent::ResponseData = query(
+        executor_url,
+        cap_token,
+        TrustAgent::build_query(trust_agent::Variables { did }),
+    )
+    .await
+    .with_context(|| "Failed to run runtime->trust-agent query")?;
+    Ok(response_data
+        .runtime_trust_agent
+        .into_iter()
+        .map(|s| s.into())
+        .collect())
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/runtime.gql",
+    response_derives = "Debug"
+)]
+pub struct RevokeTrust;
+
+pub async fn revoke_trust(
+    executor_url: String,
+    cap_token: String,
+    did: String,
+) -> Result<Vec<TrustStatement>> {
+    let response_data: revoke_trust::ResponseData = query(
+        executor_url,
+        cap_token,
+        RevokeTrust::build_query(revoke_trust::Variables { did }),
+    )
+    .await
+    .with_context(|| "Failed to run runtime->revoke-trust query")?;
+    Ok(response_data
+        .runtime_revoke_trust
+        .into_iter()
+        .map(|s| s.into())
+        .collect())
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/runtime.gql",
+    response_derives = "Debug"
+)]
+pub struct SetTrustConfig;
+
+pub async fn set_trust_config(
+    executor_url: String,
+    cap_token: String,
+    max_depth: i64,
+    threshold: i64,
+) -> Result<set_trust_config::SetTrustConfigRuntimeSetTrustConfig> {
+    let response_data: set_trust_config::ResponseData = query(
+        executor_url,
+        cap_token,
+        SetTrustConfig::build_query(set_trust_config::Variables {
+            max_depth,
+            threshold,
+        }),
+    )
+    .await
+    .with_context(|| "Failed to run runtime->set-trust-config query")?;
+    Ok(response_data.runtime_set_trust_config)
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/runtime.gql",
+    response_derives = "Debug"
+)]
+pub struct RefreshTrust;
+
+pub async fn refresh_trust(executor_url: String, cap_token: String) -> Result<i64> {
+    let response_data: refresh_trust::ResponseData = query(
+        executor_url,
+        cap_token,
+        RefreshTrust::build_query(refresh_trust::Variables {}),
+    )
+    .await
+    .with_context(|| "Failed to run runtime->refresh-trust query")?;
+    Ok(response_data.runtime_refresh_trust)
+}
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "schema.gql",
@@ -434,6 +609,202 @@ pub async fn message_outbox(
         .collect())
 }
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/runtime.gql",
+    response_derives = "Debug"
+)]
+pub struct Conversations;
+
+pub async fn conversations(executor_url: String, cap_token: String) -> Result<Vec<Conversation>> {
+    let response: conversations::ResponseData = query(
+        executor_url,
+        cap_token,
+        Conversations::build_query(conversations::Variables {}),
+    )
+    .await
+    .with_context(|| "Failed to run runtime->conversations query")?;
+
+    Ok(response
+        .runtime_conversations
+        .into_iter()
+        .map(|c| c.into())
+        .collect())
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/runtime.gql",
+    response_derives = "Debug"
+)]
+pub struct ConversationMessages;
+
+pub async fn conversation_messages(
+    executor_url: String,
+    cap_token: String,
+    conversation_id: String,
+    before: Option<String>,
+    limit: Option<i64>,
+) -> Result<Vec<ConversationMessage>> {
+    let response: conversation_messages::ResponseData = query(
+        executor_url,
+        cap_token,
+        ConversationMessages::build_query(conversation_messages::Variables {
+            conversation_id,
+            before,
+            limit,
+        }),
+    )
+    .await
+    .with_context(|| "Failed to run runtime->conversation-messages query")?;
+
+    Ok(response
+        .runtime_conversation_messages
+        .into_iter()
+        .map(|m| m.into())
+        .collect())
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/runtime.gql",
+    response_derives = "Debug"
+)]
+pub struct MessageThread;
+
+pub async fn message_thread(
+    executor_url: String,
+    cap_token: String,
+    message_id: String,
+) -> Result<Vec<ConversationMessage>> {
+    let response: message_thread::ResponseData = query(
+        executor_url,
+        cap_token,
+        MessageThread::build_query(message_thread::Variables { message_id }),
+    )
+    .await
+    .with_context(|| "Failed to run runtime->message-thread query")?;
+
+    Ok(response
+        .runtime_message_thread
+        .into_iter()
+        .map(|m| m.into())
+        .collect())
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/runtime.gql",
+    response_derives = "Debug"
+)]
+pub struct CreateConversation;
+
+pub async fn create_conversation(
+    executor_url: String,
+    cap_token: String,
+    name: String,
+    members: Vec<String>,
+    encrypted: Option<bool>,
+) -> Result<String> {
+    let response: create_conversation::ResponseData = query(
+        executor_url,
+        cap_token,
+        CreateConversation::build_query(create_conversation::Variables {
+            name,
+            members,
+            encrypted,
+        }),
+    )
+    .await
+    .with_context(|| "Failed to run runtime->create-conversation query")?;
+    Ok(response.runtime_create_conversation.id)
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/runtime.gql",
+    response_derives = "Debug"
+)]
+pub struct UpdateConversationMembers;
+
+pub async fn update_conversation_members(
+    executor_url: String,
+    cap_token: String,
+    conversation_id: String,
+    add: Option<Vec<String>>,
+    remove: Option<Vec<String>>,
+) -> Result<Vec<String>> {
+    let response: update_conversation_members::ResponseData = query(
+        executor_url,
+        cap_token,
+        UpdateConversationMembers::build_query(update_conversation_members::Variables {
+            conversation_id,
+            add,
+            remove,
+        }),
+    )
+    .await
+    .with_context(|| "Failed to run runtime->update-conversation-members query")?;
+    Ok(response.runtime_update_conversation_members.members)
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/runtime.gql",
+    response_derives = "Debug"
+)]
+pub struct SendConversationMessage;
+
+pub async fn send_conversation_message(
+    executor_url: String,
+    cap_token: String,
+    conversation_id: String,
+    message: send_conversation_message::PerspectiveInput,
+    reply_to: Option<String>,
+) -> Result<String> {
+    let response: send_conversation_message::ResponseData = query(
+        executor_url,
+        cap_token,
+        SendConversationMessage::build_query(send_conversation_message::Variables {
+            conversation_id,
+            message,
+            reply_to,
+        }),
+    )
+    .await
+    .with_context(|| "Failed to run runtime->send-conversation-message query")?;
+    Ok(response.runtime_send_conversation_message.id)
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.gql",
+    query_path = "src/runtime.gql",
+    response_derives = "Debug"
+)]
+pub struct MarkConversationRead;
+
+pub async fn mark_conversation_read(
+    executor_url: String,
+    cap_token: String,
+    conversation_id: String,
+) -> Result<i64> {
+    let response: mark_conversation_read::ResponseData = query(
+        executor_url,
+        cap_token,
+        MarkConversationRead::build_query(mark_conversation_read::Variables { conversation_id }),
+    )
+    .await
+    .with_context(|| "Failed to run runtime->mark-conversation-read query")?;
+    Ok(response.runtime_mark_conversation_read)
+}
+
 pub struct RuntimeClient {
     info: Arc<ClientInfo>,
 }
@@ -479,6 +850,64 @@ impl RuntimeClient {
         trusted_agents(self.info.executor_url.clone(), self.info.cap_token.clone()).await
     }
 
+    pub async fn agent_trust(&self, did: String) -> Result<TrustReport> {
+        agent_trust(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            did,
+        )
+        .await
+    }
+
+    pub async fn trust_statements(&self, issuer: Option<String>) -> Result<Vec<TrustStatement>> {
+        trust_statements(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            issuer,
+        )
+        .await
+    }
+
+    pub async fn trust_config(&self) -> Result<trust_config::TrustConfigRuntimeTrustConfig> {
+        trust_config(self.info.executor_url.clone(), self.info.cap_token.clone()).await
+    }
+
+    pub async fn trust_agent(&self, did: String) -> Result<Vec<TrustStatement>> {
+        trust_agent(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            did,
+        )
+        .await
+    }
+
+    pub async fn revoke_trust(&self, did: String) -> Result<Vec<TrustStatement>> {
+        revoke_trust(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            did,
+        )
+        .await
+    }
+
+    pub async fn set_trust_config(
+        &self,
+        max_depth: i64,
+        threshold: i64,
+    ) -> Result<set_trust_config::SetTrustConfigRuntimeSetTrustConfig> {
+        set_trust_config(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            max_depth,
+            threshold,
+        )
+        .await
+    }
+
+    pub async fn refresh_trust(&self) -> Result<i64> {
+        refresh_trust(self.info.executor_url.clone(), self.info.cap_token.clone()).await
+    }
+
     pub async fn link_language_templates(&self) -> Result<Vec<String>> {
         link_language_templates(self.info.executor_url.clone(), self.info.cap_token.clone()).await
     }
@@ -625,4 +1054,90 @@ impl RuntimeClient {
         )
         .await
     }
+
+    pub async fn conversations(&self) -> Result<Vec<Conversation>> {
+        conversations(self.info.executor_url.clone(), self.info.cap_token.clone()).await
+    }
+
+    pub async fn conversation_messages(
+        &self,
+        conversation_id: String,
+        before: Option<String>,
+        limit: Option<i64>,
+    ) -> Result<Vec<ConversationMessage>> {
+        conversation_messages(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            conversation_id,
+            before,
+            limit,
+        )
+        .await
+    }
+
+    pub async fn message_thread(&self, message_id: String) -> Result<Vec<ConversationMessage>> {
+        message_thread(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            message_id,
+        )
+        .await
+    }
+
+    pub async fn create_conversation(
+        &self,
+        name: String,
+        members: Vec<String>,
+        encrypted: Option<bool>,
+    ) -> Result<String> {
+        create_conversation(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            name,
+            members,
+            encrypted,
+        )
+        .await
+    }
+
+    pub async fn update_conversation_members(
+        &self,
+        conversation_id: String,
+        add: Option<Vec<String>>,
+        remove: Option<Vec<String>>,
+    ) -> Result<Vec<String>> {
+        update_conversation_members(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            conversation_id,
+            add,
+            remove,
+        )
+        .await
+    }
+
+    pub async fn send_conversation_message(
+        &self,
+        conversation_id: String,
+        message: send_conversation_message::PerspectiveInput,
+        reply_to: Option<String>,
+    ) -> Result<String> {
+        send_conversation_message(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            conversation_id,
+            message,
+            reply_to,
+        )
+        .await
+    }
+
+    pub async fn mark_conversation_read(&self, conversation_id: String) -> Result<i64> {
+        mark_conversation_read(
+            self.info.executor_url.clone(),
+            self.info.cap_token.clone(),
+            conversation_id,
+        )
+        .await
+    }
 }
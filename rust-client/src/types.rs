@@ -415,6 +415,262 @@ impl From<MessageOutboxRuntimeMessageOutbox> for SentPerspectiveMessage {
     }
 }
 
+use crate::runtime::agent_trust::AgentTrustRuntimeAgentTrust;
+use crate::runtime::revoke_trust::RevokeTrustRuntimeRevokeTrust;
+use crate::runtime::trust_agent::TrustAgentRuntimeTrustAgent;
+use crate::runtime::trust_statements::TrustStatementsRuntimeTrustStatements;
+
+pub struct TrustStatement {
+    pub issuer: String,
+    pub subject: String,
+    pub timestamp: String,
+    pub key: String,
+    pub signature: String,
+}
+
+impl From<TrustStatementsRuntimeTrustStatements> for TrustStatement {
+    fn from(statement: TrustStatementsRuntimeTrustStatements) -> Self {
+        Self {
+            issuer: statement.issuer,
+            subject: statement.subject,
+            timestamp: statement.timestamp,
+            key: statement.key,
+            signature: statement.signature,
+        }
+    }
+}
+
+impl From<TrustAgentRuntimeTrustAgent> for TrustStatement {
+    fn from(statement: TrustAgentRuntimeTrustAgent) -> Self {
+        Self {
+            issuer: statement.issuer,
+            subject: statement.subject,
+            timestamp: statement.timestamp,
+            key: statement.key,
+            signature: statement.signature,
+        }
+    }
+}
+
+impl From<RevokeTrustRuntimeRevokeTrust> for TrustStatement {
+    fn from(statement: RevokeTrustRuntimeRevokeTrust) -> Self {
+        Self {
+            issuer: statement.issuer,
+            subject: statement.subject,
+            timestamp: statement.timestamp,
+            key: statement.key,
+            signature: statement.signature,
+        }
+    }
+}
+
+pub struct TrustReport {
+    pub did: String,
+    pub trusted: bool,
+    pub depth: Option<i64>,
+    pub endorsers: Vec<String>,
+    pub path: Vec<String>,
+    pub reason: String,
+}
+
+impl From<AgentTrustRuntimeAgentTrust> for TrustReport {
+    fn from(report: AgentTrustRuntimeAgentTrust) -> Self {
+        Self {
+            did: report.did,
+            trusted: report.trusted,
+            depth: report.depth,
+            endorsers: report.endorsers,
+            path: report.path,
+            reason: report.reason,
+        }
+    }
+}
+
+use crate::runtime::conversations::ConversationsRuntimeConversations;
+
+pub struct Conversation {
+    pub id: String,
+    pub name: String,
+    pub created_by: String,
+    pub members: Vec<String>,
+    pub encrypted: bool,
+    pub created_at: String,
+    pub updated_at: String,
+    pub unread_count: i64,
+}
+
+impl From<ConversationsRuntimeConversations> for Conversation {
+    fn from(conversation: ConversationsRuntimeConversations) -> Self {
+        Self {
+            id: conversation.id,
+            name: conversation.name,
+            created_by: conversation.created_by,
+            members: conversation.members,
+            encrypted: conversation.encrypted,
+            created_at: conversation.created_at,
+            updated_at: conversation.updated_at,
+            unread_count: conversation.unread_count,
+        }
+    }
+}
+
+pub struct MessageReadReceipt {
+    pub reader: String,
+    pub read_at: String,
+}
+
+pub struct ConversationMessage {
+    pub id: String,
+    pub author: String,
+    pub message: Perspective,
+    pub reply_to: Option<String>,
+    pub timestamp: String,
+    pub read_at: Option<String>,
+    pub read_by: Vec<MessageReadReceipt>,
+}
+
+use crate::runtime::conversation_messages::{
+    ConversationMessagesRuntimeConversationMessages,
+    ConversationMessagesRuntimeConversationMessagesMessageLinks,
+};
+
+impl From<ConversationMessagesRuntimeConversationMessagesMessageLinks> for LinkExpression {
+    fn from(link: ConversationMessagesRuntimeConversationMessagesMessageLinks) -> Self {
+        Self {
+            author: link.author,
+            timestamp: link.timestamp,
+            data: Link {
+                predicate: link.data.predicate,
+                source: link.data.source,
+                target: link.data.target,
+            },
+            proof: ExpressionProof {
+                invalid: None,
+                key: link.proof.key,
+                signature: link.proof.signature,
+                valid: None,
+            },
+            status: link.status,
+        }
+    }
+}
+
+impl From<ConversationMessagesRuntimeConversationMessages> for ConversationMessage {
+    fn from(message: ConversationMessagesRuntimeConversationMessages) -> Self {
+        Self {
+            id: message.id,
+            author: message.author,
+            message: Perspective {
+                links: message
+                    .message
+                    .links
+                    .into_iter()
+                    .map(LinkExpression::from)
+                    .collect(),
+            },
+            reply_to: message.reply_to,
+            timestamp: message.timestamp,
+            read_at: message.read_at,
+            read_by: message
+                .read_by
+                .into_iter()
+                .map(|receipt| MessageReadReceipt {
+                    reader: receipt.reader,
+                    read_at: receipt.read_at,
+                })
+                .collect(),
+        }
+    }
+}
+
+use crate::runtime::message_thread::{
+    MessageThreadRuntimeMessageThread, MessageThreadRuntimeMessageThreadMessageLinks,
+};
+
+impl From<MessageThreadRuntimeMessageThreadMessageLinks> for LinkExpression {
+    fn from(link: MessageThreadRuntimeMessageThreadMessageLinks) -> Self {
+        Self {
+            author: link.author,
+            timestamp: link.timestamp,
+            data: Link {
+                predicate: link.data.predicate,
+                source: link.data.source,
+                target: link.data.target,
+            },
+            proof: ExpressionProof {
+                invalid: None,
+                key: link.proof.key,
+                signature: link.proof.signature,
+                valid: None,
+            },
+            status: link.status,
+        }
+    }
+}
+
+impl From<MessageThreadRuntimeMessageThread> for ConversationMessage {
+    fn from(message: MessageThreadRuntimeMessageThread) -> Self {
+        Self {
+            id: message.id,
+            author: message.author,
+            message: Perspective {
+                links: message
+                    .message
+                    .links
+                    .into_iter()
+                    .map(LinkExpression::from)
+                    .collect(),
+            },
+            reply_to: message.reply_to,
+            timestamp: message.timestamp,
+            read_at: message.read_at,
+            read_by: message
+                .read_by
+                .into_iter()
+                .map(|receipt| MessageReadReceipt {
+                    reader: receipt.reader,
+                    read_at: receipt.read_at,
+                })
+                .collect(),
+        }
+    }
+}
+
+use crate::runtime::send_conversation_message;
+
+impl From<LinkExpression> for send_conversation_message::LinkExpressionInput {
+    fn from(link: LinkExpression) -> Self {
+        Self {
+            author: link.author,
+            timestamp: link.timestamp,
+            data: send_conversation_message::LinkInput {
+                predicate: link.data.predicate,
+                source: link.data.source,
+                target: link.data.target,
+            },
+            proof: send_conversation_message::ExpressionProofInput {
+                key: link.proof.key,
+                signature: link.proof.signature,
+                invalid: link.proof.invalid,
+                valid: link.proof.valid,
+            },
+            status: link.status,
+        }
+    }
+}
+
+impl From<Perspective> for send_conversation_message::PerspectiveInput {
+    fn from(perspective: Perspective) -> Self {
+        Self {
+            links: perspective
+                .links
+                .into_iter()
+                .map(send_conversation_message::LinkExpressionInput::from)
+                .collect(),
+        }
+    }
+}
+
 pub struct Agent {
     pub did: String,
     pub direct_message_language: Option<String>,
@@ -464,3 +720,84 @@ pub struct NeighbourhoodExpression {
     pub proof: ExpressionProof,
     pub timestamp: String,
 }
+
+use crate::languages::approve_permissions::ApprovePermissionsLanguageApprovePermissions;
+use crate::languages::permissions::PermissionsLanguagePermissions;
+use crate::languages::upgrades::UpgradesLanguageUpgrades;
+
+/// What a language may do beyond its own storage directory
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LanguagePermissions {
+    #[serde(default)]
+    pub net: Vec<String>,
+    #[serde(default)]
+    pub read: Vec<String>,
+    #[serde(default)]
+    pub write: Vec<String>,
+    #[serde(default)]
+    pub env: Vec<String>,
+    #[serde(default)]
+    pub run: Vec<String>,
+}
+
+impl LanguagePermissions {
+    pub fn is_empty(&self) -> bool {
+        self.net.is_empty()
+            && self.read.is_empty()
+            && self.write.is_empty()
+            && self.env.is_empty()
+            && self.run.is_empty()
+    }
+}
+
+impl From<PermissionsLanguagePermissions> for LanguagePermissions {
+    fn from(permissions: PermissionsLanguagePermissions) -> Self {
+        Self {
+            net: permissions.net,
+            read: permissions.read,
+            write: permissions.write,
+            env: permissions.env,
+            run: permissions.run,
+        }
+    }
+}
+
+impl From<ApprovePermissionsLanguageApprovePermissions> for LanguagePermissions {
+    fn from(permissions: ApprovePermissionsLanguageApprovePermissions) -> Self {
+        Self {
+            net: permissions.net,
+            read: permissions.read,
+            write: permissions.write,
+            env: permissions.env,
+            run: permissions.run,
+        }
+    }
+}
+
+/// A newer version of an installed language, signed by the same author
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LanguageUpgrade {
+    pub address: String,
+    pub name: String,
+    pub version: Option<String>,
+    pub upgrade_address: String,
+    pub upgrade_version: Option<String>,
+    /// Set if the language is the link language of this perspective's neighbourhood
+    pub perspective_uuid: Option<String>,
+    /// Where another member already moved that neighbourhood to
+    pub neighbourhood_url: Option<String>,
+}
+
+impl From<UpgradesLanguageUpgrades> for LanguageUpgrade {
+    fn from(upgrade: UpgradesLanguageUpgrades) -> Self {
+        Self {
+            address: upgrade.address,
+            name: upgrade.name,
+            version: upgrade.version,
+            upgrade_address: upgrade.upgrade_address,
+            upgrade_version: upgrade.upgrade_version,
+            perspective_uuid: upgrade.perspective_uuid,
+            neighbourhood_url: upgrade.neighbourhood_url,
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use serde::{Deserialize, Serialize};
+
+use super::capabilities::apps_map::{seal_auth_info, App, StoredApp};
+use super::AgentStore;
+use crate::db::Ad4mDb;
+use crate::graphql::graphql_types::KeySuccession;
+use crate::wallet::{decrypt, encrypt};
+
+pub const BACKUP_VERSION: u32 = 1;
+
+/// Everything needed to bring an agent back on a fresh executor:
+/// the (still encrypted) keystore together with the agent's local
+/// relationships that are not published anywhere.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentBackup {
+    pub version: u32,
+    pub created_at: String,
+    pub agent_store: AgentStore,
+    pub apps: Vec<App>,
+    pub trusted_agents: Vec<String>,
+    pub friends: Vec<String>,
+    #[serde(default)]
+    pub key_successions: Vec<KeySuccession>,
+}
+
+impl AgentBackup {
+    /// Collects apps, trusted agents, friends and key history from Ad4mDb
+    pub fn from_db(agent_store: AgentStore) -> Result<AgentBackup, AnyError> {
+        // Opened outside of the database lock, this needs the wallet
+        let apps = Ad4mDb::with_global_instance(|db| db.get_apps())?
+            .into_iter()
+            .map(StoredApp::open)
+            .collect::<Result<Vec<App>, AnyError>>()?;
+        Ad4mDb::with_global_instance(|db| {
+            Ok(AgentBackup {
+                version: BACKUP_VERSION,
+                created_at: chrono::Utc::now().to_rfc3339(),
+                agent_store,
+                apps,
+                trusted_agents: db.get_all_trusted_agents()?,
+                friends: db.get_all_friends()?,
+                key_successions: db.get_key_successions()?,
+            })
+        })
+    }
+
+    /// Expects the wallet to be unlocked with the restored keys, apps get sealed to them
+    pub fn restore_db(&self) -> Result<(), AnyError> {
+        let sealed_auth_infos = self
+            .apps
+            .iter()
+            .map(|app| seal_auth_info(&app.auth_info_extended))
+            .collect::<Result<Vec<String>, AnyError>>()?;
+        Ad4mDb::with_global_instance(|db| {
+            for (app, auth_info) in self.apps.iter().zip(sealed_auth_infos.iter()) {
+                db.add_app(&app.request_id, auth_info, &app.token_hash, app.revoked)?;
+            }
+            db.add_trusted_agents(self.trusted_agents.clone())?;
+            db.add_friends(self.friends.clone())?;
+            for succession in self.key_successions.iter() {
+                db.add_key_succession(succession)?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn encrypt(&self, passphrase: String) -> Result<String, AnyError> {
+        Ok(encrypt(serde_json::to_string(self)?, passphrase))
+    }
+
+    pub fn decrypt(backup: String, passphrase: String) -> Result<AgentBackup, AnyError> {
+        let json = decrypt(backup.trim().to_string(), passphrase)
+            .map_err(|_| anyhow!("Could not decrypt backup, wrong passphrase?"))?;
+        let backup: AgentBackup = serde_json::from_str(&json)?;
+        if backup.version > BACKUP_VERSION {
+            return Err(anyhow!(
+                "Backup version {} is newer than supported version {}",
+                backup.version,
+                BACKUP_VERSION
+            ));
+        }
+        Ok(backup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::signatures::{hash_message, verify_string_signed_by_did};
+    use crate::wallet::{Wallet, MAIN_KEY_NAME};
+
+    fn backup_of(wallet: &mut Wallet, passphrase: &str) -> AgentBackup {
+        let main = MAIN_KEY_NAME.to_string();
+        let did_document = wallet.get_did_document(&main).unwrap();
+        AgentBackup {
+            version: BACKUP_VERSION,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            agent_store: AgentStore {
+                did: did_document.id.clone(),
+                did_document: serde_json::to_string(&did_document).unwrap(),
+                signing_key_id: did_document.verification_method[0].id.clone(),
+                keystore: wallet.export(passphrase.to_string()),
+                agent: None,
+            },
+            apps: vec![],
+            trusted_agents: vec!["did:key:trusted".to_string()],
+            friends: vec!["did:key:friend".to_string()],
+            key_successions: vec![],
+        }
+    }
+
+    #[test]
+    fn backup_roundtrip_recovers_did_and_signing_key() {
+        let passphrase = "backup passphrase";
+        let main = MAIN_KEY_NAME.to_string();
+        let mut wallet = Wallet::new();
+        wallet.generate_keypair(main.clone());
+        let did = wallet.get_did_document(&main).unwrap().id;
+
+        let encrypted = backup_of(&mut wallet, passphrase)
+            .encrypt(passphrase.to_string())
+            .unwrap();
+        assert!(!encrypted.contains("did:key"));
+
+        let restored = AgentBackup::decrypt(encrypted, passphrase.to_string()).unwrap();
+        assert_eq!(restored.agent_store.did, did);
+        assert_eq!(restored.trusted_agents, vec!["did:key:trusted".to_string()]);
+        assert_eq!(restored.friends, vec!["did:key:friend".to_string()]);
+
+        let mut restored_wallet = Wallet::new();
+        restored_wallet.load(restored.agent_store.keystore);
+        restored_wallet.unlock(passphrase.to_string()).unwrap();
+        assert_eq!(restored_wallet.get_did_document(&main).unwrap().id, did);
+
+        let message = "signed after restore";
+        let signature = hex::encode(
+            restored_wallet
+                .sign(&main, &hash_message(&message.to_string()))
+                .unwrap(),
+        );
+        assert!(verify_string_signed_by_did(&did, message, &signature).unwrap());
+    }
+
+    #[test]
+    fn backup_needs_the_right_passphrase() {
+        let mut wallet = Wallet::new();
+        wallet.generate_keypair(MAIN_KEY_NAME.to_string());
+        let encrypted = backup_of(&mut wallet, "right")
+            .encrypt("right".to_string())
+            .unwrap();
+        assert!(AgentBackup::decrypt(encrypted, "wrong".to_string()).is_err());
+    }
+}
@@ -1,69 +1,206 @@
 use super::types::AuthInfoExtended;
+use crate::agent::retired_key_name;
+use crate::db::Ad4mDb;
+use crate::wallet::{Wallet, MAIN_KEY_NAME};
+use base64::Engine;
+use crypto_box::aead::Aead;
+use crypto_box::{Nonce, SalsaBox, SecretKey};
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use log::{info, warn};
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::{self, Read};
+use std::fs;
 use std::path::Path;
-use std::sync::Mutex;
 
+const AUTH_INFO_KEY_CONTEXT: &[u8] = b"ad4m app auth info encryption key";
+
+/// An app that has been issued a capability token.
+/// Only the SHA-256 hash of the token is kept, so the stored record
+/// can be used to check revocation but not to impersonate the app.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct App {
-    auth_info_extended: AuthInfoExtended,
-    revoked: bool,
-    token: String,
+    pub request_id: String,
+    pub auth_info_extended: AuthInfoExtended,
+    pub token_hash: String,
+    pub revoked: bool,
 }
 
-impl App {
-    pub fn new(auth_info_extended: AuthInfoExtended, revoked: bool, token: String) -> Self {
-        App {
-            auth_info_extended,
-            revoked,
-            token,
-        }
+/// An app as it is kept in Ad4mDb. `auth_info` is sealed with a key derived from
+/// the agent's signing key. Only apps imported from the legacy apps file are plain JSON,
+/// until the agent gets unlocked.
+#[derive(Clone, Debug)]
+pub struct StoredApp {
+    pub request_id: String,
+    pub auth_info: String,
+    pub token_hash: String,
+    pub revoked: bool,
+}
+
+impl StoredApp {
+    pub fn open(self) -> Result<App, AnyError> {
+        Ok(App {
+            auth_info_extended: open_auth_info(&self.auth_info)?,
+            request_id: self.request_id,
+            token_hash: self.token_hash,
+            revoked: self.revoked,
+        })
     }
 }
 
-use std::env;
+#[derive(Serialize, Deserialize)]
+struct SealedAuthInfo {
+    nonce: String,
+    ciphertext: String,
+}
 
-lazy_static! {
-    static ref DATA_FILE_PATH: Mutex<String> =
-        Mutex::new(env::var("APPS_DATA_FILE").unwrap_or_else(|_| "apps_data.json".to_string()));
+fn auth_info_box(signing_secret: &[u8]) -> SalsaBox {
+    let mut hasher = Sha256::new();
+    hasher.update(AUTH_INFO_KEY_CONTEXT);
+    hasher.update(signing_secret);
+    let bytes: [u8; 32] = hasher.finalize().into();
+    let secret = SecretKey::from(bytes);
+    SalsaBox::new(&secret.public_key(), &secret)
 }
 
-fn get_data_file_path() -> String {
-    DATA_FILE_PATH.lock().unwrap().clone()
+/// Secret keys of the main key and all retired keys, so auth info sealed
+/// before a key rotation can still be opened. Empty while the agent is locked.
+pub(crate) fn agent_signing_secrets() -> Vec<Vec<u8>> {
+    let wallet_instance = Wallet::instance();
+    let wallet = wallet_instance.lock().expect("wallet lock");
+    let wallet_ref = match wallet.as_ref() {
+        Some(wallet) => wallet,
+        None => return vec![],
+    };
+    let retired_prefix = retired_key_name("");
+    let mut names = vec![MAIN_KEY_NAME.to_string()];
+    names.extend(
+        wallet_ref
+            .key_names()
+            .into_iter()
+            .filter(|name| name.starts_with(&retired_prefix)),
+    );
+    names
+        .iter()
+        .filter_map(|name| wallet_ref.get_secret_key(name))
+        .collect()
 }
 
-pub fn set_data_file_path(file_path: String) {
-    let mut data_file_path = DATA_FILE_PATH.lock().unwrap();
-    *data_file_path = file_path;
+fn seal_with(auth_info: &AuthInfoExtended, signing_secret: &[u8]) -> Result<String, AnyError> {
+    let mut nonce = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = auth_info_box(signing_secret)
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            serde_json::to_vec(auth_info)?.as_slice(),
+        )
+        .map_err(|_| anyhow!("Failed to encrypt app auth info"))?;
+    Ok(serde_json::to_string(&SealedAuthInfo {
+        nonce: hex::encode(nonce),
+        ciphertext: base64::engine::general_purpose::STANDARD_NO_PAD.encode(ciphertext),
+    })?)
 }
 
-fn persist_apps_to_file(apps: &HashMap<String, App>) -> io::Result<()> {
-    let file_path = get_data_file_path();
-    let serialized_apps = serde_json::to_string(apps)?;
-    fs::write(file_path, serialized_apps)?;
+fn open_with(stored: &str, signing_secrets: &[Vec<u8>]) -> Result<AuthInfoExtended, AnyError> {
+    // Plaintext is only accepted by the legacy migration in seal_plaintext_apps
+    let sealed: SealedAuthInfo =
+        serde_json::from_str(stored).map_err(|_| anyhow!("App auth info is not sealed"))?;
+    let nonce = hex::decode(&sealed.nonce)?;
+    if nonce.len() != 24 {
+        return Err(anyhow!("Invalid app auth info nonce"));
+    }
+    let ciphertext = base64::engine::general_purpose::STANDARD_NO_PAD.decode(&sealed.ciphertext)?;
+    let plaintext = signing_secrets
+        .iter()
+        .find_map(|secret| {
+            auth_info_box(secret)
+                .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+                .ok()
+        })
+        .ok_or(anyhow!(
+            "Couldn't decrypt app auth info, is the agent unlocked?"
+        ))?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+/// Encrypts auth info with a key derived from the agent's main key
+pub fn seal_auth_info(auth_info: &AuthInfoExtended) -> Result<String, AnyError> {
+    let secrets = agent_signing_secrets();
+    let main_secret = secrets
+        .first()
+        .ok_or(anyhow!("Agent is locked, can't encrypt app auth info"))?;
+    seal_with(auth_info, main_secret)
+}
+
+pub fn open_auth_info(stored: &str) -> Result<AuthInfoExtended, AnyError> {
+    open_with(stored, &agent_signing_secrets())
+}
+
+/// Encrypts the auth info of apps imported from the legacy apps file, which happens
+/// while the agent is locked. Called once the agent is unlocked, this is the only place
+/// plaintext auth info is read.
+pub fn seal_plaintext_apps() -> Result<(), AnyError> {
+    let apps = Ad4mDb::with_global_instance(|db| db.get_legacy_apps())?;
+    for app in apps {
+        let auth_info: AuthInfoExtended = serde_json::from_str(&app.auth_info)?;
+        let sealed = seal_auth_info(&auth_info)?;
+        Ad4mDb::with_global_instance(|db| db.update_app_auth_info(&app.request_id, &sealed))?;
+    }
     Ok(())
 }
 
-fn load_apps_from_file() -> io::Result<HashMap<String, App>> {
-    let file_path = get_data_file_path();
-    let mut file = File::open(file_path)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    let apps: HashMap<String, App> = serde_json::from_str(&contents)?;
-    Ok(apps)
+/// Shape of the entries in the legacy `apps_data.json` file
+#[derive(Deserialize)]
+struct LegacyApp {
+    auth_info_extended: AuthInfoExtended,
+    revoked: bool,
+    token: String,
 }
 
-lazy_static! {
-    static ref APPS: Mutex<HashMap<String, App>> = {
-        let apps = if Path::new(&get_data_file_path()).exists() {
-            load_apps_from_file().unwrap_or_else(|_| HashMap::new())
-        } else {
-            HashMap::new()
-        };
-        Mutex::new(apps)
-    };
+pub fn hash_token(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Imports apps from the legacy plaintext `apps_data.json` file into Ad4mDb,
+/// hashing their tokens on the way, and deletes the file afterwards.
+/// This runs before the agent is unlocked, so the auth info gets sealed on unlock.
+pub fn migrate_apps_from_file(file_path: &Path) -> Result<(), String> {
+    if !file_path.exists() {
+        return Ok(());
+    }
+
+    info!("Migrating apps from {} into Ad4mDb...", file_path.display());
+
+    let contents = fs::read_to_string(file_path).map_err(|e| e.to_string())?;
+    let legacy_apps: HashMap<String, LegacyApp> =
+        serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+
+    Ad4mDb::with_global_instance(|db| {
+        for (request_id, app) in legacy_apps.iter() {
+            db.add_legacy_app(
+                request_id,
+                &serde_json::to_string(&app.auth_info_extended)?,
+                &hash_token(&app.token),
+                app.revoked,
+            )?;
+        }
+        Ok::<(), deno_core::error::AnyError>(())
+    })
+    .map_err(|e| e.to_string())?;
+
+    if let Err(e) = fs::remove_file(file_path) {
+        warn!(
+            "Migrated apps but could not remove legacy file {}: {}",
+            file_path.display(),
+            e
+        );
+    }
+
+    Ok(())
 }
 
 pub fn insert_app(
@@ -71,25 +208,20 @@ pub fn insert_app(
     auth_info_extended: AuthInfoExtended,
     token: String,
 ) -> Result<(), String> {
-    let mut apps = APPS.lock().map_err(|e| e.to_string())?;
-    apps.insert(request_key, App::new(auth_info_extended, false, token));
-    persist_apps_to_file(&apps).map_err(|e| e.to_string())?;
-    Ok(())
+    let auth_info = seal_auth_info(&auth_info_extended).map_err(|e| e.to_string())?;
+    Ad4mDb::with_global_instance(|db| {
+        db.add_app(&request_key, &auth_info, &hash_token(&token), false)
+    })
+    .map_err(|e| e.to_string())
 }
 
 pub fn revoke_app(request_key: &str) -> Result<(), String> {
-    let mut apps = APPS.lock().map_err(|e| e.to_string())?;
-    if let Some(app) = apps.get_mut(request_key) {
-        app.revoked = true;
-        persist_apps_to_file(&apps).map_err(|e| e.to_string())?;
-    }
+    Ad4mDb::with_global_instance(|db| db.revoke_app(request_key)).map_err(|e| e.to_string())?;
     Ok(())
 }
 
 pub fn remove_app(request_key: &str) -> Result<(), String> {
-    let mut apps = APPS.lock().map_err(|e| e.to_string())?;
-    if apps.remove(request_key).is_some() {
-        persist_apps_to_file(&apps).map_err(|e| e.to_string())?;
+    if Ad4mDb::with_global_instance(|db| db.remove_app(request_key)).map_err(|e| e.to_string())? {
         Ok(())
     } else {
         Err(format!("App with request_key '{}' not found.", request_key))
@@ -97,18 +229,90 @@ pub fn remove_app(request_key: &str) -> Result<(), String> {
 }
 
 pub fn get_app(request_key: &str) -> Result<Option<App>, String> {
-    let apps = APPS.lock().map_err(|e| e.to_string())?;
-    Ok(apps.get(request_key).cloned())
+    Ad4mDb::with_global_instance(|db| db.get_app(request_key))
+        .and_then(|app| app.map(StoredApp::open).transpose())
+        .map_err(|e| e.to_string())
+}
+
+/// Looks up an app without opening its auth info, which works while the agent is locked
+pub fn get_app_by_token(token: &str) -> Result<Option<StoredApp>, String> {
+    Ad4mDb::with_global_instance(|db| db.get_app_by_token_hash(&hash_token(token)))
+        .map_err(|e| e.to_string())
 }
 
 pub fn get_apps() -> Vec<crate::graphql::graphql_types::Apps> {
-    let apps = APPS.lock().unwrap();
-    apps.iter()
-        .map(|(request_id, app)| crate::graphql::graphql_types::Apps {
-            auth: app.auth_info_extended.auth.clone(),
-            request_id: request_id.clone(),
-            revoked: Some(app.revoked),
-            token: app.token.clone(),
+    Ad4mDb::with_global_instance(|db| db.get_apps())
+        .map_err(|e| e.to_string())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|app| {
+            // Apps stay listed, so they can be revoked or removed even if we can't read them
+            let auth = match open_auth_info(&app.auth_info) {
+                Ok(auth_info) => auth_info.auth,
+                Err(e) => {
+                    warn!("Couldn't open auth info of app {}: {}", app.request_id, e);
+                    Default::default()
+                }
+            };
+            crate::graphql::graphql_types::Apps {
+                auth,
+                request_id: app.request_id,
+                revoked: Some(app.revoked),
+                token: String::new(),
+                token_hash: app.token_hash,
+            }
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_sealed(stored: &str) -> bool {
+        serde_json::from_str::<SealedAuthInfo>(stored).is_ok()
+    }
+
+    #[test]
+    fn hash_token_is_stable_and_does_not_leak_the_token() {
+        let token = "header.payload.signature";
+        assert_eq!(hash_token(token), hash_token(token));
+        assert_ne!(hash_token(token), token);
+        assert_ne!(hash_token(token), hash_token("other.token.value"));
+        assert_eq!(hash_token(token).len(), 64);
+    }
+
+    fn auth_info() -> AuthInfoExtended {
+        AuthInfoExtended {
+            request_id: "request-id".to_string(),
+            auth: crate::agent::capabilities::AuthInfo {
+                app_name: "Test App".to_string(),
+                app_desc: "Test App Description".to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn auth_info_is_sealed_to_the_agent_key() {
+        let main_secret = vec![1u8; 32];
+        let retired_secret = vec![2u8; 32];
+
+        let sealed = seal_with(&auth_info(), &main_secret).unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.contains("Test App"));
+
+        let opened = open_with(&sealed, &[retired_secret.clone(), main_secret]).unwrap();
+        assert_eq!(opened.auth.app_name, "Test App");
+        assert!(open_with(&sealed, &[retired_secret]).is_err());
+        assert!(open_with(&sealed, &[]).is_err());
+    }
+
+    #[test]
+    fn plaintext_auth_info_does_not_open() {
+        let plaintext = serde_json::to_string(&auth_info()).unwrap();
+        assert!(!is_sealed(&plaintext));
+        assert!(open_with(&plaintext, &[]).is_err());
+        assert!(open_with(&plaintext, &[vec![1u8; 32]]).is_err());
+    }
+}
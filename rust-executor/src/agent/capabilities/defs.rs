@@ -12,6 +12,7 @@ pub const TRANSCRIBE: &str = "TRANSCRIBE";
 
 // capabilities domains
 pub const AGENT: &str = "agent";
+pub const AGENT_ENTANGLEMENT_PROOFS: &str = "agent.entanglement_proofs";
 pub const EXPRESSION: &str = "expression";
 pub const LANGUAGE: &str = "language";
 pub const PERSPECTIVE: &str = "perspective";
@@ -21,6 +22,7 @@ pub const RUNTIME_TRUSTED_AGENTS: &str = "runtime.trusted_agents";
 pub const RUNTIME_KNOWN_LINK_LANGUAGES: &str = "runtime.known_link_languages";
 pub const RUNTIME_FRIENDS: &str = "runtime.friends";
 pub const RUNTIME_MESSAGES: &str = "runtime.messages";
+pub const RUNTIME_JOBS: &str = "runtime.jobs";
 pub const AI: &str = "artificial intelligence";
 
 // admin capabilities
@@ -97,6 +99,30 @@ lazy_static! {
         can: vec!["SIGN".to_string()],
     };
 
+    pub static ref AGENT_ENTANGLEMENT_PROOFS_READ_CAPABILITY: Capability = Capability {
+        with: Resource {
+            domain: AGENT_ENTANGLEMENT_PROOFS.to_string(),
+            pointers: vec![WILD_CARD.to_string()],
+        },
+        can: vec![READ.to_string()],
+    };
+
+    pub static ref AGENT_ENTANGLEMENT_PROOFS_CREATE_CAPABILITY: Capability = Capability {
+        with: Resource {
+            domain: AGENT_ENTANGLEMENT_PROOFS.to_string(),
+            pointers: vec![WILD_CARD.to_string()],
+        },
+        can: vec![CREATE.to_string()],
+    };
+
+    pub static ref AGENT_ENTANGLEMENT_PROOFS_DELETE_CAPABILITY: Capability = Capability {
+        with: Resource {
+            domain: AGENT_ENTANGLEMENT_PROOFS.to_string(),
+            pointers: vec![WILD_CARD.to_string()],
+        },
+        can: vec![DELETE.to_string()],
+    };
+
     // expression related capabilities
     pub static ref EXPRESSION_READ_CAPABILITY: Capability = Capability {
         with: Resource {
@@ -429,4 +455,36 @@ lazy_static! {
         },
         can: vec![SUBSCRIBE.to_string()],
     };
+
+    pub static ref RUNTIME_JOBS_READ_CAPABILITY: Capability = Capability {
+        with: Resource {
+            domain: RUNTIME_JOBS.to_string(),
+            pointers: vec![WILD_CARD.to_string()],
+        },
+        can: vec![READ.to_string()],
+    };
+
+    pub static ref RUNTIME_JOBS_CREATE_CAPABILITY: Capability = Capability {
+        with: Resource {
+            domain: RUNTIME_JOBS.to_string(),
+            pointers: vec![WILD_CARD.to_string()],
+        },
+        can: vec![CREATE.to_string()],
+    };
+
+    pub static ref RUNTIME_JOBS_UPDATE_CAPABILITY: Capability = Capability {
+        with: Resource {
+            domain: RUNTIME_JOBS.to_string(),
+            pointers: vec![WILD_CARD.to_string()],
+        },
+        can: vec![UPDATE.to_string()],
+    };
+
+    pub static ref RUNTIME_JOBS_DELETE_CAPABILITY: Capability = Capability {
+        with: Resource {
+            domain: RUNTIME_JOBS.to_string(),
+            pointers: vec![WILD_CARD.to_string()],
+        },
+        can: vec![DELETE.to_string()],
+    };
 }
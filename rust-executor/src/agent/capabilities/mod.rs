@@ -1,5 +1,6 @@
 pub mod apps_map;
 pub mod defs;
+pub mod rate_limits;
 pub mod requests_map;
 pub mod token;
 pub mod types;
@@ -57,8 +58,8 @@ pub fn check_capability(
 }
 
 pub fn check_token_revoked(token: &String) -> Result<(), String> {
-    if let Some(app) = apps_map::get_apps().iter().find(|app| app.token == *token) {
-        if app.revoked.unwrap_or(false) {
+    if let Some(app) = apps_map::get_app_by_token(token)? {
+        if app.revoked {
             return Err("Unauthorized access".to_string());
         }
     };
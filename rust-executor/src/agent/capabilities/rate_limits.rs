@@ -0,0 +1,442 @@
+use coasys_juniper::{graphql_value, FieldError, GraphQLEnum, GraphQLObject};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::apps_map::hash_token;
+
+/// Operations that can be throttled per app token.
+/// `Request` counts every GraphQL request (and every websocket handshake),
+/// the others count the units consumed by the respective resolvers
+/// (e.g. the number of links added or prompts run).
+#[derive(GraphQLEnum, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RateLimitedOperation {
+    Request,
+    PerspectiveAddLinks,
+    AiPrompt,
+    AiEmbed,
+    AiTranscribe,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitConfig {
+    pub operation: RateLimitedOperation,
+    /// Maximum number of units allowed per window
+    pub limit: u32,
+    pub window_seconds: u64,
+}
+
+/// Parses `<operation>=<limit>/<window_seconds>`, e.g. `aiPrompt=20/3600`,
+/// as used by the `--rate-limit` command line argument
+impl std::str::FromStr for RateLimitConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let format_error = || {
+            format!(
+                "Invalid rate limit '{}', expected <operation>=<limit>/<window_seconds>",
+                s
+            )
+        };
+        let (operation, rest) = s.split_once('=').ok_or_else(format_error)?;
+        let (limit, window_seconds) = rest.split_once('/').ok_or_else(format_error)?;
+        let operation: RateLimitedOperation =
+            serde_json::from_value(serde_json::Value::String(operation.trim().to_string()))
+                .map_err(|_| format!("Unknown rate limited operation '{}'", operation))?;
+        Ok(RateLimitConfig {
+            operation,
+            limit: limit.trim().parse().map_err(|_| format_error())?,
+            window_seconds: window_seconds.trim().parse().map_err(|_| format_error())?,
+        })
+    }
+}
+
+#[derive(GraphQLObject, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitUsage {
+    pub operation: RateLimitedOperation,
+    pub limit: i32,
+    pub used: i32,
+    pub window_seconds: i32,
+    pub resets_in_seconds: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitExceeded {
+    pub operation: RateLimitedOperation,
+    pub limit: u32,
+    pub window_seconds: u64,
+    pub retry_after: Duration,
+}
+
+impl std::fmt::Display for RateLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Rate limit exceeded for {:?}: {} per {}s, retry in {}s",
+            self.operation,
+            self.limit,
+            self.window_seconds,
+            self.retry_after.as_secs()
+        )
+    }
+}
+
+impl warp::reject::Reject for RateLimitExceeded {}
+
+impl From<RateLimitExceeded> for FieldError {
+    fn from(e: RateLimitExceeded) -> Self {
+        let operation = format!("{:?}", e.operation);
+        let retry_after = e.retry_after.as_secs() as i32;
+        FieldError::new(
+            e.to_string(),
+            graphql_value!({
+                "type": "RATE_LIMIT_EXCEEDED",
+                "operation": operation,
+                "retryAfterSeconds": retry_after,
+            }),
+        )
+    }
+}
+
+struct Window {
+    started: Instant,
+    used: u32,
+}
+
+/// How often expired windows are dropped from the limiter
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Fixed-window counters per (key, operation), see [`rate_limit_key`].
+/// Expired windows are pruned so that keys which are no longer used
+/// don't accumulate.
+pub struct RateLimiter {
+    limits: HashMap<RateLimitedOperation, RateLimitConfig>,
+    windows: HashMap<(String, RateLimitedOperation), Window>,
+    last_pruned: Instant,
+}
+
+lazy_static! {
+    static ref RATE_LIMITER: Arc<Mutex<Option<RateLimiter>>> = Arc::new(Mutex::new(None));
+}
+
+impl RateLimiter {
+    pub fn new(limits: Vec<RateLimitConfig>) -> Self {
+        RateLimiter {
+            limits: limits
+                .into_iter()
+                .map(|limit| (limit.operation, limit))
+                .collect(),
+            windows: HashMap::new(),
+            last_pruned: Instant::now(),
+        }
+    }
+
+    pub fn init_global_instance(limits: Vec<RateLimitConfig>) {
+        let mut instance = RATE_LIMITER.lock().unwrap();
+        *instance = Some(RateLimiter::new(limits));
+    }
+
+    pub fn with_global_instance<F, R>(func: F) -> Option<R>
+    where
+        F: FnOnce(&mut RateLimiter) -> R,
+    {
+        let mut instance = RATE_LIMITER
+            .lock()
+            .expect("Couldn't get lock on RateLimiter");
+        instance.as_mut().map(func)
+    }
+
+    /// Consumes `amount` units of `operation` for the given key, or returns
+    /// the limit that would be exceeded without consuming anything.
+    pub fn consume(
+        &mut self,
+        key: &str,
+        operation: RateLimitedOperation,
+        amount: u32,
+    ) -> Result<(), RateLimitExceeded> {
+        let limit = match self.limits.get(&operation) {
+            Some(limit) => limit.clone(),
+            None => return Ok(()),
+        };
+        let window_length = Duration::from_secs(limit.window_seconds);
+        let now = Instant::now();
+
+        if now.duration_since(self.last_pruned) >= PRUNE_INTERVAL {
+            self.prune(now);
+        }
+
+        let window = self
+            .windows
+            .entry((key.to_string(), operation))
+            .or_insert(Window {
+                started: now,
+                used: 0,
+            });
+
+        if now.duration_since(window.started) >= window_length {
+            window.started = now;
+            window.used = 0;
+        }
+
+        if window.used.saturating_add(amount) > limit.limit {
+            return Err(RateLimitExceeded {
+                operation,
+                limit: limit.limit,
+                window_seconds: limit.window_seconds,
+                retry_after: window_length.saturating_sub(now.duration_since(window.started)),
+            });
+        }
+
+        window.used += amount;
+        Ok(())
+    }
+
+    /// Drops all windows that have expired at `now`
+    fn prune(&mut self, now: Instant) {
+        let limits = &self.limits;
+        self.windows
+            .retain(|(_, operation), window| match limits.get(operation) {
+                Some(limit) => {
+                    now.duration_since(window.started) < Duration::from_secs(limit.window_seconds)
+                }
+                None => false,
+            });
+        self.last_pruned = now;
+    }
+
+    pub fn usage(&self, key: &str) -> Vec<RateLimitUsage> {
+        let now = Instant::now();
+        let mut usage: Vec<RateLimitUsage> = self
+            .limits
+            .values()
+            .map(|limit| {
+                let window_length = Duration::from_secs(limit.window_seconds);
+                let (used, resets_in) = match self.windows.get(&(key.to_string(), limit.operation))
+                {
+                    Some(window) if now.duration_since(window.started) < window_length => (
+                        window.used,
+                        window_length - now.duration_since(window.started),
+                    ),
+                    _ => (0, Duration::ZERO),
+                };
+                RateLimitUsage {
+                    operation: limit.operation,
+                    limit: limit.limit as i32,
+                    used: used as i32,
+                    window_seconds: limit.window_seconds as i32,
+                    resets_in_seconds: resets_in.as_secs() as i32,
+                }
+            })
+            .collect();
+        usage.sort_by_key(|u| format!("{:?}", u.operation));
+        usage
+    }
+}
+
+/// Returns the key under which requests made with `token` are throttled,
+/// or `None` if the token is the admin credential and thus not limited.
+///
+/// `capabilities` is the result of validating `token`. Only valid app tokens
+/// get a window of their own (keyed by the token hash), unauthenticated
+/// requests and requests with invalid tokens are throttled per client
+/// address so that made up tokens can't be used to get fresh windows.
+pub fn rate_limit_key<T, E>(
+    token: &str,
+    capabilities: &Result<T, E>,
+    admin_credential: &Option<String>,
+    remote: Option<SocketAddr>,
+) -> Option<String> {
+    match admin_credential {
+        Some(admin_credential) if token == admin_credential => return None,
+        None if token.is_empty() => return None,
+        _ => {}
+    }
+
+    if !token.is_empty() && capabilities.is_ok() {
+        return Some(format!("token:{}", hash_token(token)));
+    }
+
+    match remote {
+        Some(remote) => Some(format!("addr:{}", remote.ip())),
+        None => Some(String::from("addr:unknown")),
+    }
+}
+
+pub fn check_rate_limit(
+    key: &Option<String>,
+    operation: RateLimitedOperation,
+    amount: u32,
+) -> Result<(), RateLimitExceeded> {
+    match key {
+        Some(key) => {
+            RateLimiter::with_global_instance(|limiter| limiter.consume(key, operation, amount))
+                .unwrap_or(Ok(()))
+        }
+        None => Ok(()),
+    }
+}
+
+pub fn rate_limit_usage(key: &Option<String>) -> Vec<RateLimitUsage> {
+    match key {
+        Some(key) => {
+            RateLimiter::with_global_instance(|limiter| limiter.usage(key)).unwrap_or_default()
+        }
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limits_parse_from_command_line_format() {
+        let config: RateLimitConfig = "aiPrompt=20/3600".parse().unwrap();
+        assert_eq!(config.operation, RateLimitedOperation::AiPrompt);
+        assert_eq!(config.limit, 20);
+        assert_eq!(config.window_seconds, 3600);
+
+        assert!("aiPrompt=20".parse::<RateLimitConfig>().is_err());
+        assert!("unknown=20/60".parse::<RateLimitConfig>().is_err());
+        assert!("request=many/60".parse::<RateLimitConfig>().is_err());
+    }
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(vec![
+            RateLimitConfig {
+                operation: RateLimitedOperation::AiPrompt,
+                limit: 2,
+                window_seconds: 3600,
+            },
+            RateLimitConfig {
+                operation: RateLimitedOperation::PerspectiveAddLinks,
+                limit: 10,
+                window_seconds: 60,
+            },
+        ])
+    }
+
+    #[test]
+    fn consume_rejects_calls_over_the_limit() {
+        let mut limiter = limiter();
+        assert!(limiter
+            .consume("app", RateLimitedOperation::AiPrompt, 1)
+            .is_ok());
+        assert!(limiter
+            .consume("app", RateLimitedOperation::AiPrompt, 1)
+            .is_ok());
+        let err = limiter
+            .consume("app", RateLimitedOperation::AiPrompt, 1)
+            .unwrap_err();
+        assert_eq!(err.operation, RateLimitedOperation::AiPrompt);
+        assert_eq!(err.limit, 2);
+    }
+
+    #[test]
+    fn limits_are_tracked_per_key() {
+        let mut limiter = limiter();
+        assert!(limiter
+            .consume("app1", RateLimitedOperation::AiPrompt, 2)
+            .is_ok());
+        assert!(limiter
+            .consume("app1", RateLimitedOperation::AiPrompt, 1)
+            .is_err());
+        assert!(limiter
+            .consume("app2", RateLimitedOperation::AiPrompt, 1)
+            .is_ok());
+    }
+
+    #[test]
+    fn amounts_larger_than_remaining_quota_are_rejected_without_being_counted() {
+        let mut limiter = limiter();
+        assert!(limiter
+            .consume("app", RateLimitedOperation::PerspectiveAddLinks, 8)
+            .is_ok());
+        assert!(limiter
+            .consume("app", RateLimitedOperation::PerspectiveAddLinks, 5)
+            .is_err());
+        assert!(limiter
+            .consume("app", RateLimitedOperation::PerspectiveAddLinks, 2)
+            .is_ok());
+    }
+
+    #[test]
+    fn unconfigured_operations_are_not_limited() {
+        let mut limiter = limiter();
+        for _ in 0..100 {
+            assert!(limiter
+                .consume("app", RateLimitedOperation::Request, 1)
+                .is_ok());
+        }
+    }
+
+    #[test]
+    fn usage_reports_consumed_units() {
+        let mut limiter = limiter();
+        limiter
+            .consume("app", RateLimitedOperation::PerspectiveAddLinks, 3)
+            .unwrap();
+        let usage = limiter.usage("app");
+        let links = usage
+            .iter()
+            .find(|u| u.operation == RateLimitedOperation::PerspectiveAddLinks)
+            .unwrap();
+        assert_eq!(links.used, 3);
+        assert_eq!(links.limit, 10);
+        let prompts = usage
+            .iter()
+            .find(|u| u.operation == RateLimitedOperation::AiPrompt)
+            .unwrap();
+        assert_eq!(prompts.used, 0);
+    }
+
+    #[test]
+    fn expired_windows_are_pruned() {
+        let mut limiter = limiter();
+        limiter
+            .consume("app", RateLimitedOperation::AiPrompt, 1)
+            .unwrap();
+        limiter
+            .consume("app", RateLimitedOperation::PerspectiveAddLinks, 1)
+            .unwrap();
+        assert_eq!(limiter.windows.len(), 2);
+
+        limiter.prune(Instant::now() + Duration::from_secs(61));
+        assert_eq!(limiter.windows.len(), 1);
+        limiter.prune(Instant::now() + Duration::from_secs(3601));
+        assert!(limiter.windows.is_empty());
+    }
+
+    #[test]
+    fn admin_credential_is_not_rate_limited() {
+        let admin = Some("secret".to_string());
+        let valid: Result<(), ()> = Ok(());
+        assert_eq!(rate_limit_key("secret", &valid, &admin, None), None);
+        assert!(rate_limit_key("app-token", &valid, &admin, None).is_some());
+        assert_eq!(rate_limit_key("", &valid, &None, None), None);
+    }
+
+    #[test]
+    fn invalid_tokens_are_keyed_by_client_address() {
+        let admin = Some("secret".to_string());
+        let valid: Result<(), ()> = Ok(());
+        let invalid: Result<(), ()> = Err(());
+        let client: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+        let same_client: SocketAddr = "10.0.0.1:4001".parse().unwrap();
+
+        let first = rate_limit_key("made-up-1", &invalid, &admin, Some(client));
+        let second = rate_limit_key("made-up-2", &invalid, &admin, Some(same_client));
+        let anonymous = rate_limit_key("", &valid, &admin, Some(client));
+        assert_eq!(first, Some("addr:10.0.0.1".to_string()));
+        assert_eq!(first, second);
+        assert_eq!(first, anonymous);
+
+        let app = rate_limit_key("app-token", &valid, &admin, Some(client));
+        assert_eq!(app, Some(format!("token:{}", hash_token("app-token"))));
+    }
+}
@@ -1,5 +1,6 @@
 use super::types::*;
-use crate::wallet::Wallet;
+use crate::agent::retired_key_name;
+use crate::wallet::{Wallet, MAIN_KEY_NAME};
 use deno_core::{anyhow::anyhow, error::AnyError};
 use jsonwebtoken::{encode, Algorithm, DecodingKey, EncodingKey, Header};
 
@@ -12,7 +13,7 @@ pub fn generate_jwt(
     let wallet = Wallet::instance();
     let wallet_lock = wallet.lock().expect("wallet lock");
     let wallet_ref = wallet_lock.as_ref().expect("wallet instance");
-    let name = "main".to_string();
+    let name = MAIN_KEY_NAME.to_string();
 
     let secret_key = wallet_ref
         .get_secret_key(&name)
@@ -38,7 +39,7 @@ pub fn decode_jwt(token: String) -> Result<Claims, AnyError> {
     let wallet = Wallet::instance();
     let wallet_lock = wallet.lock().expect("wallet lock");
     let wallet_ref = wallet_lock.as_ref().expect("wallet instance");
-    let name = "main".to_string();
+    let name = MAIN_KEY_NAME.to_string();
 
     let secret_key = wallet_ref
         .get_secret_key(&name)
@@ -48,7 +49,25 @@ pub fn decode_jwt(token: String) -> Result<Claims, AnyError> {
         &token,
         &DecodingKey::from_secret(secret_key.as_slice()),
         &jsonwebtoken::Validation::new(Algorithm::HS256),
-    )?;
+    );
+
+    // Tokens issued before a key rotation were signed with a now retired key
+    let result = result.or_else(|main_key_error| {
+        wallet_ref
+            .key_names()
+            .iter()
+            .filter(|name| name.starts_with(&retired_key_name("")))
+            .filter_map(|name| wallet_ref.get_secret_key(name))
+            .find_map(|secret_key| {
+                jsonwebtoken::decode::<Claims>(
+                    &token,
+                    &DecodingKey::from_secret(secret_key.as_slice()),
+                    &jsonwebtoken::Validation::new(Algorithm::HS256),
+                )
+                .ok()
+            })
+            .ok_or(main_key_error)
+    })?;
 
     Ok(result.claims)
 }
@@ -0,0 +1,399 @@
+//! Encryption of device sync neighbourhoods.
+//!
+//! Perspectives an agent syncs between its devices are published as neighbourhoods, which
+//! anyone who learns their URL could join. Their link languages therefore only ever see
+//! encrypted links. All devices of the agent share a random sync key: the primary device
+//! creates it and hands it to new devices sealed to their device key, with the `DeviceLink`
+//! returned by `agentAuthoriseDevice`. Every device keeps the key sealed to its own signing key.
+//!
+//! A link is committed as a wrapper link with the same author, timestamp and proof, whose
+//! target is the encrypted original. Nonces are derived from the key and the link, so a link
+//! always encrypts to the same wrapper and removals match what was added. The original proof
+//! verifies again once the link is decrypted.
+
+use base64::Engine;
+use crypto_box::aead::Aead;
+use crypto_box::{Nonce, PublicKey, SalsaBox, SecretKey};
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+
+use super::capabilities::apps_map::agent_signing_secrets;
+use super::encryption::EncryptedEnvelope;
+use super::AgentService;
+use crate::db::Ad4mDb;
+use crate::types::{Link, LinkExpression, PerspectiveDiff};
+
+/// Predicate of the wrapper links that carry encrypted links
+pub const DEVICE_SYNC_LINK_PREDICATE: &str = "ad4m://device_sync_link";
+const SEALED_KEY_CONTEXT: &[u8] = b"ad4m device sync key sealing key";
+const LINK_NONCE_CONTEXT: &[u8] = b"ad4m device sync link nonce";
+const ENVELOPE_PREFIX: &str = "literal://string:";
+const ENVELOPE_VERSION: u32 = 1;
+/// Multicodec prefix of the ed25519 public key in a did:key
+const ED25519_MULTICODEC: [u8; 2] = [0xed, 0x01];
+
+/// Symmetric key shared by all devices of the agent
+#[derive(Clone)]
+pub struct DeviceSyncKey([u8; 32]);
+
+#[derive(Serialize, Deserialize)]
+struct SealedData {
+    nonce: String,
+    ciphertext: String,
+}
+
+fn self_box(secret: [u8; 32]) -> SalsaBox {
+    let secret = SecretKey::from(secret);
+    SalsaBox::new(&secret.public_key(), &secret)
+}
+
+fn seal(salsa_box: &SalsaBox, nonce: [u8; 24], plaintext: &[u8]) -> Result<SealedData, AnyError> {
+    let ciphertext = salsa_box
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| anyhow!("Failed to encrypt device sync data"))?;
+    Ok(SealedData {
+        nonce: hex::encode(nonce),
+        ciphertext: base64::engine::general_purpose::STANDARD_NO_PAD.encode(ciphertext),
+    })
+}
+
+fn open(salsa_box: &SalsaBox, sealed: &SealedData) -> Result<Vec<u8>, AnyError> {
+    let nonce = hex::decode(&sealed.nonce)?;
+    if nonce.len() != 24 {
+        return Err(anyhow!("Invalid nonce length"));
+    }
+    let ciphertext = base64::engine::general_purpose::STANDARD_NO_PAD.decode(&sealed.ciphertext)?;
+    salsa_box
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| anyhow!("Device sync data was tampered with or is not encrypted for us"))
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+impl DeviceSyncKey {
+    fn generate() -> Self {
+        DeviceSyncKey(random_bytes())
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, AnyError> {
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("Device sync key has the wrong length"))?;
+        Ok(DeviceSyncKey(bytes))
+    }
+
+    fn link_nonce(&self, link: &LinkExpression, plaintext: &[u8]) -> [u8; 24] {
+        let mut hasher = Sha256::new();
+        hasher.update(LINK_NONCE_CONTEXT);
+        hasher.update(self.0);
+        hasher.update(link.author.as_bytes());
+        hasher.update([0]);
+        hasher.update(link.timestamp.as_bytes());
+        hasher.update([0]);
+        hasher.update(plaintext);
+        let hash = hasher.finalize();
+        let mut nonce = [0u8; 24];
+        nonce.copy_from_slice(&hash[..24]);
+        nonce
+    }
+
+    /// Wraps `link` into a link with the same author, timestamp and proof that carries
+    /// its data encrypted
+    pub fn encrypt_link(&self, link: &LinkExpression) -> Result<LinkExpression, AnyError> {
+        let plaintext = serde_json::to_vec(&link.data)?;
+        let sealed = seal(
+            &self_box(self.0),
+            self.link_nonce(link, &plaintext),
+            &plaintext,
+        )?;
+        Ok(LinkExpression {
+            data: Link {
+                source: "ad4m://self".to_string(),
+                predicate: Some(DEVICE_SYNC_LINK_PREDICATE.to_string()),
+                target: format!(
+                    "{}{}",
+                    ENVELOPE_PREFIX,
+                    base64::engine::general_purpose::URL_SAFE_NO_PAD
+                        .encode(serde_json::to_vec(&sealed)?)
+                ),
+            },
+            ..link.clone()
+        })
+    }
+
+    pub fn decrypt_link(&self, link: &LinkExpression) -> Result<LinkExpression, AnyError> {
+        if link.data.predicate.as_deref() != Some(DEVICE_SYNC_LINK_PREDICATE) {
+            return Err(anyhow!("Link is not encrypted for device sync"));
+        }
+        let encoded = link
+            .data
+            .target
+            .strip_prefix(ENVELOPE_PREFIX)
+            .ok_or(anyhow!("Invalid encrypted link"))?;
+        let sealed: SealedData = serde_json::from_slice(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(encoded)?,
+        )?;
+        let data: Link = serde_json::from_slice(&open(&self_box(self.0), &sealed)?)?;
+        Ok(LinkExpression {
+            data,
+            ..link.clone()
+        })
+    }
+
+    pub fn encrypt_diff(&self, diff: &PerspectiveDiff) -> Result<PerspectiveDiff, AnyError> {
+        let encrypt = |links: &Vec<LinkExpression>| {
+            links
+                .iter()
+                .map(|link| self.encrypt_link(link))
+                .collect::<Result<Vec<_>, AnyError>>()
+        };
+        Ok(PerspectiveDiff {
+            additions: encrypt(&diff.additions)?,
+            removals: encrypt(&diff.removals)?,
+        })
+    }
+
+    /// Decrypts a diff received from a device sync neighbourhood. Links that are not
+    /// encrypted with this key are dropped.
+    pub fn decrypt_diff(&self, diff: PerspectiveDiff) -> PerspectiveDiff {
+        let decrypt = |links: Vec<LinkExpression>| {
+            links
+                .iter()
+                .filter_map(|link| match self.decrypt_link(link) {
+                    Ok(link) => Some(link),
+                    Err(e) => {
+                        log::warn!("Dropping device sync link by {}: {}", link.author, e);
+                        None
+                    }
+                })
+                .collect()
+        };
+        PerspectiveDiff {
+            additions: decrypt(diff.additions),
+            removals: decrypt(diff.removals),
+        }
+    }
+}
+
+fn sealing_box(signing_secret: &[u8]) -> SalsaBox {
+    let mut hasher = Sha256::new();
+    hasher.update(SEALED_KEY_CONTEXT);
+    hasher.update(signing_secret);
+    self_box(hasher.finalize().into())
+}
+
+/// Keeps `key` as the device sync key of this device
+pub fn store_sync_key(key: &DeviceSyncKey) -> Result<(), AnyError> {
+    let secrets = agent_signing_secrets();
+    let main_secret = secrets
+        .first()
+        .ok_or(anyhow!("Agent is locked, can't store the device sync key"))?;
+    let sealed = serde_json::to_string(&seal(&sealing_box(main_secret), random_bytes(), &key.0)?)?;
+    Ad4mDb::with_global_instance(|db| db.set_device_sync_key(&sealed))
+}
+
+/// The device sync key of this device, `None` if it has none yet
+pub fn sync_key() -> Result<Option<DeviceSyncKey>, AnyError> {
+    let stored = match Ad4mDb::with_global_instance(|db| db.get_device_sync_key())? {
+        Some(stored) => stored,
+        None => return Ok(None),
+    };
+    let sealed: SealedData = serde_json::from_str(&stored)?;
+    // Retired keys are tried too, so the key survives key rotations
+    let key = agent_signing_secrets()
+        .iter()
+        .find_map(|secret| open(&sealing_box(secret), &sealed).ok())
+        .ok_or(anyhow!(
+            "Couldn't open the device sync key, is the agent unlocked?"
+        ))?;
+    Ok(Some(DeviceSyncKey::from_bytes(key)?))
+}
+
+/// The device sync key, created if there is none yet and this is the agent's primary device.
+/// Linked devices get theirs from the primary device when they are linked.
+pub fn ensure_sync_key() -> Result<DeviceSyncKey, AnyError> {
+    if let Some(key) = sync_key()? {
+        return Ok(key);
+    }
+    if AgentService::with_global_instance(|agent_service| agent_service.is_linked_device()) {
+        return Err(anyhow!(
+            "This device has no device sync key, link it to the agent again"
+        ));
+    }
+    let key = DeviceSyncKey::generate();
+    store_sync_key(&key)?;
+    Ok(key)
+}
+
+/// X25519 key of the ed25519 key behind a did:key
+fn device_public_key(device_did: &str) -> Result<PublicKey, AnyError> {
+    let encoded = device_did
+        .strip_prefix("did:key:")
+        .ok_or(anyhow!("{} is not a did:key", device_did))?;
+    let (_, bytes) = multibase::decode(encoded)?;
+    let key = bytes
+        .strip_prefix(&ED25519_MULTICODEC)
+        .filter(|key| key.len() == 32)
+        .ok_or(anyhow!("{} is not an ed25519 did:key", device_did))?;
+    let point = CompressedEdwardsY::from_slice(key)
+        .decompress()
+        .ok_or(anyhow!("{} is not a valid ed25519 key", device_did))?;
+    Ok(PublicKey::from(point.to_montgomery().to_bytes()))
+}
+
+/// X25519 secret belonging to an ed25519 signing secret, the scalar of RFC 8032
+fn device_secret_key(signing_secret: &[u8]) -> SecretKey {
+    let hash = Sha512::digest(signing_secret);
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(&hash[..32]);
+    SecretKey::from(bytes)
+}
+
+fn seal_key_for(key: &DeviceSyncKey, device_did: &str) -> Result<String, AnyError> {
+    let recipient = device_public_key(device_did)?;
+    let ephemeral_secret = SecretKey::from(random_bytes::<32>());
+    let sealed = seal(
+        &SalsaBox::new(&recipient, &ephemeral_secret),
+        random_bytes(),
+        &key.0,
+    )?;
+    let envelope = EncryptedEnvelope {
+        version: ENVELOPE_VERSION,
+        ephemeral_key: hex::encode(ephemeral_secret.public_key().as_bytes()),
+        nonce: sealed.nonce,
+        ciphertext: sealed.ciphertext,
+    };
+    Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(&envelope)?))
+}
+
+fn open_key_from(sealed_key: &str, signing_secret: &[u8]) -> Result<DeviceSyncKey, AnyError> {
+    let envelope: EncryptedEnvelope = serde_json::from_slice(
+        &base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(sealed_key)?,
+    )?;
+    if envelope.version > ENVELOPE_VERSION {
+        return Err(anyhow!(
+            "Unsupported device sync key version {}",
+            envelope.version
+        ));
+    }
+    let ephemeral_key: [u8; 32] = hex::decode(&envelope.ephemeral_key)?
+        .try_into()
+        .map_err(|_| anyhow!("Invalid ephemeral key length"))?;
+    let key = open(
+        &SalsaBox::new(
+            &PublicKey::from(ephemeral_key),
+            &device_secret_key(signing_secret),
+        ),
+        &SealedData {
+            nonce: envelope.nonce,
+            ciphertext: envelope.ciphertext,
+        },
+    )?;
+    DeviceSyncKey::from_bytes(key)
+}
+
+/// The device sync key sealed to the device `device_did`, to hand it over in a `DeviceLink`
+pub fn seal_sync_key_for(device_did: &str) -> Result<String, AnyError> {
+    seal_key_for(&ensure_sync_key()?, device_did)
+}
+
+/// Opens the device sync key the primary device sealed to us in a `DeviceLink`
+pub fn open_sync_key_from_link(sealed_key: &str) -> Result<DeviceSyncKey, AnyError> {
+    let secrets = agent_signing_secrets();
+    let main_secret = secrets
+        .first()
+        .ok_or(anyhow!("Agent is locked, can't open the device sync key"))?;
+    open_key_from(sealed_key, main_secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ExpressionProof;
+    use crate::wallet::Wallet;
+
+    fn link(target: &str) -> LinkExpression {
+        LinkExpression {
+            author: "did:key:alice".to_string(),
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+            data: Link {
+                source: "ad4m://self".to_string(),
+                predicate: Some("ad4m://has_note".to_string()),
+                target: target.to_string(),
+            },
+            proof: ExpressionProof {
+                key: "did:key:alice#key".to_string(),
+                signature: "signature".to_string(),
+            },
+            status: None,
+        }
+    }
+
+    #[test]
+    fn links_encrypt_to_the_same_wrapper_and_decrypt_with_the_key() {
+        let key = DeviceSyncKey::generate();
+        let original = link("literal://string:secret");
+        let wrapped = key.encrypt_link(&original).unwrap();
+
+        assert_eq!(wrapped, key.encrypt_link(&original).unwrap());
+        assert_eq!(wrapped.author, original.author);
+        assert_eq!(wrapped.timestamp, original.timestamp);
+        assert_eq!(wrapped.proof, original.proof);
+        assert!(!wrapped.data.target.contains("secret"));
+        assert_ne!(
+            wrapped,
+            key.encrypt_link(&link("literal://string:other")).unwrap()
+        );
+        assert_eq!(key.decrypt_link(&wrapped).unwrap(), original);
+
+        assert!(DeviceSyncKey::generate().decrypt_link(&wrapped).is_err());
+        assert!(key.decrypt_link(&original).is_err());
+    }
+
+    #[test]
+    fn diffs_drop_links_that_are_not_encrypted_with_the_key() {
+        let key = DeviceSyncKey::generate();
+        let diff = PerspectiveDiff {
+            additions: vec![link("literal://string:a"), link("literal://string:b")],
+            removals: vec![link("literal://string:c")],
+        };
+        let mut encrypted = key.encrypt_diff(&diff).unwrap();
+        encrypted.additions.push(link("literal://string:plain"));
+        encrypted.removals.push(
+            DeviceSyncKey::generate()
+                .encrypt_link(&link("literal://string:foreign"))
+                .unwrap(),
+        );
+
+        assert_eq!(key.decrypt_diff(encrypted), diff);
+    }
+
+    #[test]
+    fn sync_keys_sealed_to_a_device_open_with_its_signing_key() {
+        let mut wallet = Wallet::new();
+        wallet.generate_keypair("device".to_string());
+        wallet.generate_keypair("other".to_string());
+        let device_did = wallet.get_did_document(&"device".to_string()).unwrap().id;
+        let device_secret = wallet.get_secret_key(&"device".to_string()).unwrap();
+        let other_secret = wallet.get_secret_key(&"other".to_string()).unwrap();
+
+        assert_eq!(
+            device_public_key(&device_did).unwrap(),
+            device_secret_key(&device_secret).public_key()
+        );
+
+        let key = DeviceSyncKey::generate();
+        let sealed = seal_key_for(&key, &device_did).unwrap();
+        assert_eq!(open_key_from(&sealed, &device_secret).unwrap().0, key.0);
+        assert!(open_key_from(&sealed, &other_secret).is_err());
+        assert!(seal_key_for(&key, "did:ad4m:test").is_err());
+    }
+}
@@ -0,0 +1,401 @@
+//! End-to-end encryption for direct messages.
+//!
+//! Every agent has an X25519 key pair derived from its signing key. The public key is published
+//! in the agent expression together with a signature by the agent's DID, so senders can check
+//! that the key belongs to the recipient before encrypting to it.
+//!
+//! Messages are sealed with a fresh ephemeral key per message (XSalsa20-Poly1305) and sent as a
+//! perspective with a single link that carries the envelope. Tampered envelopes fail to decrypt.
+//! The sender's DID is sealed with the message and has to match the author of the signed
+//! expression around it.
+
+use base64::Engine;
+use crypto_box::aead::Aead;
+use crypto_box::{Nonce, PublicKey, SalsaBox, SecretKey};
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::retired_key_name;
+use super::signatures::{hash_message, verify_string_signed_by_did};
+use crate::graphql::graphql_types::{AgentEncryptionKey, Perspective, PerspectiveExpression};
+use crate::types::{DecoratedExpressionProof, DecoratedLinkExpression, Link};
+use crate::wallet::{Wallet, MAIN_KEY_NAME};
+
+/// Predicate of the link that carries an encrypted message
+pub const ENCRYPTED_MESSAGE_PREDICATE: &str = "ad4m://encrypted_message";
+const ENVELOPE_VERSION: u32 = 1;
+const KEY_DERIVATION_CONTEXT: &[u8] = b"ad4m direct message encryption key";
+const ENVELOPE_PREFIX: &str = "literal://string:";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptedEnvelope {
+    pub version: u32,
+    /// Hex encoded ephemeral X25519 public key of the sender
+    pub ephemeral_key: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct SealedMessage {
+    from: String,
+    message: Perspective,
+}
+
+/// X25519 secret key belonging to an Ed25519 signing key
+pub fn derive_secret_key(signing_secret: &[u8]) -> SecretKey {
+    let mut hasher = Sha256::new();
+    hasher.update(KEY_DERIVATION_CONTEXT);
+    hasher.update(signing_secret);
+    let bytes: [u8; 32] = hasher.finalize().into();
+    SecretKey::from(bytes)
+}
+
+/// What the DID signs to attest a public encryption key
+fn attestation_payload(public_key: &str) -> String {
+    format!("ad4m-encryption-key:{}", public_key)
+}
+
+/// Encryption key of the wallet key `key_name`, attested by a signature of that key
+pub fn attested_key(wallet: &Wallet, key_name: &String) -> Option<AgentEncryptionKey> {
+    let secret = derive_secret_key(&wallet.get_secret_key(key_name)?);
+    let public_key = hex::encode(PublicKey::from(&secret).as_bytes());
+    let signature = wallet.sign(key_name, &hash_message(&attestation_payload(&public_key)))?;
+    Some(AgentEncryptionKey {
+        public_key,
+        signature: hex::encode(signature),
+    })
+}
+
+/// Encryption key of the agent's main key, to be published in the agent expression
+pub fn agent_encryption_key() -> Result<AgentEncryptionKey, AnyError> {
+    let wallet_instance = Wallet::instance();
+    let wallet = wallet_instance.lock().expect("wallet lock");
+    let wallet_ref = wallet.as_ref().expect("wallet instance");
+    attested_key(wallet_ref, &MAIN_KEY_NAME.to_string())
+        .ok_or(anyhow!("main key not found. call createMainKey() first"))
+}
+
+/// Checks that `key` was published by `did` and returns it
+pub fn verified_public_key(did: &str, key: &AgentEncryptionKey) -> Result<PublicKey, AnyError> {
+    if !verify_string_signed_by_did(did, &attestation_payload(&key.public_key), &key.signature)? {
+        return Err(anyhow!("Encryption key of {} is not signed by them", did));
+    }
+    let bytes: [u8; 32] = hex::decode(&key.public_key)?
+        .try_into()
+        .map_err(|_| anyhow!("Encryption key of {} has the wrong length", did))?;
+    Ok(PublicKey::from(bytes))
+}
+
+fn encrypt(
+    from: &str,
+    message: &Perspective,
+    recipient: &PublicKey,
+) -> Result<EncryptedEnvelope, AnyError> {
+    let mut ephemeral_secret = [0u8; 32];
+    let mut nonce = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut ephemeral_secret);
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ephemeral_secret = SecretKey::from(ephemeral_secret);
+
+    let plaintext = serde_json::to_vec(&SealedMessage {
+        from: from.to_string(),
+        message: message.clone(),
+    })?;
+    let ciphertext = SalsaBox::new(recipient, &ephemeral_secret)
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| anyhow!("Failed to encrypt message"))?;
+
+    Ok(EncryptedEnvelope {
+        version: ENVELOPE_VERSION,
+        ephemeral_key: hex::encode(PublicKey::from(&ephemeral_secret).as_bytes()),
+        nonce: hex::encode(nonce),
+        ciphertext: base64::engine::general_purpose::STANDARD_NO_PAD.encode(ciphertext),
+    })
+}
+
+fn decrypt(envelope: &EncryptedEnvelope, secret: &SecretKey) -> Result<SealedMessage, AnyError> {
+    if envelope.version > ENVELOPE_VERSION {
+        return Err(anyhow!(
+            "Unsupported message envelope version {}",
+            envelope.version
+        ));
+    }
+    let ephemeral_key: [u8; 32] = hex::decode(&envelope.ephemeral_key)?
+        .try_into()
+        .map_err(|_| anyhow!("Invalid ephemeral key length"))?;
+    let nonce = hex::decode(&envelope.nonce)?;
+    if nonce.len() != 24 {
+        return Err(anyhow!("Invalid nonce length"));
+    }
+    let ciphertext =
+        base64::engine::general_purpose::STANDARD_NO_PAD.decode(&envelope.ciphertext)?;
+
+    let plaintext = SalsaBox::new(&PublicKey::from(ephemeral_key), secret)
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| anyhow!("Message was tampered with or is not encrypted for us"))?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+/// Encrypts `message` from `from` to the `recipient` key, wrapped in a perspective
+/// that direct message languages can carry like any other message
+pub fn seal(
+    from: &str,
+    message: &Perspective,
+    recipient: &PublicKey,
+) -> Result<Perspective, AnyError> {
+    let envelope = encrypt(from, message, recipient)?;
+    Ok(Perspective {
+        links: vec![DecoratedLinkExpression {
+            author: from.to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            data: Link {
+                source: "ad4m://self".to_string(),
+                predicate: Some(ENCRYPTED_MESSAGE_PREDICATE.to_string()),
+                target: format!(
+                    "{}{}",
+                    ENVELOPE_PREFIX,
+                    base64::engine::general_purpose::URL_SAFE_NO_PAD
+                        .encode(serde_json::to_vec(&envelope)?)
+                ),
+            },
+            proof: DecoratedExpressionProof::default(),
+            status: None,
+        }],
+    })
+}
+
+fn decode_envelope(target: &str) -> Result<EncryptedEnvelope, AnyError> {
+    let encoded = target
+        .strip_prefix(ENVELOPE_PREFIX)
+        .ok_or(anyhow!("Invalid encrypted message"))?;
+    let json = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(encoded)?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+/// The envelope of a sealed message, `None` for plain messages
+pub fn envelope(message: &Perspective) -> Option<Result<EncryptedEnvelope, AnyError>> {
+    match message.links.as_slice() {
+        [link] if link.data.predicate.as_deref() == Some(ENCRYPTED_MESSAGE_PREDICATE) => {
+            Some(decode_envelope(&link.data.target))
+        }
+        _ => None,
+    }
+}
+
+/// Decrypts a sealed message with the first of `secrets` it was encrypted for.
+/// Plain messages are returned as they are.
+pub fn open(
+    expression: PerspectiveExpression,
+    secrets: &[SecretKey],
+) -> Result<PerspectiveExpression, AnyError> {
+    let envelope = match envelope(&expression.data) {
+        Some(envelope) => envelope?,
+        None => return Ok(expression),
+    };
+
+    let sealed = secrets
+        .iter()
+        .find_map(|secret| decrypt(&envelope, secret).ok())
+        .ok_or(anyhow!(
+            "Couldn't decrypt message from {}: it was tampered with or is not encrypted for us",
+            expression.author
+        ))?;
+
+    if sealed.from != expression.author {
+        return Err(anyhow!(
+            "Encrypted message from {} claims to be from {}",
+            expression.author,
+            sealed.from
+        ));
+    }
+
+    Ok(PerspectiveExpression {
+        data: sealed.message,
+        ..expression
+    })
+}
+
+/// Encryption keys of the main key and all retired keys, so messages encrypted to a key
+/// from before a key rotation can still be read
+fn own_secret_keys() -> Vec<SecretKey> {
+    let wallet_instance = Wallet::instance();
+    let wallet = wallet_instance.lock().expect("wallet lock");
+    let wallet_ref = match wallet.as_ref() {
+        Some(wallet) => wallet,
+        None => return vec![],
+    };
+    let retired_prefix = retired_key_name("");
+    let mut names = vec![MAIN_KEY_NAME.to_string()];
+    names.extend(
+        wallet_ref
+            .key_names()
+            .into_iter()
+            .filter(|name| name.starts_with(&retired_prefix)),
+    );
+    names
+        .iter()
+        .filter_map(|name| wallet_ref.get_secret_key(name))
+        .map(|secret| derive_secret_key(&secret))
+        .collect()
+}
+
+/// Decrypts a received direct message with the agent's keys
+pub fn decrypt_message(
+    expression: PerspectiveExpression,
+) -> Result<PerspectiveExpression, AnyError> {
+    match envelope(&expression.data) {
+        Some(_) => open(expression, &own_secret_keys()),
+        None => Ok(expression),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str) -> Perspective {
+        Perspective {
+            links: vec![DecoratedLinkExpression {
+                author: "did:key:alice".to_string(),
+                timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+                data: Link {
+                    source: "ad4m://self".to_string(),
+                    predicate: Some("ad4m://message".to_string()),
+                    target: format!("literal://string:{}", text),
+                },
+                proof: DecoratedExpressionProof::default(),
+                status: None,
+            }],
+        }
+    }
+
+    fn expression(author: &str, data: Perspective) -> PerspectiveExpression {
+        PerspectiveExpression {
+            author: author.to_string(),
+            data,
+            ..Default::default()
+        }
+    }
+
+    fn key_pair(seed: u8) -> (SecretKey, PublicKey) {
+        let secret = derive_secret_key(&[seed; 32]);
+        let public = PublicKey::from(&secret);
+        (secret, public)
+    }
+
+    fn tamper(sealed: &Perspective, change: impl Fn(&mut EncryptedEnvelope)) -> Perspective {
+        let mut envelope = envelope(sealed).unwrap().unwrap();
+        change(&mut envelope);
+        let mut tampered = sealed.clone();
+        tampered.links[0].data.target = format!(
+            "{}{}",
+            ENVELOPE_PREFIX,
+            base64::engine::general_purpose::URL_SAFE_NO_PAD
+                .encode(serde_json::to_vec(&envelope).unwrap())
+        );
+        tampered
+    }
+
+    #[test]
+    fn sealed_messages_open_with_the_recipient_key() {
+        let (bob_secret, bob_public) = key_pair(2);
+        let plain = message("hello");
+
+        let sealed = seal("did:key:alice", &plain, &bob_public).unwrap();
+        assert!(envelope(&sealed).is_some());
+        assert!(!sealed.links[0].data.target.contains("hello"));
+
+        let opened = open(expression("did:key:alice", sealed), &[bob_secret]).unwrap();
+        assert_eq!(opened.author, "did:key:alice");
+        assert_eq!(opened.data.links[0].data, plain.links[0].data);
+    }
+
+    #[test]
+    fn plain_messages_pass_through() {
+        let (bob_secret, _) = key_pair(2);
+        let plain = expression("did:key:alice", message("hello"));
+        assert!(envelope(&plain.data).is_none());
+        let opened = open(plain.clone(), &[bob_secret]).unwrap();
+        assert_eq!(opened.data.links[0].data, plain.data.links[0].data);
+    }
+
+    #[test]
+    fn other_keys_cannot_open_messages() {
+        let (_, bob_public) = key_pair(2);
+        let (eve_secret, _) = key_pair(3);
+        let sealed = seal("did:key:alice", &message("hello"), &bob_public).unwrap();
+        assert!(open(expression("did:key:alice", sealed), &[eve_secret]).is_err());
+    }
+
+    #[test]
+    fn any_of_our_keys_can_open_messages() {
+        let (old_secret, old_public) = key_pair(2);
+        let (new_secret, _) = key_pair(4);
+        let sealed = seal("did:key:alice", &message("hello"), &old_public).unwrap();
+        assert!(open(
+            expression("did:key:alice", sealed),
+            &[new_secret, old_secret]
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn detects_tampering() {
+        let (bob_secret, bob_public) = key_pair(2);
+        let (_, eve_public) = key_pair(3);
+        let sealed = seal("did:key:alice", &message("hello"), &bob_public).unwrap();
+
+        let flipped_ciphertext = tamper(&sealed, |envelope| {
+            let b64 = base64::engine::general_purpose::STANDARD_NO_PAD;
+            let mut ciphertext = b64.decode(&envelope.ciphertext).unwrap();
+            ciphertext[0] ^= 1;
+            envelope.ciphertext = b64.encode(ciphertext);
+        });
+        let swapped_key = tamper(&sealed, |envelope| {
+            envelope.ephemeral_key = hex::encode(eve_public.as_bytes());
+        });
+        let other_nonce = tamper(&sealed, |envelope| {
+            envelope.nonce = hex::encode([0u8; 24]);
+        });
+
+        for tampered in [flipped_ciphertext, swapped_key, other_nonce] {
+            assert!(open(expression("did:key:alice", tampered), &[bob_secret.clone()]).is_err());
+        }
+    }
+
+    #[test]
+    fn sender_has_to_match_the_expression_author() {
+        let (bob_secret, bob_public) = key_pair(2);
+        let sealed = seal("did:key:alice", &message("hello"), &bob_public).unwrap();
+        // Eve re-signing Alice's ciphertext as her own
+        assert!(open(expression("did:key:eve", sealed), &[bob_secret]).is_err());
+    }
+
+    #[test]
+    fn published_keys_are_attested_by_the_did() {
+        let mut wallet = Wallet::new();
+        wallet.generate_keypair("alice".to_string());
+        wallet.generate_keypair("eve".to_string());
+        let alice = wallet.get_did_document(&"alice".to_string()).unwrap().id;
+
+        let key = attested_key(&wallet, &"alice".to_string()).unwrap();
+        let public = verified_public_key(&alice, &key).unwrap();
+        let secret = derive_secret_key(&wallet.get_secret_key(&"alice".to_string()).unwrap());
+        assert_eq!(public.as_bytes(), PublicKey::from(&secret).as_bytes());
+
+        // Eve's key with Alice's name on it
+        let eve_key = attested_key(&wallet, &"eve".to_string()).unwrap();
+        assert!(verified_public_key(&alice, &eve_key).is_err());
+        let forged = AgentEncryptionKey {
+            public_key: eve_key.public_key,
+            signature: key.signature,
+        };
+        assert!(verified_public_key(&alice, &forged).is_err());
+    }
+}
@@ -5,12 +5,22 @@ use deno_core::anyhow::anyhow;
 use deno_core::error::AnyError;
 use serde::{Deserialize, Serialize};
 
-use crate::graphql::graphql_types::{Agent, AgentStatus, Perspective};
+use crate::agent::backup::AgentBackup;
+use crate::db::Ad4mDb;
+use crate::entanglement_service::{
+    load_device_proofs, own_device_proofs, verify_entanglement_proof, AD4M_DEVICE_KEY_TYPE,
+};
+use crate::graphql::graphql_types::{
+    Agent, AgentKey, AgentStatus, EntanglementProof, KeySuccession, Perspective,
+};
 
 use crate::types::{Expression, ExpressionProof};
-use crate::wallet::Wallet;
+use crate::wallet::{Wallet, MAIN_KEY_NAME};
 
+pub mod backup;
 pub mod capabilities;
+pub mod device_sync;
+pub mod encryption;
 pub mod signatures;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,7 +38,7 @@ pub fn did_document() -> did_key::Document {
     let wallet_instance = Wallet::instance();
     let wallet = wallet_instance.lock().expect("wallet lock");
     let wallet_ref = wallet.as_ref().expect("wallet instance");
-    let name = "main".to_string();
+    let name = MAIN_KEY_NAME.to_string();
     wallet_ref
         .get_did_document(&name)
         .ok_or(anyhow!("main key not found. call createMainKey() first"))
@@ -48,7 +58,7 @@ pub fn check_keys_and_create(did: String) -> did_key::Document {
     let wallet_instance = Wallet::instance();
     let mut wallet = wallet_instance.lock().expect("wallet lock");
     let wallet_ref = wallet.as_mut().expect("wallet instance");
-    let name = "main".to_string();
+    let name = MAIN_KEY_NAME.to_string();
     if wallet_ref.get_did_document(&name).is_none() {
         wallet_ref.initialize_keys(name, did).unwrap()
     } else {
@@ -56,6 +66,17 @@ pub fn check_keys_and_create(did: String) -> did_key::Document {
     }
 }
 
+/// Wallet name under which a key is kept after it got rotated away from
+pub fn retired_key_name(did: &str) -> String {
+    format!("retired:{}", did)
+}
+
+/// Wallet name under which an additional key, created with [`AgentService::generate_named_key`],
+/// is kept. The prefix keeps these apart from the main and retired keys.
+pub fn named_key_name(name: &str) -> String {
+    format!("named:{}", name)
+}
+
 pub fn create_signed_expression<T: Serialize>(data: T) -> Result<Expression<T>, AnyError> {
     let timestamp = chrono::Utc::now();
     let signature = hex::encode(sign(&signatures::hash_data_and_timestamp(
@@ -77,7 +98,7 @@ pub fn sign(payload: &[u8]) -> Result<Vec<u8>, AnyError> {
     let wallet_instance = Wallet::instance();
     let wallet = wallet_instance.lock().expect("wallet lock");
     let wallet_ref = wallet.as_ref().expect("wallet instance");
-    let name = "main".to_string();
+    let name = MAIN_KEY_NAME.to_string();
     let signature = wallet_ref
         .sign(&name, payload)
         .ok_or(anyhow!("main key not found. call createMainKey() first"))?;
@@ -104,6 +125,28 @@ impl AgentSignature {
             public_key: signing_key_id(),
         })
     }
+
+    /// Signs with one of the agent's named keys instead of the main key
+    pub fn from_message_with_key(
+        message: String,
+        key_name: &str,
+    ) -> Result<AgentSignature, AnyError> {
+        let payload_bytes = signatures::hash_message(&message);
+        let wallet_instance = Wallet::instance();
+        let wallet = wallet_instance.lock().expect("wallet lock");
+        let wallet_ref = wallet.as_ref().expect("wallet instance");
+        let name = named_key_name(key_name);
+        let signature = wallet_ref
+            .sign(&name, &payload_bytes)
+            .ok_or(anyhow!("Key '{}' not found", key_name))?;
+        let did_document = wallet_ref
+            .get_did_document(&name)
+            .ok_or(anyhow!("Key '{}' not found", key_name))?;
+        Ok(AgentSignature {
+            signature: hex::encode(signature),
+            public_key: did_document.verification_method[0].id.clone(),
+        })
+    }
 }
 
 impl From<AgentSignature> for crate::graphql::graphql_types::AgentSignature {
@@ -149,6 +192,15 @@ impl AgentService {
         }
     }
 
+    /// Where the JS core sets up the Holochain conductor, whose keystore
+    /// is encrypted with the agent's passphrase as well
+    pub fn holochain_conductor_path(&self) -> path::PathBuf {
+        path::Path::new(&self.file)
+            .parent()
+            .map(|ad4m_path| ad4m_path.join("h").join("c"))
+            .unwrap_or_default()
+    }
+
     pub fn global_instance() -> Arc<Mutex<Option<AgentService>>> {
         AGENT_SERVICE.clone()
     }
@@ -240,28 +292,448 @@ impl AgentService {
         let did = {
             let mut wallet = wallet_instance.lock().expect("wallet lock");
             let wallet_ref: &mut Wallet = wallet.as_mut().expect("wallet instance");
-            wallet_ref.generate_keypair("main".to_string());
+            wallet_ref.generate_keypair(MAIN_KEY_NAME.to_string());
             wallet_ref
-                .get_did_document(&"main".to_string())
+                .get_did_document(&MAIN_KEY_NAME.to_string())
                 .expect("couldn't get DID document for keys that were just generated above")
                 .id
         };
 
+        self.init_with_main_key(did);
+    }
+
+    /// Recreates the agent's main key from its recovery phrase
+    /// instead of generating a new one.
+    pub fn restore_from_mnemonic(&mut self, mnemonic: String) -> Result<(), AnyError> {
+        if self.is_initialized() {
+            return Err(anyhow!("Agent already initialized"));
+        }
+
+        let wallet_instance = Wallet::instance();
+        let did = {
+            let mut wallet = wallet_instance.lock().expect("wallet lock");
+            let wallet_ref: &mut Wallet = wallet.as_mut().expect("wallet instance");
+            wallet_ref.import_mnemonic(MAIN_KEY_NAME.to_string(), &mnemonic)?;
+            wallet_ref
+                .get_did_document(&MAIN_KEY_NAME.to_string())
+                .ok_or(anyhow!("Couldn't get DID document for restored key"))?
+                .id
+        };
+
+        self.init_with_main_key(did);
+        Ok(())
+    }
+
+    /// Returns the recovery phrase of the agent's main key
+    pub fn mnemonic(&self, passphrase: String) -> Result<String, AnyError> {
+        self.signing_checks()?;
+        let wallet_instance = Wallet::instance();
+        let wallet = wallet_instance.lock().expect("wallet lock");
+        let wallet_ref: &Wallet = wallet.as_ref().expect("wallet instance");
+        if !wallet_ref.verify_passphrase(passphrase) {
+            return Err(anyhow!("Wrong passphrase"));
+        }
+        wallet_ref
+            .mnemonic(&MAIN_KEY_NAME.to_string())
+            .ok_or(anyhow!("Main key not found"))
+    }
+
+    /// Exports keystore, profile, apps, trusted agents and friends,
+    /// encrypted with the agent's passphrase.
+    pub fn export_backup(&self, passphrase: String) -> Result<String, AnyError> {
+        self.signing_checks()?;
+        let keystore = {
+            let wallet_instance = Wallet::instance();
+            let mut wallet = wallet_instance.lock().expect("wallet lock");
+            let wallet_ref: &mut Wallet = wallet.as_mut().expect("wallet instance");
+            if !wallet_ref.verify_passphrase(passphrase.clone()) {
+                return Err(anyhow!("Wrong passphrase"));
+            }
+            wallet_ref.export(passphrase.clone())
+        };
+
+        let store = AgentStore {
+            did: self.did.clone().ok_or(anyhow!("Agent DID not set"))?,
+            did_document: self
+                .did_document
+                .clone()
+                .ok_or(anyhow!("Agent DID document not set"))?,
+            signing_key_id: self
+                .signing_key_id
+                .clone()
+                .ok_or(anyhow!("Agent signing key not found"))?,
+            keystore,
+            agent: self.agent.clone(),
+        };
+
+        AgentBackup::from_db(store)?.encrypt(passphrase)
+    }
+
+    /// Sets up an uninitialized agent from a backup created with [`AgentService::export_backup`].
+    /// The wallet is left unlocked.
+    pub fn import_backup(&mut self, backup: String, passphrase: String) -> Result<(), AnyError> {
+        if self.is_initialized() {
+            return Err(anyhow!("Agent already initialized"));
+        }
+
+        let backup = AgentBackup::decrypt(backup, passphrase.clone())?;
+        {
+            let wallet_instance = Wallet::instance();
+            let mut wallet = wallet_instance.lock().expect("wallet lock");
+            let wallet_ref: &mut Wallet = wallet.as_mut().expect("wallet instance");
+            wallet_ref.load(backup.agent_store.keystore.clone());
+            wallet_ref.unlock(passphrase.clone())?;
+        }
+        backup.restore_db()?;
+        load_device_proofs();
+        signatures::load_key_successions();
+
+        let mut agent = backup.agent_store.agent.clone().unwrap_or(Agent {
+            did: backup.agent_store.did.clone(),
+            perspective: Some(Perspective { links: vec![] }),
+            direct_message_language: None,
+            encryption_key: None,
+            device_proofs: None,
+            key_successions: None,
+        });
+        // The old DM language is bound to the previous Holochain agent key,
+        // a new one gets created once Holochain is initialized
+        agent.direct_message_language = None;
+        agent.encryption_key = encryption::agent_encryption_key().ok();
+
+        self.did = Some(backup.agent_store.did);
+        self.did_document = Some(backup.agent_store.did_document);
+        self.signing_key_id = Some(backup.agent_store.signing_key_id);
+        self.agent = Some(agent);
+
+        self.save(passphrase);
+        self.store_agent_profile();
+        Ok(())
+    }
+
+    fn init_with_main_key(&mut self, did: String) {
         self.did_document = Some(serde_json::to_string(&did_document()).unwrap());
         self.did = Some(did.clone());
         self.agent = Some(Agent {
             did,
             perspective: Some(Perspective { links: vec![] }),
             direct_message_language: None,
+            encryption_key: encryption::agent_encryption_key().ok(),
+            device_proofs: None,
+            key_successions: None,
         });
         self.signing_key_id = Some(signing_key_id());
     }
 
-    pub fn unlock(&self, password: String) -> Result<(), AnyError> {
+    /// Puts the encryption key of the current main key into the agent profile,
+    /// returns true if the profile changed and needs to be published
+    pub fn ensure_encryption_key(&mut self) -> bool {
+        let key = match encryption::agent_encryption_key() {
+            Ok(key) => key,
+            Err(e) => {
+                log::warn!("No encryption key for agent: {}", e);
+                return false;
+            }
+        };
+        match self.agent.as_mut() {
+            Some(agent) if agent.encryption_key.as_ref() != Some(&key) => {
+                agent.encryption_key = Some(key);
+                self.store_agent_profile();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Puts our device proofs into the agent profile, so other agents accept signatures of our
+    /// devices. Returns true if the profile changed and needs to be published.
+    pub fn ensure_device_proofs(&mut self) -> bool {
+        let proofs = own_device_proofs();
+        let proofs = if proofs.is_empty() {
+            None
+        } else {
+            Some(proofs)
+        };
+        match self.agent.as_mut() {
+            Some(agent) if agent.device_proofs != proofs => {
+                agent.device_proofs = proofs;
+                self.store_agent_profile();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Puts our key successions into the agent profile, so other agents accept signatures of
+    /// our previous keys. Returns true if the profile changed and needs to be published.
+    pub fn ensure_key_successions(&mut self) -> bool {
+        let successions = match self.key_history() {
+            Ok(successions) if successions.is_empty() => None,
+            Ok(successions) => Some(successions),
+            Err(e) => {
+                log::warn!("Couldn't load key successions: {}", e);
+                return false;
+            }
+        };
+        match self.agent.as_mut() {
+            Some(agent) if agent.key_successions != successions => {
+                agent.key_successions = successions;
+                self.store_agent_profile();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Re-encrypts the keystore with a new passphrase.
+    /// The wallet is left locked or unlocked as it was before.
+    pub fn change_passphrase(
+        &self,
+        old_passphrase: String,
+        new_passphrase: String,
+    ) -> Result<(), AnyError> {
+        if !self.is_initialized() {
+            return Err(anyhow!("Agent not initialized"));
+        }
+
+        let was_unlocked = {
+            let wallet_instance = Wallet::instance();
+            let mut wallet = wallet_instance.lock().expect("wallet lock");
+            let wallet_ref: &mut Wallet = wallet.as_mut().expect("wallet instance");
+            if !wallet_ref.verify_passphrase(old_passphrase.clone()) {
+                return Err(anyhow!("Wrong passphrase"));
+            }
+            let was_unlocked = wallet_ref.is_unlocked();
+            if !was_unlocked {
+                wallet_ref.unlock(old_passphrase.clone())?;
+            }
+            was_unlocked
+        };
+
+        self.save(new_passphrase.clone());
+
+        if !was_unlocked {
+            self.lock(new_passphrase);
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the agent's main key with a freshly generated one.
+    /// The old key signs a succession statement pointing to the new DID and is
+    /// kept in the wallet, so that its signatures keep verifying for data
+    /// created before the rotation.
+    pub fn rotate_key(&mut self, passphrase: String) -> Result<KeySuccession, AnyError> {
+        self.signing_checks()?;
+        if self.is_linked_device() {
+            return Err(anyhow!(
+                "Keys of a linked device can't be rotated, rotate them on the primary device"
+            ));
+        }
+        let previous_did = self.did.clone().ok_or(anyhow!("Agent DID not set"))?;
+        let pending_key_name = format!("{}-pending", MAIN_KEY_NAME);
+
+        let new_did = {
+            let wallet_instance = Wallet::instance();
+            let mut wallet = wallet_instance.lock().expect("wallet lock");
+            let wallet_ref: &mut Wallet = wallet.as_mut().expect("wallet instance");
+            if !wallet_ref.verify_passphrase(passphrase.clone()) {
+                return Err(anyhow!("Wrong passphrase"));
+            }
+            wallet_ref.generate_keypair(pending_key_name.clone());
+            wallet_ref
+                .get_did_document(&pending_key_name)
+                .ok_or(anyhow!("Couldn't get DID document for new key"))?
+                .id
+        };
+
+        let timestamp = chrono::Utc::now();
+        let signature = hex::encode(sign(&signatures::hash_data_and_timestamp(
+            &new_did, &timestamp,
+        ))?);
+        let succession = KeySuccession {
+            previous_did: previous_did.clone(),
+            new_did: new_did.clone(),
+            timestamp: timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            signature,
+        };
+
+        {
+            let wallet_instance = Wallet::instance();
+            let mut wallet = wallet_instance.lock().expect("wallet lock");
+            let wallet_ref: &mut Wallet = wallet.as_mut().expect("wallet instance");
+            wallet_ref.rename_key(MAIN_KEY_NAME, retired_key_name(&previous_did))?;
+            wallet_ref.rename_key(&pending_key_name, MAIN_KEY_NAME.to_string())?;
+        }
+
+        Ad4mDb::with_global_instance(|db| db.add_key_succession(&succession))?;
+        signatures::load_key_successions();
+        let successions = self.key_history()?;
+
+        self.did_document = Some(serde_json::to_string(&did_document())?);
+        self.did = Some(new_did.clone());
+        self.signing_key_id = Some(signing_key_id());
+        if let Some(agent) = self.agent.as_mut() {
+            agent.did = new_did;
+            agent.encryption_key = encryption::agent_encryption_key().ok();
+            // Published with the agent expression, so others accept our previous keys
+            agent.key_successions = Some(successions);
+        }
+
+        self.save(passphrase);
+        if self.agent.is_some() {
+            self.store_agent_profile();
+        }
+
+        Ok(succession)
+    }
+
+    /// Makes this executor another device of the agent whose primary device created `proof`
+    /// with `agentAuthoriseDevice`. Our main key signs the agent's DID to complete the proof,
+    /// and from then on we act as the agent, signing with our own key. The completed proof
+    /// needs to be added on the primary device as well, so it accepts our signatures.
+    pub fn link_device(
+        &mut self,
+        mut proof: EntanglementProof,
+        passphrase: String,
+    ) -> Result<EntanglementProof, AnyError> {
+        self.signing_checks()?;
+        if self.is_linked_device() {
+            return Err(anyhow!(
+                "This executor is already linked to {}",
+                self.did.clone().unwrap_or_default()
+            ));
+        }
+        {
+            let wallet_instance = Wallet::instance();
+            let wallet = wallet_instance.lock().expect("wallet lock");
+            let wallet_ref: &Wallet = wallet.as_ref().expect("wallet instance");
+            if !wallet_ref.verify_passphrase(passphrase.clone()) {
+                return Err(anyhow!("Wrong passphrase"));
+            }
+        }
+
+        let device_did = self.did.clone().ok_or(anyhow!("Agent DID not set"))?;
+        if proof.device_key != device_did {
+            return Err(anyhow!(
+                "Device link is for {}, not for this device {}",
+                proof.device_key,
+                device_did
+            ));
+        }
+        if proof.did == device_did {
+            return Err(anyhow!("A device can't be linked to itself"));
+        }
+        proof.device_key_type = AD4M_DEVICE_KEY_TYPE.to_string();
+        proof.did_signed_by_device_key = Some(sign_string_hex(proof.did.clone())?);
+        verify_entanglement_proof(&proof)?;
+
+        Ad4mDb::with_global_instance(|db| db.add_entanglement_proofs(vec![proof.clone()]))?;
+        load_device_proofs();
+
+        self.did = Some(proof.did.clone());
+        if let Some(agent) = self.agent.as_mut() {
+            agent.did = proof.did.clone();
+        }
+        self.save(passphrase);
+        if self.agent.is_some() {
+            self.store_agent_profile();
+        }
+
+        Ok(proof)
+    }
+
+    /// Whether this executor acts as another device of an agent, signing with a key
+    /// that is not the agent's own
+    pub fn is_linked_device(&self) -> bool {
+        match (&self.did, &self.signing_key_id) {
+            (Some(did), Some(signing_key_id)) => !signing_key_id.starts_with(&format!("{}#", did)),
+            _ => false,
+        }
+    }
+
+    pub fn key_history(&self) -> Result<Vec<KeySuccession>, AnyError> {
+        Ad4mDb::with_global_instance(|db| db.get_key_successions())
+    }
+
+    /// Generates an additional key the agent can sign with next to its main key.
+    /// Named keys don't change the agent's DID and are kept in the keystore,
+    /// so they are part of backups.
+    pub fn generate_named_key(
+        &self,
+        name: String,
+        passphrase: String,
+    ) -> Result<AgentKey, AnyError> {
+        self.signing_checks()?;
+        if name.trim().is_empty() {
+            return Err(anyhow!("Key name can't be empty"));
+        }
+        let key_name = named_key_name(&name);
+
+        let did = {
+            let wallet_instance = Wallet::instance();
+            let mut wallet = wallet_instance.lock().expect("wallet lock");
+            let wallet_ref: &mut Wallet = wallet.as_mut().expect("wallet instance");
+            if !wallet_ref.verify_passphrase(passphrase.clone()) {
+                return Err(anyhow!("Wrong passphrase"));
+            }
+            if wallet_ref.get_did_document(&key_name).is_some() {
+                return Err(anyhow!("Key with name '{}' already exists", name));
+            }
+            wallet_ref.generate_keypair(key_name.clone());
+            wallet_ref
+                .get_did_document(&key_name)
+                .ok_or(anyhow!("Couldn't get DID document for new key"))?
+                .id
+        };
+
+        self.save(passphrase);
+        Ok(AgentKey { name, did })
+    }
+
+    pub fn named_keys(&self) -> Vec<AgentKey> {
         let wallet_instance = Wallet::instance();
-        let mut wallet = wallet_instance.lock().expect("wallet lock");
-        let wallet_ref: &mut Wallet = wallet.as_mut().expect("wallet instance");
-        wallet_ref.unlock(password)
+        let wallet = wallet_instance.lock().expect("wallet lock");
+        let wallet_ref = match wallet.as_ref() {
+            Some(wallet) => wallet,
+            None => return vec![],
+        };
+        let prefix = named_key_name("");
+        let mut keys: Vec<AgentKey> = wallet_ref
+            .key_names()
+            .into_iter()
+            .filter_map(|key_name| {
+                let name = key_name.strip_prefix(&prefix)?.to_string();
+                let did = wallet_ref.get_did_document(&key_name)?.id;
+                Some(AgentKey { name, did })
+            })
+            .collect();
+        keys.sort_by(|a, b| a.name.cmp(&b.name));
+        keys
+    }
+
+    /// Unlocks the wallet, migrating keystores written in an older cipher
+    /// format or with other KDF parameters to the current ones.
+    pub fn unlock(&self, password: String) -> Result<(), AnyError> {
+        let needs_upgrade = {
+            let wallet_instance = Wallet::instance();
+            let mut wallet = wallet_instance.lock().expect("wallet lock");
+            let wallet_ref: &mut Wallet = wallet.as_mut().expect("wallet instance");
+            let needs_upgrade = wallet_ref.cipher_needs_upgrade();
+            wallet_ref.unlock(password.clone())?;
+            needs_upgrade
+        };
+
+        if needs_upgrade && self.is_initialized() && self.did.is_some() {
+            log::info!("Re-encrypting agent keystore with current cipher parameters");
+            self.save(password);
+        }
+
+        if let Err(e) = capabilities::apps_map::seal_plaintext_apps() {
+            log::error!("Failed to encrypt stored app auth info: {}", e);
+        }
+
+        Ok(())
     }
 
     pub fn lock(&self, password: String) {
@@ -327,6 +799,9 @@ impl AgentService {
                 did,
                 perspective: Some(Perspective { links: vec![] }),
                 direct_message_language: None,
+                encryption_key: None,
+                device_proofs: None,
+                key_successions: None,
             });
         }
     }
@@ -338,6 +813,7 @@ impl AgentService {
             is_initialized: self.is_initialized(),
             is_unlocked: self.is_unlocked(),
             error: None,
+            mnemonic: None,
         }
     }
 }
@@ -420,6 +896,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn named_keys_sign_under_their_own_did() {
+        ensure_setup();
+        let did_document = {
+            let wallet_instance = Wallet::instance();
+            let mut wallet = wallet_instance.lock().expect("wallet lock");
+            let wallet_ref = wallet.as_mut().expect("wallet instance");
+            let name = named_key_name("test-app");
+            wallet_ref.generate_keypair(name.clone());
+            wallet_ref.get_did_document(&name).expect("named key")
+        };
+
+        let test_message = "Named key test".to_string();
+        let agent_signature =
+            AgentSignature::from_message_with_key(test_message.clone(), "test-app")
+                .expect("Failed to sign with named key");
+        assert_eq!(
+            agent_signature.public_key,
+            did_document.verification_method[0].id
+        );
+        assert_ne!(did_document.id, did());
+        assert!(verify_string_signed_by_did(
+            &did_document.id,
+            &test_message,
+            &agent_signature.signature
+        )
+        .expect("Verification failed"));
+
+        assert!(AgentSignature::from_message_with_key(test_message, "unknown").is_err());
+    }
+
     //#[test]
     fn _test_create_signed_expression_and_verify_with_changed_sorting() {
         ensure_setup();
@@ -457,4 +964,15 @@ mod tests {
             "Signature verification for create_signed_expression with string data should succeed"
         );
     }
+
+    #[test]
+    fn linked_devices_sign_with_a_key_of_another_did() {
+        let mut agent_service = AgentService::new("/tmp".to_string());
+        agent_service.did = Some("did:key:zAgent".to_string());
+        agent_service.signing_key_id = Some("did:key:zAgent#zAgent".to_string());
+        assert!(!agent_service.is_linked_device());
+
+        agent_service.signing_key_id = Some("did:key:zDevice#zDevice".to_string());
+        assert!(agent_service.is_linked_device());
+    }
 }
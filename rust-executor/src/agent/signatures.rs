@@ -1,3 +1,6 @@
+use crate::db::Ad4mDb;
+use crate::entanglement_service::{device_proofs, request_published_proofs, AD4M_DEVICE_KEY_TYPE};
+use crate::graphql::graphql_types::{Agent, EntanglementProof, KeySuccession};
 use crate::types::Expression;
 use chrono::SecondsFormat;
 use chrono::{DateTime, Utc};
@@ -7,7 +10,43 @@ use did_key::{CoreSign, PatchedKeyPair};
 use log::error;
 use serde::Serialize;
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::RwLock;
+use std::time::Instant;
+
+/// Published key successions are kept for this many retired keys, the ones learned longest
+/// ago get dropped
+const MAX_PUBLISHED_KEY_SUCCESSIONS: usize = 4096;
+
+lazy_static! {
+    /// Our key successions, kept in memory so checking a signature never touches the database
+    static ref KEY_SUCCESSIONS: RwLock<Vec<KeySuccession>> = RwLock::new(Vec::new());
+    /// Key successions other agents published with their agent expression, by retired DID,
+    /// all verified, with when we learned them
+    static ref PUBLISHED_KEY_SUCCESSIONS: RwLock<HashMap<String, (Instant, KeySuccession)>> =
+        RwLock::new(HashMap::new());
+}
+
+/// Outcome of checking the signature of an expression
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SignatureCheck {
+    pub valid: bool,
+    /// Made with a key the author has rotated away from since. Such signatures are accepted
+    /// for timestamps before the rotation, but the timestamp is the signer's own claim, so
+    /// they could also have been made with a leaked key after the rotation.
+    pub retired_key: bool,
+}
+
+/// Reloads our key successions, needs to be called whenever they change in the database
+pub fn load_key_successions() {
+    let successions = Ad4mDb::with_global_instance(|db| db.get_key_successions())
+        .map_err(|e| error!("Failed to load key successions: {}", e))
+        .unwrap_or_default();
+    *KEY_SUCCESSIONS
+        .write()
+        .expect("Couldn't get lock on key successions") = successions;
+}
 
 pub fn verify_string_signed_by_did(
     did: &str,
@@ -20,6 +59,10 @@ pub fn verify_string_signed_by_did(
 }
 
 pub fn verify<T: Serialize>(expr: &Expression<T>) -> Result<bool, AnyError> {
+    Ok(check_signature(expr)?.valid)
+}
+
+pub fn check_signature<T: Serialize>(expr: &Expression<T>) -> Result<SignatureCheck, AnyError> {
     let sig_bytes = hex::decode(&expr.proof.signature)?;
     let timestamp = DateTime::<Utc>::from_str(&expr.timestamp).map_err(|e| {
         anyhow!(
@@ -28,8 +71,203 @@ pub fn verify<T: Serialize>(expr: &Expression<T>) -> Result<bool, AnyError> {
         )
     })?;
     let message = hash_data_and_timestamp(&expr.data, &timestamp);
-    let result = inner_verify(&expr.author, &message, &sig_bytes);
-    Ok(result)
+    let signing_did = expr.proof.key.split('#').next().unwrap_or_default();
+    let with_key_history = verify_with_key_history(
+        &expr.author,
+        &expr.proof.key,
+        &timestamp,
+        &message,
+        &sig_bytes,
+        &successions_for(&expr.author, signing_did),
+    );
+    if let Some(retired_key) = with_key_history {
+        return Ok(SignatureCheck {
+            valid: true,
+            retired_key,
+        });
+    }
+
+    let known_devices = device_proofs(&expr.author);
+    let result = verify_with_device_key(
+        &expr.author,
+        &expr.proof.key,
+        &message,
+        &sig_bytes,
+        &known_devices,
+    );
+    if !result {
+        // Might be a device of the author we didn't hear about yet. Only worth asking the
+        // author if the signing key really made the signature and is no device we know.
+        if signing_did != expr.author
+            && !known_devices
+                .iter()
+                .any(|proof| proof.device_key == signing_did)
+            && inner_verify(signing_did, &message, &sig_bytes)
+        {
+            request_published_proofs(&expr.author);
+        }
+    }
+    Ok(SignatureCheck {
+        valid: result,
+        retired_key: false,
+    })
+}
+
+/// Our key successions and the published ones that lead on from `author` or `signing_did`
+fn successions_for(author: &str, signing_did: &str) -> Vec<KeySuccession> {
+    let mut successions = KEY_SUCCESSIONS
+        .read()
+        .expect("Couldn't get lock on key successions")
+        .clone();
+    let published = PUBLISHED_KEY_SUCCESSIONS
+        .read()
+        .expect("Couldn't get lock on published key successions");
+    for start in [author, signing_did] {
+        let mut current = start;
+        while let Some((_, succession)) = published.get(current) {
+            if successions.contains(succession) {
+                break;
+            }
+            successions.push(succession.clone());
+            current = succession.new_did.as_str();
+        }
+    }
+    successions
+}
+
+fn succession_timestamp(succession: &KeySuccession) -> Option<DateTime<Utc>> {
+    DateTime::<Utc>::from_str(&succession.timestamp).ok()
+}
+
+/// Takes the key successions `agent` published, so signatures of its previous keys verify.
+/// Only verified successions along the chain that ends in the agent's DID are kept. A key
+/// gets retired once: the earliest succession we saw wins, so a leaked previous key can't
+/// move its own retirement to later.
+pub fn learn_published_key_successions(agent: &Agent) {
+    let published = match &agent.key_successions {
+        Some(published) => published,
+        None => return,
+    };
+    let mut chain = Vec::new();
+    let mut current = agent.did.clone();
+    while chain.len() < published.len() {
+        let previous = published.iter().find(|succession| {
+            succession.new_did == current && verify_key_succession(succession).unwrap_or(false)
+        });
+        match previous {
+            Some(succession) => {
+                current = succession.previous_did.clone();
+                chain.push(succession.clone());
+            }
+            None => break,
+        }
+    }
+
+    let mut known = PUBLISHED_KEY_SUCCESSIONS
+        .write()
+        .expect("Couldn't get lock on published key successions");
+    let now = Instant::now();
+    for succession in chain {
+        let timestamp = match succession_timestamp(&succession) {
+            Some(timestamp) => timestamp,
+            None => continue,
+        };
+        match known.get(&succession.previous_did) {
+            Some((_, earlier))
+                if succession_timestamp(earlier).map_or(false, |at| at <= timestamp) =>
+            {
+                continue
+            }
+            Some(_) => {}
+            None if known.len() >= MAX_PUBLISHED_KEY_SUCCESSIONS => {
+                let oldest = known
+                    .iter()
+                    .min_by_key(|(_, (learned_at, _))| *learned_at)
+                    .map(|(did, _)| did.clone());
+                if let Some(oldest) = oldest {
+                    known.remove(&oldest);
+                }
+            }
+            None => {}
+        }
+        known.insert(succession.previous_did.clone(), (now, succession));
+    }
+}
+
+pub fn verify_key_succession(succession: &KeySuccession) -> Result<bool, AnyError> {
+    let sig_bytes = hex::decode(&succession.signature)?;
+    let timestamp = DateTime::<Utc>::from_str(&succession.timestamp)
+        .map_err(|e| anyhow!("Failed to parse key succession timestamp: {}", e))?;
+    let message = hash_data_and_timestamp(&succession.new_did, &timestamp);
+    Ok(inner_verify(&succession.previous_did, &message, &sig_bytes))
+}
+
+fn retired_at(did: &str, successions: &[KeySuccession]) -> Option<DateTime<Utc>> {
+    successions
+        .iter()
+        .find(|s| s.previous_did == did)
+        .and_then(|s| DateTime::<Utc>::from_str(&s.timestamp).ok())
+}
+
+/// True if `ancestor` is a previous key of `did` according to the succession chain
+fn is_previous_key_of(ancestor: &str, did: &str, successions: &[KeySuccession]) -> bool {
+    let mut current = did.to_string();
+    for _ in 0..successions.len() {
+        match successions.iter().find(|s| s.new_did == current) {
+            Some(succession) if succession.previous_did == ancestor => return true,
+            Some(succession) => current = succession.previous_did.clone(),
+            None => return false,
+        }
+    }
+    false
+}
+
+/// Verifies a signature made by `author` or one of its previous keys.
+/// A key is only accepted for data timestamped before it was rotated away from.
+/// Returns whether the signing key is retired, `None` if the signature doesn't verify.
+fn verify_with_key_history(
+    author: &str,
+    key_id: &str,
+    timestamp: &DateTime<Utc>,
+    message: &[u8],
+    signature: &[u8],
+    successions: &[KeySuccession],
+) -> Option<bool> {
+    let signed_by = |did: &str| match retired_at(did, successions) {
+        Some(retired) if *timestamp >= retired => None,
+        retired if inner_verify(did, message, signature) => Some(retired.is_some()),
+        _ => None,
+    };
+
+    if let Some(retired_key) = signed_by(author) {
+        return Some(retired_key);
+    }
+
+    let signing_did = key_id.split('#').next().unwrap_or_default();
+    if signing_did != author && is_previous_key_of(signing_did, author, successions) {
+        signed_by(signing_did)
+    } else {
+        None
+    }
+}
+
+/// Verifies a signature made by another device of `author`, which is linked to it
+/// through an entanglement proof. Proofs get verified before they are stored.
+fn verify_with_device_key(
+    author: &str,
+    key_id: &str,
+    message: &[u8],
+    signature: &[u8],
+    proofs: &[EntanglementProof],
+) -> bool {
+    let signing_did = key_id.split('#').next().unwrap_or_default();
+    signing_did != author
+        && proofs.iter().any(|proof| {
+            proof.device_key_type == AD4M_DEVICE_KEY_TYPE
+                && proof.did == author
+                && proof.device_key == signing_did
+        })
+        && inner_verify(signing_did, message, signature)
 }
 
 pub(super) fn hash_data_and_timestamp<T: Serialize>(
@@ -70,3 +308,398 @@ fn inner_verify(did: &str, message: &[u8], signature: &[u8]) -> bool {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::Wallet;
+    use chrono::Duration;
+
+    struct TestKey {
+        did: String,
+        name: String,
+    }
+
+    fn generate_key(wallet: &mut Wallet, name: &str) -> TestKey {
+        wallet.generate_keypair(name.to_string());
+        TestKey {
+            did: wallet.get_did_document(&name.to_string()).unwrap().id,
+            name: name.to_string(),
+        }
+    }
+
+    fn sign_at(
+        wallet: &Wallet,
+        key: &TestKey,
+        data: &str,
+        timestamp: &DateTime<Utc>,
+    ) -> (Vec<u8>, Vec<u8>) {
+        let message = hash_data_and_timestamp(&data, timestamp);
+        let signature = wallet.sign(&key.name, &message).unwrap();
+        (message, signature)
+    }
+
+    fn succession(
+        wallet: &Wallet,
+        from: &TestKey,
+        to: &TestKey,
+        timestamp: DateTime<Utc>,
+    ) -> KeySuccession {
+        let (_, signature) = sign_at(wallet, from, &to.did, &timestamp);
+        KeySuccession {
+            previous_did: from.did.clone(),
+            new_did: to.did.clone(),
+            timestamp: timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            signature: hex::encode(signature),
+        }
+    }
+
+    #[test]
+    fn key_succession_statements_verify_against_the_previous_key() {
+        let mut wallet = Wallet::new();
+        let old_key = generate_key(&mut wallet, "old");
+        let new_key = generate_key(&mut wallet, "new");
+        let statement = succession(&wallet, &old_key, &new_key, Utc::now());
+
+        assert!(verify_key_succession(&statement).unwrap());
+
+        let mut forged = statement.clone();
+        forged.new_did = old_key.did.clone();
+        assert!(!verify_key_succession(&forged).unwrap());
+    }
+
+    #[test]
+    fn previous_keys_verify_within_their_validity_window_only() {
+        let mut wallet = Wallet::new();
+        let old_key = generate_key(&mut wallet, "old");
+        let new_key = generate_key(&mut wallet, "new");
+        let rotated_at = Utc::now();
+        let successions = vec![succession(&wallet, &old_key, &new_key, rotated_at)];
+
+        let before = rotated_at - Duration::hours(1);
+        let (message, signature) = sign_at(&wallet, &old_key, "data", &before);
+        let old_key_id = format!("{}#key", old_key.did);
+
+        // Signed by the old key as itself and on behalf of its successor, flagged as retired
+        assert_eq!(
+            verify_with_key_history(
+                &old_key.did,
+                &old_key_id,
+                &before,
+                &message,
+                &signature,
+                &successions
+            ),
+            Some(true)
+        );
+        assert_eq!(
+            verify_with_key_history(
+                &new_key.did,
+                &old_key_id,
+                &before,
+                &message,
+                &signature,
+                &successions
+            ),
+            Some(true)
+        );
+
+        // The current key isn't
+        let (message, signature) = sign_at(&wallet, &new_key, "data", &before);
+        assert_eq!(
+            verify_with_key_history(
+                &new_key.did,
+                &format!("{}#key", new_key.did),
+                &before,
+                &message,
+                &signature,
+                &successions
+            ),
+            Some(false)
+        );
+
+        // After the rotation the old key is no longer accepted
+        let after = rotated_at + Duration::hours(1);
+        let (message, signature) = sign_at(&wallet, &old_key, "data", &after);
+        assert!(verify_with_key_history(
+            &old_key.did,
+            &old_key_id,
+            &after,
+            &message,
+            &signature,
+            &successions
+        )
+        .is_none());
+        assert!(verify_with_key_history(
+            &new_key.did,
+            &old_key_id,
+            &after,
+            &message,
+            &signature,
+            &successions
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn unrelated_keys_are_not_accepted_for_an_author() {
+        let mut wallet = Wallet::new();
+        let author = generate_key(&mut wallet, "author");
+        let stranger = generate_key(&mut wallet, "stranger");
+        let timestamp = Utc::now();
+        let (message, signature) = sign_at(&wallet, &stranger, "data", &timestamp);
+
+        assert!(verify_with_key_history(
+            &author.did,
+            &format!("{}#key", stranger.did),
+            &timestamp,
+            &message,
+            &signature,
+            &[]
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn previous_keys_are_found_along_the_succession_chain() {
+        let mut wallet = Wallet::new();
+        let first = generate_key(&mut wallet, "first");
+        let second = generate_key(&mut wallet, "second");
+        let third = generate_key(&mut wallet, "third");
+        let now = Utc::now();
+        let successions = vec![
+            succession(&wallet, &first, &second, now),
+            succession(&wallet, &second, &third, now),
+        ];
+
+        assert!(is_previous_key_of(&first.did, &third.did, &successions));
+        assert!(is_previous_key_of(&second.did, &third.did, &successions));
+        assert!(!is_previous_key_of(&third.did, &first.did, &successions));
+    }
+
+    #[test]
+    fn linked_devices_sign_for_the_agent() {
+        let mut wallet = Wallet::new();
+        let agent = generate_key(&mut wallet, "agent");
+        let device = generate_key(&mut wallet, "device");
+        let stranger = generate_key(&mut wallet, "stranger");
+        let proofs = vec![EntanglementProof {
+            did: agent.did.clone(),
+            device_key_type: AD4M_DEVICE_KEY_TYPE.to_string(),
+            device_key: device.did.clone(),
+            ..Default::default()
+        }];
+        let timestamp = Utc::now();
+
+        let (message, signature) = sign_at(&wallet, &device, "data", &timestamp);
+        assert!(verify_with_device_key(
+            &agent.did,
+            &format!("{}#key", device.did),
+            &message,
+            &signature,
+            &proofs
+        ));
+
+        let (message, signature) = sign_at(&wallet, &stranger, "data", &timestamp);
+        assert!(!verify_with_device_key(
+            &agent.did,
+            &format!("{}#key", stranger.did),
+            &message,
+            &signature,
+            &proofs
+        ));
+        // The device only signs for the agent, not the other way around
+        let (message, signature) = sign_at(&wallet, &agent, "data", &timestamp);
+        assert!(!verify_with_device_key(
+            &device.did,
+            &format!("{}#key", agent.did),
+            &message,
+            &signature,
+            &proofs
+        ));
+    }
+
+    #[test]
+    fn key_history_is_checked_without_the_database() {
+        use crate::types::ExpressionProof;
+
+        let mut wallet = Wallet::new();
+        let old_key = generate_key(&mut wallet, "rotated-old");
+        let new_key = generate_key(&mut wallet, "rotated-new");
+        let rotated_at = Utc::now();
+        let statement = succession(&wallet, &old_key, &new_key, rotated_at);
+
+        Ad4mDb::init_global_instance(":memory:").unwrap();
+        Ad4mDb::with_global_instance(|db| db.add_key_succession(&statement)).unwrap();
+        load_key_successions();
+
+        let before = rotated_at - Duration::hours(1);
+        let (_, signature) = sign_at(&wallet, &old_key, "data", &before);
+        let expression = Expression {
+            author: new_key.did.clone(),
+            timestamp: before.to_rfc3339_opts(SecondsFormat::Millis, true),
+            data: "data",
+            proof: ExpressionProof {
+                key: format!("{}#key", old_key.did),
+                signature: hex::encode(signature),
+            },
+        };
+
+        // Callers that already hold the database lock can verify signatures
+        let check = Ad4mDb::with_global_instance(|_| check_signature(&expression).unwrap());
+        assert_eq!(
+            check,
+            SignatureCheck {
+                valid: true,
+                retired_key: true
+            }
+        );
+    }
+
+    #[test]
+    fn key_successions_published_by_other_agents_verify() {
+        use crate::types::ExpressionProof;
+
+        // Keys of another agent that rotated twice, none of them in our database
+        let mut wallet = Wallet::new();
+        let first = generate_key(&mut wallet, "published-first");
+        let second = generate_key(&mut wallet, "published-second");
+        let third = generate_key(&mut wallet, "published-third");
+        let stranger = generate_key(&mut wallet, "published-stranger");
+        let rotated_at = Utc::now();
+        let successions = vec![
+            succession(&wallet, &first, &second, rotated_at),
+            succession(&wallet, &second, &third, rotated_at),
+        ];
+
+        let expression = |key: &TestKey, timestamp: DateTime<Utc>| {
+            let (_, signature) = sign_at(&wallet, key, "data", &timestamp);
+            Expression {
+                author: third.did.clone(),
+                timestamp: timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+                data: "data",
+                proof: ExpressionProof {
+                    key: format!("{}#key", key.did),
+                    signature: hex::encode(signature),
+                },
+            }
+        };
+        let before = rotated_at - Duration::hours(1);
+        let signed_by_first = expression(&first, before);
+        assert!(!verify(&signed_by_first).unwrap());
+
+        // Successions that don't lead to the publishing agent are ignored
+        learn_published_key_successions(&Agent {
+            did: stranger.did.clone(),
+            key_successions: Some(successions.clone()),
+            ..Default::default()
+        });
+        assert!(!verify(&signed_by_first).unwrap());
+
+        learn_published_key_successions(&Agent {
+            did: third.did.clone(),
+            key_successions: Some(successions.clone()),
+            ..Default::default()
+        });
+        assert_eq!(
+            check_signature(&signed_by_first).unwrap(),
+            SignatureCheck {
+                valid: true,
+                retired_key: true
+            }
+        );
+        assert!(!verify(&expression(&first, rotated_at + Duration::hours(1))).unwrap());
+
+        // A later succession of the same key doesn't extend its validity
+        let later = rotated_at + Duration::days(1);
+        learn_published_key_successions(&Agent {
+            did: stranger.did.clone(),
+            key_successions: Some(vec![succession(&wallet, &first, &stranger, later)]),
+            ..Default::default()
+        });
+        let after = rotated_at + Duration::hours(1);
+        let (_, signature) = sign_at(&wallet, &first, "data", &after);
+        let for_stranger = Expression {
+            author: stranger.did.clone(),
+            timestamp: after.to_rfc3339_opts(SecondsFormat::Millis, true),
+            data: "data",
+            proof: ExpressionProof {
+                key: format!("{}#key", first.did),
+                signature: hex::encode(signature),
+            },
+        };
+        assert!(!verify(&for_stranger).unwrap());
+    }
+
+    #[test]
+    fn devices_published_by_other_agents_verify() {
+        use crate::entanglement_service::learn_published_proofs;
+        use crate::graphql::graphql_types::Agent;
+        use crate::types::ExpressionProof;
+
+        // Keys of another agent and its linked device, none of them in our database
+        let mut wallet = Wallet::new();
+        let agent = generate_key(&mut wallet, "other-agent");
+        let device = generate_key(&mut wallet, "other-device");
+        let sign_hex = |key: &TestKey, data: &str| {
+            hex::encode(
+                wallet
+                    .sign(&key.name, &hash_message(&data.to_string()))
+                    .unwrap(),
+            )
+        };
+        let proof = EntanglementProof {
+            did: agent.did.clone(),
+            did_signing_key_id: format!("{}#key", agent.did),
+            device_key_type: AD4M_DEVICE_KEY_TYPE.to_string(),
+            device_key: device.did.clone(),
+            device_key_signed_by_did: sign_hex(&agent, &device.did),
+            did_signed_by_device_key: Some(sign_hex(&device, &agent.did)),
+        };
+
+        let timestamp = Utc::now();
+        let (_, signature) = sign_at(&wallet, &device, "data", &timestamp);
+        let expression = Expression {
+            author: agent.did.clone(),
+            timestamp: timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            data: "data",
+            proof: ExpressionProof {
+                key: format!("{}#key", device.did),
+                signature: hex::encode(signature),
+            },
+        };
+        assert!(!verify(&expression).unwrap());
+
+        // Someone else publishing the proof doesn't make the device the agent's
+        learn_published_proofs(&Agent {
+            did: device.did.clone(),
+            device_proofs: Some(vec![proof.clone()]),
+            ..Default::default()
+        });
+        assert!(!verify(&expression).unwrap());
+
+        // Neither does a proof the device didn't sign
+        let mut forged = proof.clone();
+        forged.did_signed_by_device_key = Some(sign_hex(&agent, &agent.did));
+        learn_published_proofs(&Agent {
+            did: agent.did.clone(),
+            device_proofs: Some(vec![forged]),
+            ..Default::default()
+        });
+        assert!(!verify(&expression).unwrap());
+
+        learn_published_proofs(&Agent {
+            did: agent.did.clone(),
+            device_proofs: Some(vec![proof]),
+            ..Default::default()
+        });
+        assert!(verify(&expression).unwrap());
+
+        // Proofs the agent doesn't publish anymore stop counting
+        learn_published_proofs(&Agent {
+            did: agent.did.clone(),
+            ..Default::default()
+        });
+        assert!(!verify(&expression).unwrap());
+    }
+}
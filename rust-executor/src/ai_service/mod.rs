@@ -2,6 +2,7 @@ use self::{audio_stream::AudioStream, error::AIServiceError};
 use crate::graphql::graphql_types::ModelInput;
 #[allow(unused_imports)]
 use crate::graphql::graphql_types::{AIModelLoadingStatus, AITaskInput, TranscriptionTextFilter};
+use crate::metrics::AI_TASK_DURATION;
 use crate::pubsub::AI_MODEL_LOADING_STATUS;
 #[allow(unused_imports)]
 use crate::pubsub::AI_TRANSCRIPTION_TEXT_TOPIC;
@@ -22,7 +23,7 @@ use std::panic::catch_unwind;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, oneshot, Mutex};
 use tokio::time::sleep;
 
@@ -687,6 +688,7 @@ impl AIService {
     }
 
     pub async fn prompt(&self, task_id: String, prompt: String) -> Result<String> {
+        let started = Instant::now();
         let (result_sender, rx) = oneshot::channel();
 
         // Retrieve the task to find the associated model_id
@@ -710,7 +712,9 @@ impl AIService {
             ));
         }
 
-        rx.await?
+        let result = rx.await?;
+        AI_TASK_DURATION.observe(&[("task", "prompt")], started.elapsed());
+        result
     }
 
     // -------------------------------------
@@ -773,6 +777,7 @@ impl AIService {
     }
 
     pub async fn embed(&self, model_id: String, text: String) -> Result<Vec<f32>> {
+        let started = Instant::now();
         let (result_sender, rx) = oneshot::channel();
         let embedding_channel = self.embedding_channel.lock().await;
         if let Some(sender) = embedding_channel.get(&model_id) {
@@ -788,7 +793,9 @@ impl AIService {
             ));
         }
 
-        rx.await?
+        let result = rx.await?;
+        AI_TASK_DURATION.observe(&[("task", "embed")], started.elapsed());
+        result
     }
 
     // -------------------------------------
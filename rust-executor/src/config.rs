@@ -1,5 +1,10 @@
+use crate::agent::capabilities::rate_limits::RateLimitConfig;
+pub use crate::holochain_service::call_policy::ZomeCallPolicyConfig;
 use crate::utils;
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
 use serde::{Deserialize, Serialize};
+use std::io::Read;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -8,6 +13,13 @@ pub struct TlsConfig {
     pub key_file_path: String,
 }
 
+/// Recovery phrase to recreate the agent from, and the passphrase to encrypt its keys with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentRestoreConfig {
+    pub mnemonic: String,
+    pub passphrase: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Ad4mConfig {
@@ -32,6 +44,66 @@ pub struct Ad4mConfig {
     pub auto_permit_cap_requests: Option<bool>,
     pub tls: Option<TlsConfig>,
     pub log_holochain_metrics: Option<bool>,
+    pub rate_limits: Option<Vec<RateLimitConfig>>,
+    /// Argon2 memory cost in KiB used to encrypt the agent's keystore
+    pub wallet_kdf_memory_cost: Option<u32>,
+    /// Argon2 iterations used to encrypt the agent's keystore
+    pub wallet_kdf_iterations: Option<u32>,
+    /// Seconds Rust waits for an answer from the JS core, defaults to 60.
+    /// Holochain setup and language installs get longer.
+    pub js_call_timeout_secs: Option<u64>,
+    /// Directory or zip archive of language bundles to add to the bundle cache at startup,
+    /// so they can be installed without network
+    pub language_bundle_seed: Option<String>,
+    /// Timeouts and retries of Holochain zome calls per app, zome or function
+    pub zome_call_policies: Option<Vec<ZomeCallPolicyConfig>>,
+    /// Serves metrics in OpenMetrics format on `/metrics`, off by default
+    pub metrics_endpoint: Option<bool>,
+    /// Serves `/metrics` on this port instead of the GraphQL port
+    pub metrics_port: Option<u16>,
+    /// Binds the metrics port to 127.0.0.1 only, defaults to true
+    pub metrics_localhost: Option<bool>,
+    /// Restores the agent from its recovery phrase at startup if there is no agent yet.
+    /// Not passed on to the JS core.
+    #[serde(skip_serializing)]
+    pub restore_agent_from_mnemonic: Option<AgentRestoreConfig>,
+}
+
+/// Reads zome call policies from a JSON file holding an array of policies,
+/// e.g. `[{ "zomeName": "perspective_diff_sync", "timeoutMs": 30000, "retries": 2 }]`
+pub fn read_zome_call_policies(path: &str) -> Result<Vec<ZomeCallPolicyConfig>, AnyError> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Couldn't read zome call policies from {}: {}", path, e))?;
+    serde_json::from_str(&json)
+        .map_err(|e| anyhow!("Invalid zome call policies in {}: {}", path, e))
+}
+
+/// Reads the recovery phrase and the passphrase to encrypt the restored keys with,
+/// one per line, from the file at `path` or from stdin if `path` is `-`.
+/// This keeps both out of the process arguments.
+pub fn read_agent_restore_config(path: &str) -> Result<AgentRestoreConfig, AnyError> {
+    let input = if path == "-" {
+        let mut input = String::new();
+        std::io::stdin()
+            .read_to_string(&mut input)
+            .map_err(|e| anyhow!("Couldn't read recovery phrase from stdin: {}", e))?;
+        input
+    } else {
+        std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Couldn't read recovery phrase from {}: {}", path, e))?
+    };
+    let mut lines = input.lines();
+    match (lines.next(), lines.next()) {
+        (Some(mnemonic), Some(passphrase)) if !mnemonic.trim().is_empty() => {
+            Ok(AgentRestoreConfig {
+                mnemonic: mnemonic.trim().to_string(),
+                passphrase: passphrase.to_string(),
+            })
+        }
+        _ => Err(anyhow!(
+            "Expected the recovery phrase on the first and the passphrase on the second line"
+        )),
+    }
 }
 
 impl Ad4mConfig {
@@ -87,6 +159,12 @@ impl Ad4mConfig {
         if self.log_holochain_metrics.is_none() {
             self.log_holochain_metrics = Some(true);
         }
+        if self.metrics_endpoint.is_none() {
+            self.metrics_endpoint = Some(false);
+        }
+        if self.metrics_localhost.is_none() {
+            self.metrics_localhost = Some(true);
+        }
     }
 
     pub fn get_json(&self) -> String {
@@ -116,6 +194,16 @@ impl Default for Ad4mConfig {
             auto_permit_cap_requests: None,
             tls: None,
             log_holochain_metrics: None,
+            rate_limits: None,
+            wallet_kdf_memory_cost: None,
+            wallet_kdf_iterations: None,
+            js_call_timeout_secs: None,
+            language_bundle_seed: None,
+            zome_call_policies: None,
+            metrics_endpoint: None,
+            metrics_port: None,
+            metrics_localhost: None,
+            restore_agent_from_mnemonic: None,
         };
         config.prepare();
         config
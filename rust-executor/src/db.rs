@@ -1,10 +1,19 @@
+use crate::agent::capabilities::apps_map::StoredApp;
 use crate::graphql::graphql_types::{
-    AIModelLoadingStatus, EntanglementProof, LinkStatus, ModelInput, NotificationInput,
-    PerspectiveExpression, PerspectiveHandle, SentMessage,
+    AIModelLoadingStatus, Conversation, ConversationMessage, EntanglementProof, KeySuccession,
+    LanguagePermissions, LinkStatus, MessageReadReceipt, ModelInput, NotificationInput,
+    PerspectiveExpression, PerspectiveHandle, ScheduledJobInput, SentMessage,
+};
+use crate::runtime_service::notification_throttle::PendingNotificationMatch;
+use crate::runtime_service::webhooks::{
+    generate_signing_secret, DeliveryOutcome, QueuedWebhookDelivery,
 };
 use crate::types::{
-    AIPromptExamples, AITask, Expression, ExpressionProof, Link, LinkExpression, LocalModel, Model,
-    ModelApi, ModelApiType, ModelType, Notification, PerspectiveDiff,
+    AIPromptExamples, AITask, Expression, ExpressionProof, FriendRequest, FriendRequestDirection,
+    FriendRequestState, Link, LinkExpression, LocalModel, MessageDeliveryStatus, Model, ModelApi,
+    ModelApiType, ModelType, Notification, NotificationChannel, NotificationThrottle,
+    NotificationTriggerType, PerspectiveDiff, ScheduledJob, ScheduledJobRun, WebhookDelivery,
+    WebhookDeliveryAttempt, WebhookDeliveryStatus,
 };
 use deno_core::anyhow::anyhow;
 use deno_core::error::AnyError;
@@ -35,6 +44,12 @@ struct ExpressionSchema {
 
 pub type Ad4mDbResult<T> = Result<T, AnyError>;
 
+fn unix_to_rfc3339(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .to_rfc3339()
+}
+
 use std::sync::{Arc, Mutex};
 
 lazy_static! {
@@ -136,7 +151,125 @@ impl Ad4mDb {
             "CREATE TABLE IF NOT EXISTS outbox (
                 id INTEGER PRIMARY KEY,
                 message TEXT NOT NULL,
-                recipient TEXT NOT NULL
+                recipient TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'storedInInbox',
+                attempts INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                created_at INTEGER,
+                next_attempt_at INTEGER,
+                encrypted BOOLEAN NOT NULL DEFAULT 0
+             )",
+            [],
+        )?;
+        // Outboxes from before delivery states existed only hold messages that got delivered
+        // one way or the other, so they default to the more conservative inbox state
+        Self::add_column_if_missing(
+            &conn,
+            "outbox",
+            "status",
+            "TEXT NOT NULL DEFAULT 'storedInInbox'",
+        )?;
+        Self::add_column_if_missing(&conn, "outbox", "attempts", "INTEGER NOT NULL DEFAULT 0")?;
+        Self::add_column_if_missing(&conn, "outbox", "last_error", "TEXT")?;
+        Self::add_column_if_missing(&conn, "outbox", "created_at", "INTEGER")?;
+        Self::add_column_if_missing(&conn, "outbox", "next_attempt_at", "INTEGER")?;
+        Self::add_column_if_missing(&conn, "outbox", "encrypted", "BOOLEAN NOT NULL DEFAULT 0")?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS inbox (
+                id INTEGER PRIMARY KEY,
+                author TEXT NOT NULL,
+                timestamp TEXT NOT NULL,
+                signature TEXT NOT NULL,
+                message TEXT NOT NULL,
+                received_at INTEGER NOT NULL,
+                UNIQUE(author, timestamp, signature)
+             )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS friend_requests (
+                did TEXT PRIMARY KEY,
+                direction TEXT NOT NULL,
+                state TEXT NOT NULL,
+                message TEXT,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+             )",
+            [],
+        )?;
+        Self::migrate_legacy_friends(&conn)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS trust_statements (
+                issuer TEXT NOT NULL,
+                subject TEXT NOT NULL,
+                timestamp TEXT NOT NULL,
+                key TEXT NOT NULL,
+                signature TEXT NOT NULL,
+                PRIMARY KEY (issuer, subject)
+             )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS trust_config (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                max_depth INTEGER NOT NULL,
+                threshold INTEGER NOT NULL
+             )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS language_permissions (
+                address TEXT PRIMARY KEY,
+                permissions TEXT NOT NULL
+             )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS conversations (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                created_by TEXT NOT NULL,
+                encrypted BOOLEAN NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+             )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS conversation_members (
+                conversation_id TEXT NOT NULL,
+                did TEXT NOT NULL,
+                UNIQUE(conversation_id, did)
+             )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS conversation_messages (
+                id TEXT PRIMARY KEY,
+                conversation_id TEXT NOT NULL,
+                author TEXT NOT NULL,
+                reply_to TEXT,
+                message TEXT NOT NULL,
+                sent_at INTEGER NOT NULL,
+                read_at INTEGER
+             )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS message_read_receipts (
+                message_id TEXT NOT NULL,
+                reader TEXT NOT NULL,
+                read_at INTEGER NOT NULL,
+                UNIQUE(message_id, reader)
              )",
             [],
         )?;
@@ -149,6 +282,25 @@ impl Ad4mDb {
             [],
         )?;
 
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS device_sync_key (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                sealed_key TEXT NOT NULL
+             )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS key_succession (
+                id INTEGER PRIMARY KEY,
+                previous_did TEXT NOT NULL UNIQUE,
+                new_did TEXT NOT NULL,
+                timestamp TEXT NOT NULL,
+                signature TEXT NOT NULL
+             )",
+            [],
+        )?;
+
         // Start Generation Here
         conn.execute(
             "CREATE TABLE IF NOT EXISTS notifications (
@@ -161,7 +313,122 @@ impl Ad4mDb {
                 trigger TEXT NOT NULL,
                 perspective_ids TEXT NOT NULL,
                 webhookUrl TEXT NOT NULL,
-                webhookAuth TEXT NOT NULL
+                webhookAuth TEXT NOT NULL,
+                triggerType TEXT NOT NULL DEFAULT 'prolog',
+                channels TEXT NOT NULL DEFAULT '[]',
+                throttle TEXT,
+                webhookSigningSecret TEXT NOT NULL DEFAULT ''
+             )",
+            [],
+        )?;
+        // Notification tables created before trigger types, channels and throttling existed
+        Self::add_column_if_missing(
+            &conn,
+            "notifications",
+            "triggerType",
+            "TEXT NOT NULL DEFAULT 'prolog'",
+        )?;
+        Self::add_column_if_missing(
+            &conn,
+            "notifications",
+            "channels",
+            "TEXT NOT NULL DEFAULT '[]'",
+        )?;
+        Self::add_column_if_missing(&conn, "notifications", "throttle", "TEXT")?;
+        // Webhooks used to be signed with their auth token, which is also sent as bearer token
+        Self::add_column_if_missing(
+            &conn,
+            "notifications",
+            "webhookSigningSecret",
+            "TEXT NOT NULL DEFAULT ''",
+        )?;
+        conn.execute(
+            "UPDATE notifications SET webhookSigningSecret = lower(hex(randomblob(32)))
+             WHERE webhookSigningSecret = ''",
+            [],
+        )?;
+        // Only the app that installed a notification gets to see its signing secret
+        Self::add_column_if_missing(
+            &conn,
+            "notifications",
+            "installerTokenHash",
+            "TEXT NOT NULL DEFAULT ''",
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS notification_pending_matches (
+                id INTEGER PRIMARY KEY,
+                notification_id TEXT NOT NULL,
+                perspective_id TEXT NOT NULL,
+                trigger_match TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+             )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS scheduled_jobs (
+                id TEXT PRIMARY KEY,
+                perspective_uuid TEXT NOT NULL,
+                name TEXT NOT NULL,
+                schedule TEXT NOT NULL,
+                job_type TEXT NOT NULL,
+                query TEXT,
+                commands TEXT,
+                task_id TEXT,
+                prompt TEXT,
+                paused BOOLEAN NOT NULL,
+                created_at INTEGER NOT NULL,
+                last_run_at INTEGER,
+                next_run_at INTEGER
+             )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS scheduled_job_runs (
+                id INTEGER PRIMARY KEY,
+                job_id TEXT NOT NULL,
+                started_at INTEGER NOT NULL,
+                finished_at INTEGER NOT NULL,
+                success BOOLEAN NOT NULL,
+                output TEXT,
+                error TEXT
+             )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS notification_delivery_log (
+                id INTEGER PRIMARY KEY,
+                notification_id TEXT NOT NULL,
+                perspective_id TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+             )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                id TEXT PRIMARY KEY,
+                notification_id TEXT NOT NULL,
+                url TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                status TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                next_attempt_at INTEGER
+             )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS webhook_delivery_attempts (
+                id INTEGER PRIMARY KEY,
+                delivery_id TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                status_code INTEGER,
+                error TEXT,
+                success BOOLEAN NOT NULL
              )",
             [],
         )?;
@@ -214,6 +481,23 @@ impl Ad4mDb {
             [],
         )?;
 
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS apps (
+                request_id TEXT PRIMARY KEY,
+                auth_info TEXT NOT NULL,
+                token_hash TEXT NOT NULL UNIQUE,
+                revoked BOOLEAN NOT NULL
+            )",
+            [],
+        )?;
+        // Set for apps imported from the plaintext apps_data.json until they got sealed
+        Self::add_column_if_missing(
+            &conn,
+            "apps",
+            "legacy_plaintext",
+            "BOOLEAN NOT NULL DEFAULT 0",
+        )?;
+
         Ok(Self { conn })
     }
 
@@ -351,13 +635,54 @@ impl Ad4mDb {
         Ok(result > 0)
     }
 
+    /// Friends added before the handshake existed were never confirmed by the other side.
+    /// They become pending outgoing requests and only count as friends once accepted.
+    fn migrate_legacy_friends(conn: &Connection) -> Result<(), rusqlite::Error> {
+        conn.execute(
+            "INSERT OR IGNORE INTO friend_requests (did, direction, state, message, created_at, updated_at)
+             SELECT DISTINCT friend, 'outgoing', 'pending', NULL, ?1, ?1 FROM friends",
+            [chrono::Utc::now().timestamp()],
+        )?;
+        conn.execute(
+            "DELETE FROM friends WHERE friend NOT IN
+                (SELECT did FROM friend_requests WHERE state = 'accepted')",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn add_column_if_missing(
+        conn: &Connection,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<(), rusqlite::Error> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let columns = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .collect::<Result<Vec<String>, _>>()?;
+        if !columns.iter().any(|c| c == column) {
+            conn.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+                [],
+            )?;
+        }
+        Ok(())
+    }
+
     pub fn add_notification(
         &self,
         notification: NotificationInput,
     ) -> Result<String, rusqlite::Error> {
         let id = uuid::Uuid::new_v4().to_string();
+        let channels: Vec<NotificationChannel> = notification
+            .channels
+            .unwrap_or_default()
+            .into_iter()
+            .map(NotificationChannel::from)
+            .collect();
         self.conn.execute(
-            "INSERT INTO notifications (id, granted, description, appName, appUrl, appIconPath, trigger, perspective_ids, webhookUrl, webhookAuth) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            "INSERT INTO notifications (id, granted, description, appName, appUrl, appIconPath, trigger, perspective_ids, webhookUrl, webhookAuth, triggerType, channels, throttle, webhookSigningSecret) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
             params![
                 id,
                 false,
@@ -369,6 +694,14 @@ impl Ad4mDb {
                 serde_json::to_string(&notification.perspective_ids).unwrap(),
                 notification.webhook_url,
                 notification.webhook_auth,
+                notification
+                    .trigger_type
+                    .unwrap_or_else(|| NotificationTriggerType::Prolog.to_string()),
+                serde_json::to_string(&channels).unwrap(),
+                notification
+                    .throttle
+                    .map(|t| serde_json::to_string(&NotificationThrottle::from(t)).unwrap()),
+                generate_signing_secret(),
             ],
         )?;
         Ok(id)
@@ -388,6 +721,12 @@ impl Ad4mDb {
                 perspective_ids: serde_json::from_str(&row.get::<_, String>(7)?).unwrap(),
                 webhook_url: row.get(8)?,
                 webhook_auth: row.get(9)?,
+                webhook_signing_secret: row.get(13)?,
+                trigger_type: row.get(10)?,
+                channels: serde_json::from_str(&row.get::<_, String>(11)?).unwrap_or_default(),
+                throttle: row
+                    .get::<_, Option<String>>(12)?
+                    .and_then(|t| serde_json::from_str(&t).ok()),
             })
         })?;
 
@@ -416,25 +755,512 @@ impl Ad4mDb {
                 perspective_ids: serde_json::from_str(&row.get::<_, String>(7)?).unwrap(),
                 webhook_url: row.get(8)?,
                 webhook_auth: row.get(9)?,
+                webhook_signing_secret: row.get(13)?,
+                trigger_type: row.get(10)?,
+                channels: serde_json::from_str(&row.get::<_, String>(11)?).unwrap_or_default(),
+                throttle: row
+                    .get::<_, Option<String>>(12)?
+                    .and_then(|t| serde_json::from_str(&t).ok()),
             }))
         } else {
             Ok(None)
         }
     }
 
-    pub fn remove_notification(&self, id: String) -> Result<(), rusqlite::Error> {
+    pub fn set_notification_installer(
+        &self,
+        id: &str,
+        installer_token_hash: &str,
+    ) -> Result<(), rusqlite::Error> {
+        self.conn.execute(
+            "UPDATE notifications SET installerTokenHash = ?2 WHERE id = ?1",
+            params![id, installer_token_hash],
+        )?;
+        Ok(())
+    }
+
+    /// Hash of the token the notification got installed with, empty if unknown
+    pub fn get_notification_installer(&self, id: &str) -> Result<Option<String>, rusqlite::Error> {
+        self.conn
+            .query_row(
+                "SELECT installerTokenHash FROM notifications WHERE id = ?",
+                [id],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    /// Replaces the webhook signing secret with a new one and returns it,
+    /// `None` if there is no notification with that ID
+    pub fn rotate_notification_webhook_secret(
+        &self,
+        id: &str,
+    ) -> Result<Option<String>, rusqlite::Error> {
+        let secret = generate_signing_secret();
+        let updated = self.conn.execute(
+            "UPDATE notifications SET webhookSigningSecret = ?2 WHERE id = ?1",
+            params![id, secret],
+        )?;
+        Ok((updated > 0).then_some(secret))
+    }
+
+    pub fn remove_notification(&self, id: String) -> Result<(), rusqlite::Error> {
+        self.conn.execute(
+            "DELETE FROM webhook_delivery_attempts WHERE delivery_id IN
+                (SELECT id FROM webhook_deliveries WHERE notification_id = ?)",
+            [id.clone()],
+        )?;
+        self.conn.execute(
+            "DELETE FROM webhook_deliveries WHERE notification_id = ?",
+            [id.clone()],
+        )?;
+        self.conn.execute(
+            "DELETE FROM notification_pending_matches WHERE notification_id = ?",
+            [id.clone()],
+        )?;
+        self.conn.execute(
+            "DELETE FROM notification_delivery_log WHERE notification_id = ?",
+            [id.clone()],
+        )?;
+        self.conn
+            .execute("DELETE FROM notifications WHERE id = ?", [id])?;
+        Ok(())
+    }
+
+    pub fn queue_notification_match(
+        &self,
+        notification_id: &str,
+        perspective_id: &str,
+        trigger_match: &str,
+        created_at: i64,
+    ) -> Result<(), rusqlite::Error> {
+        self.conn.execute(
+            "INSERT INTO notification_pending_matches (notification_id, perspective_id, trigger_match, created_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![notification_id, perspective_id, trigger_match, created_at],
+        )?;
+        Ok(())
+    }
+
+    /// IDs of notifications that have matches waiting to be delivered
+    pub fn get_notifications_with_pending_matches(&self) -> Result<Vec<String>, rusqlite::Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT notification_id FROM notification_pending_matches")?;
+        let ids = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(ids)
+    }
+
+    pub fn get_pending_notification_matches(
+        &self,
+        notification_id: &str,
+    ) -> Result<Vec<PendingNotificationMatch>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, perspective_id, trigger_match, created_at FROM notification_pending_matches
+             WHERE notification_id = ?1 ORDER BY id",
+        )?;
+        let matches = stmt
+            .query_map([notification_id], |row| {
+                Ok(PendingNotificationMatch {
+                    id: row.get(0)?,
+                    perspective_id: row.get(1)?,
+                    trigger_match: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(matches)
+    }
+
+    pub fn remove_pending_notification_matches(&self, ids: &[i64]) -> Result<(), rusqlite::Error> {
+        for id in ids {
+            self.conn.execute(
+                "DELETE FROM notification_pending_matches WHERE id = ?1",
+                [id],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Logs a delivery on a perspective and forgets about deliveries before `prune_before`
+    pub fn record_notification_delivery(
+        &self,
+        notification_id: &str,
+        perspective_id: &str,
+        timestamp: i64,
+        prune_before: i64,
+    ) -> Result<(), rusqlite::Error> {
+        self.conn.execute(
+            "INSERT INTO notification_delivery_log (notification_id, perspective_id, timestamp) VALUES (?1, ?2, ?3)",
+            params![notification_id, perspective_id, timestamp],
+        )?;
+        self.conn.execute(
+            "DELETE FROM notification_delivery_log WHERE notification_id = ?1 AND timestamp < ?2",
+            params![notification_id, prune_before],
+        )?;
+        Ok(())
+    }
+
+    pub fn count_notification_deliveries_since(
+        &self,
+        notification_id: &str,
+        perspective_id: &str,
+        since: i64,
+    ) -> Result<u32, rusqlite::Error> {
+        self.conn.query_row(
+            "SELECT COUNT(*) FROM notification_delivery_log
+             WHERE notification_id = ?1 AND perspective_id = ?2 AND timestamp >= ?3",
+            params![notification_id, perspective_id, since],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn last_notification_delivery(
+        &self,
+        notification_id: &str,
+        perspective_id: &str,
+    ) -> Result<Option<i64>, rusqlite::Error> {
+        self.conn.query_row(
+            "SELECT MAX(timestamp) FROM notification_delivery_log
+             WHERE notification_id = ?1 AND perspective_id = ?2",
+            params![notification_id, perspective_id],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn enqueue_webhook_delivery(
+        &self,
+        notification_id: &str,
+        url: &str,
+        payload: &str,
+        now: i64,
+    ) -> Ad4mDbResult<String> {
+        let id = Uuid::new_v4().to_string();
+        self.conn.execute(
+            "INSERT INTO webhook_deliveries (id, notification_id, url, payload, status, created_at, next_attempt_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)",
+            params![
+                id,
+                notification_id,
+                url,
+                payload,
+                WebhookDeliveryStatus::Pending.to_string(),
+                now
+            ],
+        )?;
+        Ok(id)
+    }
+
+    /// Pending deliveries whose next attempt is due at `now`, oldest first
+    pub fn get_due_webhook_deliveries(&self, now: i64) -> Ad4mDbResult<Vec<QueuedWebhookDelivery>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT d.id, d.notification_id, d.url, d.payload,
+                (SELECT COUNT(*) FROM webhook_delivery_attempts a WHERE a.delivery_id = d.id)
+             FROM webhook_deliveries d
+             WHERE d.status = ?1 AND d.next_attempt_at <= ?2
+             ORDER BY d.next_attempt_at, d.created_at",
+        )?;
+        let delivery_iter = stmt.query_map(
+            params![WebhookDeliveryStatus::Pending.to_string(), now],
+            |row| {
+                Ok(QueuedWebhookDelivery {
+                    id: row.get(0)?,
+                    notification_id: row.get(1)?,
+                    url: row.get(2)?,
+                    payload: row.get(3)?,
+                    attempts: row.get(4)?,
+                })
+            },
+        )?;
+        let deliveries: Result<Vec<_>, _> = delivery_iter.collect();
+        Ok(deliveries?)
+    }
+
+    /// Logs a delivery attempt. The delivery is done if the attempt succeeded,
+    /// otherwise it gets retried at `next_attempt_at` or fails for good if there is none.
+    pub fn record_webhook_delivery_attempt(
+        &self,
+        id: &str,
+        outcome: &DeliveryOutcome,
+        timestamp: i64,
+        next_attempt_at: Option<i64>,
+    ) -> Ad4mDbResult<()> {
+        self.conn.execute(
+            "INSERT INTO webhook_delivery_attempts (delivery_id, timestamp, status_code, error, success)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                id,
+                timestamp,
+                outcome.status_code,
+                outcome.error,
+                outcome.is_success()
+            ],
+        )?;
+
+        let status = if outcome.is_success() {
+            WebhookDeliveryStatus::Delivered
+        } else if next_attempt_at.is_some() {
+            WebhookDeliveryStatus::Pending
+        } else {
+            WebhookDeliveryStatus::Failed
+        };
+        self.conn.execute(
+            "UPDATE webhook_deliveries SET status = ?2, next_attempt_at = ?3 WHERE id = ?1",
+            params![
+                id,
+                status.to_string(),
+                next_attempt_at.filter(|_| status == WebhookDeliveryStatus::Pending)
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_webhook_deliveries(
+        &self,
+        notification_id: &str,
+    ) -> Ad4mDbResult<Vec<WebhookDelivery>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, notification_id, url, status, created_at, next_attempt_at
+             FROM webhook_deliveries WHERE notification_id = ?1 ORDER BY created_at, rowid",
+        )?;
+        let delivery_iter = stmt.query_map([notification_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, Option<i64>>(5)?,
+            ))
+        })?;
+
+        let mut attempts_stmt = self.conn.prepare(
+            "SELECT timestamp, status_code, error, success FROM webhook_delivery_attempts
+             WHERE delivery_id = ?1 ORDER BY id",
+        )?;
+
+        let mut deliveries = Vec::new();
+        for delivery in delivery_iter {
+            let (id, notification_id, url, status, created_at, next_attempt_at) = delivery?;
+            let attempts: Result<Vec<_>, _> = attempts_stmt
+                .query_map([id.clone()], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, Option<i32>>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, bool>(3)?,
+                    ))
+                })?
+                .collect();
+            let attempts = attempts?
+                .into_iter()
+                .enumerate()
+                .map(
+                    |(index, (timestamp, status_code, error, success))| WebhookDeliveryAttempt {
+                        attempt: index as i32 + 1,
+                        timestamp: unix_to_rfc3339(timestamp),
+                        status_code,
+                        error,
+                        success,
+                    },
+                )
+                .collect();
+
+            deliveries.push(WebhookDelivery {
+                id,
+                notification_id,
+                url,
+                status,
+                created_at: unix_to_rfc3339(created_at),
+                next_attempt_at: next_attempt_at.map(unix_to_rfc3339),
+                attempts,
+            });
+        }
+        Ok(deliveries)
+    }
+
+    pub fn add_scheduled_job(
+        &self,
+        job: &ScheduledJobInput,
+        created_at: i64,
+        next_run_at: i64,
+    ) -> Result<String, rusqlite::Error> {
+        let id = uuid::Uuid::new_v4().to_string();
+        self.conn.execute(
+            "INSERT INTO scheduled_jobs (id, perspective_uuid, name, schedule, job_type, query, commands, task_id, prompt, paused, created_at, next_run_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                id,
+                job.perspective_uuid,
+                job.name,
+                job.schedule,
+                job.job_type,
+                job.query,
+                job.commands,
+                job.task_id,
+                job.prompt,
+                false,
+                created_at,
+                next_run_at,
+            ],
+        )?;
+        Ok(id)
+    }
+
+    fn scheduled_job_from_row(row: &rusqlite::Row) -> Result<ScheduledJob, rusqlite::Error> {
+        Ok(ScheduledJob {
+            id: row.get(0)?,
+            perspective_uuid: row.get(1)?,
+            name: row.get(2)?,
+            schedule: row.get(3)?,
+            job_type: row.get(4)?,
+            query: row.get(5)?,
+            commands: row.get(6)?,
+            task_id: row.get(7)?,
+            prompt: row.get(8)?,
+            paused: row.get(9)?,
+            created_at: unix_to_rfc3339(row.get(10)?),
+            last_run_at: row.get::<_, Option<i64>>(11)?.map(unix_to_rfc3339),
+            next_run_at: row.get::<_, Option<i64>>(12)?.map(unix_to_rfc3339),
+        })
+    }
+
+    const SCHEDULED_JOB_COLUMNS: &'static str = "id, perspective_uuid, name, schedule, job_type, query, commands, task_id, prompt, paused, created_at, last_run_at, next_run_at";
+
+    pub fn get_scheduled_job(&self, id: &str) -> Result<Option<ScheduledJob>, rusqlite::Error> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM scheduled_jobs WHERE id = ?1",
+                    Self::SCHEDULED_JOB_COLUMNS
+                ),
+                [id],
+                Self::scheduled_job_from_row,
+            )
+            .optional()
+    }
+
+    /// All jobs, or the ones bound to the given perspective
+    pub fn get_scheduled_jobs(
+        &self,
+        perspective_uuid: Option<&str>,
+    ) -> Result<Vec<ScheduledJob>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM scheduled_jobs WHERE ?1 IS NULL OR perspective_uuid = ?1 ORDER BY created_at, rowid",
+            Self::SCHEDULED_JOB_COLUMNS
+        ))?;
+        let jobs = stmt
+            .query_map([perspective_uuid], Self::scheduled_job_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(jobs)
+    }
+
+    pub fn get_due_scheduled_jobs(&self, now: i64) -> Result<Vec<ScheduledJob>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM scheduled_jobs WHERE NOT paused AND next_run_at IS NOT NULL AND next_run_at <= ?1 ORDER BY next_run_at",
+            Self::SCHEDULED_JOB_COLUMNS
+        ))?;
+        let jobs = stmt
+            .query_map([now], Self::scheduled_job_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(jobs)
+    }
+
+    pub fn set_scheduled_job_paused(
+        &self,
+        id: &str,
+        paused: bool,
+        next_run_at: Option<i64>,
+    ) -> Result<bool, rusqlite::Error> {
+        let result = self.conn.execute(
+            "UPDATE scheduled_jobs SET paused = ?2, next_run_at = ?3 WHERE id = ?1",
+            params![id, paused, next_run_at],
+        )?;
+        Ok(result > 0)
+    }
+
+    pub fn set_scheduled_job_next_run(
+        &self,
+        id: &str,
+        last_run_at: i64,
+        next_run_at: Option<i64>,
+    ) -> Result<(), rusqlite::Error> {
+        self.conn.execute(
+            "UPDATE scheduled_jobs SET last_run_at = ?2, next_run_at = ?3 WHERE id = ?1",
+            params![id, last_run_at, next_run_at],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_scheduled_job(&self, id: &str) -> Result<bool, rusqlite::Error> {
         self.conn
-            .execute("DELETE FROM notifications WHERE id = ?", [id])?;
+            .execute("DELETE FROM scheduled_job_runs WHERE job_id = ?1", [id])?;
+        let result = self
+            .conn
+            .execute("DELETE FROM scheduled_jobs WHERE id = ?1", [id])?;
+        Ok(result > 0)
+    }
+
+    /// Records the outcome of a job run, keeping only the latest `keep` runs of the job
+    pub fn add_scheduled_job_run(
+        &self,
+        job_id: &str,
+        started_at: i64,
+        finished_at: i64,
+        result: &Result<String, String>,
+        keep: u32,
+    ) -> Result<(), rusqlite::Error> {
+        let (output, error) = match result {
+            Ok(output) => (Some(output), None),
+            Err(error) => (None, Some(error)),
+        };
+        self.conn.execute(
+            "INSERT INTO scheduled_job_runs (job_id, started_at, finished_at, success, output, error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![job_id, started_at, finished_at, result.is_ok(), output, error],
+        )?;
+        self.conn.execute(
+            "DELETE FROM scheduled_job_runs WHERE job_id = ?1 AND id NOT IN
+                (SELECT id FROM scheduled_job_runs WHERE job_id = ?1 ORDER BY id DESC LIMIT ?2)",
+            params![job_id, keep],
+        )?;
         Ok(())
     }
 
+    /// Runs of a job, latest first
+    pub fn get_scheduled_job_runs(
+        &self,
+        job_id: &str,
+        limit: u32,
+    ) -> Result<Vec<ScheduledJobRun>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT job_id, started_at, finished_at, success, output, error FROM scheduled_job_runs
+             WHERE job_id = ?1 ORDER BY id DESC LIMIT ?2",
+        )?;
+        let runs = stmt
+            .query_map(params![job_id, limit], |row| {
+                Ok(ScheduledJobRun {
+                    job_id: row.get(0)?,
+                    started_at: unix_to_rfc3339(row.get(1)?),
+                    finished_at: unix_to_rfc3339(row.get(2)?),
+                    success: row.get(3)?,
+                    output: row.get(4)?,
+                    error: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(runs)
+    }
+
     pub fn update_notification(
         &self,
         id: String,
         updated_notification: &Notification,
     ) -> Result<bool, rusqlite::Error> {
         let result = self.conn.execute(
-            "UPDATE notifications SET description = ?2, appName = ?3, appUrl = ?4, appIconPath = ?5, trigger = ?6, perspective_ids = ?7, webhookUrl = ?8, webhookAuth = ?9, granted = ?10 WHERE id = ?1",
+            "UPDATE notifications SET description = ?2, appName = ?3, appUrl = ?4, appIconPath = ?5, trigger = ?6, perspective_ids = ?7, webhookUrl = ?8, webhookAuth = ?9, granted = ?10, triggerType = ?11, channels = ?12, throttle = ?13 WHERE id = ?1",
             params![
                 id,
                 updated_notification.description,
@@ -446,6 +1272,12 @@ impl Ad4mDb {
                 updated_notification.webhook_url,
                 updated_notification.webhook_auth,
                 updated_notification.granted,
+                updated_notification.trigger_type,
+                serde_json::to_string(&updated_notification.channels).unwrap(),
+                updated_notification
+                    .throttle
+                    .as_ref()
+                    .map(|t| serde_json::to_string(t).unwrap()),
             ],
         )?;
         Ok(result > 0)
@@ -492,33 +1324,680 @@ impl Ad4mDb {
         Ok(())
     }
 
-    pub fn add_to_outbox(
+    pub fn add_key_succession(&self, succession: &KeySuccession) -> Ad4mDbResult<()> {
+        self.conn.execute(
+            "INSERT INTO key_succession (previous_did, new_did, timestamp, signature)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                succession.previous_did,
+                succession.new_did,
+                succession.timestamp,
+                succession.signature,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_key_successions(&self) -> Ad4mDbResult<Vec<KeySuccession>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT previous_did, new_did, timestamp, signature FROM key_succession ORDER BY id",
+        )?;
+        let succession_iter = stmt.query_map([], |row| {
+            Ok(KeySuccession {
+                previous_did: row.get(0)?,
+                new_did: row.get(1)?,
+                timestamp: row.get(2)?,
+                signature: row.get(3)?,
+            })
+        })?;
+        let successions: Result<Vec<_>, _> = succession_iter.collect();
+        Ok(successions?)
+    }
+
+    /// Adds a message that is yet to be delivered
+    pub fn queue_outbox_message(
+        &self,
+        recipient: &str,
+        message: &PerspectiveExpression,
+        encrypted: bool,
+        created_at: i64,
+        next_attempt_at: i64,
+    ) -> Ad4mDbResult<String> {
+        self.conn.execute(
+            "INSERT INTO outbox (message, recipient, status, attempts, created_at, next_attempt_at, encrypted)
+             VALUES (?1, ?2, ?3, 0, ?4, ?5, ?6)",
+            params![
+                serde_json::to_string(message)?,
+                recipient,
+                MessageDeliveryStatus::Queued.to_string(),
+                created_at,
+                next_attempt_at,
+                encrypted
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid().to_string())
+    }
+
+    const OUTBOX_COLUMNS: &'static str =
+        "id, message, recipient, status, attempts, last_error, next_attempt_at, encrypted";
+
+    fn sent_message_from_row(row: &rusqlite::Row) -> Result<SentMessage, rusqlite::Error> {
+        let message_json: String = row.get(1)?;
+        let message: PerspectiveExpression = serde_json::from_str(&message_json).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, Box::new(e))
+        })?;
+        Ok(SentMessage {
+            id: row.get::<_, i64>(0)?.to_string(),
+            message,
+            recipient: row.get(2)?,
+            status: row.get(3)?,
+            attempts: row.get(4)?,
+            last_error: row.get(5)?,
+            next_attempt_at: row.get::<_, Option<i64>>(6)?.map(unix_to_rfc3339),
+            encrypted: row.get(7)?,
+        })
+    }
+
+    pub fn get_outbox_message(&self, id: &str) -> Ad4mDbResult<Option<SentMessage>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!("SELECT {} FROM outbox WHERE id = ?1", Self::OUTBOX_COLUMNS),
+                [id],
+                Self::sent_message_from_row,
+            )
+            .optional()?)
+    }
+
+    /// All sent and queued messages, optionally only those to `recipient`, oldest first
+    pub fn get_all_from_outbox(
+        &self,
+        recipient: Option<&str>,
+    ) -> Result<Vec<SentMessage>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM outbox WHERE ?1 IS NULL OR recipient = ?1 ORDER BY id",
+            Self::OUTBOX_COLUMNS
+        ))?;
+        let outbox_iter = stmt.query_map([recipient], Self::sent_message_from_row)?;
+        outbox_iter.collect()
+    }
+
+    /// Queued messages whose next attempt is due at `now`, oldest first
+    pub fn get_due_outbox_messages(&self, now: i64) -> Result<Vec<SentMessage>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM outbox WHERE status = ?1 AND next_attempt_at <= ?2 ORDER BY id",
+            Self::OUTBOX_COLUMNS
+        ))?;
+        let outbox_iter = stmt.query_map(
+            params![MessageDeliveryStatus::Queued.to_string(), now],
+            Self::sent_message_from_row,
+        )?;
+        outbox_iter.collect()
+    }
+
+    /// Marks a message as delivered, replacing it with the expression the recipient got
+    pub fn record_outbox_delivery(
         &self,
+        id: &str,
         message: &PerspectiveExpression,
-        recipient: String,
+        status: MessageDeliveryStatus,
+    ) -> Ad4mDbResult<()> {
+        self.conn.execute(
+            "UPDATE outbox SET message = ?2, status = ?3, attempts = attempts + 1,
+                last_error = NULL, next_attempt_at = NULL
+             WHERE id = ?1",
+            params![id, serde_json::to_string(message)?, status.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Logs a failed delivery attempt. The message stays queued until `next_attempt_at`,
+    /// or fails for good if there is none.
+    pub fn record_outbox_failure(
+        &self,
+        id: &str,
+        error: &str,
+        next_attempt_at: Option<i64>,
     ) -> Result<(), rusqlite::Error> {
-        let message_json = serde_json::to_string(message).unwrap();
+        let status = match next_attempt_at {
+            Some(_) => MessageDeliveryStatus::Queued,
+            None => MessageDeliveryStatus::Failed,
+        };
         self.conn.execute(
-            "INSERT INTO outbox (message, recipient) VALUES (?, ?)",
-            [message_json, recipient],
+            "UPDATE outbox SET status = ?2, attempts = attempts + 1, last_error = ?3,
+                next_attempt_at = ?4
+             WHERE id = ?1",
+            params![id, status.to_string(), error, next_attempt_at],
         )?;
         Ok(())
     }
 
-    pub fn get_all_from_outbox(&self) -> Result<Vec<SentMessage>, rusqlite::Error> {
-        let mut stmt = self.conn.prepare("SELECT message, recipient FROM outbox")?;
-        let outbox_iter = stmt.query_map([], |row| {
-            let message_json: String = row.get(0)?;
-            let message: PerspectiveExpression = serde_json::from_str(&message_json).unwrap();
-            let recipient: String = row.get(1)?;
-            Ok(SentMessage { message, recipient })
-        })?;
+    /// Makes queued messages to `recipient` due right away, returns how many there are
+    pub fn retry_outbox_messages_to(
+        &self,
+        recipient: &str,
+        now: i64,
+    ) -> Result<usize, rusqlite::Error> {
+        self.conn.execute(
+            "UPDATE outbox SET next_attempt_at = ?3
+             WHERE recipient = ?1 AND status = ?2 AND next_attempt_at > ?3",
+            params![recipient, MessageDeliveryStatus::Queued.to_string(), now],
+        )
+    }
+
+    /// Stores a received message, returns false if it was stored before
+    pub fn add_to_inbox(
+        &self,
+        message: &PerspectiveExpression,
+        received_at: i64,
+    ) -> Ad4mDbResult<bool> {
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO inbox (author, timestamp, signature, message, received_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                message.author,
+                message.timestamp,
+                message.proof.signature,
+                serde_json::to_string(message)?,
+                received_at
+            ],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// Received messages, optionally only those from `author`, in the order they arrived
+    pub fn get_inbox(&self, author: Option<&str>) -> Ad4mDbResult<Vec<PerspectiveExpression>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT message FROM inbox WHERE ?1 IS NULL OR author = ?1 ORDER BY received_at, id",
+        )?;
+        let messages = stmt
+            .query_map([author], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        messages
+            .iter()
+            .map(|message| Ok(serde_json::from_str(message)?))
+            .collect()
+    }
+
+    /// Records the handshake state with `did`. A new direction starts a new request,
+    /// otherwise the request keeps its creation time and note.
+    pub fn set_friend_request(
+        &self,
+        did: &str,
+        direction: FriendRequestDirection,
+        state: FriendRequestState,
+        message: Option<&str>,
+        now: i64,
+    ) -> Ad4mDbResult<()> {
+        self.conn.execute(
+            "INSERT INTO friend_requests (did, direction, state, message, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+             ON CONFLICT(did) DO UPDATE SET
+                created_at = CASE WHEN direction = excluded.direction AND state = 'pending' THEN created_at ELSE excluded.created_at END,
+                message = CASE WHEN excluded.message IS NULL AND direction = excluded.direction THEN message ELSE excluded.message END,
+                direction = excluded.direction,
+                state = excluded.state,
+                updated_at = excluded.updated_at",
+            params![
+                did,
+                direction.to_string(),
+                state.to_string(),
+                message,
+                now
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn friend_request_from_row(row: &rusqlite::Row) -> Result<FriendRequest, rusqlite::Error> {
+        Ok(FriendRequest {
+            did: row.get(0)?,
+            direction: row.get(1)?,
+            state: row.get(2)?,
+            message: row.get(3)?,
+            created_at: unix_to_rfc3339(row.get(4)?),
+            updated_at: unix_to_rfc3339(row.get(5)?),
+        })
+    }
+
+    const FRIEND_REQUEST_COLUMNS: &'static str =
+        "did, direction, state, message, created_at, updated_at";
+
+    pub fn get_friend_request(&self, did: &str) -> Ad4mDbResult<Option<FriendRequest>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM friend_requests WHERE did = ?1",
+                    Self::FRIEND_REQUEST_COLUMNS
+                ),
+                [did],
+                Self::friend_request_from_row,
+            )
+            .optional()?)
+    }
+
+    /// Handshakes, optionally only those in `state`, the most recently changed first
+    pub fn get_friend_requests(&self, state: Option<&str>) -> Ad4mDbResult<Vec<FriendRequest>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM friend_requests WHERE ?1 IS NULL OR state = ?1 ORDER BY updated_at DESC, did",
+            Self::FRIEND_REQUEST_COLUMNS
+        ))?;
+        let requests = stmt
+            .query_map([state], Self::friend_request_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(requests)
+    }
+
+    pub fn remove_friend_request(&self, did: &str) -> Ad4mDbResult<bool> {
+        let removed = self
+            .conn
+            .execute("DELETE FROM friend_requests WHERE did = ?1", [did])?;
+        Ok(removed > 0)
+    }
+
+    /// Replaces everything cached from `issuer` with its currently published statements
+    pub fn set_trust_statements(
+        &self,
+        issuer: &str,
+        statements: &[TrustStatement],
+    ) -> Ad4mDbResult<()> {
+        self.conn
+            .execute("DELETE FROM trust_statements WHERE issuer = ?1", [issuer])?;
+        for statement in statements {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO trust_statements (issuer, subject, timestamp, key, signature)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    issuer,
+                    statement.subject,
+                    statement.timestamp,
+                    statement.key,
+                    statement.signature
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Cached statements, optionally only those issued by `issuer`
+    pub fn get_trust_statements(&self, issuer: Option<&str>) -> Ad4mDbResult<Vec<TrustStatement>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT issuer, subject, timestamp, key, signature FROM trust_statements
+             WHERE ?1 IS NULL OR issuer = ?1 ORDER BY issuer, subject",
+        )?;
+        let statements = stmt
+            .query_map([issuer], |row| {
+                Ok(TrustStatement {
+                    issuer: row.get(0)?,
+                    subject: row.get(1)?,
+                    timestamp: row.get(2)?,
+                    key: row.get(3)?,
+                    signature: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(statements)
+    }
+
+    pub fn get_trust_config(&self) -> Ad4mDbResult<Option<TrustConfig>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT max_depth, threshold FROM trust_config WHERE id = 1",
+                [],
+                |row| {
+                    Ok(TrustConfig {
+                        max_depth: row.get(0)?,
+                        threshold: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    pub fn set_trust_config(&self, config: &TrustConfig) -> Ad4mDbResult<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO trust_config (id, max_depth, threshold) VALUES (1, ?1, ?2)",
+            params![config.max_depth, config.threshold],
+        )?;
+        Ok(())
+    }
+
+    /// The device sync key, sealed to this device's signing key
+    pub fn get_device_sync_key(&self) -> Ad4mDbResult<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT sealed_key FROM device_sync_key WHERE id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub fn set_device_sync_key(&self, sealed_key: &str) -> Ad4mDbResult<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO device_sync_key (id, sealed_key) VALUES (1, ?1)",
+            params![sealed_key],
+        )?;
+        Ok(())
+    }
+
+    /// Replaces what the language at `address` was approved to do
+    pub fn set_language_permissions(
+        &self,
+        address: &str,
+        permissions: &LanguagePermissions,
+    ) -> Ad4mDbResult<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO language_permissions (address, permissions) VALUES (?1, ?2)",
+            params![address, serde_json::to_string(permissions)?],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_language_permissions(
+        &self,
+        address: &str,
+    ) -> Ad4mDbResult<Option<LanguagePermissions>> {
+        let permissions: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT permissions FROM language_permissions WHERE address = ?1",
+                [address],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match permissions {
+            Some(permissions) => Ok(Some(serde_json::from_str(&permissions)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn remove_language_permissions(&self, address: &str) -> Ad4mDbResult<bool> {
+        let removed = self.conn.execute(
+            "DELETE FROM language_permissions WHERE address = ?1",
+            [address],
+        )?;
+        Ok(removed > 0)
+    }
+
+    pub fn add_conversation(
+        &self,
+        id: &str,
+        name: &str,
+        created_by: &str,
+        members: &[String],
+        encrypted: bool,
+        created_at: i64,
+    ) -> Ad4mDbResult<()> {
+        self.conn.execute(
+            "INSERT INTO conversations (id, name, created_by, encrypted, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+            params![id, name, created_by, encrypted, created_at],
+        )?;
+        self.set_conversation_members(id, members, created_at)
+    }
+
+    /// Replaces the member list of a conversation
+    pub fn set_conversation_members(
+        &self,
+        id: &str,
+        members: &[String],
+        updated_at: i64,
+    ) -> Ad4mDbResult<()> {
+        self.conn.execute(
+            "DELETE FROM conversation_members WHERE conversation_id = ?1",
+            [id],
+        )?;
+        for member in members {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO conversation_members (conversation_id, did) VALUES (?1, ?2)",
+                params![id, member],
+            )?;
+        }
+        self.conn.execute(
+            "UPDATE conversations SET updated_at = MAX(updated_at, ?2) WHERE id = ?1",
+            params![id, updated_at],
+        )?;
+        Ok(())
+    }
+
+    fn conversation_members(&self, id: &str) -> Ad4mDbResult<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT did FROM conversation_members WHERE conversation_id = ?1 ORDER BY did",
+        )?;
+        let members = stmt
+            .query_map([id], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(members)
+    }
+
+    fn conversation_from_row(row: &rusqlite::Row) -> Result<Conversation, rusqlite::Error> {
+        Ok(Conversation {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            created_by: row.get(2)?,
+            members: vec![],
+            encrypted: row.get(3)?,
+            created_at: unix_to_rfc3339(row.get(4)?),
+            updated_at: unix_to_rfc3339(row.get(5)?),
+            unread_count: row.get(6)?,
+        })
+    }
+
+    const CONVERSATION_COLUMNS: &'static str = "id, name, created_by, encrypted, created_at, updated_at,
+        (SELECT COUNT(*) FROM conversation_messages m WHERE m.conversation_id = conversations.id AND m.read_at IS NULL)";
+
+    pub fn get_conversation(&self, id: &str) -> Ad4mDbResult<Option<Conversation>> {
+        let conversation = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM conversations WHERE id = ?1",
+                    Self::CONVERSATION_COLUMNS
+                ),
+                [id],
+                Self::conversation_from_row,
+            )
+            .optional()?;
+        match conversation {
+            Some(mut conversation) => {
+                conversation.members = self.conversation_members(id)?;
+                Ok(Some(conversation))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// All conversations, the most recently active first
+    pub fn get_conversations(&self) -> Ad4mDbResult<Vec<Conversation>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM conversations ORDER BY updated_at DESC, created_at DESC",
+            Self::CONVERSATION_COLUMNS
+        ))?;
+        let conversations = stmt
+            .query_map([], Self::conversation_from_row)?
+            .collect::<Result<Vec<Conversation>, _>>()?;
+        conversations
+            .into_iter()
+            .map(|mut conversation| {
+                conversation.members = self.conversation_members(&conversation.id)?;
+                Ok(conversation)
+            })
+            .collect()
+    }
+
+    /// Stores a message of a conversation, returns false if we had it already
+    pub fn add_conversation_message(
+        &self,
+        message: &ConversationMessage,
+        sent_at: i64,
+        read_at: Option<i64>,
+    ) -> Ad4mDbResult<bool> {
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO conversation_messages (id, conversation_id, author, reply_to, message, sent_at, read_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                message.id,
+                message.conversation_id,
+                message.author,
+                message.reply_to,
+                serde_json::to_string(&message.message)?,
+                sent_at,
+                read_at
+            ],
+        )?;
+        if inserted > 0 {
+            self.conn.execute(
+                "UPDATE conversations SET updated_at = MAX(updated_at, ?2) WHERE id = ?1",
+                params![message.conversation_id, sent_at],
+            )?;
+        }
+        Ok(inserted > 0)
+    }
+
+    fn conversation_message_from_row(
+        row: &rusqlite::Row,
+    ) -> Result<ConversationMessage, rusqlite::Error> {
+        let message: String = row.get(4)?;
+        Ok(ConversationMessage {
+            id: row.get(0)?,
+            conversation_id: row.get(1)?,
+            author: row.get(2)?,
+            reply_to: row.get(3)?,
+            message: serde_json::from_str(&message).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    4,
+                    rusqlite::types::Type::Text,
+                    Box::new(e),
+                )
+            })?,
+            timestamp: unix_to_rfc3339(row.get(5)?),
+            read_at: row.get::<_, Option<i64>>(6)?.map(unix_to_rfc3339),
+            read_by: vec![],
+        })
+    }
+
+    const CONVERSATION_MESSAGE_COLUMNS: &'static str =
+        "id, conversation_id, author, reply_to, message, sent_at, read_at";
+
+    fn with_read_receipts(
+        &self,
+        messages: Vec<ConversationMessage>,
+    ) -> Ad4mDbResult<Vec<ConversationMessage>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT reader, read_at FROM message_read_receipts WHERE message_id = ?1 ORDER BY read_at, reader",
+        )?;
+        messages
+            .into_iter()
+            .map(|mut message| {
+                message.read_by = stmt
+                    .query_map([&message.id], |row| {
+                        Ok(MessageReadReceipt {
+                            reader: row.get(0)?,
+                            read_at: unix_to_rfc3339(row.get(1)?),
+                        })
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(message)
+            })
+            .collect()
+    }
 
-        let mut outbox = Vec::new();
-        for message in outbox_iter {
-            outbox.push(message?);
+    pub fn get_conversation_message(&self, id: &str) -> Ad4mDbResult<Option<ConversationMessage>> {
+        let message = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM conversation_messages WHERE id = ?1",
+                    Self::CONVERSATION_MESSAGE_COLUMNS
+                ),
+                [id],
+                Self::conversation_message_from_row,
+            )
+            .optional()?;
+        match message {
+            Some(message) => Ok(self.with_read_receipts(vec![message])?.pop()),
+            None => Ok(None),
         }
-        Ok(outbox)
+    }
+
+    /// A page of up to `limit` messages in the order they were sent.
+    /// Without `before` this is the latest page, otherwise the page of messages sent before
+    /// the message with that id.
+    pub fn get_conversation_messages(
+        &self,
+        conversation_id: &str,
+        before: Option<&str>,
+        limit: u32,
+    ) -> Ad4mDbResult<Vec<ConversationMessage>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM conversation_messages
+             WHERE conversation_id = ?1
+             AND (?2 IS NULL OR (sent_at, id) < (SELECT sent_at, id FROM conversation_messages WHERE id = ?2))
+             ORDER BY sent_at DESC, id DESC
+             LIMIT ?3",
+            Self::CONVERSATION_MESSAGE_COLUMNS
+        ))?;
+        let mut messages = stmt
+            .query_map(
+                params![conversation_id, before, limit],
+                Self::conversation_message_from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        messages.reverse();
+        self.with_read_receipts(messages)
+    }
+
+    /// A message and all replies to it, directly or further down the thread, in the order they were sent
+    pub fn get_message_thread(&self, message_id: &str) -> Ad4mDbResult<Vec<ConversationMessage>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "WITH RECURSIVE thread(id) AS (
+                SELECT id FROM conversation_messages WHERE id = ?1
+                UNION
+                SELECT m.id FROM conversation_messages m JOIN thread t ON m.reply_to = t.id
+             )
+             SELECT {} FROM conversation_messages WHERE id IN (SELECT id FROM thread)
+             ORDER BY sent_at, id",
+            Self::CONVERSATION_MESSAGE_COLUMNS
+        ))?;
+        let messages = stmt
+            .query_map([message_id], Self::conversation_message_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        self.with_read_receipts(messages)
+    }
+
+    /// Marks all unread messages of a conversation as read and returns their ids
+    pub fn mark_conversation_read(
+        &self,
+        conversation_id: &str,
+        read_at: i64,
+    ) -> Ad4mDbResult<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "UPDATE conversation_messages SET read_at = ?2
+             WHERE conversation_id = ?1 AND read_at IS NULL
+             RETURNING id",
+        )?;
+        let ids = stmt
+            .query_map(params![conversation_id, read_at], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(ids)
+    }
+
+    /// Records that `reader` has read a message, returns false if we knew already
+    pub fn add_read_receipt(
+        &self,
+        message_id: &str,
+        reader: &str,
+        read_at: i64,
+    ) -> Ad4mDbResult<bool> {
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO message_read_receipts (message_id, reader, read_at) VALUES (?1, ?2, ?3)",
+            params![message_id, reader, read_at],
+        )?;
+        Ok(inserted > 0)
     }
 
     pub fn add_friends(&self, friends: Vec<String>) -> Result<(), rusqlite::Error> {
@@ -1032,6 +2511,17 @@ impl Ad4mDb {
         ))
     }
 
+    /// Number of pending diffs per perspective, leaving out perspectives without any
+    pub fn count_pending_diffs(&self) -> Ad4mDbResult<Vec<(String, u64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT perspective, COUNT(*) FROM perspective_diff WHERE is_pending = ?1 GROUP BY perspective",
+        )?;
+        let counts = stmt.query_map(params![true], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+        })?;
+        Ok(counts.collect::<Result<Vec<_>, _>>()?)
+    }
+
     pub fn clear_pending_diffs(&self, perspective_uuid: &str, ids: Vec<u64>) -> Ad4mDbResult<()> {
         let id_list = ids
             .iter()
@@ -1254,6 +2744,123 @@ impl Ad4mDb {
         }
     }
 
+    fn upsert_app(
+        &self,
+        request_id: &str,
+        auth_info: &str,
+        token_hash: &str,
+        revoked: bool,
+        legacy_plaintext: bool,
+    ) -> Ad4mDbResult<()> {
+        self.conn.execute(
+            "INSERT INTO apps (request_id, auth_info, token_hash, revoked, legacy_plaintext)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(request_id) DO UPDATE SET
+             auth_info = excluded.auth_info,
+             token_hash = excluded.token_hash,
+             revoked = excluded.revoked,
+             legacy_plaintext = excluded.legacy_plaintext",
+            params![request_id, auth_info, token_hash, revoked, legacy_plaintext],
+        )?;
+        Ok(())
+    }
+
+    /// Stores an app with sealed auth info
+    pub fn add_app(
+        &self,
+        request_id: &str,
+        auth_info: &str,
+        token_hash: &str,
+        revoked: bool,
+    ) -> Ad4mDbResult<()> {
+        self.upsert_app(request_id, auth_info, token_hash, revoked, false)
+    }
+
+    /// Stores an app imported from the legacy apps file, whose auth info is plain JSON
+    /// until the agent gets unlocked and [`Ad4mDb::update_app_auth_info`] seals it
+    pub fn add_legacy_app(
+        &self,
+        request_id: &str,
+        auth_info: &str,
+        token_hash: &str,
+        revoked: bool,
+    ) -> Ad4mDbResult<()> {
+        self.upsert_app(request_id, auth_info, token_hash, revoked, true)
+    }
+
+    pub fn update_app_auth_info(&self, request_id: &str, auth_info: &str) -> Ad4mDbResult<()> {
+        self.conn.execute(
+            "UPDATE apps SET auth_info = ?2, legacy_plaintext = 0 WHERE request_id = ?1",
+            params![request_id, auth_info],
+        )?;
+        Ok(())
+    }
+
+    /// Apps imported from the legacy apps file that haven't been sealed yet
+    pub fn get_legacy_apps(&self) -> Ad4mDbResult<Vec<StoredApp>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT request_id, auth_info, token_hash, revoked FROM apps WHERE legacy_plaintext = 1",
+        )?;
+        let apps = stmt
+            .query_map([], Self::app_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(apps)
+    }
+
+    pub fn get_app(&self, request_id: &str) -> Ad4mDbResult<Option<StoredApp>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT request_id, auth_info, token_hash, revoked FROM apps WHERE request_id = ?1",
+        )?;
+        let app = stmt
+            .query_row(params![request_id], Self::app_from_row)
+            .optional()?;
+        Ok(app)
+    }
+
+    pub fn get_app_by_token_hash(&self, token_hash: &str) -> Ad4mDbResult<Option<StoredApp>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT request_id, auth_info, token_hash, revoked FROM apps WHERE token_hash = ?1",
+        )?;
+        let app = stmt
+            .query_row(params![token_hash], Self::app_from_row)
+            .optional()?;
+        Ok(app)
+    }
+
+    pub fn get_apps(&self) -> Ad4mDbResult<Vec<StoredApp>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT request_id, auth_info, token_hash, revoked FROM apps")?;
+        let app_iter = stmt.query_map([], Self::app_from_row)?;
+        let apps: Result<Vec<_>, _> = app_iter.collect();
+        Ok(apps?)
+    }
+
+    pub fn revoke_app(&self, request_id: &str) -> Ad4mDbResult<bool> {
+        let result = self.conn.execute(
+            "UPDATE apps SET revoked = ?2 WHERE request_id = ?1",
+            params![request_id, true],
+        )?;
+        Ok(result > 0)
+    }
+
+    pub fn remove_app(&self, request_id: &str) -> Ad4mDbResult<bool> {
+        let result = self.conn.execute(
+            "DELETE FROM apps WHERE request_id = ?1",
+            params![request_id],
+        )?;
+        Ok(result > 0)
+    }
+
+    fn app_from_row(row: &rusqlite::Row) -> Result<StoredApp, rusqlite::Error> {
+        Ok(StoredApp {
+            request_id: row.get(0)?,
+            auth_info: row.get(1)?,
+            token_hash: row.get(2)?,
+            revoked: row.get(3)?,
+        })
+    }
+
     pub fn with_global_instance<F, R>(func: F) -> R
     where
         F: FnOnce(&Ad4mDb) -> R,
@@ -1440,10 +3047,13 @@ mod tests {
             }
         );
 
+        assert_eq!(db.count_pending_diffs().unwrap(), vec![(p_uuid.clone(), 1)]);
+
         db.clear_pending_diffs(&p_uuid, ids).unwrap();
         let (diff, ids) = db.get_pending_diffs(&p_uuid, None).unwrap();
         assert_eq!(ids.len(), 0);
         assert_eq!(diff.additions.len(), 0);
+        assert!(db.count_pending_diffs().unwrap().is_empty());
 
         // Create 3 different diffs
         let diff1 = PerspectiveDiff {
@@ -1487,6 +3097,9 @@ mod tests {
             perspective_ids: vec!["Test Perspective ID".to_string()],
             webhook_url: "Test Webhook URL".to_string(),
             webhook_auth: "Test Webhook Auth".to_string(),
+            trigger_type: None,
+            channels: None,
+            throttle: None,
         };
 
         // Add the test notification
@@ -1513,6 +3126,40 @@ mod tests {
         );
         assert_eq!(test_notification.webhook_url, "Test Webhook URL");
         assert_eq!(test_notification.webhook_auth, "Test Webhook Auth");
+        assert_eq!(test_notification.webhook_signing_secret.len(), 64);
+        assert_ne!(
+            test_notification.webhook_signing_secret,
+            test_notification.webhook_auth
+        );
+        assert_eq!(test_notification.trigger_type, "prolog");
+
+        // The installer is recorded and rotating hands out a fresh signing secret
+        assert_eq!(
+            db.get_notification_installer(&notification_id).unwrap(),
+            Some(String::new())
+        );
+        db.set_notification_installer(&notification_id, "token-hash")
+            .unwrap();
+        assert_eq!(
+            db.get_notification_installer(&notification_id).unwrap(),
+            Some("token-hash".to_string())
+        );
+        let rotated = db
+            .rotate_notification_webhook_secret(&notification_id)
+            .unwrap()
+            .unwrap();
+        assert_ne!(rotated, test_notification.webhook_signing_secret);
+        assert_eq!(
+            db.get_notification(notification_id.clone())
+                .unwrap()
+                .unwrap()
+                .webhook_signing_secret,
+            rotated
+        );
+        assert_eq!(
+            db.rotate_notification_webhook_secret("unknown").unwrap(),
+            None
+        );
 
         // Modify the test notification
         let updated_notification = Notification {
@@ -1526,6 +3173,23 @@ mod tests {
             perspective_ids: vec!["Test Perspective ID".to_string()],
             webhook_url: "Test Webhook URL".to_string(),
             webhook_auth: "Test Webhook Auth".to_string(),
+            webhook_signing_secret: String::new(),
+            trigger_type: "prolog".to_string(),
+            channels: vec![NotificationChannel {
+                channel_type: "ntfy".to_string(),
+                url: "https://ntfy.example.com/topic".to_string(),
+                recipient: None,
+                sender: None,
+                username: None,
+                secret: Some("token".to_string()),
+                title_template: Some("{{appName}}".to_string()),
+                body_template: Some("New message: {{Message}}".to_string()),
+            }],
+            throttle: Some(NotificationThrottle {
+                max_deliveries: Some(5),
+                window_seconds: Some(3600),
+                ..Default::default()
+            }),
         };
 
         // Update the test notification
@@ -1544,6 +3208,14 @@ mod tests {
             updated_test_notification.description,
             "Update Test Description"
         );
+        assert_eq!(
+            updated_test_notification.channels,
+            updated_notification.channels
+        );
+        assert_eq!(
+            updated_test_notification.throttle,
+            updated_notification.throttle
+        );
 
         // Remove the test notification
         db.remove_notification(notification_id.clone()).unwrap();
@@ -1554,6 +3226,602 @@ mod tests {
             .all(|n| n.id != notification_id));
     }
 
+    #[test]
+    fn adds_trigger_type_to_existing_notifications_table() {
+        let path = std::env::temp_dir().join(format!("ad4m-db-{}.sqlite", Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_string();
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute(
+                "CREATE TABLE notifications (
+                    id TEXT PRIMARY KEY,
+                    granted BOOLEAN NOT NULL,
+                    description TEXT NOT NULL,
+                    appName TEXT NOT NULL,
+                    appUrl TEXT NOT NULL,
+                    appIconPath TEXT,
+                    trigger TEXT NOT NULL,
+                    perspective_ids TEXT NOT NULL,
+                    webhookUrl TEXT NOT NULL,
+                    webhookAuth TEXT NOT NULL
+                 )",
+                [],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO notifications VALUES ('old', 1, 'd', 'a', 'u', 'i', 't', '[\"p\"]', '', '')",
+                [],
+            )
+            .unwrap();
+        }
+
+        let db = Ad4mDb::new(&path).unwrap();
+        let old = db.get_notification("old".to_string()).unwrap().unwrap();
+        assert_eq!(old.trigger_type, "prolog");
+
+        let id = db
+            .add_notification(NotificationInput {
+                description: "d".to_string(),
+                app_name: "a".to_string(),
+                app_url: "u".to_string(),
+                app_icon_path: "i".to_string(),
+                trigger: "*".to_string(),
+                perspective_ids: vec![],
+                webhook_url: "".to_string(),
+                webhook_auth: "".to_string(),
+                trigger_type: Some("neighbourhoodJoined".to_string()),
+                channels: None,
+                throttle: None,
+            })
+            .unwrap();
+        let new = db.get_notification(id).unwrap().unwrap();
+        assert_eq!(
+            new.trigger_type(),
+            Some(NotificationTriggerType::NeighbourhoodJoined)
+        );
+
+        drop(db);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn can_queue_throttled_notification_matches() {
+        let db = Ad4mDb::new(":memory:").unwrap();
+
+        db.queue_notification_match("n1", "p1", "[{\"X\": \"a\"}]", 100)
+            .unwrap();
+        db.queue_notification_match("n1", "p2", "[{\"X\": \"b\"}]", 110)
+            .unwrap();
+        db.queue_notification_match("n2", "p1", "{}", 120).unwrap();
+
+        let mut ids = db.get_notifications_with_pending_matches().unwrap();
+        ids.sort();
+        assert_eq!(ids, vec!["n1".to_string(), "n2".to_string()]);
+
+        let pending = db.get_pending_notification_matches("n1").unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].perspective_id, "p1");
+        assert_eq!(pending[1].created_at, 110);
+
+        db.remove_pending_notification_matches(&[pending[0].id, pending[1].id])
+            .unwrap();
+        assert!(db
+            .get_pending_notification_matches("n1")
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            db.get_notifications_with_pending_matches().unwrap(),
+            vec!["n2".to_string()]
+        );
+
+        assert_eq!(db.last_notification_delivery("n1", "p1").unwrap(), None);
+        db.record_notification_delivery("n1", "p1", 100, 0).unwrap();
+        db.record_notification_delivery("n1", "p1", 200, 0).unwrap();
+        assert_eq!(
+            db.count_notification_deliveries_since("n1", "p1", 150)
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            db.last_notification_delivery("n1", "p1").unwrap(),
+            Some(200)
+        );
+        // Deliveries are logged per perspective
+        assert_eq!(
+            db.count_notification_deliveries_since("n1", "p2", 0)
+                .unwrap(),
+            0
+        );
+        assert_eq!(db.last_notification_delivery("n1", "p2").unwrap(), None);
+
+        // Old entries get pruned
+        db.record_notification_delivery("n1", "p1", 300, 150)
+            .unwrap();
+        assert_eq!(
+            db.count_notification_deliveries_since("n1", "p1", 0)
+                .unwrap(),
+            2
+        );
+    }
+
+    #[test]
+    fn can_schedule_pause_and_log_jobs() {
+        let db = Ad4mDb::new(":memory:").unwrap();
+        let input = ScheduledJobInput {
+            perspective_uuid: "p1".to_string(),
+            name: "daily summary".to_string(),
+            schedule: "0 6 * * *".to_string(),
+            job_type: "aiTask".to_string(),
+            query: None,
+            commands: None,
+            task_id: Some("task".to_string()),
+            prompt: Some("Summarize".to_string()),
+        };
+        let id = db.add_scheduled_job(&input, 100, 200).unwrap();
+        db.add_scheduled_job(
+            &ScheduledJobInput {
+                perspective_uuid: "p2".to_string(),
+                ..input.clone()
+            },
+            100,
+            300,
+        )
+        .unwrap();
+
+        let job = db.get_scheduled_job(&id).unwrap().unwrap();
+        assert_eq!(job.name, "daily summary");
+        assert!(!job.paused);
+        assert_eq!(job.last_run_at, None);
+        assert_eq!(job.next_run_at, Some(unix_to_rfc3339(200)));
+        assert_eq!(db.get_scheduled_jobs(None).unwrap().len(), 2);
+        assert_eq!(db.get_scheduled_jobs(Some("p2")).unwrap().len(), 1);
+
+        assert!(db.get_due_scheduled_jobs(150).unwrap().is_empty());
+        assert_eq!(db.get_due_scheduled_jobs(250).unwrap()[0].id, id);
+
+        // Paused jobs are never due
+        assert!(db.set_scheduled_job_paused(&id, true, None).unwrap());
+        assert!(db.get_due_scheduled_jobs(250).unwrap().is_empty());
+        db.set_scheduled_job_paused(&id, false, Some(260)).unwrap();
+        db.set_scheduled_job_next_run(&id, 260, Some(400)).unwrap();
+        let job = db.get_scheduled_job(&id).unwrap().unwrap();
+        assert_eq!(job.last_run_at, Some(unix_to_rfc3339(260)));
+        assert_eq!(job.next_run_at, Some(unix_to_rfc3339(400)));
+
+        // Run history keeps the latest runs only
+        for i in 0..3 {
+            db.add_scheduled_job_run(&id, i, i + 1, &Ok(format!("run {}", i)), 2)
+                .unwrap();
+        }
+        db.add_scheduled_job_run(&id, 10, 11, &Err("failed".to_string()), 2)
+            .unwrap();
+        let runs = db.get_scheduled_job_runs(&id, 10).unwrap();
+        assert_eq!(runs.len(), 2);
+        assert!(!runs[0].success);
+        assert_eq!(runs[0].error, Some("failed".to_string()));
+        assert_eq!(runs[1].output, Some("run 2".to_string()));
+
+        assert!(db.remove_scheduled_job(&id).unwrap());
+        assert!(db.get_scheduled_job(&id).unwrap().is_none());
+        assert!(db.get_scheduled_job_runs(&id, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn can_track_outbox_delivery_state() {
+        let db = Ad4mDb::new(":memory:").unwrap();
+        let unsent = PerspectiveExpression {
+            author: "did:test:me".to_string(),
+            ..Default::default()
+        };
+        let mut signed = unsent.clone();
+        signed.proof.signature = "signature".to_string();
+
+        let p2p = db
+            .queue_outbox_message("did:test:alice", &unsent, false, 100, 100)
+            .unwrap();
+        let offline = db
+            .queue_outbox_message("did:test:bob", &unsent, true, 100, 130)
+            .unwrap();
+
+        let due = db.get_due_outbox_messages(100).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, p2p);
+        assert_eq!(due[0].status, MessageDeliveryStatus::Queued.to_string());
+        assert!(!due[0].encrypted);
+
+        db.record_outbox_delivery(&p2p, &signed, MessageDeliveryStatus::SentP2P)
+            .unwrap();
+        let sent = db.get_outbox_message(&p2p).unwrap().unwrap();
+        assert_eq!(sent.status, MessageDeliveryStatus::SentP2P.to_string());
+        assert_eq!(sent.attempts, 1);
+        assert_eq!(sent.message.proof.signature, "signature");
+        assert_eq!(sent.next_attempt_at, None);
+
+        // Failed attempts stay queued until there is no next attempt
+        db.record_outbox_failure(&offline, "offline", Some(1000))
+            .unwrap();
+        let queued = db.get_outbox_message(&offline).unwrap().unwrap();
+        assert_eq!(queued.status, MessageDeliveryStatus::Queued.to_string());
+        assert_eq!(queued.last_error, Some("offline".to_string()));
+        assert!(queued.encrypted);
+        assert!(db.get_due_outbox_messages(500).unwrap().is_empty());
+
+        // Recipient showing up online makes their messages due right away
+        assert_eq!(
+            db.retry_outbox_messages_to("did:test:alice", 500).unwrap(),
+            0
+        );
+        assert_eq!(db.retry_outbox_messages_to("did:test:bob", 500).unwrap(), 1);
+        assert_eq!(db.get_due_outbox_messages(500).unwrap()[0].id, offline);
+
+        db.record_outbox_failure(&offline, "offline", None).unwrap();
+        let failed = db.get_outbox_message(&offline).unwrap().unwrap();
+        assert_eq!(failed.status, MessageDeliveryStatus::Failed.to_string());
+        assert_eq!(failed.attempts, 2);
+        assert!(db.get_due_outbox_messages(i64::MAX).unwrap().is_empty());
+
+        assert_eq!(db.get_all_from_outbox(None).unwrap().len(), 2);
+        let to_bob = db.get_all_from_outbox(Some("did:test:bob")).unwrap();
+        assert_eq!(to_bob.len(), 1);
+        assert_eq!(to_bob[0].id, offline);
+    }
+
+    #[test]
+    fn adds_delivery_state_to_existing_outbox_table() {
+        let path = std::env::temp_dir().join(format!("ad4m-db-{}.sqlite", Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_string();
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute(
+                "CREATE TABLE outbox (
+                    id INTEGER PRIMARY KEY,
+                    message TEXT NOT NULL,
+                    recipient TEXT NOT NULL
+                 )",
+                [],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO outbox (message, recipient) VALUES (?1, ?2)",
+                [
+                    serde_json::to_string(&PerspectiveExpression::default()).unwrap(),
+                    "did:test:alice".to_string(),
+                ],
+            )
+            .unwrap();
+        }
+
+        let db = Ad4mDb::new(&path).unwrap();
+        let outbox = db.get_all_from_outbox(None).unwrap();
+        assert_eq!(outbox.len(), 1);
+        assert_eq!(
+            outbox[0].status,
+            MessageDeliveryStatus::StoredInInbox.to_string()
+        );
+        assert_eq!(outbox[0].attempts, 0);
+        assert!(!outbox[0].encrypted);
+
+        drop(db);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn inbox_keeps_each_message_once() {
+        let db = Ad4mDb::new(":memory:").unwrap();
+        let from = |author: &str, signature: &str| {
+            let mut message = PerspectiveExpression {
+                author: author.to_string(),
+                timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+                ..Default::default()
+            };
+            message.proof.signature = signature.to_string();
+            message
+        };
+
+        assert!(db.add_to_inbox(&from("did:test:alice", "a1"), 1).unwrap());
+        assert!(!db.add_to_inbox(&from("did:test:alice", "a1"), 2).unwrap());
+        assert!(db.add_to_inbox(&from("did:test:alice", "a2"), 3).unwrap());
+        assert!(db.add_to_inbox(&from("did:test:bob", "b1"), 4).unwrap());
+
+        assert_eq!(db.get_inbox(None).unwrap().len(), 3);
+        let from_alice = db.get_inbox(Some("did:test:alice")).unwrap();
+        assert_eq!(from_alice.len(), 2);
+        assert_eq!(from_alice[0].proof.signature, "a1");
+        assert_eq!(from_alice[1].proof.signature, "a2");
+    }
+
+    #[test]
+    fn legacy_friends_become_pending_outgoing_requests() {
+        let db = Ad4mDb::new(":memory:").unwrap();
+        let alice = "did:test:alice";
+        let bob = "did:test:bob";
+        db.set_friend_request(
+            bob,
+            FriendRequestDirection::Incoming,
+            FriendRequestState::Accepted,
+            None,
+            1,
+        )
+        .unwrap();
+        db.add_friends(vec![alice.to_string(), alice.to_string(), bob.to_string()])
+            .unwrap();
+
+        Ad4mDb::migrate_legacy_friends(&db.conn).unwrap();
+
+        assert_eq!(db.get_all_friends().unwrap(), vec![bob.to_string()]);
+        let request = db.get_friend_request(alice).unwrap().unwrap();
+        assert_eq!(request.direction(), Some(FriendRequestDirection::Outgoing));
+        assert_eq!(request.state(), Some(FriendRequestState::Pending));
+        let request = db.get_friend_request(bob).unwrap().unwrap();
+        assert_eq!(request.state(), Some(FriendRequestState::Accepted));
+
+        // Running it again on every start changes nothing
+        Ad4mDb::migrate_legacy_friends(&db.conn).unwrap();
+        assert_eq!(db.get_all_friends().unwrap(), vec![bob.to_string()]);
+        assert_eq!(db.get_friend_requests(None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn friend_requests_keep_their_origin_until_the_direction_changes() {
+        let db = Ad4mDb::new(":memory:").unwrap();
+        let alice = "did:test:alice";
+        db.set_friend_request(
+            alice,
+            FriendRequestDirection::Incoming,
+            FriendRequestState::Pending,
+            Some("Hi, it's Alice"),
+            100,
+        )
+        .unwrap();
+        db.set_friend_request(
+            alice,
+            FriendRequestDirection::Incoming,
+            FriendRequestState::Accepted,
+            None,
+            200,
+        )
+        .unwrap();
+
+        let request = db.get_friend_request(alice).unwrap().unwrap();
+        assert_eq!(request.direction(), Some(FriendRequestDirection::Incoming));
+        assert_eq!(request.state(), Some(FriendRequestState::Accepted));
+        assert_eq!(request.message, Some("Hi, it's Alice".to_string()));
+        assert_eq!(request.created_at, unix_to_rfc3339(100));
+        assert_eq!(request.updated_at, unix_to_rfc3339(200));
+
+        db.set_friend_request(
+            alice,
+            FriendRequestDirection::Outgoing,
+            FriendRequestState::Pending,
+            None,
+            300,
+        )
+        .unwrap();
+        let request = db.get_friend_request(alice).unwrap().unwrap();
+        assert_eq!(request.direction(), Some(FriendRequestDirection::Outgoing));
+        assert_eq!(request.message, None);
+        assert_eq!(request.created_at, unix_to_rfc3339(300));
+
+        assert_eq!(db.get_friend_requests(None).unwrap().len(), 1);
+        assert_eq!(db.get_friend_requests(Some("pending")).unwrap().len(), 1);
+        assert!(db.get_friend_requests(Some("accepted")).unwrap().is_empty());
+        assert!(db.remove_friend_request(alice).unwrap());
+        assert!(db.get_friend_request(alice).unwrap().is_none());
+    }
+
+    #[test]
+    fn trust_statements_of_an_issuer_get_replaced_as_a_whole() {
+        let db = Ad4mDb::new(":memory:").unwrap();
+        let statement = |issuer: &str, subject: &str| TrustStatement {
+            issuer: issuer.to_string(),
+            subject: subject.to_string(),
+            timestamp: "2024-01-01T00:00:00.000Z".to_string(),
+            key: format!("{}#key", issuer),
+            signature: "abcd".to_string(),
+        };
+        db.set_trust_statements(
+            "did:test:alice",
+            &[
+                statement("did:test:alice", "did:test:bob"),
+                statement("did:test:alice", "did:test:carol"),
+            ],
+        )
+        .unwrap();
+        db.set_trust_statements(
+            "did:test:bob",
+            &[statement("did:test:bob", "did:test:carol")],
+        )
+        .unwrap();
+        assert_eq!(db.get_trust_statements(None).unwrap().len(), 3);
+
+        db.set_trust_statements(
+            "did:test:alice",
+            &[statement("did:test:alice", "did:test:dave")],
+        )
+        .unwrap();
+        let from_alice = db.get_trust_statements(Some("did:test:alice")).unwrap();
+        assert_eq!(
+            from_alice,
+            vec![statement("did:test:alice", "did:test:dave")]
+        );
+        assert_eq!(db.get_trust_statements(None).unwrap().len(), 2);
+
+        assert_eq!(db.get_trust_config().unwrap(), None);
+        let config = TrustConfig {
+            max_depth: 2,
+            threshold: 3,
+        };
+        db.set_trust_config(&config).unwrap();
+        db.set_trust_config(&config).unwrap();
+        assert_eq!(db.get_trust_config().unwrap(), Some(config));
+    }
+
+    #[test]
+    fn language_permissions_can_be_approved_and_revoked() {
+        let db = Ad4mDb::new(":memory:").unwrap();
+        assert_eq!(db.get_language_permissions("QmLang").unwrap(), None);
+
+        let permissions = LanguagePermissions {
+            net: vec!["example.com:443".to_string()],
+            env: vec!["HOME".to_string()],
+            ..Default::default()
+        };
+        db.set_language_permissions("QmLang", &LanguagePermissions::default())
+            .unwrap();
+        db.set_language_permissions("QmLang", &permissions).unwrap();
+        assert_eq!(
+            db.get_language_permissions("QmLang").unwrap(),
+            Some(permissions)
+        );
+
+        assert!(db.remove_language_permissions("QmLang").unwrap());
+        assert!(!db.remove_language_permissions("QmLang").unwrap());
+        assert_eq!(db.get_language_permissions("QmLang").unwrap(), None);
+    }
+
+    #[test]
+    fn conversations_page_threads_and_read_receipts() {
+        let db = Ad4mDb::new(":memory:").unwrap();
+        let members = vec!["did:test:alice".to_string(), "did:test:bob".to_string()];
+        db.add_conversation("c1", "Book club", "did:test:alice", &members, true, 100)
+            .unwrap();
+        let message = |id: &str, author: &str, reply_to: Option<&str>| ConversationMessage {
+            id: id.to_string(),
+            conversation_id: "c1".to_string(),
+            author: author.to_string(),
+            reply_to: reply_to.map(|r| r.to_string()),
+            ..Default::default()
+        };
+
+        assert!(db
+            .add_conversation_message(&message("m1", "did:test:alice", None), 110, Some(110))
+            .unwrap());
+        assert!(!db
+            .add_conversation_message(&message("m1", "did:test:alice", None), 110, Some(110))
+            .unwrap());
+        db.add_conversation_message(&message("m2", "did:test:bob", Some("m1")), 120, None)
+            .unwrap();
+        db.add_conversation_message(&message("m3", "did:test:bob", None), 130, None)
+            .unwrap();
+        db.add_conversation_message(&message("m4", "did:test:alice", Some("m2")), 140, Some(140))
+            .unwrap();
+
+        let conversation = db.get_conversation("c1").unwrap().unwrap();
+        assert_eq!(conversation.members, members);
+        assert!(conversation.encrypted);
+        assert_eq!(conversation.unread_count, 2);
+        assert_eq!(conversation.updated_at, unix_to_rfc3339(140));
+
+        let latest = db.get_conversation_messages("c1", None, 2).unwrap();
+        let ids: Vec<&str> = latest.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["m3", "m4"]);
+        let earlier = db.get_conversation_messages("c1", Some("m3"), 2).unwrap();
+        let ids: Vec<&str> = earlier.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["m1", "m2"]);
+        assert!(db
+            .get_conversation_messages("c1", Some("m1"), 2)
+            .unwrap()
+            .is_empty());
+
+        let thread = db.get_message_thread("m1").unwrap();
+        let ids: Vec<&str> = thread.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["m1", "m2", "m4"]);
+
+        let mut read = db.mark_conversation_read("c1", 150).unwrap();
+        read.sort();
+        assert_eq!(read, vec!["m2", "m3"]);
+        assert!(db.mark_conversation_read("c1", 160).unwrap().is_empty());
+        assert_eq!(db.get_conversation("c1").unwrap().unwrap().unread_count, 0);
+
+        assert!(db.add_read_receipt("m1", "did:test:bob", 155).unwrap());
+        assert!(!db.add_read_receipt("m1", "did:test:bob", 156).unwrap());
+        let m1 = db.get_conversation_message("m1").unwrap().unwrap();
+        assert_eq!(
+            m1.read_by,
+            vec![MessageReadReceipt {
+                reader: "did:test:bob".to_string(),
+                read_at: unix_to_rfc3339(155),
+            }]
+        );
+
+        db.set_conversation_members("c1", &members[..1], 170)
+            .unwrap();
+        let conversations = db.get_conversations().unwrap();
+        assert_eq!(conversations.len(), 1);
+        assert_eq!(conversations[0].members, vec!["did:test:alice"]);
+    }
+
+    #[test]
+    fn can_queue_and_log_webhook_deliveries() {
+        let db = Ad4mDb::new(":memory:").unwrap();
+        let failure = DeliveryOutcome {
+            status_code: Some(503),
+            error: Some("Service Unavailable".to_string()),
+        };
+        let success = DeliveryOutcome {
+            status_code: Some(200),
+            error: None,
+        };
+
+        let id = db
+            .enqueue_webhook_delivery("n1", "http://localhost/hook", "{}", 100)
+            .unwrap();
+        db.enqueue_webhook_delivery("n2", "http://localhost/other", "{}", 200)
+            .unwrap();
+
+        let due = db.get_due_webhook_deliveries(150).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, id);
+        assert_eq!(due[0].attempts, 0);
+
+        // Failed attempt gets rescheduled
+        db.record_webhook_delivery_attempt(&id, &failure, 150, Some(160))
+            .unwrap();
+        assert!(db
+            .get_due_webhook_deliveries(155)
+            .unwrap()
+            .iter()
+            .all(|d| d.id != id));
+        let due = db.get_due_webhook_deliveries(160).unwrap();
+        assert_eq!(due[0].id, id);
+        assert_eq!(due[0].attempts, 1);
+
+        db.record_webhook_delivery_attempt(&id, &success, 160, None)
+            .unwrap();
+        assert!(db
+            .get_due_webhook_deliveries(1000)
+            .unwrap()
+            .iter()
+            .all(|d| d.id != id));
+
+        let deliveries = db.get_webhook_deliveries("n1").unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(
+            deliveries[0].status,
+            WebhookDeliveryStatus::Delivered.to_string()
+        );
+        assert_eq!(deliveries[0].next_attempt_at, None);
+        assert_eq!(deliveries[0].attempts.len(), 2);
+        assert_eq!(deliveries[0].attempts[0].attempt, 1);
+        assert_eq!(deliveries[0].attempts[0].status_code, Some(503));
+        assert!(!deliveries[0].attempts[0].success);
+        assert_eq!(deliveries[0].attempts[1].attempt, 2);
+        assert!(deliveries[0].attempts[1].success);
+
+        // Without a next attempt a failed delivery is given up
+        let other = db.get_due_webhook_deliveries(200).unwrap()[0].id.clone();
+        db.record_webhook_delivery_attempt(&other, &failure, 200, None)
+            .unwrap();
+        assert_eq!(
+            db.get_webhook_deliveries("n2").unwrap()[0].status,
+            WebhookDeliveryStatus::Failed.to_string()
+        );
+        assert!(db.get_due_webhook_deliveries(i64::MAX).unwrap().is_empty());
+
+        db.remove_notification("n1".to_string()).unwrap();
+        assert!(db.get_webhook_deliveries("n1").unwrap().is_empty());
+    }
+
     #[test]
     fn test_task_operations() {
         let db = Ad4mDb::new(":memory:").unwrap();
@@ -1921,4 +4189,50 @@ mod tests {
         db.remove_model(&model.name).unwrap();
         db.remove_model(&model2.name).unwrap();
     }
+
+    #[test]
+    fn can_store_revoke_and_remove_apps() {
+        let db = Ad4mDb::new(":memory:").unwrap();
+        let auth_info = crate::agent::capabilities::AuthInfoExtended {
+            request_id: "request-id".to_string(),
+            auth: crate::agent::capabilities::AuthInfo {
+                app_name: "Test App".to_string(),
+                app_desc: "Test App Description".to_string(),
+                ..Default::default()
+            },
+        };
+
+        let auth_info = serde_json::to_string(&auth_info).unwrap();
+        db.add_legacy_app("request-id", &auth_info, "token-hash", false)
+            .unwrap();
+        assert_eq!(db.get_legacy_apps().unwrap().len(), 1);
+
+        let app = db.get_app("request-id").unwrap().expect("app to be stored");
+        assert_eq!(app.token_hash, "token-hash");
+        assert_eq!(app.auth_info, auth_info);
+        assert!(!app.revoked);
+
+        db.update_app_auth_info("request-id", "sealed").unwrap();
+        assert_eq!(
+            db.get_app("request-id").unwrap().unwrap().auth_info,
+            "sealed"
+        );
+        assert!(db.get_legacy_apps().unwrap().is_empty());
+
+        let app = db
+            .get_app_by_token_hash("token-hash")
+            .unwrap()
+            .expect("app to be found by token hash");
+        assert_eq!(app.request_id, "request-id");
+        assert!(db.get_app_by_token_hash("other-hash").unwrap().is_none());
+
+        assert!(db.revoke_app("request-id").unwrap());
+        assert!(db.get_app("request-id").unwrap().unwrap().revoked);
+        assert!(!db.revoke_app("unknown-request-id").unwrap());
+
+        assert_eq!(db.get_apps().unwrap().len(), 1);
+        assert!(db.remove_app("request-id").unwrap());
+        assert!(!db.remove_app("request-id").unwrap());
+        assert!(db.get_apps().unwrap().is_empty());
+    }
 }
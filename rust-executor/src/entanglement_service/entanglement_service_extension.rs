@@ -31,8 +31,7 @@ pub fn generate_entanglement_proof(
 #[op2]
 #[serde]
 pub fn add_entanglement_proofs(#[serde] proofs: Vec<EntanglementProof>) -> Result<(), AnyError> {
-    add_entanglement_proofs_service(proofs);
-    Ok(())
+    add_entanglement_proofs_service(proofs)
 }
 
 #[op2]
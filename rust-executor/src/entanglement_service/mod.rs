@@ -1,10 +1,20 @@
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
 use crate::{
     agent::{sign_string_hex, AgentService},
     db::Ad4mDb,
-    graphql::graphql_types::EntanglementProof,
+    graphql::graphql_types::{Agent, EntanglementProof},
+    languages::LanguageController,
 };
 
 pub(crate) mod entanglement_service_extension;
+mod verification;
+
+pub use verification::{verify_entanglement_proof, AD4M_DEVICE_KEY_TYPE};
 
 pub fn sign_device_key(device_key: String, device_key_type: String) -> EntanglementProof {
     let signed_device_key = sign_string_hex(device_key.clone()).unwrap();
@@ -33,16 +43,190 @@ pub fn generate_holochain_proof(holochain_key: String, signed_did: String) -> En
     })
 }
 
-pub fn add_entanglement_proofs(proofs: Vec<EntanglementProof>) {
-    Ad4mDb::with_global_instance(|db| db.add_entanglement_proofs(proofs))
-        .map_err(|e| e.to_string())
-        .unwrap_or(())
+/// Stores the proofs if all of them are about our DID and verify, otherwise none of them
+pub fn add_entanglement_proofs(proofs: Vec<EntanglementProof>) -> Result<(), AnyError> {
+    let did = AgentService::with_global_instance(|agent_service| agent_service.did.clone())
+        .ok_or(anyhow!("Agent has no DID yet"))?;
+    for proof in proofs.iter() {
+        if proof.did != did {
+            return Err(anyhow!(
+                "Entanglement proof is about {}, not about our DID {}",
+                proof.did,
+                did
+            ));
+        }
+        verify_entanglement_proof(proof)?;
+    }
+
+    Ad4mDb::with_global_instance(|db| db.add_entanglement_proofs(proofs))?;
+    load_device_proofs();
+    Ok(())
 }
 
 pub fn delete_entanglement_proof(proofs: Vec<EntanglementProof>) {
     Ad4mDb::with_global_instance(|db| db.remove_entanglement_proofs(proofs))
         .map_err(|e| e.to_string())
-        .unwrap_or(())
+        .unwrap_or(());
+    load_device_proofs();
+}
+
+/// How long to wait before fetching an agent's published device proofs again
+const DEVICE_PROOFS_REFETCH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// At most this many agents get asked for their device proofs per window, which together
+/// with the refetch interval also bounds how many requests we remember
+const MAX_DEVICE_PROOF_REQUESTS_PER_WINDOW: usize = 30;
+const DEVICE_PROOF_REQUEST_WINDOW: Duration = Duration::from_secs(60);
+/// Published proofs are kept for this many agents, the ones learned longest ago get dropped
+const MAX_AGENTS_WITH_PUBLISHED_PROOFS: usize = 1024;
+
+/// Proofs of other devices of an agent, which make signatures of those devices count as the
+/// agent's. Kept in memory so checking a signature never touches the database.
+#[derive(Default)]
+struct DeviceProofs {
+    /// From our database, for our own DID
+    own: Vec<EntanglementProof>,
+    /// Published by other agents with their agent expression, by DID, all verified,
+    /// with when we learned them
+    published: HashMap<String, (Instant, Vec<EntanglementProof>)>,
+    /// When we last asked for an agent's published proofs, within the refetch interval
+    requested: HashMap<String, Instant>,
+}
+
+impl DeviceProofs {
+    fn learn(&mut self, did: String, proofs: Vec<EntanglementProof>, now: Instant) {
+        if proofs.is_empty() {
+            self.published.remove(&did);
+            return;
+        }
+        if !self.published.contains_key(&did)
+            && self.published.len() >= MAX_AGENTS_WITH_PUBLISHED_PROOFS
+        {
+            let oldest = self
+                .published
+                .iter()
+                .min_by_key(|(_, (learned_at, _))| *learned_at)
+                .map(|(did, _)| did.clone());
+            if let Some(oldest) = oldest {
+                self.published.remove(&oldest);
+            }
+        }
+        self.published.insert(did, (now, proofs));
+    }
+
+    /// Whether to ask `did` for its proofs now, which is remembered if so. Not if we asked
+    /// recently, or already asked too many agents within the current window.
+    fn should_request(&mut self, did: &str, now: Instant) -> bool {
+        self.requested
+            .retain(|_, at| now.duration_since(*at) < DEVICE_PROOFS_REFETCH_INTERVAL);
+        if self.requested.contains_key(did) {
+            return false;
+        }
+        let in_window = self
+            .requested
+            .values()
+            .filter(|at| now.duration_since(**at) < DEVICE_PROOF_REQUEST_WINDOW)
+            .count();
+        if in_window >= MAX_DEVICE_PROOF_REQUESTS_PER_WINDOW {
+            return false;
+        }
+        self.requested.insert(did.to_string(), now);
+        true
+    }
+}
+
+lazy_static! {
+    static ref DEVICE_PROOFS: RwLock<DeviceProofs> = RwLock::new(DeviceProofs::default());
+}
+
+fn is_device_proof_of(proof: &EntanglementProof, did: &str) -> bool {
+    proof.device_key_type == AD4M_DEVICE_KEY_TYPE && proof.did == did
+}
+
+/// Reloads our own device proofs, needs to be called whenever they change in the database
+pub fn load_device_proofs() {
+    let own = get_entanglement_proofs()
+        .into_iter()
+        .filter(|proof| proof.device_key_type == AD4M_DEVICE_KEY_TYPE)
+        .collect();
+    DEVICE_PROOFS
+        .write()
+        .expect("Couldn't get lock on device proofs")
+        .own = own;
+}
+
+/// Our device proofs, which we publish with our agent expression
+pub fn own_device_proofs() -> Vec<EntanglementProof> {
+    DEVICE_PROOFS
+        .read()
+        .expect("Couldn't get lock on device proofs")
+        .own
+        .clone()
+}
+
+/// All known device proofs of `did`, ours or the ones it published
+pub fn device_proofs(did: &str) -> Vec<EntanglementProof> {
+    let proofs = DEVICE_PROOFS
+        .read()
+        .expect("Couldn't get lock on device proofs");
+    proofs
+        .own
+        .iter()
+        .chain(
+            proofs
+                .published
+                .get(did)
+                .into_iter()
+                .flat_map(|(_, published)| published),
+        )
+        .filter(|proof| is_device_proof_of(proof, did))
+        .cloned()
+        .collect()
+}
+
+/// Takes the device proofs `agent` published, replacing what it published before.
+/// Proofs carry both signatures, so only those that verify are kept, wherever the agent came from.
+pub fn learn_published_proofs(agent: &Agent) {
+    let proofs: Vec<EntanglementProof> = agent
+        .device_proofs
+        .iter()
+        .flatten()
+        .filter(|proof| is_device_proof_of(proof, &agent.did))
+        .filter(|proof| match verify_entanglement_proof(proof) {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("Ignoring device proof published by {}: {}", agent.did, e);
+                false
+            }
+        })
+        .cloned()
+        .collect();
+    DEVICE_PROOFS
+        .write()
+        .expect("Couldn't get lock on device proofs")
+        .learn(agent.did.clone(), proofs, Instant::now());
+}
+
+/// Fetches the agent expression of `did` in the background to learn about devices we don't
+/// know yet. Signatures of those devices verify once it arrived. Rate limited, so
+/// unverifiable expressions can't make us fetch agents in bulk.
+pub fn request_published_proofs(did: &str) {
+    let runtime = match tokio::runtime::Handle::try_current() {
+        Ok(runtime) => runtime,
+        Err(_) => return,
+    };
+    let should_request = DEVICE_PROOFS
+        .write()
+        .expect("Couldn't get lock on device proofs")
+        .should_request(did, Instant::now());
+    if !should_request {
+        return;
+    }
+    let did = did.to_string();
+    runtime.spawn(async move {
+        if let Err(e) = LanguageController::agent_by_did(&did).await {
+            log::warn!("Couldn't fetch device proofs of {}: {}", did, e);
+        }
+    });
 }
 
 pub fn get_entanglement_proofs() -> Vec<EntanglementProof> {
@@ -50,3 +234,57 @@ pub fn get_entanglement_proofs() -> Vec<EntanglementProof> {
         .map_err(|e| e.to_string())
         .unwrap_or_default()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proof(did: &str) -> EntanglementProof {
+        EntanglementProof {
+            did: did.to_string(),
+            device_key_type: AD4M_DEVICE_KEY_TYPE.to_string(),
+            device_key: format!("{}-device", did),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn published_proofs_are_kept_for_a_bounded_number_of_agents() {
+        let mut proofs = DeviceProofs::default();
+        let start = Instant::now();
+        for i in 0..MAX_AGENTS_WITH_PUBLISHED_PROOFS + 10 {
+            let did = format!("did:key:{}", i);
+            proofs.learn(
+                did.clone(),
+                vec![proof(&did)],
+                start + Duration::from_millis(i as u64),
+            );
+        }
+        assert_eq!(proofs.published.len(), MAX_AGENTS_WITH_PUBLISHED_PROOFS);
+        assert!(!proofs.published.contains_key("did:key:0"));
+        assert!(!proofs.published.contains_key("did:key:9"));
+        assert!(proofs.published.contains_key("did:key:10"));
+
+        proofs.learn("did:key:10".to_string(), vec![], Instant::now());
+        assert!(!proofs.published.contains_key("did:key:10"));
+    }
+
+    #[test]
+    fn requests_are_rate_limited_and_forgotten_after_the_refetch_interval() {
+        let mut proofs = DeviceProofs::default();
+        let start = Instant::now();
+        for i in 0..MAX_DEVICE_PROOF_REQUESTS_PER_WINDOW {
+            assert!(proofs.should_request(&format!("did:key:{}", i), start));
+        }
+        assert!(!proofs.should_request("did:key:0", start));
+        assert!(!proofs.should_request("did:key:other", start));
+
+        let next_window = start + DEVICE_PROOF_REQUEST_WINDOW;
+        assert!(proofs.should_request("did:key:other", next_window));
+        assert!(!proofs.should_request("did:key:0", next_window));
+
+        let later = start + DEVICE_PROOFS_REFETCH_INTERVAL;
+        assert!(proofs.should_request("did:key:0", later));
+        assert_eq!(proofs.requested.len(), 2);
+    }
+}
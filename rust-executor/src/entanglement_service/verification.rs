@@ -0,0 +1,275 @@
+//! Verification of entanglement proofs.
+//!
+//! A proof binds a device key to the agent's DID in both directions: the DID signs the device
+//! key and the device key signs the DID. Both signatures have to verify before a proof gets
+//! stored, which is only possible for device key types whose signatures we know how to check.
+
+use base64::Engine;
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use did_key::{CoreSign, Ed25519KeyPair};
+use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use secp256k1::{Message, Secp256k1};
+use sha3::{Digest, Keccak256};
+
+use crate::agent::signatures::verify_string_signed_by_did;
+use crate::graphql::graphql_types::EntanglementProof;
+
+pub const HOLOCHAIN_KEY_TYPE: &str = "holochain";
+pub const ETHEREUM_KEY_TYPE: &str = "ethereum";
+/// Another executor of the same agent, whose device key is the DID of its own main key
+pub const AD4M_DEVICE_KEY_TYPE: &str = "ad4m";
+
+/// Length of the type prefix of a Holochain agent key, followed by the ed25519 key
+/// and its 4 byte DHT location
+const HOLO_HASH_PREFIX_LENGTH: usize = 3;
+const ED25519_KEY_LENGTH: usize = 32;
+
+pub fn verify_entanglement_proof(proof: &EntanglementProof) -> Result<(), AnyError> {
+    if !verify_string_signed_by_did(
+        &proof.did,
+        &proof.device_key,
+        &proof.device_key_signed_by_did,
+    )? {
+        return Err(anyhow!(
+            "Device key {} is not signed by {}",
+            proof.device_key,
+            proof.did
+        ));
+    }
+
+    let did_signed_by_device_key = proof.did_signed_by_device_key.as_ref().ok_or(anyhow!(
+        "Device key {} did not sign the DID",
+        proof.device_key
+    ))?;
+    let valid = match proof.device_key_type.as_str() {
+        HOLOCHAIN_KEY_TYPE => {
+            verify_holochain_signature(&proof.device_key, &proof.did, did_signed_by_device_key)?
+        }
+        ETHEREUM_KEY_TYPE => {
+            verify_ethereum_signature(&proof.device_key, &proof.did, did_signed_by_device_key)?
+        }
+        AD4M_DEVICE_KEY_TYPE => {
+            verify_string_signed_by_did(&proof.device_key, &proof.did, did_signed_by_device_key)?
+        }
+        other => return Err(anyhow!("Unsupported device key type {}", other)),
+    };
+    if !valid {
+        return Err(anyhow!(
+            "{} is not signed by device key {}",
+            proof.did,
+            proof.device_key
+        ));
+    }
+    Ok(())
+}
+
+/// Holochain signs the raw bytes of the message with the agent's ed25519 key
+fn verify_holochain_signature(
+    agent_key: &str,
+    message: &str,
+    signature: &str,
+) -> Result<bool, AnyError> {
+    let public_key = holochain_public_key(agent_key)?;
+    let signature = decode_bytes(signature)?;
+    let key = did_key::from_existing_key::<Ed25519KeyPair>(&public_key, None);
+    Ok(key.verify(message.as_bytes(), &signature).is_ok())
+}
+
+/// The ed25519 key of a Holochain agent key, given as `u`-prefixed base64 like Holochain prints
+/// them, or as the bytes of either the full hash or just the key
+fn holochain_public_key(agent_key: &str) -> Result<Vec<u8>, AnyError> {
+    let bytes = match agent_key.strip_prefix('u') {
+        Some(encoded) => base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(encoded)?,
+        None => decode_bytes(agent_key)?,
+    };
+    match bytes.len() {
+        ED25519_KEY_LENGTH => Ok(bytes),
+        length if length == HOLO_HASH_PREFIX_LENGTH + ED25519_KEY_LENGTH + 4 => Ok(bytes
+            [HOLO_HASH_PREFIX_LENGTH..HOLO_HASH_PREFIX_LENGTH + ED25519_KEY_LENGTH]
+            .to_vec()),
+        length => Err(anyhow!(
+            "Holochain agent key {} has unexpected length {}",
+            agent_key,
+            length
+        )),
+    }
+}
+
+/// Ethereum signatures are `personal_sign` signatures (EIP-191), which we check by recovering
+/// the signing address
+fn verify_ethereum_signature(
+    address: &str,
+    message: &str,
+    signature: &str,
+) -> Result<bool, AnyError> {
+    let signature = decode_bytes(signature)?;
+    if signature.len() != 65 {
+        return Err(anyhow!(
+            "Ethereum signature has length {}, expected 65",
+            signature.len()
+        ));
+    }
+    let recovery_id = match signature[64] {
+        v @ 27..=28 => v - 27,
+        v => v,
+    };
+    let signature = RecoverableSignature::from_compact(
+        &signature[..64],
+        RecoveryId::from_i32(recovery_id as i32)?,
+    )?;
+    let prefixed = format!("\x19Ethereum Signed Message:\n{}{}", message.len(), message);
+    let digest = Message::from_slice(&Keccak256::digest(prefixed.as_bytes()))?;
+    let public_key = Secp256k1::verification_only().recover_ecdsa(&digest, &signature)?;
+
+    let recovered = ethereum_address(&public_key);
+    let address = address.trim_start_matches("0x").to_lowercase();
+    Ok(recovered == address)
+}
+
+fn ethereum_address(public_key: &secp256k1::PublicKey) -> String {
+    let hash = Keccak256::digest(&public_key.serialize_uncompressed()[1..]);
+    hex::encode(&hash[12..])
+}
+
+/// Decodes hex, with or without `0x`, or the comma separated bytes that a JS byte array
+/// turns into when stringified
+fn decode_bytes(data: &str) -> Result<Vec<u8>, AnyError> {
+    if data.contains(',') {
+        return data
+            .split(',')
+            .map(|byte| byte.trim().parse::<u8>().map_err(AnyError::from))
+            .collect();
+    }
+    Ok(hex::decode(data.trim_start_matches("0x"))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::Wallet;
+    use did_key::KeyMaterial;
+    use sha2::Sha256;
+
+    fn sign_hex(wallet: &Wallet, name: &str, data: &str) -> String {
+        hex::encode(
+            wallet
+                .sign(&name.to_string(), &Sha256::digest(data.as_bytes()))
+                .unwrap(),
+        )
+    }
+
+    fn signed_by_did(wallet: &Wallet, device_key: &str) -> EntanglementProof {
+        let did = wallet.get_did_document(&"main".to_string()).unwrap().id;
+        EntanglementProof {
+            did: did.clone(),
+            did_signing_key_id: did,
+            device_key_type: String::new(),
+            device_key: device_key.to_string(),
+            device_key_signed_by_did: sign_hex(wallet, "main", device_key),
+            did_signed_by_device_key: None,
+        }
+    }
+
+    fn wallet() -> Wallet {
+        let mut wallet = Wallet::new();
+        wallet.generate_keypair("main".to_string());
+        wallet
+    }
+
+    #[test]
+    fn holochain_proofs_verify_both_signatures() {
+        let wallet = wallet();
+        let device = did_key::generate::<Ed25519KeyPair>(None);
+        let mut hash = vec![0x84, 0x20, 0x24];
+        hash.extend(device.public_key_bytes());
+        hash.extend([0, 0, 0, 0]);
+        let agent_key = format!(
+            "u{}",
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(hash)
+        );
+
+        let mut proof = signed_by_did(&wallet, &agent_key);
+        proof.device_key_type = HOLOCHAIN_KEY_TYPE.to_string();
+        let signature = device.sign(proof.did.as_bytes());
+        proof.did_signed_by_device_key = Some(
+            signature
+                .iter()
+                .map(|byte| byte.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        assert!(verify_entanglement_proof(&proof).is_ok());
+
+        let mut unsigned = proof.clone();
+        unsigned.did_signed_by_device_key = None;
+        assert!(verify_entanglement_proof(&unsigned).is_err());
+
+        let mut forged = proof.clone();
+        forged.did_signed_by_device_key = Some(hex::encode(device.sign(b"something else")));
+        assert!(verify_entanglement_proof(&forged).is_err());
+    }
+
+    #[test]
+    fn ethereum_proofs_verify_against_the_address() {
+        let wallet = wallet();
+        let secp = Secp256k1::new();
+        let (secret_key, public_key) = secp.generate_keypair(&mut rand::thread_rng());
+        let address = format!("0x{}", ethereum_address(&public_key));
+
+        let mut proof = signed_by_did(&wallet, &address);
+        proof.device_key_type = ETHEREUM_KEY_TYPE.to_string();
+        let prefixed = format!(
+            "\x19Ethereum Signed Message:\n{}{}",
+            proof.did.len(),
+            proof.did
+        );
+        let digest = Message::from_slice(&Keccak256::digest(prefixed.as_bytes())).unwrap();
+        let (recovery_id, compact) = secp
+            .sign_ecdsa_recoverable(&digest, &secret_key)
+            .serialize_compact();
+        let mut signature = compact.to_vec();
+        signature.push(27 + recovery_id.to_i32() as u8);
+        proof.did_signed_by_device_key = Some(format!("0x{}", hex::encode(signature)));
+        assert!(verify_entanglement_proof(&proof).is_ok());
+
+        let mut other_address = proof.clone();
+        other_address.device_key = format!("0x{}", "00".repeat(20));
+        assert!(verify_entanglement_proof(&other_address).is_err());
+    }
+
+    #[test]
+    fn ad4m_device_proofs_verify_against_the_device_did() {
+        let mut wallet = wallet();
+        wallet.generate_keypair("device".to_string());
+        let device_did = wallet.get_did_document(&"device".to_string()).unwrap().id;
+
+        let mut proof = signed_by_did(&wallet, &device_did);
+        proof.device_key_type = AD4M_DEVICE_KEY_TYPE.to_string();
+        proof.did_signed_by_device_key = Some(sign_hex(&wallet, "device", &proof.did));
+        assert!(verify_entanglement_proof(&proof).is_ok());
+
+        let mut self_signed = proof.clone();
+        self_signed.did_signed_by_device_key = Some(sign_hex(&wallet, "main", &proof.did));
+        assert!(verify_entanglement_proof(&self_signed).is_err());
+    }
+
+    #[test]
+    fn proofs_need_a_valid_did_signature_and_a_known_key_type() {
+        let wallet = wallet();
+        let mut proof = signed_by_did(&wallet, "0xabc");
+        proof.device_key_type = "solana".to_string();
+        proof.did_signed_by_device_key = Some("00".to_string());
+        assert!(verify_entanglement_proof(&proof)
+            .unwrap_err()
+            .to_string()
+            .contains("Unsupported device key type"));
+
+        proof.device_key_type = ETHEREUM_KEY_TYPE.to_string();
+        proof.device_key = "0xdef".to_string();
+        assert!(verify_entanglement_proof(&proof)
+            .unwrap_err()
+            .to_string()
+            .contains("is not signed by"));
+    }
+}
@@ -1,5 +1,5 @@
 use crate::agent::capabilities::{AuthInfo, Capability};
-use crate::agent::signatures::verify;
+use crate::agent::signatures::check_signature;
 use crate::js_core::JsCoreHandle;
 use crate::types::{
     AIPromptExamples, AITask, DecoratedExpressionProof, DecoratedLinkExpression, Expression,
@@ -17,6 +17,12 @@ pub struct RequestContext {
     pub capabilities: Result<Vec<Capability>, String>,
     pub js_handle: JsCoreHandle,
     pub auto_permit_cap_requests: bool,
+    /// Key under which this request's token is rate limited, `None` for admin requests
+    pub rate_limit_key: Option<String>,
+    /// Whether the request was made with the admin credential
+    pub is_admin: bool,
+    /// Hash of the request's token, to recognize the app that created something
+    pub token_hash: Option<String>,
 }
 
 #[derive(GraphQLObject, Default, Debug, Deserialize, Serialize, Clone)]
@@ -26,6 +32,25 @@ pub struct Agent {
     #[graphql(name = "directMessageLanguage")]
     pub direct_message_language: Option<String>,
     pub perspective: Option<Perspective>,
+    /// Key other agents encrypt direct messages to this agent with
+    pub encryption_key: Option<AgentEncryptionKey>,
+    /// Proofs of other executors linked as devices of this agent, whose signatures count as the agent's
+    #[graphql(name = "deviceProofs")]
+    pub device_proofs: Option<Vec<EntanglementProof>>,
+    /// Rotations of the agent's key, so signatures of its previous keys keep verifying
+    #[graphql(name = "keySuccessions")]
+    pub key_successions: Option<Vec<KeySuccession>>,
+}
+
+/// X25519 public key for end-to-end encrypted direct messages,
+/// see [`crate::agent::encryption`]
+#[derive(GraphQLObject, Default, Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentEncryptionKey {
+    /// Hex encoded X25519 public key
+    pub public_key: String,
+    /// Hex encoded signature over the public key by the agent's DID
+    pub signature: String,
 }
 
 #[derive(GraphQLObject, Default, Debug, Deserialize, Serialize, Clone)]
@@ -43,6 +68,8 @@ pub struct AgentStatus {
     pub error: Option<String>,
     pub is_initialized: bool,
     pub is_unlocked: bool,
+    /// Recovery phrase of newly generated keys, only set in the response to `agentGenerate`
+    pub mnemonic: Option<String>,
 }
 
 #[derive(GraphQLObject, Default, Debug, Deserialize, Serialize, Clone)]
@@ -51,7 +78,10 @@ pub struct Apps {
     pub auth: AuthInfo,
     pub request_id: String,
     pub revoked: Option<bool>,
+    #[graphql(deprecated = "Tokens are not stored, always empty. Use tokenHash")]
     pub token: String,
+    /// SHA-256 hash of the app's capability token
+    pub token_hash: String,
 }
 
 #[derive(GraphQLInputObject, Default, Debug, Deserialize, Serialize)]
@@ -96,7 +126,7 @@ impl From<chrono::DateTime<chrono::Utc>> for DateTime {
     }
 }
 
-#[derive(GraphQLObject, Default, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(GraphQLObject, Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
 pub struct EntanglementProof {
     #[graphql(name = "deviceKey")]
@@ -113,6 +143,24 @@ pub struct EntanglementProof {
     pub did_signing_key_id: String,
 }
 
+/// Statement signed by `previous_did` that the agent's key got rotated to `new_did`
+#[derive(GraphQLObject, Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct KeySuccession {
+    pub previous_did: String,
+    pub new_did: String,
+    pub timestamp: String,
+    pub signature: String,
+}
+
+/// Additional key of the agent, usable in `agentSignMessage` next to the main key
+#[derive(GraphQLObject, Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentKey {
+    pub name: String,
+    pub did: String,
+}
+
 #[derive(GraphQLInputObject, Default, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EntanglementProofInput {
@@ -130,6 +178,28 @@ pub struct EntanglementProofInput {
     pub did_signing_key_id: String,
 }
 
+/// What the primary device hands to a device it authorises: the half of the entanglement
+/// proof it signed and the neighbourhoods that keep perspectives in sync between devices
+#[derive(GraphQLObject, Default, Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceLink {
+    pub proof: EntanglementProof,
+    pub neighbourhoods: Vec<String>,
+    /// Key the device sync neighbourhoods are encrypted with, sealed to the device
+    pub sync_key: String,
+}
+
+#[derive(GraphQLInputObject, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceLinkInput {
+    pub did: String,
+    pub did_signing_key_id: String,
+    pub device_key: String,
+    pub device_key_signed_by_did: String,
+    pub neighbourhoods: Vec<String>,
+    pub sync_key: String,
+}
+
 #[derive(GraphQLObject, Default, Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ExceptionInfo {
@@ -147,6 +217,8 @@ pub enum ExceptionType {
     #[default]
     CapabilityRequested = 3,
     InstallNotificationRequest = 4,
+    LanguagePermissionsRequested = 5,
+    LanguagePermissionDenied = 6,
 }
 
 #[derive(GraphQLInputObject, Default, Debug, Deserialize, Serialize, Clone)]
@@ -215,11 +287,15 @@ pub struct LanguageMeta {
     pub author: String,
     pub description: Option<String>,
     pub name: String,
+    pub permissions: Option<LanguagePermissions>,
     pub possible_template_params: Option<Vec<String>>,
     pub source_code_link: Option<String>,
+    /// Address of the previous version of this language, by the same author
+    pub supersedes: Option<String>,
     pub template_applied_params: Option<String>,
     pub template_source_language_address: Option<String>,
     pub templated: Option<bool>,
+    pub version: Option<String>,
 }
 
 #[derive(GraphQLInputObject, Default, Debug, Deserialize, Serialize, Clone)]
@@ -227,8 +303,67 @@ pub struct LanguageMeta {
 pub struct LanguageMetaInput {
     pub description: String,
     pub name: String,
+    pub permissions: Option<LanguagePermissionsInput>,
     pub possible_template_params: Option<Vec<String>>,
     pub source_code_link: Option<String>,
+    pub supersedes: Option<String>,
+    pub version: Option<String>,
+}
+
+/// What a language bundle may do beyond its own storage directory, declared by its
+/// author in the language meta and approved by the user before the language is installed
+#[derive(GraphQLObject, Default, Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LanguagePermissions {
+    /// Hosts it can connect to, `host` or `host:port`, `*.domain` for subdomains
+    pub net: Vec<String>,
+    /// Paths it can read from, including everything below them
+    pub read: Vec<String>,
+    /// Paths it can write to, including everything below them
+    pub write: Vec<String>,
+    /// Environment variables it can read
+    pub env: Vec<String>,
+    /// Programs it can run
+    pub run: Vec<String>,
+}
+
+#[derive(GraphQLInputObject, Default, Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LanguagePermissionsInput {
+    pub net: Option<Vec<String>>,
+    pub read: Option<Vec<String>>,
+    pub write: Option<Vec<String>>,
+    pub env: Option<Vec<String>>,
+    pub run: Option<Vec<String>>,
+}
+
+impl From<LanguagePermissionsInput> for LanguagePermissions {
+    fn from(input: LanguagePermissionsInput) -> Self {
+        LanguagePermissions {
+            net: input.net.unwrap_or_default(),
+            read: input.read.unwrap_or_default(),
+            write: input.write.unwrap_or_default(),
+            env: input.env.unwrap_or_default(),
+            run: input.run.unwrap_or_default(),
+        }
+    }
+}
+
+/// A newer version of an installed language, signed by the same author
+#[derive(GraphQLObject, Default, Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LanguageUpgrade {
+    /// The installed language
+    pub address: String,
+    pub name: String,
+    pub version: Option<String>,
+    /// The newest version, for template instances the version of their source
+    pub upgrade_address: String,
+    pub upgrade_version: Option<String>,
+    /// The perspective whose neighbourhood uses the language as link language
+    pub perspective_uuid: Option<String>,
+    /// Where another member already moved that neighbourhood to
+    pub neighbourhood_url: Option<String>,
 }
 
 #[derive(GraphQLObject, Default, Debug, Deserialize, Serialize, Clone)]
@@ -404,6 +539,7 @@ impl TryFrom<LinkExpressionInput> for DecoratedLinkExpression {
                 signature: input.proof.signature.ok_or(anyhow!("Key is required"))?,
                 valid: input.proof.valid,
                 invalid: input.proof.invalid,
+                retired_key: None,
             },
             status: input.status,
         })
@@ -429,6 +565,7 @@ impl From<Expression<Perspective>> for PerspectiveExpression {
                 signature: expr.proof.signature,
                 valid: None,
                 invalid: None,
+                retired_key: None,
             },
             timestamp: expr.timestamp,
         }
@@ -449,10 +586,8 @@ impl PerspectiveExpression {
             timestamp: self.timestamp.clone(),
         };
 
-        let valid = verify(&perspective_expression).unwrap_or(false);
-
-        self.proof.valid = Some(valid);
-        self.proof.invalid = Some(!valid);
+        self.proof
+            .tag(check_signature(&perspective_expression).unwrap_or_default());
     }
 }
 
@@ -528,6 +663,54 @@ pub struct NotificationInput {
     pub perspective_ids: Vec<String>,
     pub webhook_url: String,
     pub webhook_auth: String,
+    /// Defaults to "prolog"
+    pub trigger_type: Option<String>,
+    pub channels: Option<Vec<NotificationChannelInput>>,
+    pub throttle: Option<NotificationThrottleInput>,
+}
+
+#[derive(GraphQLInputObject, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledJobInput {
+    pub perspective_uuid: String,
+    pub name: String,
+    /// Cron expression with 5 (minute precision) or 6 (seconds) fields, in UTC
+    pub schedule: String,
+    /// "prolog": run `query` and `commands` once per match, with the match bindings as parameters,
+    /// "aiTask": send `prompt` to the AI task `task_id`
+    pub job_type: String,
+    pub query: Option<String>,
+    /// JSON encoded list of commands, like in `perspectiveExecuteCommands`
+    pub commands: Option<String>,
+    pub task_id: Option<String>,
+    pub prompt: Option<String>,
+}
+
+#[derive(GraphQLInputObject, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationThrottleInput {
+    pub max_deliveries: Option<i32>,
+    pub window_seconds: Option<i32>,
+    pub digest_interval_minutes: Option<i32>,
+    /// "HH:MM"
+    pub quiet_hours_start: Option<String>,
+    /// "HH:MM"
+    pub quiet_hours_end: Option<String>,
+    pub utc_offset_minutes: Option<i32>,
+}
+
+#[derive(GraphQLInputObject, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationChannelInput {
+    /// "email", "ntfy" or "matrix"
+    pub channel_type: String,
+    pub url: String,
+    pub recipient: Option<String>,
+    pub sender: Option<String>,
+    pub username: Option<String>,
+    pub secret: Option<String>,
+    pub title_template: Option<String>,
+    pub body_template: Option<String>,
 }
 
 #[derive(GraphQLObject, Default, Debug, Deserialize, Serialize, Clone)]
@@ -555,8 +738,55 @@ pub struct RuntimeInfo {
 #[derive(GraphQLObject, Default, Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct SentMessage {
+    pub id: String,
     pub message: PerspectiveExpression,
     pub recipient: String,
+    /// "queued", "sentP2P", "storedInInbox" or "failed"
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<String>,
+    /// Sent end-to-end encrypted to the recipient's published encryption key
+    pub encrypted: bool,
+}
+
+/// A group chat, see [`crate::runtime_service::conversations`]
+#[derive(GraphQLObject, Default, Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Conversation {
+    pub id: String,
+    pub name: String,
+    /// Only the creator can change the members
+    pub created_by: String,
+    pub members: Vec<String>,
+    /// All messages of the conversation are end-to-end encrypted
+    pub encrypted: bool,
+    pub created_at: String,
+    pub updated_at: String,
+    pub unread_count: i32,
+}
+
+#[derive(GraphQLObject, Default, Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageReadReceipt {
+    pub reader: String,
+    pub read_at: String,
+}
+
+#[derive(GraphQLObject, Default, Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationMessage {
+    pub id: String,
+    pub conversation_id: String,
+    pub author: String,
+    pub message: Perspective,
+    /// The message this one replies to, replies form threads
+    pub reply_to: Option<String>,
+    pub timestamp: String,
+    /// When we read it, our own messages count as read when sent
+    pub read_at: Option<String>,
+    /// Read receipts of the other members
+    pub read_by: Vec<MessageReadReceipt>,
 }
 
 #[derive(Default, Debug, Deserialize, Serialize)]
@@ -10,13 +10,19 @@ use reqwest::header::ACCESS_CONTROL_ALLOW_ORIGIN;
 use subscription_resolvers::*;
 use warp::reply::with_header;
 
+use crate::agent::capabilities::apps_map::hash_token;
 use crate::agent::capabilities::capabilities_from_token;
+use crate::agent::capabilities::rate_limits::{
+    check_rate_limit, rate_limit_key, RateLimitExceeded, RateLimitedOperation,
+};
 use crate::js_core::JsCoreHandle;
+use crate::metrics::{self, GRAPHQL_REQUESTS};
 use crate::Ad4mConfig;
 
 use std::collections::HashMap;
+use std::io::Write;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use std::{convert::Infallible, io::Write};
 
 use coasys_juniper::{InputValue, RootNode};
 use coasys_juniper_graphql_transport_ws::ConnectionConfig;
@@ -24,7 +30,7 @@ use coasys_juniper_warp::{playground_filter, subscriptions::serve_graphql_transp
 use deno_core::error::AnyError;
 use futures::FutureExt as _;
 use std::path::Path;
-use warp::{http::Response, Filter};
+use warp::{http::Response, Filter, Rejection, Reply};
 
 impl coasys_juniper::Context for RequestContext {}
 
@@ -42,6 +48,80 @@ fn _reply_with_header(
     warp::reply::with_header(reply, name, value)
 }
 
+/// Metrics describe the whole executor, so only the admin may read them, with the same
+/// credential that grants all capabilities on GraphQL. Scrapers can send it as a bearer token.
+pub(crate) fn is_admin_credential(
+    authorization: Option<String>,
+    admin_credential: &Option<String>,
+) -> bool {
+    let authorization = authorization.unwrap_or_default();
+    let token = authorization
+        .strip_prefix("Bearer ")
+        .unwrap_or(&authorization);
+    match admin_credential {
+        Some(admin_credential) => token == admin_credential,
+        None => token.is_empty(),
+    }
+}
+
+/// Serves metrics where enabled, and 404s everywhere else
+fn metrics_filter(
+    enabled: bool,
+    admin_credential: Option<String>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::get()
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(move |authorization: Option<String>| {
+            let authorized = is_admin_credential(authorization, &admin_credential);
+            async move {
+                if !enabled {
+                    return Err(warp::reject::not_found());
+                }
+                if !authorized {
+                    return Ok(warp::reply::with_status(
+                        "Metrics require the admin credential",
+                        warp::http::StatusCode::UNAUTHORIZED,
+                    )
+                    .into_response());
+                }
+                Ok(warp::reply::with_header(
+                    metrics::render(),
+                    "content-type",
+                    metrics::CONTENT_TYPE,
+                )
+                .into_response())
+            }
+        })
+}
+
+async fn handle_rate_limit_rejection(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    if let Some(exceeded) = rejection.find::<RateLimitExceeded>() {
+        let body = serde_json::json!({
+            "errors": [{
+                "message": exceeded.to_string(),
+                "extensions": {
+                    "type": "RATE_LIMIT_EXCEEDED",
+                    "operation": format!("{:?}", exceeded.operation),
+                    "retryAfterSeconds": exceeded.retry_after.as_secs(),
+                }
+            }]
+        });
+        let reply = warp::reply::with_status(
+            warp::reply::json(&body),
+            warp::http::StatusCode::TOO_MANY_REQUESTS,
+        );
+        Ok(warp::reply::with_header(
+            reply,
+            "Retry-After",
+            exceeded.retry_after.as_secs().to_string(),
+        ))
+    } else {
+        Err(rejection)
+    }
+}
+
 pub async fn start_server(
     js_core_handle: JsCoreHandle,
     config: Ad4mConfig,
@@ -71,14 +151,29 @@ pub async fn start_server(
         .and(warp::header::<String>("authorization"))
         .or(default_auth)
         .unify()
-        .map(move |auth_header| {
+        .and(warp::addr::remote())
+        .and_then(move |auth_header: String, remote: Option<SocketAddr>| {
             //println!("Request body: {}", std::str::from_utf8(body_data::bytes()).expect("error converting bytes to &str"));
-            let capabilities = capabilities_from_token(auth_header, admin_credential.clone());
-            RequestContext {
-                capabilities,
-                js_handle: js_core_handle_cloned1.clone(),
-                auto_permit_cap_requests: config.auto_permit_cap_requests.unwrap_or(false),
-            }
+            let capabilities =
+                capabilities_from_token(auth_header.clone(), admin_credential.clone());
+            let rate_limit_key =
+                rate_limit_key(&auth_header, &capabilities, &admin_credential, remote);
+            let is_admin = is_admin_credential(Some(auth_header.clone()), &admin_credential);
+            let token_hash = (!auth_header.is_empty()).then(|| hash_token(&auth_header));
+            let result = check_rate_limit(&rate_limit_key, RateLimitedOperation::Request, 1)
+                .map(|_| {
+                    GRAPHQL_REQUESTS.inc(&[("transport", "http")]);
+                    RequestContext {
+                        capabilities,
+                        js_handle: js_core_handle_cloned1.clone(),
+                        auto_permit_cap_requests: config.auto_permit_cap_requests.unwrap_or(false),
+                        rate_limit_key,
+                        is_admin,
+                        token_hash,
+                    }
+                })
+                .map_err(warp::reject::custom);
+            futures::future::ready(result)
         });
     let qm_graphql_filter = coasys_juniper_warp::make_graphql_filter(qm_schema, qm_state.boxed());
 
@@ -87,7 +182,8 @@ pub async fn start_server(
     let admin_credential_arc = Arc::new(config.admin_credential.clone());
     let routes = (warp::path("graphql")
         .and(warp::ws())
-        .map(move |ws: warp::ws::Ws| {
+        .and(warp::addr::remote())
+        .map(move |ws: warp::ws::Ws, remote: Option<SocketAddr>| {
             let root_node = root_node.clone();
             let js_core_handle = js_core_handle.clone();
             let admin_credential_arc = admin_credential_arc.clone();
@@ -110,17 +206,32 @@ pub async fn start_server(
                         };
 
                         let capabilities = capabilities_from_token(
-                            auth_header,
+                            auth_header.clone(),
                             admin_credential_arc.as_ref().clone(),
                         );
 
+                        let rate_limit_key = rate_limit_key(
+                            &auth_header,
+                            &capabilities,
+                            admin_credential_arc.as_ref(),
+                            remote,
+                        );
+                        check_rate_limit(&rate_limit_key, RateLimitedOperation::Request, 1)
+                            .map_err(|e| e.to_string())?;
+                        GRAPHQL_REQUESTS.inc(&[("transport", "websocket")]);
+
                         let context = RequestContext {
                             capabilities,
                             js_handle: js_core_handle.clone(),
                             auto_permit_cap_requests,
+                            rate_limit_key,
+                            is_admin: is_admin_credential(
+                                Some(auth_header.clone()),
+                                admin_credential_arc.as_ref(),
+                            ),
+                            token_hash: (!auth_header.is_empty()).then(|| hash_token(&auth_header)),
                         };
-                        Ok(ConnectionConfig::new(context))
-                            as Result<ConnectionConfig<_>, Infallible>
+                        Ok(ConnectionConfig::new(context)) as Result<ConnectionConfig<_>, String>
                     },
                 )
                 .map(|r| {
@@ -137,7 +248,8 @@ pub async fn start_server(
     })
     .or(warp::post()
         .and(warp::path("graphql"))
-        .and(qm_graphql_filter.clone()))
+        .and(qm_graphql_filter.clone())
+        .recover(handle_rate_limit_rejection))
     .or(
         warp::get() //This is required for the ad4m-connect port checker to have the correct cors headers set
             .and(warp::path("graphql"))
@@ -148,6 +260,10 @@ pub async fn start_server(
     .or(warp::get()
         .and(warp::path("playground"))
         .and(playground_filter("/graphql", Some("/subscriptions"))))
+    .or(metrics_filter(
+        config.metrics_endpoint.unwrap_or(false) && config.metrics_port.is_none(),
+        config.admin_credential.clone(),
+    ))
     .or(homepage)
     .with(log);
 
@@ -185,6 +301,18 @@ pub async fn start_server(
         [0, 0, 0, 0]
     };
 
+    if let (Some(true), Some(metrics_port)) = (config.metrics_endpoint, config.metrics_port) {
+        let metrics_address = if config.metrics_localhost.unwrap_or(true) {
+            [127, 0, 0, 1]
+        } else {
+            address
+        };
+        tokio::spawn(
+            warp::serve(metrics_filter(true, config.admin_credential.clone()))
+                .run((metrics_address, metrics_port)),
+        );
+    }
+
     if let Some(tls_config) = config.tls {
         warp::serve(routes_with_cors)
             .tls()
@@ -198,3 +326,33 @@ pub async fn start_server(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_need_the_admin_credential() {
+        let admin_credential = Some("secret".to_string());
+        assert!(is_admin_credential(
+            Some("secret".to_string()),
+            &admin_credential
+        ));
+        assert!(is_admin_credential(
+            Some("Bearer secret".to_string()),
+            &admin_credential
+        ));
+        assert!(!is_admin_credential(None, &admin_credential));
+        assert!(!is_admin_credential(
+            Some("app.jwt.token".to_string()),
+            &admin_credential
+        ));
+
+        // Without a credential the empty token is the admin, like on GraphQL
+        assert!(is_admin_credential(None, &None));
+        assert!(!is_admin_credential(
+            Some("app.jwt.token".to_string()),
+            &None
+        ));
+    }
+}
@@ -13,23 +13,37 @@ use crate::{
 };
 use crate::{
     db::Ad4mDb,
+    languages::permissions,
     perspectives::perspective_instance::{Command, Parameter, SubjectClassOption},
-    runtime_service::RuntimeService,
-    types::Notification,
+    runtime_service::{
+        conversations, direct_messages, friend_requests, scheduler, web_of_trust, RuntimeService,
+    },
+    types::{FriendRequest, MessageDeliveryStatus, ScheduledJob, TrustConfig, TrustStatement},
 };
 use coasys_juniper::{graphql_object, graphql_value, FieldError, FieldResult};
 
 use super::graphql_types::*;
 use crate::{
-    agent::{self, capabilities::*, AgentService},
+    agent::{
+        self,
+        capabilities::{
+            rate_limits::{check_rate_limit, RateLimitedOperation},
+            *,
+        },
+        device_sync, AgentService,
+    },
     entanglement_service::{
         add_entanglement_proofs, delete_entanglement_proof, get_entanglement_proofs,
-        sign_device_key,
+        sign_device_key, AD4M_DEVICE_KEY_TYPE,
+    },
+    holochain_service::{
+        agent_infos_from_str, get_holochain_service, keystore::change_keystore_passphrase,
     },
-    holochain_service::{agent_infos_from_str, get_holochain_service},
-    pubsub::{get_global_pubsub, AGENT_STATUS_CHANGED_TOPIC},
+    js_core::rpc::JsRpc,
+    pubsub::{get_global_pubsub, AGENT_STATUS_CHANGED_TOPIC, AGENT_UPDATED_TOPIC},
 };
 use base64::prelude::*;
+use serde_json::json;
 
 pub struct Mutation;
 
@@ -42,6 +56,15 @@ fn get_perspective_with_uuid_field_error(uuid: &str) -> FieldResult<PerspectiveI
     })
 }
 
+fn get_scheduled_job_field_error(id: &str) -> FieldResult<ScheduledJob> {
+    Ad4mDb::with_global_instance(|db| db.get_scheduled_job(id))?.ok_or_else(|| {
+        FieldError::new(
+            "Scheduled job not found",
+            graphql_value!({ "id": id.to_owned() }),
+        )
+    })
+}
+
 fn link_status_from_input(status: Option<String>) -> Result<LinkStatus, FieldError> {
     match status.as_deref() {
         Some("shared") => Ok(LinkStatus::Shared),
@@ -54,6 +77,35 @@ fn link_status_from_input(status: Option<String>) -> Result<LinkStatus, FieldErr
     }
 }
 
+/// Sets up Holochain, languages and the agent's DM language
+/// once the agent's keys are in place
+async fn init_agent_in_js(context: &RequestContext, passphrase: &str) -> FieldResult<()> {
+    let mut js = context.js_handle.clone();
+    js.rpc::<serde_json::Value>(JsRpc::mutation(
+        "agentGenerate",
+        json!({ "passphrase": passphrase }),
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Publishes the agent expression again if the proofs of our devices changed,
+/// so other agents learn which keys sign for us
+async fn publish_device_proofs(context: &RequestContext) {
+    if !AgentService::with_mutable_global_instance(|agent_service| {
+        agent_service.ensure_device_proofs()
+    }) {
+        return;
+    }
+    let mut js = context.js_handle.clone();
+    if let Err(e) = js
+        .rpc::<serde_json::Value>(JsRpc::PublishAgentExpression)
+        .await
+    {
+        log::error!("Failed to publish device proofs: {}", e);
+    }
+}
+
 #[graphql_object(context = RequestContext)]
 impl Mutation {
     async fn add_trusted_agents(
@@ -73,12 +125,68 @@ impl Mutation {
         })
     }
 
+    /// Publishes a signed statement that the agent trusts `did`,
+    /// returns all trust statements the agent published
+    async fn runtime_trust_agent(
+        &self,
+        context: &RequestContext,
+        did: String,
+    ) -> FieldResult<Vec<TrustStatement>> {
+        check_capability(
+            &context.capabilities,
+            &RUNTIME_TRUSTED_AGENTS_CREATE_CAPABILITY,
+        )?;
+        let mut js = context.js_handle.clone();
+        Ok(web_of_trust::set_trust(&mut js, &did, true).await?)
+    }
+
+    async fn runtime_revoke_trust(
+        &self,
+        context: &RequestContext,
+        did: String,
+    ) -> FieldResult<Vec<TrustStatement>> {
+        check_capability(
+            &context.capabilities,
+            &RUNTIME_TRUSTED_AGENTS_DELETE_CAPABILITY,
+        )?;
+        let mut js = context.js_handle.clone();
+        Ok(web_of_trust::set_trust(&mut js, &did, false).await?)
+    }
+
+    async fn runtime_set_trust_config(
+        &self,
+        context: &RequestContext,
+        max_depth: i32,
+        threshold: i32,
+    ) -> FieldResult<TrustConfig> {
+        check_capability(
+            &context.capabilities,
+            &RUNTIME_TRUSTED_AGENTS_CREATE_CAPABILITY,
+        )?;
+        Ok(web_of_trust::set_config(TrustConfig {
+            max_depth,
+            threshold,
+        })?)
+    }
+
+    /// Fetches the trust statements of trusted agents, returns how many agents were fetched
+    async fn runtime_refresh_trust(&self, context: &RequestContext) -> FieldResult<i32> {
+        check_capability(
+            &context.capabilities,
+            &RUNTIME_TRUSTED_AGENTS_READ_CAPABILITY,
+        )?;
+        Ok(web_of_trust::refresh().await? as i32)
+    }
+
     async fn agent_add_entanglement_proofs(
         &self,
-        _context: &RequestContext,
+        context: &RequestContext,
         proofs: Vec<EntanglementProofInput>,
     ) -> FieldResult<Vec<EntanglementProof>> {
-        //TODO: capability missing for this function
+        check_capability(
+            &context.capabilities,
+            &AGENT_ENTANGLEMENT_PROOFS_CREATE_CAPABILITY,
+        )?;
         let converted_proofs: Vec<EntanglementProof> = proofs
             .into_iter()
             .map(|input| EntanglementProof {
@@ -91,7 +199,8 @@ impl Mutation {
             })
             .collect();
 
-        add_entanglement_proofs(converted_proofs);
+        add_entanglement_proofs(converted_proofs)?;
+        publish_device_proofs(context).await;
 
         let proofs = get_entanglement_proofs();
 
@@ -100,10 +209,13 @@ impl Mutation {
 
     async fn agent_delete_entanglement_proofs(
         &self,
-        _context: &RequestContext,
+        context: &RequestContext,
         proofs: Vec<EntanglementProofInput>,
     ) -> FieldResult<Vec<EntanglementProof>> {
-        //TODO: capability missing for this function
+        check_capability(
+            &context.capabilities,
+            &AGENT_ENTANGLEMENT_PROOFS_DELETE_CAPABILITY,
+        )?;
         let converted_proofs: Vec<EntanglementProof> = proofs
             .into_iter()
             .map(|input| EntanglementProof {
@@ -117,6 +229,7 @@ impl Mutation {
             .collect();
 
         delete_entanglement_proof(converted_proofs);
+        publish_device_proofs(context).await;
 
         let proofs = get_entanglement_proofs();
 
@@ -125,37 +238,228 @@ impl Mutation {
 
     async fn agent_entanglement_proof_pre_flight(
         &self,
-        _context: &RequestContext,
+        context: &RequestContext,
         device_key: String,
         device_key_type: String,
     ) -> FieldResult<EntanglementProof> {
-        //TODO: capability missing for this function
+        check_capability(
+            &context.capabilities,
+            &AGENT_ENTANGLEMENT_PROOFS_CREATE_CAPABILITY,
+        )?;
         let proof = sign_device_key(device_key, device_key_type);
 
         Ok(proof)
     }
 
+    /// Authorises another executor, identified by the DID of its own main key,
+    /// to act as a device of this agent
+    async fn agent_authorise_device(
+        &self,
+        context: &RequestContext,
+        device_did: String,
+    ) -> FieldResult<DeviceLink> {
+        check_capability(
+            &context.capabilities,
+            &AGENT_ENTANGLEMENT_PROOFS_CREATE_CAPABILITY,
+        )?;
+        if AgentService::with_global_instance(|agent_service| agent_service.is_linked_device()) {
+            return Err(FieldError::new(
+                "Devices can only be authorised on the agent's primary device",
+                graphql_value!(null),
+            ));
+        }
+        let sync_key = device_sync::seal_sync_key_for(&device_did)?;
+        let proof = sign_device_key(device_did, AD4M_DEVICE_KEY_TYPE.to_string());
+
+        Ok(DeviceLink {
+            proof,
+            neighbourhoods: neighbourhoods::device_sync_neighbourhoods().await,
+            sync_key,
+        })
+    }
+
+    /// Links this executor to the agent that created `link` with `agentAuthoriseDevice` and
+    /// joins the agent's device sync neighbourhoods. Returns the completed entanglement proof,
+    /// which has to be added on the primary device.
+    async fn agent_link_device(
+        &self,
+        context: &RequestContext,
+        link: DeviceLinkInput,
+        passphrase: String,
+    ) -> FieldResult<EntanglementProof> {
+        check_capability(&context.capabilities, &AGENT_UPDATE_CAPABILITY)?;
+        let proof = EntanglementProof {
+            did: link.did,
+            did_signing_key_id: link.did_signing_key_id,
+            device_key_type: AD4M_DEVICE_KEY_TYPE.to_string(),
+            device_key: link.device_key,
+            device_key_signed_by_did: link.device_key_signed_by_did,
+            did_signed_by_device_key: None,
+        };
+        let (proof, agent_status, agent) =
+            AgentService::with_mutable_global_instance(|agent_service| {
+                let sync_key = device_sync::open_sync_key_from_link(&link.sync_key)?;
+                let proof = agent_service.link_device(proof, passphrase)?;
+                device_sync::store_sync_key(&sync_key)?;
+                Ok::<_, deno_core::error::AnyError>((
+                    proof,
+                    agent_service.dump(),
+                    agent_service.agent.clone(),
+                ))
+            })?;
+
+        get_global_pubsub()
+            .await
+            .publish(
+                &AGENT_STATUS_CHANGED_TOPIC,
+                &serde_json::to_string(&agent_status).unwrap(),
+            )
+            .await;
+        if let Some(agent) = agent {
+            get_global_pubsub()
+                .await
+                .publish(
+                    &AGENT_UPDATED_TOPIC,
+                    &serde_json::to_string(&agent).unwrap(),
+                )
+                .await;
+        }
+
+        let joined = neighbourhoods::join_device_sync_neighbourhoods(link.neighbourhoods).await;
+        log::info!(
+            "Linked to agent {}, joined {} device sync neighbourhoods",
+            proof.did,
+            joined.len()
+        );
+
+        Ok(proof)
+    }
+
+    async fn agent_change_passphrase(
+        &self,
+        context: &RequestContext,
+        old_passphrase: String,
+        new_passphrase: String,
+    ) -> FieldResult<AgentStatus> {
+        check_capability(&context.capabilities, &AGENT_UPDATE_CAPABILITY)?;
+        // Holochain's keystore is encrypted with the passphrase too. It gets re-keyed first,
+        // which fails on a wrong old passphrase before the wallet is touched.
+        let conductor_path = AgentService::with_global_instance(|agent_service| {
+            agent_service.holochain_conductor_path()
+        });
+        change_keystore_passphrase(&conductor_path, &old_passphrase, &new_passphrase).await?;
+
+        let result = AgentService::with_global_instance(|agent_service| {
+            agent_service.change_passphrase(old_passphrase.clone(), new_passphrase.clone())?;
+            Ok::<AgentStatus, deno_core::error::AnyError>(agent_service.dump())
+        });
+        let agent = match result {
+            Ok(agent) => agent,
+            Err(e) => {
+                // Keeps the keystore on the passphrase the wallet is still encrypted with
+                if let Err(restore_error) =
+                    change_keystore_passphrase(&conductor_path, &new_passphrase, &old_passphrase)
+                        .await
+                {
+                    log::error!(
+                        "Failed to restore Holochain keystore passphrase: {}",
+                        restore_error
+                    );
+                }
+                return Err(e.into());
+            }
+        };
+
+        get_global_pubsub()
+            .await
+            .publish(
+                &AGENT_STATUS_CHANGED_TOPIC,
+                &serde_json::to_string(&agent).unwrap(),
+            )
+            .await;
+
+        Ok(agent)
+    }
+
+    async fn agent_rotate_key(
+        &self,
+        context: &RequestContext,
+        passphrase: String,
+    ) -> FieldResult<KeySuccession> {
+        check_capability(&context.capabilities, &AGENT_UPDATE_CAPABILITY)?;
+        let (succession, agent_status, agent) =
+            AgentService::with_mutable_global_instance(|agent_service| {
+                let succession = agent_service.rotate_key(passphrase)?;
+                Ok::<_, deno_core::error::AnyError>((
+                    succession,
+                    agent_service.dump(),
+                    agent_service.agent.clone(),
+                ))
+            })?;
+
+        // Publish the agent expression under the new DID
+        let mut js = context.js_handle.clone();
+        if let Err(e) = js
+            .rpc::<serde_json::Value>(JsRpc::EnsureAgentExpression)
+            .await
+        {
+            log::error!(
+                "Failed to publish agent expression after key rotation: {}",
+                e
+            );
+        }
+
+        get_global_pubsub()
+            .await
+            .publish(
+                &AGENT_STATUS_CHANGED_TOPIC,
+                &serde_json::to_string(&agent_status).unwrap(),
+            )
+            .await;
+
+        if let Some(agent) = agent {
+            get_global_pubsub()
+                .await
+                .publish(
+                    &AGENT_UPDATED_TOPIC,
+                    &serde_json::to_string(&agent).unwrap(),
+                )
+                .await;
+        }
+
+        Ok(succession)
+    }
+
+    async fn agent_generate_key(
+        &self,
+        context: &RequestContext,
+        name: String,
+        passphrase: String,
+    ) -> FieldResult<AgentKey> {
+        check_capability(&context.capabilities, &AGENT_UPDATE_CAPABILITY)?;
+        Ok(AgentService::with_global_instance(|agent_service| {
+            agent_service.generate_named_key(name, passphrase)
+        })?)
+    }
+
     async fn agent_generate(
         &self,
         context: &RequestContext,
         passphrase: String,
     ) -> FieldResult<AgentStatus> {
         check_capability(&context.capabilities, &AGENT_CREATE_CAPABILITY)?;
-        let agent = AgentService::with_mutable_global_instance(|agent_service| {
+        let (mut agent, mnemonic) = AgentService::with_mutable_global_instance(|agent_service| {
             agent_service.create_new_keys();
             agent_service.save(passphrase.clone());
 
-            agent_service.dump().clone()
-        });
+            let mnemonic = agent_service.mnemonic(passphrase.clone())?;
+            Ok::<(AgentStatus, String), deno_core::error::AnyError>((
+                agent_service.dump(),
+                mnemonic,
+            ))
+        })?;
 
-        let mut js = context.js_handle.clone();
-        let script = format!(
-            r#"JSON.stringify(
-                await core.callResolver("Mutation", "agentGenerate", {{ passphrase: "{}" }})
-            )"#,
-            passphrase
-        );
-        js.execute(script).await?;
+        init_agent_in_js(context, &passphrase).await?;
 
         get_global_pubsub()
             .await
@@ -167,6 +471,64 @@ impl Mutation {
 
         log::info!("AD4M init complete");
 
+        // Only the caller that generated the keys gets to see the phrase,
+        // it's not part of the status published to subscribers
+        agent.mnemonic = Some(mnemonic);
+        Ok(agent)
+    }
+
+    async fn agent_restore_from_mnemonic(
+        &self,
+        context: &RequestContext,
+        mnemonic: String,
+        passphrase: String,
+    ) -> FieldResult<AgentStatus> {
+        check_capability(&context.capabilities, &AGENT_CREATE_CAPABILITY)?;
+        let agent = AgentService::with_mutable_global_instance(|agent_service| {
+            agent_service.restore_from_mnemonic(mnemonic)?;
+            agent_service.save(passphrase.clone());
+            Ok::<AgentStatus, deno_core::error::AnyError>(agent_service.dump())
+        })?;
+
+        init_agent_in_js(context, &passphrase).await?;
+
+        get_global_pubsub()
+            .await
+            .publish(
+                &AGENT_STATUS_CHANGED_TOPIC,
+                &serde_json::to_string(&agent).unwrap(),
+            )
+            .await;
+
+        log::info!("AD4M init from recovery phrase complete");
+
+        Ok(agent)
+    }
+
+    async fn agent_import_backup(
+        &self,
+        context: &RequestContext,
+        backup: String,
+        passphrase: String,
+    ) -> FieldResult<AgentStatus> {
+        check_capability(&context.capabilities, &AGENT_CREATE_CAPABILITY)?;
+        let agent = AgentService::with_mutable_global_instance(|agent_service| {
+            agent_service.import_backup(backup, passphrase.clone())?;
+            Ok::<AgentStatus, deno_core::error::AnyError>(agent_service.dump())
+        })?;
+
+        init_agent_in_js(context, &passphrase).await?;
+
+        get_global_pubsub()
+            .await
+            .publish(
+                &AGENT_STATUS_CHANGED_TOPIC,
+                &serde_json::to_string(&agent).unwrap(),
+            )
+            .await;
+
+        log::info!("AD4M init from backup complete");
+
         Ok(agent)
     }
 
@@ -262,9 +624,14 @@ impl Mutation {
         &self,
         context: &RequestContext,
         message: String,
+        key_name: Option<String>,
     ) -> FieldResult<AgentSignature> {
         check_capability(&context.capabilities, &AGENT_SIGN_CAPABILITY)?;
-        Ok(agent::AgentSignature::from_message(message)?.into())
+        let signature = match key_name {
+            Some(key_name) => agent::AgentSignature::from_message_with_key(message, &key_name)?,
+            None => agent::AgentSignature::from_message(message)?,
+        };
+        Ok(signature.into())
     }
 
     async fn agent_unlock(
@@ -290,14 +657,21 @@ impl Mutation {
             .expect("agent instance")
             .is_unlocked()
         {
+            // Agents created before encrypted direct messages, device proofs or published
+            // key successions get them here,
+            // they get published with the agent expression when JS unlocks
+            AgentService::with_mutable_global_instance(|agent_service| {
+                agent_service.ensure_encryption_key();
+                agent_service.ensure_device_proofs();
+                agent_service.ensure_key_successions();
+            });
+
             let mut js = context.js_handle.clone();
-            let script = format!(
-                r#"JSON.stringify(
-                    await core.callResolver("Mutation", "agentUnlock", {{ passphrase: "{}", holochain: "{}" }})
-                )"#,
-                passphrase, holochain
-            );
-            js.execute(script).await?;
+            js.rpc::<serde_json::Value>(JsRpc::mutation(
+                "agentUnlock",
+                json!({ "passphrase": passphrase, "holochain": holochain }),
+            ))
+            .await?;
         }
 
         let mut agent = {
@@ -334,14 +708,12 @@ impl Mutation {
     ) -> FieldResult<Agent> {
         check_capability(&context.capabilities, &AGENT_UPDATE_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let script = format!(
-            r#"JSON.stringify(
-                await core.callResolver("Mutation", "agentUpdateDirectMessageLanguage", {{ directMessageLanguage: "{}" }})
-            )"#,
-            direct_message_language
-        );
-        let result = js.execute(script).await?;
-        let result: JsResultType<Agent> = serde_json::from_str(&result)?;
+        let result: JsResultType<Agent> = js
+            .rpc(JsRpc::mutation(
+                "agentUpdateDirectMessageLanguage",
+                json!({ "directMessageLanguage": direct_message_language }),
+            ))
+            .await?;
         result.get_graphql_result()
     }
 
@@ -352,15 +724,12 @@ impl Mutation {
     ) -> FieldResult<Agent> {
         check_capability(&context.capabilities, &AGENT_UPDATE_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let perspective_json = serde_json::to_string(&perspective)?;
-        let script = format!(
-            r#"JSON.stringify(
-                await core.callResolver("Mutation", "agentUpdatePublicPerspective", {{ perspective: {} }})
-            )"#,
-            perspective_json
-        );
-        let result = js.execute(script).await?;
-        let result: JsResultType<Agent> = serde_json::from_str(&result)?;
+        let result: JsResultType<Agent> = js
+            .rpc(JsRpc::mutation(
+                "agentUpdatePublicPerspective",
+                json!({ "perspective": perspective }),
+            ))
+            .await?;
         result.get_graphql_result()
     }
 
@@ -389,14 +758,15 @@ impl Mutation {
     ) -> FieldResult<String> {
         check_capability(&context.capabilities, &EXPRESSION_CREATE_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let script = format!(
-            r#"JSON.stringify(
-                await core.callResolver("Mutation", "expressionCreate", {{ content: {}, languageAddress: "{}" }})
-            )"#,
-            content, language_address
-        );
-        let result = js.execute(script).await?;
-        let result: JsResultType<String> = serde_json::from_str(&result)?;
+        // Content is JSON, plain strings are passed on as they are
+        let content = serde_json::from_str::<serde_json::Value>(&content)
+            .unwrap_or(serde_json::Value::String(content));
+        let result: JsResultType<String> = js
+            .rpc(JsRpc::mutation(
+                "expressionCreate",
+                json!({ "content": content, "languageAddress": language_address }),
+            ))
+            .await?;
         result.get_graphql_result()
     }
 
@@ -408,18 +778,12 @@ impl Mutation {
     ) -> FieldResult<String> {
         check_capability(&context.capabilities, &EXPRESSION_UPDATE_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let interaction_call_json = serde_json::to_string(&interaction_call)?;
-        let script = format!(
-            r#"JSON.stringify(
-            await core.callResolver(
-                "Mutation",
+        let result: JsResultType<String> = js
+            .rpc(JsRpc::mutation(
                 "expressionInteract",
-                {{ interactionCall: {}, url: "{}" }},
-            ))"#,
-            interaction_call_json, url
-        );
-        let result = js.execute(script).await?;
-        let result: JsResultType<String> = serde_json::from_str(&result)?;
+                json!({ "interactionCall": interaction_call, "url": url }),
+            ))
+            .await?;
         result.get_graphql_result()
     }
 
@@ -431,17 +795,13 @@ impl Mutation {
     ) -> FieldResult<LanguageRef> {
         check_capability(&context.capabilities, &LANGUAGE_CREATE_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let script = format!(
-            r#"JSON.stringify(
-            await core.callResolver(
-                "Mutation",
+        let template_data = serde_json::from_str::<serde_json::Value>(&template_data)?.to_string();
+        let result: JsResultType<LanguageRef> = js
+            .rpc(JsRpc::mutation(
                 "languageApplyTemplateAndPublish",
-                {{ sourceLanguageHash: "{}", templateData: JSON.stringify({}) }},
-            ))"#,
-            source_language_hash, template_data
-        );
-        let result = js.execute(script).await?;
-        let result: JsResultType<LanguageRef> = serde_json::from_str(&result)?;
+                json!({ "sourceLanguageHash": source_language_hash, "templateData": template_data }),
+            ))
+            .await?;
         result.get_graphql_result()
     }
 
@@ -453,21 +813,12 @@ impl Mutation {
     ) -> FieldResult<LanguageMeta> {
         check_capability(&context.capabilities, &LANGUAGE_CREATE_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let language_meta_json = serde_json::to_string(&language_meta)?;
-        let script = format!(
-            r#"JSON.stringify(
-            await core.callResolver(
-                "Mutation",
+        let result: JsResultType<LanguageMeta> = js
+            .rpc(JsRpc::mutation(
                 "languagePublish",
-                {{ languageMeta: {}, languagePath: "{}" }},
-            ))"#,
-            language_meta_json, language_path
-        );
-
-        let result = js.execute(script).await?;
-        println!("language_publish result: {:?}", result);
-        let result: JsResultType<LanguageMeta> = serde_json::from_str(&result)?;
-        println!("language_publish result 1: {:?}", result);
+                json!({ "languageMeta": language_meta, "languagePath": language_path }),
+            ))
+            .await?;
         result.get_graphql_result()
     }
 
@@ -478,18 +829,17 @@ impl Mutation {
     ) -> FieldResult<bool> {
         check_capability(&context.capabilities, &LANGUAGE_DELETE_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let script = format!(
-            r#"JSON.stringify(
-            await core.callResolver(
-                "Mutation",
+        let result: JsResultType<bool> = js
+            .rpc(JsRpc::mutation(
                 "languageRemove",
-                {{ address: "{}" }},
-            ))"#,
-            address
-        );
-        let result = js.execute(script).await?;
-        let result: JsResultType<bool> = serde_json::from_str(&result)?;
-        result.get_graphql_result()
+                json!({ "address": address }),
+            ))
+            .await?;
+        let removed = result.get_graphql_result()?;
+        if removed {
+            permissions::forget(&address)?;
+        }
+        Ok(removed)
     }
 
     async fn language_write_settings(
@@ -500,20 +850,37 @@ impl Mutation {
     ) -> FieldResult<bool> {
         check_capability(&context.capabilities, &LANGUAGE_UPDATE_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let script = format!(
-            r#"JSON.stringify(
-            await core.callResolver(
-                "Mutation",
+        let result: JsResultType<bool> = js
+            .rpc(JsRpc::mutation(
                 "languageWriteSettings",
-                {{ languageAddress: "{}", settings: "{}" }},
-            ))"#,
-            language_address, settings
-        );
-        let result = js.execute(script).await?;
-        let result: JsResultType<bool> = serde_json::from_str(&result)?;
+                json!({ "languageAddress": language_address, "settings": settings }),
+            ))
+            .await?;
         result.get_graphql_result()
     }
 
+    /// Replaces what the language at `address` is allowed to do, checked on each access
+    async fn language_approve_permissions(
+        &self,
+        context: &RequestContext,
+        address: String,
+        permissions: LanguagePermissionsInput,
+    ) -> FieldResult<LanguagePermissions> {
+        check_capability(&context.capabilities, &LANGUAGE_UPDATE_CAPABILITY)?;
+        let permissions: LanguagePermissions = permissions.into();
+        permissions::approve(&address, &permissions)?;
+        Ok(permissions)
+    }
+
+    async fn language_revoke_permissions(
+        &self,
+        context: &RequestContext,
+        address: String,
+    ) -> FieldResult<bool> {
+        check_capability(&context.capabilities, &LANGUAGE_UPDATE_CAPABILITY)?;
+        Ok(permissions::revoke(&address)?)
+    }
+
     async fn neighbourhood_join_from_url(
         &self,
         context: &RequestContext,
@@ -541,6 +908,19 @@ impl Mutation {
         Ok(url)
     }
 
+    async fn neighbourhood_upgrade_link_language(
+        &self,
+        context: &RequestContext,
+        #[allow(non_snake_case)] perspectiveUUID: String,
+        language_address: Option<String>,
+    ) -> FieldResult<PerspectiveHandle> {
+        check_capability(&context.capabilities, &NEIGHBOURHOOD_CREATE_CAPABILITY)?;
+        Ok(
+            neighbourhoods::neighbourhood_upgrade_link_language(&perspectiveUUID, language_address)
+                .await?,
+        )
+    }
+
     async fn neighbourhood_send_broadcast(
         &self,
         context: &RequestContext,
@@ -721,6 +1101,11 @@ impl Mutation {
             &context.capabilities,
             &perspective_update_capability(vec![uuid.clone()]),
         )?;
+        check_rate_limit(
+            &context.rate_limit_key,
+            RateLimitedOperation::PerspectiveAddLinks,
+            1,
+        )?;
 
         let mut perspective = get_perspective_with_uuid_field_error(&uuid)?;
         Ok(perspective
@@ -739,6 +1124,11 @@ impl Mutation {
             &context.capabilities,
             &perspective_update_capability(vec![uuid.clone()]),
         )?;
+        check_rate_limit(
+            &context.rate_limit_key,
+            RateLimitedOperation::PerspectiveAddLinks,
+            1,
+        )?;
         let mut perspective = get_perspective_with_uuid_field_error(&uuid)?;
         let link = crate::types::LinkExpression::try_from(link)?;
         Ok(perspective
@@ -757,6 +1147,11 @@ impl Mutation {
             &context.capabilities,
             &perspective_update_capability(vec![uuid.clone()]),
         )?;
+        check_rate_limit(
+            &context.rate_limit_key,
+            RateLimitedOperation::PerspectiveAddLinks,
+            links.len() as u32,
+        )?;
         let mut perspective = get_perspective_with_uuid_field_error(&uuid)?;
         Ok(perspective
             .add_links(
@@ -777,6 +1172,11 @@ impl Mutation {
             &context.capabilities,
             &perspective_update_capability(vec![uuid.clone()]),
         )?;
+        check_rate_limit(
+            &context.rate_limit_key,
+            RateLimitedOperation::PerspectiveAddLinks,
+            mutations.additions.len() as u32,
+        )?;
         let mut perspective = get_perspective_with_uuid_field_error(&uuid)?;
         Ok(perspective
             .link_mutations(mutations, link_status_from_input(status)?)
@@ -795,6 +1195,18 @@ impl Mutation {
         unimplemented!()
     }
 
+    /// Shares the perspective with the agent's other devices, returns the URL of the
+    /// neighbourhood that keeps it in sync
+    async fn perspective_sync_with_devices(
+        &self,
+        context: &RequestContext,
+        uuid: String,
+        link_language: String,
+    ) -> FieldResult<String> {
+        check_capability(&context.capabilities, &NEIGHBOURHOOD_CREATE_CAPABILITY)?;
+        Ok(neighbourhoods::sync_perspective_with_devices(&uuid, link_language).await?)
+    }
+
     async fn perspective_remove(
         &self,
         context: &RequestContext,
@@ -990,30 +1402,46 @@ impl Mutation {
         dids: Vec<String>,
     ) -> FieldResult<Vec<String>> {
         check_capability(&context.capabilities, &RUNTIME_FRIENDS_CREATE_CAPABILITY)?;
-        let cloned_did = dids.clone();
-        let friends = RuntimeService::with_global_instance(|runtime_service| {
-            runtime_service.add_friend(dids);
+
+        // Friendship needs their consent, so only ask them.
+        // They get added to our friends once they accept.
+        for did in dids.iter() {
+            if let Err(e) = friend_requests::send_request(did, None) {
+                log::error!("Failed to send friend request to {}: {}", did, e);
+            }
+        }
+
+        Ok(RuntimeService::with_global_instance(|runtime_service| {
             runtime_service.get_friends()
-        });
+        }))
+    }
 
-        // TODO: remove this when language controller is moved.
-        let mut js = context.js_handle.clone();
-        let dids_json = serde_json::to_string(&cloned_did)?;
-        let script = format!(
-            r#"JSON.stringify(
-            await core.callResolver(
-                "Mutation",
-                "runtimeAddFriends",
-                {{ dids: {} }},
-            ))"#,
-            dids_json,
-        );
-        let result = js.execute(script).await?;
-        // TODO: what is this for? result is not used.. should this error if it can't be parsed?
-        let result: JsResultType<Vec<String>> = serde_json::from_str(&result)?;
-        result.get_graphql_result()?;
+    async fn runtime_send_friend_request(
+        &self,
+        context: &RequestContext,
+        did: String,
+        message: Option<String>,
+    ) -> FieldResult<FriendRequest> {
+        check_capability(&context.capabilities, &RUNTIME_FRIENDS_CREATE_CAPABILITY)?;
+        Ok(friend_requests::send_request(&did, message)?)
+    }
+
+    async fn runtime_accept_friend_request(
+        &self,
+        context: &RequestContext,
+        did: String,
+    ) -> FieldResult<FriendRequest> {
+        check_capability(&context.capabilities, &RUNTIME_FRIENDS_CREATE_CAPABILITY)?;
+        Ok(friend_requests::accept(&did)?)
+    }
 
-        Ok(friends)
+    async fn runtime_reject_friend_request(
+        &self,
+        context: &RequestContext,
+        did: String,
+    ) -> FieldResult<FriendRequest> {
+        check_capability(&context.capabilities, &RUNTIME_FRIENDS_DELETE_CAPABILITY)?;
+        Ok(friend_requests::reject(&did)?)
     }
 
     async fn runtime_add_known_link_language_templates(
@@ -1038,6 +1466,7 @@ impl Mutation {
         context: &RequestContext,
         did: String,
         message: PerspectiveInput,
+        encrypted: Option<bool>,
     ) -> FieldResult<bool> {
         check_capability(&context.capabilities, &RUNTIME_MESSAGES_CREATE_CAPABILITY)?;
 
@@ -1050,21 +1479,67 @@ impl Mutation {
             return Ok(false);
         }
 
-        let mut js = context.js_handle.clone();
-        let message_json = serde_json::to_string(&message)?;
-        let script = format!(
-            r#"JSON.stringify(
-            await core.callResolver(
-                "Mutation",
-                "runtimeFriendSendMessage",
-                {{ did: "{}", message: {} }},
-            ))"#,
-            did, message_json,
-        );
-        let result = js.execute(script).await?;
-        let result: JsResultType<bool> = serde_json::from_str(&result)?;
-        let get_graphql_result = result.get_graphql_result()?;
-        Ok(get_graphql_result)
+        // Messages that can't be delivered right away stay queued in the outbox and are retried
+        let sent =
+            direct_messages::send_message(did, message.into(), encrypted.unwrap_or(false)).await?;
+        Ok(matches!(
+            sent.status.parse(),
+            Ok(MessageDeliveryStatus::SentP2P | MessageDeliveryStatus::StoredInInbox)
+        ))
+    }
+
+    async fn runtime_create_conversation(
+        &self,
+        context: &RequestContext,
+        name: String,
+        members: Vec<String>,
+        encrypted: Option<bool>,
+    ) -> FieldResult<Conversation> {
+        check_capability(&context.capabilities, &RUNTIME_MESSAGES_CREATE_CAPABILITY)?;
+        Ok(conversations::create_conversation(
+            name,
+            members,
+            encrypted.unwrap_or(false),
+        )?)
+    }
+
+    async fn runtime_update_conversation_members(
+        &self,
+        context: &RequestContext,
+        conversation_id: String,
+        add: Option<Vec<String>>,
+        remove: Option<Vec<String>>,
+    ) -> FieldResult<Conversation> {
+        check_capability(&context.capabilities, &RUNTIME_MESSAGES_CREATE_CAPABILITY)?;
+        Ok(conversations::update_members(
+            &conversation_id,
+            add.unwrap_or_default(),
+            remove.unwrap_or_default(),
+        )?)
+    }
+
+    async fn runtime_send_conversation_message(
+        &self,
+        context: &RequestContext,
+        conversation_id: String,
+        message: PerspectiveInput,
+        reply_to: Option<String>,
+    ) -> FieldResult<ConversationMessage> {
+        check_capability(&context.capabilities, &RUNTIME_MESSAGES_CREATE_CAPABILITY)?;
+        Ok(conversations::send_message(
+            &conversation_id,
+            message.into(),
+            reply_to,
+        )?)
+    }
+
+    async fn runtime_mark_conversation_read(
+        &self,
+        context: &RequestContext,
+        conversation_id: String,
+    ) -> FieldResult<i32> {
+        check_capability(&context.capabilities, &RUNTIME_MESSAGES_CREATE_CAPABILITY)?;
+        Ok(conversations::mark_read(&conversation_id)? as i32)
     }
 
     async fn runtime_hc_add_agent_infos(
@@ -1114,6 +1589,10 @@ impl Mutation {
     ) -> FieldResult<Vec<String>> {
         check_capability(&context.capabilities, &RUNTIME_FRIENDS_DELETE_CAPABILITY)?;
 
+        for did in dids.iter() {
+            friend_requests::unfriend(did)?;
+        }
+
         RuntimeService::with_global_instance(|runtime_service| {
             runtime_service.remove_friend(dids.clone());
 
@@ -1145,18 +1624,12 @@ impl Mutation {
     ) -> FieldResult<bool> {
         check_capability(&context.capabilities, &RUNTIME_MY_STATUS_UPDATE_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let status_json = serde_json::to_string(&status)?;
-        let script = format!(
-            r#"JSON.stringify(
-            await core.callResolver(
-                "Mutation",
+        let result: JsResultType<bool> = js
+            .rpc(JsRpc::mutation(
                 "runtimeSetStatus",
-                {{ status: {} }},
-            ))"#,
-            status_json,
-        );
-        let result = js.execute(script).await?;
-        let result: JsResultType<bool> = serde_json::from_str(&result)?;
+                json!({ "status": status }),
+            ))
+            .await?;
         result.get_graphql_result()
     }
 
@@ -1166,7 +1639,32 @@ impl Mutation {
         notification: NotificationInput,
     ) -> FieldResult<String> {
         check_capability(&context.capabilities, &AGENT_UPDATE_CAPABILITY)?;
-        Ok(RuntimeService::request_install_notification(notification).await?)
+        Ok(
+            RuntimeService::request_install_notification(notification, context.token_hash.clone())
+                .await?,
+        )
+    }
+
+    /// Gives the notification a new webhook signing secret and returns it.
+    /// Only the app that installed the notification, or the admin, can do this.
+    async fn runtime_rotate_notification_webhook_secret(
+        &self,
+        context: &RequestContext,
+        id: String,
+    ) -> FieldResult<String> {
+        check_capability(&context.capabilities, &AGENT_UPDATE_CAPABILITY)?;
+        let installer = Ad4mDb::with_global_instance(|db| db.get_notification_installer(&id))?
+            .ok_or("Notification with given id not found")?;
+        let is_installer = !installer.is_empty() && context.token_hash.as_ref() == Some(&installer);
+        if !context.is_admin && !is_installer {
+            return Err(
+                "Only the app that installed the notification can rotate its secret".into(),
+            );
+        }
+        Ok(
+            Ad4mDb::with_global_instance(|db| db.rotate_notification_webhook_secret(&id))?
+                .ok_or("Notification with given id not found")?,
+        )
     }
 
     async fn runtime_update_notification(
@@ -1176,12 +1674,7 @@ impl Mutation {
         notification: NotificationInput,
     ) -> FieldResult<bool> {
         check_capability(&context.capabilities, &AGENT_UPDATE_CAPABILITY)?;
-
-        let notification = Notification::from_input_and_id(id.clone(), notification);
-
-        Ad4mDb::with_global_instance(|db| db.update_notification(id, &notification))?;
-
-        Ok(true)
+        Ok(RuntimeService::update_notification(id, notification)?)
     }
 
     async fn runtime_remove_notification(
@@ -1212,6 +1705,67 @@ impl Mutation {
         Ok(true)
     }
 
+    async fn runtime_add_scheduled_job(
+        &self,
+        context: &RequestContext,
+        job: ScheduledJobInput,
+    ) -> FieldResult<ScheduledJob> {
+        check_capability(&context.capabilities, &RUNTIME_JOBS_CREATE_CAPABILITY)?;
+        check_capability(
+            &context.capabilities,
+            &perspective_update_capability(vec![job.perspective_uuid.clone()]),
+        )?;
+        if job.job_type == scheduler::AI_TASK_JOB {
+            check_capability(&context.capabilities, &AI_PROMPT_CAPABILITY)?;
+        }
+        get_perspective_with_uuid_field_error(&job.perspective_uuid)?;
+        Ok(scheduler::schedule_job(job)?)
+    }
+
+    async fn runtime_pause_scheduled_job(
+        &self,
+        context: &RequestContext,
+        id: String,
+    ) -> FieldResult<ScheduledJob> {
+        check_capability(&context.capabilities, &RUNTIME_JOBS_UPDATE_CAPABILITY)?;
+        let job = get_scheduled_job_field_error(&id)?;
+        check_capability(
+            &context.capabilities,
+            &perspective_update_capability(vec![job.perspective_uuid]),
+        )?;
+        Ok(scheduler::set_job_paused(&id, true)?)
+    }
+
+    async fn runtime_resume_scheduled_job(
+        &self,
+        context: &RequestContext,
+        id: String,
+    ) -> FieldResult<ScheduledJob> {
+        check_capability(&context.capabilities, &RUNTIME_JOBS_UPDATE_CAPABILITY)?;
+        let job = get_scheduled_job_field_error(&id)?;
+        check_capability(
+            &context.capabilities,
+            &perspective_update_capability(vec![job.perspective_uuid]),
+        )?;
+        Ok(scheduler::set_job_paused(&id, false)?)
+    }
+
+    async fn runtime_remove_scheduled_job(
+        &self,
+        context: &RequestContext,
+        id: String,
+    ) -> FieldResult<bool> {
+        check_capability(&context.capabilities, &RUNTIME_JOBS_DELETE_CAPABILITY)?;
+        let job = get_scheduled_job_field_error(&id)?;
+        check_capability(
+            &context.capabilities,
+            &perspective_update_capability(vec![job.perspective_uuid]),
+        )?;
+        Ok(Ad4mDb::with_global_instance(|db| {
+            db.remove_scheduled_job(&id)
+        })?)
+    }
+
     async fn ai_add_model(
         &self,
         context: &RequestContext,
@@ -1326,6 +1880,7 @@ impl Mutation {
         prompt: String,
     ) -> FieldResult<String> {
         check_capability(&context.capabilities, &AI_PROMPT_CAPABILITY)?;
+        check_rate_limit(&context.rate_limit_key, RateLimitedOperation::AiPrompt, 1)?;
         Ok(AIService::global_instance()
             .await?
             .prompt(task_id, prompt)
@@ -1339,6 +1894,7 @@ impl Mutation {
         text: String,
     ) -> FieldResult<String> {
         check_capability(&context.capabilities, &AI_PROMPT_CAPABILITY)?;
+        check_rate_limit(&context.rate_limit_key, RateLimitedOperation::AiEmbed, 1)?;
         let vector = AIService::global_instance()
             .await?
             .embed(model_id, text)
@@ -1361,6 +1917,11 @@ impl Mutation {
         model_id: String,
     ) -> FieldResult<String> {
         check_capability(&context.capabilities, &AI_TRANSCRIBE_CAPABILITY)?;
+        check_rate_limit(
+            &context.rate_limit_key,
+            RateLimitedOperation::AiTranscribe,
+            1,
+        )?;
         Ok(AIService::global_instance()
             .await?
             .open_transcription_stream(model_id)
@@ -1,18 +1,35 @@
 #![allow(non_snake_case)]
 use super::graphql_types::*;
-use crate::agent::{capabilities::*, signatures};
+use crate::agent::{
+    capabilities::{
+        rate_limits::{rate_limit_usage, RateLimitUsage},
+        *,
+    },
+    signatures,
+};
 use crate::ai_service::AIService;
 use crate::types::{AITask, ModelType};
-use crate::{agent::AgentService, entanglement_service::get_entanglement_proofs};
+use crate::{
+    agent::AgentService,
+    entanglement_service::{get_entanglement_proofs, learn_published_proofs},
+};
 use crate::{
     db::Ad4mDb,
     holochain_service::get_holochain_service,
+    js_core::rpc::JsRpc,
+    languages::{permissions, versions},
     perspectives::{all_perspectives, get_perspective, utils::prolog_resolution_to_string},
-    runtime_service::RuntimeService,
-    types::{DecoratedLinkExpression, Model, Notification},
+    runtime_service::{
+        conversations, direct_messages, friend_requests, scheduler, web_of_trust, RuntimeService,
+    },
+    types::{
+        DecoratedLinkExpression, FriendRequest, Model, Notification, ScheduledJob, ScheduledJobRun,
+        TrustConfig, TrustReport, TrustStatement, WebhookDelivery,
+    },
 };
 use base64::prelude::*;
 use coasys_juniper::{graphql_object, FieldError, FieldResult, Value};
+use serde_json::json;
 use std::env;
 
 pub struct Query;
@@ -51,18 +68,15 @@ impl Query {
 
         if !did_match {
             let mut js = context.js_handle.clone();
-            let result = js
-                .execute(format!(
-                    r#"JSON.stringify(
-                        await core.callResolver("Query", "agentByDID",
-                            {{ did: "{}" }},
-                        )
-                    )"#,
-                    did,
-                ))
+            let result: JsResultType<Option<Agent>> = js
+                .rpc(JsRpc::query("agentByDID", json!({ "did": did })))
                 .await?;
-            let result: JsResultType<Option<Agent>> = serde_json::from_str(&result)?;
-            result.get_graphql_result()
+            let agent = result.get_graphql_result()?;
+            if let Some(agent) = agent.as_ref() {
+                learn_published_proofs(agent);
+                signatures::learn_published_key_successions(agent);
+            }
+            Ok(agent)
         } else {
             let agent_service = agent_instance.lock().expect("agent lock");
             let agent_ref: &AgentService = agent_service.as_ref().expect("agent instance");
@@ -77,12 +91,52 @@ impl Query {
 
     async fn agent_get_entanglement_proofs(
         &self,
-        _context: &RequestContext,
+        context: &RequestContext,
     ) -> FieldResult<Vec<EntanglementProof>> {
+        check_capability(
+            &context.capabilities,
+            &AGENT_ENTANGLEMENT_PROOFS_READ_CAPABILITY,
+        )?;
         let proofs = get_entanglement_proofs();
         Ok(proofs)
     }
 
+    async fn agent_key_history(&self, context: &RequestContext) -> FieldResult<Vec<KeySuccession>> {
+        check_capability(&context.capabilities, &AGENT_READ_CAPABILITY)?;
+        Ok(AgentService::with_global_instance(|agent_service| {
+            agent_service.key_history()
+        })?)
+    }
+
+    async fn agent_keys(&self, context: &RequestContext) -> FieldResult<Vec<AgentKey>> {
+        check_capability(&context.capabilities, &AGENT_READ_CAPABILITY)?;
+        Ok(AgentService::with_global_instance(|agent_service| {
+            agent_service.named_keys()
+        }))
+    }
+
+    async fn agent_mnemonic(
+        &self,
+        context: &RequestContext,
+        passphrase: String,
+    ) -> FieldResult<String> {
+        check_capability(&context.capabilities, &AGENT_CREATE_CAPABILITY)?;
+        Ok(AgentService::with_global_instance(|agent_service| {
+            agent_service.mnemonic(passphrase)
+        })?)
+    }
+
+    async fn agent_export_backup(
+        &self,
+        context: &RequestContext,
+        passphrase: String,
+    ) -> FieldResult<String> {
+        check_capability(&context.capabilities, &AGENT_CREATE_CAPABILITY)?;
+        Ok(AgentService::with_global_instance(|agent_service| {
+            agent_service.export_backup(passphrase)
+        })?)
+    }
+
     async fn agent_is_locked(&self, _context: &RequestContext) -> FieldResult<bool> {
         AgentService::with_global_instance(|agent_service| {
             let _agent = agent_service
@@ -107,13 +161,9 @@ impl Query {
     ) -> FieldResult<Option<ExpressionRendered>> {
         check_capability(&context.capabilities, &EXPRESSION_READ_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let result = js
-            .execute(format!(
-                r#"JSON.stringify(await core.callResolver("Query", "expression", {{ url: "{}" }}))"#,
-                url
-            ))
+        let result: JsResultType<Option<ExpressionRendered>> = js
+            .rpc(JsRpc::query("expression", json!({ "url": url })))
             .await?;
-        let result: JsResultType<Option<ExpressionRendered>> = serde_json::from_str(&result)?;
         result.get_graphql_result()
     }
 
@@ -124,13 +174,12 @@ impl Query {
     ) -> FieldResult<Vec<InteractionMeta>> {
         check_capability(&context.capabilities, &EXPRESSION_READ_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let result = js
-            .execute(format!(
-                r#"JSON.stringify(await core.callResolver("Query", "expressionInteractions", {{ url: "{}" }}))"#,
-                url,
+        let result: JsResultType<Vec<InteractionMeta>> = js
+            .rpc(JsRpc::query(
+                "expressionInteractions",
+                json!({ "url": url }),
             ))
             .await?;
-        let result: JsResultType<Vec<InteractionMeta>> = serde_json::from_str(&result)?;
         result.get_graphql_result()
     }
 
@@ -139,20 +188,11 @@ impl Query {
         context: &RequestContext,
         urls: Vec<String>,
     ) -> FieldResult<Vec<Option<ExpressionRendered>>> {
-        let urls_string = urls
-            .into_iter()
-            .map(|url| format!("\"{}\"", url))
-            .collect::<Vec<String>>()
-            .join(",");
         check_capability(&context.capabilities, &EXPRESSION_READ_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let result = js
-            .execute(format!(
-                r#"JSON.stringify(await core.callResolver("Query", "expressionMany", {{ urls: [{}] }}))"#,
-                urls_string,
-            ))
+        let result: JsResultType<Vec<Option<ExpressionRendered>>> = js
+            .rpc(JsRpc::query("expressionMany", json!({ "urls": urls })))
             .await?;
-        let result: JsResultType<Vec<Option<ExpressionRendered>>> = serde_json::from_str(&result)?;
         result.get_graphql_result()
     }
 
@@ -163,13 +203,9 @@ impl Query {
     ) -> FieldResult<Option<String>> {
         check_capability(&context.capabilities, &EXPRESSION_READ_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let result = js
-            .execute(format!(
-                r#"JSON.stringify(await core.callResolver("Query", "expressionRaw", {{ url: "{}" }}))"#,
-                url,
-            ))
+        let result: JsResultType<Option<String>> = js
+            .rpc(JsRpc::query("expressionRaw", json!({ "url": url })))
             .await?;
-        let result: JsResultType<Option<String>> = serde_json::from_str(&result)?;
         result.get_graphql_result()
     }
 
@@ -185,6 +221,41 @@ impl Query {
         })
     }
 
+    /// Whether `did` is trusted, directly or through trust statements, and why
+    async fn runtime_agent_trust(
+        &self,
+        context: &RequestContext,
+        did: String,
+    ) -> FieldResult<TrustReport> {
+        check_capability(
+            &context.capabilities,
+            &RUNTIME_TRUSTED_AGENTS_READ_CAPABILITY,
+        )?;
+        Ok(web_of_trust::agent_trust(&did)?)
+    }
+
+    async fn runtime_trust_statements(
+        &self,
+        context: &RequestContext,
+        issuer: Option<String>,
+    ) -> FieldResult<Vec<TrustStatement>> {
+        check_capability(
+            &context.capabilities,
+            &RUNTIME_TRUSTED_AGENTS_READ_CAPABILITY,
+        )?;
+        Ok(Ad4mDb::with_global_instance(|db| {
+            db.get_trust_statements(issuer.as_deref())
+        })?)
+    }
+
+    async fn runtime_trust_config(&self, context: &RequestContext) -> FieldResult<TrustConfig> {
+        check_capability(
+            &context.capabilities,
+            &RUNTIME_TRUSTED_AGENTS_READ_CAPABILITY,
+        )?;
+        Ok(web_of_trust::config())
+    }
+
     async fn language(
         &self,
         context: &RequestContext,
@@ -192,13 +263,9 @@ impl Query {
     ) -> FieldResult<LanguageHandle> {
         check_capability(&context.capabilities, &LANGUAGE_READ_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let result = js
-            .execute(format!(
-                r#"JSON.stringify(await core.callResolver("Query", "language", {{ address: "{}" }}))"#,
-                address,
-            ))
+        let result: JsResultType<LanguageHandle> = js
+            .rpc(JsRpc::query("language", json!({ "address": address })))
             .await?;
-        let result: JsResultType<LanguageHandle> = serde_json::from_str(&result)?;
         result.get_graphql_result()
     }
 
@@ -209,16 +276,31 @@ impl Query {
     ) -> FieldResult<LanguageMeta> {
         check_capability(&context.capabilities, &LANGUAGE_READ_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let result = js
-            .execute(format!(
-                r#"JSON.stringify(await core.callResolver("Query", "languageMeta", {{ address: "{}" }}))"#,
-                address,
-            ))
+        let result: JsResultType<LanguageMeta> = js
+            .rpc(JsRpc::query("languageMeta", json!({ "address": address })))
             .await?;
-        let result: JsResultType<LanguageMeta> = serde_json::from_str(&result)?;
         result.get_graphql_result()
     }
 
+    /// What the language at `address` was approved to do, if anything
+    async fn language_permissions(
+        &self,
+        context: &RequestContext,
+        address: String,
+    ) -> FieldResult<Option<LanguagePermissions>> {
+        check_capability(&context.capabilities, &LANGUAGE_READ_CAPABILITY)?;
+        Ok(permissions::approved(&address)?)
+    }
+
+    /// Newer versions of installed languages and of the link languages of our neighbourhoods
+    async fn language_upgrades(
+        &self,
+        context: &RequestContext,
+    ) -> FieldResult<Vec<LanguageUpgrade>> {
+        check_capability(&context.capabilities, &LANGUAGE_READ_CAPABILITY)?;
+        Ok(versions::available_upgrades().await?)
+    }
+
     async fn language_source(
         &self,
         context: &RequestContext,
@@ -226,13 +308,12 @@ impl Query {
     ) -> FieldResult<String> {
         check_capability(&context.capabilities, &LANGUAGE_READ_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let result = js
-            .execute(format!(
-                r#"JSON.stringify(await core.callResolver("Query", "languageSource", {{ address: "{}" }}))"#,
-                address,
+        let result: JsResultType<String> = js
+            .rpc(JsRpc::query(
+                "languageSource",
+                json!({ "address": address }),
             ))
             .await?;
-        let result: JsResultType<String> = serde_json::from_str(&result)?;
         result.get_graphql_result()
     }
 
@@ -241,16 +322,11 @@ impl Query {
         context: &RequestContext,
         filter: Option<String>,
     ) -> FieldResult<Vec<LanguageHandle>> {
-        let filter_string = filter.map_or("null".to_string(), |f| f.to_string());
         check_capability(&context.capabilities, &LANGUAGE_READ_CAPABILITY)?;
         let mut js = context.js_handle.clone();
-        let result = js
-            .execute(format!(
-                r#"JSON.stringify(await core.callResolver("Query", "languages", {{ filter: "{}" }}))"#,
-                filter_string,
-            ))
+        let result: JsResultType<Vec<LanguageHandle>> = js
+            .rpc(JsRpc::query("languages", json!({ "filter": filter })))
             .await?;
-        let result: JsResultType<Vec<LanguageHandle>> = serde_json::from_str(&result)?;
         result.get_graphql_result()
     }
 
@@ -408,24 +484,15 @@ impl Query {
             &RUNTIME_FRIEND_STATUS_READ_CAPABILITY,
         )?;
 
-        let friends =
-            RuntimeService::with_global_instance(|runtime_service| runtime_service.get_friends());
-
-        if !friends.contains(&did.clone()) {
-            log::error!("Friend not found: {}", did);
-
-            return Ok(PerspectiveExpression::default());
+        if !friend_requests::is_mutual_friend(&did) {
+            return Err(FieldError::new(
+                format!("Not friends with {} until both sides accepted", did),
+                Value::null(),
+            ));
         }
 
         let mut js = context.js_handle.clone();
-        let result = js
-            .execute(format!(
-                r#"JSON.stringify(await core.friendsDirectMessageLanguage("{}") ? await (await core.friendsDirectMessageLanguage("{}")).directMessageAdapter.status()  : null)"#,
-                did,
-                did
-            ))
-            .await?;
-        let result: PerspectiveExpression = serde_json::from_str(&result)?;
+        let result: PerspectiveExpression = js.rpc(JsRpc::FriendStatus { did }).await?;
         Ok(result)
     }
 
@@ -438,6 +505,17 @@ impl Query {
         })
     }
 
+    async fn runtime_friend_requests(
+        &self,
+        context: &RequestContext,
+        state: Option<String>,
+    ) -> FieldResult<Vec<FriendRequest>> {
+        check_capability(&context.capabilities, &RUNTIME_FRIENDS_READ_CAPABILITY)?;
+        Ok(Ad4mDb::with_global_instance(|db| {
+            db.get_friend_requests(state.as_deref())
+        })?)
+    }
+
     async fn runtime_hc_agent_infos(&self, context: &RequestContext) -> FieldResult<String> {
         check_capability(
             &context.capabilities,
@@ -493,33 +571,59 @@ impl Query {
         filter: Option<String>,
     ) -> FieldResult<Vec<PerspectiveExpression>> {
         check_capability(&context.capabilities, &RUNTIME_MESSAGES_READ_CAPABILITY)?;
-        let filter_str = filter
-            .map(|val| format!(r#"{{ filter: "{}" }}"#, val))
-            .unwrap_or_else(|| String::from("{ filter: null }"));
-        let script = format!(
-            r#"JSON.stringify(await (await core.myDirectMessageLanguage()).directMessageAdapter.inbox("{}"))"#,
-            filter_str,
-        );
-        let mut js = context.js_handle.clone();
-        let result = js.execute(script).await?;
-        let result: Vec<PerspectiveExpression> = serde_json::from_str(&result)?;
-        println!("llllll inbox result: {:?}", result);
-        Ok(result)
+        Ok(direct_messages::inbox(filter).await?)
     }
 
     async fn runtime_message_outbox(
         &self,
         context: &RequestContext,
-        _filter: Option<String>,
+        filter: Option<String>,
     ) -> FieldResult<Vec<SentMessage>> {
         check_capability(&context.capabilities, &RUNTIME_MESSAGES_READ_CAPABILITY)?;
 
         RuntimeService::with_global_instance(|runtime_service| {
-            let outbox = runtime_service.get_outbox();
+            let outbox = runtime_service.get_outbox(filter.as_deref());
             Ok(outbox)
         })
     }
 
+    async fn runtime_conversations(
+        &self,
+        context: &RequestContext,
+    ) -> FieldResult<Vec<Conversation>> {
+        check_capability(&context.capabilities, &RUNTIME_MESSAGES_READ_CAPABILITY)?;
+        Ok(Ad4mDb::with_global_instance(|db| db.get_conversations())?)
+    }
+
+    /// Pages backwards through a conversation, `before` is the id of the oldest message seen so far
+    async fn runtime_conversation_messages(
+        &self,
+        context: &RequestContext,
+        conversation_id: String,
+        before: Option<String>,
+        limit: Option<i32>,
+    ) -> FieldResult<Vec<ConversationMessage>> {
+        check_capability(&context.capabilities, &RUNTIME_MESSAGES_READ_CAPABILITY)?;
+        Ok(Ad4mDb::with_global_instance(|db| {
+            db.get_conversation_messages(
+                &conversation_id,
+                before.as_deref(),
+                conversations::page_size(limit),
+            )
+        })?)
+    }
+
+    async fn runtime_message_thread(
+        &self,
+        context: &RequestContext,
+        message_id: String,
+    ) -> FieldResult<Vec<ConversationMessage>> {
+        check_capability(&context.capabilities, &RUNTIME_MESSAGES_READ_CAPABILITY)?;
+        Ok(Ad4mDb::with_global_instance(|db| {
+            db.get_message_thread(&message_id)
+        })?)
+    }
+
     async fn runtime_verify_string_signed_by_did(
         &self,
         context: &RequestContext,
@@ -534,6 +638,13 @@ impl Query {
             .map_err(|e| coasys_juniper::FieldError::new(e, coasys_juniper::Value::Null))
     }
 
+    async fn runtime_rate_limit_usage(
+        &self,
+        context: &RequestContext,
+    ) -> FieldResult<Vec<RateLimitUsage>> {
+        Ok(rate_limit_usage(&context.rate_limit_key))
+    }
+
     async fn runtime_notifications(
         &self,
         context: &RequestContext,
@@ -546,6 +657,55 @@ impl Query {
         Ok(notifications_result.unwrap())
     }
 
+    async fn runtime_notification_deliveries(
+        &self,
+        context: &RequestContext,
+        notification_id: String,
+    ) -> FieldResult<Vec<WebhookDelivery>> {
+        check_capability(&context.capabilities, &AGENT_READ_CAPABILITY)?;
+        Ok(Ad4mDb::with_global_instance(|db| {
+            db.get_webhook_deliveries(&notification_id)
+        })?)
+    }
+
+    async fn runtime_scheduled_jobs(
+        &self,
+        context: &RequestContext,
+        perspective_uuid: Option<String>,
+    ) -> FieldResult<Vec<ScheduledJob>> {
+        check_capability(&context.capabilities, &RUNTIME_JOBS_READ_CAPABILITY)?;
+        check_capability(
+            &context.capabilities,
+            &perspective_query_capability(vec![perspective_uuid
+                .clone()
+                .unwrap_or(WILD_CARD.to_string())]),
+        )?;
+        Ok(Ad4mDb::with_global_instance(|db| {
+            db.get_scheduled_jobs(perspective_uuid.as_deref())
+        })?)
+    }
+
+    async fn runtime_scheduled_job_runs(
+        &self,
+        context: &RequestContext,
+        job_id: String,
+        limit: Option<i32>,
+    ) -> FieldResult<Vec<ScheduledJobRun>> {
+        check_capability(&context.capabilities, &RUNTIME_JOBS_READ_CAPABILITY)?;
+        let job = Ad4mDb::with_global_instance(|db| db.get_scheduled_job(&job_id))?
+            .ok_or_else(|| FieldError::new("Scheduled job not found", Value::null()))?;
+        check_capability(
+            &context.capabilities,
+            &perspective_query_capability(vec![job.perspective_uuid]),
+        )?;
+        let limit = limit
+            .unwrap_or(scheduler::MAX_RUNS_KEPT as i32)
+            .clamp(0, scheduler::MAX_RUNS_KEPT as i32) as u32;
+        Ok(Ad4mDb::with_global_instance(|db| {
+            db.get_scheduled_job_runs(&job_id, limit)
+        })?)
+    }
+
     async fn ai_get_models(&self, context: &RequestContext) -> FieldResult<Vec<Model>> {
         check_capability(&context.capabilities, &AGENT_READ_CAPABILITY)?;
         let models_result = Ad4mDb::with_global_instance(|db| db.get_models());
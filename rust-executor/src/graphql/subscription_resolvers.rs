@@ -12,9 +12,10 @@ use crate::{
         PERSPECTIVE_LINK_ADDED_TOPIC, PERSPECTIVE_LINK_REMOVED_TOPIC,
         PERSPECTIVE_LINK_UPDATED_TOPIC, PERSPECTIVE_REMOVED_TOPIC,
         PERSPECTIVE_SYNC_STATE_CHANGE_TOPIC, PERSPECTIVE_UPDATED_TOPIC,
-        RUNTIME_MESSAGED_RECEIVED_TOPIC, RUNTIME_NOTIFICATION_TRIGGERED_TOPIC,
+        RUNTIME_FRIEND_REQUEST_RECEIVED_TOPIC, RUNTIME_MESSAGED_RECEIVED_TOPIC,
+        RUNTIME_NOTIFICATION_TRIGGERED_TOPIC,
     },
-    types::{DecoratedLinkExpression, TriggeredNotification},
+    types::{DecoratedLinkExpression, FriendRequest, TriggeredNotification},
 };
 
 use super::graphql_types::*;
@@ -240,6 +241,20 @@ impl Subscription {
         }
     }
 
+    async fn runtime_friend_request_received(
+        &self,
+        context: &RequestContext,
+    ) -> Pin<Box<dyn Stream<Item = FieldResult<FriendRequest>> + Send>> {
+        match check_capability(&context.capabilities, &RUNTIME_FRIENDS_READ_CAPABILITY) {
+            Err(e) => Box::pin(stream::once(async move { Err(e.into()) })),
+            Ok(_) => {
+                let pubsub = get_global_pubsub().await;
+                let topic = &RUNTIME_FRIEND_REQUEST_RECEIVED_TOPIC;
+                subscribe_and_process::<FriendRequest>(pubsub, topic.to_string(), None).await
+            }
+        }
+    }
+
     async fn runtime_notification_triggered(
         &self,
         context: &RequestContext,
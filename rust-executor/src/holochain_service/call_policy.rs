@@ -0,0 +1,305 @@
+//! Timeouts and retries of zome calls.
+//!
+//! Defaults can be overridden per app, zome or function through `Ad4mConfig.zome_call_policies`
+//! and per call from JS. More specific config entries win over less specific ones, options passed
+//! with a call win over the config. Only calls marked idempotent get retried, since a call that
+//! timed out on our side may still have been committed by the conductor.
+
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+
+use crate::metrics::{HOLOCHAIN_ZOME_CALL_RETRIES, HOLOCHAIN_ZOME_CALL_TIMEOUTS};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_BACKOFF: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZomeCallOptions {
+    pub timeout_ms: Option<u64>,
+    /// How often a timed out call is tried again, only used if the call is idempotent
+    pub retries: Option<u32>,
+    /// Delay before the first retry, doubled for every further one
+    pub backoff_ms: Option<u64>,
+    pub idempotent: Option<bool>,
+}
+
+impl ZomeCallOptions {
+    fn merge(&mut self, other: &ZomeCallOptions) {
+        if other.timeout_ms.is_some() {
+            self.timeout_ms = other.timeout_ms;
+        }
+        if other.retries.is_some() {
+            self.retries = other.retries;
+        }
+        if other.backoff_ms.is_some() {
+            self.backoff_ms = other.backoff_ms;
+        }
+        if other.idempotent.is_some() {
+            self.idempotent = other.idempotent;
+        }
+    }
+}
+
+/// Options for all calls matching the given app, zome and function.
+/// Unset selectors match everything.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZomeCallPolicyConfig {
+    pub app_id: Option<String>,
+    pub zome_name: Option<String>,
+    pub fn_name: Option<String>,
+    #[serde(flatten)]
+    pub options: ZomeCallOptions,
+}
+
+impl ZomeCallPolicyConfig {
+    fn matches(&self, app_id: &str, zome_name: &str, fn_name: &str) -> bool {
+        let matches = |selector: &Option<String>, value: &str| match selector {
+            Some(selector) => selector == value,
+            None => true,
+        };
+        matches(&self.app_id, app_id)
+            && matches(&self.zome_name, zome_name)
+            && matches(&self.fn_name, fn_name)
+    }
+
+    fn specificity(&self) -> usize {
+        [&self.app_id, &self.zome_name, &self.fn_name]
+            .iter()
+            .filter(|selector| selector.is_some())
+            .count()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZomeCallPolicy {
+    pub timeout: Duration,
+    pub retries: u32,
+    pub backoff: Duration,
+}
+
+impl ZomeCallPolicy {
+    pub fn resolve(
+        policies: &[ZomeCallPolicyConfig],
+        app_id: &str,
+        zome_name: &str,
+        fn_name: &str,
+        call_options: Option<&ZomeCallOptions>,
+    ) -> ZomeCallPolicy {
+        let mut matching: Vec<&ZomeCallPolicyConfig> = policies
+            .iter()
+            .filter(|policy| policy.matches(app_id, zome_name, fn_name))
+            .collect();
+        // Stable, so later entries of the same specificity win
+        matching.sort_by_key(|policy| policy.specificity());
+
+        let mut options = ZomeCallOptions::default();
+        for policy in matching {
+            options.merge(&policy.options);
+        }
+        if let Some(call_options) = call_options {
+            options.merge(call_options);
+        }
+
+        ZomeCallPolicy {
+            timeout: options
+                .timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_TIMEOUT),
+            retries: match options.idempotent {
+                Some(true) => options.retries.unwrap_or(0),
+                _ => 0,
+            },
+            backoff: options
+                .backoff_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_BACKOFF),
+        }
+    }
+
+    fn backoff_before(&self, retry: u32) -> Duration {
+        self.backoff.saturating_mul(2u32.saturating_pow(retry))
+    }
+}
+
+lazy_static! {
+    static ref ZOME_CALL_POLICIES: RwLock<Vec<ZomeCallPolicyConfig>> = RwLock::new(Vec::new());
+}
+
+pub fn init_zome_call_policies(policies: Vec<ZomeCallPolicyConfig>) {
+    *ZOME_CALL_POLICIES
+        .write()
+        .expect("Couldn't get lock on zome call policies") = policies;
+}
+
+pub fn zome_call_policy(
+    app_id: &str,
+    zome_name: &str,
+    fn_name: &str,
+    call_options: Option<&ZomeCallOptions>,
+) -> ZomeCallPolicy {
+    let policies = ZOME_CALL_POLICIES
+        .read()
+        .expect("Couldn't get lock on zome call policies");
+    ZomeCallPolicy::resolve(&policies, app_id, zome_name, fn_name, call_options)
+}
+
+/// Runs `call` under the policy's timeout, retrying with backoff while attempts time out.
+/// Errors returned by the call itself are not retried.
+pub async fn call_with_policy<T, F, Fut>(
+    policy: &ZomeCallPolicy,
+    labels: &[(&'static str, &str)],
+    mut call: F,
+) -> Result<T, AnyError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, AnyError>>,
+{
+    let mut retry = 0;
+    loop {
+        match timeout(policy.timeout, call()).await {
+            Ok(result) => return result,
+            Err(_) => {
+                HOLOCHAIN_ZOME_CALL_TIMEOUTS.inc(labels);
+                if retry >= policy.retries {
+                    return Err(anyhow!(
+                        "Timeout error; Call Zome Function after {}ms and {} retries",
+                        policy.timeout.as_millis(),
+                        retry
+                    ));
+                }
+                sleep(policy.backoff_before(retry)).await;
+                retry += 1;
+                HOLOCHAIN_ZOME_CALL_RETRIES.inc(labels);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn policy(
+        app_id: Option<&str>,
+        fn_name: Option<&str>,
+        options: ZomeCallOptions,
+    ) -> ZomeCallPolicyConfig {
+        ZomeCallPolicyConfig {
+            app_id: app_id.map(String::from),
+            zome_name: None,
+            fn_name: fn_name.map(String::from),
+            options,
+        }
+    }
+
+    #[test]
+    fn more_specific_policies_and_call_options_win() {
+        let policies = vec![
+            policy(
+                None,
+                Some("pull"),
+                ZomeCallOptions {
+                    timeout_ms: Some(30_000),
+                    idempotent: Some(true),
+                    retries: Some(2),
+                    ..Default::default()
+                },
+            ),
+            policy(
+                None,
+                None,
+                ZomeCallOptions {
+                    timeout_ms: Some(8_000),
+                    ..Default::default()
+                },
+            ),
+            policy(
+                Some("lang"),
+                Some("pull"),
+                ZomeCallOptions {
+                    timeout_ms: Some(60_000),
+                    ..Default::default()
+                },
+            ),
+        ];
+
+        let commit = ZomeCallPolicy::resolve(&policies, "lang", "zome", "commit", None);
+        assert_eq!(commit.timeout, Duration::from_secs(8));
+        assert_eq!(commit.retries, 0);
+
+        let pull = ZomeCallPolicy::resolve(&policies, "lang", "zome", "pull", None);
+        assert_eq!(pull.timeout, Duration::from_secs(60));
+        assert_eq!(pull.retries, 2);
+
+        let other_pull = ZomeCallPolicy::resolve(&policies, "other", "zome", "pull", None);
+        assert_eq!(other_pull.timeout, Duration::from_secs(30));
+
+        let from_call = ZomeCallPolicy::resolve(
+            &policies,
+            "lang",
+            "zome",
+            "pull",
+            Some(&ZomeCallOptions {
+                timeout_ms: Some(1_000),
+                idempotent: Some(false),
+                ..Default::default()
+            }),
+        );
+        assert_eq!(from_call.timeout, Duration::from_secs(1));
+        assert_eq!(from_call.retries, 0);
+
+        let defaults = ZomeCallPolicy::resolve(&[], "lang", "zome", "pull", None);
+        assert_eq!(defaults.timeout, DEFAULT_TIMEOUT);
+        assert_eq!(defaults.retries, 0);
+    }
+
+    #[tokio::test]
+    async fn timed_out_calls_are_retried_and_counted() {
+        let policy = ZomeCallPolicy {
+            timeout: Duration::from_millis(20),
+            retries: 2,
+            backoff: Duration::from_millis(1),
+        };
+        let labels = [("fn", "retried_and_counted")];
+        let attempts = AtomicU32::new(0);
+
+        let result = call_with_policy(&policy, &labels, || {
+            let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if attempt < 2 {
+                    sleep(Duration::from_millis(200)).await;
+                }
+                Ok(attempt)
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(HOLOCHAIN_ZOME_CALL_TIMEOUTS.get(&labels), 2);
+        assert_eq!(HOLOCHAIN_ZOME_CALL_RETRIES.get(&labels), 2);
+
+        let labels = [("fn", "gives_up")];
+        let result: Result<(), AnyError> = call_with_policy(&policy, &labels, || async {
+            sleep(Duration::from_millis(200)).await;
+            Ok(())
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(HOLOCHAIN_ZOME_CALL_TIMEOUTS.get(&labels), 3);
+        assert_eq!(HOLOCHAIN_ZOME_CALL_RETRIES.get(&labels), 2);
+
+        let labels = [("fn", "errors")];
+        let result: Result<(), AnyError> =
+            call_with_policy(&policy, &labels, || async { Err(anyhow!("no such cell")) }).await;
+        assert!(result.is_err());
+        assert_eq!(HOLOCHAIN_ZOME_CALL_RETRIES.get(&labels), 0);
+    }
+}
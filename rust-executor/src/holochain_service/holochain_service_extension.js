@@ -20,8 +20,8 @@ import {
         getAppInfo: async (app_id) => {
             return get_app_info(app_id);
         },
-        callZomeFunction: async (app_id, cell_name, zome_name, fn_name, payload) => {
-            return call_zome_function(app_id, cell_name, zome_name, fn_name, payload);
+        callZomeFunction: async (app_id, cell_name, zome_name, fn_name, payload, options) => {
+            return call_zome_function(app_id, cell_name, zome_name, fn_name, payload, options);
         },
         agentInfos: async () => {
             return agent_infos();
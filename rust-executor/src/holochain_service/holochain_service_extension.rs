@@ -12,6 +12,8 @@ use tokio::time::timeout;
 
 use crate::holochain_service::{HolochainService, LocalConductorConfig};
 
+use super::call_policy::ZomeCallOptions;
+
 use super::get_holochain_service;
 
 // The duration to use for timeouts
@@ -73,15 +75,13 @@ async fn call_zome_function(
     #[string] zome_name: String,
     #[string] fn_name: String,
     #[serde] payload: Option<ExternIO>,
+    #[serde] options: Option<ZomeCallOptions>,
 ) -> Result<ZomeCallResponse, AnyError> {
-    timeout(TIMEOUT_DURATION, async {
-        let interface = get_holochain_service().await;
-        interface
-            .call_zome_function(app_id, cell_name, zome_name, fn_name, payload)
-            .await
-    })
-    .await
-    .map_err(|_| anyhow!("Timeout error"))?
+    // Timeouts and retries are applied by the service according to the call's policy
+    let interface = get_holochain_service().await;
+    interface
+        .call_zome_function(app_id, cell_name, zome_name, fn_name, payload, options)
+        .await
 }
 
 #[op2(async)]
@@ -10,6 +10,8 @@ use std::sync::Arc;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::sync::{oneshot, Mutex, RwLock};
 
+use super::call_policy::ZomeCallOptions;
+
 #[derive(Clone)]
 pub struct HolochainServiceInterface {
     pub sender: UnboundedSender<HolochainServiceRequest>,
@@ -25,6 +27,7 @@ pub enum HolochainServiceRequest {
         zome_name: String,
         fn_name: String,
         payload: Option<ExternIO>,
+        options: Option<ZomeCallOptions>,
         response: oneshot::Sender<HolochainServiceResponse>,
     },
     RemoveApp(String, oneshot::Sender<HolochainServiceResponse>),
@@ -79,6 +82,7 @@ impl HolochainServiceInterface {
         zome_name: String,
         fn_name: String,
         payload: Option<ExternIO>,
+        options: Option<ZomeCallOptions>,
     ) -> Result<ZomeCallResponse, AnyError> {
         let (response_sender, response_receiver) = oneshot::channel();
         self.sender
@@ -88,6 +92,7 @@ impl HolochainServiceInterface {
                 zome_name,
                 fn_name,
                 payload,
+                options,
                 response: response_sender,
             })?;
         match response_receiver.await.unwrap() {
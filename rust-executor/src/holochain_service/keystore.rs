@@ -0,0 +1,183 @@
+//! Holochain's in-process lair keystore encrypts its runtime secrets with the agent's
+//! passphrase. When the passphrase changes, those secrets get re-encrypted with the new
+//! one, so the keystore keeps unlocking without the old passphrase being kept around.
+
+use std::path::{Path, PathBuf};
+
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use lair_keystore_api::config::LairServerConfigInner;
+use lair_keystore_api::dependencies::sodoken;
+use lair_keystore_api::types::{BinDataSized, SecretDataSized};
+use rand::Rng;
+
+/// Where Holochain puts lair below the conductor's data root
+const LAIR_ROOT_DIRECTORY: &str = "ks";
+const LAIR_CONFIG_FILE: &str = "lair-keystore-config.yaml";
+
+pub fn lair_config_path(conductor_path: &Path) -> PathBuf {
+    conductor_path
+        .join(LAIR_ROOT_DIRECTORY)
+        .join(LAIR_CONFIG_FILE)
+}
+
+/// Derives the keys that encrypt lair's context key and id seed from a passphrase,
+/// the same way lair does when it unlocks
+async fn runtime_secret_keys(
+    passphrase: &str,
+    salt: &BinDataSized<16>,
+    ops_limit: u32,
+    mem_limit: u32,
+) -> Result<(sodoken::BufReadSized<32>, sodoken::BufReadSized<32>), AnyError> {
+    let passphrase = sodoken::BufRead::new_no_lock(passphrase.as_bytes());
+    let passphrase_hash = sodoken::BufWriteSized::<64>::new_mem_locked()?;
+    sodoken::hash::blake2b::hash(passphrase_hash.clone(), passphrase).await?;
+
+    let pre_secret = sodoken::BufWriteSized::<32>::new_mem_locked()?;
+    sodoken::hash::argon2id::hash(
+        pre_secret.clone(),
+        passphrase_hash,
+        sodoken::BufReadSized::from(salt.cloned_inner()),
+        ops_limit as u64,
+        mem_limit as usize,
+    )
+    .await?;
+
+    let context_secret = sodoken::BufWriteSized::<32>::new_mem_locked()?;
+    sodoken::kdf::derive_from_key(context_secret.clone(), 42, *b"CtxSecKy", pre_secret.clone())?;
+    let id_secret = sodoken::BufWriteSized::<32>::new_mem_locked()?;
+    sodoken::kdf::derive_from_key(id_secret.clone(), 142, *b"IdnSecKy", pre_secret)?;
+
+    Ok((context_secret.to_read_sized(), id_secret.to_read_sized()))
+}
+
+/// Re-encrypts lair's runtime secrets from `old_passphrase` to `new_passphrase`.
+/// Does nothing if Holochain hasn't created its keystore yet. Lair reads its config on
+/// startup only, so a running conductor is not affected until it gets started again.
+pub async fn change_keystore_passphrase(
+    conductor_path: &Path,
+    old_passphrase: &str,
+    new_passphrase: &str,
+) -> Result<(), AnyError> {
+    let config_path = lair_config_path(conductor_path);
+    if !config_path.exists() {
+        return Ok(());
+    }
+
+    let mut config = LairServerConfigInner::from_bytes(&std::fs::read(&config_path)?)
+        .map_err(|e| anyhow!("Couldn't read Holochain keystore config: {}", e))?;
+
+    let (context_secret, id_secret) = runtime_secret_keys(
+        old_passphrase,
+        &config.runtime_secrets_salt,
+        config.runtime_secrets_ops_limit,
+        config.runtime_secrets_mem_limit,
+    )
+    .await?;
+    let context_key = config
+        .runtime_secrets_context_key
+        .decrypt(context_secret)
+        .await
+        .map_err(|_| anyhow!("Holochain keystore is not encrypted with the old passphrase"))?;
+    let id_seed = config
+        .runtime_secrets_id_seed
+        .decrypt(id_secret)
+        .await
+        .map_err(|_| anyhow!("Holochain keystore is not encrypted with the old passphrase"))?;
+
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill(&mut salt);
+    config.runtime_secrets_salt = salt.into();
+    let (context_secret, id_secret) = runtime_secret_keys(
+        new_passphrase,
+        &config.runtime_secrets_salt,
+        config.runtime_secrets_ops_limit,
+        config.runtime_secrets_mem_limit,
+    )
+    .await?;
+    config.runtime_secrets_context_key = SecretDataSized::encrypt(context_secret, context_key)
+        .await
+        .map_err(|e| anyhow!("Couldn't encrypt Holochain keystore secrets: {}", e))?;
+    config.runtime_secrets_id_seed = SecretDataSized::encrypt(id_secret, id_seed)
+        .await
+        .map_err(|e| anyhow!("Couldn't encrypt Holochain keystore secrets: {}", e))?;
+
+    // Written next to the config and renamed, so a crash can't leave a half written file
+    let temp_path = config_path.with_extension("yaml.tmp");
+    std::fs::write(&temp_path, config.to_string())?;
+    std::fs::rename(&temp_path, &config_path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    async fn create_keystore(conductor_path: &Path, passphrase: &str) {
+        let lair_root = conductor_path.join(LAIR_ROOT_DIRECTORY);
+        std::fs::create_dir_all(&lair_root).unwrap();
+        let config = LairServerConfigInner::new(
+            &lair_root,
+            sodoken::BufRead::new_no_lock(passphrase.as_bytes()),
+        )
+        .await
+        .unwrap();
+        std::fs::write(lair_config_path(conductor_path), config.to_string()).unwrap();
+    }
+
+    async fn unlocks_with(conductor_path: &Path, passphrase: &str) -> bool {
+        let bytes = std::fs::read(lair_config_path(conductor_path)).unwrap();
+        let config = LairServerConfigInner::from_bytes(&bytes).unwrap();
+        let (context_secret, id_secret) = runtime_secret_keys(
+            passphrase,
+            &config.runtime_secrets_salt,
+            config.runtime_secrets_ops_limit,
+            config.runtime_secrets_mem_limit,
+        )
+        .await
+        .unwrap();
+        config
+            .runtime_secrets_context_key
+            .decrypt(context_secret)
+            .await
+            .is_ok()
+            && config
+                .runtime_secrets_id_seed
+                .decrypt(id_secret)
+                .await
+                .is_ok()
+    }
+
+    #[tokio::test]
+    async fn keystore_is_rekeyed_to_the_new_passphrase() {
+        let conductor_path =
+            std::env::temp_dir().join(format!("ad4m-conductor-{}", Uuid::new_v4()));
+        create_keystore(&conductor_path, "old").await;
+        // Our derivation has to match the one lair used to create the keystore
+        assert!(unlocks_with(&conductor_path, "old").await);
+
+        assert!(change_keystore_passphrase(&conductor_path, "wrong", "new")
+            .await
+            .is_err());
+        assert!(unlocks_with(&conductor_path, "old").await);
+
+        change_keystore_passphrase(&conductor_path, "old", "new")
+            .await
+            .unwrap();
+        assert!(unlocks_with(&conductor_path, "new").await);
+        assert!(!unlocks_with(&conductor_path, "old").await);
+
+        let _ = std::fs::remove_dir_all(&conductor_path);
+    }
+
+    #[tokio::test]
+    async fn missing_keystores_are_left_alone() {
+        let conductor_path =
+            std::env::temp_dir().join(format!("ad4m-conductor-{}", Uuid::new_v4()));
+        assert!(change_keystore_passphrase(&conductor_path, "old", "new")
+            .await
+            .is_ok());
+        assert!(!lair_config_path(&conductor_path).exists());
+    }
+}
@@ -1,7 +1,7 @@
 import {
     agent_did_document, agent_signing_key_id, agent_did, agent_create_signed_expression, agent_sign,
     agent_sign_string_hex, agent_load, agent, agent_is_initialized, agent_is_unlocked, agent_unlock,
-    agent_lock, agent_create_signed_expression_stringified, save_agent_profile
+    agent_lock, agent_create_signed_expression_stringified, save_agent_profile, agent_is_linked_device
 } from 'ext:core/ops';
 
 ((globalThis) => {
@@ -53,6 +53,9 @@ import {
         isUnlocked: () => {
             return agent_is_unlocked();
         },
+        isLinkedDevice: () => {
+            return agent_is_linked_device();
+        },
         unlock: (password) => {
             return agent_unlock(password);
         },
@@ -70,6 +70,11 @@ fn agent_is_unlocked() -> Result<bool, AnyError> {
     AgentService::with_global_instance(|agent_service| Ok(agent_service.is_unlocked()))
 }
 
+#[op2(fast)]
+fn agent_is_linked_device() -> Result<bool, AnyError> {
+    AgentService::with_global_instance(|agent_service| Ok(agent_service.is_linked_device()))
+}
+
 #[op2]
 #[serde]
 fn agent() -> Result<Agent, AnyError> {
@@ -123,7 +128,7 @@ fn save_agent_profile(#[serde] agent: Agent) -> Result<(), AnyError> {
 
 deno_core::extension!(
     agent_service,
-    ops = [agent_did_document, agent_signing_key_id, agent_did, agent_create_signed_expression, agent_create_signed_expression_stringified, agent_sign, agent_sign_string_hex, agent_is_initialized, agent_is_unlocked, agent, agent_load, agent_unlock, agent_lock, save_agent_profile],
+    ops = [agent_did_document, agent_signing_key_id, agent_did, agent_create_signed_expression, agent_create_signed_expression_stringified, agent_sign, agent_sign_string_hex, agent_is_initialized, agent_is_unlocked, agent_is_linked_device, agent, agent_load, agent_unlock, agent_lock, save_agent_profile],
     esm_entry_point = "ext:agent_service/agent_extension.js",
     esm = [dir "src/js_core", "agent_extension.js"]
 );
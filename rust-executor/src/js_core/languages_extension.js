@@ -1,5 +1,8 @@
 import {
-    perspective_diff_received, sync_state_changed, telepresence_signal_received
+    perspective_diff_received, sync_state_changed, telepresence_signal_received,
+    check_language_permissions, approved_language_permissions, migrate_language_permissions,
+    language_permission_denied,
+    cached_language, cache_language_bundle, cache_language_meta
 } from 'ext:core/ops';
 
 ((globalThis) => {
@@ -13,6 +16,27 @@ import {
         telepresenceSignalReceived: (signal, language_address) => {
             return telepresence_signal_received(signal, language_address);
         },
+        checkPermissions: async (language_address, name, requested) => {
+            return check_language_permissions(language_address, name, requested);
+        },
+        approvedPermissions: (language_address) => {
+            return approved_language_permissions(language_address);
+        },
+        migratePermissions: (language_address, declared) => {
+            return migrate_language_permissions(language_address, declared);
+        },
+        permissionDenied: async (language_address, permission, target) => {
+            return language_permission_denied(language_address, permission, target);
+        },
+        cachedLanguage: (language_address) => {
+            return cached_language(language_address);
+        },
+        cacheBundle: (language_address, bundle) => {
+            return cache_language_bundle(language_address, bundle);
+        },
+        cacheMeta: (language_address, meta) => {
+            return cache_language_meta(language_address, meta);
+        },
     };
   })(globalThis);
   
\ No newline at end of file
@@ -1,7 +1,11 @@
-use deno_core::op2;
+use deno_core::{error::AnyError, op2};
 
 use crate::{
-    graphql::graphql_types::{PerspectiveExpression, PerspectiveState},
+    graphql::graphql_types::{LanguagePermissions, PerspectiveExpression, PerspectiveState},
+    languages::{
+        bundle_cache::{BundleCache, CachedLanguage},
+        permissions::{self, PermissionViolation},
+    },
     types::PerspectiveDiff,
 };
 
@@ -26,9 +30,85 @@ fn telepresence_signal_received(
     crate::perspectives::handle_telepresence_signal_from_link_language(signal, language_address);
 }
 
+/// Fails, asking the user for approval, unless everything `requested` is approved
+#[op2(async)]
+async fn check_language_permissions(
+    #[string] address: String,
+    #[string] name: String,
+    #[serde] requested: LanguagePermissions,
+) -> Result<(), AnyError> {
+    permissions::check_install(&address, &name, &requested).await
+}
+
+#[op2]
+#[serde]
+fn approved_language_permissions(
+    #[string] address: String,
+) -> Result<Option<LanguagePermissions>, AnyError> {
+    permissions::approved(&address)
+}
+
+/// Approves what a language installed before permissions were checked declares
+#[op2]
+fn migrate_language_permissions(
+    #[string] address: String,
+    #[serde] declared: LanguagePermissions,
+) -> Result<bool, AnyError> {
+    permissions::migrate(&address, &declared)
+}
+
+#[op2(async)]
+async fn language_permission_denied(
+    #[string] address: String,
+    #[string] permission: String,
+    #[string] target: String,
+) -> Result<(), AnyError> {
+    permissions::report_violation(PermissionViolation {
+        address,
+        permission,
+        target,
+    })
+    .await
+}
+
+/// The cached bundle of the language at `address`, checked against the address
+#[op2]
+#[serde]
+fn cached_language(#[string] address: String) -> Result<Option<CachedLanguage>, AnyError> {
+    BundleCache::global_instance()?.get(&address)
+}
+
+/// Caches the bundle of a language fetched through the language language, if it matches `address`
+#[op2]
+fn cache_language_bundle(
+    #[string] address: String,
+    #[string] bundle: String,
+) -> Result<(), AnyError> {
+    BundleCache::global_instance()?.put_bundle(&address, &bundle)
+}
+
+#[op2]
+fn cache_language_meta(
+    #[string] address: String,
+    #[serde] meta: serde_json::Value,
+) -> Result<(), AnyError> {
+    BundleCache::global_instance()?.put_meta(&address, &meta)
+}
+
 deno_core::extension!(
     language_service,
-    ops = [perspective_diff_received, sync_state_changed, telepresence_signal_received],
+    ops = [
+        perspective_diff_received,
+        sync_state_changed,
+        telepresence_signal_received,
+        check_language_permissions,
+        approved_language_permissions,
+        migrate_language_permissions,
+        language_permission_denied,
+        cached_language,
+        cache_language_bundle,
+        cache_language_meta
+    ],
     esm_entry_point = "ext:language_service/languages_extension.js",
     esm = [dir "src/js_core", "languages_extension.js"]
 );
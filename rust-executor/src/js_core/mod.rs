@@ -4,20 +4,22 @@ use deno_core::error::AnyError;
 use deno_core::{resolve_url_or_path, v8, PollEventLoopOptions};
 use deno_runtime::worker::MainWorker;
 use deno_runtime::{permissions::PermissionsContainer, BootstrapOptions};
-use holochain::prelude::{ExternIO, Signal};
-use log::{error, info};
+use holochain::prelude::Signal;
+use log::{error, info, warn};
 use once_cell::sync::Lazy;
 use options::{main_module_url, main_worker_options};
+use serde::de::DeserializeOwned;
 use std::collections::HashSet;
 use std::env::current_dir;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
 use tokio::runtime::Builder;
 use tokio::sync::broadcast;
 use tokio::sync::Mutex as TokioMutex;
 use tokio::sync::{
     broadcast::{Receiver, Sender},
     mpsc::{self, UnboundedReceiver, UnboundedSender},
-    oneshot,
+    oneshot, Notify,
 };
 
 mod agent_extension;
@@ -25,6 +27,7 @@ mod futures;
 mod languages_extension;
 mod options;
 mod pubsub_extension;
+pub mod rpc;
 mod signature_extension;
 mod string_module_loader;
 mod utils;
@@ -32,9 +35,22 @@ mod utils_extension;
 mod wallet_extension;
 
 use self::futures::{EventLoopFuture, SmartGlobalVariableFuture};
+use self::rpc::JsRpc;
 use crate::holochain_service::maybe_get_holochain_service;
+use crate::metrics::JS_CORE_PENDING_CALLS;
 use crate::Ad4mConfig;
 
+/// Used when the config doesn't set `js_call_timeout_secs`
+const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(60);
+/// For calls that set up Holochain, install languages or sync links, see [`JsRpc::is_long_running`]
+const LONG_CALL_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_FAILED_HEALTH_CHECKS: u32 = 3;
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+/// Workers that ran this long reset the restart backoff
+const STABLE_WORKER_UPTIME: Duration = Duration::from_secs(5 * 60);
+
 pub(crate) static JS_CORE_HANDLE: Lazy<Arc<TokioMutex<Option<JsCoreHandle>>>> =
     Lazy::new(|| Arc::new(TokioMutex::new(None)));
 
@@ -43,6 +59,7 @@ pub struct JsCoreHandle {
     tx: UnboundedSender<JsCoreRequest>,
     tx_module_load: UnboundedSender<JsCoreRequest>,
     broadcast_tx: Sender<JsCoreResponse>,
+    call_timeout: Duration,
 }
 
 impl Clone for JsCoreHandle {
@@ -52,16 +69,32 @@ impl Clone for JsCoreHandle {
             tx: self.tx.clone(),
             tx_module_load: self.tx_module_load.clone(),
             broadcast_tx: self.broadcast_tx.clone(),
+            call_timeout: self.call_timeout,
         }
     }
 }
 
 impl JsCoreHandle {
-    pub async fn initialized(&mut self) {
-        self.rx.recv().await.expect("couldn't receive on channel");
+    /// Waits for the first worker to run `initCore()` and returns its error if it failed
+    pub async fn initialized(&mut self) -> Result<(), AnyError> {
+        let response = self
+            .rx
+            .recv()
+            .await
+            .map_err(|e| anyhow!("The JS core stopped before it was initialized: {}", e))?;
+        response.result.map(|_| ()).map_err(|err| anyhow!(err))
     }
 
     pub async fn execute(&mut self, script: String) -> Result<String, AnyError> {
+        self.execute_with_timeout(script, self.call_timeout).await
+    }
+
+    pub async fn execute_with_timeout(
+        &mut self,
+        script: String,
+        timeout: Duration,
+    ) -> Result<String, AnyError> {
+        let _pending = JS_CORE_PENDING_CALLS.track();
         let id = uuid::Uuid::new_v4().to_string();
         let (response_tx, response_rx) = oneshot::channel();
 
@@ -69,31 +102,53 @@ impl JsCoreHandle {
             .send(JsCoreRequest {
                 script,
                 id: id.clone(),
-                response_tx
+                response_tx,
             })
-            .expect("couldn't send on channel... it is likely that the main worker thread has crashed...");
-
-        let response = response_rx.await?;
+            .map_err(|_| anyhow!("The JS core is not running"))?;
 
-        // info!("Got response: {:?}", response);
+        wait_for_response(response_rx, timeout).await
+    }
 
-        response.result.map_err(|err| anyhow!(err))
+    /// Runs a typed request through `core.rpc` and decodes its result
+    pub async fn rpc<T: DeserializeOwned>(&mut self, request: JsRpc) -> Result<T, AnyError> {
+        let timeout = if request.is_long_running() {
+            LONG_CALL_TIMEOUT
+        } else {
+            self.call_timeout
+        };
+        let result = self
+            .execute_with_timeout(request.script()?, timeout)
+            .await?;
+        Ok(serde_json::from_str(&result)?)
     }
 
     pub async fn load_module(&mut self, path: String) -> Result<String, AnyError> {
+        let _pending = JS_CORE_PENDING_CALLS.track();
         let id = uuid::Uuid::new_v4().to_string();
         let (response_tx, response_rx) = oneshot::channel();
         self.tx_module_load
             .send(JsCoreRequest {
                 script: path,
                 id: id.clone(),
-                response_tx
+                response_tx,
             })
-            .expect("couldn't send on channel... it is likely that the main worker thread has crashed...");
+            .map_err(|_| anyhow!("The JS core is not running"))?;
 
-        let response = response_rx.await?;
+        wait_for_response(response_rx, LONG_CALL_TIMEOUT).await
+    }
+}
 
-        response.result.map_err(|err| anyhow!(err))
+/// Scripts that time out keep running in the JS core, their result gets dropped
+async fn wait_for_response(
+    response_rx: oneshot::Receiver<JsCoreResponse>,
+    timeout: Duration,
+) -> Result<String, AnyError> {
+    match tokio::time::timeout(timeout, response_rx).await {
+        Ok(Ok(response)) => response.result.map_err(|err| anyhow!(err)),
+        Ok(Err(_)) => Err(anyhow!(
+            "The JS core stopped before answering, it is being restarted"
+        )),
+        Err(_) => Err(anyhow!("The JS core didn't answer within {:?}", timeout)),
     }
 }
 
@@ -110,31 +165,47 @@ struct JsCoreResponse {
     result: Result<String, String>,
 }
 
-#[derive(Clone)]
-pub struct JsCore {
-    worker: Arc<TokioMutex<MainWorker>>,
-    loaded_modules: Arc<TokioMutex<HashSet<String>>>,
+/// How often the watchdog pings the JS core and how many missed answers it accepts
+#[derive(Clone, Copy)]
+struct HealthChecks {
+    interval: Duration,
+    timeout: Duration,
+    max_failures: u32,
 }
 
-pub struct ExternWrapper(ExternIO);
-
-impl std::fmt::Display for ExternWrapper {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        //Write the bytes to string like: [0, 1, 3]
-        let bytes = self.0.as_bytes();
-        let mut bytes_str = String::from("[");
-        for (i, byte) in bytes.iter().enumerate() {
-            bytes_str.push_str(&format!("{}", byte));
-            if i < bytes.len() - 1 {
-                bytes_str.push_str(", ");
-            }
+impl Default for HealthChecks {
+    fn default() -> Self {
+        HealthChecks {
+            interval: HEALTH_CHECK_INTERVAL,
+            timeout: HEALTH_CHECK_TIMEOUT,
+            max_failures: MAX_FAILED_HEALTH_CHECKS,
         }
-        bytes_str.push(']');
-        write!(f, "{}", bytes_str).unwrap();
-        Ok(())
     }
 }
 
+/// Lets the watchdog stop a worker that doesn't answer anymore
+struct WorkerControl {
+    isolate: v8::IsolateHandle,
+    stop: Arc<Notify>,
+}
+
+type CurrentWorker = Arc<StdMutex<Option<WorkerControl>>>;
+
+/// The ends of the channels that outlive a single worker
+#[derive(Clone)]
+struct WorkerChannels {
+    requests: Arc<TokioMutex<UnboundedReceiver<JsCoreRequest>>>,
+    module_requests: Arc<TokioMutex<UnboundedReceiver<JsCoreRequest>>>,
+    initialized: Sender<JsCoreResponse>,
+    current_worker: CurrentWorker,
+}
+
+#[derive(Clone)]
+pub struct JsCore {
+    worker: Arc<TokioMutex<MainWorker>>,
+    loaded_modules: Arc<TokioMutex<HashSet<String>>>,
+}
+
 impl JsCore {
     pub fn new() -> Self {
         deno_core::v8::V8::set_flags_from_string("--no-opt --turbo-disable-all");
@@ -199,7 +270,7 @@ impl JsCore {
         let resolve_fut = {
             let mut worker = self.worker.lock().await;
             let execute_async = worker.execute_script("js_core", wrapped_script.into());
-            worker.js_runtime.resolve(execute_async.unwrap())
+            worker.js_runtime.resolve(execute_async?)
         };
 
         Ok(SmartGlobalVariableFuture::new(
@@ -227,25 +298,28 @@ impl JsCore {
                     tokio::task::spawn_local(async move {
                         // info!("Spawn local driving: {}", id);
                         //let local_variable_name = uuid_to_valid_variable_name(&id);
-                        let script_fut = js_core_cloned
-                            .execute_async_smart(script)
-                            .await
-                            .expect("Couldn't create execute_async_smart future");
+                        let script_fut = match js_core_cloned.execute_async_smart(script).await {
+                            Ok(script_fut) => script_fut,
+                            Err(err) => {
+                                error!("Error creating script future: {:?}", err);
+                                let _ = response_tx.send(JsCoreResponse {
+                                    result: Err(err.to_string()),
+                                });
+                                return;
+                            }
+                        };
                         //info!("Script fut created: {}", id);
+                        // The caller might have timed out already
                         match script_fut.await {
                             Ok(res) => {
                                 //info!("Script execution completed Succesfully: {}", id);
-                                response_tx
-                                    .send(JsCoreResponse { result: Ok(res) })
-                                    .expect("couldn't send on channel");
+                                let _ = response_tx.send(JsCoreResponse { result: Ok(res) });
                             }
                             Err(err) => {
                                 error!("Error executing script: {:?}", err);
-                                response_tx
-                                    .send(JsCoreResponse {
-                                        result: Err(err.to_string()),
-                                    })
-                                    .expect("couldn't send on channel");
+                                let _ = response_tx.send(JsCoreResponse {
+                                    result: Err(err.to_string()),
+                                });
                             }
                         }
                     });
@@ -256,169 +330,312 @@ impl JsCore {
         }
     }
 
-    pub async fn start(config: Ad4mConfig) -> JsCoreHandle {
-        let (tx_inside, rx_outside) = broadcast::channel::<JsCoreResponse>(50);
-        let (tx_outside, rx_inside) = mpsc::unbounded_channel::<JsCoreRequest>();
-        let rx_inside = Arc::new(TokioMutex::new(rx_inside));
-
-        let (tx_outside_loader, mut rx_inside_loader) = mpsc::unbounded_channel::<JsCoreRequest>();
-
-        let tx_inside_clone = tx_inside.clone();
-        std::thread::spawn(move || {
-            let rt = Builder::new_current_thread()
-                .thread_name(String::from("js_core"))
-                .enable_all()
-                .build()
-                .expect("Failed to create Tokio runtime");
-            let _guard = rt.enter();
-
-            let js_core = JsCore::new();
-
-            rt.block_on(async {
-                let result = js_core.init_engine().await;
-                info!("AD4M JS engine init completed, with result: {:?}", result);
-
-                let result = js_core
-                    .execute_async_smart(format!("initCore({})", config.get_json()))
-                    .await
-                    .expect("to be able to create js execution future")
-                    .await ;
-
-                match result {
-                    Ok(res) => {
-                        info!("AD4M coreInit() completed Succesfully: {:?}", res);
-                        tx_inside
+    async fn isolate_handle(&self) -> v8::IsolateHandle {
+        let mut worker = self.worker.lock().await;
+        worker.js_runtime.v8_isolate().thread_safe_handle()
+    }
+
+    /// Runs one JS worker until its event loop fails or the watchdog stops it.
+    /// After a restart, the installed languages are loaded again if the agent is unlocked.
+    fn run_worker(init_script: String, channels: WorkerChannels, restarted: bool) {
+        let rt = Builder::new_current_thread()
+            .thread_name(String::from("js_core"))
+            .enable_all()
+            .build()
+            .expect("Failed to create Tokio runtime");
+        let _guard = rt.enter();
+
+        let js_core = JsCore::new();
+        let stop = Arc::new(Notify::new());
+
+        rt.block_on(async {
+            let result = js_core.init_engine().await;
+            info!("AD4M JS engine init completed, with result: {:?}", result);
+
+            let result = js_core
+                .execute_async_smart(init_script)
+                .await
+                .expect("to be able to create js execution future")
+                .await ;
+
+            match result {
+                Ok(res) => {
+                    info!("AD4M coreInit() completed Succesfully: {:?}", res);
+                    if !restarted {
+                        channels.initialized
                             .send(JsCoreResponse {
                                 result: Ok(String::from("initialized")),
                             })
                             .expect("couldn't send on channel");
                     }
-                    Err(err) => {
-                        error!("Error executing coreInit(): {:?}", err);
-                        tx_inside
-                            .send(JsCoreResponse {
-                                result: Err(format!("Error executing coreInit(): {:?}", err)),
-                            })
-                            .expect("couldn't send on channel");
+                }
+                Err(err) => {
+                    error!("Error executing coreInit(): {:?}", err);
+                    if restarted {
+                        return;
                     }
+                    channels.initialized
+                        .send(JsCoreResponse {
+                            result: Err(format!("Error executing coreInit(): {:?}", err)),
+                        })
+                        .expect("couldn't send on channel");
                 }
+            }
 
-                loop {
-                    //info!("Main loop running");
-                    //Listener future for loading JS modules into runtime
-                    let module_load_fut = async {
-                        loop {
-                            //info!("Module load loop running");
-                            if let Some(request) = rx_inside_loader.recv().await {
-                                let script = request.script;
-                                let js_core_cloned = js_core.clone();
-                                let ts_response = request.response_tx;
-
-                                tokio::task::spawn_local(async move {
-                                    match js_core_cloned.load_module(script).await {
-                                        Ok(()) => {
-                                            info!("Module loaded!");
-                                            ts_response
-                                                .send(JsCoreResponse {
-                                                    result: Ok(String::from("")),
-                                                })
-                                                .expect("couldn't send on channel");
-                                        }
-                                        Err(err) => {
-                                            error!("Error loading module: {:?}", err);
-                                            ts_response
-                                                .send(JsCoreResponse {
-                                                    result: Err(err.to_string()),
-                                                })
-                                                .expect("couldn't send on channel");
-                                        }
+            if restarted && maybe_get_holochain_service().await.is_some() {
+                let resumed = match JsRpc::Resume.script() {
+                    Ok(script) => match js_core.execute_async_smart(script).await {
+                        Ok(resume_fut) => resume_fut.await.map(|_| ()),
+                        Err(err) => Err(err),
+                    },
+                    Err(err) => Err(err),
+                };
+                match resumed {
+                    Ok(()) => info!("AD4M JS core resumed after restart"),
+                    Err(err) => error!("Error resuming JS core after restart: {:?}", err),
+                }
+            }
+
+            *channels.current_worker.lock().expect("current worker lock") = Some(WorkerControl {
+                isolate: js_core.isolate_handle().await,
+                stop: stop.clone(),
+            });
+
+            loop {
+                //info!("Main loop running");
+                //Listener future for loading JS modules into runtime
+                let module_load_fut = async {
+                    loop {
+                        //info!("Module load loop running");
+                        let mut maybe_request = channels.module_requests.lock().await;
+                        if let Some(request) = maybe_request.recv().await {
+                            let script = request.script;
+                            let js_core_cloned = js_core.clone();
+                            let ts_response = request.response_tx;
+
+                            tokio::task::spawn_local(async move {
+                                match js_core_cloned.load_module(script).await {
+                                    Ok(()) => {
+                                        info!("Module loaded!");
+                                        let _ = ts_response.send(JsCoreResponse {
+                                            result: Ok(String::from("")),
+                                        });
                                     }
-                                });
-                            }
-                            tokio::task::yield_now().await;
+                                    Err(err) => {
+                                        error!("Error loading module: {:?}", err);
+                                        let _ = ts_response.send(JsCoreResponse {
+                                            result: Err(err.to_string()),
+                                        });
+                                    }
+                                }
+                            });
                         }
-                    };
-
-                    let local_set = tokio::task::LocalSet::new();
-                    let holochain_local_set = tokio::task::LocalSet::new();
-                    let module_load_local_set = tokio::task::LocalSet::new();
-
-                    let holochain_signal_receiver_fut = async {
-                        loop {
-                            //info!("Holochain service loop");
-                            if let Some(holochain_service) = maybe_get_holochain_service().await {
-                                let mut stream_receiver = holochain_service.stream_receiver.lock().await;
-                                if let Some(signal) = stream_receiver.recv().await {
-                                    match signal.clone() {
-                                        Signal::App {
-                                            cell_id,
-                                            zome_name,
-                                            signal: payload,
-                                        } => {
-                                            let js_core_cloned = js_core.clone();
-                                            tokio::task::spawn_local(async move {
-                                                // Handle the received signal here
-                                                let script = format!(
-                                                    "await core.holochainService.handleCallback({{cell_id: [{:?}, {:?}], zome_name: '{}', signal: {}}})",
-                                                    cell_id.dna_hash().get_raw_39().to_vec(), cell_id.agent_pubkey().get_raw_39().to_vec(), zome_name, ExternWrapper(payload.into_inner())
-                                                );
-                                                match js_core_cloned.execute_async_smart(script).await {
-                                                    Ok(_res) => {
-                                                        info!(
-                                                            "Holochain Handle Callback Completed Succesfully",
-                                                        );
-                                                    }
-                                                    Err(err) => {
-                                                        error!("Error executing callback: {:?}", err);
-                                                    }
-                                                }
-                                            });
-                                        },
-                                        Signal::System(_) => {
+                        drop(maybe_request);
+                        tokio::task::yield_now().await;
+                    }
+                };
+
+                let local_set = tokio::task::LocalSet::new();
+                let holochain_local_set = tokio::task::LocalSet::new();
+                let module_load_local_set = tokio::task::LocalSet::new();
+
+                let holochain_signal_receiver_fut = async {
+                    loop {
+                        //info!("Holochain service loop");
+                        if let Some(holochain_service) = maybe_get_holochain_service().await {
+                            let mut stream_receiver = holochain_service.stream_receiver.lock().await;
+                            if let Some(signal) = stream_receiver.recv().await {
+                                match signal.clone() {
+                                    Signal::App {
+                                        cell_id,
+                                        zome_name,
+                                        signal: payload,
+                                    } => {
+                                        let js_core_cloned = js_core.clone();
+                                        tokio::task::spawn_local(async move {
                                             // Handle the received signal here
-                                            info!("Received system signal");
-                                        }
+                                            let request = JsRpc::HolochainSignal {
+                                                cell_id: (
+                                                    cell_id.dna_hash().get_raw_39().to_vec(),
+                                                    cell_id.agent_pubkey().get_raw_39().to_vec(),
+                                                ),
+                                                zome_name: zome_name.to_string(),
+                                                signal: payload.into_inner().as_bytes().to_vec(),
+                                            };
+                                            let script = match request.script() {
+                                                Ok(script) => script,
+                                                Err(err) => {
+                                                    error!("Error encoding callback: {:?}", err);
+                                                    return;
+                                                }
+                                            };
+                                            match js_core_cloned.execute_async_smart(script).await {
+                                                Ok(_res) => {
+                                                    info!(
+                                                        "Holochain Handle Callback Completed Succesfully",
+                                                    );
+                                                }
+                                                Err(err) => {
+                                                    error!("Error executing callback: {:?}", err);
+                                                }
+                                            }
+                                        });
+                                    },
+                                    Signal::System(_) => {
+                                        // Handle the received signal here
+                                        info!("Received system signal");
                                     }
                                 }
                             }
-                            tokio::task::yield_now().await;
                         }
-                    };
+                        tokio::task::yield_now().await;
+                    }
+                };
 
-                    tokio::select! {
-                        biased;
+                tokio::select! {
+                    biased;
 
-                        event_loop_result = js_core.event_loop() => {
-                            match event_loop_result {
-                                Ok(_) => {} //info!("AD4M event loop finished"),
-                                Err(err) => {
-                                    error!("AD4M event loop closed with error: {}", err);
-                                    break;
-                                }
+                    _ = stop.notified() => {
+                        error!("AD4M JS core stopped by watchdog");
+                        break;
+                    }
+                    event_loop_result = js_core.event_loop() => {
+                        match event_loop_result {
+                            Ok(_) => {} //info!("AD4M event loop finished"),
+                            Err(err) => {
+                                error!("AD4M event loop closed with error: {}", err);
+                                break;
                             }
                         }
-                        _drive_local_set = local_set.run_until(Self::generate_execution_slot(rx_inside.clone(), js_core.clone())) => {
-                            info!("AD4M drive local set completed");
-                        }
-                        _module_load = module_load_local_set.run_until(module_load_fut) => {
-                            info!("AD4M module load completed");
-                            //break;
-                        }
-                        _holochain_signal_receivers = holochain_local_set.run_until(holochain_signal_receiver_fut) => {
-                            info!("AD4M holochain signal receiver completed");
-                        }
+                    }
+                    _drive_local_set = local_set.run_until(Self::generate_execution_slot(channels.requests.clone(), js_core.clone())) => {
+                        info!("AD4M drive local set completed");
+                    }
+                    _module_load = module_load_local_set.run_until(module_load_fut) => {
+                        info!("AD4M module load completed");
+                        //break;
+                    }
+                    _holochain_signal_receivers = holochain_local_set.run_until(holochain_signal_receiver_fut) => {
+                        info!("AD4M holochain signal receiver completed");
                     }
                 }
-            })
+            }
         });
+    }
+
+    /// Keeps a JS worker running, starting a new one whenever the current one stops.
+    /// Requests sent in the meantime wait in the channels for the next worker.
+    fn supervise(init_script: String, channels: WorkerChannels) {
+        let mut restarts: u32 = 0;
+        let mut restarted = false;
+        loop {
+            let started = Instant::now();
+            let worker_init_script = init_script.clone();
+            let worker_channels = channels.clone();
+            let worker = std::thread::Builder::new()
+                .name(String::from("js_core"))
+                .spawn(move || Self::run_worker(worker_init_script, worker_channels, restarted));
+            match worker.map(|worker| worker.join()) {
+                Ok(Ok(())) => error!("AD4M JS core stopped"),
+                Ok(Err(_)) => error!("AD4M JS core crashed"),
+                Err(err) => error!("Couldn't start AD4M JS core thread: {}", err),
+            }
+            restarted = true;
+            *channels.current_worker.lock().expect("current worker lock") = None;
+
+            if started.elapsed() > STABLE_WORKER_UPTIME {
+                restarts = 0;
+            }
+            let backoff = Duration::from_secs(1 << restarts.min(6)).min(MAX_RESTART_BACKOFF);
+            restarts += 1;
+            warn!("Restarting AD4M JS core in {:?}", backoff);
+            std::thread::sleep(backoff);
+        }
+    }
+
+    /// Pings the JS core and stops it for a restart once it stopped answering
+    async fn watchdog(
+        mut handle: JsCoreHandle,
+        current_worker: CurrentWorker,
+        health_checks: HealthChecks,
+    ) {
+        let mut failed_checks = 0;
+        loop {
+            tokio::time::sleep(health_checks.interval).await;
+            match handle
+                .execute_with_timeout(String::from("true"), health_checks.timeout)
+                .await
+            {
+                Ok(_) => failed_checks = 0,
+                Err(err) => {
+                    failed_checks += 1;
+                    warn!(
+                        "AD4M JS core health check failed ({}/{}): {}",
+                        failed_checks, health_checks.max_failures, err
+                    );
+                    if failed_checks < health_checks.max_failures {
+                        continue;
+                    }
+                    failed_checks = 0;
+                    // Still starting up if there's no worker yet
+                    let worker = current_worker.lock().expect("current worker lock").take();
+                    if let Some(worker) = worker {
+                        error!("AD4M JS core is unresponsive, restarting it");
+                        worker.stop.notify_one();
+                        // Gets it out of scripts that never return
+                        worker.isolate.terminate_execution();
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn start(config: Ad4mConfig) -> JsCoreHandle {
+        let call_timeout = config
+            .js_call_timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_CALL_TIMEOUT);
+        Self::start_supervised(
+            format!("initCore({})", config.get_json()),
+            call_timeout,
+            HealthChecks::default(),
+        )
+        .await
+    }
+
+    async fn start_supervised(
+        init_script: String,
+        call_timeout: Duration,
+        health_checks: HealthChecks,
+    ) -> JsCoreHandle {
+        let (tx_inside, rx_outside) = broadcast::channel::<JsCoreResponse>(50);
+        let (tx_outside, rx_inside) = mpsc::unbounded_channel::<JsCoreRequest>();
+        let (tx_outside_loader, rx_inside_loader) = mpsc::unbounded_channel::<JsCoreRequest>();
+
+        let channels = WorkerChannels {
+            requests: Arc::new(TokioMutex::new(rx_inside)),
+            module_requests: Arc::new(TokioMutex::new(rx_inside_loader)),
+            initialized: tx_inside.clone(),
+            current_worker: CurrentWorker::default(),
+        };
+        let current_worker = channels.current_worker.clone();
+
+        std::thread::spawn(move || Self::supervise(init_script, channels));
 
         let handle = JsCoreHandle {
             rx: rx_outside,
             tx: tx_outside,
             tx_module_load: tx_outside_loader,
-            broadcast_tx: tx_inside_clone,
+            broadcast_tx: tx_inside,
+            call_timeout,
         };
 
+        tokio::spawn(Self::watchdog(
+            handle.clone(),
+            current_worker,
+            health_checks,
+        ));
+
         //Set the JsCoreHandle to a global object so we can use it inside of deno op calls
         let mut global_handle = JS_CORE_HANDLE.lock().await;
         *global_handle = Some(handle.clone());
@@ -426,3 +643,51 @@ impl JsCore {
         handle
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn hanging_scripts_time_out_and_the_worker_gets_restarted() {
+        let mut handle = JsCore::start_supervised(
+            String::from("true"),
+            Duration::from_secs(2),
+            HealthChecks {
+                interval: Duration::from_millis(200),
+                timeout: Duration::from_millis(200),
+                max_failures: 2,
+            },
+        )
+        .await;
+        handle.initialized().await.unwrap();
+
+        handle
+            .execute(String::from("globalThis.firstWorker = true"))
+            .await
+            .unwrap();
+        let hanging = handle
+            .execute(String::from("(() => { while (true) {} })()"))
+            .await;
+        assert!(hanging
+            .unwrap_err()
+            .to_string()
+            .contains("didn't answer within"));
+
+        // Calls made while the watchdog replaces the stuck worker may still reach it
+        let deadline = Instant::now() + Duration::from_secs(30);
+        loop {
+            let worker = handle
+                .execute(String::from("typeof globalThis.firstWorker"))
+                .await;
+            if let Ok(worker) = worker {
+                if worker == "undefined" {
+                    break;
+                }
+            }
+            assert!(Instant::now() < deadline, "JS core wasn't restarted");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(handle.execute(String::from("1 + 1")).await.unwrap(), "2");
+    }
+}
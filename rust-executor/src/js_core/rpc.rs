@@ -0,0 +1,324 @@
+//! Typed calls from Rust into the JS core.
+//!
+//! Requests never become part of the JavaScript source. They are serialized to JSON, embedded
+//! as a single string literal and handed to `JSON.parse`, so the only code that runs is the
+//! fixed entry point `core.rpc`, which dispatches on `op` (see `executor/src/core/Rpc.ts`).
+
+use deno_core::error::AnyError;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::graphql::graphql_types::{Neighbourhood, Perspective, PerspectiveExpression};
+use crate::types::PerspectiveDiff;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum ResolverType {
+    Query,
+    Mutation,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "method", rename_all = "camelCase")]
+pub enum LinksAdapterCall {
+    Sync,
+    Commit { diff: PerspectiveDiff },
+    CurrentRevision,
+    Render,
+    Others,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "method", rename_all = "camelCase")]
+pub enum TelepresenceAdapterCall {
+    /// Whether the language has a telepresence adapter at all
+    Exists,
+    SetOnlineStatus {
+        status: PerspectiveExpression,
+    },
+    GetOnlineAgents,
+    #[serde(rename_all = "camelCase")]
+    SendSignal {
+        remote_agent_did: String,
+        payload: PerspectiveExpression,
+    },
+    SendBroadcast {
+        payload: PerspectiveExpression,
+    },
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum JsRpc {
+    /// Runs one of the resolvers still implemented in JS, results come back as `JsResultType`
+    #[serde(rename_all = "camelCase")]
+    CallResolver {
+        resolver_type: ResolverType,
+        name: String,
+        args: Value,
+    },
+    WaitForLanguages,
+    /// Sets a restarted JS core up again, with the Holochain conductor still running
+    Resume,
+    EnsureAgentExpression,
+    /// Publishes the agent profile as it is in the agent service
+    PublishAgentExpression,
+    InstallLanguage {
+        address: String,
+    },
+    /// Whether the language is installed, installing it if needed
+    LanguageInstalled {
+        address: String,
+    },
+    GetNeighbourhood {
+        address: String,
+    },
+    CreateNeighbourhood {
+        neighbourhood: Neighbourhood,
+    },
+    AgentByDid {
+        did: String,
+    },
+    SendDirectMessage {
+        did: String,
+        message: Perspective,
+    },
+    DirectMessageInbox {
+        author: Option<String>,
+    },
+    FriendStatus {
+        did: String,
+    },
+    LinksAdapter {
+        address: String,
+        call: LinksAdapterCall,
+    },
+    TelepresenceAdapter {
+        address: String,
+        call: TelepresenceAdapterCall,
+    },
+    #[serde(rename_all = "camelCase")]
+    HolochainSignal {
+        cell_id: (Vec<u8>, Vec<u8>),
+        zome_name: String,
+        signal: Vec<u8>,
+    },
+}
+
+impl JsRpc {
+    pub fn query(name: &str, args: Value) -> Self {
+        JsRpc::CallResolver {
+            resolver_type: ResolverType::Query,
+            name: name.to_string(),
+            args,
+        }
+    }
+
+    pub fn mutation(name: &str, args: Value) -> Self {
+        JsRpc::CallResolver {
+            resolver_type: ResolverType::Mutation,
+            name: name.to_string(),
+            args,
+        }
+    }
+
+    /// Calls that start Holochain or install languages, they get a longer timeout.
+    /// So do links adapter syncs and renders, which retry zome calls that can each take
+    /// up to 30s in p-diff-sync (a render may take about 90s) before giving up.
+    pub fn is_long_running(&self) -> bool {
+        match self {
+            JsRpc::CallResolver { name, .. } => matches!(
+                name.as_str(),
+                "agentGenerate"
+                    | "agentUnlock"
+                    | "languagePublish"
+                    | "languageApplyTemplateAndPublish"
+            ),
+            JsRpc::WaitForLanguages
+            | JsRpc::Resume
+            | JsRpc::InstallLanguage { .. }
+            | JsRpc::LanguageInstalled { .. }
+            | JsRpc::CreateNeighbourhood { .. } => true,
+            JsRpc::LinksAdapter { call, .. } => {
+                matches!(call, LinksAdapterCall::Sync | LinksAdapterCall::Render)
+            }
+            _ => false,
+        }
+    }
+
+    /// The script that runs this request, it evaluates to the JSON encoded result
+    pub fn script(&self) -> Result<String, AnyError> {
+        let request = serde_json::to_string(&serde_json::to_string(self)?)?;
+        Ok(format!("{}{}{}", SCRIPT_PREFIX, request, SCRIPT_SUFFIX))
+    }
+}
+
+const SCRIPT_PREFIX: &str = "JSON.stringify((await core.rpc(JSON.parse(";
+const SCRIPT_SUFFIX: &str = "))) ?? null)";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const HOSTILE_INPUTS: &[&str] = &[
+        r#"" }); Deno.exit(1); ({ ""#,
+        r#"\", Deno.exit(1), \""#,
+        "'); Deno.exit(1); ('",
+        "`${Deno.exit(1)}`",
+        "\")))); Deno.exit(1); JSON.parse((((\"",
+        "line\nbreak\r\u{2028}\u{2029}",
+        "nul\u{0}byte",
+        "</script><script>Deno.exit(1)</script>",
+        "\\",
+        "\u{1F600} \u{FEFF}",
+    ];
+
+    /// The literal passed to `JSON.parse`, asserting the script is nothing else
+    fn embedded_literal(script: &str) -> &str {
+        assert!(script.starts_with(SCRIPT_PREFIX));
+        assert!(script.ends_with(SCRIPT_SUFFIX));
+        &script[SCRIPT_PREFIX.len()..script.len() - SCRIPT_SUFFIX.len()]
+    }
+
+    fn assert_data_only(request: JsRpc) {
+        let script = request.script().unwrap();
+        let literal = embedded_literal(&script);
+
+        // A single JSON string token, which is also a valid JS string literal, that can only
+        // contain quotes, backslashes and line breaks in escaped form
+        assert!(literal.starts_with('"') && literal.ends_with('"'));
+        let inner = &literal[1..literal.len() - 1];
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            assert!(
+                c != '"' && c != '\n' && c != '\r' && c >= ' ',
+                "{:?}",
+                literal
+            );
+            if c == '\\' {
+                assert!(chars.next().is_some());
+            }
+        }
+
+        let json: String = serde_json::from_str(literal).unwrap();
+        let parsed: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, serde_json::to_value(&request).unwrap());
+    }
+
+    #[test]
+    fn hostile_strings_stay_data() {
+        for input in HOSTILE_INPUTS {
+            let input = input.to_string();
+            assert_data_only(JsRpc::InstallLanguage {
+                address: input.clone(),
+            });
+            assert_data_only(JsRpc::mutation(
+                "languagePublish",
+                json!({ "languagePath": input.clone(), "languageMeta": { "name": input.clone() } }),
+            ));
+            assert_data_only(JsRpc::mutation(
+                "languageApplyTemplateAndPublish",
+                json!({ "sourceLanguageHash": input.clone(), "templateData": input.clone() }),
+            ));
+            assert_data_only(JsRpc::GetNeighbourhood {
+                address: input.clone(),
+            });
+            assert_data_only(JsRpc::TelepresenceAdapter {
+                address: input.clone(),
+                call: TelepresenceAdapterCall::SendSignal {
+                    remote_agent_did: input.clone(),
+                    payload: PerspectiveExpression::default(),
+                },
+            });
+            assert_data_only(JsRpc::HolochainSignal {
+                cell_id: (vec![1, 2], vec![3]),
+                zome_name: input.clone(),
+                signal: vec![0, 255],
+            });
+        }
+    }
+
+    #[test]
+    fn hostile_strings_survive_unchanged() {
+        for input in HOSTILE_INPUTS {
+            let script = JsRpc::mutation("languageRemove", json!({ "address": input }))
+                .script()
+                .unwrap();
+            let json: String = serde_json::from_str(embedded_literal(&script)).unwrap();
+            let parsed: Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed["args"]["address"], json!(input));
+        }
+    }
+
+    #[test]
+    fn only_setup_installs_and_link_syncs_are_long_running() {
+        assert!(JsRpc::mutation("agentUnlock", json!({})).is_long_running());
+        assert!(JsRpc::InstallLanguage {
+            address: "Qm123".to_string()
+        }
+        .is_long_running());
+        assert!(JsRpc::LinksAdapter {
+            address: "Qm123".to_string(),
+            call: LinksAdapterCall::Render,
+        }
+        .is_long_running());
+        assert!(JsRpc::LinksAdapter {
+            address: "Qm123".to_string(),
+            call: LinksAdapterCall::Sync,
+        }
+        .is_long_running());
+        assert!(!JsRpc::LinksAdapter {
+            address: "Qm123".to_string(),
+            call: LinksAdapterCall::CurrentRevision,
+        }
+        .is_long_running());
+        assert!(!JsRpc::mutation("languageRemove", json!({})).is_long_running());
+        assert!(!JsRpc::FriendStatus {
+            did: "did:key:z6Mk".to_string()
+        }
+        .is_long_running());
+    }
+
+    #[test]
+    fn requests_are_tagged_with_their_operation() {
+        assert_eq!(
+            serde_json::to_value(JsRpc::query("expression", json!({ "url": "lit://x" }))).unwrap(),
+            json!({
+                "op": "callResolver",
+                "resolverType": "Query",
+                "name": "expression",
+                "args": { "url": "lit://x" },
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(JsRpc::LinksAdapter {
+                address: "Qm123".to_string(),
+                call: LinksAdapterCall::CurrentRevision,
+            })
+            .unwrap(),
+            json!({
+                "op": "linksAdapter",
+                "address": "Qm123",
+                "call": { "method": "currentRevision" },
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(JsRpc::DirectMessageInbox { author: None }).unwrap(),
+            json!({ "op": "directMessageInbox", "author": null })
+        );
+        assert_eq!(
+            serde_json::to_value(JsRpc::HolochainSignal {
+                cell_id: (vec![1], vec![2]),
+                zome_name: "zome".to_string(),
+                signal: vec![3],
+            })
+            .unwrap(),
+            json!({
+                "op": "holochainSignal",
+                "cellId": [[1], [2]],
+                "zomeName": "zome",
+                "signal": [3],
+            })
+        );
+    }
+}
@@ -12,6 +12,9 @@ import {
         verify: (expression) => {
             let { isValid } = signature_verify(expression);
             return isValid;
+        },
+        check: (expression) => {
+            return signature_verify(expression);
         }
     };
 })(globalThis);
\ No newline at end of file
@@ -1,4 +1,4 @@
-use crate::agent::signatures::{verify, verify_string_signed_by_did};
+use crate::agent::signatures::{check_signature, verify_string_signed_by_did};
 use crate::types::Expression;
 use deno_core::{error::AnyError, op2};
 use serde::{Deserialize, Serialize};
@@ -9,6 +9,7 @@ use super::utils::sort_json_value;
 #[serde(rename_all = "camelCase")]
 pub struct SignatureVerificationResult {
     pub is_valid: bool,
+    pub retired_key: bool,
 }
 
 #[op2]
@@ -19,7 +20,10 @@ fn signature_verify_string_signed_by_did(
     #[string] signed_data: String,
 ) -> Result<SignatureVerificationResult, AnyError> {
     let is_valid = verify_string_signed_by_did(&did, &data, &signed_data)?;
-    Ok(SignatureVerificationResult { is_valid })
+    Ok(SignatureVerificationResult {
+        is_valid,
+        retired_key: false,
+    })
 }
 
 #[op2]
@@ -33,8 +37,11 @@ fn signature_verify(
         data: sort_json_value(&expr.data),
         proof: expr.proof,
     };
-    let is_valid = verify(&sorted_expression)?;
-    Ok(SignatureVerificationResult { is_valid })
+    let check = check_signature(&sorted_expression)?;
+    Ok(SignatureVerificationResult {
+        is_valid: check.valid,
+        retired_key: check.retired_key,
+    })
 }
 
 deno_core::extension!(
@@ -1,24 +1,12 @@
-use cid::Cid;
 use deno_core::{error::AnyError, op2};
 use log::{debug, error, info, warn};
-use multibase::Base;
-use multihash::{Code, MultihashDigest};
 
 use super::JS_CORE_HANDLE;
 
 #[op2]
 #[string]
 fn hash(#[string] data: String) -> Result<String, AnyError> {
-    // Compute the SHA-256 multihash
-    let multihash = Code::Sha2_256.digest(data.as_bytes());
-
-    // Create a CID with default settings (version 1, DAG-Protobuf)
-    let cid = Cid::new_v1(0, multihash);
-
-    // Encode the CID in base58btc (IPFS default)
-    let encoded_cid = multibase::encode(Base::Base58Btc, cid.to_bytes());
-
-    Ok(format!("Qm{}", encoded_cid))
+    Ok(crate::utils::ipfs_hash(&data))
 }
 
 #[op2]
@@ -2,7 +2,7 @@ use base64::{engine::general_purpose as base64engine, Engine as _};
 use deno_core::{anyhow::anyhow, error::AnyError, op2};
 use serde::{Deserialize, Serialize};
 
-use crate::wallet::Wallet;
+use crate::wallet::{Wallet, MAIN_KEY_NAME};
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -18,7 +18,7 @@ fn wallet_get_main_key() -> Result<Key, AnyError> {
     let wallet_instance = Wallet::instance();
     let wallet = wallet_instance.lock().expect("wallet lock");
     let wallet_ref = wallet.as_ref().expect("wallet instance");
-    let name = "main".to_string();
+    let name = MAIN_KEY_NAME.to_string();
     let public_key = wallet_ref
         .get_public_key(&name)
         .ok_or(anyhow!("main key not found. call createMainKey() first"))?;
@@ -38,7 +38,7 @@ fn wallet_get_main_key_document() -> Result<did_key::Document, AnyError> {
     let wallet_instance = Wallet::instance();
     let wallet = wallet_instance.lock().expect("wallet lock");
     let wallet_ref = wallet.as_ref().expect("wallet instance");
-    let name = "main".to_string();
+    let name = MAIN_KEY_NAME.to_string();
     wallet_ref
         .get_did_document(&name)
         .ok_or(anyhow!("main key not found. call createMainKey() first"))
@@ -50,7 +50,7 @@ fn wallet_create_main_key() -> Result<(), AnyError> {
     let wallet_instance = Wallet::instance();
     let mut wallet = wallet_instance.lock().expect("wallet lock");
     let wallet_ref = wallet.as_mut().expect("wallet instance");
-    wallet_ref.generate_keypair("main".to_string());
+    wallet_ref.generate_keypair(MAIN_KEY_NAME.to_string());
     Ok(())
 }
 
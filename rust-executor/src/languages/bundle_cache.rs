@@ -0,0 +1,340 @@
+//! Local cache of language bundles.
+//!
+//! Bundles are kept in `<app data>/language_cache/<address>/bundle.js`, next to the signed
+//! language expression in `meta.json` once we have it. The address of a language is the hash of
+//! its bundle, so bundles get checked against their address on the way in and on the way out,
+//! and whatever filled the cache can't make us run something other than what was asked for.
+//!
+//! Installing a language looks here before asking the language language, which lets executors
+//! without network install the languages pre-seeded through `Ad4mConfig.language_bundle_seed`.
+
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::utils::ipfs_hash;
+
+const BUNDLE_FILE: &str = "bundle.js";
+const META_FILE: &str = "meta.json";
+
+lazy_static! {
+    static ref BUNDLE_CACHE: Mutex<Option<BundleCache>> = Mutex::new(None);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CachedLanguage {
+    pub bundle: String,
+    /// The language expression, if it was cached or seeded with the bundle
+    pub meta: Option<Value>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BundleCache {
+    directory: PathBuf,
+}
+
+/// A bundle found in a seed, with the address it was filed under if any
+struct SeedEntry {
+    address: Option<String>,
+    bundle: String,
+    meta: Option<Value>,
+}
+
+impl BundleCache {
+    pub fn new(directory: PathBuf) -> Self {
+        BundleCache { directory }
+    }
+
+    pub fn init_global_instance(app_data_path: &str) {
+        let cache = BundleCache::new(Path::new(app_data_path).join("language_cache"));
+        *BUNDLE_CACHE.lock().expect("bundle cache lock") = Some(cache);
+    }
+
+    pub fn global_instance() -> Result<BundleCache, AnyError> {
+        BUNDLE_CACHE
+            .lock()
+            .expect("bundle cache lock")
+            .clone()
+            .ok_or(anyhow!("Language bundle cache not initialized"))
+    }
+
+    /// Addresses come from the JS core, so they must not be able to point out of the cache
+    fn entry_directory(&self, address: &str) -> Result<PathBuf, AnyError> {
+        if address.is_empty() || !address.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(anyhow!("Invalid language address {}", address));
+        }
+        Ok(self.directory.join(address))
+    }
+
+    /// The cached language at `address`. Bundles that don't match their address are removed.
+    pub fn get(&self, address: &str) -> Result<Option<CachedLanguage>, AnyError> {
+        let directory = self.entry_directory(address)?;
+        let bundle_path = directory.join(BUNDLE_FILE);
+        if !bundle_path.exists() {
+            return Ok(None);
+        }
+
+        let bundle = fs::read_to_string(&bundle_path)?;
+        if ipfs_hash(&bundle) != address {
+            log::warn!(
+                "Removing cached language bundle {} which does not match its address",
+                address
+            );
+            fs::remove_dir_all(&directory)?;
+            return Ok(None);
+        }
+
+        let meta = fs::read_to_string(directory.join(META_FILE))
+            .ok()
+            .and_then(|meta| serde_json::from_str(&meta).ok());
+        Ok(Some(CachedLanguage { bundle, meta }))
+    }
+
+    pub fn put_bundle(&self, address: &str, bundle: &str) -> Result<(), AnyError> {
+        let hash = ipfs_hash(bundle);
+        if hash != address {
+            return Err(anyhow!(
+                "Language bundle hashes to {}, not to its address {}",
+                hash,
+                address
+            ));
+        }
+        let directory = self.entry_directory(address)?;
+        fs::create_dir_all(&directory)?;
+        fs::write(directory.join(BUNDLE_FILE), bundle)?;
+        Ok(())
+    }
+
+    /// Caches the language expression, which has to be about the language at `address`
+    pub fn put_meta(&self, address: &str, meta: &Value) -> Result<(), AnyError> {
+        if let Some(meta_address) = meta["data"]["address"].as_str() {
+            if meta_address != address {
+                return Err(anyhow!(
+                    "Language meta is about {}, not about {}",
+                    meta_address,
+                    address
+                ));
+            }
+        }
+        let directory = self.entry_directory(address)?;
+        fs::create_dir_all(&directory)?;
+        fs::write(directory.join(META_FILE), serde_json::to_string(meta)?)?;
+        Ok(())
+    }
+
+    /// Adds the bundles found at `source`, a directory or a zip archive, to the cache and
+    /// returns their addresses.
+    ///
+    /// Bundles are either laid out like the cache, `<address>/bundle.js` with an optional
+    /// `<address>/meta.json`, or loose `.js` files which get filed under their hash.
+    /// Bundles filed under an address they don't hash to are skipped.
+    pub fn seed(&self, source: &Path) -> Result<Vec<String>, AnyError> {
+        let entries = if source.is_dir() {
+            seed_entries_from_directory(source)?
+        } else if source.extension().and_then(|e| e.to_str()) == Some("zip") {
+            seed_entries_from_zip(source)?
+        } else {
+            return Err(anyhow!(
+                "Language bundle seed {} is neither a directory nor a zip archive",
+                source.display()
+            ));
+        };
+
+        let mut seeded = Vec::new();
+        for entry in entries {
+            let address = ipfs_hash(&entry.bundle);
+            if let Some(filed_under) = &entry.address {
+                if filed_under != &address {
+                    log::warn!(
+                        "Skipping language bundle seeded as {} which hashes to {}",
+                        filed_under,
+                        address
+                    );
+                    continue;
+                }
+            }
+            self.put_bundle(&address, &entry.bundle)?;
+            if let Some(meta) = &entry.meta {
+                if let Err(e) = self.put_meta(&address, meta) {
+                    log::warn!("Not caching seeded meta of language {}: {}", address, e);
+                }
+            }
+            seeded.push(address);
+        }
+        Ok(seeded)
+    }
+}
+
+fn seed_entries_from_directory(source: &Path) -> Result<Vec<SeedEntry>, AnyError> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(source)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            let bundle_path = path.join(BUNDLE_FILE);
+            if !bundle_path.exists() {
+                continue;
+            }
+            entries.push(SeedEntry {
+                address: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned()),
+                bundle: fs::read_to_string(bundle_path)?,
+                meta: fs::read_to_string(path.join(META_FILE))
+                    .ok()
+                    .and_then(|meta| serde_json::from_str(&meta).ok()),
+            });
+        } else if path.extension().and_then(|e| e.to_str()) == Some("js") {
+            entries.push(SeedEntry {
+                address: None,
+                bundle: fs::read_to_string(path)?,
+                meta: None,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+fn seed_entries_from_zip(source: &Path) -> Result<Vec<SeedEntry>, AnyError> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(source)?)?;
+    let mut filed: BTreeMap<String, SeedEntry> = BTreeMap::new();
+    let mut metas: BTreeMap<String, Value> = BTreeMap::new();
+    let mut loose = Vec::new();
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if !file.is_file() {
+            continue;
+        }
+        let path = PathBuf::from(file.name());
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+
+        let file_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let parent = path
+            .parent()
+            .and_then(|p| p.file_name())
+            .map(|n| n.to_string_lossy().into_owned());
+        match (file_name, parent) {
+            (BUNDLE_FILE, Some(address)) => {
+                filed.insert(
+                    address.clone(),
+                    SeedEntry {
+                        address: Some(address),
+                        bundle: content,
+                        meta: None,
+                    },
+                );
+            }
+            (META_FILE, Some(address)) => {
+                if let Ok(meta) = serde_json::from_str(&content) {
+                    metas.insert(address, meta);
+                }
+            }
+            (name, _) if name.ends_with(".js") => loose.push(SeedEntry {
+                address: None,
+                bundle: content,
+                meta: None,
+            }),
+            _ => {}
+        }
+    }
+
+    let mut entries: Vec<SeedEntry> = filed
+        .into_iter()
+        .map(|(address, mut entry)| {
+            entry.meta = metas.remove(&address);
+            entry
+        })
+        .collect();
+    entries.append(&mut loose);
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use uuid::Uuid;
+
+    fn temp_directory() -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("ad4m-bundles-{}", Uuid::new_v4()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn bundles_are_checked_against_their_address() {
+        let cache = BundleCache::new(temp_directory());
+        let bundle = "export default function create() {}";
+        let address = ipfs_hash(bundle);
+
+        assert!(cache.put_bundle("QmSomethingElse", bundle).is_err());
+        assert!(cache
+            .put_meta("../escaped", &serde_json::json!({ "data": {} }))
+            .is_err());
+        cache.put_bundle(&address, bundle).unwrap();
+        assert_eq!(cache.get(&address).unwrap().unwrap().bundle, bundle);
+
+        // Tampering with the cached bundle makes it disappear
+        fs::write(cache.directory.join(&address).join(BUNDLE_FILE), "evil()").unwrap();
+        assert_eq!(cache.get(&address).unwrap(), None);
+        assert!(!cache.directory.join(&address).exists());
+    }
+
+    #[test]
+    fn seeds_from_a_directory() {
+        let seed = temp_directory();
+        let filed = "export default 'filed'";
+        let filed_address = ipfs_hash(filed);
+        fs::create_dir_all(seed.join(&filed_address)).unwrap();
+        fs::write(seed.join(&filed_address).join(BUNDLE_FILE), filed).unwrap();
+        fs::write(
+            seed.join(&filed_address).join(META_FILE),
+            serde_json::json!({ "data": { "address": filed_address } }).to_string(),
+        )
+        .unwrap();
+        let loose = "export default 'loose'";
+        fs::write(seed.join("loose.js"), loose).unwrap();
+        fs::create_dir_all(seed.join("QmWrongAddress")).unwrap();
+        fs::write(seed.join("QmWrongAddress").join(BUNDLE_FILE), "x").unwrap();
+
+        let cache = BundleCache::new(temp_directory());
+        let mut seeded = cache.seed(&seed).unwrap();
+        seeded.sort();
+        let mut expected = vec![filed_address.clone(), ipfs_hash(loose)];
+        expected.sort();
+        assert_eq!(seeded, expected);
+
+        let cached = cache.get(&filed_address).unwrap().unwrap();
+        assert_eq!(cached.meta.unwrap()["data"]["address"], filed_address);
+        assert_eq!(cache.get(&ipfs_hash(loose)).unwrap().unwrap().bundle, loose);
+        assert_eq!(cache.get("QmWrongAddress").unwrap(), None);
+    }
+
+    #[test]
+    fn seeds_from_a_zip_archive() {
+        let bundle = "export default 'zipped'";
+        let address = ipfs_hash(bundle);
+        let archive_path = temp_directory().join("languages.zip");
+        let mut archive = zip::ZipWriter::new(fs::File::create(&archive_path).unwrap());
+        let options = zip::write::FileOptions::default();
+        archive
+            .start_file(format!("languages/{}/{}", address, BUNDLE_FILE), options)
+            .unwrap();
+        archive.write_all(bundle.as_bytes()).unwrap();
+        archive.finish().unwrap();
+
+        let cache = BundleCache::new(temp_directory());
+        assert_eq!(cache.seed(&archive_path).unwrap(), vec![address.clone()]);
+        assert_eq!(cache.get(&address).unwrap().unwrap().bundle, bundle);
+    }
+}
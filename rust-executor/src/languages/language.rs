@@ -1,180 +1,175 @@
 use super::byte_array::ByteArray;
+use super::link_language::LinkLanguage;
 use crate::{
     graphql::graphql_types::{OnlineAgent, PerspectiveExpression},
-    js_core::JsCoreHandle,
+    js_core::{
+        rpc::{JsRpc, LinksAdapterCall, TelepresenceAdapterCall},
+        JsCoreHandle,
+    },
     types::{Perspective, PerspectiveDiff},
 };
 use base64::prelude::*;
 use deno_core::error::AnyError;
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+
+#[derive(Clone)]
+enum Backend {
+    Js(JsCoreHandle),
+    Native(Arc<dyn LinkLanguage>),
+}
 
 #[derive(Clone)]
 pub struct Language {
     address: String,
-    js_core: JsCoreHandle,
+    backend: Backend,
 }
 
-fn parse_revision(js_result: String) -> Result<Option<String>, AnyError> {
-    if let Ok(maybe_revision) = serde_json::from_str::<Option<ByteArray>>(&js_result) {
+fn parse_revision(js_result: serde_json::Value) -> Result<Option<String>, AnyError> {
+    if let Ok(maybe_revision) = serde_json::from_value::<Option<ByteArray>>(js_result.clone()) {
         Ok(maybe_revision.map(|revision| {
             let vec: Vec<u8> = revision.into();
             BASE64_STANDARD.encode(vec)
         }))
     } else {
-        Ok(serde_json::from_str::<Option<String>>(&js_result)?)
+        Ok(serde_json::from_value::<Option<String>>(js_result)?)
     }
 }
 impl Language {
     pub fn new(address: String, js_core: JsCoreHandle) -> Self {
-        Self { address, js_core }
+        Self {
+            address,
+            backend: Backend::Js(js_core),
+        }
+    }
+
+    /// A language implemented in Rust, calls don't go through the JS core
+    pub fn native(address: String, language: Arc<dyn LinkLanguage>) -> Self {
+        Self {
+            address,
+            backend: Backend::Native(language),
+        }
+    }
+
+    async fn links_adapter<T: DeserializeOwned>(
+        js_core: &mut JsCoreHandle,
+        address: &str,
+        call: LinksAdapterCall,
+    ) -> Result<T, AnyError> {
+        js_core
+            .rpc(JsRpc::LinksAdapter {
+                address: address.to_string(),
+                call,
+            })
+            .await
+    }
+
+    async fn telepresence_adapter<T: DeserializeOwned>(
+        js_core: &mut JsCoreHandle,
+        address: &str,
+        call: TelepresenceAdapterCall,
+    ) -> Result<T, AnyError> {
+        js_core
+            .rpc(JsRpc::TelepresenceAdapter {
+                address: address.to_string(),
+                call,
+            })
+            .await
     }
 
     pub async fn sync(&mut self) -> Result<(), AnyError> {
-        let script = format!(
-            r#"
-                JSON.stringify(
-                    await core.languageController.languageByRef({{address:"{}"}}) 
-                    ? 
-                    await (await core.languageController.languageByRef({{address:"{}"}})).linksAdapter.sync() 
-                    : 
-                    null
-                )
-            "#,
-            self.address, self.address,
-        );
-        let _result: String = self.js_core.execute(script).await?;
-        Ok(())
+        match &mut self.backend {
+            Backend::Js(js_core) => {
+                let _result: serde_json::Value =
+                    Self::links_adapter(js_core, &self.address, LinksAdapterCall::Sync).await?;
+                Ok(())
+            }
+            Backend::Native(language) => language.sync().await,
+        }
     }
 
     pub async fn commit(&mut self, diff: PerspectiveDiff) -> Result<Option<String>, AnyError> {
-        let script = format!(
-            r#"
-                JSON.stringify(
-                    await core.languageController.languageByRef({{address:"{}"}}) 
-                    ? 
-                    await (await core.languageController.languageByRef({{address:"{}"}})).linksAdapter.commit({}) 
-                    : 
-                    null
-                )
-            "#,
-            self.address,
-            self.address,
-            serde_json::to_string(&diff)?,
-        );
-        let result: String = self.js_core.execute(script).await?;
-        parse_revision(result)
+        match &mut self.backend {
+            Backend::Js(js_core) => {
+                let result =
+                    Self::links_adapter(js_core, &self.address, LinksAdapterCall::Commit { diff })
+                        .await?;
+                parse_revision(result)
+            }
+            Backend::Native(language) => language.commit(diff).await,
+        }
     }
 
     pub async fn current_revision(&mut self) -> Result<Option<String>, AnyError> {
-        let script = format!(
-            r#"
-                JSON.stringify(
-                    await core.languageController.languageByRef({{address:"{}"}}) 
-                    ? 
-                    await (await core.languageController.languageByRef({{address:"{}"}})).linksAdapter.currentRevision() 
-                    : 
-                    null
-                )
-            "#,
-            self.address, self.address,
-        );
-        let result: String = self.js_core.execute(script).await?;
-        parse_revision(result)
+        match &mut self.backend {
+            Backend::Js(js_core) => {
+                let result =
+                    Self::links_adapter(js_core, &self.address, LinksAdapterCall::CurrentRevision)
+                        .await?;
+                parse_revision(result)
+            }
+            Backend::Native(language) => language.current_revision().await,
+        }
     }
 
     pub async fn render(&mut self) -> Result<Option<Perspective>, AnyError> {
-        let script = format!(
-            r#"
-                JSON.stringify(
-                    await core.languageController.languageByRef({{address:"{}"}}) 
-                    ? 
-                    await (await core.languageController.languageByRef({{address:"{}"}})).linksAdapter.render() 
-                    : 
-                    null
-                )
-            "#,
-            self.address, self.address,
-        );
-        let result: String = self.js_core.execute(script).await?;
-        let maybe_value = serde_json::from_str(&result)?;
-        Ok(maybe_value)
+        match &mut self.backend {
+            Backend::Js(js_core) => {
+                Self::links_adapter(js_core, &self.address, LinksAdapterCall::Render).await
+            }
+            Backend::Native(language) => language.render().await,
+        }
     }
 
     pub async fn others(&mut self) -> Result<Vec<String>, AnyError> {
-        let script = format!(
-            r#"
-                JSON.stringify(
-                    await core.languageController.languageByRef({{address:"{}"}}) 
-                    ? 
-                    await (await core.languageController.languageByRef({{address:"{}"}})).linksAdapter.others() 
-                    : 
-                    null
-                )
-            "#,
-            self.address, self.address,
-        );
-        let result: String = self.js_core.execute(script).await?;
-        let others_vec = serde_json::from_str(&result)?;
-        Ok(others_vec)
+        match &mut self.backend {
+            Backend::Js(js_core) => {
+                Self::links_adapter(js_core, &self.address, LinksAdapterCall::Others).await
+            }
+            Backend::Native(language) => language.others().await,
+        }
     }
 
     pub async fn has_telepresence_adapter(&mut self) -> Result<bool, AnyError> {
-        let script = format!(
-            r#"
-                JSON.stringify(
-                    await core.languageController.languageByRef({{address:"{}"}}) 
-                    &&
-                    await (await core.languageController.languageByRef({{address:"{}"}})).telepresenceAdapter
-                    ? 
-                    true
-                    : 
-                    false
-                )
-            "#,
-            self.address, self.address,
-        );
-        let result: String = self.js_core.execute(script).await?;
-        let has_telepresence_adapter = serde_json::from_str(&result)?;
-        Ok(has_telepresence_adapter)
+        match &mut self.backend {
+            Backend::Js(js_core) => {
+                Self::telepresence_adapter(js_core, &self.address, TelepresenceAdapterCall::Exists)
+                    .await
+            }
+            Backend::Native(language) => Ok(language.has_telepresence_adapter()),
+        }
     }
 
     pub async fn set_online_status(
         &mut self,
         status: PerspectiveExpression,
     ) -> Result<(), AnyError> {
-        let script = format!(
-            r#"
-                JSON.stringify(
-                    await core.languageController.languageByRef({{address:"{}"}}) 
-                    ? 
-                    await (await core.languageController.languageByRef({{address:"{}"}})).telepresenceAdapter.setOnlineStatus({})
-                    : 
-                    null
+        match &mut self.backend {
+            Backend::Js(js_core) => {
+                let _result: serde_json::Value = Self::telepresence_adapter(
+                    js_core,
+                    &self.address,
+                    TelepresenceAdapterCall::SetOnlineStatus { status },
                 )
-            "#,
-            self.address,
-            self.address,
-            serde_json::to_string(&status)?,
-        );
-        let _result: String = self.js_core.execute(script).await?;
-        Ok(())
+                .await?;
+                Ok(())
+            }
+            Backend::Native(language) => language.set_online_status(status).await,
+        }
     }
 
     pub async fn get_online_agents(&mut self) -> Result<Vec<OnlineAgent>, AnyError> {
-        let script = format!(
-            r#"
-                JSON.stringify(
-                    await core.languageController.languageByRef({{address:"{}"}}) 
-                    ? 
-                    await (await core.languageController.languageByRef({{address:"{}"}})).telepresenceAdapter.getOnlineAgents()
-                    : 
-                    null
+        match &mut self.backend {
+            Backend::Js(js_core) => {
+                Self::telepresence_adapter(
+                    js_core,
+                    &self.address,
+                    TelepresenceAdapterCall::GetOnlineAgents,
                 )
-            "#,
-            self.address, self.address,
-        );
-        let result: String = self.js_core.execute(script).await?;
-        let online_agents = serde_json::from_str(&result)?;
-        Ok(online_agents)
+                .await
+            }
+            Backend::Native(language) => language.get_online_agents().await,
+        }
     }
 
     pub async fn send_signal(
@@ -182,41 +177,35 @@ impl Language {
         remote_agent_did: String,
         payload: PerspectiveExpression,
     ) -> Result<(), AnyError> {
-        let script = format!(
-            r#"
-                JSON.stringify(
-                    await core.languageController.languageByRef({{address:"{}"}}) 
-                    ? 
-                    await (await core.languageController.languageByRef({{address:"{}"}})).telepresenceAdapter.sendSignal("{}", {})
-                    : 
-                    null
+        match &mut self.backend {
+            Backend::Js(js_core) => {
+                let _result: serde_json::Value = Self::telepresence_adapter(
+                    js_core,
+                    &self.address,
+                    TelepresenceAdapterCall::SendSignal {
+                        remote_agent_did,
+                        payload,
+                    },
                 )
-            "#,
-            self.address,
-            self.address,
-            remote_agent_did,
-            serde_json::to_string(&payload)?,
-        );
-        let _result: String = self.js_core.execute(script).await?;
-        Ok(())
+                .await?;
+                Ok(())
+            }
+            Backend::Native(language) => language.send_signal(remote_agent_did, payload).await,
+        }
     }
 
     pub async fn send_broadcast(&mut self, payload: PerspectiveExpression) -> Result<(), AnyError> {
-        let script = format!(
-            r#"
-                JSON.stringify(
-                    await core.languageController.languageByRef({{address:"{}"}}) 
-                    ? 
-                    await (await core.languageController.languageByRef({{address:"{}"}})).telepresenceAdapter.sendBroadcast({})
-                    : 
-                    null
+        match &mut self.backend {
+            Backend::Js(js_core) => {
+                let _result: serde_json::Value = Self::telepresence_adapter(
+                    js_core,
+                    &self.address,
+                    TelepresenceAdapterCall::SendBroadcast { payload },
                 )
-            "#,
-            self.address,
-            self.address,
-            serde_json::to_string(&payload)?,
-        );
-        let _result: String = self.js_core.execute(script).await?;
-        Ok(())
+                .await?;
+                Ok(())
+            }
+            Backend::Native(language) => language.send_broadcast(payload).await,
+        }
     }
 }
@@ -0,0 +1,96 @@
+//! Link languages implemented in Rust.
+//!
+//! A [`LinkLanguage`] registered with [`LanguageController::register_link_language`] takes the
+//! place of a JS link language with the same address: perspectives call it directly instead of
+//! going through the JS core. What it receives from the network it reports through
+//! [`LinkLanguageEvents`], the same way JS languages do through the `language_service` ops.
+//!
+//! [`LanguageController::register_link_language`]: super::LanguageController::register_link_language
+
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use futures::future::BoxFuture;
+
+use crate::graphql::graphql_types::{OnlineAgent, PerspectiveExpression, PerspectiveState};
+use crate::types::{Perspective, PerspectiveDiff};
+
+pub type LinkLanguageResult<'a, T> = BoxFuture<'a, Result<T, AnyError>>;
+
+/// The links and telepresence adapters of a language, revisions are opaque strings
+pub trait LinkLanguage: Send + Sync {
+    fn sync(&self) -> LinkLanguageResult<'_, ()>;
+
+    /// Shares `diff` with the other agents, returns the new revision
+    fn commit(&self, diff: PerspectiveDiff) -> LinkLanguageResult<'_, Option<String>>;
+
+    fn current_revision(&self) -> LinkLanguageResult<'_, Option<String>>;
+
+    /// All links currently in the neighbourhood
+    fn render(&self) -> LinkLanguageResult<'_, Option<Perspective>>;
+
+    /// DIDs of the other agents in the neighbourhood
+    fn others(&self) -> LinkLanguageResult<'_, Vec<String>>;
+
+    fn has_telepresence_adapter(&self) -> bool {
+        false
+    }
+
+    fn set_online_status(&self, _status: PerspectiveExpression) -> LinkLanguageResult<'_, ()> {
+        no_telepresence_adapter()
+    }
+
+    fn get_online_agents(&self) -> LinkLanguageResult<'_, Vec<OnlineAgent>> {
+        no_telepresence_adapter()
+    }
+
+    fn send_signal(
+        &self,
+        _remote_agent_did: String,
+        _payload: PerspectiveExpression,
+    ) -> LinkLanguageResult<'_, ()> {
+        no_telepresence_adapter()
+    }
+
+    fn send_broadcast(&self, _payload: PerspectiveExpression) -> LinkLanguageResult<'_, ()> {
+        no_telepresence_adapter()
+    }
+}
+
+fn no_telepresence_adapter<'a, T: Send + 'a>() -> LinkLanguageResult<'a, T> {
+    Box::pin(async { Err(anyhow!("Language has no telepresence adapter")) })
+}
+
+/// Where a link language reports what it received from other agents
+pub trait LinkLanguageEvents: Send + Sync {
+    fn diff_received(&self, diff: PerspectiveDiff);
+    fn sync_state_changed(&self, state: PerspectiveState);
+    fn telepresence_signal_received(&self, signal: PerspectiveExpression);
+}
+
+/// Hands events to the perspective whose neighbourhood uses the language at `language_address`
+pub struct PerspectiveEvents {
+    pub language_address: String,
+}
+
+impl LinkLanguageEvents for PerspectiveEvents {
+    fn diff_received(&self, diff: PerspectiveDiff) {
+        crate::perspectives::handle_perspective_diff_from_link_language(
+            diff,
+            self.language_address.clone(),
+        );
+    }
+
+    fn sync_state_changed(&self, state: PerspectiveState) {
+        crate::perspectives::handle_sync_state_changed_from_link_language(
+            state,
+            self.language_address.clone(),
+        );
+    }
+
+    fn telepresence_signal_received(&self, signal: PerspectiveExpression) {
+        crate::perspectives::handle_telepresence_signal_from_link_language(
+            signal,
+            self.language_address.clone(),
+        );
+    }
+}
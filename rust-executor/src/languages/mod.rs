@@ -1,19 +1,44 @@
+pub mod bundle_cache;
 mod byte_array;
 pub mod language;
+pub mod link_language;
+pub mod permissions;
+pub mod relay;
+pub mod versions;
 
+use deno_core::anyhow::anyhow;
 use deno_core::error::AnyError;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
+use crate::agent::signatures::learn_published_key_successions;
+use crate::entanglement_service::learn_published_proofs;
 use crate::types::Address;
 use crate::{
-    graphql::graphql_types::{DecoratedNeighbourhoodExpression, Neighbourhood},
-    js_core::JsCoreHandle,
+    graphql::graphql_types::{
+        Agent, DecoratedNeighbourhoodExpression, ExpressionRendered, JsResultType, LanguageRef,
+        Neighbourhood, Perspective, PerspectiveExpression,
+    },
+    js_core::{rpc::JsRpc, JsCoreHandle},
 };
 use language::Language;
+use link_language::LinkLanguage;
+use serde::Deserialize;
 
 lazy_static! {
     static ref LANGUAGE_CONTROLLER_INSTANCE: Arc<Mutex<Option<LanguageController>>> =
         Arc::new(Mutex::new(None));
+    static ref LINK_LANGUAGES: Mutex<HashMap<Address, Arc<dyn LinkLanguage>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// How the direct message language of a friend delivered a message
+#[derive(Deserialize, Debug, Clone)]
+pub struct DirectMessageDelivery {
+    /// "sentP2P" or "storedInInbox"
+    pub status: String,
+    /// The signed message as the recipient gets it
+    pub expression: PerspectiveExpression,
 }
 
 #[derive(Clone)]
@@ -40,82 +65,129 @@ impl LanguageController {
         Self { js_core }
     }
 
-    pub async fn install_language(language: Address) -> Result<(), AnyError> {
+    async fn wait_for_languages() -> Result<(), AnyError> {
         Self::global_instance()
             .js_core
-            .execute("await core.waitForLanguages()".into())
-            .await?;
+            .rpc(JsRpc::WaitForLanguages)
+            .await
+    }
 
-        let script = format!(
-            r#"JSON.stringify(
-                await core.languageController.installLanguage("{}")
-            )"#,
-            language,
-        );
-        let _result = Self::global_instance().js_core.execute(script).await?;
+    pub async fn install_language(language: Address) -> Result<(), AnyError> {
+        Self::wait_for_languages().await?;
+        let _result: serde_json::Value = Self::global_instance()
+            .js_core
+            .rpc(JsRpc::InstallLanguage { address: language })
+            .await?;
         Ok(())
     }
 
     pub async fn create_neighbourhood(neighbourhood: Neighbourhood) -> Result<Address, AnyError> {
+        Self::wait_for_languages().await?;
         Self::global_instance()
             .js_core
-            .execute("await core.waitForLanguages()".into())
-            .await?;
-
-        let neighbourhood_json = serde_json::to_string(&neighbourhood)?;
-        let script = format!(
-            r#"
-            await core
-                    .languageController
-                    .getNeighbourhoodLanguage()
-                    .expressionAdapter
-                    .putAdapter
-                    .createPublic({})
-            "#,
-            neighbourhood_json,
-        );
-        let result: String = Self::global_instance().js_core.execute(script).await?;
-        Ok(result)
+            .rpc(JsRpc::CreateNeighbourhood { neighbourhood })
+            .await
     }
 
     pub async fn get_neighbourhood(
         address: Address,
     ) -> Result<Option<DecoratedNeighbourhoodExpression>, AnyError> {
+        Self::wait_for_languages().await?;
         Self::global_instance()
             .js_core
-            .execute("await core.waitForLanguages()".into())
+            .rpc(JsRpc::GetNeighbourhood { address })
+            .await
+    }
+
+    /// The signed meta of the language at `address`, as published in the language language
+    pub async fn language_expression(
+        address: &str,
+    ) -> Result<Option<ExpressionRendered>, AnyError> {
+        Self::wait_for_languages().await?;
+        let result: JsResultType<Option<ExpressionRendered>> = Self::global_instance()
+            .js_core
+            .rpc(JsRpc::query(
+                "expression",
+                serde_json::json!({ "url": format!("lang://{}", address) }),
+            ))
             .await?;
+        match result {
+            JsResultType::Ok(expression) => Ok(expression),
+            JsResultType::Error(error) => Err(anyhow!(error)),
+        }
+    }
 
-        let script = format!(
-            r#"
-            JSON.stringify(
-                await core
-                    .languageController
-                    .getPerspective("{}")
-            )
-            "#,
-            address,
-        );
-        let result: String = Self::global_instance().js_core.execute(script).await?;
-        let neighbourhood: Option<DecoratedNeighbourhoodExpression> =
-            serde_json::from_str(&result)?;
-        Ok(neighbourhood)
+    pub async fn installed_languages() -> Result<Vec<LanguageRef>, AnyError> {
+        Self::wait_for_languages().await?;
+        let result: JsResultType<Vec<LanguageRef>> = Self::global_instance()
+            .js_core
+            .rpc(JsRpc::query(
+                "languages",
+                serde_json::json!({ "filter": null }),
+            ))
+            .await?;
+        match result {
+            JsResultType::Ok(languages) => Ok(languages),
+            JsResultType::Error(error) => Err(anyhow!(error)),
+        }
     }
 
-    pub async fn language_by_address(address: Address) -> Result<Option<Language>, AnyError> {
-        Self::global_instance()
+    /// Publishes the language at `source` templated with `template_data` (JSON),
+    /// which always gives the same address for the same source and data
+    pub async fn apply_template_and_publish(
+        source: Address,
+        template_data: String,
+    ) -> Result<Address, AnyError> {
+        Self::wait_for_languages().await?;
+        let result: JsResultType<LanguageRef> = Self::global_instance()
             .js_core
-            .execute("await core.waitForLanguages()".into())
+            .rpc(JsRpc::mutation(
+                "languageApplyTemplateAndPublish",
+                serde_json::json!({ "sourceLanguageHash": source, "templateData": template_data }),
+            ))
             .await?;
+        match result {
+            JsResultType::Ok(language) => Ok(language.address),
+            JsResultType::Error(error) => Err(anyhow!(error)),
+        }
+    }
+
+    /// Makes perspectives use `language` for the link language at `address`
+    /// instead of a JS language with that address
+    pub fn register_link_language(address: Address, language: Arc<dyn LinkLanguage>) {
+        LINK_LANGUAGES
+            .lock()
+            .expect("link languages lock")
+            .insert(address, language);
+    }
+
+    pub fn unregister_link_language(address: &str) -> bool {
+        LINK_LANGUAGES
+            .lock()
+            .expect("link languages lock")
+            .remove(address)
+            .is_some()
+    }
+
+    fn registered_link_language(address: &str) -> Option<Language> {
+        LINK_LANGUAGES
+            .lock()
+            .expect("link languages lock")
+            .get(address)
+            .map(|language| Language::native(address.to_string(), language.clone()))
+    }
 
-        let script = format!(
-            r#"
-            await core.languageController.languageByRef({{ address: "{}" }}) ? true : false
-            "#,
-            address,
-        );
-        let result: String = Self::global_instance().js_core.execute(script).await?;
-        let language_installed = serde_json::from_str::<bool>(&result)?;
+    pub async fn language_by_address(address: Address) -> Result<Option<Language>, AnyError> {
+        if let Some(language) = Self::registered_link_language(&address) {
+            return Ok(Some(language));
+        }
+        Self::wait_for_languages().await?;
+        let language_installed: bool = Self::global_instance()
+            .js_core
+            .rpc(JsRpc::LanguageInstalled {
+                address: address.clone(),
+            })
+            .await?;
         if language_installed {
             let language = Language::new(address, Self::global_instance().js_core.clone());
             Ok(Some(language))
@@ -123,4 +195,50 @@ impl LanguageController {
             Ok(None)
         }
     }
+
+    /// The agent expression published by `did` in the agent language
+    /// Fetches the agent expression of `did`, learning about the devices and previous keys
+    /// it published
+    pub async fn agent_by_did(did: &str) -> Result<Option<Agent>, AnyError> {
+        Self::wait_for_languages().await?;
+        let agent: Option<Agent> = Self::global_instance()
+            .js_core
+            .rpc(JsRpc::AgentByDid {
+                did: did.to_string(),
+            })
+            .await?;
+        if let Some(agent) = agent.as_ref() {
+            learn_published_proofs(agent);
+            learn_published_key_successions(agent);
+        }
+        Ok(agent)
+    }
+
+    /// Sends a message through the recipient's direct message language.
+    /// Fails if the message could neither be sent p2p nor stored in their inbox.
+    pub async fn send_direct_message(
+        did: &str,
+        message: &Perspective,
+    ) -> Result<DirectMessageDelivery, AnyError> {
+        Self::wait_for_languages().await?;
+        Self::global_instance()
+            .js_core
+            .rpc(JsRpc::SendDirectMessage {
+                did: did.to_string(),
+                message: message.clone(),
+            })
+            .await
+    }
+
+    /// Fetches the messages that were stored in our inbox while we were offline,
+    /// together with the ones we already got, optionally only those from `author`
+    pub async fn direct_message_inbox(
+        author: Option<String>,
+    ) -> Result<Vec<PerspectiveExpression>, AnyError> {
+        Self::wait_for_languages().await?;
+        Self::global_instance()
+            .js_core
+            .rpc(JsRpc::DirectMessageInbox { author })
+            .await
+    }
 }
@@ -0,0 +1,220 @@
+//! Permissions of language bundles.
+//!
+//! Languages declare what they need beyond their storage directory in
+//! `LanguageMeta.permissions`. A language only gets installed once the user approved at least
+//! that, and while it runs the JS core keeps it to what is approved at the time of each access
+//! (see executor/src/core/LanguageSandbox.ts), so revoking takes effect right away.
+
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use serde::Serialize;
+
+use crate::db::Ad4mDb;
+use crate::graphql::graphql_types::{ExceptionInfo, ExceptionType, LanguagePermissions};
+use crate::pubsub::{get_global_pubsub, EXCEPTION_OCCURRED_TOPIC};
+
+/// Addon of a `LanguagePermissionsRequested` exception
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PermissionsRequest {
+    pub address: String,
+    pub name: String,
+    /// What still needs to be approved
+    pub permissions: LanguagePermissions,
+}
+
+/// Addon of a `LanguagePermissionDenied` exception
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PermissionViolation {
+    pub address: String,
+    /// "net", "read", "write", "env" or "run"
+    pub permission: String,
+    pub target: String,
+}
+
+impl LanguagePermissions {
+    pub fn is_empty(&self) -> bool {
+        self.net.is_empty()
+            && self.read.is_empty()
+            && self.write.is_empty()
+            && self.env.is_empty()
+            && self.run.is_empty()
+    }
+}
+
+pub fn approved(address: &str) -> Result<Option<LanguagePermissions>, AnyError> {
+    Ad4mDb::with_global_instance(|db| db.get_language_permissions(address))
+}
+
+pub fn approve(address: &str, permissions: &LanguagePermissions) -> Result<(), AnyError> {
+    Ad4mDb::with_global_instance(|db| db.set_language_permissions(address, permissions))
+}
+
+/// Takes back everything the language at `address` was approved to do. The now empty approval
+/// stays recorded, so [`migrate`] doesn't grant its declared permissions again.
+pub fn revoke(address: &str) -> Result<bool, AnyError> {
+    Ad4mDb::with_global_instance(|db| {
+        if db.get_language_permissions(address)?.is_none() {
+            return Ok(false);
+        }
+        db.set_language_permissions(address, &LanguagePermissions::default())?;
+        Ok(true)
+    })
+}
+
+/// Forgets about the language at `address` once it is removed, so installing it again asks anew
+pub fn forget(address: &str) -> Result<bool, AnyError> {
+    Ad4mDb::with_global_instance(|db| db.remove_language_permissions(address))
+}
+
+/// Languages installed before permissions were checked have no approval recorded and would be
+/// denied everything they declared. They were installed by the user, so what they `declared`
+/// gets approved for them, once. Returns whether anything was recorded.
+pub fn migrate(address: &str, declared: &LanguagePermissions) -> Result<bool, AnyError> {
+    Ad4mDb::with_global_instance(|db| {
+        if db.get_language_permissions(address)?.is_some() {
+            return Ok(false);
+        }
+        db.set_language_permissions(address, declared)?;
+        Ok(true)
+    })
+}
+
+/// What `requested` asks for beyond `approved`
+pub fn missing(
+    requested: &LanguagePermissions,
+    approved: &LanguagePermissions,
+) -> LanguagePermissions {
+    let not_in = |requested: &Vec<String>, approved: &Vec<String>| -> Vec<String> {
+        requested
+            .iter()
+            .filter(|entry| !approved.contains(entry))
+            .cloned()
+            .collect()
+    };
+    LanguagePermissions {
+        net: not_in(&requested.net, &approved.net),
+        read: not_in(&requested.read, &approved.read),
+        write: not_in(&requested.write, &approved.write),
+        env: not_in(&requested.env, &approved.env),
+        run: not_in(&requested.run, &approved.run),
+    }
+}
+
+/// Lets the installation of the language at `address` go ahead if everything it `requested`
+/// was approved. Otherwise asks the user for approval through an exception and fails.
+pub async fn check_install(
+    address: &str,
+    name: &str,
+    requested: &LanguagePermissions,
+) -> Result<(), AnyError> {
+    let missing = missing(requested, &approved(address)?.unwrap_or_default());
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let exception_info = ExceptionInfo {
+        title: "Language needs permissions".to_string(),
+        message: format!(
+            "Language {} ({}) needs permissions that were not approved yet, approve them to install it.",
+            name, address
+        ),
+        r#type: ExceptionType::LanguagePermissionsRequested,
+        addon: Some(serde_json::to_string(&PermissionsRequest {
+            address: address.to_string(),
+            name: name.to_string(),
+            permissions: missing.clone(),
+        })?),
+    };
+    get_global_pubsub()
+        .await
+        .publish(
+            &EXCEPTION_OCCURRED_TOPIC,
+            &serde_json::to_string(&exception_info)?,
+        )
+        .await;
+
+    Err(anyhow!(
+        "Permissions of language {} are not approved: {}",
+        address,
+        serde_json::to_string(&missing)?
+    ))
+}
+
+/// Tells the user the language at `address` tried to do something it is not allowed to
+pub async fn report_violation(violation: PermissionViolation) -> Result<(), AnyError> {
+    let exception_info = ExceptionInfo {
+        title: "Language permission denied".to_string(),
+        message: format!(
+            "Language {} tried to access {} \"{}\" without permission.",
+            violation.address, violation.permission, violation.target
+        ),
+        r#type: ExceptionType::LanguagePermissionDenied,
+        addon: Some(serde_json::to_string(&violation)?),
+    };
+    get_global_pubsub()
+        .await
+        .publish(
+            &EXCEPTION_OCCURRED_TOPIC,
+            &serde_json::to_string(&exception_info)?,
+        )
+        .await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn missing_lists_only_what_was_not_approved() {
+        let requested = LanguagePermissions {
+            net: strings(&["example.com", "api.example.com:443"]),
+            env: strings(&["HOME"]),
+            run: strings(&["git"]),
+            ..Default::default()
+        };
+        let approved = LanguagePermissions {
+            net: strings(&["example.com"]),
+            env: strings(&["HOME", "PATH"]),
+            ..Default::default()
+        };
+
+        let missing = missing(&requested, &approved);
+        assert_eq!(
+            missing,
+            LanguagePermissions {
+                net: strings(&["api.example.com:443"]),
+                run: strings(&["git"]),
+                ..Default::default()
+            }
+        );
+        assert!(!missing.is_empty());
+        assert!(super::missing(&approved, &approved).is_empty());
+    }
+
+    #[test]
+    fn declared_permissions_are_migrated_once() {
+        Ad4mDb::init_global_instance(":memory:").unwrap();
+        let declared = LanguagePermissions {
+            net: strings(&["example.com"]),
+            ..Default::default()
+        };
+
+        assert!(migrate("QmOldLang", &declared).unwrap());
+        assert_eq!(approved("QmOldLang").unwrap(), Some(declared.clone()));
+
+        // Revoking keeps an empty approval, so loading the language again doesn't restore it
+        assert!(revoke("QmOldLang").unwrap());
+        assert!(!migrate("QmOldLang", &declared).unwrap());
+        assert!(approved("QmOldLang").unwrap().unwrap().is_empty());
+
+        assert!(forget("QmOldLang").unwrap());
+        assert!(!revoke("QmOldLang").unwrap());
+    }
+}
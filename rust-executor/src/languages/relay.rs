@@ -0,0 +1,625 @@
+//! Reference [`LinkLanguage`] that syncs through a relay over TCP.
+//!
+//! The relay keeps an ordered log of the diffs committed to each room (one room per
+//! neighbourhood) and forwards new diffs and telepresence signals to the other agents in the
+//! room. Revisions are positions in that log. Messages are newline delimited JSON.
+//!
+//! There is no persistence or authentication, it is meant for neighbourhood tests and local
+//! deployments where all agents can reach the same relay.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use super::link_language::{LinkLanguage, LinkLanguageEvents, LinkLanguageResult};
+use crate::graphql::graphql_types::{OnlineAgent, PerspectiveExpression, PerspectiveState};
+use crate::types::{LinkExpression, Perspective, PerspectiveDiff};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "call", rename_all = "camelCase")]
+enum RelayCall {
+    Join {
+        room: String,
+        did: String,
+    },
+    Commit {
+        diff: PerspectiveDiff,
+    },
+    /// Resends all diffs after `since`, replies with the latest revision
+    Sync {
+        since: u64,
+    },
+    Render,
+    Others,
+    SetOnlineStatus {
+        status: PerspectiveExpression,
+    },
+    GetOnlineAgents,
+    SendSignal {
+        to: String,
+        payload: PerspectiveExpression,
+    },
+    SendBroadcast {
+        payload: PerspectiveExpression,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct RelayRequest {
+    id: u64,
+    #[serde(flatten)]
+    call: RelayCall,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum RelayMessage {
+    Reply {
+        id: u64,
+        result: Result<Value, String>,
+    },
+    Diff {
+        revision: u64,
+        diff: PerspectiveDiff,
+    },
+    Signal {
+        payload: PerspectiveExpression,
+    },
+}
+
+struct Member {
+    did: String,
+    status: Option<PerspectiveExpression>,
+    sender: mpsc::UnboundedSender<RelayMessage>,
+}
+
+#[derive(Default)]
+struct Room {
+    log: Vec<PerspectiveDiff>,
+    members: HashMap<u64, Member>,
+}
+
+impl Room {
+    fn render(&self) -> Perspective {
+        let mut links: Vec<LinkExpression> = Vec::new();
+        for diff in &self.log {
+            links.retain(|link| !diff.removals.contains(link));
+            links.extend(diff.additions.iter().cloned());
+        }
+        Perspective { links }
+    }
+
+    fn send_to_others(&self, connection: u64, message: RelayMessage) {
+        for (_, member) in self.members.iter().filter(|(id, _)| **id != connection) {
+            let _ = member.sender.send(message.clone());
+        }
+    }
+}
+
+type Rooms = Arc<Mutex<HashMap<String, Room>>>;
+
+pub struct RelayServer {
+    local_addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl RelayServer {
+    pub async fn bind(addr: impl ToSocketAddrs) -> Result<Self, AnyError> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let rooms = Rooms::default();
+        let task = tokio::spawn(async move {
+            let mut next_connection = 0;
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        next_connection += 1;
+                        tokio::spawn(serve_connection(stream, next_connection, rooms.clone()));
+                    }
+                    Err(e) => log::error!("Relay failed to accept connection: {}", e),
+                }
+            }
+        });
+        Ok(Self { local_addr, task })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for RelayServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve_connection(stream: TcpStream, connection: u64, rooms: Rooms) {
+    let (reader, mut writer) = stream.into_split();
+    let (sender, mut receiver) = mpsc::unbounded_channel::<RelayMessage>();
+    tokio::spawn(async move {
+        while let Some(message) = receiver.recv().await {
+            let mut line = match serde_json::to_vec(&message) {
+                Ok(line) => line,
+                Err(e) => {
+                    log::error!("Relay failed to encode message: {}", e);
+                    continue;
+                }
+            };
+            line.push(b'\n');
+            if writer.write_all(&line).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut room_name: Option<String> = None;
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let request: RelayRequest = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                log::warn!("Relay got invalid request: {}", e);
+                break;
+            }
+        };
+        let result = handle_call(request.call, connection, &mut room_name, &rooms, &sender)
+            .map_err(|e| e.to_string());
+        let _ = sender.send(RelayMessage::Reply {
+            id: request.id,
+            result,
+        });
+    }
+
+    if let Some(room_name) = room_name {
+        if let Some(room) = rooms.lock().expect("relay rooms lock").get_mut(&room_name) {
+            room.members.remove(&connection);
+        }
+    }
+}
+
+fn handle_call(
+    call: RelayCall,
+    connection: u64,
+    room_name: &mut Option<String>,
+    rooms: &Rooms,
+    sender: &mpsc::UnboundedSender<RelayMessage>,
+) -> Result<Value, AnyError> {
+    let mut rooms = rooms.lock().expect("relay rooms lock");
+
+    if let RelayCall::Join { room, did } = call {
+        if room_name.is_some() {
+            return Err(anyhow!("Already joined a room"));
+        }
+        rooms.entry(room.clone()).or_default().members.insert(
+            connection,
+            Member {
+                did,
+                status: None,
+                sender: sender.clone(),
+            },
+        );
+        *room_name = Some(room);
+        return Ok(Value::Null);
+    }
+
+    let room = match room_name.as_ref().and_then(|name| rooms.get_mut(name)) {
+        Some(room) => room,
+        None => return Err(anyhow!("Join a room first")),
+    };
+    let own_did = room.members[&connection].did.clone();
+
+    Ok(match call {
+        RelayCall::Join { .. } => unreachable!("handled above"),
+        RelayCall::Commit { diff } => {
+            room.log.push(diff.clone());
+            let revision = room.log.len() as u64;
+            room.send_to_others(connection, RelayMessage::Diff { revision, diff });
+            serde_json::to_value(revision)?
+        }
+        RelayCall::Sync { since } => {
+            for (index, diff) in room.log.iter().enumerate().skip(since as usize) {
+                let _ = sender.send(RelayMessage::Diff {
+                    revision: index as u64 + 1,
+                    diff: diff.clone(),
+                });
+            }
+            serde_json::to_value(room.log.len() as u64)?
+        }
+        RelayCall::Render => serde_json::to_value(room.render())?,
+        RelayCall::Others => {
+            let mut others: Vec<String> = room
+                .members
+                .values()
+                .map(|member| member.did.clone())
+                .filter(|did| *did != own_did)
+                .collect();
+            others.sort();
+            others.dedup();
+            serde_json::to_value(others)?
+        }
+        RelayCall::SetOnlineStatus { status } => {
+            if let Some(member) = room.members.get_mut(&connection) {
+                member.status = Some(status);
+            }
+            Value::Null
+        }
+        RelayCall::GetOnlineAgents => {
+            let online: Vec<OnlineAgent> = room
+                .members
+                .iter()
+                .filter(|(id, member)| **id != connection && member.did != own_did)
+                .filter_map(|(_, member)| {
+                    member.status.clone().map(|status| OnlineAgent {
+                        did: member.did.clone(),
+                        status,
+                    })
+                })
+                .collect();
+            serde_json::to_value(online)?
+        }
+        RelayCall::SendSignal { to, payload } => {
+            for member in room.members.values().filter(|member| member.did == to) {
+                let _ = member.sender.send(RelayMessage::Signal {
+                    payload: payload.clone(),
+                });
+            }
+            Value::Null
+        }
+        RelayCall::SendBroadcast { payload } => {
+            room.send_to_others(connection, RelayMessage::Signal { payload });
+            Value::Null
+        }
+    })
+}
+
+type PendingReplies = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value, String>>>>>;
+
+/// An agent's connection to one room of a [`RelayServer`]
+pub struct RelayLinkLanguage {
+    outgoing: mpsc::UnboundedSender<String>,
+    pending: PendingReplies,
+    next_id: AtomicU64,
+    /// Latest revision handed to the events
+    revision: Arc<AtomicU64>,
+    events: Arc<dyn LinkLanguageEvents>,
+}
+
+impl RelayLinkLanguage {
+    pub async fn connect(
+        relay: impl ToSocketAddrs,
+        room: &str,
+        did: &str,
+        events: Arc<dyn LinkLanguageEvents>,
+    ) -> Result<Self, AnyError> {
+        let (reader, mut writer) = TcpStream::connect(relay).await?.into_split();
+        let (outgoing, mut outgoing_receiver) = mpsc::unbounded_channel::<String>();
+        tokio::spawn(async move {
+            while let Some(mut line) = outgoing_receiver.recv().await {
+                line.push('\n');
+                if writer.write_all(line.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let pending = PendingReplies::default();
+        let revision = Arc::new(AtomicU64::new(0));
+        tokio::spawn(receive_messages(
+            BufReader::new(reader),
+            pending.clone(),
+            revision.clone(),
+            events.clone(),
+        ));
+
+        let language = Self {
+            outgoing,
+            pending,
+            next_id: AtomicU64::new(0),
+            revision,
+            events,
+        };
+        let _: Value = language
+            .request(RelayCall::Join {
+                room: room.to_string(),
+                did: did.to_string(),
+            })
+            .await?;
+        Ok(language)
+    }
+
+    async fn request<T: DeserializeOwned>(&self, call: RelayCall) -> Result<T, AnyError> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        self.pending
+            .lock()
+            .expect("relay pending lock")
+            .insert(id, reply_sender);
+        let line = serde_json::to_string(&RelayRequest { id, call })?;
+        if self.outgoing.send(line).is_err() {
+            self.pending.lock().expect("relay pending lock").remove(&id);
+            return Err(anyhow!("Relay connection closed"));
+        }
+        let result = reply_receiver
+            .await
+            .map_err(|_| anyhow!("Relay connection closed"))?;
+        Ok(serde_json::from_value(result.map_err(|e| anyhow!(e))?)?)
+    }
+}
+
+/// Diffs come in log order, from pushes as well as from syncs, so anything at or below the
+/// latest revision was already handed on
+async fn receive_messages(
+    reader: BufReader<tokio::net::tcp::OwnedReadHalf>,
+    pending: PendingReplies,
+    revision: Arc<AtomicU64>,
+    events: Arc<dyn LinkLanguageEvents>,
+) {
+    let mut lines = reader.lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        match serde_json::from_str::<RelayMessage>(&line) {
+            Ok(RelayMessage::Reply { id, result }) => {
+                if let Some(reply) = pending.lock().expect("relay pending lock").remove(&id) {
+                    let _ = reply.send(result);
+                }
+            }
+            Ok(RelayMessage::Diff {
+                revision: diff_revision,
+                diff,
+            }) => {
+                if revision.fetch_max(diff_revision, Ordering::SeqCst) < diff_revision {
+                    events.diff_received(diff);
+                }
+            }
+            Ok(RelayMessage::Signal { payload }) => events.telepresence_signal_received(payload),
+            Err(e) => log::warn!("Got invalid message from relay: {}", e),
+        }
+    }
+    // Fails all requests still waiting for a reply
+    pending.lock().expect("relay pending lock").clear();
+}
+
+fn revision_string(revision: u64) -> Option<String> {
+    if revision == 0 {
+        None
+    } else {
+        Some(revision.to_string())
+    }
+}
+
+impl LinkLanguage for RelayLinkLanguage {
+    fn sync(&self) -> LinkLanguageResult<'_, ()> {
+        Box::pin(async move {
+            let since = self.revision.load(Ordering::SeqCst);
+            let _latest: u64 = self.request(RelayCall::Sync { since }).await?;
+            self.events.sync_state_changed(PerspectiveState::Synced);
+            Ok(())
+        })
+    }
+
+    fn commit(&self, diff: PerspectiveDiff) -> LinkLanguageResult<'_, Option<String>> {
+        Box::pin(async move {
+            let revision: u64 = self.request(RelayCall::Commit { diff }).await?;
+            self.revision.fetch_max(revision, Ordering::SeqCst);
+            Ok(revision_string(revision))
+        })
+    }
+
+    fn current_revision(&self) -> LinkLanguageResult<'_, Option<String>> {
+        Box::pin(async move { Ok(revision_string(self.revision.load(Ordering::SeqCst))) })
+    }
+
+    fn render(&self) -> LinkLanguageResult<'_, Option<Perspective>> {
+        Box::pin(async move { Ok(Some(self.request(RelayCall::Render).await?)) })
+    }
+
+    fn others(&self) -> LinkLanguageResult<'_, Vec<String>> {
+        Box::pin(self.request(RelayCall::Others))
+    }
+
+    fn has_telepresence_adapter(&self) -> bool {
+        true
+    }
+
+    fn set_online_status(&self, status: PerspectiveExpression) -> LinkLanguageResult<'_, ()> {
+        Box::pin(async move {
+            let _: Value = self.request(RelayCall::SetOnlineStatus { status }).await?;
+            Ok(())
+        })
+    }
+
+    fn get_online_agents(&self) -> LinkLanguageResult<'_, Vec<OnlineAgent>> {
+        Box::pin(self.request(RelayCall::GetOnlineAgents))
+    }
+
+    fn send_signal(
+        &self,
+        remote_agent_did: String,
+        payload: PerspectiveExpression,
+    ) -> LinkLanguageResult<'_, ()> {
+        Box::pin(async move {
+            let _: Value = self
+                .request(RelayCall::SendSignal {
+                    to: remote_agent_did,
+                    payload,
+                })
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn send_broadcast(&self, payload: PerspectiveExpression) -> LinkLanguageResult<'_, ()> {
+        Box::pin(async move {
+            let _: Value = self.request(RelayCall::SendBroadcast { payload }).await?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ExpressionProof, Link};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct RecordedEvents {
+        diffs: Mutex<Vec<PerspectiveDiff>>,
+        signals: Mutex<Vec<PerspectiveExpression>>,
+        synced: Mutex<bool>,
+    }
+
+    impl LinkLanguageEvents for RecordedEvents {
+        fn diff_received(&self, diff: PerspectiveDiff) {
+            self.diffs.lock().unwrap().push(diff);
+        }
+
+        fn sync_state_changed(&self, state: PerspectiveState) {
+            *self.synced.lock().unwrap() = state == PerspectiveState::Synced;
+        }
+
+        fn telepresence_signal_received(&self, signal: PerspectiveExpression) {
+            self.signals.lock().unwrap().push(signal);
+        }
+    }
+
+    fn link(target: &str) -> LinkExpression {
+        LinkExpression {
+            author: "did:test:alice".to_string(),
+            timestamp: "2024-01-01T00:00:00Z".to_string(),
+            data: Link {
+                source: "ad4m://self".to_string(),
+                predicate: None,
+                target: target.to_string(),
+            },
+            proof: ExpressionProof {
+                key: "key".to_string(),
+                signature: "signature".to_string(),
+            },
+            status: None,
+        }
+    }
+
+    async fn eventually(condition: impl Fn() -> bool) {
+        for _ in 0..100 {
+            if condition() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("condition not met in time");
+    }
+
+    async fn join(
+        relay: &RelayServer,
+        room: &str,
+        did: &str,
+    ) -> (RelayLinkLanguage, Arc<RecordedEvents>) {
+        let events = Arc::new(RecordedEvents::default());
+        let language = RelayLinkLanguage::connect(relay.local_addr(), room, did, events.clone())
+            .await
+            .unwrap();
+        (language, events)
+    }
+
+    #[tokio::test]
+    async fn commits_reach_the_other_agents_of_the_room() {
+        let relay = RelayServer::bind("127.0.0.1:0").await.unwrap();
+        let (alice, _) = join(&relay, "nh", "did:test:alice").await;
+        let (bob, bob_events) = join(&relay, "nh", "did:test:bob").await;
+        let (_carol, carol_events) = join(&relay, "other", "did:test:carol").await;
+
+        let diff = PerspectiveDiff::from_additions(vec![link("a"), link("b")]);
+        assert_eq!(alice.commit(diff.clone()).await.unwrap(), Some("1".into()));
+
+        eventually(|| bob_events.diffs.lock().unwrap().len() == 1).await;
+        assert_eq!(bob_events.diffs.lock().unwrap()[0], diff);
+        assert_eq!(bob.current_revision().await.unwrap(), Some("1".into()));
+        assert!(carol_events.diffs.lock().unwrap().is_empty());
+
+        alice
+            .commit(PerspectiveDiff {
+                additions: vec![],
+                removals: vec![link("a")],
+            })
+            .await
+            .unwrap();
+        let rendered = bob.render().await.unwrap().unwrap();
+        assert_eq!(rendered.links, vec![link("b")]);
+        assert_eq!(alice.others().await.unwrap(), vec!["did:test:bob"]);
+    }
+
+    #[tokio::test]
+    async fn late_joiners_catch_up_on_sync() {
+        let relay = RelayServer::bind("127.0.0.1:0").await.unwrap();
+        let (alice, _) = join(&relay, "nh", "did:test:alice").await;
+        for target in ["a", "b", "c"] {
+            alice
+                .commit(PerspectiveDiff::from_additions(vec![link(target)]))
+                .await
+                .unwrap();
+        }
+
+        let (bob, bob_events) = join(&relay, "nh", "did:test:bob").await;
+        assert_eq!(bob.current_revision().await.unwrap(), None);
+        bob.sync().await.unwrap();
+        assert_eq!(bob_events.diffs.lock().unwrap().len(), 3);
+        assert!(*bob_events.synced.lock().unwrap());
+        assert_eq!(bob.current_revision().await.unwrap(), Some("3".into()));
+
+        // Nothing new, nothing handed on twice
+        bob.sync().await.unwrap();
+        assert_eq!(bob_events.diffs.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn telepresence_goes_through_the_relay() {
+        let relay = RelayServer::bind("127.0.0.1:0").await.unwrap();
+        let (alice, alice_events) = join(&relay, "nh", "did:test:alice").await;
+        let (bob, bob_events) = join(&relay, "nh", "did:test:bob").await;
+
+        assert!(alice.get_online_agents().await.unwrap().is_empty());
+        bob.set_online_status(PerspectiveExpression::default())
+            .await
+            .unwrap();
+        let online = alice.get_online_agents().await.unwrap();
+        assert_eq!(online.len(), 1);
+        assert_eq!(online[0].did, "did:test:bob");
+
+        alice
+            .send_signal("did:test:bob".into(), PerspectiveExpression::default())
+            .await
+            .unwrap();
+        bob.send_broadcast(PerspectiveExpression::default())
+            .await
+            .unwrap();
+        eventually(|| bob_events.signals.lock().unwrap().len() == 1).await;
+        eventually(|| alice_events.signals.lock().unwrap().len() == 1).await;
+    }
+
+    #[tokio::test]
+    async fn connecting_fails_once_the_relay_is_gone() {
+        let relay = RelayServer::bind("127.0.0.1:0").await.unwrap();
+        let addr = relay.local_addr();
+        drop(relay);
+        // The listener is gone with the accept loop
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let events = Arc::new(RecordedEvents::default());
+        assert!(
+            RelayLinkLanguage::connect(addr, "nh", "did:test:alice", events)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,315 @@
+//! Versions of languages.
+//!
+//! Languages are content addressed, so a new version is a new language. Its author names the
+//! version it replaces in `LanguageMeta.supersedes`, and since the meta is signed as part of
+//! the language expression, a version only counts if it is signed by the author of the one it
+//! supersedes. Template instances, like the link languages of neighbourhoods, are upgraded by
+//! applying the same template data to a newer version of their source.
+
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+
+use super::LanguageController;
+use crate::graphql::graphql_types::{ExpressionRendered, LanguageUpgrade};
+use crate::neighbourhoods::announced_upgrade;
+use crate::perspectives::all_perspectives;
+use crate::runtime_service::RuntimeService;
+
+/// How far back through older versions an upgrade gets checked
+const MAX_UPGRADE_STEPS: usize = 32;
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionMeta {
+    pub name: String,
+    pub version: Option<String>,
+    pub supersedes: Option<String>,
+    pub template_source_language_address: Option<String>,
+    pub template_applied_params: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LanguageVersion {
+    pub address: String,
+    pub author: String,
+    /// Whether the author's signature over the meta is valid
+    pub signed: bool,
+    pub meta: VersionMeta,
+}
+
+impl LanguageVersion {
+    fn from_expression(address: &str, expression: ExpressionRendered) -> Result<Self, AnyError> {
+        Ok(LanguageVersion {
+            address: address.to_string(),
+            author: expression.author,
+            signed: expression.proof.valid.unwrap_or(false),
+            meta: serde_json::from_str(&expression.data)?,
+        })
+    }
+
+    /// The language this one is a template instance of, or itself
+    pub fn source(&self) -> &str {
+        self.meta
+            .template_source_language_address
+            .as_deref()
+            .unwrap_or(&self.address)
+    }
+}
+
+pub async fn language_version(address: &str) -> Result<Option<LanguageVersion>, AnyError> {
+    LanguageController::language_expression(address)
+        .await?
+        .map(|expression| LanguageVersion::from_expression(address, expression))
+        .transpose()
+}
+
+async fn expect_version(address: &str) -> Result<LanguageVersion, AnyError> {
+    language_version(address)
+        .await?
+        .ok_or(anyhow!("Could not get the meta of language {}", address))
+}
+
+/// Fails unless `newer` is a version that may replace `older`
+pub fn check_supersedes(newer: &LanguageVersion, older: &LanguageVersion) -> Result<(), AnyError> {
+    if newer.meta.supersedes.as_deref() != Some(older.address.as_str()) {
+        return Err(anyhow!(
+            "Language {} does not supersede {}",
+            newer.address,
+            older.address
+        ));
+    }
+    if !newer.signed {
+        return Err(anyhow!(
+            "Signature of language {} is not valid",
+            newer.address
+        ));
+    }
+    if newer.author != older.author {
+        return Err(anyhow!(
+            "Language {} is by {}, not by {} who published {}",
+            newer.address,
+            newer.author,
+            older.author,
+            older.address
+        ));
+    }
+    Ok(())
+}
+
+/// The newest version of `base` among `candidates`, following the versions that supersede
+/// each other as long as every step checks out
+pub fn newest_version<'a>(
+    base: &LanguageVersion,
+    candidates: &'a [LanguageVersion],
+) -> Option<&'a LanguageVersion> {
+    let mut newest: Option<&LanguageVersion> = None;
+    // There can't be more steps than candidates, which also ends cycles
+    for _ in 0..candidates.len() {
+        let current = newest.unwrap_or(base);
+        match candidates
+            .iter()
+            .find(|candidate| check_supersedes(candidate, current).is_ok())
+        {
+            Some(next) => newest = Some(next),
+            None => break,
+        }
+    }
+    newest
+}
+
+/// Checks that the language at `newer` is a later version of the one at `older`,
+/// step by step through the versions in between, and returns it
+pub async fn verify_upgrade(newer: &str, older: &str) -> Result<LanguageVersion, AnyError> {
+    if newer == older {
+        return Err(anyhow!("Language {} is already in use", newer));
+    }
+    let newer_version = expect_version(newer).await?;
+    let mut current = newer_version.clone();
+    for _ in 0..MAX_UPGRADE_STEPS {
+        let previous = current.meta.supersedes.clone().ok_or(anyhow!(
+            "Language {} is no newer version of {}",
+            newer,
+            older
+        ))?;
+        let previous = expect_version(&previous).await?;
+        check_supersedes(&current, &previous)?;
+        if previous.address == older {
+            return Ok(newer_version);
+        }
+        current = previous;
+    }
+    Err(anyhow!(
+        "Language {} is more than {} versions away from {}",
+        newer,
+        MAX_UPGRADE_STEPS,
+        older
+    ))
+}
+
+/// Versions of the languages we know about: the installed ones and the known link languages
+async fn known_versions() -> Result<Vec<LanguageVersion>, AnyError> {
+    let mut addresses: BTreeSet<String> =
+        RuntimeService::with_global_instance(|runtime| runtime.get_know_link_languages())
+            .into_iter()
+            .collect();
+    addresses.extend(
+        LanguageController::installed_languages()
+            .await?
+            .into_iter()
+            .map(|language| language.address),
+    );
+
+    let mut versions = Vec::new();
+    for address in addresses {
+        match language_version(&address).await {
+            Ok(Some(version)) => versions.push(version),
+            Ok(None) => {}
+            Err(e) => log::warn!("Could not get the meta of language {}: {:?}", address, e),
+        }
+    }
+    Ok(versions)
+}
+
+/// The newest known version of the language at `address`
+pub async fn newest_known_version(address: &str) -> Result<Option<LanguageVersion>, AnyError> {
+    let base = expect_version(address).await?;
+    let versions = known_versions().await?;
+    Ok(newest_version(&base, &versions).cloned())
+}
+
+/// Upgrades for the link languages of our neighbourhoods and for the other installed languages
+pub async fn available_upgrades() -> Result<Vec<LanguageUpgrade>, AnyError> {
+    let versions = known_versions().await?;
+    let mut upgrades = Vec::new();
+    let mut link_languages = BTreeSet::new();
+
+    for perspective in all_perspectives() {
+        let handle = perspective.persisted.lock().await.clone();
+        let link_language = match handle.neighbourhood {
+            Some(neighbourhood) => neighbourhood.data.link_language,
+            None => continue,
+        };
+        link_languages.insert(link_language.clone());
+        let current = match versions.iter().find(|v| v.address == link_language) {
+            Some(current) => current,
+            None => continue,
+        };
+
+        let announced = match announced_upgrade(&perspective).await {
+            Ok(announced) => announced,
+            Err(e) => {
+                log::warn!(
+                    "Ignoring upgrade announcement in perspective {}: {:?}",
+                    handle.uuid,
+                    e
+                );
+                None
+            }
+        };
+        let (upgrade, neighbourhood_url) = match announced {
+            Some(announced) => (Some(announced.source), Some(announced.url)),
+            None => {
+                // Sources of templates are usually not installed themselves
+                let source = match versions.iter().find(|v| v.address == current.source()) {
+                    Some(source) => Some(source.clone()),
+                    None => language_version(current.source()).await.unwrap_or(None),
+                };
+                let newest = source.and_then(|source| newest_version(&source, &versions).cloned());
+                (newest, None)
+            }
+        };
+
+        if let Some(upgrade) = upgrade {
+            upgrades.push(LanguageUpgrade {
+                address: current.address.clone(),
+                name: current.meta.name.clone(),
+                version: current.meta.version.clone(),
+                upgrade_address: upgrade.address,
+                upgrade_version: upgrade.meta.version,
+                perspective_uuid: Some(handle.uuid),
+                neighbourhood_url,
+            });
+        }
+    }
+
+    for current in versions.iter() {
+        // Template instances only get upgraded together with their neighbourhood
+        if link_languages.contains(&current.address)
+            || current.meta.template_source_language_address.is_some()
+        {
+            continue;
+        }
+        if let Some(upgrade) = newest_version(current, &versions) {
+            upgrades.push(LanguageUpgrade {
+                address: current.address.clone(),
+                name: current.meta.name.clone(),
+                version: current.meta.version.clone(),
+                upgrade_address: upgrade.address.clone(),
+                upgrade_version: upgrade.meta.version.clone(),
+                perspective_uuid: None,
+                neighbourhood_url: None,
+            });
+        }
+    }
+
+    Ok(upgrades)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(address: &str, author: &str, supersedes: Option<&str>) -> LanguageVersion {
+        LanguageVersion {
+            address: address.to_string(),
+            author: author.to_string(),
+            signed: true,
+            meta: VersionMeta {
+                name: "links".to_string(),
+                supersedes: supersedes.map(|s| s.to_string()),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn only_signed_versions_by_the_same_author_supersede() {
+        let v1 = version("Qm1", "did:key:alice", None);
+        let v2 = version("Qm2", "did:key:alice", Some("Qm1"));
+        assert!(check_supersedes(&v2, &v1).is_ok());
+        assert!(check_supersedes(&v1, &v2).is_err());
+
+        let unsigned = LanguageVersion {
+            signed: false,
+            ..v2.clone()
+        };
+        assert!(check_supersedes(&unsigned, &v1).is_err());
+
+        let hijacked = version("Qm3", "did:key:mallory", Some("Qm1"));
+        assert!(check_supersedes(&hijacked, &v1).is_err());
+    }
+
+    #[test]
+    fn newest_version_follows_the_chain() {
+        let v1 = version("Qm1", "did:key:alice", None);
+        let v2 = version("Qm2", "did:key:alice", Some("Qm1"));
+        let v3 = version("Qm3", "did:key:alice", Some("Qm2"));
+        let fork = version("Qm4", "did:key:mallory", Some("Qm3"));
+        let candidates = vec![fork, v3.clone(), v1.clone(), v2.clone()];
+
+        assert_eq!(newest_version(&v1, &candidates), Some(&v3));
+        assert_eq!(newest_version(&v2, &candidates), Some(&v3));
+        assert_eq!(newest_version(&v3, &candidates), None);
+    }
+
+    #[test]
+    fn newest_version_ends_on_cycles() {
+        let v1 = version("Qm1", "did:key:alice", Some("Qm2"));
+        let v2 = version("Qm2", "did:key:alice", Some("Qm1"));
+        let candidates = vec![v1.clone(), v2.clone()];
+
+        assert!(newest_version(&v1, &candidates).is_some());
+    }
+}
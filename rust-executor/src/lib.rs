@@ -7,6 +7,7 @@ mod globals;
 pub mod graphql;
 mod holochain_service;
 mod js_core;
+mod metrics;
 mod prolog_service;
 mod runtime_service;
 mod utils;
@@ -29,14 +30,19 @@ use std::{env, thread::JoinHandle};
 
 use log::{error, info, warn};
 
-use js_core::JsCore;
+use js_core::{rpc::JsRpc, JsCore, JsCoreHandle};
 
 use crate::{
-    agent::AgentService, ai_service::AIService, dapp_server::serve_dapp, db::Ad4mDb,
-    languages::LanguageController, prolog_service::init_prolog_service,
+    agent::AgentService,
+    ai_service::AIService,
+    dapp_server::serve_dapp,
+    db::Ad4mDb,
+    languages::{bundle_cache::BundleCache, LanguageController},
+    prolog_service::init_prolog_service,
     runtime_service::RuntimeService,
 };
-pub use config::Ad4mConfig;
+pub use config::{Ad4mConfig, AgentRestoreConfig};
+pub use holochain_service::call_policy::{ZomeCallOptions, ZomeCallPolicyConfig};
 pub use holochain_service::run_local_hc_services;
 use libc::{sigaction, sigemptyset, sighandler_t, SA_ONSTACK, SIGURG};
 use std::ptr;
@@ -89,6 +95,19 @@ pub async fn run(mut config: Ad4mConfig) -> JoinHandle<()> {
 
     info!("Initializing Agent service...");
     AgentService::init_global_instance(config.app_data_path.clone().unwrap());
+    entanglement_service::load_device_proofs();
+    agent::signatures::load_key_successions();
+
+    info!("Initializing language bundle cache...");
+    BundleCache::init_global_instance(config.app_data_path.as_ref().unwrap());
+    if let Some(seed) = &config.language_bundle_seed {
+        match BundleCache::global_instance()
+            .and_then(|cache| cache.seed(std::path::Path::new(seed)))
+        {
+            Ok(seeded) => info!("Seeded {} language bundles from {}", seeded.len(), seed),
+            Err(e) => error!("Failed to seed language bundles from {}: {}", seed, e),
+        }
+    }
 
     info!("Initializing Runtime service...");
     RuntimeService::init_global_instance(
@@ -98,18 +117,17 @@ pub async fn run(mut config: Ad4mConfig) -> JoinHandle<()> {
             .into_owned(),
     );
 
-    agent::capabilities::apps_map::set_data_file_path(
-        config
-            .app_data_path
-            .as_ref()
-            .map(|path| {
-                std::path::Path::new(path)
-                    .join("apps_data.json")
-                    .to_string_lossy()
-                    .into_owned()
-            })
-            .expect("App data path not set in Ad4mConfig"),
-    );
+    if let Err(e) = agent::capabilities::apps_map::migrate_apps_from_file(
+        &std::path::Path::new(
+            config
+                .app_data_path
+                .as_ref()
+                .expect("App data path not set in Ad4mConfig"),
+        )
+        .join("apps_data.json"),
+    ) {
+        error!("Failed to migrate apps_data.json into Ad4mDb: {}", e);
+    }
 
     if let Some(admin_credential) = &config.admin_credential {
         if admin_credential.is_empty() {
@@ -121,17 +139,55 @@ pub async fn run(mut config: Ad4mConfig) -> JoinHandle<()> {
         warn!("adminCredential is not set or empty, empty token will possess admin capabilities.");
     }
 
+    let default_kdf_params = wallet::KdfParams::default();
+    if let Err(e) = wallet::set_kdf_params(wallet::KdfParams {
+        memory_cost: config
+            .wallet_kdf_memory_cost
+            .unwrap_or(default_kdf_params.memory_cost),
+        iterations: config
+            .wallet_kdf_iterations
+            .unwrap_or(default_kdf_params.iterations),
+        parallelism: default_kdf_params.parallelism,
+    }) {
+        error!("Invalid wallet KDF configuration, using defaults: {}", e);
+    }
+
+    agent::capabilities::rate_limits::RateLimiter::init_global_instance(
+        config.rate_limits.clone().unwrap_or_default(),
+    );
+
+    holochain_service::call_policy::init_zome_call_policies(
+        config.zome_call_policies.clone().unwrap_or_default(),
+    );
+
     info!("Initializing Prolog service...");
     init_prolog_service().await;
 
     info!("Starting js_core...");
     let mut js_core_handle = JsCore::start(config.clone()).await;
-    js_core_handle.initialized().await;
-    info!("js_core initialized.");
+    match js_core_handle.initialized().await {
+        Ok(()) => info!("js_core initialized."),
+        Err(e) => error!("js_core failed to initialize: {}", e),
+    }
 
     LanguageController::init_global_instance(js_core_handle.clone());
     perspectives::initialize_from_db();
 
+    if let Some(restore) = &config.restore_agent_from_mnemonic {
+        info!("Restoring agent from recovery phrase...");
+        restore_agent_from_mnemonic(&mut js_core_handle, restore).await;
+    }
+
+    info!("Starting notification webhook delivery and digests...");
+    tokio::spawn(runtime_service::webhooks::run_delivery_loop());
+    tokio::spawn(runtime_service::notification_throttle::run_throttle_loop());
+
+    info!("Starting direct message outbox...");
+    tokio::spawn(runtime_service::direct_messages::run_outbox_loop());
+
+    info!("Starting job scheduler...");
+    tokio::spawn(runtime_service::scheduler::run_scheduler_loop());
+
     let app_dir = config
         .app_data_path
         .as_ref()
@@ -166,3 +222,36 @@ pub async fn run(mut config: Ad4mConfig) -> JoinHandle<()> {
             .unwrap();
     })
 }
+
+/// Creates the agent from the recovery phrase in the config, unless there already is one
+async fn restore_agent_from_mnemonic(
+    js_core_handle: &mut JsCoreHandle,
+    restore: &AgentRestoreConfig,
+) {
+    let restored = AgentService::with_mutable_global_instance(|agent_service| {
+        if agent_service.is_initialized() {
+            return Ok(false);
+        }
+        agent_service.restore_from_mnemonic(restore.mnemonic.clone())?;
+        agent_service.save(restore.passphrase.clone());
+        Ok::<bool, deno_core::error::AnyError>(true)
+    });
+
+    match restored {
+        Ok(true) => {
+            if let Err(e) = js_core_handle
+                .rpc::<serde_json::Value>(JsRpc::mutation(
+                    "agentGenerate",
+                    serde_json::json!({ "passphrase": restore.passphrase }),
+                ))
+                .await
+            {
+                error!("Failed to initialize restored agent in js_core: {}", e);
+            } else {
+                info!("Agent restored from recovery phrase");
+            }
+        }
+        Ok(false) => warn!("Agent already initialized, not restoring it from recovery phrase"),
+        Err(e) => error!("Failed to restore agent from recovery phrase: {}", e),
+    }
+}
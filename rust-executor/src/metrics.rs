@@ -0,0 +1,366 @@
+//! Counters, gauges and histograms describing the executor's runtime behaviour,
+//! kept in process and exported in OpenMetrics text format on `/metrics`.
+
+use lazy_static::lazy_static;
+use log::error;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::db::Ad4mDb;
+
+pub type Labels = Vec<(&'static str, String)>;
+
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Upper bounds in seconds, for latencies from fast Prolog queries to slow LLM prompts
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+fn to_labels(labels: &[(&'static str, &str)]) -> Labels {
+    labels
+        .iter()
+        .map(|(name, value)| (*name, value.to_string()))
+        .collect()
+}
+
+/// A monotonically increasing counter with one value per label set.
+pub struct Counter {
+    pub name: &'static str,
+    pub help: &'static str,
+    values: Mutex<BTreeMap<Labels, u64>>,
+}
+
+impl Counter {
+    pub fn new(name: &'static str, help: &'static str) -> Self {
+        Counter {
+            name,
+            help,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inc(&self, labels: &[(&'static str, &str)]) {
+        let mut values = self.values.lock().expect("Couldn't get lock on counter");
+        *values.entry(to_labels(labels)).or_insert(0) += 1;
+    }
+
+    #[cfg(test)]
+    pub fn get(&self, labels: &[(&'static str, &str)]) -> u64 {
+        let values = self.values.lock().expect("Couldn't get lock on counter");
+        values.get(&to_labels(labels)).copied().unwrap_or(0)
+    }
+
+    fn render(&self, out: &mut String) {
+        let values = self.values.lock().expect("Couldn't get lock on counter");
+        write_header(out, self.name, "counter", self.help);
+        for (labels, value) in values.iter() {
+            write_sample(out, self.name, "_total", labels, None, &value.to_string());
+        }
+    }
+}
+
+/// A value that can go up and down, with one value per label set.
+pub struct Gauge {
+    pub name: &'static str,
+    pub help: &'static str,
+    values: Mutex<BTreeMap<Labels, i64>>,
+}
+
+impl Gauge {
+    pub fn new(name: &'static str, help: &'static str) -> Self {
+        Gauge {
+            name,
+            help,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn add(&self, labels: &[(&'static str, &str)], delta: i64) {
+        let mut values = self.values.lock().expect("Couldn't get lock on gauge");
+        *values.entry(to_labels(labels)).or_insert(0) += delta;
+    }
+
+    #[cfg(test)]
+    pub fn get(&self, labels: &[(&'static str, &str)]) -> i64 {
+        let values = self.values.lock().expect("Couldn't get lock on gauge");
+        values.get(&to_labels(labels)).copied().unwrap_or(0)
+    }
+
+    /// Replaces all values, so label sets that aren't given anymore disappear
+    pub fn replace(&self, values: Vec<(Labels, i64)>) {
+        let mut current = self.values.lock().expect("Couldn't get lock on gauge");
+        *current = values.into_iter().collect();
+    }
+
+    /// Counts up until the returned guard is dropped
+    pub fn track(&'static self) -> GaugeGuard {
+        self.add(&[], 1);
+        GaugeGuard { gauge: self }
+    }
+
+    fn render(&self, out: &mut String) {
+        let values = self.values.lock().expect("Couldn't get lock on gauge");
+        write_header(out, self.name, "gauge", self.help);
+        for (labels, value) in values.iter() {
+            write_sample(out, self.name, "", labels, None, &value.to_string());
+        }
+    }
+}
+
+pub struct GaugeGuard {
+    gauge: &'static Gauge,
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.gauge.add(&[], -1);
+    }
+}
+
+struct HistogramValue {
+    /// Non-cumulative, one per bucket plus one for `+Inf`
+    bucket_counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// Durations in seconds, with one distribution per label set.
+pub struct Histogram {
+    pub name: &'static str,
+    pub help: &'static str,
+    buckets: &'static [f64],
+    values: Mutex<BTreeMap<Labels, HistogramValue>>,
+}
+
+impl Histogram {
+    pub fn new(name: &'static str, help: &'static str) -> Self {
+        Histogram {
+            name,
+            help,
+            buckets: DURATION_BUCKETS,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn observe(&self, labels: &[(&'static str, &str)], duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = self
+            .buckets
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(self.buckets.len());
+        let mut values = self.values.lock().expect("Couldn't get lock on histogram");
+        let value = values
+            .entry(to_labels(labels))
+            .or_insert_with(|| HistogramValue {
+                bucket_counts: vec![0; self.buckets.len() + 1],
+                sum: 0.0,
+                count: 0,
+            });
+        value.bucket_counts[bucket] += 1;
+        value.sum += seconds;
+        value.count += 1;
+    }
+
+    fn render(&self, out: &mut String) {
+        let values = self.values.lock().expect("Couldn't get lock on histogram");
+        write_header(out, self.name, "histogram", self.help);
+        for (labels, value) in values.iter() {
+            let mut cumulative = 0;
+            for (index, count) in value.bucket_counts.iter().enumerate() {
+                cumulative += count;
+                let bound = match self.buckets.get(index) {
+                    Some(bound) => format!("{:?}", bound),
+                    None => "+Inf".to_string(),
+                };
+                write_sample(
+                    out,
+                    self.name,
+                    "_bucket",
+                    labels,
+                    Some(&bound),
+                    &cumulative.to_string(),
+                );
+            }
+            write_sample(
+                out,
+                self.name,
+                "_count",
+                labels,
+                None,
+                &value.count.to_string(),
+            );
+            write_sample(out, self.name, "_sum", labels, None, &value.sum.to_string());
+        }
+    }
+}
+
+fn write_header(out: &mut String, name: &str, metric_type: &str, help: &str) {
+    let _ = writeln!(out, "# TYPE {} {}", name, metric_type);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+}
+
+fn write_sample(
+    out: &mut String,
+    name: &str,
+    suffix: &str,
+    labels: &Labels,
+    le: Option<&str>,
+    value: &str,
+) {
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|(label, value)| format!("{}=\"{}\"", label, escape_label_value(value)))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+    if pairs.is_empty() {
+        let _ = writeln!(out, "{}{} {}", name, suffix, value);
+    } else {
+        let _ = writeln!(out, "{}{}{{{}}} {}", name, suffix, pairs.join(","), value);
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+lazy_static! {
+    pub static ref HOLOCHAIN_ZOME_CALL_TIMEOUTS: Counter = Counter::new(
+        "ad4m_holochain_zome_call_timeouts",
+        "Zome call attempts that hit their timeout"
+    );
+    pub static ref HOLOCHAIN_ZOME_CALL_RETRIES: Counter = Counter::new(
+        "ad4m_holochain_zome_call_retries",
+        "Zome calls retried after a timeout"
+    );
+    pub static ref GRAPHQL_REQUESTS: Counter = Counter::new(
+        "ad4m_graphql_requests",
+        "GraphQL requests and websocket connections accepted"
+    );
+    pub static ref PROLOG_QUERY_DURATION: Histogram = Histogram::new(
+        "ad4m_prolog_query_duration_seconds",
+        "Time taken by Prolog queries"
+    );
+    pub static ref PERSPECTIVE_PENDING_DIFFS: Gauge = Gauge::new(
+        "ad4m_perspective_pending_diffs",
+        "Diffs waiting to be committed to link languages, over all perspectives"
+    );
+    pub static ref PERSPECTIVES_WITH_PENDING_DIFFS: Gauge = Gauge::new(
+        "ad4m_perspectives_with_pending_diffs",
+        "Perspectives with diffs waiting to be committed to their link language"
+    );
+    pub static ref JS_CORE_PENDING_CALLS: Gauge = Gauge::new(
+        "ad4m_js_core_pending_calls",
+        "Calls to the JS core waiting for their result"
+    );
+    pub static ref AI_TASK_DURATION: Histogram = Histogram::new(
+        "ad4m_ai_task_duration_seconds",
+        "Time taken by AI prompts and embeddings"
+    );
+}
+
+/// Pending diffs live in the database, so they are read when metrics are scraped.
+/// Only totals are exported, perspective UUIDs don't belong in metrics.
+fn refresh_pending_diffs() {
+    match Ad4mDb::with_global_instance(|db| db.count_pending_diffs()) {
+        Ok(counts) => {
+            let total: u64 = counts.iter().map(|(_, count)| count).sum();
+            PERSPECTIVE_PENDING_DIFFS.replace(vec![(vec![], total as i64)]);
+            PERSPECTIVES_WITH_PENDING_DIFFS.replace(vec![(vec![], counts.len() as i64)]);
+        }
+        Err(e) => error!("Couldn't count pending diffs for metrics: {}", e),
+    }
+}
+
+/// All metrics in OpenMetrics text format
+pub fn render() -> String {
+    refresh_pending_diffs();
+
+    let mut out = String::new();
+    HOLOCHAIN_ZOME_CALL_TIMEOUTS.render(&mut out);
+    HOLOCHAIN_ZOME_CALL_RETRIES.render(&mut out);
+    GRAPHQL_REQUESTS.render(&mut out);
+    PROLOG_QUERY_DURATION.render(&mut out);
+    PERSPECTIVE_PENDING_DIFFS.render(&mut out);
+    PERSPECTIVES_WITH_PENDING_DIFFS.render(&mut out);
+    JS_CORE_PENDING_CALLS.render(&mut out);
+    AI_TASK_DURATION.render(&mut out);
+    out.push_str("# EOF\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_openmetrics_text() {
+        let mut out = String::new();
+        let counter = Counter::new("requests", "Requests");
+        counter.inc(&[("transport", "http")]);
+        counter.inc(&[("transport", "http")]);
+        counter.inc(&[("transport", "say \"hi\"")]);
+        counter.render(&mut out);
+        assert_eq!(
+            out,
+            "# TYPE requests counter\n\
+             # HELP requests Requests\n\
+             requests_total{transport=\"http\"} 2\n\
+             requests_total{transport=\"say \\\"hi\\\"\"} 1\n"
+        );
+
+        let mut out = String::new();
+        let histogram = Histogram::new("latency_seconds", "Latency");
+        histogram.observe(&[], Duration::from_millis(20));
+        histogram.observe(&[], Duration::from_secs(120));
+        histogram.render(&mut out);
+        assert!(out.contains("latency_seconds_bucket{le=\"0.01\"} 0\n"));
+        assert!(out.contains("latency_seconds_bucket{le=\"0.025\"} 1\n"));
+        assert!(out.contains("latency_seconds_bucket{le=\"60.0\"} 1\n"));
+        assert!(out.contains("latency_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(out.contains("latency_seconds_count 2\n"));
+        assert!(out.contains("latency_seconds_sum 120.0"));
+    }
+
+    #[test]
+    fn gauge_guards_count_while_alive() {
+        lazy_static! {
+            static ref IN_FLIGHT: Gauge = Gauge::new("in_flight", "In flight");
+        }
+        let first = IN_FLIGHT.track();
+        let second = IN_FLIGHT.track();
+        assert_eq!(IN_FLIGHT.get(&[]), 2);
+        drop(first);
+        drop(second);
+        assert_eq!(IN_FLIGHT.get(&[]), 0);
+
+        IN_FLIGHT.replace(vec![(vec![("perspective", "a".to_string())], 3)]);
+        assert_eq!(IN_FLIGHT.get(&[]), 0);
+        assert_eq!(IN_FLIGHT.get(&[("perspective", "a")]), 3);
+    }
+
+    #[test]
+    fn pending_diffs_are_counted_without_perspective_uuids() {
+        Ad4mDb::init_global_instance(":memory:").unwrap();
+        let perspective_uuid = uuid::Uuid::new_v4().to_string();
+        let diff = crate::types::PerspectiveDiff {
+            additions: vec![],
+            removals: vec![],
+        };
+        Ad4mDb::with_global_instance(|db| db.add_pending_diff(&perspective_uuid, &diff)).unwrap();
+
+        let out = render();
+        assert!(out.contains("\nad4m_perspective_pending_diffs "));
+        assert!(out.contains("\nad4m_perspectives_with_pending_diffs "));
+        assert!(!out.contains("perspective=\""));
+        assert!(!out.contains(&perspective_uuid));
+    }
+}
@@ -2,11 +2,16 @@ use deno_core::anyhow::anyhow;
 use deno_core::error::AnyError;
 use uuid::Uuid;
 
+use crate::agent::{self, create_signed_expression, device_sync};
 use crate::graphql::graphql_types::{
-    Neighbourhood, Perspective, PerspectiveHandle, PerspectiveState,
+    DecoratedNeighbourhoodExpression, LinkQuery, LinkStatus, Neighbourhood, Perspective,
+    PerspectiveHandle, PerspectiveState,
 };
+use crate::languages::versions::{self, LanguageVersion};
 use crate::languages::LanguageController;
+use crate::perspectives::perspective_instance::PerspectiveInstance;
 use crate::perspectives::{add_perspective, all_perspectives, get_perspective, update_perspective};
+use crate::runtime_service::notification_triggers;
 use crate::types::*;
 
 pub async fn neighbourhood_publish_from_perspective(
@@ -42,6 +47,181 @@ pub async fn neighbourhood_publish_from_perspective(
     Ok(neighbourhood_url)
 }
 
+/// Links from `ad4m://self` with this predicate announce the neighbourhood at the target
+/// as the new home of the neighbourhood, using a newer version of the link language
+pub const UPGRADED_TO_PREDICATE: &str = "ad4m://upgraded_to";
+const SELF: &str = "ad4m://self";
+
+pub struct AnnouncedUpgrade {
+    pub url: String,
+    pub neighbourhood: DecoratedNeighbourhoodExpression,
+    /// The version of the link language's source it upgrades to
+    pub source: LanguageVersion,
+}
+
+/// The newest neighbourhood another member moved the neighbourhood of `perspective` to,
+/// if its link language is a valid upgrade of ours
+pub async fn announced_upgrade(
+    perspective: &PerspectiveInstance,
+) -> Result<Option<AnnouncedUpgrade>, AnyError> {
+    let handle = perspective.persisted.lock().await.clone();
+    let link_language = match handle.neighbourhood {
+        Some(neighbourhood) => neighbourhood.data.link_language,
+        None => return Ok(None),
+    };
+
+    let mut announcements = perspective
+        .get_links(&LinkQuery {
+            source: Some(SELF.to_string()),
+            predicate: Some(UPGRADED_TO_PREDICATE.to_string()),
+            ..Default::default()
+        })
+        .await?;
+    // Moving on carries the announcements over, including the one pointing to ourselves
+    announcements.retain(|link| Some(&link.data.target) != handle.shared_url.as_ref());
+    announcements.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    if announcements.is_empty() {
+        return Ok(None);
+    }
+
+    let current = versions::language_version(&link_language)
+        .await?
+        .ok_or(anyhow!(
+            "Could not get the meta of link language {}",
+            link_language
+        ))?;
+    for announcement in announcements {
+        let url = announcement.data.target;
+        match verify_announcement(&url, &current).await {
+            Ok((neighbourhood, source)) => {
+                return Ok(Some(AnnouncedUpgrade {
+                    url,
+                    neighbourhood,
+                    source,
+                }))
+            }
+            Err(e) => log::warn!(
+                "Ignoring announced upgrade of perspective {} to {}: {:?}",
+                handle.uuid,
+                url,
+                e
+            ),
+        }
+    }
+    Ok(None)
+}
+
+async fn verify_announcement(
+    url: &str,
+    current: &LanguageVersion,
+) -> Result<(DecoratedNeighbourhoodExpression, LanguageVersion), AnyError> {
+    let expression_ref = ExpressionRef::try_from(url.to_string())?;
+    let neighbourhood = LanguageController::get_neighbourhood(expression_ref.expression)
+        .await?
+        .ok_or(anyhow!("Could not find neighbourhood with URL {}", url))?;
+    let upgraded = versions::language_version(&neighbourhood.data.link_language)
+        .await?
+        .ok_or(anyhow!(
+            "Could not get the meta of link language {}",
+            neighbourhood.data.link_language
+        ))?;
+    // Another template instance would be another neighbourhood
+    if upgraded.meta.template_applied_params != current.meta.template_applied_params {
+        return Err(anyhow!(
+            "Link language {} was templated differently than {}",
+            upgraded.address,
+            current.address
+        ));
+    }
+    let source = versions::verify_upgrade(upgraded.source(), current.source()).await?;
+    Ok((neighbourhood, source))
+}
+
+/// Moves the neighbourhood of the perspective to a newer version of its link language.
+///
+/// If another member already did, the perspective follows them. Otherwise the newer version
+/// of the link language's source, `language_address` or the newest one we know of, gets the
+/// same template data applied and a new neighbourhood with it is created. All shared links
+/// are committed to it, and the old neighbourhood gets a link pointing to the new one, so the
+/// other members find it through `languageUpgrades` and can follow.
+pub async fn neighbourhood_upgrade_link_language(
+    uuid: &str,
+    language_address: Option<String>,
+) -> Result<PerspectiveHandle, AnyError> {
+    let perspective = get_perspective(uuid).ok_or(anyhow!("Perspective not found"))?;
+    let neighbourhood = perspective
+        .persisted
+        .lock()
+        .await
+        .neighbourhood
+        .clone()
+        .ok_or(anyhow!("Perspective {} is not a neighbourhood", uuid))?;
+    let current = versions::language_version(&neighbourhood.data.link_language)
+        .await?
+        .ok_or(anyhow!(
+            "Could not get the meta of link language {}",
+            neighbourhood.data.link_language
+        ))?;
+
+    if language_address.is_none() {
+        if let Some(announced) = announced_upgrade(&perspective).await? {
+            let link_language = announced.neighbourhood.data.link_language.clone();
+            let language = LanguageController::language_by_address(link_language.clone())
+                .await?
+                .ok_or(anyhow!("Could not install link language {}", link_language))?;
+            perspective
+                .switch_neighbourhood(announced.url, announced.neighbourhood, language, None)
+                .await?;
+            return Ok(perspective.persisted.lock().await.clone());
+        }
+    }
+
+    let source = match language_address {
+        Some(address) => versions::verify_upgrade(&address, current.source()).await?,
+        None => versions::newest_known_version(current.source())
+            .await?
+            .ok_or(anyhow!(
+                "No newer version of link language {} known",
+                current.address
+            ))?,
+    };
+    let link_language = match &current.meta.template_applied_params {
+        Some(template_data) => {
+            LanguageController::apply_template_and_publish(source.address, template_data.clone())
+                .await?
+        }
+        None => source.address,
+    };
+    let language = LanguageController::language_by_address(link_language.clone())
+        .await?
+        .ok_or(anyhow!("Could not install link language {}", link_language))?;
+
+    let neighbourhood_address = LanguageController::create_neighbourhood(Neighbourhood {
+        link_language,
+        meta: neighbourhood.data.meta,
+    })
+    .await?;
+    let neighbourhood_url = format!("neighbourhood://{}", neighbourhood_address);
+    let neighbourhood_exp = LanguageController::get_neighbourhood(neighbourhood_address)
+        .await?
+        .ok_or(anyhow!("Could not retrieve NeigbourhoodExpression which was just created. Problem with Neighbourhood language"))?;
+
+    let announcement = Link {
+        source: SELF.to_string(),
+        predicate: Some(UPGRADED_TO_PREDICATE.to_string()),
+        target: neighbourhood_url.clone(),
+    };
+    perspective
+        .switch_neighbourhood(
+            neighbourhood_url,
+            neighbourhood_exp,
+            language,
+            Some(announcement),
+        )
+        .await?;
+    Ok(perspective.persisted.lock().await.clone())
+}
+
 pub async fn install_neighbourhood(url: String) -> Result<PerspectiveHandle, AnyError> {
     let perspectives = all_perspectives();
 
@@ -91,5 +271,101 @@ pub async fn install_neighbourhood(url: String) -> Result<PerspectiveHandle, Any
         .await
         .map_err(|e| anyhow!(e))?;
 
+    tokio::spawn(notification_triggers::neighbourhood_joined(
+        url,
+        handle.clone(),
+    ));
+
     Ok(handle)
 }
+
+/// Neighbourhoods with a link from `ad4m://self` with this predicate in their meta keep a
+/// perspective in sync between the devices of the agent at the target
+pub const DEVICE_SYNC_PREDICATE: &str = "ad4m://device_sync_of";
+
+/// Whether `neighbourhood` was published by `did`, with a valid signature, to sync its devices
+pub(crate) fn is_device_sync_of(
+    neighbourhood: &DecoratedNeighbourhoodExpression,
+    did: &str,
+) -> bool {
+    neighbourhood.author == did
+        && neighbourhood.proof.valid == Some(true)
+        && neighbourhood.data.meta.links.iter().any(|link| {
+            link.data.source == SELF
+                && link.data.predicate.as_deref() == Some(DEVICE_SYNC_PREDICATE)
+                && link.data.target == did
+        })
+}
+
+/// Shares the perspective with the other devices of the agent, through a neighbourhood
+/// marked as device sync in its meta, and returns the neighbourhood's URL.
+/// Its links are only committed encrypted with the device sync key, see [`device_sync`].
+pub async fn sync_perspective_with_devices(
+    uuid: &str,
+    link_language: String,
+) -> Result<String, AnyError> {
+    let perspective = get_perspective(uuid).ok_or(anyhow!("Perspective not found"))?;
+    let did = agent::did();
+    let handle = perspective.persisted.lock().await.clone();
+    if let Some(neighbourhood) = handle.neighbourhood {
+        return match handle.shared_url {
+            Some(url) if is_device_sync_of(&neighbourhood, &did) => Ok(url),
+            _ => Err(anyhow!(
+                "Perspective {} is already shared as a neighbourhood",
+                uuid
+            )),
+        };
+    }
+
+    device_sync::ensure_sync_key()?;
+    let marker = LinkExpression::from(create_signed_expression(Link {
+        source: SELF.to_string(),
+        predicate: Some(DEVICE_SYNC_PREDICATE.to_string()),
+        target: did,
+    })?);
+    let meta = Perspective {
+        links: vec![DecoratedLinkExpression::from((marker, LinkStatus::Shared))],
+    };
+    neighbourhood_publish_from_perspective(uuid, link_language, meta).await
+}
+
+/// URLs of the neighbourhoods that keep our perspectives in sync between our devices
+pub async fn device_sync_neighbourhoods() -> Vec<String> {
+    let did = agent::did();
+    let mut urls = Vec::new();
+    for perspective in all_perspectives() {
+        let handle = perspective.persisted.lock().await;
+        if let (Some(neighbourhood), Some(url)) = (&handle.neighbourhood, &handle.shared_url) {
+            if is_device_sync_of(neighbourhood, &did) {
+                urls.push(url.clone());
+            }
+        }
+    }
+    urls
+}
+
+/// Joins the device sync neighbourhoods at `urls` that we haven't joined yet.
+/// Neighbourhoods that are not device syncs of our agent are left alone.
+pub async fn join_device_sync_neighbourhoods(urls: Vec<String>) -> Vec<PerspectiveHandle> {
+    let did = agent::did();
+    let joined = device_sync_neighbourhoods().await;
+    let mut handles = Vec::new();
+    for url in urls.into_iter().filter(|url| !joined.contains(url)) {
+        let neighbourhood = match ExpressionRef::try_from(url.clone()) {
+            Ok(expression_ref) => LanguageController::get_neighbourhood(expression_ref.expression)
+                .await
+                .unwrap_or(None),
+            Err(_) => None,
+        };
+        match neighbourhood {
+            Some(neighbourhood) if is_device_sync_of(&neighbourhood, &did) => {
+                match install_neighbourhood(url.clone()).await {
+                    Ok(handle) => handles.push(handle),
+                    Err(e) => log::warn!("Could not join device sync {}: {:?}", url, e),
+                }
+            }
+            _ => log::warn!("Not joining {}, which is no device sync of {}", url, did),
+        }
+    }
+    handles
+}
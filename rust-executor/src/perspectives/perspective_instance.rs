@@ -3,21 +3,26 @@ use super::update_perspective;
 use super::utils::{
     prolog_get_all_string_bindings, prolog_get_first_string_binding, prolog_resolution_to_string,
 };
-use crate::agent::{self, create_signed_expression};
+use crate::agent::device_sync::{self, DeviceSyncKey};
+use crate::agent::{self, create_signed_expression, AgentService};
 use crate::graphql::graphql_types::{
-    DecoratedPerspectiveDiff, ExpressionRendered, JsResultType, LinkMutations, LinkQuery,
-    LinkStatus, NeighbourhoodSignalFilter, OnlineAgent, PerspectiveExpression, PerspectiveHandle,
-    PerspectiveLinkFilter, PerspectiveLinkUpdatedFilter, PerspectiveState, PerspectiveStateFilter,
+    DecoratedNeighbourhoodExpression, DecoratedPerspectiveDiff, ExpressionRendered, JsResultType,
+    LinkMutations, LinkQuery, LinkStatus, NeighbourhoodSignalFilter, OnlineAgent,
+    PerspectiveExpression, PerspectiveHandle, PerspectiveLinkFilter, PerspectiveLinkUpdatedFilter,
+    PerspectiveState, PerspectiveStateFilter,
 };
+use crate::js_core::rpc::JsRpc;
 use crate::languages::language::Language;
 use crate::languages::LanguageController;
+use crate::neighbourhoods::is_device_sync_of;
 use crate::perspectives::utils::{prolog_get_first_binding, prolog_value_to_json_string};
 use crate::prolog_service::engine::PrologEngine;
 use crate::pubsub::{
     get_global_pubsub, NEIGHBOURHOOD_SIGNAL_TOPIC, PERSPECTIVE_LINK_ADDED_TOPIC,
     PERSPECTIVE_LINK_REMOVED_TOPIC, PERSPECTIVE_LINK_UPDATED_TOPIC,
-    PERSPECTIVE_SYNC_STATE_CHANGE_TOPIC, RUNTIME_NOTIFICATION_TRIGGERED_TOPIC,
+    PERSPECTIVE_SYNC_STATE_CHANGE_TOPIC,
 };
+use crate::runtime_service::notification_triggers;
 use crate::{db::Ad4mDb, types::*};
 use ad4m_client::literal::Literal;
 use chrono::DateTime;
@@ -133,6 +138,12 @@ pub struct Parameter {
     value: serde_json::Value,
 }
 
+impl Parameter {
+    pub fn new(name: String, value: serde_json::Value) -> Self {
+        Parameter { name, value }
+    }
+}
+
 #[derive(Clone)]
 pub struct PerspectiveInstance {
     pub persisted: Arc<Mutex<PerspectiveHandle>>,
@@ -328,6 +339,7 @@ impl PerspectiveInstance {
         })?;
 
         if !pending_ids.is_empty() {
+            let pending_diffs = self.outgoing_diff(&pending_diffs).await?;
             let mut link_language_lock = self.link_language.lock().await;
             if let Some(link_language) = link_language_lock.as_mut() {
                 log::info!("Committing {} pending diffs...", pending_ids.len());
@@ -375,12 +387,33 @@ impl PerspectiveInstance {
 
     async fn ensure_public_links_are_shared(&self) {
         let uuid = self.persisted.lock().await.uuid.clone();
+        let sync_key = match self.device_sync_key().await {
+            Ok(sync_key) => sync_key,
+            Err(e) => {
+                log::error!("Not sharing links of device sync {}: {:?}", uuid, e);
+                return;
+            }
+        };
         let mut link_language_guard = self.link_language.lock().await;
         if let Some(link_language) = link_language_guard.as_mut() {
             let mut local_links =
                 Ad4mDb::with_global_instance(|db| db.get_all_links(&uuid)).unwrap();
 
             local_links.retain(|(_, status)| status == &LinkStatus::Shared);
+            // Encrypted links are deterministic, so they can be compared to the remote ones
+            if let Some(sync_key) = &sync_key {
+                local_links = match local_links
+                    .into_iter()
+                    .map(|(link, status)| sync_key.encrypt_link(&link).map(|link| (link, status)))
+                    .collect::<Result<Vec<_>, AnyError>>()
+                {
+                    Ok(local_links) => local_links,
+                    Err(e) => {
+                        log::error!("Failed to encrypt links of device sync {}: {:?}", uuid, e);
+                        return;
+                    }
+                };
+            }
 
             let remote_links = match link_language.current_revision().await {
                 Ok(Some(_)) => {
@@ -453,6 +486,52 @@ impl PerspectiveInstance {
         }
     }
 
+    /// Moves the perspective to the neighbourhood at `url`, which uses another link language,
+    /// and commits all shared links to it. An `announcement` gets committed through the current
+    /// link language first, so the members still there learn where the neighbourhood went.
+    pub async fn switch_neighbourhood(
+        &self,
+        url: String,
+        neighbourhood: DecoratedNeighbourhoodExpression,
+        link_language: Language,
+        announcement: Option<Link>,
+    ) -> Result<(), AnyError> {
+        let mut handle = self.persisted.lock().await.clone();
+
+        if let Some(announcement) = announcement {
+            let link_expression: LinkExpression = create_signed_expression(announcement)?.into();
+            let diff = self
+                .outgoing_diff(&PerspectiveDiff::from_additions(vec![
+                    link_expression.clone()
+                ]))
+                .await?;
+            match self.link_language.lock().await.as_mut() {
+                Some(current) => {
+                    current.commit(diff).await?;
+                }
+                None => return Err(self.no_link_language_error().await),
+            }
+            Ad4mDb::with_global_instance(|db| {
+                db.add_link(&handle.uuid, &link_expression, &LinkStatus::Shared)
+            })?;
+            let decorated_diff =
+                DecoratedPerspectiveDiff::from_additions(vec![DecoratedLinkExpression::from((
+                    link_expression,
+                    LinkStatus::Shared,
+                ))]);
+            self.spawn_prolog_facts_update(decorated_diff.clone());
+            self.pubsub_publish_diff(decorated_diff).await;
+        }
+
+        handle.shared_url = Some(url);
+        handle.neighbourhood = Some(neighbourhood);
+        handle.state = PerspectiveState::LinkLanguageInstalledButNotSynced;
+        update_perspective(&handle).await.map_err(|e| anyhow!(e))?;
+        *self.link_language.lock().await = Some(link_language);
+        self.ensure_public_links_are_shared().await;
+        Ok(())
+    }
+
     pub async fn update_from_handle(&self, handle: PerspectiveHandle) {
         *self.persisted.lock().await = handle;
     }
@@ -470,6 +549,7 @@ impl PerspectiveInstance {
 
         let commit_result = if pending_ids.is_empty() {
             // No pending diffs, let's try
+            let outgoing = self.outgoing_diff(diff).await?;
             if let Some(link_language) = self.link_language.lock().await.as_mut() {
                 // Got lock on Link Language, no other commit running
                 if link_language.current_revision().await?.is_some() {
@@ -480,7 +560,7 @@ impl PerspectiveInstance {
                         self.immediate_commits_remaining.lock().await;
                     if *immediate_commits_remaining > 0 {
                         *immediate_commits_remaining -= 1;
-                        link_language.commit(diff.clone()).await
+                        link_language.commit(outgoing).await
                     } else {
                         Err(anyhow!("Debouncing commit burst"))
                     }
@@ -535,6 +615,18 @@ impl PerspectiveInstance {
 
     pub async fn diff_from_link_language(&self, diff: PerspectiveDiff) {
         let handle = self.persisted.lock().await.clone();
+        let diff = match self.device_sync_key().await {
+            Ok(Some(sync_key)) => sync_key.decrypt_diff(diff),
+            Ok(None) => diff,
+            Err(e) => {
+                log::error!(
+                    "Dropping diff for device sync {}, which we can't decrypt: {:?}",
+                    handle.uuid,
+                    e
+                );
+                return;
+            }
+        };
         if !diff.additions.is_empty() {
             Ad4mDb::with_global_instance(|db| {
                 db.add_many_links(&handle.uuid, diff.additions.clone(), &LinkStatus::Shared)
@@ -572,17 +664,21 @@ impl PerspectiveInstance {
     pub async fn telepresence_signal_from_link_language(&self, mut signal: PerspectiveExpression) {
         signal.verify_signatures();
         let handle = self.persisted.lock().await.clone();
+        let uuid = handle.uuid.clone();
         get_global_pubsub()
             .await
             .publish(
                 &NEIGHBOURHOOD_SIGNAL_TOPIC,
                 &serde_json::to_string(&NeighbourhoodSignalFilter {
                     perspective: handle,
-                    signal,
+                    signal: signal.clone(),
                 })
                 .unwrap(),
             )
             .await;
+        tokio::spawn(notification_triggers::telepresence_signal_received(
+            uuid, signal,
+        ));
     }
 
     pub async fn add_link(
@@ -1133,7 +1229,11 @@ impl PerspectiveInstance {
     fn all_notifications_for_perspective_id(uuid: String) -> Result<Vec<Notification>, AnyError> {
         Ok(Ad4mDb::with_global_instance(|db| db.get_notifications())?
             .into_iter()
-            .filter(|n| n.perspective_ids.contains(&uuid))
+            .filter(|n| {
+                n.granted
+                    && n.trigger_type() == Some(NotificationTriggerType::Prolog)
+                    && n.is_active_on(&uuid)
+            })
             .collect())
     }
 
@@ -1190,34 +1290,12 @@ impl PerspectiveInstance {
     ) {
         for (notification, matches) in match_map {
             if !matches.is_empty() {
-                let payload = TriggeredNotification {
-                    notification: notification.clone(),
-                    perspective_id: uuid.clone(),
-                    trigger_match: prolog_resolution_to_string(QueryResolution::Matches(matches)),
-                };
-
-                let message = serde_json::to_string(&payload).unwrap();
-
-                get_global_pubsub()
-                    .await
-                    .publish(&RUNTIME_NOTIFICATION_TRIGGERED_TOPIC, &message)
-                    .await;
-
-                if url::Url::parse(&notification.webhook_url).is_ok() {
-                    log::info!(
-                        "Notification webhook - posting to {:?}",
-                        notification.webhook_url
-                    );
-                    let client = reqwest::Client::new();
-                    let res = client
-                        .post(&notification.webhook_url)
-                        .bearer_auth(&notification.webhook_auth)
-                        .header("Content-Type", "application/json")
-                        .body(message.clone())
-                        .send()
-                        .await;
-                    log::info!("Notification webhook response: {:?}", res);
-                }
+                notification_triggers::publish_triggered_notification(
+                    notification,
+                    uuid.clone(),
+                    prolog_resolution_to_string(QueryResolution::Matches(matches)),
+                )
+                .await;
             }
         }
     }
@@ -1246,6 +1324,26 @@ impl PerspectiveInstance {
         Ok(())
     }
 
+    /// The key to encrypt links with, if this perspective syncs the agent's devices
+    async fn device_sync_key(&self) -> Result<Option<DeviceSyncKey>, AnyError> {
+        let neighbourhood = self.persisted.lock().await.neighbourhood.clone();
+        let did = AgentService::with_global_instance(|agent_service| agent_service.did.clone());
+        match (neighbourhood, did) {
+            (Some(neighbourhood), Some(did)) if is_device_sync_of(&neighbourhood, &did) => {
+                Ok(Some(device_sync::ensure_sync_key()?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// `diff` as it gets committed to the link language, encrypted for device syncs
+    async fn outgoing_diff(&self, diff: &PerspectiveDiff) -> Result<PerspectiveDiff, AnyError> {
+        match self.device_sync_key().await? {
+            Some(sync_key) => sync_key.encrypt_diff(diff),
+            None => Ok(diff.clone()),
+        }
+    }
+
     async fn no_link_language_error(&self) -> AnyError {
         let handle = self.persisted.lock().await.clone();
         anyhow!(
@@ -1592,15 +1690,13 @@ impl PerspectiveInstance {
                             let mut lock = crate::js_core::JS_CORE_HANDLE.lock().await;
 
                             if let Some(ref mut js) = *lock {
-                                let result = js.execute(format!(
-                                        r#"JSON.stringify(await core.callResolver("Query", "expression", {{ url: "{}" }}))"#,
-                                        s
+                                let result: JsResultType<Option<ExpressionRendered>> = js
+                                    .rpc(JsRpc::query(
+                                        "expression",
+                                        serde_json::json!({ "url": s }),
                                     ))
                                     .await?;
 
-                                let result: JsResultType<Option<ExpressionRendered>> =
-                                    serde_json::from_str(&result)?;
-
                                 match result {
                                     JsResultType::Ok(Some(expr)) => expr.data,
                                     JsResultType::Ok(None) | JsResultType::Error(_) => {
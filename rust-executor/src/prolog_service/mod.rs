@@ -3,8 +3,11 @@ use lazy_static::lazy_static;
 use scryer_prolog::machine::parsed_results::QueryResult;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::RwLock;
 
+use crate::metrics::PROLOG_QUERY_DURATION;
+
 pub(crate) mod engine;
 pub(crate) mod prolog_service_extension;
 
@@ -51,7 +54,9 @@ impl PrologService {
         let engine = engines
             .get(&engine_name)
             .ok_or_else(|| Error::msg("Engine not found"))?;
+        let started = Instant::now();
         let result = engine.run_query(query).await?;
+        PROLOG_QUERY_DURATION.observe(&[], started.elapsed());
         Ok(result)
     }
 
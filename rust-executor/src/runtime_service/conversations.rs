@@ -0,0 +1,582 @@
+//! Group conversations on top of 1:1 direct messages.
+//!
+//! Direct message languages only know a single recipient, so every message of a group is sent
+//! to each member through the outbox. Messages carry the conversation they belong to, with its
+//! name and members, so members learn about a conversation with its first message.
+//! Only the creator of a conversation can change its members.
+
+use base64::Engine;
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::direct_messages;
+use crate::agent::did;
+use crate::db::Ad4mDb;
+use crate::graphql::graphql_types::{
+    Conversation, ConversationMessage, Perspective, PerspectiveExpression,
+};
+use crate::types::{DecoratedExpressionProof, DecoratedLinkExpression, Link};
+
+/// Predicate of the link that carries a group message
+pub const GROUP_MESSAGE_PREDICATE: &str = "ad4m://group_message";
+const ENVELOPE_PREFIX: &str = "literal://string:";
+pub const DEFAULT_PAGE_SIZE: u32 = 50;
+pub const MAX_PAGE_SIZE: u32 = 200;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationHeader {
+    pub id: String,
+    pub name: String,
+    pub created_by: String,
+    pub members: Vec<String>,
+    pub encrypted: bool,
+}
+
+impl From<&Conversation> for ConversationHeader {
+    fn from(conversation: &Conversation) -> Self {
+        ConversationHeader {
+            id: conversation.id.clone(),
+            name: conversation.name.clone(),
+            created_by: conversation.created_by.clone(),
+            members: conversation.members.clone(),
+            encrypted: conversation.encrypted,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum GroupEnvelope {
+    #[serde(rename_all = "camelCase")]
+    Message {
+        conversation: ConversationHeader,
+        id: String,
+        reply_to: Option<String>,
+        sent_at: i64,
+        message: Perspective,
+    },
+    #[serde(rename_all = "camelCase")]
+    Members { conversation: ConversationHeader },
+    #[serde(rename_all = "camelCase")]
+    ReadReceipt {
+        conversation_id: String,
+        message_ids: Vec<String>,
+        read_at: i64,
+    },
+}
+
+/// Wraps an envelope in a perspective that direct message languages can carry
+pub fn pack(from: &str, envelope: &GroupEnvelope) -> Result<Perspective, AnyError> {
+    Ok(Perspective {
+        links: vec![DecoratedLinkExpression {
+            author: from.to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            data: Link {
+                source: "ad4m://self".to_string(),
+                predicate: Some(GROUP_MESSAGE_PREDICATE.to_string()),
+                target: format!(
+                    "{}{}",
+                    ENVELOPE_PREFIX,
+                    base64::engine::general_purpose::URL_SAFE_NO_PAD
+                        .encode(serde_json::to_vec(envelope)?)
+                ),
+            },
+            proof: DecoratedExpressionProof::default(),
+            status: None,
+        }],
+    })
+}
+
+fn decode_envelope(target: &str) -> Result<GroupEnvelope, AnyError> {
+    let encoded = target
+        .strip_prefix(ENVELOPE_PREFIX)
+        .ok_or(anyhow!("Invalid group message"))?;
+    let json = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(encoded)?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+/// The envelope of a group message, `None` for 1:1 messages
+pub fn unpack(message: &Perspective) -> Option<Result<GroupEnvelope, AnyError>> {
+    match message.links.as_slice() {
+        [link] if link.data.predicate.as_deref() == Some(GROUP_MESSAGE_PREDICATE) => {
+            Some(decode_envelope(&link.data.target))
+        }
+        _ => None,
+    }
+}
+
+/// The members a conversation has after `author` sent us `header`, or `None` if
+/// `author` is not allowed to post in it.
+/// Conversations we don't know yet are taken from members that include us,
+/// the members of known conversations only change when their creator says so.
+pub fn accepted_members(
+    author: &str,
+    me: &str,
+    known: Option<&Conversation>,
+    header: &ConversationHeader,
+) -> Option<Vec<String>> {
+    match known {
+        None => {
+            let includes = |did: &str| header.members.iter().any(|member| member == did);
+            (includes(author) && includes(me) && includes(&header.created_by))
+                .then(|| header.members.clone())
+        }
+        Some(conversation) if author == conversation.created_by => Some(header.members.clone()),
+        Some(conversation) => conversation
+            .members
+            .iter()
+            .any(|member| member == author)
+            .then(|| conversation.members.clone()),
+    }
+}
+
+pub fn page_size(limit: Option<i32>) -> u32 {
+    match limit {
+        Some(limit) => (limit.max(1) as u32).min(MAX_PAGE_SIZE),
+        None => DEFAULT_PAGE_SIZE,
+    }
+}
+
+fn normalized_members(members: Vec<String>, me: &str) -> Vec<String> {
+    let mut members: Vec<String> = members
+        .into_iter()
+        .map(|member| member.trim().to_string())
+        .filter(|member| !member.is_empty())
+        .chain(std::iter::once(me.to_string()))
+        .collect();
+    members.sort();
+    members.dedup();
+    members
+}
+
+fn get_conversation(id: &str) -> Result<Conversation, AnyError> {
+    Ad4mDb::with_global_instance(|db| db.get_conversation(id))?
+        .ok_or_else(|| anyhow!("Conversation {} not found", id))
+}
+
+/// Sends an envelope to every member but us, through the outbox so that members
+/// that are offline get it once they are back
+fn send_to_members(conversation: &Conversation, recipients: Vec<String>, envelope: GroupEnvelope) {
+    let me = did();
+    let encrypted = conversation.encrypted;
+    let data = match pack(&me, &envelope) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Failed to pack group message: {}", e);
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        for recipient in recipients.into_iter().filter(|recipient| *recipient != me) {
+            if let Err(e) =
+                direct_messages::send_message(recipient.clone(), data.clone(), encrypted).await
+            {
+                log::error!("Failed to queue group message for {}: {}", recipient, e);
+            }
+        }
+    });
+}
+
+pub fn create_conversation(
+    name: String,
+    members: Vec<String>,
+    encrypted: bool,
+) -> Result<Conversation, AnyError> {
+    let me = did();
+    let members = normalized_members(members, &me);
+    if name.trim().is_empty() {
+        return Err(anyhow!("Conversations need a name"));
+    }
+    if members.len() < 2 {
+        return Err(anyhow!("Conversations need at least one other member"));
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().timestamp();
+    Ad4mDb::with_global_instance(|db| {
+        db.add_conversation(&id, name.trim(), &me, &members, encrypted, now)
+    })?;
+
+    let conversation = get_conversation(&id)?;
+    send_to_members(
+        &conversation,
+        conversation.members.clone(),
+        GroupEnvelope::Members {
+            conversation: (&conversation).into(),
+        },
+    );
+    Ok(conversation)
+}
+
+/// Adds and removes members. Removed members are told, so they stop posting.
+pub fn update_members(
+    conversation_id: &str,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> Result<Conversation, AnyError> {
+    let me = did();
+    let conversation = get_conversation(conversation_id)?;
+    if conversation.created_by != me {
+        return Err(anyhow!(
+            "Only the creator of a conversation can change its members"
+        ));
+    }
+    if remove.contains(&me) {
+        return Err(anyhow!("The creator can't be removed from a conversation"));
+    }
+
+    let members: Vec<String> = normalized_members(
+        conversation.members.iter().cloned().chain(add).collect(),
+        &me,
+    )
+    .into_iter()
+    .filter(|member| !remove.contains(member))
+    .collect();
+
+    let now = chrono::Utc::now().timestamp();
+    Ad4mDb::with_global_instance(|db| db.set_conversation_members(conversation_id, &members, now))?;
+
+    let updated = get_conversation(conversation_id)?;
+    let mut recipients = conversation.members.clone();
+    recipients.extend(updated.members.iter().cloned());
+    recipients.sort();
+    recipients.dedup();
+    send_to_members(
+        &updated,
+        recipients,
+        GroupEnvelope::Members {
+            conversation: (&updated).into(),
+        },
+    );
+    Ok(updated)
+}
+
+/// ID under which every member stores a message. Envelopes carry the ID the author picked,
+/// binding it to the conversation and the author keeps a member from reusing the ID of
+/// someone else's message to suppress it.
+pub fn message_id(conversation_id: &str, author: &str, id: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [conversation_id, author, id] {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Errors unless `reply_to` is a message of the conversation
+fn check_reply_to(conversation_id: &str, reply_to: &str) -> Result<(), AnyError> {
+    match Ad4mDb::with_global_instance(|db| db.get_conversation_message(reply_to))? {
+        Some(replied) if replied.conversation_id == conversation_id => Ok(()),
+        _ => Err(anyhow!(
+            "Message {} not found in conversation {}",
+            reply_to,
+            conversation_id
+        )),
+    }
+}
+
+pub fn send_message(
+    conversation_id: &str,
+    message: Perspective,
+    reply_to: Option<String>,
+) -> Result<ConversationMessage, AnyError> {
+    let me = did();
+    let conversation = get_conversation(conversation_id)?;
+    if !conversation.members.contains(&me) {
+        return Err(anyhow!("Not a member of conversation {}", conversation_id));
+    }
+    if let Some(reply_to) = reply_to.as_ref() {
+        check_reply_to(conversation_id, reply_to)?;
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let envelope_id = uuid::Uuid::new_v4().to_string();
+    let sent = ConversationMessage {
+        id: message_id(conversation_id, &me, &envelope_id),
+        conversation_id: conversation_id.to_string(),
+        author: me,
+        message,
+        reply_to,
+        ..Default::default()
+    };
+    Ad4mDb::with_global_instance(|db| db.add_conversation_message(&sent, now, Some(now)))?;
+
+    send_to_members(
+        &conversation,
+        conversation.members.clone(),
+        GroupEnvelope::Message {
+            conversation: (&conversation).into(),
+            id: envelope_id,
+            reply_to: sent.reply_to.clone(),
+            sent_at: now,
+            message: sent.message.clone(),
+        },
+    );
+
+    Ad4mDb::with_global_instance(|db| db.get_conversation_message(&sent.id))?
+        .ok_or_else(|| anyhow!("Conversation message {} not found", sent.id))
+}
+
+/// Marks all messages of a conversation as read and sends read receipts to the other members.
+/// Returns how many messages got marked.
+pub fn mark_read(conversation_id: &str) -> Result<usize, AnyError> {
+    let conversation = get_conversation(conversation_id)?;
+    let now = chrono::Utc::now().timestamp();
+    let message_ids =
+        Ad4mDb::with_global_instance(|db| db.mark_conversation_read(conversation_id, now))?;
+    if !message_ids.is_empty() {
+        send_to_members(
+            &conversation,
+            conversation.members.clone(),
+            GroupEnvelope::ReadReceipt {
+                conversation_id: conversation_id.to_string(),
+                message_ids: message_ids.clone(),
+                read_at: now,
+            },
+        );
+    }
+    Ok(message_ids.len())
+}
+
+fn apply_header(
+    author: &str,
+    header: &ConversationHeader,
+    now: i64,
+) -> Result<Option<Conversation>, AnyError> {
+    let known = Ad4mDb::with_global_instance(|db| db.get_conversation(&header.id))?;
+    let members = match accepted_members(author, &did(), known.as_ref(), header) {
+        Some(members) => members,
+        None => return Ok(None),
+    };
+    match known {
+        None => Ad4mDb::with_global_instance(|db| {
+            db.add_conversation(
+                &header.id,
+                &header.name,
+                &header.created_by,
+                &members,
+                header.encrypted,
+                now,
+            )
+        })?,
+        Some(known) if known.members != members => Ad4mDb::with_global_instance(|db| {
+            db.set_conversation_members(&header.id, &members, now)
+        })?,
+        Some(_) => {}
+    }
+    Ok(Some(get_conversation(&header.id)?))
+}
+
+fn apply_envelope(author: &str, envelope: GroupEnvelope) -> Result<(), AnyError> {
+    let now = chrono::Utc::now().timestamp();
+    match envelope {
+        GroupEnvelope::Message {
+            conversation,
+            id,
+            reply_to,
+            sent_at,
+            message,
+        } => {
+            if apply_header(author, &conversation, now)?.is_none() {
+                return Err(anyhow!("{} is not a member of {}", author, conversation.id));
+            }
+            if let Some(reply_to) = reply_to.as_ref() {
+                check_reply_to(&conversation.id, reply_to)?;
+            }
+            let received = ConversationMessage {
+                id: message_id(&conversation.id, author, &id),
+                conversation_id: conversation.id,
+                author: author.to_string(),
+                message,
+                reply_to,
+                ..Default::default()
+            };
+            Ad4mDb::with_global_instance(|db| {
+                db.add_conversation_message(&received, sent_at, None)
+            })?;
+        }
+        GroupEnvelope::Members { conversation } => {
+            if apply_header(author, &conversation, now)?.is_none() {
+                return Err(anyhow!("{} is not a member of {}", author, conversation.id));
+            }
+        }
+        GroupEnvelope::ReadReceipt {
+            conversation_id,
+            message_ids,
+            read_at,
+        } => {
+            let conversation = get_conversation(&conversation_id)?;
+            if !conversation.members.iter().any(|member| member == author) {
+                return Err(anyhow!("{} is not a member of {}", author, conversation_id));
+            }
+            for message_id in message_ids {
+                let belongs =
+                    Ad4mDb::with_global_instance(|db| db.get_conversation_message(&message_id))?
+                        .is_some_and(|message| message.conversation_id == conversation_id);
+                if belongs {
+                    Ad4mDb::with_global_instance(|db| {
+                        db.add_read_receipt(&message_id, author, read_at)
+                    })?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Files a received direct message into its conversation if it is a group message.
+/// Receiving the same message twice is harmless, messages not signed by their author are dropped.
+pub fn received(message: &PerspectiveExpression) {
+    let envelope = match unpack(&message.data) {
+        Some(Ok(envelope)) => envelope,
+        Some(Err(e)) => {
+            log::warn!(
+                "Ignoring malformed group message from {}: {}",
+                message.author,
+                e
+            );
+            return;
+        }
+        None => return,
+    };
+    if !direct_messages::is_verified(message) {
+        log::warn!(
+            "Dropping group message with invalid signature from {}",
+            message.author
+        );
+        return;
+    }
+    if let Err(e) = apply_envelope(&message.author, envelope) {
+        log::warn!("Ignoring group message from {}: {}", message.author, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(created_by: &str, members: &[&str]) -> ConversationHeader {
+        ConversationHeader {
+            id: "c1".to_string(),
+            name: "Book club".to_string(),
+            created_by: created_by.to_string(),
+            members: members.iter().map(|m| m.to_string()).collect(),
+            encrypted: false,
+        }
+    }
+
+    fn conversation(created_by: &str, members: &[&str]) -> Conversation {
+        Conversation {
+            id: "c1".to_string(),
+            name: "Book club".to_string(),
+            created_by: created_by.to_string(),
+            members: members.iter().map(|m| m.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn envelopes_survive_packing() {
+        let envelope = GroupEnvelope::ReadReceipt {
+            conversation_id: "c1".to_string(),
+            message_ids: vec!["m1".to_string(), "m2".to_string()],
+            read_at: 100,
+        };
+        let packed = pack("did:test:alice", &envelope).unwrap();
+        match unpack(&packed) {
+            Some(Ok(GroupEnvelope::ReadReceipt {
+                conversation_id,
+                message_ids,
+                read_at,
+            })) => {
+                assert_eq!(conversation_id, "c1");
+                assert_eq!(message_ids, vec!["m1", "m2"]);
+                assert_eq!(read_at, 100);
+            }
+            other => panic!("unexpected envelope: {:?}", other),
+        }
+
+        assert!(unpack(&Perspective::default()).is_none());
+        let mut broken = packed.clone();
+        broken.links[0].data.target = "literal://string:not-json".to_string();
+        assert!(matches!(unpack(&broken), Some(Err(_))));
+    }
+
+    #[test]
+    fn new_conversations_need_us_and_the_author_as_members() {
+        let members = ["did:test:alice", "did:test:bob", "did:test:me"];
+        assert_eq!(
+            accepted_members(
+                "did:test:bob",
+                "did:test:me",
+                None,
+                &header("did:test:alice", &members)
+            ),
+            Some(members.iter().map(|m| m.to_string()).collect())
+        );
+        assert!(accepted_members(
+            "did:test:eve",
+            "did:test:me",
+            None,
+            &header("did:test:alice", &members)
+        )
+        .is_none());
+        assert!(accepted_members(
+            "did:test:alice",
+            "did:test:me",
+            None,
+            &header("did:test:alice", &["did:test:alice", "did:test:bob"])
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn only_the_creator_changes_members() {
+        let known = conversation(
+            "did:test:alice",
+            &["did:test:alice", "did:test:bob", "did:test:me"],
+        );
+        let grown = header(
+            "did:test:alice",
+            &[
+                "did:test:alice",
+                "did:test:bob",
+                "did:test:eve",
+                "did:test:me",
+            ],
+        );
+
+        assert_eq!(
+            accepted_members("did:test:alice", "did:test:me", Some(&known), &grown),
+            Some(grown.members.clone())
+        );
+        assert_eq!(
+            accepted_members("did:test:bob", "did:test:me", Some(&known), &grown),
+            Some(known.members.clone())
+        );
+        assert!(accepted_members("did:test:eve", "did:test:me", Some(&known), &grown).is_none());
+    }
+
+    #[test]
+    fn message_ids_are_scoped_to_conversation_and_author() {
+        let id = message_id("c1", "did:test:alice", "m1");
+        assert_eq!(id, message_id("c1", "did:test:alice", "m1"));
+        assert_ne!(id, message_id("c1", "did:test:eve", "m1"));
+        assert_ne!(id, message_id("c2", "did:test:alice", "m1"));
+        assert_ne!(
+            message_id("c1", "did:test:alice", "m1"),
+            message_id("c1", "did:test:alic", "em1")
+        );
+    }
+
+    #[test]
+    fn page_size_is_bounded() {
+        assert_eq!(page_size(None), DEFAULT_PAGE_SIZE);
+        assert_eq!(page_size(Some(10)), 10);
+        assert_eq!(page_size(Some(0)), 1);
+        assert_eq!(page_size(Some(10_000)), MAX_PAGE_SIZE);
+    }
+}
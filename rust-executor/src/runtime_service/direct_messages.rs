@@ -0,0 +1,249 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use tokio::sync::Notify;
+
+use super::{conversations, friend_requests};
+use crate::agent::{did, encryption};
+use crate::db::Ad4mDb;
+use crate::graphql::graphql_types::{Perspective, PerspectiveExpression, SentMessage};
+use crate::languages::LanguageController;
+use crate::types::{DecoratedExpressionProof, MessageDeliveryStatus};
+
+/// Messages are given up after this many failed attempts
+pub const MAX_DELIVERY_ATTEMPTS: i32 = 20;
+const BASE_RETRY_DELAY_SECS: i64 = 30;
+const MAX_RETRY_DELAY_SECS: i64 = 30 * 60;
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+lazy_static! {
+    static ref OUTBOX_WAKEUP: Arc<Notify> = Arc::new(Notify::new());
+}
+
+/// Backoff between delivery attempts: 30s, 1m, 2m, ... capped at half an hour,
+/// so a recipient coming back online gets their messages soon after
+pub fn retry_delay_secs(attempts: i32) -> i64 {
+    let exponent = attempts.saturating_sub(1).clamp(0, 16);
+    (BASE_RETRY_DELAY_SECS << exponent).min(MAX_RETRY_DELAY_SECS)
+}
+
+/// When to try again after `attempts` failed attempts, if at all
+pub fn next_attempt_at(attempts: i32, now: i64) -> Option<i64> {
+    if attempts >= MAX_DELIVERY_ATTEMPTS {
+        None
+    } else {
+        Some(now + retry_delay_secs(attempts))
+    }
+}
+
+/// Stand-in for the signed expression the direct message language creates on delivery
+fn unsent_expression(message: Perspective, now: i64) -> PerspectiveExpression {
+    PerspectiveExpression {
+        author: did(),
+        data: message,
+        proof: DecoratedExpressionProof::default(),
+        timestamp: chrono::DateTime::from_timestamp(now, 0)
+            .unwrap_or_default()
+            .to_rfc3339(),
+    }
+}
+
+/// Encrypts a message to the key the recipient published with their agent expression
+async fn encrypt_for(recipient: &str, message: &Perspective) -> Result<Perspective, AnyError> {
+    let key = LanguageController::agent_by_did(recipient)
+        .await?
+        .and_then(|agent| agent.encryption_key)
+        .ok_or_else(|| anyhow!("{} has not published an encryption key", recipient))?;
+    let public_key = encryption::verified_public_key(recipient, &key)?;
+    encryption::seal(&did(), message, &public_key)
+}
+
+async fn deliver(
+    message: &SentMessage,
+) -> Result<(MessageDeliveryStatus, PerspectiveExpression), AnyError> {
+    let data = if message.encrypted {
+        encrypt_for(&message.recipient, &message.message.data).await?
+    } else {
+        message.message.data.clone()
+    };
+    let delivery = LanguageController::send_direct_message(&message.recipient, &data).await?;
+    let status = MessageDeliveryStatus::from_str(&delivery.status).map_err(|e| anyhow!(e))?;
+    // The outbox keeps the plain message, with the proof of what was actually sent
+    let expression = PerspectiveExpression {
+        data: message.message.data.clone(),
+        ..delivery.expression
+    };
+    Ok((status, expression))
+}
+
+/// Tries to deliver a message from the outbox once and records the outcome
+async fn attempt_delivery(message: &SentMessage) -> Result<MessageDeliveryStatus, AnyError> {
+    match deliver(message).await {
+        Ok((status, expression)) => {
+            Ad4mDb::with_global_instance(|db| {
+                db.record_outbox_delivery(&message.id, &expression, status)
+            })?;
+            Ok(status)
+        }
+        Err(e) => {
+            let attempts = message.attempts + 1;
+            let next_attempt_at = next_attempt_at(attempts, chrono::Utc::now().timestamp());
+            log::warn!(
+                "Direct message {} to {} not delivered (attempt {}): {}",
+                message.id,
+                message.recipient,
+                attempts,
+                e
+            );
+            Ad4mDb::with_global_instance(|db| {
+                db.record_outbox_failure(&message.id, &e.to_string(), next_attempt_at)
+            })?;
+            Ok(match next_attempt_at {
+                Some(_) => MessageDeliveryStatus::Queued,
+                None => MessageDeliveryStatus::Failed,
+            })
+        }
+    }
+}
+
+/// Queues a message for `did` in the outbox and tries to deliver it right away.
+/// Messages that can't be delivered yet stay queued and are retried in the background.
+/// Encrypted messages are only sent once the recipient has published an encryption key.
+pub async fn send_message(
+    did: String,
+    message: Perspective,
+    encrypted: bool,
+) -> Result<SentMessage, AnyError> {
+    let now = chrono::Utc::now().timestamp();
+    let expression = unsent_expression(message, now);
+    // The first attempt is made right here, the outbox loop only picks the message up
+    // if this one is cut short
+    let id = Ad4mDb::with_global_instance(|db| {
+        db.queue_outbox_message(&did, &expression, encrypted, now, now + retry_delay_secs(1))
+    })?;
+    let queued = Ad4mDb::with_global_instance(|db| db.get_outbox_message(&id))?
+        .ok_or_else(|| anyhow!("Outbox message {} not found", id))?;
+
+    attempt_delivery(&queued).await?;
+
+    Ad4mDb::with_global_instance(|db| db.get_outbox_message(&id))?
+        .ok_or_else(|| anyhow!("Outbox message {} not found", id))
+}
+
+/// Hearing from an agent means they are online, so messages queued for them are sent right away
+pub fn recipient_online(did: &str) {
+    let now = chrono::Utc::now().timestamp();
+    match Ad4mDb::with_global_instance(|db| db.retry_outbox_messages_to(did, now)) {
+        Ok(0) => {}
+        Ok(_) => OUTBOX_WAKEUP.notify_one(),
+        Err(e) => log::error!("Failed to reschedule queued messages to {}: {}", did, e),
+    }
+}
+
+/// Checks the signature of a received message, which covers the message as it was sent,
+/// and decrypts it. The outcome of the check stays in the proof, see [`is_verified`].
+pub fn verify_and_decrypt(
+    mut message: PerspectiveExpression,
+) -> Result<PerspectiveExpression, AnyError> {
+    message.verify_signatures();
+    encryption::decrypt_message(message)
+}
+
+/// Whether a received message was signed by its author. Direct message languages
+/// don't check this, so anything acting on the author has to.
+pub fn is_verified(message: &PerspectiveExpression) -> bool {
+    message.proof.valid == Some(true)
+}
+
+/// Keeps a received message in the local inbox, returns false if we had it already
+pub fn store_received(message: &PerspectiveExpression) -> bool {
+    let now = chrono::Utc::now().timestamp();
+    match Ad4mDb::with_global_instance(|db| db.add_to_inbox(message, now)) {
+        Ok(new) => new,
+        Err(e) => {
+            log::error!("Failed to store received message: {}", e);
+            false
+        }
+    }
+}
+
+/// Received messages, optionally only those from `author`.
+/// Syncs with the inbox of our direct message language first, but still answers
+/// from the local copy if the language is not available.
+pub async fn inbox(author: Option<String>) -> Result<Vec<PerspectiveExpression>, AnyError> {
+    match LanguageController::direct_message_inbox(author.clone()).await {
+        Ok(messages) => {
+            for message in messages {
+                match verify_and_decrypt(message) {
+                    Ok(message) => {
+                        if store_received(&message) {
+                            conversations::received(&message);
+                            friend_requests::received(&message).await;
+                        }
+                    }
+                    Err(e) => log::warn!("Skipping inbox message that failed to decrypt: {}", e),
+                }
+            }
+        }
+        Err(e) => log::warn!(
+            "Direct message language unavailable, answering from the local inbox: {}",
+            e
+        ),
+    }
+    Ad4mDb::with_global_instance(|db| db.get_inbox(author.as_deref()))
+}
+
+async fn deliver_due() {
+    let now = chrono::Utc::now().timestamp();
+    let due = match Ad4mDb::with_global_instance(|db| db.get_due_outbox_messages(now)) {
+        Ok(due) => due,
+        Err(e) => {
+            log::error!("Failed to load due outbox messages: {}", e);
+            return;
+        }
+    };
+
+    for message in due {
+        if let Err(e) = attempt_delivery(&message).await {
+            log::error!("Failed to record delivery of message {}: {}", message.id, e);
+        }
+    }
+}
+
+/// Retries queued messages, on a fixed interval and whenever a recipient shows up online.
+/// Messages queued before a restart are picked up on the first run.
+pub async fn run_outbox_loop() {
+    loop {
+        deliver_due().await;
+        tokio::select! {
+            _ = OUTBOX_WAKEUP.notified() => {},
+            _ = tokio::time::sleep(POLL_INTERVAL) => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_backs_off_up_to_half_an_hour() {
+        assert_eq!(retry_delay_secs(1), 30);
+        assert_eq!(retry_delay_secs(2), 60);
+        assert_eq!(retry_delay_secs(3), 120);
+        assert_eq!(
+            retry_delay_secs(MAX_DELIVERY_ATTEMPTS),
+            MAX_RETRY_DELAY_SECS
+        );
+    }
+
+    #[test]
+    fn messages_give_up_after_max_attempts() {
+        assert_eq!(next_attempt_at(1, 100), Some(130));
+        assert!(next_attempt_at(MAX_DELIVERY_ATTEMPTS - 1, 0).is_some());
+        assert_eq!(next_attempt_at(MAX_DELIVERY_ATTEMPTS, 0), None);
+    }
+}
@@ -0,0 +1,418 @@
+//! Friendship handshake over direct messages.
+//!
+//! Adding a friend sends them a friend request. We only count as friends once both sides have
+//! agreed, either by one side accepting the other's request or by both asking each other.
+//! Removing a friend or rejecting a request tells the other side, so both agree on the outcome.
+
+use base64::Engine;
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use serde::{Deserialize, Serialize};
+
+use super::direct_messages;
+use crate::db::Ad4mDb;
+use crate::graphql::graphql_types::{Perspective, PerspectiveExpression};
+use crate::pubsub::{get_global_pubsub, RUNTIME_FRIEND_REQUEST_RECEIVED_TOPIC};
+use crate::types::{
+    DecoratedExpressionProof, DecoratedLinkExpression, FriendRequest, FriendRequestDirection,
+    FriendRequestState, Link,
+};
+
+/// Predicate of the link that carries a friend request message
+pub const FRIEND_REQUEST_PREDICATE: &str = "ad4m://friend_request";
+const ENVELOPE_PREFIX: &str = "literal://string:";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum FriendRequestMessage {
+    Request { message: Option<String> },
+    Accept,
+    Reject,
+}
+
+/// Our side of the handshake after receiving a message, and what to answer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub direction: FriendRequestDirection,
+    pub state: FriendRequestState,
+    pub reply: Option<FriendRequestMessage>,
+}
+
+/// What receiving `message` does to the handshake in `current`, `None` if it is ignored
+pub fn on_received(
+    current: Option<(FriendRequestDirection, FriendRequestState)>,
+    message: &FriendRequestMessage,
+) -> Option<Transition> {
+    use FriendRequestDirection::*;
+    use FriendRequestState::*;
+
+    let transition = |direction, state, reply| {
+        Some(Transition {
+            direction,
+            state,
+            reply,
+        })
+    };
+
+    match (message, current) {
+        // Both asked each other
+        (FriendRequestMessage::Request { .. }, Some((Outgoing, Pending))) => {
+            transition(Outgoing, Accepted, Some(FriendRequestMessage::Accept))
+        }
+        // They lost track of our friendship, confirm it again
+        (FriendRequestMessage::Request { .. }, Some((direction, Accepted))) => {
+            transition(direction, Accepted, Some(FriendRequestMessage::Accept))
+        }
+        (FriendRequestMessage::Request { .. }, _) => transition(Incoming, Pending, None),
+        (FriendRequestMessage::Accept, Some((Outgoing, Pending))) => {
+            transition(Outgoing, Accepted, None)
+        }
+        (FriendRequestMessage::Accept, _) => None,
+        // Our request got turned down, or they ended the friendship
+        (FriendRequestMessage::Reject, Some((Outgoing, Pending))) => {
+            transition(Outgoing, Rejected, None)
+        }
+        (FriendRequestMessage::Reject, Some((direction, Accepted))) => {
+            transition(direction, Rejected, None)
+        }
+        (FriendRequestMessage::Reject, _) => None,
+    }
+}
+
+pub fn pack(from: &str, message: &FriendRequestMessage) -> Result<Perspective, AnyError> {
+    Ok(Perspective {
+        links: vec![DecoratedLinkExpression {
+            author: from.to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            data: Link {
+                source: "ad4m://self".to_string(),
+                predicate: Some(FRIEND_REQUEST_PREDICATE.to_string()),
+                target: format!(
+                    "{}{}",
+                    ENVELOPE_PREFIX,
+                    base64::engine::general_purpose::URL_SAFE_NO_PAD
+                        .encode(serde_json::to_vec(message)?)
+                ),
+            },
+            proof: DecoratedExpressionProof::default(),
+            status: None,
+        }],
+    })
+}
+
+fn decode_message(target: &str) -> Result<FriendRequestMessage, AnyError> {
+    let encoded = target
+        .strip_prefix(ENVELOPE_PREFIX)
+        .ok_or(anyhow!("Invalid friend request"))?;
+    let json = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(encoded)?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+/// The friend request message carried by a direct message, `None` for other messages
+pub fn unpack(message: &Perspective) -> Option<Result<FriendRequestMessage, AnyError>> {
+    match message.links.as_slice() {
+        [link] if link.data.predicate.as_deref() == Some(FRIEND_REQUEST_PREDICATE) => {
+            Some(decode_message(&link.data.target))
+        }
+        _ => None,
+    }
+}
+
+fn current_state(did: &str) -> Result<Option<FriendRequest>, AnyError> {
+    Ad4mDb::with_global_instance(|db| db.get_friend_request(did))
+}
+
+fn handshake(request: &FriendRequest) -> Option<(FriendRequestDirection, FriendRequestState)> {
+    match (request.direction(), request.state()) {
+        (Some(direction), Some(state)) => Some((direction, state)),
+        _ => None,
+    }
+}
+
+/// True once both sides have agreed to be friends
+pub fn is_mutual_friend(did: &str) -> bool {
+    match current_state(did) {
+        Ok(Some(request)) => request.state() == Some(FriendRequestState::Accepted),
+        Ok(None) => false,
+        Err(e) => {
+            log::error!("Failed to load friend request of {}: {}", did, e);
+            false
+        }
+    }
+}
+
+fn record(
+    did: &str,
+    direction: FriendRequestDirection,
+    state: FriendRequestState,
+    message: Option<&str>,
+) -> Result<FriendRequest, AnyError> {
+    let now = chrono::Utc::now().timestamp();
+    Ad4mDb::with_global_instance(|db| {
+        db.set_friend_request(did, direction, state, message, now)?;
+        match state {
+            FriendRequestState::Accepted => {
+                if !db.get_all_friends()?.iter().any(|friend| friend == did) {
+                    db.add_friends(vec![did.to_string()])?;
+                }
+            }
+            FriendRequestState::Rejected => db.remove_friends(vec![did.to_string()])?,
+            FriendRequestState::Pending => {}
+        }
+        Ok(())
+    })?;
+    current_state(did)?.ok_or_else(|| anyhow!("Friend request of {} not found", did))
+}
+
+/// Sends through the outbox, which retries until the other side can be reached
+fn send(did: &str, message: FriendRequestMessage) {
+    let data = match pack(&crate::agent::did(), &message) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Failed to pack friend request message: {}", e);
+            return;
+        }
+    };
+    let recipient = did.to_string();
+    tokio::spawn(async move {
+        if let Err(e) = direct_messages::send_message(recipient.clone(), data, false).await {
+            log::error!(
+                "Failed to queue friend request message for {}: {}",
+                recipient,
+                e
+            );
+        }
+    });
+}
+
+/// Asks `did` to become friends. If they asked us already, this accepts their request.
+pub fn send_request(did: &str, message: Option<String>) -> Result<FriendRequest, AnyError> {
+    if did == crate::agent::did() {
+        return Err(anyhow!("Can't send a friend request to ourselves"));
+    }
+    let current = current_state(did)?;
+    match (current.as_ref().and_then(handshake), current) {
+        (Some((FriendRequestDirection::Incoming, FriendRequestState::Pending)), _) => accept(did),
+        (Some((_, FriendRequestState::Accepted)), Some(friends)) => Ok(friends),
+        _ => {
+            let request = record(
+                did,
+                FriendRequestDirection::Outgoing,
+                FriendRequestState::Pending,
+                message.as_deref(),
+            )?;
+            send(did, FriendRequestMessage::Request { message });
+            Ok(request)
+        }
+    }
+}
+
+fn pending_incoming(did: &str) -> Result<(), AnyError> {
+    match current_state(did)?.as_ref().and_then(handshake) {
+        Some((FriendRequestDirection::Incoming, FriendRequestState::Pending)) => Ok(()),
+        _ => Err(anyhow!("No pending friend request from {}", did)),
+    }
+}
+
+pub fn accept(did: &str) -> Result<FriendRequest, AnyError> {
+    pending_incoming(did)?;
+    let request = record(
+        did,
+        FriendRequestDirection::Incoming,
+        FriendRequestState::Accepted,
+        None,
+    )?;
+    send(did, FriendRequestMessage::Accept);
+    Ok(request)
+}
+
+pub fn reject(did: &str) -> Result<FriendRequest, AnyError> {
+    pending_incoming(did)?;
+    let request = record(
+        did,
+        FriendRequestDirection::Incoming,
+        FriendRequestState::Rejected,
+        None,
+    )?;
+    send(did, FriendRequestMessage::Reject);
+    Ok(request)
+}
+
+/// Forgets the handshake with `did` and tells them, if there was one
+pub fn unfriend(did: &str) -> Result<(), AnyError> {
+    let current = current_state(did)?;
+    let removed = Ad4mDb::with_global_instance(|db| db.remove_friend_request(did))?;
+    let ongoing = matches!(
+        current.as_ref().and_then(handshake),
+        Some((
+            _,
+            FriendRequestState::Pending | FriendRequestState::Accepted
+        ))
+    );
+    if removed && ongoing {
+        send(did, FriendRequestMessage::Reject);
+    }
+    Ok(())
+}
+
+async fn apply(author: &str, message: FriendRequestMessage) -> Result<(), AnyError> {
+    let current = current_state(author)?;
+    let transition = match on_received(current.as_ref().and_then(handshake), &message) {
+        Some(transition) => transition,
+        None => return Ok(()),
+    };
+
+    let note = match &message {
+        FriendRequestMessage::Request { message } => message.as_deref(),
+        _ => None,
+    };
+    let request = record(author, transition.direction, transition.state, note)?;
+    if let Some(reply) = transition.reply {
+        send(author, reply);
+    }
+
+    let new_request = transition.direction == FriendRequestDirection::Incoming
+        && transition.state == FriendRequestState::Pending;
+    if new_request {
+        get_global_pubsub()
+            .await
+            .publish(
+                &RUNTIME_FRIEND_REQUEST_RECEIVED_TOPIC,
+                &serde_json::to_string(&request)?,
+            )
+            .await;
+    }
+    Ok(())
+}
+
+/// Updates the handshake with the author of a received direct message if it is a friend request.
+/// Messages not signed by their author are dropped, so nobody can answer in someone else's name.
+pub async fn received(message: &PerspectiveExpression) {
+    let request = match unpack(&message.data) {
+        Some(Ok(request)) => request,
+        Some(Err(e)) => {
+            log::warn!(
+                "Ignoring malformed friend request from {}: {}",
+                message.author,
+                e
+            );
+            return;
+        }
+        None => return,
+    };
+    if !direct_messages::is_verified(message) {
+        log::warn!(
+            "Dropping friend request message with invalid signature from {}",
+            message.author
+        );
+        return;
+    }
+    if let Err(e) = apply(&message.author, request).await {
+        log::error!(
+            "Failed to handle friend request from {}: {}",
+            message.author,
+            e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use FriendRequestDirection::*;
+    use FriendRequestState::*;
+
+    fn request() -> FriendRequestMessage {
+        FriendRequestMessage::Request {
+            message: Some("Hi!".to_string()),
+        }
+    }
+
+    fn state(
+        transition: Option<Transition>,
+    ) -> Option<(FriendRequestDirection, FriendRequestState)> {
+        transition.map(|t| (t.direction, t.state))
+    }
+
+    #[test]
+    fn requests_become_pending_until_answered() {
+        assert_eq!(
+            state(on_received(None, &request())),
+            Some((Incoming, Pending))
+        );
+        assert_eq!(
+            state(on_received(Some((Incoming, Rejected)), &request())),
+            Some((Incoming, Pending))
+        );
+        assert_eq!(on_received(None, &request()).unwrap().reply, None);
+    }
+
+    #[test]
+    fn asking_each_other_makes_friends() {
+        let transition = on_received(Some((Outgoing, Pending)), &request()).unwrap();
+        assert_eq!(
+            (transition.direction, transition.state),
+            (Outgoing, Accepted)
+        );
+        assert_eq!(transition.reply, Some(FriendRequestMessage::Accept));
+
+        let again = on_received(Some((Incoming, Accepted)), &request()).unwrap();
+        assert_eq!((again.direction, again.state), (Incoming, Accepted));
+        assert_eq!(again.reply, Some(FriendRequestMessage::Accept));
+    }
+
+    #[test]
+    fn answers_only_count_for_our_own_requests() {
+        let accept = FriendRequestMessage::Accept;
+        let reject = FriendRequestMessage::Reject;
+        assert_eq!(
+            state(on_received(Some((Outgoing, Pending)), &accept)),
+            Some((Outgoing, Accepted))
+        );
+        assert_eq!(
+            state(on_received(Some((Outgoing, Pending)), &reject)),
+            Some((Outgoing, Rejected))
+        );
+        assert!(on_received(None, &accept).is_none());
+        assert!(on_received(Some((Incoming, Pending)), &accept).is_none());
+        assert!(on_received(Some((Outgoing, Rejected)), &accept).is_none());
+        assert!(on_received(None, &reject).is_none());
+        assert!(on_received(Some((Incoming, Pending)), &reject).is_none());
+    }
+
+    #[test]
+    fn rejecting_ends_a_friendship() {
+        assert_eq!(
+            state(on_received(
+                Some((Incoming, Accepted)),
+                &FriendRequestMessage::Reject
+            )),
+            Some((Incoming, Rejected))
+        );
+    }
+
+    #[test]
+    fn forged_answers_are_not_trusted() {
+        let mut message = PerspectiveExpression {
+            author: "did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK".to_string(),
+            data: pack("did:test:alice", &FriendRequestMessage::Accept).unwrap(),
+            proof: DecoratedExpressionProof {
+                key: "#primary".to_string(),
+                signature: "00".repeat(64),
+                valid: Some(true),
+                invalid: Some(false),
+                retired_key: None,
+            },
+            timestamp: "2024-01-01T00:00:00Z".to_string(),
+        };
+        message.verify_signatures();
+        assert!(!direct_messages::is_verified(&message));
+    }
+
+    #[test]
+    fn messages_survive_packing() {
+        let packed = pack("did:test:alice", &request()).unwrap();
+        assert_eq!(unpack(&packed).unwrap().unwrap(), request());
+        assert!(unpack(&Perspective::default()).is_none());
+    }
+}
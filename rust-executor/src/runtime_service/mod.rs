@@ -1,6 +1,15 @@
 use std::io::Read;
 use std::{fs::File, sync::Mutex};
+pub(crate) mod conversations;
+pub(crate) mod direct_messages;
+pub(crate) mod friend_requests;
+pub(crate) mod notification_channels;
+pub(crate) mod notification_throttle;
+pub(crate) mod notification_triggers;
 pub(crate) mod runtime_service_extension;
+pub(crate) mod scheduler;
+pub(crate) mod web_of_trust;
+pub(crate) mod webhooks;
 use std::sync::Arc;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,7 +34,24 @@ use serde::{Deserialize, Serialize};
 
 use crate::graphql::graphql_types::{ExceptionInfo, ExceptionType, NotificationInput};
 use crate::pubsub::{get_global_pubsub, EXCEPTION_OCCURRED_TOPIC};
+use crate::types::{Notification, NotificationTriggerType};
 use crate::{agent::did, db::Ad4mDb, graphql::graphql_types::SentMessage};
+use std::str::FromStr;
+
+/// Rejects unknown trigger types, channels that lack what they need for delivery
+/// and inconsistent throttle settings
+pub fn validate_notification_input(input: &NotificationInput) -> Result<(), String> {
+    if let Some(trigger_type) = input.trigger_type.as_ref() {
+        NotificationTriggerType::from_str(trigger_type)?;
+    }
+    for channel in input.channels.iter().flatten() {
+        notification_channels::validate_channel(&channel.clone().into())?;
+    }
+    if let Some(throttle) = input.throttle.as_ref() {
+        notification_throttle::validate_throttle(&throttle.clone().into())?;
+    }
+    Ok(())
+}
 
 lazy_static! {
     static ref RUNTIME_INSTANCE: Arc<Mutex<Option<RuntimeService>>> = Arc::new(Mutex::new(None));
@@ -116,35 +142,31 @@ impl RuntimeService {
             .unwrap_or_default()
     }
 
-    pub fn add_friend(&self, friends: Vec<String>) {
-        let _ =
-            Ad4mDb::with_global_instance(|db| db.add_friends(friends)).map_err(|e| e.to_string());
-    }
-
     pub fn remove_friend(&self, friend_to_remove: Vec<String>) {
         let _ = Ad4mDb::with_global_instance(|db| db.remove_friends(friend_to_remove))
             .map_err(|e| e.to_string());
     }
 
-    pub fn get_outbox(&self) -> Vec<SentMessage> {
-        Ad4mDb::with_global_instance(|db| db.get_all_from_outbox())
+    pub fn get_outbox(&self, recipient: Option<&str>) -> Vec<SentMessage> {
+        Ad4mDb::with_global_instance(|db| db.get_all_from_outbox(recipient))
             .map_err(|e| e.to_string())
             .unwrap_or_default()
     }
 
-    pub fn add_message_to_outbox(&self, message: SentMessage) {
-        let _ = Ad4mDb::with_global_instance(|db| {
-            db.add_to_outbox(&message.message, message.recipient)
-        })
-        .map_err(|e| e.to_string());
-    }
-
     pub async fn request_install_notification(
         notification_input: NotificationInput,
+        installer_token_hash: Option<String>,
     ) -> Result<String, String> {
-        let notification_id =
-            Ad4mDb::with_global_instance(|db| db.add_notification(notification_input))
-                .map_err(|e| e.to_string())?;
+        validate_notification_input(&notification_input)?;
+
+        let notification_id = Ad4mDb::with_global_instance(|db| {
+            let id = db.add_notification(notification_input)?;
+            if let Some(installer_token_hash) = &installer_token_hash {
+                db.set_notification_installer(&id, installer_token_hash)?;
+            }
+            Ok::<_, rusqlite::Error>(id)
+        })
+        .map_err(|e| e.to_string())?;
 
         let notification =
             Ad4mDb::with_global_instance(|db| db.get_notification(notification_id.clone()))
@@ -158,7 +180,7 @@ impl RuntimeService {
                 notification.app_name
             ),
             r#type: ExceptionType::InstallNotificationRequest,
-            addon: Some(serde_json::to_string(&notification).unwrap()),
+            addon: Some(notification.public_json().to_string()),
         };
 
         get_global_pubsub()
@@ -171,4 +193,95 @@ impl RuntimeService {
 
         Ok(notification_id)
     }
+
+    /// Replaces the notification with the given input. It has to be granted again afterwards.
+    pub fn update_notification(id: String, input: NotificationInput) -> Result<bool, String> {
+        validate_notification_input(&input)?;
+
+        let existing = Ad4mDb::with_global_instance(|db| db.get_notification(id.clone()))
+            .map_err(|e| e.to_string())?
+            .ok_or("Notification with given id not found")?;
+        let notification =
+            Notification::from_input_and_id(id.clone(), input).keeping_secrets_of(&existing);
+
+        Ad4mDb::with_global_instance(|db| db.update_notification(id, &notification))
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphql::graphql_types::NotificationChannelInput;
+
+    fn notification_input(webhook_auth: &str, channel_secret: Option<&str>) -> NotificationInput {
+        NotificationInput {
+            description: "".to_string(),
+            app_name: "".to_string(),
+            app_url: "".to_string(),
+            app_icon_path: "".to_string(),
+            trigger: "*".to_string(),
+            perspective_ids: vec![],
+            webhook_url: "https://example.com/webhook".to_string(),
+            webhook_auth: webhook_auth.to_string(),
+            trigger_type: Some(NotificationTriggerType::DirectMessage.to_string()),
+            channels: Some(vec![NotificationChannelInput {
+                channel_type: "ntfy".to_string(),
+                url: "https://ntfy.example.com/topic".to_string(),
+                recipient: None,
+                sender: None,
+                username: None,
+                secret: channel_secret.map(String::from),
+                title_template: None,
+                body_template: None,
+            }]),
+            throttle: None,
+        }
+    }
+
+    #[test]
+    fn updates_without_secrets_keep_the_stored_ones() {
+        Ad4mDb::init_global_instance(":memory:").unwrap();
+        let id = Ad4mDb::with_global_instance(|db| {
+            db.add_notification(notification_input("webhook-token", Some("ntfy-token")))
+        })
+        .unwrap();
+        let installed = Ad4mDb::with_global_instance(|db| db.get_notification(id.clone()))
+            .unwrap()
+            .unwrap();
+
+        // What an app reading the notification can send back
+        let mut input = notification_input("", None);
+        input.description = "Updated".to_string();
+        assert!(RuntimeService::update_notification(id.clone(), input).unwrap());
+
+        let updated = Ad4mDb::with_global_instance(|db| db.get_notification(id.clone()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.description, "Updated");
+        assert_eq!(updated.webhook_auth, "webhook-token");
+        assert_eq!(
+            updated.webhook_signing_secret,
+            installed.webhook_signing_secret
+        );
+        assert_eq!(updated.channels[0].secret.as_deref(), Some("ntfy-token"));
+
+        // New secrets still replace the stored ones
+        let input = notification_input("new-token", Some("new-ntfy-token"));
+        assert!(RuntimeService::update_notification(id.clone(), input).unwrap());
+        let updated = Ad4mDb::with_global_instance(|db| db.get_notification(id.clone()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.webhook_auth, "new-token");
+        assert_eq!(
+            updated.channels[0].secret.as_deref(),
+            Some("new-ntfy-token")
+        );
+
+        assert!(RuntimeService::update_notification(
+            "unknown".to_string(),
+            notification_input("", None)
+        )
+        .is_err());
+    }
 }
@@ -0,0 +1,478 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::types::{NotificationChannel, TriggeredNotification};
+
+pub const EMAIL_CHANNEL: &str = "email";
+pub const NTFY_CHANNEL: &str = "ntfy";
+pub const MATRIX_CHANNEL: &str = "matrix";
+
+const DEFAULT_TITLE_TEMPLATE: &str = "{{appName}}";
+const DEFAULT_BODY_TEMPLATE: &str = "{{description}}";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+lazy_static! {
+    static ref TEMPLATE_VARIABLE: Regex = Regex::new(r"\{\{\s*([A-Za-z0-9_]+)\s*\}\}").unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedMessage {
+    pub title: String,
+    pub body: String,
+}
+
+/// Checks that a channel has everything its type needs for delivery
+pub fn validate_channel(channel: &NotificationChannel) -> Result<(), String> {
+    let required: Vec<(&str, &Option<String>)> = match channel.channel_type.as_str() {
+        EMAIL_CHANNEL => vec![
+            ("recipient", &channel.recipient),
+            ("sender", &channel.sender),
+        ],
+        NTFY_CHANNEL => vec![],
+        MATRIX_CHANNEL => vec![
+            ("recipient", &channel.recipient),
+            ("secret", &channel.secret),
+        ],
+        other => return Err(format!("Unknown notification channel type: {}", other)),
+    };
+
+    url::Url::parse(&channel.url)
+        .map_err(|e| format!("Invalid {} channel URL: {}", channel.channel_type, e))?;
+
+    for (field, value) in required {
+        if value.as_deref().unwrap_or_default().is_empty() {
+            return Err(format!(
+                "{} notification channel needs a {}",
+                channel.channel_type, field
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Replaces `{{Variable}}` placeholders, unknown variables render empty
+pub fn render_template(template: &str, variables: &BTreeMap<String, String>) -> String {
+    TEMPLATE_VARIABLE
+        .replace_all(template, |captures: &regex::Captures| {
+            variables.get(&captures[1]).cloned().unwrap_or_default()
+        })
+        .to_string()
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// One set of template variables per match: Prolog triggers match a list
+/// of variable bindings, the other trigger types a single JSON object.
+pub fn template_variables(triggered: &TriggeredNotification) -> Vec<BTreeMap<String, String>> {
+    let mut base = BTreeMap::new();
+    base.insert(
+        "description".to_string(),
+        triggered.notification.description.clone(),
+    );
+    base.insert(
+        "appName".to_string(),
+        triggered.notification.app_name.clone(),
+    );
+    base.insert(
+        "perspectiveId".to_string(),
+        triggered.perspective_id.clone(),
+    );
+    base.insert("match".to_string(), triggered.trigger_match.clone());
+
+    let binding_sets = match serde_json::from_str::<Value>(&triggered.trigger_match) {
+        Ok(Value::Array(matches)) => matches,
+        Ok(object @ Value::Object(_)) => vec![object],
+        _ => vec![],
+    };
+
+    if binding_sets.is_empty() {
+        return vec![base];
+    }
+
+    binding_sets
+        .iter()
+        .map(|bindings| {
+            let mut variables = base.clone();
+            if let Value::Object(bindings) = bindings {
+                for (name, value) in bindings {
+                    variables.insert(name.clone(), value_to_string(value));
+                }
+            }
+            variables
+        })
+        .collect()
+}
+
+/// Renders the title from the first match and one body line per match
+pub fn render_message(
+    channel: &NotificationChannel,
+    triggered: &TriggeredNotification,
+) -> RenderedMessage {
+    let variables = template_variables(triggered);
+    let title_template = channel
+        .title_template
+        .as_deref()
+        .unwrap_or(DEFAULT_TITLE_TEMPLATE);
+    let body_template = channel
+        .body_template
+        .as_deref()
+        .unwrap_or(DEFAULT_BODY_TEMPLATE);
+
+    let mut lines: Vec<String> = variables
+        .iter()
+        .map(|v| render_template(body_template, v))
+        .collect();
+    lines.dedup();
+
+    RenderedMessage {
+        title: render_template(title_template, &variables[0]),
+        body: lines.join("\n"),
+    }
+}
+
+async fn send_email(
+    channel: &NotificationChannel,
+    message: &RenderedMessage,
+) -> Result<(), AnyError> {
+    let sender: Mailbox = channel.sender.clone().unwrap_or_default().parse()?;
+    let recipient: Mailbox = channel.recipient.clone().unwrap_or_default().parse()?;
+    let email = Message::builder()
+        .from(sender)
+        .to(recipient)
+        .subject(message.title.clone())
+        .body(message.body.clone())?;
+
+    let mut transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(&channel.url)?
+        .timeout(Some(REQUEST_TIMEOUT));
+    if let (Some(username), Some(secret)) = (&channel.username, &channel.secret) {
+        transport = transport.credentials(Credentials::new(username.clone(), secret.clone()));
+    }
+
+    transport.build().send(email).await?;
+    Ok(())
+}
+
+async fn send_ntfy(
+    client: &reqwest::Client,
+    channel: &NotificationChannel,
+    message: &RenderedMessage,
+) -> Result<(), AnyError> {
+    let mut request = client
+        .post(&channel.url)
+        .header("Title", message.title.clone())
+        .timeout(REQUEST_TIMEOUT)
+        .body(message.body.clone());
+    if let Some(secret) = &channel.secret {
+        request = request.bearer_auth(secret);
+    }
+
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+async fn send_matrix(
+    client: &reqwest::Client,
+    channel: &NotificationChannel,
+    message: &RenderedMessage,
+) -> Result<(), AnyError> {
+    let room_id = channel.recipient.clone().unwrap_or_default();
+    let transaction_id = uuid::Uuid::new_v4().to_string();
+    let mut url = url::Url::parse(&channel.url)?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow!("Matrix homeserver URL can't be a base"))?
+        .pop_if_empty()
+        .extend(&[
+            "_matrix",
+            "client",
+            "v3",
+            "rooms",
+            room_id.as_str(),
+            "send",
+            "m.room.message",
+            transaction_id.as_str(),
+        ]);
+
+    let body = if message.title.is_empty() {
+        message.body.clone()
+    } else {
+        format!("{}\n{}", message.title, message.body)
+    };
+
+    client
+        .put(url)
+        .bearer_auth(channel.secret.clone().unwrap_or_default())
+        .timeout(REQUEST_TIMEOUT)
+        .json(&json!({ "msgtype": "m.text", "body": body }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+pub async fn deliver(
+    client: &reqwest::Client,
+    channel: &NotificationChannel,
+    triggered: &TriggeredNotification,
+) -> Result<(), AnyError> {
+    let message = render_message(channel, triggered);
+    match channel.channel_type.as_str() {
+        EMAIL_CHANNEL => send_email(channel, &message).await,
+        NTFY_CHANNEL => send_ntfy(client, channel, &message).await,
+        MATRIX_CHANNEL => send_matrix(client, channel, &message).await,
+        other => Err(anyhow!("Unknown notification channel type: {}", other)),
+    }
+}
+
+/// Sends a triggered notification through all channels configured on its notification
+pub async fn deliver_to_channels(triggered: TriggeredNotification) {
+    let client = reqwest::Client::new();
+    for channel in triggered.notification.channels.iter() {
+        match deliver(&client, channel, &triggered).await {
+            Ok(()) => log::info!(
+                "Notification {} - delivered via {}",
+                triggered.notification.id,
+                channel.channel_type
+            ),
+            Err(e) => log::error!(
+                "Notification {} - delivery via {} failed: {}",
+                triggered.notification.id,
+                channel.channel_type,
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Notification;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::sync::mpsc;
+    use warp::http::{HeaderMap, Method};
+    use warp::Filter;
+
+    struct ReceivedRequest {
+        method: Method,
+        path: String,
+        headers: HeaderMap,
+        body: String,
+    }
+
+    async fn mock_http_receiver() -> (SocketAddr, mpsc::UnboundedReceiver<ReceivedRequest>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let route = warp::method()
+            .and(warp::path::full())
+            .and(warp::header::headers_cloned())
+            .and(warp::body::bytes())
+            .map(
+                move |method: Method,
+                      path: warp::path::FullPath,
+                      headers: HeaderMap,
+                      body: warp::hyper::body::Bytes| {
+                    let _ = sender.send(ReceivedRequest {
+                        method,
+                        path: path.as_str().to_string(),
+                        headers,
+                        body: String::from_utf8_lossy(&body).to_string(),
+                    });
+                    warp::reply::json(&json!({ "event_id": "$event" }))
+                },
+            );
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (address, receiver)
+    }
+
+    /// Minimal SMTP server accepting a single mail, sends the DATA section on the channel
+    async fn mock_smtp_receiver() -> (SocketAddr, mpsc::UnboundedReceiver<String>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            write.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            let mut in_data = false;
+            let mut data = String::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        let _ = sender.send(data.clone());
+                        write.write_all(b"250 OK\r\n").await.unwrap();
+                    } else {
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    continue;
+                }
+                let command = line.to_uppercase();
+                let reply: &[u8] = if command.starts_with("EHLO") {
+                    b"250 localhost\r\n"
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                } else if command.starts_with("QUIT") {
+                    write.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 OK\r\n"
+                };
+                write.write_all(reply).await.unwrap();
+            }
+        });
+        (address, receiver)
+    }
+
+    fn triggered(trigger_match: &str) -> TriggeredNotification {
+        TriggeredNotification {
+            notification: Notification {
+                id: "n1".to_string(),
+                granted: true,
+                description: "New message".to_string(),
+                app_name: "Chat".to_string(),
+                app_url: "".to_string(),
+                app_icon_path: "".to_string(),
+                trigger: "".to_string(),
+                perspective_ids: vec!["p1".to_string()],
+                webhook_url: "".to_string(),
+                webhook_auth: "".to_string(),
+                webhook_signing_secret: "".to_string(),
+                trigger_type: "prolog".to_string(),
+                channels: vec![],
+                throttle: None,
+            },
+            perspective_id: "p1".to_string(),
+            trigger_match: trigger_match.to_string(),
+        }
+    }
+
+    fn channel(channel_type: &str, url: String) -> NotificationChannel {
+        NotificationChannel {
+            channel_type: channel_type.to_string(),
+            url,
+            recipient: None,
+            sender: None,
+            username: None,
+            secret: None,
+            title_template: Some("{{appName}}: {{description}}".to_string()),
+            body_template: Some("{{Author}} wrote {{Message}}".to_string()),
+        }
+    }
+
+    #[test]
+    fn renders_one_body_line_per_prolog_match() {
+        let triggered = triggered(
+            r#"[{"Author": "did:key:alice", "Message": "hi"}, {"Author": "did:key:bob", "Message": 42}]"#,
+        );
+        let message = render_message(&channel(NTFY_CHANNEL, "".to_string()), &triggered);
+        assert_eq!(message.title, "Chat: New message");
+        assert_eq!(message.body, "did:key:alice wrote hi\ndid:key:bob wrote 42");
+    }
+
+    #[test]
+    fn renders_defaults_and_json_match_payloads() {
+        let triggered = triggered(r#"{"neighbourhoodUrl": "neighbourhood://x"}"#);
+        let mut channel = channel(NTFY_CHANNEL, "".to_string());
+        channel.title_template = None;
+        channel.body_template = None;
+        let message = render_message(&channel, &triggered);
+        assert_eq!(message.title, "Chat");
+        assert_eq!(message.body, "New message");
+
+        channel.body_template = Some("Joined {{neighbourhoodUrl}} {{unknown}}".to_string());
+        assert_eq!(
+            render_message(&channel, &triggered).body,
+            "Joined neighbourhood://x "
+        );
+    }
+
+    #[test]
+    fn validates_required_channel_fields() {
+        let mut email = channel(EMAIL_CHANNEL, "smtp://localhost:25".to_string());
+        assert!(validate_channel(&email).is_err());
+        email.recipient = Some("me@example.com".to_string());
+        email.sender = Some("ad4m@example.com".to_string());
+        assert!(validate_channel(&email).is_ok());
+
+        assert!(validate_channel(&channel(NTFY_CHANNEL, "not a url".to_string())).is_err());
+        assert!(validate_channel(&channel("pager", "https://example.com".to_string())).is_err());
+    }
+
+    #[tokio::test]
+    async fn delivers_to_ntfy() {
+        let (address, mut received) = mock_http_receiver().await;
+        let mut channel = channel(NTFY_CHANNEL, format!("http://{}/alerts", address));
+        channel.secret = Some("token".to_string());
+
+        let triggered = triggered(r#"[{"Author": "did:key:alice", "Message": "hi"}]"#);
+        deliver(&reqwest::Client::new(), &channel, &triggered)
+            .await
+            .unwrap();
+
+        let request = received.recv().await.unwrap();
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.path, "/alerts");
+        assert_eq!(request.headers["title"], "Chat: New message");
+        assert_eq!(request.headers["authorization"], "Bearer token");
+        assert_eq!(request.body, "did:key:alice wrote hi");
+    }
+
+    #[tokio::test]
+    async fn delivers_to_matrix_room() {
+        let (address, mut received) = mock_http_receiver().await;
+        let mut channel = channel(MATRIX_CHANNEL, format!("http://{}", address));
+        channel.recipient = Some("!room:example.com".to_string());
+        channel.secret = Some("access-token".to_string());
+
+        let triggered = triggered(r#"[{"Author": "did:key:alice", "Message": "hi"}]"#);
+        deliver(&reqwest::Client::new(), &channel, &triggered)
+            .await
+            .unwrap();
+
+        let request = received.recv().await.unwrap();
+        assert_eq!(request.method, Method::PUT);
+        assert!(request
+            .path
+            .starts_with("/_matrix/client/v3/rooms/!room:example.com/send/m.room.message/"));
+        assert_eq!(request.headers["authorization"], "Bearer access-token");
+        let body: Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["msgtype"], "m.text");
+        assert_eq!(body["body"], "Chat: New message\ndid:key:alice wrote hi");
+    }
+
+    #[tokio::test]
+    async fn delivers_email_via_smtp() {
+        let (address, mut received) = mock_smtp_receiver().await;
+        let mut channel = channel(EMAIL_CHANNEL, format!("smtp://{}", address));
+        channel.recipient = Some("me@example.com".to_string());
+        channel.sender = Some("ad4m@example.com".to_string());
+
+        let triggered = triggered(r#"[{"Author": "did:key:alice", "Message": "hi"}]"#);
+        deliver(&reqwest::Client::new(), &channel, &triggered)
+            .await
+            .unwrap();
+
+        let data = received.recv().await.unwrap();
+        assert!(data.contains("To: me@example.com"));
+        assert!(data.contains("From: ad4m@example.com"));
+        assert!(data.contains("Subject: Chat: New message"));
+        assert!(data.contains("did:key:alice wrote hi"));
+    }
+}
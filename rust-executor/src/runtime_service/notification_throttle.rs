@@ -0,0 +1,452 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use super::notification_triggers::deliver_triggered_notification;
+use crate::db::Ad4mDb;
+use crate::types::{Notification, NotificationThrottle};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+/// Delivery log entries are kept at least this long
+const MIN_LOG_RETENTION_SECS: i64 = 24 * 60 * 60;
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingNotificationMatch {
+    pub id: i64,
+    pub perspective_id: String,
+    pub trigger_match: String,
+    pub created_at: i64,
+}
+
+/// Parses "HH:MM" into minutes since midnight
+pub fn parse_time_of_day(time: &str) -> Result<i64, String> {
+    let invalid = || format!("Invalid time of day {:?}, expected HH:MM", time);
+    let (hours, minutes) = time.trim().split_once(':').ok_or_else(invalid)?;
+    let hours: i64 = hours.parse().map_err(|_| invalid())?;
+    let minutes: i64 = minutes.parse().map_err(|_| invalid())?;
+    if !(0..24).contains(&hours) || !(0..60).contains(&minutes) {
+        return Err(invalid());
+    }
+    Ok(hours * 60 + minutes)
+}
+
+pub fn validate_throttle(throttle: &NotificationThrottle) -> Result<(), String> {
+    match (throttle.max_deliveries, throttle.window_seconds) {
+        (Some(max), Some(window)) if max > 0 && window > 0 => {}
+        (None, None) => {}
+        _ => {
+            return Err(
+                "Throttle needs both a positive maxDeliveries and windowSeconds".to_string(),
+            )
+        }
+    }
+    if matches!(throttle.digest_interval_minutes, Some(interval) if interval <= 0) {
+        return Err("Throttle digestIntervalMinutes has to be positive".to_string());
+    }
+    match (&throttle.quiet_hours_start, &throttle.quiet_hours_end) {
+        (Some(start), Some(end)) => {
+            parse_time_of_day(start)?;
+            parse_time_of_day(end)?;
+        }
+        (None, None) => {}
+        _ => return Err("Quiet hours need both a start and an end".to_string()),
+    }
+    Ok(())
+}
+
+pub fn in_quiet_hours(throttle: &NotificationThrottle, now: i64) -> bool {
+    let (start, end) = match (&throttle.quiet_hours_start, &throttle.quiet_hours_end) {
+        (Some(start), Some(end)) => match (parse_time_of_day(start), parse_time_of_day(end)) {
+            (Ok(start), Ok(end)) => (start, end),
+            _ => return false,
+        },
+        _ => return false,
+    };
+    let offset = throttle.utc_offset_minutes.unwrap_or(0) as i64;
+    let minute = (now.div_euclid(60) + offset).rem_euclid(MINUTES_PER_DAY);
+
+    if start <= end {
+        start <= minute && minute < end
+    } else {
+        // Quiet hours across midnight, e.g. 22:00 - 07:00
+        minute >= start || minute < end
+    }
+}
+
+/// How recently this notification was delivered on a perspective, as far as its throttle cares.
+/// Perspectives are throttled independently so that a busy one can't hold back the others.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DeliveryHistory {
+    pub deliveries_in_window: u32,
+    pub last_delivery: Option<i64>,
+}
+
+fn window_exhausted(throttle: &NotificationThrottle, history: &DeliveryHistory) -> bool {
+    matches!(throttle.max_deliveries, Some(max) if history.deliveries_in_window >= max as u32)
+}
+
+/// Whether a new match can be delivered right away instead of being queued
+pub fn can_deliver_now(
+    throttle: &NotificationThrottle,
+    history: &DeliveryHistory,
+    now: i64,
+) -> bool {
+    throttle.digest_interval_minutes.is_none()
+        && !in_quiet_hours(throttle, now)
+        && !window_exhausted(throttle, history)
+}
+
+/// Whether queued matches can be sent out as a digest now
+pub fn can_flush(throttle: &NotificationThrottle, history: &DeliveryHistory, now: i64) -> bool {
+    let digest_due = match (throttle.digest_interval_minutes, history.last_delivery) {
+        (Some(interval), Some(last)) => now - last >= interval as i64 * 60,
+        _ => true,
+    };
+    digest_due && !in_quiet_hours(throttle, now) && !window_exhausted(throttle, history)
+}
+
+/// Combines queued matches into one match list.
+/// Prolog binding lists get concatenated, other match payloads become list items.
+pub fn merge_matches<'a>(matches: impl IntoIterator<Item = &'a str>) -> String {
+    let mut merged = Vec::new();
+    for trigger_match in matches {
+        match serde_json::from_str::<Value>(trigger_match) {
+            Ok(Value::Array(items)) => merged.extend(items),
+            Ok(other) => merged.push(other),
+            Err(_) => merged.push(Value::String(trigger_match.to_string())),
+        }
+    }
+    Value::Array(merged).to_string()
+}
+
+fn log_retention_secs(throttle: &NotificationThrottle) -> i64 {
+    let window = throttle.window_seconds.unwrap_or(0) as i64;
+    let digest = throttle.digest_interval_minutes.unwrap_or(0) as i64 * 60;
+    window.max(digest).max(MIN_LOG_RETENTION_SECS)
+}
+
+fn delivery_history(
+    notification_id: &str,
+    perspective_id: &str,
+    throttle: &NotificationThrottle,
+    now: i64,
+) -> Result<DeliveryHistory, rusqlite::Error> {
+    Ad4mDb::with_global_instance(|db| {
+        let deliveries_in_window = match throttle.window_seconds {
+            Some(window) => db.count_notification_deliveries_since(
+                notification_id,
+                perspective_id,
+                now - window as i64,
+            )?,
+            None => 0,
+        };
+        Ok(DeliveryHistory {
+            deliveries_in_window,
+            last_delivery: db.last_notification_delivery(notification_id, perspective_id)?,
+        })
+    })
+}
+
+fn record_delivery(
+    notification_id: &str,
+    perspective_id: &str,
+    throttle: &NotificationThrottle,
+    now: i64,
+) {
+    if let Err(e) = Ad4mDb::with_global_instance(|db| {
+        db.record_notification_delivery(
+            notification_id,
+            perspective_id,
+            now,
+            now - log_retention_secs(throttle),
+        )
+    }) {
+        log::error!("Failed to log notification delivery: {}", e);
+    }
+}
+
+/// Lets a triggered notification through if its throttle allows it,
+/// otherwise queues the match for a later digest and returns false.
+pub fn admit(notification: &Notification, perspective_id: &str, trigger_match: &str) -> bool {
+    let throttle = match notification.throttle.as_ref() {
+        Some(throttle) => throttle,
+        None => return true,
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let history = match delivery_history(&notification.id, perspective_id, throttle, now) {
+        Ok(history) => history,
+        Err(e) => {
+            log::error!("Failed to load notification delivery history: {}", e);
+            DeliveryHistory::default()
+        }
+    };
+
+    if can_deliver_now(throttle, &history, now) {
+        record_delivery(&notification.id, perspective_id, throttle, now);
+        return true;
+    }
+
+    if let Err(e) = Ad4mDb::with_global_instance(|db| {
+        db.queue_notification_match(&notification.id, perspective_id, trigger_match, now)
+    }) {
+        log::error!("Failed to queue throttled notification match: {}", e);
+    }
+    false
+}
+
+/// Sends queued matches of all notifications whose throttle allows it,
+/// one digest per notification and perspective
+async fn flush_pending() {
+    let now = chrono::Utc::now().timestamp();
+    let notification_ids =
+        match Ad4mDb::with_global_instance(|db| db.get_notifications_with_pending_matches()) {
+            Ok(ids) => ids,
+            Err(e) => {
+                log::error!("Failed to load throttled notifications: {}", e);
+                return;
+            }
+        };
+
+    for notification_id in notification_ids {
+        let loaded = Ad4mDb::with_global_instance(|db| {
+            Ok::<_, rusqlite::Error>((
+                db.get_notification(notification_id.clone())?,
+                db.get_pending_notification_matches(&notification_id)?,
+            ))
+        });
+        let (notification, pending) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                log::error!("Failed to load pending notification matches: {}", e);
+                continue;
+            }
+        };
+        let notification = match notification {
+            Some(notification) => notification,
+            None => continue,
+        };
+        // Matches of a notification whose grant got revoked are never delivered
+        if !notification.granted {
+            let ids: Vec<i64> = pending.iter().map(|m| m.id).collect();
+            if let Err(e) =
+                Ad4mDb::with_global_instance(|db| db.remove_pending_notification_matches(&ids))
+            {
+                log::error!("Failed to remove pending notification matches: {}", e);
+            }
+            continue;
+        }
+        // Throttle got removed in the meantime, nothing holds the matches back anymore
+        let throttle = notification.throttle.clone().unwrap_or_default();
+
+        let mut by_perspective: BTreeMap<String, Vec<PendingNotificationMatch>> = BTreeMap::new();
+        for pending_match in pending {
+            by_perspective
+                .entry(pending_match.perspective_id.clone())
+                .or_default()
+                .push(pending_match);
+        }
+
+        for (perspective_id, matches) in by_perspective {
+            let history = match delivery_history(&notification_id, &perspective_id, &throttle, now)
+            {
+                Ok(history) => history,
+                Err(e) => {
+                    log::error!("Failed to load notification delivery history: {}", e);
+                    continue;
+                }
+            };
+            if !can_flush(&throttle, &history, now) {
+                continue;
+            }
+
+            let digest = merge_matches(matches.iter().map(|m| m.trigger_match.as_str()));
+            let ids: Vec<i64> = matches.iter().map(|m| m.id).collect();
+            if let Err(e) =
+                Ad4mDb::with_global_instance(|db| db.remove_pending_notification_matches(&ids))
+            {
+                log::error!("Failed to remove pending notification matches: {}", e);
+                continue;
+            }
+            record_delivery(&notification_id, &perspective_id, &throttle, now);
+            deliver_triggered_notification(notification.clone(), perspective_id, digest).await;
+        }
+    }
+}
+
+/// Periodically sends out digests of throttled notifications.
+/// Matches queued before a restart are picked up from the database.
+pub async fn run_throttle_loop() {
+    loop {
+        flush_pending().await;
+        tokio::time::sleep(FLUSH_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphql::graphql_types::{NotificationInput, NotificationThrottleInput};
+
+    // 2024-01-01 00:00:00 UTC
+    const MIDNIGHT: i64 = 1704067200;
+
+    fn at(hours: i64, minutes: i64) -> i64 {
+        MIDNIGHT + hours * 3600 + minutes * 60
+    }
+
+    fn quiet(start: &str, end: &str) -> NotificationThrottle {
+        NotificationThrottle {
+            quiet_hours_start: Some(start.to_string()),
+            quiet_hours_end: Some(end.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn quiet_hours_can_span_midnight() {
+        let night = quiet("22:00", "07:00");
+        assert!(in_quiet_hours(&night, at(23, 30)));
+        assert!(in_quiet_hours(&night, at(3, 0)));
+        assert!(!in_quiet_hours(&night, at(7, 0)));
+        assert!(!in_quiet_hours(&night, at(12, 0)));
+
+        let lunch = quiet("12:00", "13:00");
+        assert!(in_quiet_hours(&lunch, at(12, 30)));
+        assert!(!in_quiet_hours(&lunch, at(13, 30)));
+    }
+
+    #[test]
+    fn quiet_hours_respect_utc_offset() {
+        let mut night = quiet("22:00", "07:00");
+        night.utc_offset_minutes = Some(120);
+        // 21:00 UTC is 23:00 local time
+        assert!(in_quiet_hours(&night, at(21, 0)));
+        // 06:00 UTC is 08:00 local time
+        assert!(!in_quiet_hours(&night, at(6, 0)));
+    }
+
+    #[test]
+    fn rate_limit_queues_once_window_is_used_up() {
+        let throttle = NotificationThrottle {
+            max_deliveries: Some(2),
+            window_seconds: Some(60),
+            ..Default::default()
+        };
+        let mut history = DeliveryHistory {
+            deliveries_in_window: 1,
+            last_delivery: Some(MIDNIGHT),
+        };
+        assert!(can_deliver_now(&throttle, &history, MIDNIGHT));
+        history.deliveries_in_window = 2;
+        assert!(!can_deliver_now(&throttle, &history, MIDNIGHT));
+        assert!(!can_flush(&throttle, &history, MIDNIGHT));
+    }
+
+    #[test]
+    fn digests_batch_matches_per_interval() {
+        let throttle = NotificationThrottle {
+            digest_interval_minutes: Some(10),
+            ..Default::default()
+        };
+        let mut history = DeliveryHistory::default();
+        assert!(!can_deliver_now(&throttle, &history, MIDNIGHT));
+        assert!(can_flush(&throttle, &history, MIDNIGHT));
+
+        history.last_delivery = Some(MIDNIGHT);
+        assert!(!can_flush(&throttle, &history, at(0, 5)));
+        assert!(can_flush(&throttle, &history, at(0, 10)));
+    }
+
+    #[test]
+    fn quiet_hours_hold_back_digests() {
+        let throttle = quiet("22:00", "07:00");
+        let history = DeliveryHistory::default();
+        assert!(!can_deliver_now(&throttle, &history, at(23, 0)));
+        assert!(!can_flush(&throttle, &history, at(23, 0)));
+        assert!(can_flush(&throttle, &history, at(8, 0)));
+    }
+
+    #[tokio::test]
+    async fn perspectives_are_flushed_independently() {
+        Ad4mDb::init_global_instance(":memory:").unwrap();
+        let id = Ad4mDb::with_global_instance(|db| {
+            db.add_notification(NotificationInput {
+                description: "".to_string(),
+                app_name: "".to_string(),
+                app_url: "".to_string(),
+                app_icon_path: "".to_string(),
+                trigger: "".to_string(),
+                perspective_ids: vec!["p1".to_string(), "p2".to_string()],
+                webhook_url: "".to_string(),
+                webhook_auth: "".to_string(),
+                trigger_type: None,
+                channels: None,
+                throttle: Some(NotificationThrottleInput {
+                    max_deliveries: Some(1),
+                    window_seconds: Some(3600),
+                    digest_interval_minutes: None,
+                    quiet_hours_start: None,
+                    quiet_hours_end: None,
+                    utc_offset_minutes: None,
+                }),
+            })
+        })
+        .unwrap();
+        let mut notification = Ad4mDb::with_global_instance(|db| db.get_notification(id.clone()))
+            .unwrap()
+            .unwrap();
+        notification.granted = true;
+        Ad4mDb::with_global_instance(|db| db.update_notification(id.clone(), &notification))
+            .unwrap();
+
+        // Matches queued on two perspectives while the throttle held them back
+        Ad4mDb::with_global_instance(|db| {
+            db.queue_notification_match(&id, "p1", "[1]", 0)?;
+            db.queue_notification_match(&id, "p2", "[2]", 0)
+        })
+        .unwrap();
+
+        // Flushing p1 must not use up the window of p2
+        flush_pending().await;
+        let pending =
+            Ad4mDb::with_global_instance(|db| db.get_pending_notification_matches(&id)).unwrap();
+        assert!(pending.is_empty());
+
+        // Both windows are used up now, so new matches get queued on either perspective
+        assert!(!admit(&notification, "p1", "[3]"));
+        assert!(!admit(&notification, "p2", "[4]"));
+        flush_pending().await;
+        let pending =
+            Ad4mDb::with_global_instance(|db| db.get_pending_notification_matches(&id)).unwrap();
+        assert_eq!(pending.len(), 2);
+    }
+
+    #[test]
+    fn merges_prolog_bindings_and_payloads() {
+        let merged = merge_matches([r#"[{"X": "a"}]"#, r#"[{"X": "b"}, {"X": "c"}]"#]);
+        assert_eq!(merged, r#"[{"X":"a"},{"X":"b"},{"X":"c"}]"#);
+
+        let merged = merge_matches([r#"{"message": 1}"#, "true"]);
+        assert_eq!(merged, r#"[{"message":1},true]"#);
+    }
+
+    #[test]
+    fn validates_throttle_settings() {
+        assert!(validate_throttle(&NotificationThrottle::default()).is_ok());
+        assert!(validate_throttle(&quiet("22:00", "07:00")).is_ok());
+        assert!(validate_throttle(&quiet("25:00", "07:00")).is_err());
+        assert!(validate_throttle(&NotificationThrottle {
+            max_deliveries: Some(3),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(validate_throttle(&NotificationThrottle {
+            digest_interval_minutes: Some(0),
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,383 @@
+use serde_json::json;
+
+use super::{notification_channels, notification_throttle, webhooks};
+use crate::db::Ad4mDb;
+use crate::graphql::graphql_types::{PerspectiveExpression, PerspectiveHandle};
+use crate::pubsub::{get_global_pubsub, RUNTIME_NOTIFICATION_TRIGGERED_TOPIC};
+use crate::types::{Notification, NotificationTriggerType, TriggeredNotification};
+
+/// Empty triggers and `*` match everything
+fn is_wildcard(trigger: &str) -> bool {
+    let trigger = trigger.trim();
+    trigger.is_empty() || trigger == "*"
+}
+
+/// Notifications the user granted that fire on the given kind of event
+pub fn notifications_of_type(trigger_type: NotificationTriggerType) -> Vec<Notification> {
+    match Ad4mDb::with_global_instance(|db| db.get_notifications()) {
+        Ok(notifications) => notifications
+            .into_iter()
+            .filter(|n| n.granted && n.trigger_type() == Some(trigger_type))
+            .collect(),
+        Err(e) => {
+            log::error!("Error loading notifications: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Match payload for a telepresence signal received in the given perspective.
+/// A non-wildcard trigger is a link predicate the signal has to contain.
+pub fn signal_match(
+    notification: &Notification,
+    perspective_uuid: &str,
+    signal: &PerspectiveExpression,
+) -> Option<String> {
+    if notification.trigger_type() != Some(NotificationTriggerType::TelepresenceSignal)
+        || !notification.is_active_on(perspective_uuid)
+    {
+        return None;
+    }
+
+    let trigger = notification.trigger.trim();
+    let matches = is_wildcard(trigger)
+        || signal
+            .data
+            .links
+            .iter()
+            .any(|link| link.data.predicate.as_deref() == Some(trigger));
+
+    matches.then(|| json!({ "signal": signal }).to_string())
+}
+
+/// Match payload for a direct message arriving in the inbox.
+/// A non-wildcard trigger is the DID the message has to be authored by.
+pub fn direct_message_match(
+    notification: &Notification,
+    message: &PerspectiveExpression,
+) -> Option<String> {
+    if notification.trigger_type() != Some(NotificationTriggerType::DirectMessage) {
+        return None;
+    }
+
+    let trigger = notification.trigger.trim();
+    let matches = is_wildcard(trigger) || message.author == trigger;
+
+    matches.then(|| json!({ "message": message }).to_string())
+}
+
+/// Match payload for a neighbourhood that just got joined.
+/// The trigger is the neighbourhood URL, or a wildcard for any neighbourhood.
+pub fn neighbourhood_joined_match(
+    notification: &Notification,
+    neighbourhood_url: &str,
+    perspective: &PerspectiveHandle,
+) -> Option<String> {
+    if notification.trigger_type() != Some(NotificationTriggerType::NeighbourhoodJoined) {
+        return None;
+    }
+
+    let trigger = notification.trigger.trim();
+    let matches = is_wildcard(trigger) || trigger == neighbourhood_url;
+
+    matches.then(|| {
+        json!({
+            "neighbourhoodUrl": neighbourhood_url,
+            "perspective": perspective,
+        })
+        .to_string()
+    })
+}
+
+/// Sends a triggered notification to the launcher and its delivery channels,
+/// and queues it for the notification's webhook.
+/// Nothing leaves the executor for notifications the user hasn't granted.
+pub async fn deliver_triggered_notification(
+    notification: Notification,
+    perspective_id: String,
+    trigger_match: String,
+) {
+    if !notification.granted {
+        log::warn!(
+            "Not delivering notification {} which hasn't been granted",
+            notification.id
+        );
+        return;
+    }
+
+    let webhook_set = url::Url::parse(&notification.webhook_url).is_ok();
+    let payload = TriggeredNotification {
+        notification,
+        perspective_id,
+        trigger_match,
+    };
+
+    let message = payload.public_payload();
+
+    get_global_pubsub()
+        .await
+        .publish(&RUNTIME_NOTIFICATION_TRIGGERED_TOPIC, &message)
+        .await;
+
+    if !payload.notification.channels.is_empty() {
+        tokio::spawn(notification_channels::deliver_to_channels(payload.clone()));
+    }
+
+    if webhook_set {
+        webhooks::enqueue(&payload.notification, message);
+    }
+}
+
+/// Delivers a triggered notification unless its throttle holds the match back for a later digest
+pub async fn publish_triggered_notification(
+    notification: Notification,
+    perspective_id: String,
+    trigger_match: String,
+) {
+    if !notification.granted {
+        return;
+    }
+    if notification_throttle::admit(&notification, &perspective_id, &trigger_match) {
+        deliver_triggered_notification(notification, perspective_id, trigger_match).await;
+    }
+}
+
+pub async fn telepresence_signal_received(perspective_uuid: String, signal: PerspectiveExpression) {
+    for notification in notifications_of_type(NotificationTriggerType::TelepresenceSignal) {
+        if let Some(trigger_match) = signal_match(&notification, &perspective_uuid, &signal) {
+            publish_triggered_notification(notification, perspective_uuid.clone(), trigger_match)
+                .await;
+        }
+    }
+}
+
+pub async fn direct_message_received(message: PerspectiveExpression) {
+    for notification in notifications_of_type(NotificationTriggerType::DirectMessage) {
+        if let Some(trigger_match) = direct_message_match(&notification, &message) {
+            publish_triggered_notification(notification, String::new(), trigger_match).await;
+        }
+    }
+}
+
+pub async fn neighbourhood_joined(neighbourhood_url: String, perspective: PerspectiveHandle) {
+    for notification in notifications_of_type(NotificationTriggerType::NeighbourhoodJoined) {
+        if let Some(trigger_match) =
+            neighbourhood_joined_match(&notification, &neighbourhood_url, &perspective)
+        {
+            publish_triggered_notification(notification, perspective.uuid.clone(), trigger_match)
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphql::graphql_types::Perspective;
+    use crate::types::{DecoratedExpressionProof, DecoratedLinkExpression, Link, ALL_PERSPECTIVES};
+
+    fn notification(trigger_type: NotificationTriggerType, trigger: &str) -> Notification {
+        Notification {
+            id: "n1".to_string(),
+            granted: true,
+            description: "".to_string(),
+            app_name: "".to_string(),
+            app_url: "".to_string(),
+            app_icon_path: "".to_string(),
+            trigger: trigger.to_string(),
+            perspective_ids: vec!["p1".to_string()],
+            webhook_url: "".to_string(),
+            webhook_auth: "".to_string(),
+            webhook_signing_secret: "".to_string(),
+            trigger_type: trigger_type.to_string(),
+            channels: vec![],
+            throttle: None,
+        }
+    }
+
+    fn expression(author: &str, predicate: &str) -> PerspectiveExpression {
+        PerspectiveExpression {
+            author: author.to_string(),
+            data: Perspective {
+                links: vec![DecoratedLinkExpression {
+                    author: author.to_string(),
+                    timestamp: "".to_string(),
+                    data: Link {
+                        source: "ad4m://self".to_string(),
+                        predicate: Some(predicate.to_string()),
+                        target: "literal://string:hello".to_string(),
+                    },
+                    proof: DecoratedExpressionProof::default(),
+                    status: None,
+                }],
+            },
+            proof: DecoratedExpressionProof::default(),
+            timestamp: "".to_string(),
+        }
+    }
+
+    #[test]
+    fn published_payloads_leave_out_credentials() {
+        let mut notification = notification(NotificationTriggerType::Prolog, "");
+        notification.webhook_auth = "webhook-token".to_string();
+        notification.webhook_signing_secret = "signing-secret".to_string();
+        notification.channels = vec![crate::types::NotificationChannel {
+            channel_type: "matrix".to_string(),
+            url: "https://matrix.example.org".to_string(),
+            recipient: Some("!room:example.org".to_string()),
+            sender: None,
+            username: None,
+            secret: Some("matrix-token".to_string()),
+            title_template: None,
+            body_template: None,
+        }];
+        let triggered = TriggeredNotification {
+            notification,
+            perspective_id: "p1".to_string(),
+            trigger_match: "[]".to_string(),
+        };
+
+        // The same message goes to the subscription and the webhook
+        let message = triggered.public_payload();
+        assert!(!message.contains("matrix-token"));
+        assert!(!message.contains("webhook-token"));
+        assert!(!message.contains("signing-secret"));
+
+        let published: TriggeredNotification = serde_json::from_str(&message).unwrap();
+        assert!(published.notification.channels.is_empty());
+        assert_eq!(published.notification.id, "n1");
+        assert_eq!(published.trigger_match, "[]");
+    }
+
+    #[test]
+    fn signals_match_on_active_perspectives_and_predicate() {
+        let signal = expression("did:key:alice", "ad4m://typing");
+
+        let any = notification(NotificationTriggerType::TelepresenceSignal, "");
+        let payload = signal_match(&any, "p1", &signal).expect("wildcard to match");
+        let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(payload["signal"]["author"], "did:key:alice");
+        assert!(signal_match(&any, "p2", &signal).is_none());
+
+        let typing = notification(NotificationTriggerType::TelepresenceSignal, "ad4m://typing");
+        assert!(signal_match(&typing, "p1", &signal).is_some());
+        let other = notification(NotificationTriggerType::TelepresenceSignal, "ad4m://other");
+        assert!(signal_match(&other, "p1", &signal).is_none());
+
+        let mut everywhere = any.clone();
+        everywhere.perspective_ids = vec![ALL_PERSPECTIVES.to_string()];
+        assert!(signal_match(&everywhere, "p2", &signal).is_some());
+    }
+
+    #[test]
+    fn direct_messages_match_on_author() {
+        let message = expression("did:key:alice", "ad4m://message");
+
+        let any = notification(NotificationTriggerType::DirectMessage, "*");
+        let payload = direct_message_match(&any, &message).expect("wildcard to match");
+        let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(payload["message"]["author"], "did:key:alice");
+
+        let alice = notification(NotificationTriggerType::DirectMessage, "did:key:alice");
+        assert!(direct_message_match(&alice, &message).is_some());
+        let bob = notification(NotificationTriggerType::DirectMessage, "did:key:bob");
+        assert!(direct_message_match(&bob, &message).is_none());
+    }
+
+    #[test]
+    fn neighbourhood_joins_match_on_url() {
+        let perspective = PerspectiveHandle {
+            uuid: "p3".to_string(),
+            ..Default::default()
+        };
+
+        let any = notification(NotificationTriggerType::NeighbourhoodJoined, "*");
+        let payload = neighbourhood_joined_match(&any, "neighbourhood://x", &perspective)
+            .expect("wildcard to match");
+        let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(payload["neighbourhoodUrl"], "neighbourhood://x");
+        assert_eq!(payload["perspective"]["uuid"], "p3");
+
+        let x = notification(
+            NotificationTriggerType::NeighbourhoodJoined,
+            "neighbourhood://x",
+        );
+        assert!(neighbourhood_joined_match(&x, "neighbourhood://x", &perspective).is_some());
+        assert!(neighbourhood_joined_match(&x, "neighbourhood://y", &perspective).is_none());
+    }
+
+    #[tokio::test]
+    async fn ungranted_notifications_never_publish() {
+        Ad4mDb::init_global_instance(":memory:").unwrap();
+        let id = Ad4mDb::with_global_instance(|db| {
+            db.add_notification(crate::graphql::graphql_types::NotificationInput {
+                description: "".to_string(),
+                app_name: "".to_string(),
+                app_url: "".to_string(),
+                app_icon_path: "".to_string(),
+                trigger: "*".to_string(),
+                perspective_ids: vec![],
+                webhook_url: "".to_string(),
+                webhook_auth: "".to_string(),
+                trigger_type: Some(NotificationTriggerType::DirectMessage.to_string()),
+                channels: None,
+                throttle: None,
+            })
+        })
+        .unwrap();
+
+        // Installed but not granted yet
+        assert!(notifications_of_type(NotificationTriggerType::DirectMessage).is_empty());
+
+        let mut subscription = get_global_pubsub()
+            .await
+            .subscribe(&RUNTIME_NOTIFICATION_TRIGGERED_TOPIC)
+            .await;
+        let mut ungranted = notification(NotificationTriggerType::DirectMessage, "*");
+        ungranted.id = id.clone();
+        ungranted.granted = false;
+        let message = expression("did:key:alice", "ad4m://message");
+        let trigger_match = direct_message_match(&ungranted, &message).unwrap();
+        publish_triggered_notification(ungranted.clone(), String::new(), trigger_match.clone())
+            .await;
+        deliver_triggered_notification(ungranted, String::new(), trigger_match).await;
+        // Other tests share the pubsub, so only look for this notification
+        if subscription.has_changed().unwrap() {
+            let published: TriggeredNotification =
+                serde_json::from_str(&subscription.borrow_and_update()).unwrap();
+            assert_ne!(published.notification.id, id);
+        }
+
+        // Once granted, the same trigger goes out
+        let mut granted = Ad4mDb::with_global_instance(|db| db.get_notification(id.clone()))
+            .unwrap()
+            .unwrap();
+        granted.granted = true;
+        Ad4mDb::with_global_instance(|db| db.update_notification(id.clone(), &granted)).unwrap();
+        assert_eq!(
+            notifications_of_type(NotificationTriggerType::DirectMessage).len(),
+            1
+        );
+        direct_message_received(message).await;
+        assert!(subscription.has_changed().unwrap());
+        let published: TriggeredNotification =
+            serde_json::from_str(&subscription.borrow_and_update()).unwrap();
+        assert_eq!(published.notification.id, id);
+    }
+
+    #[test]
+    fn triggers_only_match_their_own_event_kind() {
+        let signal = expression("did:key:alice", "ad4m://typing");
+        let perspective = PerspectiveHandle::default();
+
+        let prolog = notification(NotificationTriggerType::Prolog, "");
+        assert!(signal_match(&prolog, "p1", &signal).is_none());
+        assert!(direct_message_match(&prolog, &signal).is_none());
+        assert!(neighbourhood_joined_match(&prolog, "neighbourhood://x", &perspective).is_none());
+
+        let mut unknown = notification(NotificationTriggerType::DirectMessage, "");
+        unknown.trigger_type = "somethingElse".to_string();
+        assert_eq!(unknown.trigger_type(), None);
+        assert!(direct_message_match(&unknown, &signal).is_none());
+    }
+}
@@ -1,5 +1,5 @@
 import {
-    friends, add_message_outbox, get_trusted_agents
+    friends, get_trusted_agents, agent_trust, message_received
 } from 'ext:core/ops';
 
 ((globalThis) => {
@@ -7,11 +7,14 @@ import {
         friends: async () => {
             return friends();
         },
-        addMessageOutbox: async (did, message, wasSent) => {
-            return add_message_outbox(did, message, wasSent);
-        },
         getTrustedAgents: async () => {
             return get_trusted_agents();
         },
+        agentTrust: async (did) => {
+            return agent_trust(did);
+        },
+        messageReceived: async (message) => {
+            return message_received(message);
+        },
     }
 })(globalThis);
\ No newline at end of file
@@ -1,7 +1,11 @@
-use crate::graphql::graphql_types::{PerspectiveExpression, SentMessage};
+use crate::graphql::graphql_types::PerspectiveExpression;
+use crate::types::TrustReport;
 use deno_core::{error::AnyError, op2};
 
-use super::RuntimeService;
+use super::{
+    conversations, direct_messages, friend_requests, notification_triggers, web_of_trust,
+    RuntimeService,
+};
 
 #[op2]
 #[serde]
@@ -15,24 +19,38 @@ pub fn get_trusted_agents() -> Result<Vec<String>, AnyError> {
     RuntimeService::with_global_instance(|runtime| Ok(runtime.get_trusted_agents()))
 }
 
+/// Whether languages by `did` can be installed, and why
 #[op2]
-pub fn add_message_outbox(
-    #[string] did: String,
+#[serde]
+pub fn agent_trust(#[string] did: String) -> Result<TrustReport, AnyError> {
+    web_of_trust::agent_trust(&did)
+}
+
+/// Returns the message as it should be shown, decrypted if it was encrypted,
+/// or nothing if it could not be decrypted
+#[op2(async)]
+#[serde]
+pub async fn message_received(
     #[serde] message: PerspectiveExpression,
-    was_sent: bool,
-) -> Result<bool, AnyError> {
-    RuntimeService::with_global_instance(|runtime| {
-        runtime.add_message_to_outbox(SentMessage {
-            recipient: did,
-            message,
-        })
-    });
-    Ok(was_sent)
+) -> Result<Option<PerspectiveExpression>, AnyError> {
+    let message = match direct_messages::verify_and_decrypt(message) {
+        Ok(message) => message,
+        Err(e) => {
+            log::warn!("Dropping direct message that failed to decrypt: {}", e);
+            return Ok(None);
+        }
+    };
+    direct_messages::store_received(&message);
+    conversations::received(&message);
+    friend_requests::received(&message).await;
+    direct_messages::recipient_online(&message.author);
+    notification_triggers::direct_message_received(message.clone()).await;
+    Ok(Some(message))
 }
 
 deno_core::extension!(
     runtime_service,
-    ops = [friends, add_message_outbox, get_trusted_agents],
+    ops = [friends, get_trusted_agents, agent_trust, message_received],
     esm_entry_point = "ext:runtime_service/runtime_service_extension.js",
     esm = [dir "src/runtime_service", "runtime_service_extension.js"]
 );
@@ -0,0 +1,306 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+use serde_json::{Map, Value};
+
+use crate::ai_service::AIService;
+use crate::db::Ad4mDb;
+use crate::graphql::graphql_types::ScheduledJobInput;
+use crate::perspectives::get_perspective;
+use crate::perspectives::perspective_instance::{Command, Parameter};
+use crate::perspectives::utils::prolog_resolution_to_string;
+use crate::types::ScheduledJob;
+
+pub const PROLOG_JOB: &str = "prolog";
+pub const AI_TASK_JOB: &str = "aiTask";
+/// Binding that `this` refers to in the commands of Prolog jobs
+const THIS_BINDING: &str = "Base";
+/// Run history kept per job
+pub const MAX_RUNS_KEPT: u32 = 100;
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Parses a cron expression, accepting the classic 5 field format
+/// (minute precision) next to the 6 and 7 field formats with seconds and years
+pub fn parse_schedule(expression: &str) -> Result<Schedule, AnyError> {
+    let expression = expression.trim();
+    let normalized = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression.to_string()
+    };
+    Schedule::from_str(&normalized)
+        .map_err(|e| anyhow!("Invalid cron expression {:?}: {}", expression, e))
+}
+
+/// Unix timestamp of the next run strictly after `after`
+pub fn next_run_after(schedule: &str, after: i64) -> Result<i64, AnyError> {
+    let after = DateTime::<Utc>::from_timestamp(after, 0)
+        .ok_or_else(|| anyhow!("Invalid timestamp {}", after))?;
+    parse_schedule(schedule)?
+        .after(&after)
+        .next()
+        .map(|next| next.timestamp())
+        .ok_or_else(|| anyhow!("Schedule {:?} has no upcoming runs", schedule))
+}
+
+pub fn validate_job(input: &ScheduledJobInput) -> Result<(), AnyError> {
+    parse_schedule(&input.schedule)?;
+    match input.job_type.as_str() {
+        PROLOG_JOB => {
+            if input.query.as_deref().unwrap_or_default().trim().is_empty() {
+                return Err(anyhow!("Prolog jobs need a query"));
+            }
+            if let Some(commands) = input.commands.as_ref() {
+                serde_json::from_str::<Vec<Command>>(commands)
+                    .map_err(|e| anyhow!("Invalid job commands: {}", e))?;
+            }
+        }
+        AI_TASK_JOB => {
+            if input.task_id.is_none() || input.prompt.is_none() {
+                return Err(anyhow!("AI task jobs need a taskId and a prompt"));
+            }
+        }
+        other => return Err(anyhow!("Unknown job type: {}", other)),
+    }
+    Ok(())
+}
+
+/// Turns Prolog matches into one parameter list per match, named after the bound variables
+pub fn match_parameters(matches: &str) -> Vec<(Option<String>, Vec<Parameter>)> {
+    match serde_json::from_str::<Value>(matches) {
+        // Query without variables that succeeded
+        Ok(Value::Bool(true)) => vec![(None, vec![])],
+        Ok(Value::Array(matches)) => matches
+            .into_iter()
+            .filter_map(|m| match m {
+                Value::Object(bindings) => Some(bindings),
+                _ => None,
+            })
+            .map(|bindings: Map<String, Value>| {
+                let this = bindings.get(THIS_BINDING).map(|value| match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                });
+                let parameters = bindings
+                    .into_iter()
+                    .map(|(name, value)| Parameter::new(name, value))
+                    .collect();
+                (this, parameters)
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+async fn run_prolog_job(job: &ScheduledJob) -> Result<String, AnyError> {
+    let mut perspective = get_perspective(&job.perspective_uuid)
+        .ok_or_else(|| anyhow!("Perspective {} not found", job.perspective_uuid))?;
+    let query = job.query.clone().unwrap_or_default();
+    let result = prolog_resolution_to_string(perspective.prolog_query(query).await?);
+
+    let commands: Vec<Command> = match job.commands.as_ref() {
+        Some(commands) => serde_json::from_str(commands)?,
+        None => return Ok(result),
+    };
+
+    let matches = match_parameters(&result);
+    for (this, parameters) in matches.iter() {
+        perspective
+            .execute_commands(
+                commands.clone(),
+                this.clone().unwrap_or_default(),
+                parameters.clone(),
+            )
+            .await?;
+    }
+
+    Ok(format!(
+        "{}\nExecuted commands for {} matches",
+        result,
+        matches.len()
+    ))
+}
+
+async fn run_ai_task_job(job: &ScheduledJob) -> Result<String, AnyError> {
+    AIService::global_instance()
+        .await?
+        .prompt(
+            job.task_id.clone().unwrap_or_default(),
+            job.prompt.clone().unwrap_or_default(),
+        )
+        .await
+}
+
+pub async fn run_job(job: &ScheduledJob) -> Result<String, AnyError> {
+    match job.job_type.as_str() {
+        PROLOG_JOB => run_prolog_job(job).await,
+        AI_TASK_JOB => run_ai_task_job(job).await,
+        other => Err(anyhow!("Unknown job type: {}", other)),
+    }
+}
+
+pub fn schedule_job(input: ScheduledJobInput) -> Result<ScheduledJob, AnyError> {
+    validate_job(&input)?;
+    let now = Utc::now().timestamp();
+    let next_run_at = next_run_after(&input.schedule, now)?;
+    let id = Ad4mDb::with_global_instance(|db| db.add_scheduled_job(&input, now, next_run_at))?;
+    Ad4mDb::with_global_instance(|db| db.get_scheduled_job(&id))?
+        .ok_or_else(|| anyhow!("Scheduled job {} not found", id))
+}
+
+/// Paused jobs keep their run history. Resuming schedules the next run from now on,
+/// runs missed while paused are skipped.
+pub fn set_job_paused(id: &str, paused: bool) -> Result<ScheduledJob, AnyError> {
+    let job = Ad4mDb::with_global_instance(|db| db.get_scheduled_job(id))?
+        .ok_or_else(|| anyhow!("Scheduled job {} not found", id))?;
+    let next_run_at = if paused {
+        None
+    } else {
+        Some(next_run_after(&job.schedule, Utc::now().timestamp())?)
+    };
+    Ad4mDb::with_global_instance(|db| db.set_scheduled_job_paused(id, paused, next_run_at))?;
+    Ad4mDb::with_global_instance(|db| db.get_scheduled_job(id))?
+        .ok_or_else(|| anyhow!("Scheduled job {} not found", id))
+}
+
+async fn run_due_jobs() {
+    let now = Utc::now().timestamp();
+    let due = match Ad4mDb::with_global_instance(|db| db.get_due_scheduled_jobs(now)) {
+        Ok(due) => due,
+        Err(e) => {
+            log::error!("Failed to load due scheduled jobs: {}", e);
+            return;
+        }
+    };
+
+    for job in due {
+        // Reschedule before running, so a job that crashes the executor doesn't run in a loop
+        let next_run_at = match next_run_after(&job.schedule, now) {
+            Ok(next) => Some(next),
+            Err(e) => {
+                log::error!("Scheduled job {} can't be rescheduled: {}", job.id, e);
+                None
+            }
+        };
+        if let Err(e) = Ad4mDb::with_global_instance(|db| {
+            db.set_scheduled_job_next_run(&job.id, now, next_run_at)
+        }) {
+            log::error!("Failed to reschedule job {}: {}", job.id, e);
+            continue;
+        }
+
+        log::info!("Running scheduled job {} ({})", job.name, job.id);
+        let result = run_job(&job).await.map_err(|e| e.to_string());
+        if let Err(e) = &result {
+            log::error!("Scheduled job {} failed: {}", job.id, e);
+        }
+
+        let finished_at = Utc::now().timestamp();
+        if let Err(e) = Ad4mDb::with_global_instance(|db| {
+            db.add_scheduled_job_run(&job.id, now, finished_at, &result, MAX_RUNS_KEPT)
+        }) {
+            log::error!("Failed to record run of scheduled job {}: {}", job.id, e);
+        }
+    }
+}
+
+/// Runs due jobs one after another. Jobs that were due while the executor
+/// was not running are run once on startup.
+pub async fn run_scheduler_loop() {
+    loop {
+        run_due_jobs().await;
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-01-01 00:00:00 UTC, a Monday
+    const MIDNIGHT: i64 = 1704067200;
+
+    fn input(job_type: &str) -> ScheduledJobInput {
+        ScheduledJobInput {
+            perspective_uuid: "p1".to_string(),
+            name: "expire posts".to_string(),
+            schedule: "0 3 * * *".to_string(),
+            job_type: job_type.to_string(),
+            query: None,
+            commands: None,
+            task_id: None,
+            prompt: None,
+        }
+    }
+
+    #[test]
+    fn accepts_five_and_six_field_cron_expressions() {
+        assert_eq!(
+            next_run_after("0 3 * * *", MIDNIGHT).unwrap(),
+            MIDNIGHT + 3 * 3600
+        );
+        assert_eq!(
+            next_run_after("30 */15 * * * *", MIDNIGHT).unwrap(),
+            MIDNIGHT + 30
+        );
+        assert!(parse_schedule("every day").is_err());
+    }
+
+    #[test]
+    fn next_run_is_strictly_after() {
+        let first = next_run_after("*/5 * * * *", MIDNIGHT).unwrap();
+        assert_eq!(first, MIDNIGHT + 300);
+        assert_eq!(next_run_after("*/5 * * * *", first).unwrap(), first + 300);
+    }
+
+    #[test]
+    fn validates_job_types() {
+        assert!(validate_job(&input(PROLOG_JOB)).is_err());
+
+        let mut prolog = input(PROLOG_JOB);
+        prolog.query = Some("triple(Base, \"todo://state\", \"todo://done\")".to_string());
+        assert!(validate_job(&prolog).is_ok());
+        prolog.commands = Some("not json".to_string());
+        assert!(validate_job(&prolog).is_err());
+        prolog.commands = Some(
+            r#"[{"action": "removeLink", "source": "this", "predicate": "todo://state", "target": "todo://done"}]"#
+                .to_string(),
+        );
+        assert!(validate_job(&prolog).is_ok());
+
+        let mut ai = input(AI_TASK_JOB);
+        assert!(validate_job(&ai).is_err());
+        ai.task_id = Some("task".to_string());
+        ai.prompt = Some("Summarize today's posts".to_string());
+        assert!(validate_job(&ai).is_ok());
+
+        let mut bad_schedule = ai.clone();
+        bad_schedule.schedule = "whenever".to_string();
+        assert!(validate_job(&bad_schedule).is_err());
+        assert!(validate_job(&input("shell")).is_err());
+    }
+
+    #[test]
+    fn matches_become_command_parameters() {
+        let matches = match_parameters(r#"[{"Base": "post://1", "Age": 3}, {"Base": "post://2"}]"#);
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].0, Some("post://1".to_string()));
+        assert_eq!(matches[0].1.len(), 2);
+        assert!(matches[0]
+            .1
+            .contains(&Parameter::new("Age".to_string(), serde_json::json!(3))));
+        assert!(matches[0].1.contains(&Parameter::new(
+            "Base".to_string(),
+            serde_json::json!("post://1")
+        )));
+        assert_eq!(matches[1].0, Some("post://2".to_string()));
+
+        assert_eq!(match_parameters("true"), vec![(None, vec![])]);
+        assert!(match_parameters("false").is_empty());
+        assert!(match_parameters("[]").is_empty());
+    }
+}
@@ -0,0 +1,449 @@
+//! Transitive trust between agents.
+//!
+//! Agents vouch for other agents by publishing trust statements: signed links
+//! `<issuer> ad4m://trusts <subject>` in their public agent perspective. Starting from the
+//! trusted agents list, an agent becomes trusted once [`TrustConfig::threshold`] already trusted
+//! agents vouch for it, up to [`TrustConfig::max_depth`] statements away from the list.
+//!
+//! Statements of other agents are fetched and verified by [`refresh`] and cached in the
+//! database, so that checks during language installation don't have to go to the network.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use deno_core::anyhow::anyhow;
+use deno_core::error::AnyError;
+
+use super::RuntimeService;
+use crate::agent::signatures::verify;
+use crate::agent::{create_signed_expression, did, AgentService};
+use crate::db::Ad4mDb;
+use crate::graphql::graphql_types::{Agent, JsResultType, LinkStatus, Perspective};
+use crate::js_core::{rpc::JsRpc, JsCoreHandle};
+use crate::languages::LanguageController;
+use crate::types::{
+    DecoratedLinkExpression, Expression, Link, LinkExpression, TrustConfig, TrustReport,
+    TrustStatement,
+};
+
+pub const TRUST_PREDICATE: &str = "ad4m://trusts";
+/// Upper bound for [`TrustConfig::max_depth`], every level needs another round of fetching
+/// agent profiles in [`refresh`]
+pub const MAX_TRUST_DEPTH: i32 = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrustedAgent {
+    /// 0 for agents on the trusted agents list
+    pub depth: i32,
+    pub endorsers: Vec<String>,
+}
+
+pub fn validate_config(config: &TrustConfig) -> Result<(), String> {
+    if config.max_depth < 0 || config.max_depth > MAX_TRUST_DEPTH {
+        return Err(format!(
+            "Trust depth has to be between 0 and {}",
+            MAX_TRUST_DEPTH
+        ));
+    }
+    if config.threshold < 1 {
+        return Err("Trust threshold has to be at least 1".to_string());
+    }
+    Ok(())
+}
+
+pub fn config() -> TrustConfig {
+    Ad4mDb::with_global_instance(|db| db.get_trust_config())
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+pub fn set_config(config: TrustConfig) -> Result<TrustConfig, AnyError> {
+    validate_config(&config).map_err(|e| anyhow!(e))?;
+    Ad4mDb::with_global_instance(|db| db.set_trust_config(&config))?;
+    Ok(config)
+}
+
+/// All agents trusted under `config`, starting from the directly trusted `roots`
+pub fn trust_graph(
+    roots: &[String],
+    statements: &[TrustStatement],
+    config: &TrustConfig,
+) -> BTreeMap<String, TrustedAgent> {
+    let mut trusted: BTreeMap<String, TrustedAgent> = roots
+        .iter()
+        .map(|did| {
+            (
+                did.clone(),
+                TrustedAgent {
+                    depth: 0,
+                    endorsers: Vec::new(),
+                },
+            )
+        })
+        .collect();
+    let threshold = config.threshold.max(1) as usize;
+
+    for depth in 1..=config.max_depth {
+        let mut candidates: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for statement in statements {
+            if trusted.contains_key(&statement.issuer) && !trusted.contains_key(&statement.subject)
+            {
+                candidates
+                    .entry(statement.subject.as_str())
+                    .or_default()
+                    .insert(statement.issuer.as_str());
+            }
+        }
+
+        let newly_trusted: Vec<(String, TrustedAgent)> = candidates
+            .into_iter()
+            .filter(|(_, endorsers)| endorsers.len() >= threshold)
+            .map(|(subject, endorsers)| {
+                (
+                    subject.to_string(),
+                    TrustedAgent {
+                        depth,
+                        endorsers: endorsers.into_iter().map(str::to_string).collect(),
+                    },
+                )
+            })
+            .collect();
+        if newly_trusted.is_empty() {
+            break;
+        }
+        trusted.extend(newly_trusted);
+    }
+
+    trusted
+}
+
+/// Chain of agents from the trusted agents list to `did`, following the closest endorsers
+fn path_to(did: &str, graph: &BTreeMap<String, TrustedAgent>) -> Vec<String> {
+    let mut path = vec![did.to_string()];
+    let mut current = did.to_string();
+    while let Some(next) = graph.get(&current).and_then(|agent| {
+        agent
+            .endorsers
+            .iter()
+            .min_by_key(|endorser| graph.get(*endorser).map(|e| e.depth))
+    }) {
+        path.push(next.clone());
+        current = next.clone();
+    }
+    path.reverse();
+    path
+}
+
+pub fn report(
+    did: &str,
+    roots: &[String],
+    statements: &[TrustStatement],
+    config: &TrustConfig,
+) -> TrustReport {
+    let graph = trust_graph(roots, statements, config);
+    let vouching: Vec<String> = statements
+        .iter()
+        .filter(|s| s.subject == did && s.issuer != did && graph.contains_key(&s.issuer))
+        .map(|s| s.issuer.clone())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let (trusted, depth, path, reason) = match graph.get(did) {
+        Some(agent) if agent.depth == 0 => (
+            true,
+            Some(0),
+            vec![did.to_string()],
+            format!("{} is on the trusted agents list", did),
+        ),
+        Some(agent) => (
+            true,
+            Some(agent.depth),
+            path_to(did, &graph),
+            format!(
+                "{} is vouched for by {} ({} of {} required), {} statement(s) away from the trusted agents list",
+                did,
+                agent.endorsers.join(", "),
+                agent.endorsers.len(),
+                config.threshold,
+                agent.depth
+            ),
+        ),
+        None if config.max_depth == 0 => (
+            false,
+            None,
+            Vec::new(),
+            format!(
+                "{} is not on the trusted agents list and transitive trust is disabled",
+                did
+            ),
+        ),
+        None if vouching.is_empty() => (
+            false,
+            None,
+            Vec::new(),
+            format!(
+                "{} is not on the trusted agents list and no agent within {} statement(s) of it vouches for {}",
+                did, config.max_depth, did
+            ),
+        ),
+        None => (
+            false,
+            None,
+            Vec::new(),
+            format!(
+                "{} is only vouched for by {}, {} trusted agent(s) within {} statement(s) of the trusted agents list are required",
+                did,
+                vouching.join(", "),
+                config.threshold,
+                config.max_depth
+            ),
+        ),
+    };
+
+    TrustReport {
+        did: did.to_string(),
+        trusted,
+        depth,
+        endorsers: vouching,
+        path,
+        reason,
+    }
+}
+
+/// Trust statement carried by `link`, if it is one and its signature checks out
+pub fn statement_from_link(link: &DecoratedLinkExpression) -> Option<TrustStatement> {
+    if link.data.predicate.as_deref() != Some(TRUST_PREDICATE) || link.author != link.data.source {
+        return None;
+    }
+    let expression: Expression<Link> = LinkExpression::from(link.clone()).into();
+    if !verify(&expression).unwrap_or(false) {
+        log::warn!(
+            "Ignoring trust statement with invalid signature by {}",
+            link.author
+        );
+        return None;
+    }
+    Some(TrustStatement {
+        issuer: link.author.clone(),
+        subject: link.data.target.clone(),
+        timestamp: link.timestamp.clone(),
+        key: link.proof.key.clone(),
+        signature: link.proof.signature.clone(),
+    })
+}
+
+/// Valid trust statements `agent` published about others
+pub fn published_statements(agent: &Agent) -> Vec<TrustStatement> {
+    agent
+        .perspective
+        .iter()
+        .flat_map(|perspective| perspective.links.iter())
+        .filter_map(statement_from_link)
+        .filter(|statement| statement.issuer == agent.did && statement.subject != agent.did)
+        .collect()
+}
+
+fn roots() -> Vec<String> {
+    RuntimeService::with_global_instance(|runtime| runtime.get_trusted_agents())
+}
+
+/// Whether `did` is trusted according to the cached trust statements
+pub fn agent_trust(did: &str) -> Result<TrustReport, AnyError> {
+    let statements = Ad4mDb::with_global_instance(|db| db.get_trust_statements(None))?;
+    Ok(report(did, &roots(), &statements, &config()))
+}
+
+fn own_profile() -> Result<Agent, AnyError> {
+    AgentService::with_global_instance(|agent_service| agent_service.agent.clone())
+        .ok_or(anyhow!("Agent profile not found"))
+}
+
+/// Adds or removes the own trust statement about `subject` and publishes the agent profile
+pub async fn set_trust(
+    js: &mut JsCoreHandle,
+    subject: &str,
+    trusted: bool,
+) -> Result<Vec<TrustStatement>, AnyError> {
+    let me = did();
+    if subject == me {
+        return Err(anyhow!("Agents can't vouch for themselves"));
+    }
+
+    let agent = own_profile()?;
+    let mut links = agent
+        .perspective
+        .map(|perspective| perspective.links)
+        .unwrap_or_default();
+    links.retain(|link| {
+        !(link.data.predicate.as_deref() == Some(TRUST_PREDICATE)
+            && link.data.source == me
+            && link.data.target == subject)
+    });
+    if trusted {
+        let statement = create_signed_expression(Link {
+            source: me.clone(),
+            predicate: Some(TRUST_PREDICATE.to_string()),
+            target: subject.to_string(),
+        })?;
+        links.push((LinkExpression::from(statement), LinkStatus::Shared).into());
+    }
+
+    let result: JsResultType<Agent> = js
+        .rpc(JsRpc::mutation(
+            "agentUpdatePublicPerspective",
+            serde_json::json!({ "perspective": Perspective { links } }),
+        ))
+        .await?;
+    let agent = match result {
+        JsResultType::Ok(agent) => agent,
+        JsResultType::Error(error) => return Err(anyhow!(error)),
+    };
+
+    let statements = published_statements(&agent);
+    Ad4mDb::with_global_instance(|db| db.set_trust_statements(&me, &statements))?;
+    Ok(statements)
+}
+
+/// Fetches the statements of every trusted agent that can still pass on trust,
+/// level by level, and returns how many agents got fetched
+pub async fn refresh() -> Result<usize, AnyError> {
+    let config = config();
+    let roots = roots();
+    let me = did();
+    let mut fetched: BTreeSet<String> = BTreeSet::new();
+
+    loop {
+        let statements = Ad4mDb::with_global_instance(|db| db.get_trust_statements(None))?;
+        let issuers: Vec<String> = trust_graph(&roots, &statements, &config)
+            .into_iter()
+            .filter(|(did, agent)| agent.depth < config.max_depth && !fetched.contains(did))
+            .map(|(did, _)| did)
+            .collect();
+        if issuers.is_empty() {
+            break;
+        }
+
+        for issuer in issuers {
+            fetched.insert(issuer.clone());
+            let agent = if issuer == me {
+                own_profile().ok()
+            } else {
+                match LanguageController::agent_by_did(&issuer).await {
+                    Ok(agent) => agent,
+                    Err(e) => {
+                        log::warn!("Couldn't fetch trust statements of {}: {}", issuer, e);
+                        continue;
+                    }
+                }
+            };
+            let statements = agent
+                .map(|agent| published_statements(&agent))
+                .unwrap_or_default();
+            Ad4mDb::with_global_instance(|db| db.set_trust_statements(&issuer, &statements))?;
+        }
+    }
+
+    Ok(fetched.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statement(issuer: &str, subject: &str) -> TrustStatement {
+        TrustStatement {
+            issuer: issuer.to_string(),
+            subject: subject.to_string(),
+            timestamp: "2024-01-01T00:00:00.000Z".to_string(),
+            key: format!("{}#key", issuer),
+            signature: String::new(),
+        }
+    }
+
+    fn config(max_depth: i32, threshold: i32) -> TrustConfig {
+        TrustConfig {
+            max_depth,
+            threshold,
+        }
+    }
+
+    fn roots() -> Vec<String> {
+        vec!["did:root:a".to_string(), "did:root:b".to_string()]
+    }
+
+    fn statements() -> Vec<TrustStatement> {
+        vec![
+            statement("did:root:a", "did:one"),
+            statement("did:root:b", "did:one"),
+            statement("did:root:a", "did:two"),
+            statement("did:one", "did:three"),
+            statement("did:two", "did:three"),
+            statement("did:three", "did:four"),
+        ]
+    }
+
+    #[test]
+    fn trust_does_not_spread_without_depth() {
+        let graph = trust_graph(&roots(), &statements(), &TrustConfig::default());
+        assert_eq!(graph.keys().cloned().collect::<Vec<_>>(), roots());
+
+        let trust = report("did:one", &roots(), &statements(), &TrustConfig::default());
+        assert!(!trust.trusted);
+        assert!(trust.reason.contains("transitive trust is disabled"));
+    }
+
+    #[test]
+    fn trust_spreads_level_by_level_up_to_the_depth() {
+        let graph = trust_graph(&roots(), &statements(), &config(2, 1));
+        assert_eq!(graph["did:one"].depth, 1);
+        assert_eq!(graph["did:two"].depth, 1);
+        assert_eq!(graph["did:three"].depth, 2);
+        assert_eq!(
+            graph["did:three"].endorsers,
+            vec!["did:one".to_string(), "did:two".to_string()]
+        );
+        assert!(!graph.contains_key("did:four"));
+
+        let trust = report("did:three", &roots(), &statements(), &config(2, 1));
+        assert!(trust.trusted);
+        assert_eq!(trust.depth, Some(2));
+        assert_eq!(trust.path.first().map(String::as_str), Some("did:root:a"));
+        assert_eq!(trust.path.last().map(String::as_str), Some("did:three"));
+        assert_eq!(trust.path.len(), 3);
+
+        let trust = report("did:four", &roots(), &statements(), &config(2, 1));
+        assert!(!trust.trusted);
+        assert_eq!(trust.endorsers, vec!["did:three".to_string()]);
+    }
+
+    #[test]
+    fn threshold_requires_independent_endorsers() {
+        let graph = trust_graph(&roots(), &statements(), &config(3, 2));
+        assert_eq!(graph["did:one"].depth, 1);
+        assert!(!graph.contains_key("did:two"));
+        // did:two isn't trusted, so did:three only has one trusted endorser
+        assert!(!graph.contains_key("did:three"));
+
+        let trust = report("did:two", &roots(), &statements(), &config(3, 2));
+        assert!(!trust.trusted);
+        assert_eq!(trust.endorsers, vec!["did:root:a".to_string()]);
+        assert!(trust.reason.contains("2 trusted agent(s)"));
+    }
+
+    #[test]
+    fn repeated_statements_count_once() {
+        let mut statements = statements();
+        statements.push(statement("did:root:a", "did:two"));
+        let graph = trust_graph(&roots(), &statements, &config(1, 2));
+        assert!(!graph.contains_key("did:two"));
+    }
+
+    #[test]
+    fn config_bounds_are_validated() {
+        assert!(validate_config(&TrustConfig::default()).is_ok());
+        assert!(validate_config(&config(-1, 1)).is_err());
+        assert!(validate_config(&config(MAX_TRUST_DEPTH + 1, 1)).is_err());
+        assert!(validate_config(&config(2, 0)).is_err());
+    }
+}
@@ -0,0 +1,370 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use tokio::sync::Notify;
+
+use crate::db::Ad4mDb;
+use crate::types::Notification;
+
+/// Deliveries are given up after this many failed attempts
+pub const MAX_DELIVERY_ATTEMPTS: u32 = 8;
+const BASE_RETRY_DELAY_SECS: i64 = 10;
+const MAX_RETRY_DELAY_SECS: i64 = 60 * 60;
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+pub const DELIVERY_ID_HEADER: &str = "X-Ad4m-Delivery";
+pub const TIMESTAMP_HEADER: &str = "X-Ad4m-Timestamp";
+pub const SIGNATURE_HEADER: &str = "X-Ad4m-Signature";
+
+lazy_static! {
+    static ref DELIVERY_WAKEUP: Arc<Notify> = Arc::new(Notify::new());
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueuedWebhookDelivery {
+    pub id: String,
+    pub notification_id: String,
+    pub url: String,
+    pub payload: String,
+    /// Number of attempts made so far
+    pub attempts: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliveryOutcome {
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+}
+
+impl DeliveryOutcome {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none() && matches!(self.status_code, Some(200..=299))
+    }
+
+    /// Client errors won't go away by retrying, except for timeouts and throttling
+    pub fn is_permanent_failure(&self) -> bool {
+        matches!(self.status_code, Some(code) if (400..500).contains(&code) && code != 408 && code != 429)
+    }
+}
+
+/// Exponential backoff: 10s, 20s, 40s, ... capped at one hour
+pub fn retry_delay_secs(attempts: u32) -> i64 {
+    let exponent = attempts.saturating_sub(1).min(16);
+    (BASE_RETRY_DELAY_SECS << exponent).min(MAX_RETRY_DELAY_SECS)
+}
+
+/// When to try again after `attempts` attempts ended with `outcome`, if at all
+pub fn next_attempt_at(outcome: &DeliveryOutcome, attempts: u32, now: i64) -> Option<i64> {
+    if outcome.is_success() || outcome.is_permanent_failure() || attempts >= MAX_DELIVERY_ATTEMPTS {
+        None
+    } else {
+        Some(now + retry_delay_secs(attempts))
+    }
+}
+
+/// Random per-notification key for [`sign_payload`]. Unlike the webhook auth it is never
+/// sent to the receiver, the installing app gets it by rotating the secret.
+pub fn generate_signing_secret() -> String {
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    hex::encode(secret)
+}
+
+/// Hex encoded HMAC-SHA256 over `"{timestamp}.{payload}"`, keyed with the notification's signing secret.
+/// Receivers recompute it to check that the payload came from this agent and was not replayed.
+pub fn sign_payload(secret: &str, timestamp: i64, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take keys of any size");
+    mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Persists a webhook delivery for the triggered notification and wakes up the delivery loop
+pub fn enqueue(notification: &Notification, payload: String) {
+    let now = chrono::Utc::now().timestamp();
+    match Ad4mDb::with_global_instance(|db| {
+        db.enqueue_webhook_delivery(&notification.id, &notification.webhook_url, &payload, now)
+    }) {
+        Ok(id) => {
+            log::info!(
+                "Notification webhook - queued delivery {} to {:?}",
+                id,
+                notification.webhook_url
+            );
+            DELIVERY_WAKEUP.notify_one();
+        }
+        Err(e) => log::error!("Failed to queue notification webhook delivery: {}", e),
+    }
+}
+
+pub async fn deliver(
+    client: &reqwest::Client,
+    delivery: &QueuedWebhookDelivery,
+    webhook_auth: &str,
+    signing_secret: &str,
+    now: i64,
+) -> DeliveryOutcome {
+    let response = client
+        .post(&delivery.url)
+        .bearer_auth(webhook_auth)
+        .header("Content-Type", "application/json")
+        .header(DELIVERY_ID_HEADER, &delivery.id)
+        .header(TIMESTAMP_HEADER, now.to_string())
+        .header(
+            SIGNATURE_HEADER,
+            format!(
+                "sha256={}",
+                sign_payload(signing_secret, now, &delivery.payload)
+            ),
+        )
+        .timeout(REQUEST_TIMEOUT)
+        .body(delivery.payload.clone())
+        .send()
+        .await;
+
+    match response {
+        Ok(response) if response.status().is_success() => DeliveryOutcome {
+            status_code: Some(response.status().as_u16()),
+            error: None,
+        },
+        Ok(response) => DeliveryOutcome {
+            status_code: Some(response.status().as_u16()),
+            error: Some(format!("Webhook responded with {}", response.status())),
+        },
+        Err(e) => DeliveryOutcome {
+            status_code: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// Attempts all deliveries that are due and records the outcomes
+async fn deliver_due(client: &reqwest::Client) {
+    let now = chrono::Utc::now().timestamp();
+    let due = match Ad4mDb::with_global_instance(|db| db.get_due_webhook_deliveries(now)) {
+        Ok(due) => due,
+        Err(e) => {
+            log::error!("Failed to load due webhook deliveries: {}", e);
+            return;
+        }
+    };
+
+    for delivery in due {
+        let notification = Ad4mDb::with_global_instance(|db| {
+            db.get_notification(delivery.notification_id.clone())
+        })
+        .ok()
+        .flatten();
+
+        let attempts = delivery.attempts + 1;
+        let (outcome, retry_at) = match notification {
+            Some(notification) if notification.granted => {
+                let outcome = deliver(
+                    client,
+                    &delivery,
+                    &notification.webhook_auth,
+                    &notification.webhook_signing_secret,
+                    now,
+                )
+                .await;
+                let retry_at = next_attempt_at(&outcome, attempts, now);
+                (outcome, retry_at)
+            }
+            Some(_) => (
+                DeliveryOutcome {
+                    status_code: None,
+                    error: Some("Notification is not granted".to_string()),
+                },
+                None,
+            ),
+            None => (
+                DeliveryOutcome {
+                    status_code: None,
+                    error: Some("Notification was removed".to_string()),
+                },
+                None,
+            ),
+        };
+
+        log::info!(
+            "Notification webhook - delivery {} attempt {}: {:?}",
+            delivery.id,
+            attempts,
+            outcome
+        );
+
+        if let Err(e) = Ad4mDb::with_global_instance(|db| {
+            db.record_webhook_delivery_attempt(&delivery.id, &outcome, now, retry_at)
+        }) {
+            log::error!("Failed to record webhook delivery attempt: {}", e);
+        }
+    }
+}
+
+/// Works through the delivery queue, on a fixed interval and whenever new deliveries get queued.
+/// Deliveries pending from before a restart are picked up on the first run.
+pub async fn run_delivery_loop() {
+    let client = reqwest::Client::new();
+    loop {
+        deliver_due(&client).await;
+        tokio::select! {
+            _ = DELIVERY_WAKEUP.notified() => {},
+            _ = tokio::time::sleep(POLL_INTERVAL) => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use tokio::sync::mpsc;
+    use warp::http::{HeaderMap, StatusCode};
+    use warp::Filter;
+
+    struct ReceivedRequest {
+        headers: HeaderMap,
+        body: String,
+    }
+
+    /// Local webhook receiver answering every request with `status`
+    async fn mock_receiver(
+        status: StatusCode,
+    ) -> (SocketAddr, mpsc::UnboundedReceiver<ReceivedRequest>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let route = warp::post()
+            .and(warp::header::headers_cloned())
+            .and(warp::body::bytes())
+            .map(move |headers: HeaderMap, body: warp::hyper::body::Bytes| {
+                let _ = sender.send(ReceivedRequest {
+                    headers,
+                    body: String::from_utf8_lossy(&body).to_string(),
+                });
+                warp::reply::with_status("", status)
+            });
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (address, receiver)
+    }
+
+    fn queued(address: SocketAddr) -> QueuedWebhookDelivery {
+        QueuedWebhookDelivery {
+            id: "delivery-1".to_string(),
+            notification_id: "notification-1".to_string(),
+            url: format!("http://{}/hook", address),
+            payload: r#"{"perspectiveId":"p1"}"#.to_string(),
+            attempts: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn delivers_signed_payload() {
+        let (address, mut received) = mock_receiver(StatusCode::OK).await;
+        let delivery = queued(address);
+
+        let outcome = deliver(
+            &reqwest::Client::new(),
+            &delivery,
+            "token",
+            "signing-secret",
+            1700000000,
+        )
+        .await;
+        assert!(outcome.is_success());
+        assert_eq!(outcome.status_code, Some(200));
+
+        let request = received.recv().await.expect("mock to receive the webhook");
+        assert_eq!(request.body, delivery.payload);
+        assert_eq!(request.headers["authorization"], "Bearer token");
+        assert_eq!(request.headers[DELIVERY_ID_HEADER], "delivery-1");
+        assert_eq!(request.headers[TIMESTAMP_HEADER], "1700000000");
+        assert_eq!(
+            request.headers[SIGNATURE_HEADER].to_str().unwrap(),
+            format!(
+                "sha256={}",
+                sign_payload("signing-secret", 1700000000, &delivery.payload)
+            )
+        );
+        // The key of the signature never goes over the wire
+        assert!(!format!("{:?}", request.headers).contains("signing-secret"));
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried_client_errors_are_not() {
+        let (address, _received) = mock_receiver(StatusCode::SERVICE_UNAVAILABLE).await;
+        let outcome = deliver(
+            &reqwest::Client::new(),
+            &queued(address),
+            "token",
+            "secret",
+            0,
+        )
+        .await;
+        assert!(!outcome.is_success());
+        assert_eq!(outcome.status_code, Some(503));
+        assert_eq!(next_attempt_at(&outcome, 1, 100), Some(110));
+
+        let (address, _received) = mock_receiver(StatusCode::NOT_FOUND).await;
+        let outcome = deliver(
+            &reqwest::Client::new(),
+            &queued(address),
+            "token",
+            "secret",
+            0,
+        )
+        .await;
+        assert!(outcome.is_permanent_failure());
+        assert_eq!(next_attempt_at(&outcome, 1, 100), None);
+    }
+
+    #[tokio::test]
+    async fn unreachable_receivers_are_retried() {
+        // Grab a free port and close it again so nothing is listening there
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let outcome = deliver(
+            &reqwest::Client::new(),
+            &queued(address),
+            "token",
+            "secret",
+            0,
+        )
+        .await;
+        assert_eq!(outcome.status_code, None);
+        assert!(outcome.error.is_some());
+        assert!(next_attempt_at(&outcome, 1, 0).is_some());
+    }
+
+    #[test]
+    fn retry_delay_backs_off_exponentially_up_to_a_cap() {
+        assert_eq!(retry_delay_secs(1), 10);
+        assert_eq!(retry_delay_secs(2), 20);
+        assert_eq!(retry_delay_secs(3), 40);
+        assert_eq!(retry_delay_secs(100), MAX_RETRY_DELAY_SECS);
+    }
+
+    #[test]
+    fn deliveries_give_up_after_max_attempts() {
+        let failure = DeliveryOutcome {
+            status_code: Some(500),
+            error: Some("Internal Server Error".to_string()),
+        };
+        assert!(next_attempt_at(&failure, MAX_DELIVERY_ATTEMPTS - 1, 0).is_some());
+        assert_eq!(next_attempt_at(&failure, MAX_DELIVERY_ATTEMPTS, 0), None);
+    }
+
+    #[test]
+    fn signature_depends_on_secret_timestamp_and_payload() {
+        let signature = sign_payload("secret", 1, "payload");
+        assert_eq!(signature, sign_payload("secret", 1, "payload"));
+        assert_ne!(signature, sign_payload("other", 1, "payload"));
+        assert_ne!(signature, sign_payload("secret", 2, "payload"));
+        assert_ne!(signature, sign_payload("secret", 1, "payload2"));
+    }
+}
@@ -1,11 +1,11 @@
 use crate::agent::AgentService;
-use crate::wallet::Wallet;
+use crate::wallet::{Wallet, MAIN_KEY_NAME};
 
 pub fn setup_wallet() {
     let wallet_instance = Wallet::instance();
     let mut wallet = wallet_instance.lock().expect("wallet lock");
     let wallet_ref = wallet.as_mut().expect("wallet instance");
-    wallet_ref.generate_keypair("main".to_string());
+    wallet_ref.generate_keypair(MAIN_KEY_NAME.to_string());
 }
 
 pub fn setup_agent() {
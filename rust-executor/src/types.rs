@@ -1,4 +1,4 @@
-use coasys_juniper::{GraphQLEnum, GraphQLObject, GraphQLValue};
+use coasys_juniper::{graphql_object, GraphQLEnum, GraphQLObject, GraphQLValue};
 use deno_core::{anyhow::anyhow, error::AnyError};
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
@@ -6,9 +6,10 @@ use std::str::FromStr;
 use url::Url;
 
 use crate::{
-    agent::signatures::verify,
+    agent::signatures::{check_signature, SignatureCheck},
     graphql::graphql_types::{
-        LinkExpressionInput, LinkInput, LinkStatus, NotificationInput, PerspectiveInput,
+        LinkExpressionInput, LinkInput, LinkStatus, NotificationChannelInput, NotificationInput,
+        NotificationThrottleInput, PerspectiveInput,
     },
 };
 use regex::Regex;
@@ -43,22 +44,33 @@ pub struct DecoratedExpressionProof {
     pub signature: String,
     pub valid: Option<bool>,
     pub invalid: Option<bool>,
+    /// Signed with a key the author has rotated away from since, see [`SignatureCheck`]
+    #[serde(rename = "retiredKey")]
+    pub retired_key: Option<bool>,
+}
+
+impl DecoratedExpressionProof {
+    pub fn tag(&mut self, check: SignatureCheck) {
+        self.valid = Some(check.valid);
+        self.invalid = Some(!check.valid);
+        self.retired_key = Some(check.retired_key);
+    }
 }
 
 impl<T: GraphQLValue + Serialize> From<Expression<T>> for VerifiedExpression<T> {
     fn from(expr: Expression<T>) -> Self {
-        let valid = verify(&expr).unwrap_or(false);
-        let invalid = !valid;
+        let check = check_signature(&expr).unwrap_or_default();
+        let mut proof = DecoratedExpressionProof {
+            key: expr.proof.key,
+            signature: expr.proof.signature,
+            ..Default::default()
+        };
+        proof.tag(check);
         VerifiedExpression {
             author: expr.author,
             timestamp: expr.timestamp,
             data: expr.data,
-            proof: DecoratedExpressionProof {
-                key: expr.proof.key,
-                signature: expr.proof.signature,
-                valid: Some(valid),
-                invalid: Some(invalid),
-            },
+            proof,
         }
     }
 }
@@ -189,9 +201,8 @@ impl DecoratedLinkExpression {
                 signature: self.proof.signature.clone(),
             },
         };
-        let valid = verify(&link_expr).unwrap_or(false);
-        self.proof.valid = Some(valid);
-        self.proof.invalid = Some(!valid);
+        self.proof
+            .tag(check_signature(&link_expr).unwrap_or_default());
     }
 }
 
@@ -372,7 +383,9 @@ impl PerspectiveDiff {
     }
 }
 
-#[derive(GraphQLObject, Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// `webhook_auth` is write-only, GraphQL only tells whether one is set.
+/// The signing secret is not readable through GraphQL at all.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "camelCase")]
 pub struct Notification {
     pub id: String,
@@ -384,7 +397,226 @@ pub struct Notification {
     pub trigger: String,
     pub perspective_ids: Vec<String>,
     pub webhook_url: String,
+    /// Left out of triggered notification payloads, see [`TriggeredNotification::public_payload`]
+    #[serde(default)]
     pub webhook_auth: String,
+    /// Key for the HMAC in the webhook signature header, generated by the executor
+    /// and never sent along with deliveries. Not readable through GraphQL, the installing
+    /// app gets it from `runtimeRotateNotificationWebhookSecret`.
+    #[serde(default)]
+    pub webhook_signing_secret: String,
+    /// What kind of event the trigger is checked against, see [`NotificationTriggerType`]
+    pub trigger_type: String,
+    /// Where triggered notifications get delivered besides the subscription and webhook
+    #[serde(default)]
+    pub channels: Vec<NotificationChannel>,
+    pub throttle: Option<NotificationThrottle>,
+}
+
+#[graphql_object]
+impl Notification {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn granted(&self) -> bool {
+        self.granted
+    }
+
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    fn app_name(&self) -> String {
+        self.app_name.clone()
+    }
+
+    fn app_url(&self) -> String {
+        self.app_url.clone()
+    }
+
+    fn app_icon_path(&self) -> String {
+        self.app_icon_path.clone()
+    }
+
+    fn trigger(&self) -> String {
+        self.trigger.clone()
+    }
+
+    fn perspective_ids(&self) -> Vec<String> {
+        self.perspective_ids.clone()
+    }
+
+    fn webhook_url(&self) -> String {
+        self.webhook_url.clone()
+    }
+
+    fn has_webhook_auth(&self) -> bool {
+        !self.webhook_auth.is_empty()
+    }
+
+    fn trigger_type(&self) -> String {
+        self.trigger_type.clone()
+    }
+
+    fn channels(&self) -> Vec<NotificationChannel> {
+        self.channels.clone()
+    }
+
+    fn throttle(&self) -> Option<NotificationThrottle> {
+        self.throttle.clone()
+    }
+}
+
+/// Limits how often a notification gets delivered on each perspective. Matches that can't
+/// be delivered right away are queued and sent later as one digest per perspective.
+/// - `max_deliveries` per `window_seconds`
+/// - `digest_interval_minutes`: always batch matches, sending at most one digest per interval
+/// - `quiet_hours_start`/`quiet_hours_end` as "HH:MM", in UTC shifted by `utc_offset_minutes`
+#[derive(
+    GraphQLObject, Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationThrottle {
+    pub max_deliveries: Option<i32>,
+    pub window_seconds: Option<i32>,
+    pub digest_interval_minutes: Option<i32>,
+    pub quiet_hours_start: Option<String>,
+    pub quiet_hours_end: Option<String>,
+    pub utc_offset_minutes: Option<i32>,
+}
+
+impl From<NotificationThrottleInput> for NotificationThrottle {
+    fn from(input: NotificationThrottleInput) -> Self {
+        NotificationThrottle {
+            max_deliveries: input.max_deliveries,
+            window_seconds: input.window_seconds,
+            digest_interval_minutes: input.digest_interval_minutes,
+            quiet_hours_start: input.quiet_hours_start,
+            quiet_hours_end: input.quiet_hours_end,
+            utc_offset_minutes: input.utc_offset_minutes,
+        }
+    }
+}
+
+/// A delivery channel for triggered notifications. Fields are interpreted per `channel_type`:
+/// - `email`: `url` is the SMTP server (`smtp://` or `smtps://`, credentials can be given
+///   in the URL or `username`/`secret`), `recipient` the address to send to, `sender` the from address
+/// - `ntfy`: `url` is the topic URL, `secret` an optional access token
+/// - `matrix`: `url` is the homeserver, `recipient` the room ID, `secret` the access token
+///
+/// Templates can use `{{description}}`, `{{appName}}`, `{{perspectiveId}}`, `{{match}}`
+/// and the variables bound by the trigger match, e.g. `{{Message}}`.
+///
+/// `secret` is write-only, GraphQL only tells whether one is set.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationChannel {
+    pub channel_type: String,
+    pub url: String,
+    pub recipient: Option<String>,
+    pub sender: Option<String>,
+    pub username: Option<String>,
+    pub secret: Option<String>,
+    pub title_template: Option<String>,
+    pub body_template: Option<String>,
+}
+
+#[graphql_object]
+impl NotificationChannel {
+    fn channel_type(&self) -> String {
+        self.channel_type.clone()
+    }
+
+    fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    fn recipient(&self) -> Option<String> {
+        self.recipient.clone()
+    }
+
+    fn sender(&self) -> Option<String> {
+        self.sender.clone()
+    }
+
+    fn username(&self) -> Option<String> {
+        self.username.clone()
+    }
+
+    fn has_secret(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    fn title_template(&self) -> Option<String> {
+        self.title_template.clone()
+    }
+
+    fn body_template(&self) -> Option<String> {
+        self.body_template.clone()
+    }
+}
+
+impl From<NotificationChannelInput> for NotificationChannel {
+    fn from(input: NotificationChannelInput) -> Self {
+        NotificationChannel {
+            channel_type: input.channel_type,
+            url: input.url,
+            recipient: input.recipient,
+            sender: input.sender,
+            username: input.username,
+            secret: input.secret,
+            title_template: input.title_template,
+            body_template: input.body_template,
+        }
+    }
+}
+
+/// Perspective ID that makes a notification active on all perspectives
+pub const ALL_PERSPECTIVES: &str = "*";
+
+/// The events a notification can be triggered by, each with its own
+/// meaning of `Notification.trigger` and its own match payload:
+/// - `prolog`: Prolog query run on link changes, matches are the variable bindings
+/// - `telepresenceSignal`: optional link predicate that a received signal has to contain,
+///   the match is the signal
+/// - `directMessage`: optional author DID of direct messages arriving in the inbox,
+///   the match is the message
+/// - `neighbourhoodJoined`: neighbourhood URL or `*` for any,
+///   the match is the URL together with the new perspective
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum NotificationTriggerType {
+    #[default]
+    Prolog,
+    TelepresenceSignal,
+    DirectMessage,
+    NeighbourhoodJoined,
+}
+
+impl FromStr for NotificationTriggerType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" | "prolog" => Ok(NotificationTriggerType::Prolog),
+            "telepresenceSignal" => Ok(NotificationTriggerType::TelepresenceSignal),
+            "directMessage" => Ok(NotificationTriggerType::DirectMessage),
+            "neighbourhoodJoined" => Ok(NotificationTriggerType::NeighbourhoodJoined),
+            _ => Err(format!("Unknown notification trigger type: {}", s)),
+        }
+    }
+}
+
+impl Display for NotificationTriggerType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotificationTriggerType::Prolog => write!(f, "prolog"),
+            NotificationTriggerType::TelepresenceSignal => write!(f, "telepresenceSignal"),
+            NotificationTriggerType::DirectMessage => write!(f, "directMessage"),
+            NotificationTriggerType::NeighbourhoodJoined => write!(f, "neighbourhoodJoined"),
+        }
+    }
 }
 
 #[derive(GraphQLObject, Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -410,6 +642,18 @@ pub struct AITask {
 }
 
 impl Notification {
+    /// JSON without the channels, webhook auth and signing secret
+    /// so that their credentials don't leave the executor
+    pub fn public_json(&self) -> serde_json::Value {
+        let mut json = serde_json::to_value(self).expect("Notification to serialize");
+        if let Some(notification) = json.as_object_mut() {
+            notification.remove("channels");
+            notification.remove("webhookAuth");
+            notification.remove("webhookSigningSecret");
+        }
+        json
+    }
+
     pub fn from_input_and_id(id: String, input: NotificationInput) -> Self {
         Notification {
             id,
@@ -422,7 +666,46 @@ impl Notification {
             perspective_ids: input.perspective_ids,
             webhook_url: input.webhook_url,
             webhook_auth: input.webhook_auth,
+            webhook_signing_secret: String::new(),
+            trigger_type: input
+                .trigger_type
+                .unwrap_or_else(|| NotificationTriggerType::Prolog.to_string()),
+            channels: input
+                .channels
+                .unwrap_or_default()
+                .into_iter()
+                .map(NotificationChannel::from)
+                .collect(),
+            throttle: input.throttle.map(NotificationThrottle::from),
+        }
+    }
+
+    /// Since the secrets can't be read back, an update that leaves them out
+    /// keeps the ones of the notification it replaces
+    pub fn keeping_secrets_of(mut self, existing: &Notification) -> Self {
+        if self.webhook_auth.is_empty() {
+            self.webhook_auth = existing.webhook_auth.clone();
+        }
+        self.webhook_signing_secret = existing.webhook_signing_secret.clone();
+        for channel in self.channels.iter_mut().filter(|c| c.secret.is_none()) {
+            channel.secret = existing
+                .channels
+                .iter()
+                .find(|c| c.channel_type == channel.channel_type && c.url == channel.url)
+                .and_then(|c| c.secret.clone());
         }
+        self
+    }
+
+    /// Unknown trigger types never match anything
+    pub fn trigger_type(&self) -> Option<NotificationTriggerType> {
+        NotificationTriggerType::from_str(&self.trigger_type).ok()
+    }
+
+    pub fn is_active_on(&self, perspective_uuid: &str) -> bool {
+        self.perspective_ids
+            .iter()
+            .any(|id| id == ALL_PERSPECTIVES || id == perspective_uuid)
     }
 }
 
@@ -430,10 +713,271 @@ impl Notification {
 #[serde(rename_all = "camelCase")]
 pub struct TriggeredNotification {
     pub notification: Notification,
+    /// Empty for triggers that are not tied to a perspective, like direct messages
     pub perspective_id: String,
     pub trigger_match: String,
 }
 
+impl TriggeredNotification {
+    /// JSON for the subscription and the webhook, see [`Notification::public_json`]
+    pub fn public_payload(&self) -> String {
+        let mut payload = serde_json::to_value(self).expect("TriggeredNotification to serialize");
+        if let Some(notification) = payload.get_mut("notification") {
+            *notification = self.notification.public_json();
+        }
+        payload.to_string()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+impl Display for WebhookDeliveryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookDeliveryStatus::Pending => write!(f, "pending"),
+            WebhookDeliveryStatus::Delivered => write!(f, "delivered"),
+            WebhookDeliveryStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// Delivery state of a direct message in the outbox
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum MessageDeliveryStatus {
+    /// Not delivered yet, retried until the recipient can be reached
+    Queued,
+    /// Sent directly to the recipient while they were online
+    SentP2P,
+    /// Stored in the recipient's inbox, to be fetched when they come online
+    StoredInInbox,
+    /// Given up after too many failed attempts
+    Failed,
+}
+
+impl Display for MessageDeliveryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessageDeliveryStatus::Queued => write!(f, "queued"),
+            MessageDeliveryStatus::SentP2P => write!(f, "sentP2P"),
+            MessageDeliveryStatus::StoredInInbox => write!(f, "storedInInbox"),
+            MessageDeliveryStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl FromStr for MessageDeliveryStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queued" => Ok(MessageDeliveryStatus::Queued),
+            "sentP2P" => Ok(MessageDeliveryStatus::SentP2P),
+            "storedInInbox" => Ok(MessageDeliveryStatus::StoredInInbox),
+            "failed" => Ok(MessageDeliveryStatus::Failed),
+            _ => Err(format!("Unknown message delivery status: {}", s)),
+        }
+    }
+}
+
+/// State of the friendship handshake with another agent
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FriendRequestState {
+    Pending,
+    /// Both sides agreed, we are friends
+    Accepted,
+    Rejected,
+}
+
+impl Display for FriendRequestState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FriendRequestState::Pending => write!(f, "pending"),
+            FriendRequestState::Accepted => write!(f, "accepted"),
+            FriendRequestState::Rejected => write!(f, "rejected"),
+        }
+    }
+}
+
+impl FromStr for FriendRequestState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(FriendRequestState::Pending),
+            "accepted" => Ok(FriendRequestState::Accepted),
+            "rejected" => Ok(FriendRequestState::Rejected),
+            _ => Err(format!("Unknown friend request state: {}", s)),
+        }
+    }
+}
+
+/// Who asked whom in a friendship handshake
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FriendRequestDirection {
+    /// The other agent asked us
+    Incoming,
+    /// We asked the other agent
+    Outgoing,
+}
+
+impl Display for FriendRequestDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FriendRequestDirection::Incoming => write!(f, "incoming"),
+            FriendRequestDirection::Outgoing => write!(f, "outgoing"),
+        }
+    }
+}
+
+impl FromStr for FriendRequestDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "incoming" => Ok(FriendRequestDirection::Incoming),
+            "outgoing" => Ok(FriendRequestDirection::Outgoing),
+            _ => Err(format!("Unknown friend request direction: {}", s)),
+        }
+    }
+}
+
+/// The friendship handshake with one agent, see [`crate::runtime_service::friend_requests`]
+#[derive(GraphQLObject, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FriendRequest {
+    pub did: String,
+    /// "incoming" or "outgoing"
+    pub direction: String,
+    /// "pending", "accepted" or "rejected"
+    pub state: String,
+    /// Note the request was sent with
+    pub message: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl FriendRequest {
+    pub fn direction(&self) -> Option<FriendRequestDirection> {
+        FriendRequestDirection::from_str(&self.direction).ok()
+    }
+
+    pub fn state(&self) -> Option<FriendRequestState> {
+        FriendRequestState::from_str(&self.state).ok()
+    }
+}
+
+/// Signed claim by `issuer` that it trusts `subject`, published as a link in the issuer's
+/// public agent perspective, see [`crate::runtime_service::web_of_trust`]
+#[derive(GraphQLObject, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustStatement {
+    pub issuer: String,
+    pub subject: String,
+    pub timestamp: String,
+    /// Signing key of the issuer
+    pub key: String,
+    pub signature: String,
+}
+
+#[derive(GraphQLObject, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustConfig {
+    /// How many trust statements away from a directly trusted agent trust reaches,
+    /// 0 only trusts the trusted agents list
+    pub max_depth: i32,
+    /// How many already trusted agents need to vouch for an agent to trust it
+    pub threshold: i32,
+}
+
+impl Default for TrustConfig {
+    fn default() -> Self {
+        TrustConfig {
+            max_depth: 0,
+            threshold: 1,
+        }
+    }
+}
+
+/// Whether an agent is trusted and why
+#[derive(GraphQLObject, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustReport {
+    pub did: String,
+    pub trusted: bool,
+    /// Number of trust statements between the agent and the trusted agents list
+    pub depth: Option<i32>,
+    /// Trusted agents that vouch for the agent
+    pub endorsers: Vec<String>,
+    /// Chain of agents from a directly trusted agent to this one
+    pub path: Vec<String>,
+    pub reason: String,
+}
+
+#[derive(GraphQLObject, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDeliveryAttempt {
+    pub attempt: i32,
+    pub timestamp: String,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub success: bool,
+}
+
+/// A triggered notification queued for delivery to its webhook
+#[derive(GraphQLObject, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub notification_id: String,
+    pub url: String,
+    /// "pending", "delivered" or "failed"
+    pub status: String,
+    pub created_at: String,
+    pub next_attempt_at: Option<String>,
+    pub attempts: Vec<WebhookDeliveryAttempt>,
+}
+
+/// A periodic job bound to a perspective, see [`crate::runtime_service::scheduler`]
+#[derive(GraphQLObject, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledJob {
+    pub id: String,
+    pub perspective_uuid: String,
+    pub name: String,
+    /// Cron expression, evaluated in UTC
+    pub schedule: String,
+    /// "prolog" or "aiTask"
+    pub job_type: String,
+    pub query: Option<String>,
+    pub commands: Option<String>,
+    pub task_id: Option<String>,
+    pub prompt: Option<String>,
+    pub paused: bool,
+    pub created_at: String,
+    pub last_run_at: Option<String>,
+    pub next_run_at: Option<String>,
+}
+
+#[derive(GraphQLObject, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledJobRun {
+    pub job_id: String,
+    pub started_at: String,
+    pub finished_at: String,
+    pub success: bool,
+    pub output: Option<String>,
+    pub error: Option<String>,
+}
+
 #[derive(GraphQLEnum, Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub enum ModelApiType {
@@ -1,6 +1,23 @@
+use cid::Cid;
 use dirs::home_dir;
+use multibase::Base;
+use multihash::{Code, MultihashDigest};
 use std::path::PathBuf;
 
 pub(crate) fn ad4m_data_directory() -> PathBuf {
     home_dir().unwrap().join(".ad4m")
 }
+
+/// The IPFS style content address of `data`, which is also how languages are addressed
+pub(crate) fn ipfs_hash(data: &str) -> String {
+    // Compute the SHA-256 multihash
+    let multihash = Code::Sha2_256.digest(data.as_bytes());
+
+    // Create a CID with default settings (version 1, DAG-Protobuf)
+    let cid = Cid::new_v1(0, multihash);
+
+    // Encode the CID in base58btc (IPFS default)
+    let encoded_cid = multibase::encode(Base::Base58Btc, cid.to_bytes());
+
+    format!("Qm{}", encoded_cid)
+}
@@ -1,17 +1,23 @@
 use argon2::password_hash::Salt;
-use argon2::{self, Argon2, PasswordHasher};
+use argon2::{self, Algorithm, Argon2, Params, PasswordHasher, Version};
 use base64::Engine;
+use bip39::Mnemonic;
 use crypto_box::aead::Aead;
 use crypto_box::{Nonce, PublicKey as cPublicKey, SalsaBox, SecretKey as cSecretKey};
 use deno_core::anyhow::anyhow;
 use deno_core::error::AnyError;
 use did_key::{CoreSign, DIDCore, Ed25519KeyPair, KeyMaterial, PatchedKeyPair};
 use lazy_static::lazy_static;
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::convert::TryInto;
 use std::sync::{Arc, Mutex};
 
+/// Name of the key the agent's DID is derived from.
+/// Retired keys stay in the wallet under other names after a key rotation.
+pub const MAIN_KEY_NAME: &str = "main";
+
 fn slice_to_u8_array(slice: &[u8]) -> [u8; 32] {
     //If length of slice is not 32 then take the first 32 bytes
 
@@ -39,52 +45,189 @@ fn padded(passphrase: String) -> String {
     passphrase
 }
 
-fn encrypt(payload: String, passphrase: String) -> String {
-    let passphrase = padded(passphrase);
-    let b64_passphrase =
-        base64::engine::general_purpose::STANDARD_NO_PAD.encode(passphrase.as_bytes());
-    let salt = Salt::from_b64(&b64_passphrase).expect("salt from passphrase to work");
+/// Version of the cipher envelope written by [`encrypt`]
+const CIPHER_VERSION: u32 = 1;
+const CIPHER_KDF: &str = "argon2id";
+const SALT_LENGTH: usize = 16;
+/// Upper bounds for KDF costs. Ciphers carry their own parameters, so without these
+/// a crafted wallet or backup could make unlocking allocate gigabytes or never finish.
+const MAX_KDF_MEMORY_COST: u32 = 1024 * 1024;
+const MAX_KDF_ITERATIONS: u32 = 64;
+const MAX_KDF_PARALLELISM: u32 = 16;
+
+/// Argon2id cost parameters for deriving the key that encrypts the wallet
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct KdfParams {
+    /// Memory cost in KiB
+    pub memory_cost: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
 
-    // Derive secret key from passphrase
-    let argon2 = Argon2::default();
-    //NOTE: we need to be sure to enforce min password size so we ensure that we will always get 32 bytes to work from
-    let derived_secret_key = argon2
-        .hash_password(passphrase.as_bytes(), salt)
-        .unwrap()
-        .to_string();
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            memory_cost: 65536,
+            iterations: 3,
+            parallelism: 1,
+        }
+    }
+}
 
-    let preambel = "$argon2id$v=19$m=19456,t=2,p=1$";
-    let derived_secret_key = derived_secret_key.replace(preambel, "");
+impl KdfParams {
+    fn argon2(&self) -> Result<Argon2<'static>, AnyError> {
+        if self.memory_cost > MAX_KDF_MEMORY_COST
+            || self.iterations > MAX_KDF_ITERATIONS
+            || self.parallelism > MAX_KDF_PARALLELISM
+        {
+            return Err(anyhow!(
+                "KDF parameters exceed the limits of {} KiB memory, {} iterations and {} lanes",
+                MAX_KDF_MEMORY_COST,
+                MAX_KDF_ITERATIONS,
+                MAX_KDF_PARALLELISM
+            ));
+        }
+        let params = Params::new(
+            self.memory_cost,
+            self.iterations,
+            self.parallelism,
+            Some(32),
+        )
+        .map_err(|e| anyhow!("Invalid KDF parameters: {}", e))?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
 
-    let derived_secret_key_bytes = derived_secret_key.as_bytes();
-    let slice = slice_to_u8_array(derived_secret_key_bytes);
-    let secret_key = cSecretKey::from(slice);
+lazy_static! {
+    static ref KDF_PARAMS: Mutex<KdfParams> = Mutex::new(KdfParams::default());
+}
+
+/// Sets the cost parameters used when encrypting the wallet.
+/// Ciphers created with other parameters get re-encrypted on their next unlock.
+pub fn set_kdf_params(params: KdfParams) -> Result<(), AnyError> {
+    params.argon2()?;
+    *KDF_PARAMS.lock().expect("Couldn't get lock on KDF params") = params;
+    Ok(())
+}
+
+fn kdf_params() -> KdfParams {
+    *KDF_PARAMS.lock().expect("Couldn't get lock on KDF params")
+}
+
+/// Self-describing ciphertext, so that KDF costs can be raised later
+/// without losing the ability to decrypt older wallets.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CipherEnvelope {
+    version: u32,
+    kdf: String,
+    kdf_params: KdfParams,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl CipherEnvelope {
+    fn parse(payload: &str) -> Option<CipherEnvelope> {
+        serde_json::from_str(payload).ok()
+    }
+}
+
+fn salsa_box(passphrase: &str, salt: &[u8], params: &KdfParams) -> Result<SalsaBox, AnyError> {
+    let mut derived_secret_key = [0u8; 32];
+    params
+        .argon2()?
+        .hash_password_into(passphrase.as_bytes(), salt, &mut derived_secret_key)
+        .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
+    let secret_key = cSecretKey::from(derived_secret_key);
     let public_key = cPublicKey::from(&secret_key);
+    Ok(SalsaBox::new(&public_key, &secret_key))
+}
+
+pub(crate) fn encrypt(payload: String, passphrase: String) -> String {
+    encrypt_with_params(payload, passphrase, &kdf_params())
+}
 
-    // Create the Box (encryptor/decryptor) using the derived secret key and the public key
-    let crypto_box = SalsaBox::new(&public_key, &secret_key);
+fn encrypt_with_params(payload: String, passphrase: String, params: &KdfParams) -> String {
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut nonce = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let crypto_box = salsa_box(&passphrase, &salt, params)
+        .expect("KDF parameters are validated in set_kdf_params()");
+    let encrypted_data = crypto_box
+        .encrypt(Nonce::from_slice(&nonce), payload.as_bytes())
+        .expect("encryption to work");
+
+    let b64 = base64::engine::general_purpose::STANDARD_NO_PAD;
+    let envelope = CipherEnvelope {
+        version: CIPHER_VERSION,
+        kdf: CIPHER_KDF.to_string(),
+        kdf_params: *params,
+        salt: b64.encode(salt),
+        nonce: b64.encode(nonce),
+        ciphertext: b64.encode(encrypted_data),
+    };
+    serde_json::to_string(&envelope).expect("envelope to serialize")
+}
 
-    //let nonce = SalsaBox::generate_nonce(&mut OsRng);
-    //let nonce: GenericArray<u8, _> = [0u8; 24].into();
-    let nonce = Nonce::default();
+pub(crate) fn decrypt(payload: String, passphrase: String) -> Result<String, AnyError> {
+    let envelope = match CipherEnvelope::parse(&payload) {
+        Some(envelope) => envelope,
+        None => return legacy_decrypt(payload, passphrase),
+    };
+
+    if envelope.version > CIPHER_VERSION {
+        return Err(anyhow!(
+            "Unsupported wallet cipher version {}",
+            envelope.version
+        ));
+    }
+    if envelope.kdf != CIPHER_KDF {
+        return Err(anyhow!("Unsupported wallet KDF {}", envelope.kdf));
+    }
 
-    // Encrypt
-    let encrypted_data = crypto_box.encrypt(&nonce, payload.as_bytes()).unwrap();
+    let b64 = base64::engine::general_purpose::STANDARD_NO_PAD;
+    let salt = b64.decode(envelope.salt)?;
+    let nonce = b64.decode(envelope.nonce)?;
+    let ciphertext = b64.decode(envelope.ciphertext)?;
+    if nonce.len() != 24 {
+        return Err(anyhow!("Invalid nonce length in wallet cipher"));
+    }
 
-    base64::engine::general_purpose::STANDARD_NO_PAD.encode(encrypted_data)
+    let crypto_box = salsa_box(&passphrase, &salt, &envelope.kdf_params)?;
+    let decrypted_data = crypto_box
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| anyhow!("Could not decrypt wallet, wrong passphrase?"))?;
+    Ok(String::from_utf8(decrypted_data)?)
 }
 
-fn decrypt(payload: String, passphrase: String) -> Result<String, crypto_box::aead::Error> {
+/// True for ciphers that are not in the current envelope format
+/// or were created with other KDF parameters than the configured ones
+fn needs_reencryption(payload: &str) -> bool {
+    match CipherEnvelope::parse(payload) {
+        Some(envelope) => envelope.version < CIPHER_VERSION || envelope.kdf_params != kdf_params(),
+        None => true,
+    }
+}
+
+/// Key derivation used before the versioned envelope was introduced:
+/// the salt is derived from the passphrase and the nonce is all zeros.
+/// Only kept to be able to open and migrate old wallets.
+fn legacy_crypto_box(passphrase: String) -> Result<SalsaBox, AnyError> {
     let passphrase = padded(passphrase);
     let b64_passphrase =
         base64::engine::general_purpose::STANDARD_NO_PAD.encode(passphrase.as_bytes());
-    let salt = Salt::from_b64(&b64_passphrase).expect("salt from passphrase to work");
+    // Passphrases too long to be used as salt never worked with this format
+    let salt = Salt::from_b64(&b64_passphrase)
+        .map_err(|e| anyhow!("Could not derive legacy wallet salt: {}", e))?;
 
-    // Derive secret key from passphrase
     let argon2 = Argon2::default();
     let derived_secret_key = argon2
         .hash_password(passphrase.as_bytes(), salt)
-        .unwrap()
+        .map_err(|e| anyhow!("Legacy key derivation failed: {}", e))?
         .to_string();
 
     let preambel = "$argon2id$v=19$m=19456,t=2,p=1$";
@@ -94,22 +237,26 @@ fn decrypt(payload: String, passphrase: String) -> Result<String, crypto_box::ae
     let secret_key = cSecretKey::from(slice);
     let public_key = cPublicKey::from(&secret_key);
 
-    // Create the Box (encryptor/decryptor) using the derived secret key and the public key
-    let crypto_box = SalsaBox::new(&public_key, &secret_key);
+    Ok(SalsaBox::new(&public_key, &secret_key))
+}
 
-    //Pretty sure this not gonna work since this will be a different nonce to what is generated on encrypt
-    let nonce = Nonce::default();
+#[cfg(test)]
+fn legacy_encrypt(payload: String, passphrase: String) -> String {
+    let encrypted_data = legacy_crypto_box(passphrase)
+        .unwrap()
+        .encrypt(&Nonce::default(), payload.as_bytes())
+        .unwrap();
+    base64::engine::general_purpose::STANDARD_NO_PAD.encode(encrypted_data)
+}
 
+fn legacy_decrypt(payload: String, passphrase: String) -> Result<String, AnyError> {
     let payload_bytes = base64::engine::general_purpose::STANDARD_NO_PAD
         .decode(payload.as_bytes())
-        .expect("Could not decode payload");
-
-    // Decrypt
-    let decrypted_data = crypto_box
-        .decrypt(&nonce, payload_bytes.as_slice())
-        .map(|data| String::from_utf8(data).expect("decrypted array to be a string"));
-
-    decrypted_data
+        .map_err(|e| anyhow!("Could not decode wallet cipher: {}", e))?;
+    let decrypted_data = legacy_crypto_box(passphrase)?
+        .decrypt(&Nonce::default(), payload_bytes.as_slice())
+        .map_err(|_| anyhow!("Could not decrypt wallet, wrong passphrase?"))?;
+    Ok(String::from_utf8(decrypted_data)?)
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -199,6 +346,62 @@ impl Wallet {
         }
     }
 
+    /// Adds a key derived from a BIP39 recovery phrase.
+    /// The phrase encodes the 32-byte Ed25519 seed, see [`Wallet::mnemonic`].
+    pub fn import_mnemonic(&mut self, name: String, mnemonic: &str) -> Result<(), AnyError> {
+        let normalized = mnemonic
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+            .to_lowercase();
+        let mnemonic = Mnemonic::parse_normalized(&normalized)
+            .map_err(|e| anyhow!("Invalid recovery phrase: {}", e))?;
+        let seed = mnemonic.to_entropy();
+        if seed.len() != 32 {
+            return Err(anyhow!("Recovery phrase needs to have 24 words"));
+        }
+
+        if self.keys.is_none() {
+            self.keys = Some(Keys::new());
+        }
+
+        let key = did_key::generate::<Ed25519KeyPair>(Some(&seed));
+        self.keys
+            .as_mut()
+            .unwrap()
+            .by_name
+            .insert(name, Key::from(key));
+        Ok(())
+    }
+
+    /// Returns the 24 word BIP39 recovery phrase for the named key
+    pub fn mnemonic(&self, name: &String) -> Option<String> {
+        let secret = self.get_secret_key(name)?;
+        Mnemonic::from_entropy(&secret)
+            .ok()
+            .map(|mnemonic| mnemonic.to_string())
+    }
+
+    pub fn key_names(&self) -> Vec<String> {
+        self.keys
+            .as_ref()
+            .map(|keys| keys.by_name.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn rename_key(&mut self, from: &str, to: String) -> Result<(), AnyError> {
+        let keys = self.keys.as_mut().ok_or(anyhow!("Wallet is locked"))?;
+        if keys.by_name.contains_key(&to) {
+            return Err(anyhow!("Key with name '{}' already exists", to));
+        }
+        let key = keys
+            .by_name
+            .remove(from)
+            .ok_or(anyhow!("Key with name '{}' not found", from))?;
+        keys.by_name.insert(to, key);
+        Ok(())
+    }
+
     pub fn get_public_key(&self, name: &String) -> Option<Vec<u8>> {
         self.keys
             .as_ref()?
@@ -245,13 +448,28 @@ impl Wallet {
     }
 
     pub fn unlock(&mut self, passphrase: String) -> Result<(), AnyError> {
-        let string = decrypt(self.cipher.clone().expect("No cypher selected"), passphrase)
-            .map_err(|err| anyhow!(err))?;
+        let string = decrypt(self.cipher.clone().expect("No cypher selected"), passphrase)?;
         let keys: Keys = serde_json::from_str(&string)?;
         self.keys = Some(keys);
         Ok(())
     }
 
+    /// Checks the passphrase against the stored cipher without unlocking the wallet
+    pub fn verify_passphrase(&self, passphrase: String) -> bool {
+        match &self.cipher {
+            Some(cipher) => decrypt(cipher.clone(), passphrase).is_ok(),
+            None => false,
+        }
+    }
+
+    /// True if the stored cipher should be re-encrypted with the current format and KDF parameters
+    pub fn cipher_needs_upgrade(&self) -> bool {
+        self.cipher
+            .as_ref()
+            .map(|cipher| needs_reencryption(cipher))
+            .unwrap_or(false)
+    }
+
     pub fn is_unlocked(&self) -> bool {
         self.keys.is_some()
     }
@@ -408,6 +626,40 @@ mod tests {
         assert!(new_wallet.is_unlocked());
     }
 
+    #[test]
+    fn test_wallet_rename_key() {
+        let mut wallet = Wallet::new();
+        wallet.generate_keypair(MAIN_KEY_NAME.to_string());
+        wallet.generate_keypair("second".to_string());
+        let public_key = wallet.get_public_key(&MAIN_KEY_NAME.to_string());
+
+        assert!(wallet
+            .rename_key(MAIN_KEY_NAME, "second".to_string())
+            .is_err());
+        assert!(wallet
+            .rename_key(MAIN_KEY_NAME, "retired".to_string())
+            .is_ok());
+        assert_eq!(wallet.get_public_key(&"retired".to_string()), public_key);
+        assert!(wallet.get_public_key(&MAIN_KEY_NAME.to_string()).is_none());
+        assert_eq!(
+            wallet.key_names(),
+            vec!["retired".to_string(), "second".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_wallet_verify_passphrase() {
+        let mut wallet = Wallet::new();
+        let passphrase = "test_passphrase".to_string();
+        wallet.generate_keypair(MAIN_KEY_NAME.to_string());
+        assert!(!wallet.verify_passphrase(passphrase.clone()));
+
+        wallet.export(passphrase.clone());
+        assert!(wallet.verify_passphrase(passphrase));
+        assert!(!wallet.verify_passphrase("wrong_passphrase".to_string()));
+        assert!(wallet.is_unlocked());
+    }
+
     #[test]
     fn test_did_sign_and_verify() {
         let mut wallet = Wallet::new();
@@ -439,4 +691,184 @@ mod tests {
             assert!(result.is_err());
         }
     }
+
+    #[test]
+    fn test_wallet_mnemonic_roundtrip() {
+        let mut wallet = Wallet::new();
+        let key_name = MAIN_KEY_NAME.to_string();
+        wallet.generate_keypair(key_name.clone());
+        let did = wallet.get_did_document(&key_name).unwrap().id;
+
+        let mnemonic = wallet
+            .mnemonic(&key_name)
+            .expect("mnemonic for generated key");
+        assert_eq!(mnemonic.split(' ').count(), 24);
+
+        let mut restored = Wallet::new();
+        restored
+            .import_mnemonic(
+                key_name.clone(),
+                &format!("  {}\n", mnemonic.to_uppercase()),
+            )
+            .expect("Failed to import mnemonic");
+        assert_eq!(restored.get_did_document(&key_name).unwrap().id, did);
+        assert_eq!(
+            restored.get_secret_key(&key_name),
+            wallet.get_secret_key(&key_name)
+        );
+
+        let message = b"test message";
+        let signature = restored.sign(&key_name, message).unwrap();
+        let key_pair = PatchedKeyPair::try_from(did.as_str()).expect("Failed to get key pair");
+        assert!(key_pair.verify(message, &signature).is_ok());
+    }
+
+    #[test]
+    fn test_wallet_import_invalid_mnemonic() {
+        let mut wallet = Wallet::new();
+        let name = MAIN_KEY_NAME.to_string();
+        assert!(wallet
+            .import_mnemonic(name.clone(), "not a mnemonic")
+            .is_err());
+        // Valid 12 word phrase, but too little entropy for an Ed25519 seed
+        assert!(wallet
+            .import_mnemonic(
+                name.clone(),
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about"
+            )
+            .is_err());
+        assert!(wallet.get_public_key(&name).is_none());
+    }
+
+    const TEST_KDF_PARAMS: KdfParams = KdfParams {
+        memory_cost: 1024,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn test_encrypt_uses_random_salt_and_nonce() {
+        let payload = "test_payload".to_string();
+        let passphrase = "test_passphrase".to_string();
+        let first = encrypt_with_params(payload.clone(), passphrase.clone(), &TEST_KDF_PARAMS);
+        let second = encrypt_with_params(payload.clone(), passphrase.clone(), &TEST_KDF_PARAMS);
+        assert_ne!(first, second);
+
+        let first_envelope = CipherEnvelope::parse(&first).expect("versioned envelope");
+        let second_envelope = CipherEnvelope::parse(&second).expect("versioned envelope");
+        assert_eq!(first_envelope.version, CIPHER_VERSION);
+        assert_eq!(first_envelope.kdf, CIPHER_KDF);
+        assert_eq!(first_envelope.kdf_params, TEST_KDF_PARAMS);
+        assert_ne!(first_envelope.salt, second_envelope.salt);
+        assert_ne!(first_envelope.nonce, second_envelope.nonce);
+
+        assert_eq!(decrypt(first, passphrase.clone()).unwrap(), payload);
+        assert_eq!(decrypt(second, passphrase).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_decrypt_rejects_tampered_and_unknown_envelopes() {
+        let passphrase = "test_passphrase".to_string();
+        let encrypted =
+            encrypt_with_params("payload".to_string(), passphrase.clone(), &TEST_KDF_PARAMS);
+
+        let mut envelope = CipherEnvelope::parse(&encrypted).unwrap();
+        envelope.kdf_params.iterations += 1;
+        let tampered = serde_json::to_string(&envelope).unwrap();
+        assert!(decrypt(tampered, passphrase.clone()).is_err());
+
+        let mut envelope = CipherEnvelope::parse(&encrypted).unwrap();
+        envelope.version = CIPHER_VERSION + 1;
+        let from_the_future = serde_json::to_string(&envelope).unwrap();
+        assert!(decrypt(from_the_future, passphrase).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_legacy_blob() {
+        let passphrase = "test_passphrase".to_string();
+        let payload = "test_payload".to_string();
+        let legacy = legacy_encrypt(payload.clone(), passphrase.clone());
+        assert!(CipherEnvelope::parse(&legacy).is_none());
+        assert!(needs_reencryption(&legacy));
+
+        assert_eq!(decrypt(legacy.clone(), passphrase).unwrap(), payload);
+        assert!(decrypt(legacy, "wrong_passphrase".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_garbage_does_not_panic() {
+        assert!(decrypt("not base64!".to_string(), "passphrase".to_string()).is_err());
+        // Too long to be turned into a legacy salt
+        let long_passphrase = "a".repeat(100);
+        assert!(decrypt("AAAA".to_string(), long_passphrase).is_err());
+    }
+
+    #[test]
+    fn test_wallet_migrates_legacy_cipher() {
+        let passphrase = "test_passphrase".to_string();
+        let key_name = "test_key".to_string();
+        let mut wallet = Wallet::new();
+        wallet.generate_keypair(key_name.clone());
+        let did = wallet.get_did_document(&key_name).unwrap().id;
+
+        let keys = serde_json::to_string(wallet.keys.as_ref().unwrap()).unwrap();
+        let mut legacy_wallet = Wallet::new();
+        legacy_wallet.load(legacy_encrypt(keys, passphrase.clone()));
+        assert!(legacy_wallet.cipher_needs_upgrade());
+
+        legacy_wallet.unlock(passphrase.clone()).unwrap();
+        assert_eq!(legacy_wallet.get_did_document(&key_name).unwrap().id, did);
+
+        let migrated = legacy_wallet.export(passphrase.clone());
+        assert!(CipherEnvelope::parse(&migrated).is_some());
+        assert!(!legacy_wallet.cipher_needs_upgrade());
+
+        let mut reopened = Wallet::new();
+        reopened.load(migrated);
+        reopened.unlock(passphrase).unwrap();
+        assert_eq!(reopened.get_did_document(&key_name).unwrap().id, did);
+    }
+
+    #[test]
+    fn test_cipher_with_other_kdf_params_needs_upgrade() {
+        let encrypted = encrypt_with_params(
+            "payload".to_string(),
+            "passphrase".to_string(),
+            &TEST_KDF_PARAMS,
+        );
+        assert_ne!(TEST_KDF_PARAMS, kdf_params());
+        assert!(needs_reencryption(&encrypted));
+    }
+
+    #[test]
+    fn test_invalid_kdf_params_are_rejected() {
+        assert!(set_kdf_params(KdfParams {
+            memory_cost: 0,
+            iterations: 0,
+            parallelism: 0,
+        })
+        .is_err());
+        assert!(set_kdf_params(KdfParams {
+            memory_cost: MAX_KDF_MEMORY_COST + 1,
+            ..KdfParams::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_decrypt_rejects_excessive_kdf_params() {
+        let passphrase = "test_passphrase".to_string();
+        let encrypted =
+            encrypt_with_params("payload".to_string(), passphrase.clone(), &TEST_KDF_PARAMS);
+
+        let mut envelope = CipherEnvelope::parse(&encrypted).unwrap();
+        envelope.kdf_params.memory_cost = u32::MAX;
+        let expensive = serde_json::to_string(&envelope).unwrap();
+        assert!(decrypt(expensive, passphrase.clone()).is_err());
+
+        let mut envelope = CipherEnvelope::parse(&encrypted).unwrap();
+        envelope.kdf_params.iterations = u32::MAX;
+        let slow = serde_json::to_string(&envelope).unwrap();
+        assert!(decrypt(slow, passphrase).is_err());
+    }
 }
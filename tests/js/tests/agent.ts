@@ -16,9 +16,11 @@ export default function agentTests(testContext: TestContext) {
                 const generate = await ad4mClient.agent.generate("passphrase")
                 expect(generate.isInitialized).to.be.true;
                 expect(generate.isUnlocked).to.be.true;
+                expect(generate.mnemonic!.split(" ").length).to.equal(24);
 
                 await sleep(1000)
                 expect(agentUpdated.calledOnce).to.be.true;
+                expect(agentUpdated.getCall(0).args[0].mnemonic).to.not.be.ok;
     
                 // //Should be able to create a perspective
                 // const create = await ad4mClient.perspective.add("test");
@@ -144,22 +146,23 @@ export default function agentTests(testContext: TestContext) {
                 const verify = await ad4mClient.runtime.verifyStringSignedByDid(preFlight.did, preFlight.didSigningKeyId, "ethAddr", preFlight.deviceKeySignedByDid);
                 expect(verify).to.be.true;
 
-                //Check can save a entanglement proof
+                //Check a proof whose device key signature doesn't verify is rejected
                 preFlight.didSignedByDeviceKey = "ethSignedDID";
-                const addProof = await ad4mClient.agent.addEntanglementProofs([preFlight as EntanglementProofInput]);
-                expect(addProof[0]).to.deep.equal(preFlight);
-
-                //Check can get entanglment proofs
+                let addError: any
+                try {
+                    await ad4mClient.agent.addEntanglementProofs([preFlight as EntanglementProofInput]);
+                } catch (e) {
+                    addError = e
+                }
+                expect(addError).not.to.be.undefined;
+
+                //Check the rejected proof was not stored
                 const getProofs = await ad4mClient.agent.getEntanglementProofs();
-                expect(getProofs[0]).to.deep.equal(preFlight);
+                expect(getProofs.length).to.be.equal(0);
 
                 //Check can delete entanglement proofs
                 const deleteProofs = await ad4mClient.agent.deleteEntanglementProofs([preFlight as EntanglementProofInput]);
                 expect(deleteProofs.length).to.be.equal(0);
-
-                //Check entanglement proof is deleted on get
-                const getProofsPostDelete = await ad4mClient.agent.getEntanglementProofs();
-                expect(getProofsPostDelete.length).to.be.equal(0);
             })
             it('can signMessage', async () => {
                 const ad4mClient = testContext.ad4mClient!;
@@ -92,6 +92,8 @@ describe("Apps integration tests", () => {
   
       expect(oldApps.length).to.be.equal(1);
       expect(oldApps[0].revoked).to.be.false;
+      expect(oldApps[0].tokenHash).to.have.lengthOf(64);
+      expect(oldApps[0].auth.appName).to.be.equal("demo-app");
   
       const newApps = await adminAd4mClient!.agent.revokeToken(requestId);
   
@@ -181,7 +181,9 @@ export default function runtimeTests(testContext: TestContext) {
                     expect(requestedNotification.trigger).to.equal(notification.trigger);
                     expect(requestedNotification.perspectiveIds).to.eql(notification.perspectiveIds);
                     expect(requestedNotification.webhookUrl).to.equal(notification.webhookUrl);
-                    expect(requestedNotification.webhookAuth).to.equal(notification.webhookAuth);
+                    // Credentials don't leave the executor
+                    expect(requestedNotification.webhookAuth).to.be.undefined;
+                    expect(requestedNotification.webhookSigningSecret).to.be.undefined;
                     // Automatically resolve without needing to manually manage a Promise
                     return null;
                 }
@@ -229,6 +231,7 @@ export default function runtimeTests(testContext: TestContext) {
             // after changing a notification it needs to be granted again
             expect(updatedNotificationInList?.granted).to.be.false
             expect(updatedNotificationInList?.description).to.equal(updatedNotification.description)
+            expect(updatedNotificationInList?.hasWebhookAuth).to.be.true
 
             // Check if the notification is removed
             const removed = await ad4mClient.runtime.removeNotification(notificationId)